@@ -0,0 +1,41 @@
+#![feature(test)]
+
+extern crate test;
+
+extern crate avro_rs;
+use avro_rs::decode::decode_long;
+use avro_rs::encode::encode_long;
+
+use test::Bencher;
+
+fn longs() -> Vec<i64> {
+    (0..1000i64).map(|i| (i * i * i) ^ -(i & 1)).collect()
+}
+
+#[bench]
+fn bench_encode_long(b: &mut Bencher) {
+    let longs = longs();
+    let mut buffer = Vec::with_capacity(10 * longs.len());
+    b.iter(|| {
+        buffer.clear();
+        for &n in &longs {
+            encode_long(n, &mut buffer);
+        }
+        test::black_box(&buffer);
+    });
+}
+
+#[bench]
+fn bench_decode_long(b: &mut Bencher) {
+    let longs = longs();
+    let mut buffer = Vec::new();
+    for &n in &longs {
+        encode_long(n, &mut buffer);
+    }
+    b.iter(|| {
+        let mut bytes = &buffer[..];
+        for _ in 0..longs.len() {
+            test::black_box(decode_long(&mut bytes).unwrap());
+        }
+    });
+}
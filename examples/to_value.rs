@@ -2,10 +2,8 @@ extern crate avro_rs;
 
 #[macro_use]
 extern crate serde_derive;
-extern crate failure;
 
-use avro_rs::to_value;
-use failure::Error;
+use avro_rs::{to_value, AvroError};
 
 #[derive(Debug, Deserialize, Serialize)]
 struct Test {
@@ -13,7 +11,7 @@ struct Test {
     b: String,
 }
 
-fn main() -> Result<(), Error> {
+fn main() -> Result<(), AvroError> {
     let test = Test {
         a: 27,
         b: "foo".to_owned(),
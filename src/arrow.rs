@@ -0,0 +1,675 @@
+//! Logic for converting Avro values to and from Arrow record batches, enabled by the `arrow`
+//! feature.
+//!
+//! Avro types map to Arrow types as follows:
+//!
+//! | Avro                          | Arrow                                                    |
+//! |-------------------------------|----------------------------------------------------------|
+//! | `null`                        | `Null`                                                   |
+//! | `boolean`                     | `Boolean`                                                |
+//! | `int`, `long`, `counter`      | `Int32`, `Int64`, `Int64`                                |
+//! | `float`, `double`             | `Float32`, `Float64`                                     |
+//! | `bytes`, `string`             | `Binary`, `Utf8`                                         |
+//! | `fixed`                       | `FixedSizeBinary`                                        |
+//! | `enum`                        | `Utf8`, holding the symbol                               |
+//! | `date`                        | `Timestamp(Millisecond)`                                 |
+//! | `array`, `bounded_list`, `set`| `List`                                                   |
+//! | `map`                         | `Map<Utf8, _>`                                           |
+//! | `lru_set`                     | `Map<Utf8, Struct<access_time: Int64, count: Int64>>`    |
+//! | `record`                      | `Struct`                                                 |
+//! | `optional`, `["null", _]`     | the type of the value, nullable                          |
+//!
+//! Other unions and the `hll`, `bloom` and `topk` types have no Arrow counterpart and fail the
+//! conversion.
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use ::arrow::array::{
+    Array, ArrayRef, BinaryArray, BooleanArray, FixedSizeBinaryArray, Float32Array, Float64Array,
+    Int32Array, Int64Array, ListArray, MapArray, NullArray, RecordBatch, RecordBatchOptions,
+    StringArray, StructArray, TimestampMillisecondArray,
+};
+use ::arrow::buffer::{NullBuffer, OffsetBuffer};
+use ::arrow::datatypes::{DataType, Field, Fields, Schema as ArrowSchema, TimeUnit};
+
+use crate::error::{at, AvroError};
+use crate::lru::LruMap;
+use crate::schema::{RecordField, Schema, SchemaKind};
+use crate::types::{LruValue, SetItem, ToAvro, Value};
+use crate::visit::PathSegment;
+
+/// Arrow schema of the record batches converted from values of the given record
+/// [Schema](../schema/enum.Schema.html).
+pub fn to_arrow_schema(schema: &Schema) -> Result<ArrowSchema, AvroError> {
+    Ok(ArrowSchema::new(record_fields(schema)?))
+}
+
+/// Convert values of the given record [Schema](../schema/enum.Schema.html) into a
+/// `RecordBatch`, one row per value and one column per field.
+///
+/// Values are [normalized](../types/enum.Value.html#method.normalize) against the schema first,
+/// so that they may use any form the schema accepts.
+pub fn to_record_batch<I>(schema: &Schema, values: I) -> Result<RecordBatch, AvroError>
+where
+    I: IntoIterator<Item = Value>,
+{
+    let fields = match schema {
+        Schema::Record { fields, .. } => fields,
+        other => return Err(not_a_record(other)),
+    };
+    let values = values
+        .into_iter()
+        .map(|value| value.normalize(schema))
+        .collect::<Result<Vec<_>, _>>()?;
+    let rows = values.iter().map(Some).collect::<Vec<_>>();
+    let columns = record_columns(fields, &rows)?;
+    let options = RecordBatchOptions::new().with_row_count(Some(values.len()));
+    let batch =
+        RecordBatch::try_new_with_options(Arc::new(to_arrow_schema(schema)?), columns, &options)?;
+    Ok(batch)
+}
+
+/// Convert the rows of a `RecordBatch` into values of the given record
+/// [Schema](../schema/enum.Schema.html), reading each field from the column of the same name.
+pub fn from_record_batch(schema: &Schema, batch: &RecordBatch) -> Result<Vec<Value>, AvroError> {
+    let fields = match schema {
+        Schema::Record { fields, .. } => fields,
+        other => return Err(not_a_record(other)),
+    };
+    let columns = fields
+        .iter()
+        .map(|field| {
+            batch.column_by_name(&field.name).ok_or_else(|| {
+                AvroError::Resolution(format!("missing column {}", field.name))
+            })
+        })
+        .collect::<Result<Vec<_>, AvroError>>()?;
+    (0..batch.num_rows())
+        .map(|row| read_record(fields, &columns, row))
+        .collect()
+}
+
+fn not_a_record(schema: &Schema) -> AvroError {
+    AvroError::Resolution(
+        format!("record schema expected, got {:?}", SchemaKind::from(schema)),
+    )
+}
+
+fn unsupported(schema: &Schema) -> AvroError {
+    AvroError::Resolution(format!("{:?} has no Arrow counterpart", SchemaKind::from(schema)))
+}
+
+fn mismatch(schema: &Schema, value: &Value) -> AvroError {
+    AvroError::Resolution(format!(
+        "{:?} expected, got {:?}",
+        SchemaKind::from(schema),
+        value
+    ))
+}
+
+/// Branch of a union of `null` and another type, the only unions mapped to Arrow.
+fn nullable_branch(schema: &Schema) -> Option<&Schema> {
+    match schema {
+        Schema::Union(union) if union.is_nullable() && union.variants().len() == 2 => {
+            Some(&union.variants()[1])
+        },
+        _ => None,
+    }
+}
+
+fn is_nullable(schema: &Schema) -> bool {
+    matches!(schema, Schema::Null | Schema::Optional(_)) || nullable_branch(schema).is_some()
+}
+
+fn record_fields(schema: &Schema) -> Result<Fields, AvroError> {
+    match schema {
+        Schema::Record { fields, .. } => fields
+            .iter()
+            .map(|field| {
+                let nullable = is_nullable(&field.schema);
+                Ok(Field::new(&*field.name, data_type(&field.schema)?, nullable))
+            })
+            .collect(),
+        other => Err(not_a_record(other)),
+    }
+}
+
+fn entries_field(values: DataType, nullable: bool) -> Arc<Field> {
+    let entries = Fields::from(vec![
+        Field::new("key", DataType::Utf8, false),
+        Field::new("value", values, nullable),
+    ]);
+    Arc::new(Field::new("entries", DataType::Struct(entries), false))
+}
+
+fn item_field(schema: &Schema) -> Result<Arc<Field>, AvroError> {
+    Ok(Arc::new(Field::new("item", data_type(schema)?, is_nullable(schema))))
+}
+
+fn data_type(schema: &Schema) -> Result<DataType, AvroError> {
+    Ok(match schema {
+        Schema::Null => DataType::Null,
+        Schema::Boolean => DataType::Boolean,
+        Schema::Int => DataType::Int32,
+        Schema::Long | Schema::Counter => DataType::Int64,
+        Schema::Float => DataType::Float32,
+        Schema::Double => DataType::Float64,
+        Schema::Bytes => DataType::Binary,
+        Schema::String | Schema::Enum { .. } => DataType::Utf8,
+        Schema::Fixed { size, .. } => DataType::FixedSizeBinary(fixed_size(*size)?),
+        Schema::Date(_) => DataType::Timestamp(TimeUnit::Millisecond, None),
+        Schema::Array(items) | Schema::BoundedList { items, .. } | Schema::Set(items) => {
+            DataType::List(item_field(items)?)
+        },
+        Schema::Map(values) => {
+            DataType::Map(entries_field(data_type(values)?, is_nullable(values)), false)
+        },
+        Schema::LruSet(..) => DataType::Map(entries_field(data_type(&LRU_VALUE)?, false), false),
+        Schema::Record { .. } => DataType::Struct(record_fields(schema)?),
+        Schema::Optional(inner) => data_type(inner)?,
+        Schema::Union(_) => match nullable_branch(schema) {
+            Some(branch) => data_type(branch)?,
+            None => return Err(unsupported(schema)),
+        },
+        Schema::Hll(_) | Schema::Bloom { .. } | Schema::TopK(_) => return Err(unsupported(schema)),
+    })
+}
+
+fn fixed_size(size: usize) -> Result<i32, AvroError> {
+    i32::try_from(size)
+        .map_err(|_| AvroError::Resolution(format!("fixed size {} is too large", size)))
+}
+
+/// Build an array of type `$array` from the items given by `$item` for the values matching
+/// `$value`, the other values failing the conversion.
+macro_rules! primitive_array {
+    ($array:ty, $schema:expr, $rows:expr, $value:pat => $item:expr) => {
+        Arc::new(<$array>::from(primitives($schema, $rows, |value| match value {
+            $value => Some($item),
+            _ => None,
+        })?))
+    };
+}
+
+/// Values of a column, `None` for the null values and for the rows of which a parent is null.
+type Rows<'a> = [Option<&'a Value>];
+
+/// Read every row with `f`, which returns `None` for values not matching `schema`.
+fn primitives<'a, T, F>(schema: &Schema, rows: &Rows<'a>, f: F) -> Result<Vec<Option<T>>, AvroError>
+where
+    F: Fn(&'a Value) -> Option<T>,
+{
+    rows.iter()
+        .map(|row| match row {
+            Some(value) => f(value).map(Some).ok_or_else(|| mismatch(schema, value)),
+            None => Ok(None),
+        })
+        .collect()
+}
+
+fn null_buffer(rows: &Rows) -> Option<NullBuffer> {
+    if rows.iter().all(Option::is_some) {
+        None
+    } else {
+        Some(NullBuffer::from(rows.iter().map(Option::is_some).collect::<Vec<_>>()))
+    }
+}
+
+fn record_columns(fields: &[RecordField], rows: &Rows) -> Result<Vec<ArrayRef>, AvroError> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(position, field)| {
+            let children = rows
+                .iter()
+                .map(|row| match row {
+                    // normalized records hold their fields in schema order
+                    Some(Value::Record(items, _)) => Ok(items.get(position).map(|(_, item)| item)),
+                    Some(other) => Err(mismatch(&field.schema, other)),
+                    None => Ok(None),
+                })
+                .collect::<Result<Vec<_>, AvroError>>()?;
+            at(build(&field.schema, &children), PathSegment::Field(&field.name))
+        })
+        .collect()
+}
+
+/// Build a list array from the items of each row, given by `items`.
+fn build_list<'a, F>(
+    schema: &Schema,
+    inner: &Schema,
+    rows: &Rows<'a>,
+    items: F,
+) -> Result<ArrayRef, AvroError>
+where
+    F: Fn(&'a Value) -> Option<Vec<&'a Value>>,
+{
+    let mut lengths = Vec::with_capacity(rows.len());
+    let mut children = Vec::new();
+    for row in rows {
+        match row {
+            Some(value) => {
+                let row_items = items(value).ok_or_else(|| mismatch(schema, value))?;
+                lengths.push(row_items.len());
+                children.extend(row_items.into_iter().map(Some));
+            },
+            None => lengths.push(0),
+        }
+    }
+    let values = build(inner, &children)?;
+    let list = ListArray::try_new(
+        item_field(inner)?,
+        OffsetBuffer::from_lengths(lengths),
+        values,
+        null_buffer(rows),
+    )?;
+    Ok(Arc::new(list))
+}
+
+/// Build a map array from the entries of each row, given by `entries`, of which the values are
+/// built by `values`.
+fn build_map<'a, E, V>(
+    schema: &Schema,
+    rows: &Rows<'a>,
+    entries: E,
+    values: V,
+) -> Result<ArrayRef, AvroError>
+where
+    E: Fn(&'a Value) -> Option<Vec<(&'a String, &'a Value)>>,
+    V: FnOnce(&[Option<&'a Value>]) -> Result<(ArrayRef, DataType, bool), AvroError>,
+{
+    let mut lengths = Vec::with_capacity(rows.len());
+    let mut keys = Vec::new();
+    let mut children = Vec::new();
+    for row in rows {
+        match row {
+            Some(value) => {
+                let mut row_entries = entries(value).ok_or_else(|| mismatch(schema, value))?;
+                row_entries.sort_by_key(|(key, _)| *key);
+                lengths.push(row_entries.len());
+                for (key, value) in row_entries {
+                    keys.push(key.as_str());
+                    children.push(Some(value));
+                }
+            },
+            None => lengths.push(0),
+        }
+    }
+    let (values, values_type, nullable) = values(&children)?;
+    let field = entries_field(values_type, nullable);
+    let fields = match field.data_type() {
+        DataType::Struct(fields) => fields.clone(),
+        _ => unreachable!("entries are structs"),
+    };
+    let keys = Arc::new(StringArray::from(keys));
+    let entries = StructArray::try_new(fields, vec![keys, values], None)?;
+    let map = MapArray::try_new(
+        field,
+        OffsetBuffer::from_lengths(lengths),
+        entries,
+        null_buffer(rows),
+        false,
+    )?;
+    Ok(Arc::new(map))
+}
+
+fn build(schema: &Schema, rows: &Rows) -> Result<ArrayRef, AvroError> {
+    let array: ArrayRef = match schema {
+        Schema::Null => Arc::new(NullArray::new(rows.len())),
+        Schema::Boolean => primitive_array!(BooleanArray, schema, rows, Value::Boolean(b, _) => *b),
+        Schema::Int => primitive_array!(Int32Array, schema, rows, Value::Int(n, _) => *n),
+        Schema::Long => primitive_array!(Int64Array, schema, rows, Value::Long(n, _) => *n),
+        Schema::Counter => primitive_array!(Int64Array, schema, rows, Value::Counter(n, _) => *n),
+        Schema::Float => primitive_array!(Float32Array, schema, rows, Value::Float(x, _) => *x),
+        Schema::Double => primitive_array!(Float64Array, schema, rows, Value::Double(x, _) => *x),
+        Schema::Bytes => {
+            primitive_array!(BinaryArray, schema, rows, Value::Bytes(bytes, _) => bytes.as_slice())
+        },
+        Schema::String => {
+            primitive_array!(StringArray, schema, rows, Value::String(s, _) => s.as_str())
+        },
+        Schema::Enum { .. } => {
+            primitive_array!(StringArray, schema, rows, Value::Enum(_, s, _) => s.as_str())
+        },
+        Schema::Fixed { size, .. } => {
+            let items = primitives(schema, rows, |value| match value {
+                Value::Fixed(_, bytes, _) => Some(bytes.as_slice()),
+                _ => None,
+            })?;
+            let size = fixed_size(*size)?;
+            Arc::new(FixedSizeBinaryArray::try_from_sparse_iter_with_size(items.into_iter(), size)?)
+        },
+        Schema::Date(_) => {
+            primitive_array!(TimestampMillisecondArray, schema, rows, Value::Date(t, _) => *t)
+        },
+        Schema::Array(inner) | Schema::BoundedList { items: inner, .. } => {
+            build_list(schema, inner, rows, |value| match value {
+                Value::Array(items, _) => Some(items.iter().collect()),
+                _ => None,
+            })?
+        },
+        Schema::Set(inner) => {
+            // set items are sorted, for equal sets to give equal lists
+            let owned = rows
+                .iter()
+                .map(|row| match row {
+                    Some(Value::Set(items, _)) => {
+                        let mut items = items.iter().cloned().collect::<Vec<_>>();
+                        items.sort();
+                        Ok(Some(Value::Array(items.into_iter().map(SetItem::avro).collect(), None)))
+                    },
+                    Some(other) => Err(mismatch(schema, other)),
+                    None => Ok(None),
+                })
+                .collect::<Result<Vec<_>, AvroError>>()?;
+            let rows = owned.iter().map(Option::as_ref).collect::<Vec<_>>();
+            build_list(schema, inner, &rows, |value| match value {
+                Value::Array(items, _) => Some(items.iter().collect()),
+                _ => None,
+            })?
+        },
+        Schema::Map(inner) => build_map(
+            schema,
+            rows,
+            |value| match value {
+                Value::Map(items, _) => Some(items.iter().collect()),
+                _ => None,
+            },
+            |children| {
+                let values = build(inner, children)?;
+                Ok((values, data_type(inner)?, is_nullable(inner)))
+            },
+        )?,
+        Schema::LruSet(..) => {
+            // lru entries are turned into records of their access time and count
+            let owned = rows
+                .iter()
+                .map(|row| match row {
+                    Some(Value::LruSet(items, _, _)) => Ok(Some(Value::Map(
+                        items
+                            .iter()
+                            .map(|(key, item)| {
+                                let record = Value::Record(vec![
+                                    ("access_time".into(), Value::Long(item.access_time, None)),
+                                    ("count".into(), Value::Long(item.count, None)),
+                                ], None);
+                                (key.clone(), record)
+                            })
+                            .collect(),
+                        None,
+                    ))),
+                    Some(other) => Err(mismatch(schema, other)),
+                    None => Ok(None),
+                })
+                .collect::<Result<Vec<_>, AvroError>>()?;
+            let rows = owned.iter().map(Option::as_ref).collect::<Vec<_>>();
+            build_map(
+                schema,
+                &rows,
+                |value| match value {
+                    Value::Map(items, _) => Some(items.iter().collect()),
+                    _ => None,
+                },
+                |children| {
+                    let schema = &*LRU_VALUE;
+                    let values = build(schema, children)?;
+                    Ok((values, data_type(schema)?, false))
+                },
+            )?
+        },
+        Schema::Record { fields, .. } => Arc::new(StructArray::try_new(
+            record_fields(schema)?,
+            record_columns(fields, rows)?,
+            null_buffer(rows),
+        )?),
+        Schema::Optional(inner) => {
+            let children = rows
+                .iter()
+                .map(|row| match row {
+                    Some(Value::Optional(value, _)) => Ok(value.as_deref()),
+                    Some(other) => Err(mismatch(schema, other)),
+                    None => Ok(None),
+                })
+                .collect::<Result<Vec<_>, AvroError>>()?;
+            build(inner, &children)?
+        },
+        Schema::Union(_) => {
+            let branch = nullable_branch(schema).ok_or_else(|| unsupported(schema))?;
+            let children = rows
+                .iter()
+                .map(|row| match row {
+                    Some(Value::Union(value, _)) if **value == Value::Null => Ok(None),
+                    Some(Value::Union(value, _)) => Ok(Some(&**value)),
+                    Some(other) => Err(mismatch(schema, other)),
+                    None => Ok(None),
+                })
+                .collect::<Result<Vec<_>, AvroError>>()?;
+            build(branch, &children)?
+        },
+        Schema::Hll(_) | Schema::Bloom { .. } | Schema::TopK(_) => return Err(unsupported(schema)),
+    };
+    Ok(array)
+}
+
+lazy_static! {
+    /// Record schema of the Arrow values of `lru_set` entries.
+    static ref LRU_VALUE: Schema = Schema::parse_str(
+        r#"{"type": "record", "name": "lru_value", "fields": [
+            {"name": "access_time", "type": "long"},
+            {"name": "count", "type": "long"}
+        ]}"#,
+    ).expect("valid lru value schema");
+}
+
+fn downcast<'a, T: 'static>(schema: &Schema, array: &'a dyn Array) -> Result<&'a T, AvroError> {
+    array.as_any().downcast_ref::<T>().ok_or_else(|| {
+        AvroError::Resolution(format!(
+            "{:?} can't be read from an Arrow {} array",
+            SchemaKind::from(schema),
+            array.data_type()
+        ))
+    })
+}
+
+fn read_record(
+    fields: &[RecordField],
+    columns: &[&ArrayRef],
+    row: usize,
+) -> Result<Value, AvroError> {
+    fields
+        .iter()
+        .zip(columns)
+        .map(|(field, column)| {
+            let value = at(read(&field.schema, &***column, row), PathSegment::Field(&field.name))?;
+            Ok((field.name.clone(), value))
+        })
+        .collect::<Result<Vec<_>, AvroError>>()
+        .map(|items| Value::Record(items, None))
+}
+
+fn read_items(schema: &Schema, array: &dyn Array) -> Result<Vec<Value>, AvroError> {
+    (0..array.len())
+        .map(|i| at(read(schema, array, i), PathSegment::Index(i)))
+        .collect()
+}
+
+fn read(schema: &Schema, array: &dyn Array, row: usize) -> Result<Value, AvroError> {
+    if array.is_null(row) {
+        return match schema {
+            Schema::Null => Ok(Value::Null),
+            Schema::Optional(_) => Ok(Value::Optional(None, None)),
+            _ if nullable_branch(schema).is_some() => Ok(Value::Union(Box::new(Value::Null), None)),
+            _ => Err(AvroError::Resolution(format!(
+                "null value for {:?}",
+                SchemaKind::from(schema)
+            ))),
+        }
+    }
+
+    Ok(match schema {
+        Schema::Null => Value::Null,
+        Schema::Boolean => {
+            Value::Boolean(downcast::<BooleanArray>(schema, array)?.value(row), None)
+        },
+        Schema::Int => Value::Int(downcast::<Int32Array>(schema, array)?.value(row), None),
+        Schema::Long => Value::Long(downcast::<Int64Array>(schema, array)?.value(row), None),
+        Schema::Counter => Value::Counter(downcast::<Int64Array>(schema, array)?.value(row), None),
+        Schema::Float => Value::Float(downcast::<Float32Array>(schema, array)?.value(row), None),
+        Schema::Double => Value::Double(downcast::<Float64Array>(schema, array)?.value(row), None),
+        Schema::Bytes => {
+            Value::Bytes(downcast::<BinaryArray>(schema, array)?.value(row).to_vec(), None)
+        },
+        Schema::String => {
+            Value::String(downcast::<StringArray>(schema, array)?.value(row).to_owned(), None)
+        },
+        Schema::Fixed { size, .. } => {
+            let bytes = downcast::<FixedSizeBinaryArray>(schema, array)?.value(row);
+            Value::Fixed(*size, bytes.to_vec(), None)
+        },
+        Schema::Enum { symbols, .. } => {
+            let symbol = downcast::<StringArray>(schema, array)?.value(row);
+            match symbols.iter().position(|s| s == symbol) {
+                Some(index) => Value::Enum(index as i32, symbol.to_owned(), None),
+                None => {
+                    return Err(AvroError::Resolution(format!(
+                        "unknown enum symbol {}",
+                        symbol
+                    )))
+                },
+            }
+        },
+        Schema::Date(_) => {
+            Value::Date(downcast::<TimestampMillisecondArray>(schema, array)?.value(row), None)
+        },
+        Schema::Array(inner) | Schema::BoundedList { items: inner, .. } => {
+            let items = downcast::<ListArray>(schema, array)?.value(row);
+            Value::Array(read_items(inner, &*items)?, None)
+        },
+        Schema::Set(inner) => {
+            let items = downcast::<ListArray>(schema, array)?.value(row);
+            let items = read_items(inner, &*items)?
+                .into_iter()
+                .map(SetItem::try_from)
+                .collect::<Result<HashSet<_>, _>>()?;
+            Value::Set(items, None)
+        },
+        Schema::Map(inner) => {
+            let entries = downcast::<MapArray>(schema, array)?.value(row);
+            let keys = downcast::<StringArray>(&Schema::String, &**entries.column(0))?;
+            let values = entries.column(1);
+            let items = (0..entries.len())
+                .map(|i| {
+                    let key = keys.value(i);
+                    let value = at(read(inner, &**values, i), PathSegment::Key(key))?;
+                    Ok((key.to_owned(), value))
+                })
+                .collect::<Result<HashMap<_, _>, AvroError>>()?;
+            Value::Map(items, None)
+        },
+        Schema::LruSet(limit, _) => {
+            let entries = downcast::<MapArray>(schema, array)?.value(row);
+            let keys = downcast::<StringArray>(&Schema::String, &**entries.column(0))?;
+            let values = downcast::<StructArray>(&LRU_VALUE, &**entries.column(1))?;
+            let access_times = downcast::<Int64Array>(&Schema::Long, &**values.column(0))?;
+            let counts = downcast::<Int64Array>(&Schema::Long, &**values.column(1))?;
+            let items = (0..entries.len())
+                .map(|i| {
+                    let value = LruValue::new(access_times.value(i), counts.value(i));
+                    (keys.value(i).to_owned(), value)
+                })
+                .collect::<LruMap>();
+            Value::LruSet(Box::new(items), limit.clone(), None)
+        },
+        Schema::Record { fields, .. } => {
+            let array = downcast::<StructArray>(schema, array)?;
+            let columns = fields
+                .iter()
+                .map(|field| {
+                    array.column_by_name(&field.name).ok_or_else(|| {
+                        AvroError::Resolution(format!("missing column {}", field.name))
+                    })
+                })
+                .collect::<Result<Vec<_>, AvroError>>()?;
+            read_record(fields, &columns, row)?
+        },
+        Schema::Optional(inner) => Value::Optional(Some(Box::new(read(inner, array, row)?)), None),
+        Schema::Union(_) => match nullable_branch(schema) {
+            Some(branch) => Value::Union(Box::new(read(branch, array, row)?), None),
+            None => return Err(unsupported(schema)),
+        },
+        Schema::Hll(_) | Schema::Bloom { .. } | Schema::TopK(_) => return Err(unsupported(schema)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::LruLimit;
+
+    #[test]
+    fn test_record_batch_round_trip() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "r", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "name", "type": ["null", "string"]},
+                {"name": "at", "type": "date"},
+                {"name": "color", "type": {
+                    "type": "enum", "name": "c", "symbols": ["red", "blue"]
+                }},
+                {"name": "tags", "type": {"type": "set", "items": "string"}},
+                {"name": "scores", "type": {"type": "map", "values": "double"}},
+                {"name": "seen", "type": {"type": "lru_set", "limit": "10"}},
+                {"name": "inner", "type": {"type": "optional", "value": {
+                    "type": "record", "name": "i", "fields": [
+                        {"name": "items", "type": {"type": "array", "items": "int"}}
+                    ]
+                }}}
+            ]}"#,
+        ).unwrap();
+        let seen = vec![("a".to_owned(), LruValue::new(5, 2))].into_iter().collect::<LruMap>();
+        let tags = vec![SetItem::String("t".to_owned())].into_iter().collect();
+        let scores = vec![("s".to_owned(), Value::Double(0.5, None))].into_iter().collect();
+        let inner = Value::record(vec![("items", Value::Array(vec![Value::Int(1, None)], None))]);
+        let values = vec![
+            Value::Record(vec![
+                ("id".into(), Value::Long(1, None)),
+                ("name".into(), Value::Union(Box::new("x".avro()), None)),
+                ("at".into(), Value::Date(1_000, None)),
+                ("color".into(), Value::Enum(1, "blue".to_owned(), None)),
+                ("tags".into(), Value::Set(tags, None)),
+                ("scores".into(), Value::Map(scores, None)),
+                ("seen".into(), Value::LruSet(Box::new(seen), LruLimit::Count(10), None)),
+                ("inner".into(), Value::Optional(Some(Box::new(inner)), None)),
+            ], None),
+            Value::Record(vec![
+                ("id".into(), Value::Long(2, None)),
+                ("name".into(), Value::Union(Box::new(Value::Null), None)),
+                ("at".into(), Value::Date(2_000, None)),
+                ("color".into(), Value::Enum(0, "red".to_owned(), None)),
+                ("tags".into(), Value::Set(HashSet::new(), None)),
+                ("scores".into(), Value::Map(HashMap::new(), None)),
+                ("seen".into(), Value::LruSet(Box::new(LruMap::new()), LruLimit::Count(10), None)),
+                ("inner".into(), Value::Optional(None, None)),
+            ], None),
+        ];
+
+        let batch = to_record_batch(&schema, values.clone()).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(batch.num_columns(), 8);
+        let date = DataType::Timestamp(TimeUnit::Millisecond, None);
+        assert_eq!(batch.schema().field(2).data_type(), &date);
+        assert!(batch.schema().field(1).is_nullable());
+        assert_eq!(batch.column(1).null_count(), 1);
+        assert_eq!(from_record_batch(&schema, &batch).unwrap(), values);
+
+        // unions of more than null and another type have no Arrow counterpart
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "r", "fields": [
+                {"name": "a", "type": ["int", "string"]}
+            ]}"#,
+        ).unwrap();
+        assert!(to_arrow_schema(&schema).is_err());
+        assert!(to_record_batch(&Schema::Long, vec![]).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+//! Logic for exporting record schemas as BigQuery table schemas.
+use serde_json::{Map, Value as JsonValue};
+
+use crate::error::AvroError;
+use crate::schema::{RecordField, Schema, SchemaKind};
+
+impl Schema {
+    /// [BigQuery table schema](https://cloud.google.com/bigquery/docs/schemas) of the values of
+    /// this record schema: the JSON array of its columns, as given to `bq load --schema` or
+    /// `bq mk --table`.
+    ///
+    /// Avro types map to BigQuery types as follows:
+    ///
+    /// - `boolean` is `BOOLEAN`, `int`, `long` and `counter` are `INTEGER`, `float` and `double`
+    ///   are `FLOAT`, `string` and `enum` are `STRING`, `bytes`, `fixed`, `hll` and `bloom` are
+    ///   `BYTES`, and `date` is `TIMESTAMP`;
+    /// - records are `RECORD` columns holding their fields;
+    /// - `array`, `bounded_list` and `set` are `REPEATED` columns of their items, arrays of
+    ///   arrays being `REPEATED RECORD`s holding a single `REPEATED` `item` column;
+    /// - `map` is a `REPEATED RECORD` of `key` and `value` columns, `lru_set` one of `key`,
+    ///   `access_time` and `count` columns, and `topk` one of `key`, `count` and `error` columns;
+    /// - `optional` and unions of `null` and another type are `NULLABLE` columns of the other
+    ///   type, other columns are `REQUIRED`.
+    ///
+    /// As BigQuery has no unions and no null items, other unions fail the conversion, and null
+    /// items of arrays are left out of their column. The documentation of fields is kept in the
+    /// `description` of their column.
+    pub fn to_bigquery_schema(&self) -> Result<JsonValue, AvroError> {
+        match self {
+            Schema::Record { fields, .. } => Ok(JsonValue::Array(field_columns(fields)?)),
+            other => Err(AvroError::Resolution(format!(
+                "Record schema expected, got {:?}",
+                SchemaKind::from(other)
+            ))),
+        }
+    }
+}
+
+fn field_columns(fields: &[RecordField]) -> Result<Vec<JsonValue>, AvroError> {
+    fields
+        .iter()
+        .map(|field| column(&field.name, &field.schema, field.doc.as_deref()))
+        .collect()
+}
+
+/// Schema of the values of `schema`, along with whether `null` is among them.
+fn non_null(schema: &Schema) -> Result<(&Schema, bool), AvroError> {
+    match schema {
+        Schema::Optional(inner) => Ok((inner, true)),
+        Schema::Union(union) => match union.variants() {
+            [Schema::Null, other] | [other, Schema::Null] => Ok((other, true)),
+            _ => {
+                Err(AvroError::Resolution(
+                    "unions of several non-null types have no BigQuery type".to_owned(),
+                ))
+            },
+        },
+        schema => Ok((schema, false)),
+    }
+}
+
+fn column(name: &str, schema: &Schema, doc: Option<&str>) -> Result<JsonValue, AvroError> {
+    let (schema, nullable) = non_null(schema)?;
+    let mut column = Map::new();
+    column.insert("name".to_owned(), json!(name));
+
+    let mode = match schema {
+        Schema::Array(items) | Schema::BoundedList { items, .. } | Schema::Set(items) => {
+            let (items, _) = non_null(items)?;
+            match items {
+                Schema::Array(_) | Schema::BoundedList { .. } | Schema::Set(_) => {
+                    column.insert("type".to_owned(), json!("RECORD"));
+                    column.insert("fields".to_owned(), json!([column_of("item", items)?]));
+                },
+                items => column_type(items, &mut column)?,
+            }
+            "REPEATED"
+        },
+        Schema::Map(values) => {
+            column.insert("type".to_owned(), json!("RECORD"));
+            column.insert(
+                "fields".to_owned(),
+                json!([column_of("key", &Schema::String)?, column_of("value", values)?]),
+            );
+            "REPEATED"
+        },
+        Schema::LruSet(..) => {
+            column.insert("type".to_owned(), json!("RECORD"));
+            column.insert("fields".to_owned(), json!([
+                column_of("key", &Schema::String)?,
+                column_of("access_time", &Schema::Long)?,
+                column_of("count", &Schema::Long)?,
+            ]));
+            "REPEATED"
+        },
+        Schema::TopK(_) => {
+            column.insert("type".to_owned(), json!("RECORD"));
+            column.insert("fields".to_owned(), json!([
+                column_of("key", &Schema::String)?,
+                column_of("count", &Schema::Long)?,
+                column_of("error", &Schema::Long)?,
+            ]));
+            "REPEATED"
+        },
+        schema => {
+            column_type(schema, &mut column)?;
+            if nullable { "NULLABLE" } else { "REQUIRED" }
+        },
+    };
+    column.insert("mode".to_owned(), json!(mode));
+    if let Some(doc) = doc {
+        column.insert("description".to_owned(), json!(doc));
+    }
+    Ok(JsonValue::Object(column))
+}
+
+fn column_of(name: &str, schema: &Schema) -> Result<JsonValue, AvroError> {
+    column(name, schema, None)
+}
+
+/// Set the `type` of a column holding single values of `schema`, and its `fields` for records.
+fn column_type(schema: &Schema, column: &mut Map<String, JsonValue>) -> Result<(), AvroError> {
+    let kind = match schema {
+        Schema::Boolean => "BOOLEAN",
+        Schema::Int | Schema::Long | Schema::Counter => "INTEGER",
+        Schema::Float | Schema::Double => "FLOAT",
+        Schema::String | Schema::Enum { .. } => "STRING",
+        Schema::Bytes | Schema::Fixed { .. } | Schema::Hll(_) | Schema::Bloom { .. } => "BYTES",
+        Schema::Date(_) => "TIMESTAMP",
+        Schema::Record { fields, .. } => {
+            column.insert("fields".to_owned(), JsonValue::Array(field_columns(fields)?));
+            "RECORD"
+        },
+        other => {
+            return Err(AvroError::Resolution(format!(
+                "{:?} has no BigQuery type",
+                SchemaKind::from(other)
+            )))
+        },
+    };
+    column.insert("type".to_owned(), json!(kind));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bigquery_schema() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "order", "fields": [
+                {"name": "id", "type": "long", "doc": "Order id"},
+                {"name": "at", "type": "date"},
+                {"name": "note", "type": ["null", "string"]},
+                {"name": "tags", "type": {"type": "set", "items": "string"}},
+                {"name": "grid", "type": {"type": "array", "items": {
+                    "type": "array", "items": "double"
+                }}},
+                {"name": "totals", "type": {"type": "map", "values": "double"}},
+                {"name": "line", "type": {"type": "optional", "value": {
+                    "type": "record", "name": "line", "fields": [
+                        {"name": "sku", "type": "string"}
+                    ]
+                }}}
+            ]}"#,
+        ).unwrap();
+        assert_eq!(schema.to_bigquery_schema().unwrap(), json!([
+            {"name": "id", "type": "INTEGER", "mode": "REQUIRED", "description": "Order id"},
+            {"name": "at", "type": "TIMESTAMP", "mode": "REQUIRED"},
+            {"name": "note", "type": "STRING", "mode": "NULLABLE"},
+            {"name": "tags", "type": "STRING", "mode": "REPEATED"},
+            {"name": "grid", "type": "RECORD", "mode": "REPEATED", "fields": [
+                {"name": "item", "type": "FLOAT", "mode": "REPEATED"}
+            ]},
+            {"name": "totals", "type": "RECORD", "mode": "REPEATED", "fields": [
+                {"name": "key", "type": "STRING", "mode": "REQUIRED"},
+                {"name": "value", "type": "FLOAT", "mode": "REQUIRED"}
+            ]},
+            {"name": "line", "type": "RECORD", "mode": "NULLABLE", "fields": [
+                {"name": "sku", "type": "STRING", "mode": "REQUIRED"}
+            ]}
+        ]));
+
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "r", "fields": [
+                {"name": "id", "type": ["long", "string"]}
+            ]}"#,
+        ).unwrap();
+        assert!(schema.to_bigquery_schema().is_err());
+    }
+}
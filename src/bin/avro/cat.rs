@@ -0,0 +1,133 @@
+//! `avro cat`: print the records of Avro files.
+use std::io::Write;
+
+use avro_rs::types::Value;
+use avro_rs::{DisplayOptions, Reader};
+use clap::Args;
+
+use crate::Error;
+
+#[derive(Args, Debug, Default)]
+pub struct CatArgs {
+    /// Avro files to read, `-` for the standard input.
+    #[arg(required = true)]
+    pub files: Vec<String>,
+    /// Print at most this many records, over all the files.
+    #[arg(long)]
+    pub limit: Option<usize>,
+    /// Comma separated fields to print, nested ones as dotted paths such as `user.id`.
+    #[arg(long, value_delimiter = ',')]
+    pub fields: Vec<String>,
+    #[command(flatten)]
+    pub format: Format,
+}
+
+/// How commands printing records print them.
+#[derive(Args, Debug, Default)]
+pub struct Format {
+    /// Print records as JSON Lines instead of text.
+    #[arg(long)]
+    pub json: bool,
+    /// Pretty-print records over several lines, and print text records untruncated.
+    #[arg(long)]
+    pub pretty: bool,
+}
+
+impl Format {
+    /// Print `value` followed by a new line.
+    pub fn print(&self, value: &Value, out: &mut dyn Write) -> Result<(), Error> {
+        match (self.json, self.pretty) {
+            (true, false) => value.write_json(&mut *out)?,
+            (true, true) => serde_json::to_writer_pretty(&mut *out, &value.json())?,
+            (false, false) => write!(out, "{}", value)?,
+            (false, true) => write!(out, "{}", value.display(DisplayOptions::untruncated()))?,
+        }
+        writeln!(out)?;
+        Ok(())
+    }
+}
+
+pub fn run(args: &CatArgs, out: &mut dyn Write) -> Result<(), Error> {
+    let mut remaining = args.limit.unwrap_or(usize::MAX);
+    for path in &args.files {
+        if remaining == 0 {
+            break
+        }
+        let reader = Reader::new(crate::open(path)?)?;
+        for value in reader.take(remaining) {
+            args.format.print(&select(value?, &args.fields), out)?;
+            remaining -= 1;
+        }
+    }
+    Ok(())
+}
+
+/// The `fields` of a record, under their dotted paths, or the whole `value` if none is given.
+/// Missing fields are `null`.
+pub fn select(value: Value, fields: &[String]) -> Value {
+    if fields.is_empty() {
+        return value
+    }
+    let selected = fields
+        .iter()
+        .map(|path| {
+            let field = path
+                .split('.')
+                .try_fold(&value, |value, name| value.field(name))
+                .cloned()
+                .unwrap_or(Value::Null);
+            (path.as_str(), field)
+        })
+        .collect::<Vec<_>>();
+    Value::record(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avro_rs::types::ToAvro;
+    use avro_rs::Schema;
+
+    #[test]
+    fn test_cat() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "user", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "address", "type": {"type": "record", "name": "address", "fields": [
+                    {"name": "city", "type": "string"}
+                ]}}
+            ]}"#,
+        ).unwrap();
+        let values = [(1, "Paris"), (2, "Lyon"), (3, "Nice")]
+            .iter()
+            .map(|(id, city)| {
+                let address = Value::record(vec![("city", city.avro())]);
+                Value::record(vec![("id", Value::Long(*id, None)), ("address", address)])
+            })
+            .collect::<Vec<_>>();
+        let path = crate::tests::write_file("cat", &schema, &values);
+
+        let mut args = CatArgs {
+            files: vec![path.to_str().unwrap().to_owned()],
+            limit: Some(2),
+            format: Format { json: true, pretty: false },
+            ..CatArgs::default()
+        };
+        let mut out = Vec::new();
+        run(&args, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"id\":1,\"address\":{\"city\":\"Paris\"}}\n\
+             {\"id\":2,\"address\":{\"city\":\"Lyon\"}}\n"
+        );
+
+        args.limit = None;
+        args.fields = vec!["address.city".to_owned(), "age".to_owned()];
+        let mut out = Vec::new();
+        run(&args, &mut out).unwrap();
+        let lines = String::from_utf8(out).unwrap();
+        assert_eq!(lines.lines().count(), 3);
+        assert_eq!(lines.lines().last(), Some("{\"address.city\":\"Nice\",\"age\":null}"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,246 @@
+//! `avro diff`: compare the schemas and the records of two Avro files.
+use std::collections::HashMap;
+use std::io::Write;
+
+use avro_rs::types::Value;
+use avro_rs::{Reader, Schema};
+use clap::Args;
+use serde_json::{json, Map, Value as JsonValue};
+
+use crate::Error;
+
+#[derive(Args, Debug, Default)]
+pub struct DiffArgs {
+    /// Avro file of the records before.
+    pub a: String,
+    /// Avro file of the records after.
+    pub b: String,
+    /// Field identifying the records, nested ones as dotted paths such as `user.id`. Records are
+    /// compared by position if not given.
+    #[arg(long)]
+    pub key: Option<String>,
+}
+
+/// Print each difference as a line of JSON, with its `change` and where it happened, and fail if
+/// there is any.
+///
+/// Schema changes are `schema_field_added`, `schema_field_removed` and `schema_field_changed`
+/// for the fields of records of the same name, and `schema_changed` otherwise. Record changes are
+/// `added`, `removed` and `changed`, given the `key` or the `index` of the record; the `fields`
+/// of a changed record tell their values in `a` and in `b`.
+pub fn run(args: &DiffArgs, out: &mut dyn Write) -> Result<(), Error> {
+    let a = Reader::new(crate::open(&args.a)?)?;
+    let b = Reader::new(crate::open(&args.b)?)?;
+
+    let mut changes = schema_changes(a.writer_schema(), b.writer_schema());
+    match args.key {
+        Some(ref key) => keyed_changes(a, b, key, &mut changes)?,
+        None => positional_changes(a, b, &mut changes)?,
+    }
+
+    for change in &changes {
+        writeln!(out, "{}", change)?;
+    }
+    if !changes.is_empty() {
+        return Err(format!("{} differences", changes.len()).into())
+    }
+    Ok(())
+}
+
+fn schema_changes(a: &Schema, b: &Schema) -> Vec<JsonValue> {
+    if a == b {
+        return vec![]
+    }
+    let to_json = |schema: &Schema| serde_json::to_value(schema).unwrap_or(JsonValue::Null);
+    match (a, b) {
+        (
+            Schema::Record { name: a_name, fields: a_fields, .. },
+            Schema::Record { name: b_name, fields: b_fields, .. },
+        ) if a_name == b_name => {
+            let mut changes = vec![];
+            for a_field in a_fields {
+                match b_fields.iter().find(|b_field| b_field.name == a_field.name) {
+                    None => changes.push(json!({
+                        "change": "schema_field_removed",
+                        "field": &*a_field.name,
+                        "schema": to_json(&a_field.schema),
+                    })),
+                    Some(b_field) if b_field.schema != a_field.schema => changes.push(json!({
+                        "change": "schema_field_changed",
+                        "field": &*a_field.name,
+                        "a": to_json(&a_field.schema),
+                        "b": to_json(&b_field.schema),
+                    })),
+                    Some(_) => (),
+                }
+            }
+            for b_field in b_fields {
+                if !a_fields.iter().any(|a_field| a_field.name == b_field.name) {
+                    changes.push(json!({
+                        "change": "schema_field_added",
+                        "field": &*b_field.name,
+                        "schema": to_json(&b_field.schema),
+                    }));
+                }
+            }
+            changes
+        },
+        _ => vec![json!({"change": "schema_changed", "a": to_json(a), "b": to_json(b)})],
+    }
+}
+
+fn positional_changes<R, S>(a: Reader<R>, b: Reader<S>, changes: &mut Vec<JsonValue>)
+    -> Result<(), Error>
+    where
+        R: std::io::Read,
+        S: std::io::Read,
+{
+    let (mut a, mut b) = (a.fuse(), b.fuse());
+    for index in 0.. {
+        let id = ("index", json!(index));
+        match (a.next().transpose()?, b.next().transpose()?) {
+            (Some(a), Some(b)) => changes.extend(changed(id, &a, &b)),
+            (Some(a), None) => changes.push(single("removed", id, &a)),
+            (None, Some(b)) => changes.push(single("added", id, &b)),
+            (None, None) => break,
+        }
+    }
+    Ok(())
+}
+
+fn keyed_changes<R, S>(a: Reader<R>, b: Reader<S>, key: &str, changes: &mut Vec<JsonValue>)
+    -> Result<(), Error>
+    where
+        R: std::io::Read,
+        S: std::io::Read,
+{
+    // the records of `a` left once the ones of `b` are matched are the removed ones, in order
+    let mut removed = vec![];
+    let mut positions = HashMap::new();
+    for value in a {
+        let value = value?;
+        let id = key_of(&value, key)?;
+        if positions.insert(id.to_string(), removed.len()).is_some() {
+            return Err(format!("duplicate key {} in a", id).into())
+        }
+        removed.push(Some((id, value)));
+    }
+
+    let mut seen = HashMap::new();
+    for value in b {
+        let value = value?;
+        let id = key_of(&value, key)?;
+        if seen.insert(id.to_string(), ()).is_some() {
+            return Err(format!("duplicate key {} in b", id).into())
+        }
+        match positions.get(&id.to_string()).and_then(|&position| removed[position].take()) {
+            Some((_, a)) => changes.extend(changed(("key", id), &a, &value)),
+            None => changes.push(single("added", ("key", id), &value)),
+        }
+    }
+    for (id, value) in removed.into_iter().flatten() {
+        changes.push(single("removed", ("key", id), &value));
+    }
+    Ok(())
+}
+
+fn key_of(value: &Value, key: &str) -> Result<JsonValue, Error> {
+    key.split('.')
+        .try_fold(value, |value, name| value.field(name))
+        .map(Value::json)
+        .ok_or_else(|| format!("record without key {}", key).into())
+}
+
+fn single(change: &str, (name, id): (&str, JsonValue), record: &Value) -> JsonValue {
+    json!({"change": change, name: id, "record": record.json()})
+}
+
+fn changed((name, id): (&str, JsonValue), a: &Value, b: &Value) -> Option<JsonValue> {
+    if a == b {
+        return None
+    }
+    let mut change = json!({"change": "changed", name: id});
+    match (a.fields_map(), b.fields_map()) {
+        (Some(a_fields), Some(b_fields)) => {
+            let mut fields = Map::new();
+            for (field, a_value) in &a_fields {
+                let b_value = b_fields.get(field);
+                if b_value != Some(a_value) {
+                    let b_value = b_value.map_or(JsonValue::Null, |value| value.json());
+                    fields.insert(field.to_string(), json!({"a": a_value.json(), "b": b_value}));
+                }
+            }
+            for (field, b_value) in &b_fields {
+                if !a_fields.contains_key(field) {
+                    fields.insert(field.to_string(), json!({"a": null, "b": b_value.json()}));
+                }
+            }
+            change["fields"] = JsonValue::Object(fields);
+        },
+        _ => {
+            change["a"] = a.json();
+            change["b"] = b.json();
+        },
+    }
+    Some(change)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avro_rs::types::ToAvro;
+
+    #[test]
+    fn test_diff() {
+        let a_schema = Schema::parse_str(
+            r#"{"type": "record", "name": "user", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "name", "type": "string"}
+            ]}"#,
+        ).unwrap();
+        let b_schema = Schema::parse_str(
+            r#"{"type": "record", "name": "user", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "name", "type": "string"},
+                {"name": "age", "type": "int", "default": 0}
+            ]}"#,
+        ).unwrap();
+        let user = |id: i64, name: &str| {
+            Value::record(vec![("id", id.avro()), ("name", name.avro())])
+        };
+        let a = crate::tests::write_file("diff-a", &a_schema, &[user(1, "ann"), user(2, "bob")]);
+        let b_values = vec![
+            Value::record(vec![("id", 2i64.avro()), ("name", "bo".avro()), ("age", 3.avro())]),
+            Value::record(vec![("id", 3i64.avro()), ("name", "cy".avro()), ("age", 4.avro())]),
+        ];
+        let b = crate::tests::write_file("diff-b", &b_schema, &b_values);
+
+        let mut args = DiffArgs {
+            a: a.to_str().unwrap().to_owned(),
+            b: b.to_str().unwrap().to_owned(),
+            key: Some("id".to_owned()),
+        };
+        let mut out = Vec::new();
+        assert!(run(&args, &mut out).is_err());
+        let changes = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<JsonValue>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(changes, vec![
+            json!({"change": "schema_field_added", "field": "age", "schema": "int"}),
+            json!({"change": "changed", "key": 2, "fields": {
+                "name": {"a": "bob", "b": "bo"},
+                "age": {"a": null, "b": 3},
+            }}),
+            json!({"change": "added", "key": 3, "record": {"id": 3, "name": "cy", "age": 4}}),
+            json!({"change": "removed", "key": 1, "record": {"id": 1, "name": "ann"}}),
+        ]);
+
+        args.b = args.a.clone();
+        args.key = None;
+        assert!(run(&args, &mut Vec::new()).is_ok());
+        std::fs::remove_file(&a).unwrap();
+        std::fs::remove_file(&b).unwrap();
+    }
+}
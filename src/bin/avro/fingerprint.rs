@@ -0,0 +1,66 @@
+//! `avro fingerprint`: print the parsing canonical form and the fingerprints of a schema.
+use std::io::Write;
+
+use clap::{Args, ValueEnum};
+use md5::Md5;
+use sha2::Sha256;
+
+use crate::Error;
+
+#[derive(Args, Debug, Default)]
+pub struct FingerprintArgs {
+    /// Schema, as a JSON file, `-` for the standard input.
+    pub schema: String,
+    /// Fingerprints to print, all of them by default.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub alg: Vec<Algorithm>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Algorithm {
+    /// 64-bit Rabin fingerprint, as little-endian bytes, as in single object encoding.
+    Rabin,
+    Md5,
+    Sha256,
+}
+
+pub fn run(args: &FingerprintArgs, out: &mut dyn Write) -> Result<(), Error> {
+    let schema = crate::read_schema(&args.schema)?;
+    writeln!(out, "canonical: {}", schema.canonical_form())?;
+    let algorithms = if args.alg.is_empty() {
+        vec![Algorithm::Rabin, Algorithm::Md5, Algorithm::Sha256]
+    } else {
+        args.alg.clone()
+    };
+    for algorithm in algorithms {
+        match algorithm {
+            Algorithm::Rabin => writeln!(out, "rabin: {}", schema.rabin_fingerprint())?,
+            Algorithm::Md5 => writeln!(out, "md5: {}", schema.fingerprint::<Md5>())?,
+            Algorithm::Sha256 => writeln!(out, "sha256: {}", schema.fingerprint::<Sha256>())?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint() {
+        let path = std::env::temp_dir()
+            .join(format!("avro-fingerprint-{}.avsc", std::process::id()));
+        std::fs::write(&path, r#"{"type": "null", "doc": "nothing"}"#).unwrap();
+        let args = FingerprintArgs {
+            schema: path.to_str().unwrap().to_owned(),
+            alg: vec![Algorithm::Rabin],
+        };
+        let mut out = Vec::new();
+        run(&args, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "canonical: \"null\"\nrabin: 8a8f25cce724dd63\n"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,50 @@
+//! `avro getschema`: print the writer schema of an Avro file.
+use std::io::Write;
+
+use avro_rs::Reader;
+use clap::Args;
+
+use crate::Error;
+
+#[derive(Args, Debug, Default)]
+pub struct GetSchemaArgs {
+    /// Avro file to read, `-` for the standard input.
+    pub file: String,
+    /// Print the schema on a single line.
+    #[arg(long)]
+    pub compact: bool,
+}
+
+pub fn run(args: &GetSchemaArgs, out: &mut dyn Write) -> Result<(), Error> {
+    // only the header is read
+    let reader = Reader::new(crate::open(&args.file)?)?;
+    if args.compact {
+        serde_json::to_writer(&mut *out, reader.writer_schema())?;
+    } else {
+        serde_json::to_writer_pretty(&mut *out, reader.writer_schema())?;
+    }
+    writeln!(out)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avro_rs::types::ToAvro;
+    use avro_rs::Schema;
+
+    #[test]
+    fn test_getschema() {
+        let schema = Schema::parse_str(r#"{"type": "array", "items": "long"}"#).unwrap();
+        let path = crate::tests::write_file("getschema", &schema, &[vec![1i64].avro()]);
+        let args = GetSchemaArgs {
+            file: path.to_str().unwrap().to_owned(),
+            compact: true,
+        };
+        let mut out = Vec::new();
+        run(&args, &mut out).unwrap();
+        let printed = Schema::parse_str(&String::from_utf8(out).unwrap()).unwrap();
+        assert_eq!(printed, schema);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,50 @@
+//! `avro head`: print the first records of an Avro file.
+use std::io::Write;
+
+use avro_rs::Reader;
+use clap::Args;
+
+use crate::cat::Format;
+use crate::Error;
+
+#[derive(Args, Debug)]
+pub struct HeadArgs {
+    /// Avro file to read, `-` for the standard input.
+    pub file: String,
+    /// Number of records to print.
+    #[arg(short = 'n', long, default_value_t = 10)]
+    pub count: usize,
+    #[command(flatten)]
+    pub format: Format,
+}
+
+pub fn run(args: &HeadArgs, out: &mut dyn Write) -> Result<(), Error> {
+    let reader = Reader::new(crate::open(&args.file)?)?;
+    for value in reader.take(args.count) {
+        args.format.print(&value?, out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avro_rs::types::ToAvro;
+    use avro_rs::Schema;
+
+    #[test]
+    fn test_head() {
+        let schema = Schema::parse_str(r#""long""#).unwrap();
+        let values = (0..5i64).map(|n| n.avro()).collect::<Vec<_>>();
+        let path = crate::tests::write_file("head", &schema, &values);
+        let args = HeadArgs {
+            file: path.to_str().unwrap().to_owned(),
+            count: 2,
+            format: Format { json: true, pretty: false },
+        };
+        let mut out = Vec::new();
+        run(&args, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "0\n1\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+//! The `avro` command line tool, inspecting and converting Avro files.
+//!
+//! Built with the `cli` feature: `cargo install avro-rs --features cli`.
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::process;
+
+use avro_rs::{AvroError, Codec, Schema};
+use clap::{Parser, Subcommand};
+
+mod cat;
+mod diff;
+mod fingerprint;
+mod getschema;
+mod head;
+mod meta;
+mod random;
+mod recodec;
+mod repair;
+mod sample;
+mod tail;
+mod validate;
+
+/// Error of a command, the library ones being `AvroError`s.
+type Error = Box<dyn std::error::Error>;
+
+/// Inspect and convert Avro files.
+#[derive(Parser, Debug)]
+#[command(name = "avro", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the records of Avro files, as text or as JSON Lines.
+    Cat(cat::CatArgs),
+    /// Print the first records of an Avro file.
+    Head(head::HeadArgs),
+    /// Print the last records of an Avro file, skipping the blocks before them.
+    Tail(tail::TailArgs),
+    /// Print a random sample of the records of an Avro file.
+    Sample(sample::SampleArgs),
+    /// Print the writer schema of an Avro file.
+    Getschema(getschema::GetSchemaArgs),
+    /// Print the codec, sync marker, metadata and block count of an Avro file.
+    Meta(meta::MetaArgs),
+    /// Print the parsing canonical form and the fingerprints of a schema.
+    Fingerprint(fingerprint::FingerprintArgs),
+    /// Rewrite an Avro file with another codec and block size.
+    Recodec(recodec::RecodecArgs),
+    /// Write an Avro file of random records of a schema.
+    Random(random::RandomArgs),
+    /// Check that every record of an Avro file resolves against a reader schema.
+    Validate(validate::ValidateArgs),
+    /// Compare the schemas and the records of two Avro files, as JSON Lines.
+    Diff(diff::DiffArgs),
+    /// Salvage the readable data blocks of a corrupted Avro file.
+    Repair(repair::RepairArgs),
+}
+
+/// Open `path` for reading, `-` being the standard input.
+fn open(path: &str) -> Result<Box<dyn Read>, Error> {
+    if path == "-" {
+        return Ok(Box::new(BufReader::new(io::stdin())))
+    }
+    let file = File::open(path).map_err(|error| format!("{}: {}", path, error))?;
+    Ok(Box::new(BufReader::new(file)))
+}
+
+/// Parse the schema of the JSON file at `path`, `-` being the standard input.
+fn read_schema(path: &str) -> Result<Schema, Error> {
+    let mut json = String::new();
+    open(path)?
+        .read_to_string(&mut json)
+        .map_err(|error| format!("{}: {}", path, error))?;
+    Ok(Schema::parse_str(&json)?)
+}
+
+/// Parse the name of a codec, as found in the header of files, or `zstd` for `zstandard`.
+fn parse_codec(name: &str) -> Result<Codec, String> {
+    match name {
+        "zstd" => "zstandard",
+        name => name,
+    }.parse().map_err(|_| format!("unsupported codec {}", name))
+}
+
+fn run(cli: Cli, out: &mut dyn Write) -> Result<(), Error> {
+    match cli.command {
+        Command::Cat(args) => cat::run(&args, out),
+        Command::Head(args) => head::run(&args, out),
+        Command::Tail(args) => tail::run(&args, out),
+        Command::Sample(args) => sample::run(&args, out),
+        Command::Getschema(args) => getschema::run(&args, out),
+        Command::Meta(args) => meta::run(&args, out),
+        Command::Fingerprint(args) => fingerprint::run(&args, out),
+        Command::Recodec(args) => recodec::run(&args, out),
+        Command::Random(args) => random::run(&args, out),
+        Command::Validate(args) => validate::run(&args, out),
+        Command::Diff(args) => diff::run(&args, out),
+        Command::Repair(args) => repair::run(&args, out),
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let result = run(cli, &mut out);
+    // what was printed before an error is flushed as well
+    let result = out.flush().map_err(Error::from).and(result);
+    if let Err(error) = result {
+        // a closed pipe, as when piping into `head`, is not an error
+        let io_error = match error.downcast_ref::<AvroError>().map(AvroError::root) {
+            Some(AvroError::Io(error)) => Some(error),
+            _ => error.downcast_ref::<io::Error>(),
+        };
+        let broken_pipe = io_error.is_some_and(|error| error.kind() == ErrorKind::BrokenPipe);
+        if !broken_pipe {
+            eprintln!("avro: {}", error);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use avro_rs::types::Value;
+    use avro_rs::{Schema, Writer};
+
+    /// Write `values` as a new Avro file in the temporary directory, of which the name starts
+    /// with `name`, with an `owner` metadata entry.
+    pub fn write_file(name: &str, schema: &Schema, values: &[Value]) -> PathBuf {
+        let mut writer = Writer::new(schema, Vec::new());
+        writer.add_user_metadata("owner", "tests").unwrap();
+        for value in values {
+            writer.append_ref(value).unwrap();
+        }
+        writer.flush().unwrap();
+        let path = std::env::temp_dir().join(format!("avro-{}-{}.avro", name, std::process::id()));
+        std::fs::write(&path, writer.into_inner()).unwrap();
+        path
+    }
+}
@@ -0,0 +1,64 @@
+//! `avro meta`: print the header and the data blocks of an Avro file.
+use std::io::Write;
+use std::str::from_utf8;
+
+use avro_rs::Reader;
+use clap::Args;
+
+use crate::Error;
+
+#[derive(Args, Debug, Default)]
+pub struct MetaArgs {
+    /// Avro file to read, `-` for the standard input.
+    pub file: String,
+}
+
+pub fn run(args: &MetaArgs, out: &mut dyn Write) -> Result<(), Error> {
+    let mut reader = Reader::new(crate::open(&args.file)?)?;
+    writeln!(out, "schema: {}", serde_json::to_string(reader.writer_schema())?)?;
+    writeln!(out, "codec: {:?}", reader.codec())?;
+    writeln!(out, "sync marker: {}", hex(reader.marker()))?;
+
+    let mut metadata = reader.user_metadata().iter().collect::<Vec<_>>();
+    metadata.sort();
+    for (key, value) in metadata {
+        // values are usually text, printed as hexadecimal otherwise
+        match from_utf8(value) {
+            Ok(text) => writeln!(out, "metadata {}: {}", key, text)?,
+            Err(_) => writeln!(out, "metadata {}: 0x{}", key, hex(value))?,
+        }
+    }
+
+    // blocks are read, but neither decompressed nor decoded
+    let counts = reader.count_blocks()?;
+    writeln!(out, "blocks: {}", counts.blocks)?;
+    writeln!(out, "records: {}", counts.records)?;
+    writeln!(out, "block bytes: {}", counts.bytes)?;
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avro_rs::types::ToAvro;
+    use avro_rs::Schema;
+
+    #[test]
+    fn test_meta() {
+        let schema = Schema::parse_str(r#"{"type": "array", "items": "long"}"#).unwrap();
+        let values = vec![vec![1i64].avro(), vec![2i64, 3].avro()];
+        let path = crate::tests::write_file("meta", &schema, &values);
+        let mut out = Vec::new();
+        run(&MetaArgs { file: path.to_str().unwrap().to_owned() }, &mut out).unwrap();
+        let lines = String::from_utf8(out).unwrap();
+        let schema_line = "schema: {\"type\":\"array\",\"items\":\"long\"}\n";
+        assert!(lines.starts_with(&format!("{}codec: Null\n", schema_line)));
+        assert!(lines.contains("metadata owner: tests\n"));
+        assert!(lines.ends_with("blocks: 1\nrecords: 2\nblock bytes: 7\n"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,93 @@
+//! `avro random`: write an Avro file of random records of a schema.
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use avro_rs::{Codec, RandomValues, Writer};
+use clap::Args;
+
+use crate::Error;
+
+#[derive(Args, Debug)]
+pub struct RandomArgs {
+    /// Schema of the records, as a JSON file.
+    #[arg(long)]
+    pub schema: String,
+    /// Number of records to write.
+    #[arg(long, default_value_t = 100)]
+    pub count: usize,
+    /// Seed of the generator, to write the same records again.
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Reference time of the dates and access times, in milliseconds since epoch, the current
+    /// time by default.
+    #[arg(long)]
+    pub now: Option<i64>,
+    /// Codec of the written file: `null`, `deflate` or `snappy`.
+    #[arg(long, default_value = "null", value_parser = crate::parse_codec)]
+    pub codec: Codec,
+    /// Avro file to write, `-` for the standard output.
+    pub output: String,
+}
+
+pub fn run(args: &RandomArgs, out: &mut dyn Write) -> Result<(), Error> {
+    if args.output == "-" {
+        return write_random(args, out)
+    }
+    let mut file = BufWriter::new(File::create(&args.output)?);
+    write_random(args, &mut file)?;
+    file.flush()?;
+    Ok(())
+}
+
+fn write_random(args: &RandomArgs, out: &mut dyn Write) -> Result<(), Error> {
+    let schema = crate::read_schema(&args.schema)?;
+    let mut values = match args.seed {
+        Some(seed) => RandomValues::with_seed(&schema, seed),
+        None => RandomValues::new(&schema),
+    };
+    if let Some(now) = args.now {
+        values.set_now(now);
+    }
+    let mut writer = Writer::with_codec(&schema, out, args.codec);
+    for value in values.take(args.count) {
+        writer.append(value)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avro_rs::Reader;
+
+    #[test]
+    fn test_random() {
+        let schema = std::env::temp_dir().join(format!("avro-random-{}.avsc", std::process::id()));
+        std::fs::write(
+            &schema,
+            r#"{"type": "record", "name": "user", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "tags", "type": {"type": "set", "items": "string"}}
+            ]}"#,
+        ).unwrap();
+        let args = RandomArgs {
+            schema: schema.to_str().unwrap().to_owned(),
+            count: 50,
+            seed: Some(42),
+            now: Some(0),
+            codec: Codec::Deflate,
+            output: "-".to_owned(),
+        };
+        let mut out = Vec::new();
+        run(&args, &mut out).unwrap();
+        let values = Reader::new(&out[..]).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(values.len(), 50);
+
+        let mut again = Vec::new();
+        run(&args, &mut again).unwrap();
+        let read = Reader::new(&again[..]).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(read, values);
+        std::fs::remove_file(&schema).unwrap();
+    }
+}
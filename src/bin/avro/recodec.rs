@@ -0,0 +1,140 @@
+//! `avro recodec`: rewrite an Avro file with another codec and block size.
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use avro_rs::decode::skip;
+use avro_rs::{Codec, Reader, Writer};
+use clap::Args;
+
+use crate::Error;
+
+#[derive(Args, Debug)]
+pub struct RecodecArgs {
+    /// Avro file to read, `-` for the standard input.
+    pub input: String,
+    /// Avro file to write, `-` for the standard output.
+    pub output: String,
+    /// Codec of the written file: `null`, `deflate`, `snappy` or `zstd`.
+    #[arg(long, value_parser = crate::parse_codec)]
+    pub codec: Codec,
+    /// Compression level of the codec, from 0 to 9 for `deflate` and up to 22 for `zstd`, the
+    /// default level of the codec if absent.
+    #[arg(long)]
+    pub level: Option<i32>,
+    /// Number of bytes of records, before compression, of the written data blocks.
+    #[arg(long, default_value_t = 16000)]
+    pub block_size: usize,
+    /// Schema to resolve the records against, as a JSON file. Records are only decoded if it
+    /// differs from the schema of the file.
+    #[arg(long)]
+    pub schema: Option<String>,
+}
+
+pub fn run(args: &RecodecArgs, out: &mut dyn Write) -> Result<(), Error> {
+    if args.output == "-" {
+        return recodec(args, out)
+    }
+    let mut file = BufWriter::new(File::create(&args.output)?);
+    recodec(args, &mut file)?;
+    file.flush()?;
+    Ok(())
+}
+
+fn recodec(args: &RecodecArgs, out: &mut dyn Write) -> Result<(), Error> {
+    let schema = args.schema.as_deref().map(crate::read_schema).transpose()?;
+    let input = crate::open(&args.input)?;
+    let mut reader = match schema {
+        Some(ref schema) => Reader::with_schema(schema, input)?,
+        None => Reader::new(input)?,
+    };
+    let writer_schema = reader.writer_schema().clone();
+    let mut writer = Writer::with_codec(schema.as_ref().unwrap_or(&writer_schema), out, args.codec);
+    writer.set_block_size(args.block_size);
+    if let Some(level) = args.level {
+        writer.set_compression_level(level)?;
+    }
+    for (key, value) in reader.user_metadata() {
+        writer.add_user_metadata(key.clone(), value.clone())?;
+    }
+
+    if schema.as_ref().is_none_or(|schema| *schema == writer_schema) {
+        // the records are copied as they are, only skipped over to find where each one ends
+        while let Some((records, bytes)) = reader.next_block()? {
+            let mut rest = &bytes[..];
+            for _ in 0..records {
+                let datum = rest;
+                skip(&writer_schema, &mut rest)?;
+                writer.append_datum(&datum[..datum.len() - rest.len()])?;
+            }
+        }
+    } else {
+        for value in reader {
+            writer.append(value?)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avro_rs::types::{ToAvro, Value};
+    use avro_rs::Schema;
+
+    #[test]
+    fn test_recodec() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "user", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "name", "type": "string"}
+            ]}"#,
+        ).unwrap();
+        let values = (0..100i64)
+            .map(|id| Value::record(vec![("id", id.avro()), ("name", "someone".avro())]))
+            .collect::<Vec<_>>();
+        let input = crate::tests::write_file("recodec", &schema, &values);
+        let mut args = RecodecArgs {
+            input: input.to_str().unwrap().to_owned(),
+            output: "-".to_owned(),
+            codec: Codec::Deflate,
+            level: None,
+            block_size: 90,
+            schema: None,
+        };
+        let mut out = Vec::new();
+        run(&args, &mut out).unwrap();
+        let mut reader = Reader::new(&out[..]).unwrap();
+        assert_eq!(reader.codec(), Codec::Deflate);
+        assert_eq!(reader.user_metadata()["owner"], b"tests".to_vec());
+        // records of 9 bytes, then of 10 once ids take 2 bytes, in blocks of 90 bytes or more
+        assert_eq!(reader.count_blocks().unwrap().blocks, 11);
+        let read = Reader::new(&out[..]).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(read, values);
+
+        let reader_schema = std::env::temp_dir()
+            .join(format!("avro-recodec-{}.avsc", std::process::id()));
+        std::fs::write(
+            &reader_schema,
+            r#"{"type": "record", "name": "user", "fields": [{"name": "id", "type": "long"}]}"#,
+        ).unwrap();
+        args.schema = Some(reader_schema.to_str().unwrap().to_owned());
+        let mut out = Vec::new();
+        run(&args, &mut out).unwrap();
+        let read = Reader::new(&out[..]).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(read[7], Value::record(vec![("id", 7i64)]));
+
+        args.schema = None;
+        args.codec = crate::parse_codec("zstd").unwrap();
+        args.level = Some(7);
+        let mut out = Vec::new();
+        run(&args, &mut out).unwrap();
+        let reader = Reader::new(&out[..]).unwrap();
+        assert_eq!(reader.codec(), Codec::Zstandard);
+        assert_eq!(reader.collect::<Result<Vec<_>, _>>().unwrap(), values);
+        args.codec = Codec::Null;
+        assert!(run(&args, &mut Vec::new()).is_err());
+        std::fs::remove_file(&input).unwrap();
+        std::fs::remove_file(&reader_schema).unwrap();
+    }
+}
@@ -0,0 +1,226 @@
+//! `avro repair`: salvage the readable data blocks of a corrupted Avro file.
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::ops::Range;
+
+use avro_rs::decode::{decode_long, skip};
+use avro_rs::{Codec, DecodeOptions, Reader, Schema, Writer};
+use clap::Args;
+
+use crate::Error;
+
+#[derive(Args, Debug)]
+pub struct RepairArgs {
+    /// Corrupted Avro file to read, `-` for the standard input. Its header must be readable.
+    pub input: String,
+    /// Avro file to write the salvaged records to, `-` for the standard output.
+    pub output: String,
+}
+
+/// Counts of a repair, printed once done.
+#[derive(Debug, Default, PartialEq)]
+struct Salvage {
+    records: u64,
+    blocks: u64,
+    bytes: u64,
+    skipped_bytes: u64,
+    skipped_regions: u64,
+}
+
+/// Copy the data blocks of which every record decodes, and skip the others up to the next sync
+/// marker, printing the counts of recovered and skipped data. The report goes to the standard
+/// error when the records go to the standard output.
+///
+/// The whole file is read in memory, to be scanned for sync markers.
+pub fn run(args: &RepairArgs, out: &mut dyn Write) -> Result<(), Error> {
+    let mut data = Vec::new();
+    crate::open(&args.input)?.read_to_end(&mut data)?;
+    if args.output == "-" {
+        let salvage = repair(&data, out)?;
+        return report(&salvage, &mut io::stderr())
+    }
+    let mut file = BufWriter::new(File::create(&args.output)?);
+    let salvage = repair(&data, &mut file)?;
+    file.flush()?;
+    report(&salvage, out)
+}
+
+fn report(salvage: &Salvage, out: &mut dyn Write) -> Result<(), Error> {
+    writeln!(
+        out,
+        "recovered {} records in {} blocks ({} bytes), skipped {} bytes in {} damaged regions",
+        salvage.records, salvage.blocks, salvage.bytes, salvage.skipped_bytes,
+        salvage.skipped_regions,
+    )?;
+    Ok(())
+}
+
+fn repair(data: &[u8], out: &mut dyn Write) -> Result<Salvage, Error> {
+    let mut rest = data;
+    let reader = Reader::new(&mut rest)
+        .map_err(|error| format!("unreadable header: {}", error))?;
+    let schema = reader.writer_schema().clone();
+    let marker = *reader.marker();
+    let codec = reader.codec();
+    let mut writer = Writer::with_codec(&schema, out, codec);
+    for (key, value) in reader.user_metadata() {
+        writer.add_user_metadata(key.clone(), value.clone())?;
+    }
+
+    let options = DecodeOptions::default();
+    let mut salvage = Salvage::default();
+    let mut position = data.len() - rest.len();
+    while position < data.len() {
+        match read_block(&data[position..], &schema, codec, &marker, &options) {
+            Some(block) => {
+                for datum in &block.datums {
+                    writer.append_datum(&block.bytes[datum.clone()])?;
+                }
+                salvage.records += block.datums.len() as u64;
+                salvage.blocks += 1;
+                salvage.bytes += block.len as u64;
+                position += block.len;
+            },
+            None => {
+                // the next block starts after the next sync marker, if any
+                let next = data[position..]
+                    .windows(marker.len())
+                    .position(|window| window == marker)
+                    .map_or(data.len(), |offset| position + offset + marker.len());
+                salvage.skipped_bytes += (next - position) as u64;
+                salvage.skipped_regions += 1;
+                position = next;
+            },
+        }
+    }
+    writer.flush()?;
+    Ok(salvage)
+}
+
+/// Data block read by [`read_block`](fn.read_block.html).
+struct Block {
+    /// Length of the block in the file, sync marker included.
+    len: usize,
+    /// Decompressed bytes of the block.
+    bytes: Vec<u8>,
+    /// Ranges of the records of the block in its bytes.
+    datums: Vec<Range<usize>>,
+}
+
+/// The data block at the start of `data`, or `None` if it is cut, not followed by the sync marker,
+/// holds more records than `options` allow or a record which does not decode.
+fn read_block(
+    mut data: &[u8],
+    schema: &Schema,
+    codec: Codec,
+    marker: &[u8; 16],
+    options: &DecodeOptions,
+) -> Option<Block> {
+    let start = data.len();
+    let records = decode_long(&mut data).ok().filter(|&records| records >= 0)? as usize;
+    // records taking no byte, such as nulls, would be counted up to any corrupted count
+    if records > options.max_collection_entries {
+        return None
+    }
+    let size = decode_long(&mut data).ok().filter(|&size| size >= 0)? as usize;
+    if size.checked_add(marker.len())? > data.len() || data[size..size + marker.len()] != marker[..]
+    {
+        return None
+    }
+    let mut bytes = data[..size].to_vec();
+    codec.decompress(&mut bytes).ok()?;
+
+    // a block holds at most a record per byte, unless they take no byte such as nulls
+    if records > bytes.len() && skip(schema, &mut &[][..]).is_err() {
+        return None
+    }
+    let mut datums = Vec::with_capacity(records.min(bytes.len() + 1));
+    let mut rest = &bytes[..];
+    for _ in 0..records {
+        let offset = bytes.len() - rest.len();
+        skip(schema, &mut rest).ok()?;
+        datums.push(offset..bytes.len() - rest.len());
+    }
+    if !rest.is_empty() {
+        return None
+    }
+    let len = start - data.len() + size + marker.len();
+    Some(Block { len, bytes, datums })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avro_rs::types::{ToAvro, Value};
+
+    #[test]
+    fn test_repair() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "user", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "name", "type": "string"}
+            ]}"#,
+        ).unwrap();
+        let values = (0..30i64)
+            .map(|id| Value::record(vec![("id", id.avro()), ("name", "someone".avro())]))
+            .collect::<Vec<_>>();
+        // blocks of 10 records of 9 bytes
+        let mut writer = Writer::with_codec(&schema, Vec::new(), Codec::Deflate);
+        writer.set_block_size(90);
+        for value in &values {
+            writer.append_ref(value).unwrap();
+        }
+        writer.flush().unwrap();
+        let mut data = writer.into_inner();
+        let marker = *Reader::new(&data[..]).unwrap().marker();
+        let markers = data
+            .windows(16)
+            .enumerate()
+            .filter(|(_, window)| *window == marker)
+            .map(|(offset, _)| offset)
+            .collect::<Vec<_>>();
+        assert_eq!(markers.len(), 4);
+        // the second block is overwritten, and the third one cut
+        for byte in &mut data[markers[1] + 16..markers[1] + 20] {
+            *byte = 0xff;
+        }
+        data.truncate(data.len() - 5);
+        let path = std::env::temp_dir().join(format!("avro-repair-{}.avro", std::process::id()));
+        std::fs::write(&path, &data).unwrap();
+
+        let args = RepairArgs { input: path.to_str().unwrap().to_owned(), output: "-".to_owned() };
+        let mut out = Vec::new();
+        run(&args, &mut out).unwrap();
+        let read = Reader::new(&out[..]).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(read, values[..10].to_vec());
+
+        let salvage = repair(&data, &mut Vec::new()).unwrap();
+        assert_eq!(salvage.records, 10);
+        assert_eq!(salvage.blocks, 1);
+        assert_eq!(salvage.bytes, (markers[1] + 16 - markers[0] - 16) as u64);
+        assert_eq!(salvage.skipped_regions, 2);
+        assert_eq!(salvage.skipped_bytes, (data.len() - markers[1] - 16) as u64);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_repair_corrupted_count() {
+        let schema = Schema::parse_str(r#""null""#).unwrap();
+        let mut writer = Writer::new(&schema, Vec::new());
+        writer.extend(vec![Value::Null; 3]).unwrap();
+        writer.flush().unwrap();
+        let data = writer.into_inner();
+        let marker = *Reader::new(&data[..]).unwrap().marker();
+        let header = data.windows(16).position(|window| window == marker).unwrap() + 16;
+
+        // the block of nulls claims to hold as many records as a long can count
+        let mut corrupted = data[..header].to_vec();
+        corrupted.extend_from_slice(&[0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+        corrupted.extend_from_slice(&data[header + 1..]);
+        let salvage = repair(&corrupted, &mut Vec::new()).unwrap();
+        assert_eq!(salvage.records, 0);
+        assert_eq!(salvage.skipped_regions, 1);
+
+        assert_eq!(repair(&data, &mut Vec::new()).unwrap().records, 3);
+    }
+}
@@ -0,0 +1,85 @@
+//! `avro sample`: print a random sample of the records of an Avro file.
+use std::io::Write;
+
+use avro_rs::decode::{decode, skip};
+use avro_rs::Reader;
+use clap::Args;
+use rand::{Rng, SeedableRng, StdRng};
+
+use crate::cat::Format;
+use crate::Error;
+
+#[derive(Args, Debug)]
+pub struct SampleArgs {
+    /// Avro file to read, `-` for the standard input.
+    pub file: String,
+    /// Probability of each record to be printed, between 0 and 1.
+    #[arg(long, value_parser = parse_rate)]
+    pub rate: f64,
+    /// Seed of the generator, to print the same sample again.
+    #[arg(long)]
+    pub seed: Option<u64>,
+    #[command(flatten)]
+    pub format: Format,
+}
+
+fn parse_rate(rate: &str) -> Result<f64, String> {
+    match rate.parse::<f64>() {
+        Ok(rate) if (0.0..=1.0).contains(&rate) => Ok(rate),
+        _ => Err(format!("{} is not a rate between 0 and 1", rate)),
+    }
+}
+
+/// Print each record with the probability `rate`, decoding only the printed ones.
+pub fn run(args: &SampleArgs, out: &mut dyn Write) -> Result<(), Error> {
+    let seed = args.seed.unwrap_or_else(rand::random);
+    let mut rng = StdRng::from_seed(&[seed as usize, (seed >> 32) as usize][..]);
+    let mut reader = Reader::new(crate::open(&args.file)?)?;
+    let schema = reader.writer_schema().clone();
+    while let Some((records, bytes)) = reader.next_block()? {
+        let mut rest = &bytes[..];
+        for _ in 0..records {
+            if rng.gen::<f64>() < args.rate {
+                args.format.print(&decode(&schema, &mut rest)?, out)?;
+            } else {
+                skip(&schema, &mut rest)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avro_rs::types::ToAvro;
+    use avro_rs::Schema;
+
+    #[test]
+    fn test_sample() {
+        let schema = Schema::parse_str(r#""long""#).unwrap();
+        let values = (0..1000i64).map(|n| n.avro()).collect::<Vec<_>>();
+        let path = crate::tests::write_file("sample", &schema, &values);
+        let mut args = SampleArgs {
+            file: path.to_str().unwrap().to_owned(),
+            rate: 0.1,
+            seed: Some(7),
+            format: Format { json: true, pretty: false },
+        };
+        let sample = |args: &SampleArgs| {
+            let mut out = Vec::new();
+            run(args, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let first = sample(&args);
+        assert_eq!(sample(&args), first);
+        let count = first.lines().count();
+        assert!(count > 50 && count < 150);
+        assert!(first.lines().all(|line| line.parse::<i64>().is_ok()));
+
+        args.rate = 1.0;
+        assert_eq!(sample(&args).lines().count(), 1000);
+        assert!(parse_rate("1.5").is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,77 @@
+//! `avro tail`: print the last records of an Avro file.
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, Write};
+
+use avro_rs::Reader;
+use clap::Args;
+
+use crate::cat::Format;
+use crate::Error;
+
+#[derive(Args, Debug)]
+pub struct TailArgs {
+    /// Avro file to read, `-` for the standard input.
+    pub file: String,
+    /// Number of records to print.
+    #[arg(short = 'n', long, default_value_t = 10)]
+    pub count: usize,
+    #[command(flatten)]
+    pub format: Format,
+}
+
+/// Print the last records of a file, only reading the blocks holding them once the counts of the
+/// others are read. The standard input, which cannot be seeked, is read in full.
+pub fn run(args: &TailArgs, out: &mut dyn Write) -> Result<(), Error> {
+    if args.file == "-" {
+        let mut last = VecDeque::with_capacity(args.count.min(1024) + 1);
+        for value in Reader::new(crate::open(&args.file)?)? {
+            last.push_back(value?);
+            if last.len() > args.count {
+                last.pop_front();
+            }
+        }
+        for value in &last {
+            args.format.print(value, out)?;
+        }
+        return Ok(())
+    }
+
+    let file = File::open(&args.file)
+        .map_err(|error| format!("{}: {}", args.file, error))?;
+    let mut reader = Reader::new(BufReader::new(file))?;
+    reader.seek_to_last(args.count)?;
+    for value in reader {
+        args.format.print(&value?, out)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avro_rs::{Schema, Writer};
+
+    #[test]
+    fn test_tail() {
+        let schema = Schema::parse_str(r#""long""#).unwrap();
+        // blocks of 3 records
+        let mut writer = Writer::new(&schema, Vec::new());
+        for chunk in (0..10i64).collect::<Vec<_>>().chunks(3) {
+            writer.extend(chunk.iter().cloned()).unwrap();
+            writer.flush().unwrap();
+        }
+        let path = std::env::temp_dir().join(format!("avro-tail-{}.avro", std::process::id()));
+        std::fs::write(&path, writer.into_inner()).unwrap();
+
+        let args = TailArgs {
+            file: path.to_str().unwrap().to_owned(),
+            count: 4,
+            format: Format { json: true, pretty: false },
+        };
+        let mut out = Vec::new();
+        run(&args, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "6\n7\n8\n9\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
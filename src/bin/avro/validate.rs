@@ -0,0 +1,171 @@
+//! `avro validate`: check that every record of an Avro file resolves against a reader schema.
+use std::io::Write;
+
+use avro_rs::decode::skip;
+use avro_rs::{AvroError, Reader, ResolutionPlan};
+use clap::Args;
+
+use crate::Error;
+
+#[derive(Args, Debug, Default)]
+pub struct ValidateArgs {
+    /// Reader schema the records must resolve against, as a JSON file.
+    #[arg(long)]
+    pub schema: String,
+    /// Avro file to validate, `-` for the standard input.
+    pub file: String,
+    /// Stop after reporting this many failures.
+    #[arg(long)]
+    pub max_errors: Option<usize>,
+    /// Report failures as JSON Lines, with their `record`, `block`, `offset`, `path` and `error`.
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// Record which failed to resolve, at `offset` in the decompressed bytes of its data block.
+struct Failure {
+    record: u64,
+    block: usize,
+    offset: usize,
+    path: String,
+    error: String,
+}
+
+impl Failure {
+    fn new(record: u64, block: usize, offset: usize, error: AvroError) -> Failure {
+        let (path, error) = match error.context() {
+            Some(context) => (context.path().to_owned(), context.error().to_string()),
+            None => (String::new(), error.to_string()),
+        };
+        Failure {
+            record,
+            block,
+            offset,
+            path,
+            error,
+        }
+    }
+
+    fn write(&self, json: bool, out: &mut dyn Write) -> Result<(), Error> {
+        if json {
+            let failure = serde_json::json!({
+                "record": self.record,
+                "block": self.block,
+                "offset": self.offset,
+                "path": self.path,
+                "error": self.error,
+            });
+            writeln!(out, "{}", failure)?;
+        } else {
+            let (record, block, offset) = (self.record, self.block, self.offset);
+            write!(out, "record {} (block {}, byte {}): {}", record, block, offset, self.error)?;
+            if !self.path.is_empty() {
+                write!(out, " at `{}`", self.path)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(())
+    }
+}
+
+/// Validate the records, reporting each failure to `out`, and fail if any record did.
+pub fn run(args: &ValidateArgs, out: &mut dyn Write) -> Result<(), Error> {
+    let schema = crate::read_schema(&args.schema)?;
+    let mut reader = Reader::new(crate::open(&args.file)?)?;
+    let writer_schema = reader.writer_schema().clone();
+    let plan = ResolutionPlan::compile(&writer_schema, &schema);
+    let max_errors = args.max_errors.unwrap_or(usize::MAX);
+
+    let (mut records, mut failures) = (0u64, 0usize);
+    let mut block = 0;
+    // records are decoded one at a time, so that a failure does not stop the validation
+    'blocks: while let Some((count, bytes)) = reader.next_block()? {
+        let mut rest = &bytes[..];
+        let mut index = 0;
+        while index < count {
+            let offset = bytes.len() - rest.len();
+            let mut datum = rest;
+            // past a record which cannot be skipped, the following ones cannot be found
+            let (result, skipped) = match skip(&writer_schema, &mut rest) {
+                Ok(()) => (plan.decode(&mut datum).map(|_| ()), None),
+                Err(error) => (Err(error), Some(count - index)),
+            };
+            if let Err(error) = result {
+                let mut failure = Failure::new(records, block, offset, error);
+                if let Some(left) = skipped {
+                    let error = &failure.error;
+                    failure.error = format!("{} (skipping the {} records left)", error, left);
+                }
+                failure.write(args.json, out)?;
+                failures += 1;
+            }
+            let checked = skipped.unwrap_or(1);
+            records += checked as u64;
+            index += checked;
+            if failures >= max_errors {
+                break 'blocks
+            }
+        }
+        block += 1;
+    }
+
+    if failures > 0 {
+        return Err(format!("{} records of {} failed validation", failures, records).into())
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use avro_rs::types::{ToAvro, Value};
+    use avro_rs::Schema;
+
+    #[test]
+    fn test_validate() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "user", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "age", "type": ["null", "long"]}
+            ]}"#,
+        ).unwrap();
+        let values = vec![
+            Value::record(vec![("id", 1i64.avro()), ("age", Some(30i64).avro())]),
+            Value::record(vec![("id", 2i64.avro()), ("age", None::<i64>.avro())]),
+            Value::record(vec![("id", 3i64.avro()), ("age", Some(40i64).avro())]),
+        ];
+        let path = crate::tests::write_file("validate", &schema, &values);
+        let reader_schema = std::env::temp_dir()
+            .join(format!("avro-validate-{}.avsc", std::process::id()));
+        std::fs::write(
+            &reader_schema,
+            r#"{"type": "record", "name": "user", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "age", "type": "long"}
+            ]}"#,
+        ).unwrap();
+
+        let mut args = ValidateArgs {
+            schema: reader_schema.to_str().unwrap().to_owned(),
+            file: path.to_str().unwrap().to_owned(),
+            json: true,
+            ..ValidateArgs::default()
+        };
+        let mut out = Vec::new();
+        let error = run(&args, &mut out).unwrap_err();
+        assert_eq!(error.to_string(), "1 records of 3 failed validation");
+        let failure: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(failure["record"], 1);
+        assert_eq!(failure["block"], 0);
+        assert_eq!(failure["offset"], 3);
+        assert_eq!(failure["path"], "age");
+
+        args.schema = {
+            std::fs::write(&reader_schema, serde_json::to_string(&schema).unwrap()).unwrap();
+            reader_schema.to_str().unwrap().to_owned()
+        };
+        assert!(run(&args, &mut Vec::new()).is_ok());
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&reader_schema).unwrap();
+    }
+}
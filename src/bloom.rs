@@ -0,0 +1,150 @@
+//! Logic for the Bloom filters held by `bloom` values.
+use crate::error::AvroError;
+use crate::hll::hash;
+
+/// Bloom filter answering whether an item might have been inserted in it.
+///
+/// A filter of `bits` bits using `hashes` hash functions never gives false negatives, and gives
+/// false positives with a probability of about `(1 - e^(-hashes * n / bits))^hashes` once `n`
+/// items are inserted. Items are hashed in a stable way, so filters built by different processes
+/// can be merged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BloomFilter {
+    bits: u32,
+    hashes: u8,
+    bytes: Vec<u8>,
+}
+
+impl BloomFilter {
+    /// Create an empty filter, if both the number of bits and of hash functions are positive.
+    pub fn new(bits: u32, hashes: u8) -> Option<BloomFilter> {
+        if bits == 0 || hashes == 0 {
+            return None
+        }
+        Some(BloomFilter {
+            bits,
+            hashes,
+            bytes: vec![0; Self::byte_len(bits)],
+        })
+    }
+
+    fn byte_len(bits: u32) -> usize {
+        (bits as usize).div_ceil(8)
+    }
+
+    /// Number of bits of the filter.
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// Number of hash functions of the filter.
+    pub fn hashes(&self) -> u8 {
+        self.hashes
+    }
+
+    /// Positions of the bits of an item, obtained by double hashing the two halves of its hash.
+    fn positions<'a>(&self, item: &'a [u8]) -> impl Iterator<Item = usize> + 'a {
+        let h = hash(item);
+        let (h1, h2) = (h & 0xffff_ffff, h >> 32);
+        let bits = u64::from(self.bits);
+        (0..u64::from(self.hashes)).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bits) as usize)
+    }
+
+    /// Insert an item in the filter.
+    pub fn insert<B: AsRef<[u8]> + ?Sized>(&mut self, item: &B) {
+        let positions = self.positions(item.as_ref()).collect::<Vec<_>>();
+        for position in positions {
+            self.bytes[position / 8] |= 1 << (position % 8);
+        }
+    }
+
+    /// Whether an item might have been inserted in the filter. `false` means it certainly was
+    /// not.
+    pub fn might_contain<B: AsRef<[u8]> + ?Sized>(&self, item: &B) -> bool {
+        self.positions(item.as_ref())
+            .all(|position| self.bytes[position / 8] & (1 << (position % 8)) != 0)
+    }
+
+    /// Merge another filter into this one, which then holds the items of both. Fails if the
+    /// parameters of the filters differ.
+    pub fn merge(&mut self, other: &BloomFilter) -> Result<(), AvroError> {
+        if self.bits != other.bits || self.hashes != other.hashes {
+            return Err(AvroError::Resolution(format!(
+                "Cannot merge Bloom filters of {} bits and {} hashes with {} bits and {} hashes",
+                self.bits, self.hashes, other.bits, other.hashes
+            )))
+        }
+        for (byte, other) in self.bytes.iter_mut().zip(other.bytes.iter()) {
+            *byte |= *other;
+        }
+        Ok(())
+    }
+
+    /// Bit array of the filter, the parameters of which are given by its schema.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Build a filter from a bit array returned by [`as_bytes`](#method.as_bytes) and its
+    /// parameters.
+    pub fn from_bytes(bytes: &[u8], bits: u32, hashes: u8) -> Result<BloomFilter, AvroError> {
+        let mut filter = BloomFilter::new(bits, hashes).ok_or_else(|| {
+            AvroError::Decode(
+                format!("Invalid Bloom filter of {} bits and {} hashes", bits, hashes),
+            )
+        })?;
+        if bytes.len() != filter.bytes.len() {
+            return Err(AvroError::Decode(format!(
+                "Bloom filter of {} bits expects {} bytes, got {}",
+                bits,
+                filter.bytes.len(),
+                bytes.len()
+            )))
+        }
+        filter.bytes.copy_from_slice(bytes);
+        Ok(filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_contains() {
+        let mut filter = BloomFilter::new(1024, 4).unwrap();
+        for i in 0..100 {
+            filter.insert(&format!("item-{}", i));
+        }
+        assert!((0..100).all(|i| filter.might_contain(&format!("item-{}", i))));
+
+        let false_positives = (100..1100)
+            .filter(|i| filter.might_contain(&format!("item-{}", i)))
+            .count();
+        assert!(false_positives < 50, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn test_bloom_merge() {
+        let mut a = BloomFilter::new(100, 3).unwrap();
+        let mut b = BloomFilter::new(100, 3).unwrap();
+        a.insert("foo");
+        b.insert("bar");
+
+        a.merge(&b).unwrap();
+        assert!(a.might_contain("foo") && a.might_contain("bar"));
+        assert!(a.merge(&BloomFilter::new(100, 2).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_bloom_bytes() {
+        let mut filter = BloomFilter::new(20, 2).unwrap();
+        filter.insert("foo");
+        assert_eq!(filter.as_bytes().len(), 3);
+        assert_eq!(BloomFilter::from_bytes(filter.as_bytes(), 20, 2).unwrap(), filter);
+
+        assert!(BloomFilter::from_bytes(&[0, 0], 20, 2).is_err());
+        assert!(BloomFilter::from_bytes(&[], 0, 2).is_err());
+        assert!(BloomFilter::new(8, 0).is_none());
+    }
+}
@@ -0,0 +1,86 @@
+//! Logic for Avro values borrowing their bytes and strings from the buffer they are decoded from.
+use std::sync::Arc;
+
+use crate::types::Value;
+
+/// Avro value decoded with [`decode_ref`](decode/fn.decode_ref.html), which borrows its bytes,
+/// strings and fixed values from the decoded buffer instead of copying them, and its enum symbols
+/// and record field names from the `Schema`.
+///
+/// Unlike [Value](enum.Value.html), it holds no `ValueSetting`, as it is not resolved against a
+/// schema. Map entries are kept in the order they were encoded in. `set`, `lru_set`, `topk`,
+/// `hll` and `bloom` values, which must be built to be checked, are decoded into owned values.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValueRef<'a> {
+    Null,
+    Boolean(bool),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    Bytes(&'a [u8]),
+    String(&'a str),
+    Fixed(&'a [u8]),
+    /// Position and symbol of an enum value.
+    Enum(i32, &'a str),
+    Union(Box<ValueRef<'a>>),
+    Array(Vec<ValueRef<'a>>),
+    Map(Vec<(&'a str, ValueRef<'a>)>),
+    Record(Vec<(&'a Arc<str>, ValueRef<'a>)>),
+    Date(i64),
+    Counter(i64),
+    Optional(Option<Box<ValueRef<'a>>>),
+    Owned(Value),
+}
+
+impl<'a> ValueRef<'a> {
+    /// Copy the borrowed parts of the value into an owned [Value](enum.Value.html), equal to the
+    /// one [`decode`](decode/fn.decode.html) gives for the same bytes.
+    pub fn to_value(&self) -> Value {
+        match self {
+            ValueRef::Null => Value::Null,
+            ValueRef::Boolean(b) => Value::Boolean(*b, None),
+            ValueRef::Int(i) => Value::Int(*i, None),
+            ValueRef::Long(l) => Value::Long(*l, None),
+            ValueRef::Float(f) => Value::Float(*f, None),
+            ValueRef::Double(d) => Value::Double(*d, None),
+            ValueRef::Bytes(bytes) => Value::Bytes(bytes.to_vec(), None),
+            ValueRef::String(s) => Value::String((*s).to_owned(), None),
+            ValueRef::Fixed(bytes) => Value::Fixed(bytes.len(), bytes.to_vec(), None),
+            ValueRef::Enum(index, symbol) => Value::Enum(*index, (*symbol).to_owned(), None),
+            ValueRef::Union(inner) => Value::Union(Box::new(inner.to_value()), None),
+            ValueRef::Array(items) => {
+                Value::Array(items.iter().map(ValueRef::to_value).collect(), None)
+            },
+            ValueRef::Map(items) => Value::Map(
+                items
+                    .iter()
+                    .map(|(key, value)| ((*key).to_owned(), value.to_value()))
+                    .collect(),
+                None,
+            ),
+            ValueRef::Record(fields) => Value::Record(
+                fields
+                    .iter()
+                    .map(|(name, value)| (Arc::clone(name), value.to_value()))
+                    .collect(),
+                None,
+            ),
+            ValueRef::Date(t) => Value::Date(*t, None),
+            ValueRef::Counter(n) => Value::Counter(*n, None),
+            ValueRef::Optional(inner) => {
+                Value::Optional(inner.as_ref().map(|inner| Box::new(inner.to_value())), None)
+            },
+            ValueRef::Owned(value) => value.clone(),
+        }
+    }
+}
+
+impl<'a> From<ValueRef<'a>> for Value {
+    fn from(value: ValueRef<'a>) -> Value {
+        match value {
+            ValueRef::Owned(value) => value,
+            value => value.to_value(),
+        }
+    }
+}
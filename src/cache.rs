@@ -0,0 +1,126 @@
+//! Logic for sharing the schemas parsed from identical documents.
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::error::AvroError;
+use crate::schema::Schema;
+use crate::util::rabin_fingerprint;
+
+/// Schemas parsed from each document, by fingerprint of the document.
+type Documents = HashMap<u64, Vec<(String, Arc<Schema>)>>;
+
+lazy_static! {
+    static ref GLOBAL_CACHE: SchemaCache = SchemaCache::new();
+}
+
+/// Cache of parsed schemas, so that parsing a document identical to one parsed before returns the
+/// same shared `Schema` instead of parsing it again.
+///
+/// Documents are keyed by their 64-bit Rabin fingerprint, and compared in full on a match so that
+/// colliding fingerprints never return the wrong schema. Documents are compared as they are, not
+/// in their Parsing Canonical Form, which leaves out the defaults and attributes the schema keeps.
+///
+/// A cache can be created and passed around, or the process-wide one used through
+/// [`SchemaCache::global`](#method.global) and
+/// [`Schema::parse_str_cached`](enum.Schema.html#method.parse_str_cached).
+#[derive(Debug, Default)]
+pub struct SchemaCache {
+    schemas: Mutex<Documents>,
+}
+
+impl SchemaCache {
+    /// Create an empty `SchemaCache`.
+    pub fn new() -> SchemaCache {
+        SchemaCache::default()
+    }
+
+    /// Process-wide `SchemaCache`.
+    pub fn global() -> &'static SchemaCache {
+        &GLOBAL_CACHE
+    }
+
+    /// Create a `Schema` from a string representing a JSON Avro schema, like
+    /// [`Schema::parse_str`](enum.Schema.html#method.parse_str), unless an identical string was
+    /// parsed with this cache before. Documents failing to parse are not cached.
+    pub fn parse_str(&self, input: &str) -> Result<Arc<Schema>, AvroError> {
+        let fingerprint = rabin_fingerprint(input.as_bytes());
+        if let Some(schema) = self.get(fingerprint, input) {
+            return Ok(schema)
+        }
+
+        // parsing is done without the lock held, at the cost of racing parses of a new document
+        let schema = Arc::new(Schema::parse_str(input)?);
+        let mut schemas = self.schemas.lock().unwrap_or_else(|error| error.into_inner());
+        let entries = schemas.entry(fingerprint).or_default();
+        match entries.iter().find(|(document, _)| document == input) {
+            Some((_, cached)) => Ok(cached.clone()),
+            None => {
+                entries.push((input.to_owned(), schema.clone()));
+                Ok(schema)
+            },
+        }
+    }
+
+    /// Number of documents in the cache.
+    pub fn len(&self) -> usize {
+        let schemas = self.schemas.lock().unwrap_or_else(|error| error.into_inner());
+        schemas.values().map(Vec::len).sum()
+    }
+
+    /// Whether the cache holds no document.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Remove every document from the cache. Schemas already handed out stay valid.
+    pub fn clear(&self) {
+        self.schemas.lock().unwrap_or_else(|error| error.into_inner()).clear();
+    }
+
+    fn get(&self, fingerprint: u64, input: &str) -> Option<Arc<Schema>> {
+        let schemas = self.schemas.lock().unwrap_or_else(|error| error.into_inner());
+        schemas
+            .get(&fingerprint)?
+            .iter()
+            .find(|(document, _)| document == input)
+            .map(|(_, schema)| schema.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_cache() {
+        let cache = SchemaCache::new();
+        let document = r#"{"type": "record", "name": "r", "fields": [
+            {"name": "a", "type": "long"}
+        ]}"#;
+
+        let schema = cache.parse_str(document).unwrap();
+        assert_eq!(*schema, Schema::parse_str(document).unwrap());
+        assert!(Arc::ptr_eq(&schema, &cache.parse_str(document).unwrap()));
+        assert_eq!(cache.len(), 1);
+
+        // documents are compared as they are
+        let other = cache.parse_str(&document.replace(": ", ":")).unwrap();
+        assert_eq!(other, schema);
+        assert!(!Arc::ptr_eq(&schema, &other));
+        assert_eq!(cache.len(), 2);
+
+        assert!(cache.parse_str(r#"{"type": "nope"}"#).is_err());
+        assert_eq!(cache.len(), 2);
+
+        cache.clear();
+        assert!(cache.is_empty());
+        assert!(!Arc::ptr_eq(&schema, &cache.parse_str(document).unwrap()));
+    }
+
+    #[test]
+    fn test_schema_parse_str_cached() {
+        let document = r#"{"type": "map", "values": "string"}"#;
+        let schema = Schema::parse_str_cached(document).unwrap();
+        assert!(Arc::ptr_eq(&schema, &SchemaCache::global().parse_str(document).unwrap()));
+    }
+}
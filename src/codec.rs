@@ -1,16 +1,16 @@
 //! Logic for all supported compression codecs in Avro.
 use std::io::{Read, Write};
+use std::ops::RangeInclusive;
 use std::str::FromStr;
 
-use failure::Error;
 use libflate::deflate::{Decoder, Encoder};
 #[cfg(feature = "snappy")]
 use byteorder;
 #[cfg(feature = "snappy")]
 use crc;
 
+use crate::error::AvroError;
 use crate::types::{ToAvro, Value};
-use crate::util::DecodeError;
 
 /// The compression codec used to compress blocks.
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -26,6 +26,10 @@ pub enum Codec {
     /// compression library. Each compressed block is followed by the 4-byte, big-endian
     /// CRC32 checksum of the uncompressed data in the block.
     Snappy,
+    #[cfg(feature = "zstd")]
+    /// The `Zstandard` codec uses Facebook's [Zstandard](https://facebook.github.io/zstd/)
+    /// compression library.
+    Zstandard,
 }
 
 impl ToAvro for Codec {
@@ -36,6 +40,8 @@ impl ToAvro for Codec {
                 Codec::Deflate => "deflate",
                 #[cfg(feature = "snappy")]
                 Codec::Snappy => "snappy",
+                #[cfg(feature = "zstd")]
+                Codec::Zstandard => "zstandard",
             }.to_owned()
                 .into_bytes(),
             None,
@@ -44,7 +50,7 @@ impl ToAvro for Codec {
 }
 
 impl FromStr for Codec {
-    type Err = DecodeError;
+    type Err = AvroError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
@@ -52,20 +58,55 @@ impl FromStr for Codec {
             "deflate" => Ok(Codec::Deflate),
             #[cfg(feature = "snappy")]
             "snappy" => Ok(Codec::Snappy),
-            _ => Err(DecodeError::new("unrecognized codec")),
+            #[cfg(feature = "zstd")]
+            "zstandard" => Ok(Codec::Zstandard),
+            _ => Err(AvroError::Decode("unrecognized codec".to_owned())),
         }
     }
 }
 
 impl Codec {
+    /// Compression levels of the codec, from the fastest to the smallest output, or `None` if it
+    /// has none.
+    pub fn levels(&self) -> Option<RangeInclusive<i32>> {
+        match *self {
+            Codec::Deflate => Some(0..=9),
+            #[cfg(feature = "zstd")]
+            Codec::Zstandard => Some(zstd::compression_level_range()),
+            _ => None,
+        }
+    }
+
     /// Compress a stream of bytes in-place.
-    pub fn compress(&self, stream: &mut Vec<u8>) -> Result<(), Error> {
+    pub fn compress(&self, stream: &mut Vec<u8>) -> Result<(), AvroError> {
+        self.compress_at(stream, None)
+    }
+
+    /// Compress a stream of bytes in-place at the given compression level, or at the default level
+    /// of the codec if `None`. Fails if the level is not one of the [`levels`](#method.levels) of
+    /// the codec.
+    pub fn compress_at(&self, stream: &mut Vec<u8>, level: Option<i32>) -> Result<(), AvroError> {
+        #[cfg(feature = "tracing")]
+        let len = stream.len();
+        if let Some(level) = level {
+            if !self.levels().is_some_and(|levels| levels.contains(&level)) {
+                return Err(AvroError::Decode(format!(
+                    "unsupported compression level {} for codec {:?}", level, self
+                )))
+            }
+        }
         match *self {
             Codec::Null => (),
-            Codec::Deflate => {
-                let mut encoder = Encoder::new(Vec::new());
-                encoder.write_all(stream)?;
-                *stream = encoder.finish().into_result()?;
+            Codec::Deflate => match level {
+                // libflate has no compression levels
+                Some(level) => {
+                    *stream = miniz_oxide::deflate::compress_to_vec(stream, level as u8);
+                },
+                None => {
+                    let mut encoder = Encoder::new(Vec::new());
+                    encoder.write_all(stream)?;
+                    *stream = encoder.finish().into_result()?;
+                },
             },
             #[cfg(feature = "snappy")]
             Codec::Snappy => {
@@ -80,13 +121,21 @@ impl Codec {
 
                 *stream = encoded;
             },
+            #[cfg(feature = "zstd")]
+            Codec::Zstandard => {
+                // 0 is the default level of zstd
+                *stream = zstd::bulk::compress(stream, level.unwrap_or(0))?;
+            },
         };
 
+        trace_event!(trace, codec = ?self, from = len, to = stream.len(), "compressed block");
         Ok(())
     }
 
     /// Decompress a stream of bytes in-place.
-    pub fn decompress(&self, stream: &mut Vec<u8>) -> Result<(), Error> {
+    pub fn decompress(&self, stream: &mut Vec<u8>) -> Result<(), AvroError> {
+        #[cfg(feature = "tracing")]
+        let len = stream.len();
         match *self {
             Codec::Null => (),
             Codec::Deflate => {
@@ -102,6 +151,9 @@ impl Codec {
             Codec::Snappy => {
                 use byteorder::ByteOrder;
 
+                if stream.len() < 4 {
+                    return Err(AvroError::Decode("Snappy block too short for its CRC32".to_owned()))
+                }
                 let decompressed_size = snap::decompress_len(&stream[..stream.len() - 4])?;
                 let mut decoded = vec!(0; decompressed_size);
                 snap::Decoder::new().decompress(&stream[..stream.len() - 4], &mut decoded[..])?;
@@ -110,14 +162,22 @@ impl Codec {
                 let actual_crc = crc::crc32::checksum_ieee(&decoded);
 
                 if expected_crc != actual_crc {
-                    return Err(DecodeError::new(format!("bad Snappy CRC32; expected {:x} but got {:x}",
-                                                        expected_crc,
-                                                        actual_crc)).into());
+                    return Err(AvroError::Decode(format!(
+                        "bad Snappy CRC32; expected {:x} but got {:x}",
+                        expected_crc, actual_crc,
+                    )));
                 }
                 *stream = decoded;
             },
+            #[cfg(feature = "zstd")]
+            Codec::Zstandard => {
+                let mut decoded = Vec::new();
+                zstd::stream::Decoder::new(&stream[..])?.read_to_end(&mut decoded)?;
+                *stream = decoded;
+            },
         };
 
+        trace_event!(trace, codec = ?self, from = len, to = stream.len(), "decompressed block");
         Ok(())
     }
 }
@@ -160,4 +220,33 @@ mod tests {
         codec.decompress(&mut stream).unwrap();
         assert_eq!(INPUT, stream.as_slice());
     }
+
+    #[test]
+    fn deflate_compress_at_levels() {
+        let codec = Codec::Deflate;
+        for level in 0..=9 {
+            let mut stream = INPUT.to_vec();
+            codec.compress_at(&mut stream, Some(level)).unwrap();
+            codec.decompress(&mut stream).unwrap();
+            assert_eq!(INPUT, stream.as_slice());
+        }
+        assert!(codec.compress_at(&mut INPUT.to_vec(), Some(10)).is_err());
+        assert!(Codec::Null.compress_at(&mut INPUT.to_vec(), Some(1)).is_err());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstandard_compress_and_decompress() {
+        let codec = Codec::Zstandard;
+        for level in &[None, Some(1), Some(7), Some(19)] {
+            let mut stream = INPUT.to_vec();
+            codec.compress_at(&mut stream, *level).unwrap();
+            assert_ne!(INPUT, stream.as_slice());
+            assert!(INPUT.len() > stream.len());
+            codec.decompress(&mut stream).unwrap();
+            assert_eq!(INPUT, stream.as_slice());
+        }
+        assert_eq!("zstandard".parse::<Codec>().unwrap(), codec);
+        assert!(codec.compress_at(&mut INPUT.to_vec(), Some(100)).is_err());
+    }
 }
@@ -0,0 +1,703 @@
+//! Logic for generating the Rust types of the values of schemas.
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::AvroError;
+use crate::schema::{LruLimit, Name, Schema, SchemaKind};
+
+const VALUE: &str = "::avro_rs::types::Value";
+const TO_AVRO: &str = "::avro_rs::types::ToAvro";
+const SET_ITEM: &str = "::avro_rs::SetItem";
+
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop",
+    "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "static",
+    "struct", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use", "virtual",
+    "where", "while", "yield",
+];
+
+/// Rust source of the types of the values of `schemas`: a struct for each record, an enum for
+/// each enum and for each union of several non-null types, along with the `ToAvro`
+/// implementations converting them into `Value`s of their schema.
+///
+/// Types are put in nested modules following their namespace, `org.example` types going to
+/// `pub mod org { pub mod example { .. } }`. Named types appearing in several schemas are
+/// generated once.
+///
+/// Avro types map to Rust types as follows:
+///
+/// - `boolean` is `bool`, `int` is `i32`, `long` and `counter` are `i64`, `float` is `f32`,
+///   `double` is `f64`, `string` is `String`, `bytes` and `fixed` are `Vec<u8>`, `null` is `()`
+///   and `date` is an `i64` of milliseconds since epoch;
+/// - `array` and `bounded_list` are `Vec`s, `map` is a `HashMap<String, _>` and `set` a `HashSet`
+///   of its items;
+/// - `lru_set` is a `HashMap<String, LruValue>` of the access time and count of its keys;
+/// - `optional` and unions of `null` and another type are `Option`s of the other type.
+///
+/// The `hll`, `bloom` and `topk` types have no generated counterpart and fail the generation.
+///
+/// The generated code refers to `avro_rs` and derives `Serialize` and `Deserialize`, for the crate
+/// including it to depend on `avro-rs` and on `serde` with its `derive` feature.
+pub fn generate_rust(schemas: &[Schema]) -> Result<String, AvroError> {
+    let mut generator = Generator::default();
+    for schema in schemas {
+        let namespace = schema_name(schema).and_then(|name| namespace(name, None));
+        generator.rust_type(schema, namespace.as_deref(), "Root")?;
+    }
+    let mut code = "// Generated from Avro schemas by avro-rs, do not edit.\n".to_owned();
+    generator.root.render(0, &mut code);
+    Ok(code)
+}
+
+/// Options driving how [`generate`](fn.generate.html) turns a directory of schemas into Rust code.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodegenOptions {
+    /// Name of the file written in the output directory.
+    pub file_name: String,
+    /// Extension of the schema files, without the dot.
+    pub extension: String,
+    /// Whether the schema files of subdirectories are read too.
+    pub recursive: bool,
+    /// Whether the `cargo:rerun-if-changed` directives of the schema directory and files are
+    /// printed, for Cargo to run the build script again when a schema changes.
+    pub rerun_if_changed: bool,
+}
+
+impl Default for CodegenOptions {
+    fn default() -> CodegenOptions {
+        CodegenOptions {
+            file_name: "avro_types.rs".to_owned(),
+            extension: "avsc".to_owned(),
+            recursive: true,
+            rerun_if_changed: true,
+        }
+    }
+}
+
+/// Generate the Rust types of the schema files of `schema_dir`, as by
+/// [`generate_rust`](fn.generate_rust.html), to a file of `out_dir`, returning its path.
+///
+/// Meant for build scripts, along with `include!` in the crate:
+///
+/// ```ignore
+/// // build.rs
+/// avro_rs::generate("schemas", std::env::var("OUT_DIR")?, avro_rs::CodegenOptions::default())?;
+///
+/// // src/lib.rs
+/// include!(concat!(env!("OUT_DIR"), "/avro_types.rs"));
+/// ```
+///
+/// The output only depends on the schemas: files are read in order of their path, and types are
+/// generated in the order the schemas define them. The file is left untouched when it already
+/// holds the generated code, not to trigger the rebuild of the crate including it.
+pub fn generate<P, Q>(
+    schema_dir: P,
+    out_dir: Q,
+    options: CodegenOptions,
+) -> Result<PathBuf, AvroError>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+{
+    let schema_dir = schema_dir.as_ref();
+    let mut paths = Vec::new();
+    schema_files(schema_dir, &options, &mut paths)?;
+    paths.sort();
+
+    let schemas = paths
+        .iter()
+        .map(|path| {
+            let document = fs::read_to_string(path)?;
+            Schema::parse_str(&document).map_err(|error| {
+                AvroError::Resolution(format!("{}: {}", path.display(), error))
+            })
+        })
+        .collect::<Result<Vec<_>, AvroError>>()?;
+    let code = generate_rust(&schemas)?;
+
+    if options.rerun_if_changed {
+        println!("cargo:rerun-if-changed={}", schema_dir.display());
+        for path in &paths {
+            println!("cargo:rerun-if-changed={}", path.display());
+        }
+    }
+    let path = out_dir.as_ref().join(&options.file_name);
+    if fs::read_to_string(&path).ok().as_deref() != Some(code.as_str()) {
+        fs::write(&path, code)?;
+    }
+    Ok(path)
+}
+
+fn schema_files(
+    dir: &Path,
+    options: &CodegenOptions,
+    paths: &mut Vec<PathBuf>,
+) -> Result<(), AvroError> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if options.recursive {
+                schema_files(&path, options, paths)?;
+            }
+        } else if path.extension().is_some_and(|extension| extension == &*options.extension) {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Items and submodules of a generated module.
+#[derive(Default)]
+struct Module {
+    items: Vec<String>,
+    children: BTreeMap<String, Module>,
+}
+
+impl Module {
+    /// Append the items and submodules of the module to `code`, each preceded by a blank line
+    /// but for the first of a submodule.
+    fn render(&self, depth: usize, code: &mut String) {
+        let indent = "    ".repeat(depth);
+        let mut separate = depth == 0;
+        for item in &self.items {
+            if separate {
+                code.push('\n');
+            }
+            separate = true;
+            for line in item.lines() {
+                if !line.is_empty() {
+                    code.push_str(&indent);
+                }
+                code.push_str(line);
+                code.push('\n');
+            }
+        }
+        for (name, child) in &self.children {
+            if separate {
+                code.push('\n');
+            }
+            separate = true;
+            code.push_str(&format!("{}pub mod {} {{\n", indent, name));
+            child.render(depth + 1, code);
+            code.push_str(&format!("{}}}\n", indent));
+        }
+    }
+}
+
+#[derive(Default)]
+struct Generator {
+    root: Module,
+    // generated named types, by full name
+    defined: HashMap<String, Schema>,
+}
+
+impl Generator {
+    /// Rust type of the values of `schema`, used from the module of `namespace`, generating the
+    /// types it refers to. Unions are named after `context`.
+    fn rust_type(
+        &mut self,
+        schema: &Schema,
+        namespace: Option<&str>,
+        context: &str,
+    ) -> Result<String, AvroError> {
+        Ok(match schema {
+            Schema::Null => "()".to_owned(),
+            Schema::Boolean => "bool".to_owned(),
+            Schema::Int => "i32".to_owned(),
+            Schema::Long | Schema::Counter | Schema::Date(_) => "i64".to_owned(),
+            Schema::Float => "f32".to_owned(),
+            Schema::Double => "f64".to_owned(),
+            Schema::String => "String".to_owned(),
+            Schema::Bytes | Schema::Fixed { .. } => "Vec<u8>".to_owned(),
+            Schema::Array(items) | Schema::BoundedList { items, .. } => {
+                format!("Vec<{}>", self.rust_type(items, namespace, context)?)
+            },
+            Schema::Set(items) => format!(
+                "::std::collections::HashSet<{}>",
+                self.rust_type(items, namespace, context)?
+            ),
+            Schema::Map(values) => format!(
+                "::std::collections::HashMap<String, {}>",
+                self.rust_type(values, namespace, context)?
+            ),
+            Schema::LruSet(..) => {
+                "::std::collections::HashMap<String, ::avro_rs::types::LruValue>".to_owned()
+            },
+            Schema::Optional(inner) => {
+                format!("Option<{}>", self.rust_type(inner, namespace, context)?)
+            },
+            Schema::Union(union) => match union.variants() {
+                [Schema::Null, other] | [other, Schema::Null] => {
+                    format!("Option<{}>", self.rust_type(other, namespace, context)?)
+                },
+                variants => {
+                    let name = self.define_union(variants, namespace, context)?;
+                    path_from(namespace, namespace, &name)
+                },
+            },
+            Schema::Record { name, .. } | Schema::Enum { name, .. } => {
+                let target = namespace_of(name, namespace);
+                let type_name = self.define(schema, name, target.as_deref())?;
+                path_from(namespace, target.as_deref(), &type_name)
+            },
+            Schema::Hll(_) | Schema::Bloom { .. } | Schema::TopK(_) => {
+                return Err(AvroError::Resolution(format!(
+                    "{:?} has no generated Rust type",
+                    SchemaKind::from(schema)
+                )))
+            },
+        })
+    }
+
+    /// Generate the type of the record or enum `schema` in the module of `namespace` unless done
+    /// before, returning its name.
+    fn define(
+        &mut self,
+        schema: &Schema,
+        name: &Name,
+        namespace: Option<&str>,
+    ) -> Result<String, AvroError> {
+        let local = name.name.rsplit('.').next().unwrap_or(&name.name);
+        let type_name = type_ident(local);
+        let full_name = match namespace {
+            Some(namespace) => format!("{}.{}", namespace, local),
+            None => local.to_owned(),
+        };
+        match self.defined.get(&full_name) {
+            Some(defined) if defined == schema => return Ok(type_name),
+            Some(_) => {
+                return Err(AvroError::Resolution(format!(
+                    "{} is defined differently by several schemas",
+                    full_name
+                )))
+            },
+            None => self.defined.insert(full_name, schema.clone()),
+        };
+
+        let item = match schema {
+            Schema::Record { doc, fields, .. } => {
+                let mut members = String::new();
+                let mut values = String::new();
+                for field in fields {
+                    let context = format!("{}{}", type_name, type_ident(&field.name));
+                    let field_type = self.rust_type(&field.schema, namespace, &context)?;
+                    let ident = field_ident(&field.name);
+                    if let Some(ref doc) = field.doc {
+                        members.push_str(&doc_comment(doc, "    "));
+                    }
+                    if ident.trim_start_matches("r#") != &*field.name {
+                        members.push_str(&format!("    #[serde(rename = {:?})]\n", field.name));
+                    }
+                    members.push_str(&format!("    pub {}: {},\n", ident, field_type));
+                    values.push_str(&format!(
+                        "            ({:?}.into(), {}),\n",
+                        field.name,
+                        value_expr(&field.schema, &format!("self.{}", ident))?
+                    ));
+                }
+                format!(
+                    "{}#[derive(Clone, Debug, PartialEq, ::serde::Serialize, ::serde::Deserialize)]
+pub struct {name} {{
+{members}}}
+
+impl {to_avro} for {name} {{
+    fn avro(self) -> {value} {{
+        {value}::Record(vec![
+{values}        ], None)
+    }}
+}}
+",
+                    doc.as_ref().map(|doc| doc_comment(doc, "")).unwrap_or_default(),
+                    name = type_name,
+                    members = members,
+                    to_avro = TO_AVRO,
+                    value = VALUE,
+                    values = values,
+                )
+            },
+            Schema::Enum { doc, symbols, .. } => {
+                let mut variants = String::new();
+                let mut arms = String::new();
+                for symbol in symbols {
+                    let variant = type_ident(symbol);
+                    if variant != *symbol {
+                        variants.push_str(&format!("    #[serde(rename = {:?})]\n", symbol));
+                    }
+                    variants.push_str(&format!("    {},\n", variant));
+                    arms.push_str(&format!(
+                        "            {}::{} => {:?},\n",
+                        type_name, variant, symbol
+                    ));
+                }
+                format!(
+                    "{}#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, Hash, ::serde::Serialize, ::serde::Deserialize,
+)]
+pub enum {name} {{
+{variants}}}
+
+impl {name} {{
+    /// Symbol of the value in the schema.
+    pub fn symbol(self) -> &'static str {{
+        match self {{
+{arms}        }}
+    }}
+}}
+
+impl {to_avro} for {name} {{
+    fn avro(self) -> {value} {{
+        {value}::Enum(self as i32, self.symbol().to_owned(), None)
+    }}
+}}
+",
+                    doc.as_ref().map(|doc| doc_comment(doc, "")).unwrap_or_default(),
+                    name = type_name,
+                    variants = variants,
+                    arms = arms,
+                    to_avro = TO_AVRO,
+                    value = VALUE,
+                )
+            },
+            _ => unreachable!(),
+        };
+        self.module(namespace).items.push(item);
+        Ok(type_name)
+    }
+
+    /// Generate the enum of the values of a union of `variants`, named after `context`, in the
+    /// module of `namespace`, returning its name.
+    fn define_union(
+        &mut self,
+        variants: &[Schema],
+        namespace: Option<&str>,
+        context: &str,
+    ) -> Result<String, AvroError> {
+        let mut members = String::new();
+        let mut arms = String::new();
+        for variant in variants {
+            let name = match schema_name(variant) {
+                Some(name) => type_ident(name.name.rsplit('.').next().unwrap_or(&name.name)),
+                None => format!("{:?}", SchemaKind::from(variant)),
+            };
+            if let Schema::Null = variant {
+                members.push_str("    Null,\n");
+                arms.push_str(&format!("            {}::Null => {}::Null,\n", context, VALUE));
+                continue
+            }
+            let variant_type = self.rust_type(variant, namespace, &format!("{}{}", context, name))?;
+            members.push_str(&format!("    {}({}),\n", name, variant_type));
+            arms.push_str(&format!(
+                "            {}::{}(v) => {},\n",
+                context,
+                name,
+                value_expr(variant, "v")?
+            ));
+        }
+        let item = format!(
+            "#[derive(Clone, Debug, PartialEq, ::serde::Serialize, ::serde::Deserialize)]
+#[serde(untagged)]
+pub enum {name} {{
+{members}}}
+
+impl {to_avro} for {name} {{
+    fn avro(self) -> {value} {{
+        let value = match self {{
+{arms}        }};
+        {value}::Union(Box::new(value), None)
+    }}
+}}
+",
+            name = context,
+            members = members,
+            to_avro = TO_AVRO,
+            value = VALUE,
+            arms = arms,
+        );
+        self.module(namespace).items.push(item);
+        Ok(context.to_owned())
+    }
+
+    fn module(&mut self, namespace: Option<&str>) -> &mut Module {
+        let mut module = &mut self.root;
+        for part in namespace.into_iter().flat_map(|namespace| namespace.split('.')) {
+            module = module.children.entry(field_ident(part)).or_default();
+        }
+        module
+    }
+}
+
+fn schema_name(schema: &Schema) -> Option<&Name> {
+    match schema {
+        Schema::Record { name, .. } | Schema::Enum { name, .. } | Schema::Fixed { name, .. } => {
+            Some(name)
+        },
+        _ => None,
+    }
+}
+
+/// Namespace of a named type, given by its full name or its `namespace`, and else the one of the
+/// enclosing type.
+fn namespace_of(name: &Name, enclosing: Option<&str>) -> Option<String> {
+    namespace(name, enclosing.map(ToOwned::to_owned))
+}
+
+fn namespace(name: &Name, enclosing: Option<String>) -> Option<String> {
+    match name.name.rfind('.') {
+        Some(dot) => Some(name.name[..dot].to_owned()),
+        None => name.namespace.clone().or(enclosing),
+    }
+}
+
+/// Path of the type `name` of the module of `to` from the module of `from`.
+fn path_from(from: Option<&str>, to: Option<&str>, name: &str) -> String {
+    if from == to {
+        return name.to_owned()
+    }
+    let depth = from.map_or(0, |from| from.split('.').count());
+    let mut path = "super::".repeat(depth);
+    for part in to.into_iter().flat_map(|to| to.split('.')) {
+        path.push_str(&field_ident(part));
+        path.push_str("::");
+    }
+    path.push_str(name);
+    path
+}
+
+/// Expression converting `expr`, a Rust value of the type of `schema`, into its `Value`.
+fn value_expr(schema: &Schema, expr: &str) -> Result<String, AvroError> {
+    Ok(match schema {
+        Schema::Null => format!("{}::Null", VALUE),
+        Schema::Boolean => format!("{}::Boolean({}, None)", VALUE, expr),
+        Schema::Int => format!("{}::Int({}, None)", VALUE, expr),
+        Schema::Long => format!("{}::Long({}, None)", VALUE, expr),
+        Schema::Counter => format!("{}::Counter({}, None)", VALUE, expr),
+        Schema::Date(_) => format!("{}::Date({}, None)", VALUE, expr),
+        Schema::Float => format!("{}::Float({}, None)", VALUE, expr),
+        Schema::Double => format!("{}::Double({}, None)", VALUE, expr),
+        Schema::String => format!("{}::String({}, None)", VALUE, expr),
+        Schema::Bytes => format!("{}::Bytes({}, None)", VALUE, expr),
+        Schema::Fixed { size, .. } => format!("{}::Fixed({}, {}, None)", VALUE, size, expr),
+        Schema::Record { .. } | Schema::Enum { .. } => format!("{}::avro({})", TO_AVRO, expr),
+        Schema::Array(items) | Schema::BoundedList { items, .. } => format!(
+            "{}::Array({}.into_iter().map(|v| {}).collect(), None)",
+            VALUE,
+            expr,
+            value_expr(items, "v")?
+        ),
+        Schema::Map(values) => format!(
+            "{}::Map({}.into_iter().map(|(k, v)| (k, {})).collect(), None)",
+            VALUE,
+            expr,
+            value_expr(values, "v")?
+        ),
+        Schema::Set(items) => {
+            let item = match **items {
+                Schema::Int => format!("{}::Int(v)", SET_ITEM),
+                Schema::Long => format!("{}::Long(v)", SET_ITEM),
+                Schema::Bytes => format!("{}::Bytes(v)", SET_ITEM),
+                Schema::String => format!("{}::String(v)", SET_ITEM),
+                Schema::Enum { .. } => {
+                    format!("{}::Enum(v as i32, v.symbol().to_owned())", SET_ITEM)
+                },
+                ref other => {
+                    return Err(AvroError::Resolution(format!(
+                        "sets of {:?} have no generated Rust type",
+                        SchemaKind::from(other)
+                    )))
+                },
+            };
+            format!("{}::Set({}.into_iter().map(|v| {}).collect(), None)", VALUE, expr, item)
+        },
+        Schema::LruSet(limit, _) => format!(
+            "{}::LruSet(Box::new({}.into_iter().collect()), {}, None)",
+            VALUE,
+            expr,
+            lru_limit_expr(limit)
+        ),
+        Schema::Optional(inner) => format!(
+            "{}::Optional({}.map(|v| Box::new({})), None)",
+            VALUE,
+            expr,
+            value_expr(inner, "v")?
+        ),
+        Schema::Union(union) => match union.variants() {
+            [Schema::Null, other] | [other, Schema::Null] => format!(
+                "{value}::Union(Box::new(match {} {{ Some(v) => {}, None => {value}::Null }}), \
+                 None)",
+                expr,
+                value_expr(other, "v")?,
+                value = VALUE
+            ),
+            _ => format!("{}::avro({})", TO_AVRO, expr),
+        },
+        Schema::Hll(_) | Schema::Bloom { .. } | Schema::TopK(_) => {
+            return Err(AvroError::Resolution(format!(
+                "{:?} has no generated Rust type",
+                SchemaKind::from(schema)
+            )))
+        },
+    })
+}
+
+fn lru_limit_expr(limit: &LruLimit) -> String {
+    let limit = match limit {
+        LruLimit::Days(n) => format!("Days({})", n),
+        LruLimit::Hour(n) => format!("Hour({})", n),
+        LruLimit::Minute(n) => format!("Minute({})", n),
+        LruLimit::Count(n) => format!("Count({})", n),
+        LruLimit::Bytes(n) => format!("Bytes({})", n),
+        LruLimit::Entries {
+            max_entries,
+            ttl_millis,
+        } => format!(
+            "Entries {{ max_entries: {:?}, ttl_millis: {:?} }}",
+            max_entries, ttl_millis
+        ),
+    };
+    format!("::avro_rs::LruLimit::{}", limit)
+}
+
+fn doc_comment(doc: &str, indent: &str) -> String {
+    doc.lines()
+        .map(|line| format!("{}/// {}\n", indent, line).replace("/// \n", "///\n"))
+        .collect()
+}
+
+/// `CamelCase` Rust identifier of a type or variant named `name`.
+fn type_ident(name: &str) -> String {
+    let mut ident = String::with_capacity(name.len());
+    let mut upper = true;
+    for c in name.chars() {
+        if c == '_' || c == '-' {
+            upper = true;
+        } else if upper {
+            ident.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            ident.push(c);
+        }
+    }
+    match ident.as_str() {
+        "" => "_".to_owned(),
+        "Self" => "Self_".to_owned(),
+        _ => ident,
+    }
+}
+
+/// `snake_case` Rust identifier of a field or module named `name`.
+fn field_ident(name: &str) -> String {
+    let mut ident = String::with_capacity(name.len() + 4);
+    let mut previous_lower = false;
+    for c in name.chars() {
+        if c.is_uppercase() && previous_lower {
+            ident.push('_');
+        }
+        previous_lower = c.is_lowercase() || c.is_ascii_digit();
+        ident.extend(c.to_lowercase());
+    }
+    match ident.as_str() {
+        "self" | "super" | "crate" | "" => format!("{}_", ident),
+        keyword if KEYWORDS.contains(&keyword) => format!("r#{}", ident),
+        _ => ident,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_rust() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "Order", "namespace": "com.shop", "doc": "An order",
+                "fields": [
+                    {"name": "orderId", "type": "long", "doc": "Order id"},
+                    {"name": "type", "type": {
+                        "type": "enum", "name": "kind", "symbols": ["retail", "BULK"]
+                    }},
+                    {"name": "note", "type": {"type": "optional", "value": "string"}},
+                    {"name": "tags", "type": {"type": "set", "items": "string"}},
+                    {"name": "seen", "type": {"type": "lru_set", "max_entries": 10}},
+                    {"name": "payment", "type": ["null", "long", {
+                        "type": "record", "name": "common.Card", "fields": [
+                            {"name": "number", "type": "string"}
+                        ]
+                    }]}
+                ]
+            }"#,
+        ).unwrap();
+        let code = generate_rust(&[schema.clone(), schema]).unwrap();
+
+        assert!(code.starts_with("// Generated from Avro schemas by avro-rs, do not edit.\n"));
+        assert!(code.contains("\npub mod com {\n    pub mod shop {\n"));
+        assert!(code.contains("\npub mod common {\n"));
+        assert_eq!(code.matches("pub struct Order {").count(), 1);
+        for line in &[
+            "        /// An order\n",
+            "            /// Order id\n            #[serde(rename = \"orderId\")]\n            \
+             pub order_id: i64,\n",
+            "            pub r#type: Kind,\n",
+            "            pub note: Option<String>,\n",
+            "            pub tags: ::std::collections::HashSet<String>,\n",
+            "            pub seen: ::std::collections::HashMap<String, \
+             ::avro_rs::types::LruValue>,\n",
+            "            pub payment: OrderPayment,\n",
+            "            #[serde(rename = \"retail\")]\n            Retail,\n            BULK,\n",
+            "            Card(super::super::common::Card),\n",
+            "                    (\"type\".into(), ::avro_rs::types::ToAvro::avro(self.r#type)),\n",
+            "::avro_rs::types::Value::Optional(self.note.map(|v| \
+             Box::new(::avro_rs::types::Value::String(v, None))), None)",
+            "::avro_rs::LruLimit::Entries { max_entries: Some(10), ttl_millis: None }",
+        ] {
+            assert!(code.contains(line), "missing {:?} in\n{}", line, code);
+        }
+
+        let sketch = Schema::parse_str(r#"{"type": "hll", "precision": 10}"#).unwrap();
+        assert!(generate_rust(&[sketch]).is_err());
+    }
+
+    #[test]
+    fn test_generate() {
+        let dir = std::env::temp_dir().join(format!("avro-codegen-{}", std::process::id()));
+        let schema_dir = dir.join("schemas");
+        fs::create_dir_all(schema_dir.join("nested")).unwrap();
+        fs::write(
+            schema_dir.join("b.avsc"),
+            r#"{"type": "record", "name": "B", "fields": [{"name": "a", "type": "int"}]}"#,
+        ).unwrap();
+        fs::write(
+            schema_dir.join("nested").join("a.avsc"),
+            r#"{"type": "enum", "name": "a.A", "symbols": ["X"]}"#,
+        ).unwrap();
+        fs::write(schema_dir.join("notes.txt"), "not a schema").unwrap();
+
+        let options = CodegenOptions {
+            rerun_if_changed: false,
+            ..CodegenOptions::default()
+        };
+        let path = generate(&schema_dir, &dir, options.clone()).unwrap();
+        assert_eq!(path, dir.join("avro_types.rs"));
+        let code = fs::read_to_string(&path).unwrap();
+        assert!(code.find("pub struct B").unwrap() < code.find("pub mod a {").unwrap());
+        assert!(!code.contains("#!["));
+
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        generate(&schema_dir, &dir, options.clone()).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), code);
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+
+        let flat = CodegenOptions {
+            recursive: false,
+            ..options.clone()
+        };
+        assert!(!fs::read_to_string(generate(&schema_dir, &dir, flat).unwrap())
+            .unwrap()
+            .contains("pub enum A"));
+
+        fs::write(schema_dir.join("c.avsc"), "{").unwrap();
+        assert!(generate(&schema_dir, &dir, options).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
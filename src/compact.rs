@@ -0,0 +1,118 @@
+//! Logic for the compaction of the stateful parts of Avro values.
+use crate::error::AvroError;
+use crate::schema::{LruLimit, Schema};
+use crate::types::{compact_lru, Value};
+use crate::visit::PathSegment;
+
+impl Value {
+    /// Evict the entries of a `LruSet` value beyond `limit`, or expired relatively to `now` (in
+    /// milliseconds since epoch) if the limit is time based. The value then holds `limit`.
+    pub fn compact_lru_set(&mut self, limit: &LruLimit, now: i64) -> Result<(), AvroError> {
+        match self {
+            Value::LruSet(items, current, _) => {
+                compact_lru(items, limit, None, now);
+                *current = limit.clone();
+                Ok(())
+            },
+            other => {
+                Err(AvroError::Resolution(format!("LruSet expected, got {:?}", other)))
+            },
+        }
+    }
+
+    /// Compact every stateful value nested in this one, in a single pass guided by the given
+    /// [Schema](../schema/enum.Schema.html), typically before writing a snapshot of the state.
+    ///
+    /// `LruSet` values are evicted according to the limit of their schema, relatively to `now`
+    /// (in milliseconds since epoch), and lose the entries whose count has decayed below one if
+    /// their schema declares a `half_life`. Bounded lists and `TopK` values are truncated to the
+    /// size of their schema. Values not matching their schema are left untouched.
+    pub fn compact_state(&mut self, schema: &Schema, now: i64) {
+        self.walk_mut(schema, &mut |_: &[PathSegment], value: &mut Value, schema: &Schema| {
+            match (value, schema) {
+                (Value::LruSet(items, current, _), Schema::LruSet(limit, decay)) => {
+                    compact_lru(items, limit, *decay, now);
+                    *current = limit.clone();
+                },
+                (
+                    Value::Array(items, _),
+                    Schema::BoundedList {
+                        max_len, truncation, ..
+                    },
+                ) => truncation.apply(items, *max_len),
+                (Value::TopK(topk, _), Schema::TopK(size)) => topk.truncate(*size),
+                _ => (),
+            }
+            true
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topk::TopK;
+    use crate::types::{LruValue, ToAvro};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_compact_lru_set() {
+        let mut items = HashMap::new();
+        items.insert("old".to_owned(), LruValue::new(0, 1));
+        items.insert("new".to_owned(), LruValue::new(60 * 1000, 1));
+        let mut value = Value::LruSet(Box::new(items.clone().into()), LruLimit::Count(10), None);
+
+        value.compact_lru_set(&LruLimit::Minute(1), 90 * 1000).unwrap();
+        items.remove("old");
+        assert_eq!(value, Value::LruSet(Box::new(items.into()), LruLimit::Minute(1), None));
+        assert!(Value::Null.compact_lru_set(&LruLimit::Count(1), 0).is_err());
+    }
+
+    #[test]
+    fn test_compact_state() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "state",
+                "fields": [
+                    {"name": "seen", "type": {"type": "lru_set", "limit": "10", "half_life": 1000}},
+                    {
+                        "name": "events",
+                        "type": {"type": "bounded_list", "items": "int", "max_length": 1}
+                    },
+                    {
+                        "name": "top",
+                        "type": {"type": "optional", "value": {"type": "topk", "size": 1}}
+                    }
+                ]
+            }
+        "#,
+        ).unwrap();
+
+        let mut seen = HashMap::new();
+        seen.insert("stale".to_owned(), LruValue::new(0, 1));
+        seen.insert("fresh".to_owned(), LruValue::new(9000, 1));
+        let mut top = TopK::new(2);
+        top.insert("a", 2);
+        top.insert("b", 1);
+        let topk = Value::TopK(Box::new(top.clone()), None);
+        let mut value = Value::record(vec![
+            ("seen", Value::LruSet(Box::new(seen.clone().into()), LruLimit::Count(10), None)),
+            ("events", vec![1i32, 2i32].avro()),
+            ("top", Value::Optional(Some(Box::new(topk)), None)),
+        ]);
+
+        value.compact_state(&schema, 10_000);
+        seen.remove("stale");
+        top.truncate(1);
+        assert_eq!(
+            value,
+            Value::record(vec![
+                ("seen", Value::LruSet(Box::new(seen.into()), LruLimit::Count(10), None)),
+                ("events", vec![2i32].avro()),
+                ("top", Value::Optional(Some(Box::new(Value::TopK(Box::new(top), None))), None)),
+            ])
+        );
+    }
+}
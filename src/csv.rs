@@ -0,0 +1,427 @@
+//! Logic for reading Avro records from CSV files and writing them as CSV files, enabled by the
+//! `csv` feature.
+//!
+//! Each field of the record schema is a column, named after the field. Fields holding records
+//! are flattened into one column per nested field, named after the path of the field (such as
+//! `address.city`), unless flattening is turned off in the [CsvOptions](struct.CsvOptions.html).
+//!
+//! Cells are coerced to the schema of their column: numbers and booleans are parsed, `date`
+//! cells are parsed as configured by [DateParsing](../struct.DateParsing.html), and written as
+//! RFC 3339 timestamps, enum cells hold the symbol, and `bytes` and `fixed` cells hold
+//! ISO-8859-1 strings. The null cell (empty by default) stands for `null` in `optional` columns
+//! and unions holding `null`; other cells take the first branch of a union they parse as.
+//! Cells of any other type, such as arrays, maps or records left nested, hold their
+//! [Avro JSON encoding](https://avro.apache.org/docs/current/spec.html#json_encoding).
+use std::io::{Read, Write};
+
+use chrono::{SecondsFormat, TimeZone, Utc};
+use serde_json::Value as JsonValue;
+
+use crate::date::DateParsing;
+use crate::error::{at, AvroError};
+use crate::json::default_to_value;
+use crate::schema::{RecordField, Schema, SchemaKind};
+use crate::types::Value;
+use crate::visit::PathSegment;
+
+/// Options driving how CSV files are read by a [CsvReader](struct.CsvReader.html) and written by
+/// a [CsvWriter](struct.CsvWriter.html).
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsvOptions {
+    /// Byte separating the cells of a row.
+    pub delimiter: u8,
+    /// Whether the first row names the columns. Without it, columns are taken in schema order.
+    pub has_headers: bool,
+    /// Whether fields holding records are flattened into one column per nested field, or held
+    /// in a single column as JSON.
+    pub flatten: bool,
+    /// String joining the names of nested fields into the name of a flattened column.
+    pub separator: String,
+    /// Cell standing for `null`.
+    pub null: String,
+    /// How `date` cells are parsed.
+    pub dates: DateParsing,
+}
+
+impl Default for CsvOptions {
+    fn default() -> CsvOptions {
+        CsvOptions {
+            delimiter: b',',
+            has_headers: true,
+            flatten: true,
+            separator: ".".to_owned(),
+            null: String::new(),
+            dates: DateParsing::default(),
+        }
+    }
+}
+
+impl CsvOptions {
+    fn flattens(&self, field: &RecordField) -> bool {
+        self.flatten && matches!(field.schema, Schema::Record { .. })
+    }
+
+    /// Names of the columns of the records of `fields`, prefixed with `prefix`.
+    fn columns(&self, fields: &[RecordField], prefix: &str, columns: &mut Vec<String>) {
+        for field in fields {
+            let name = format!("{}{}", prefix, field.name);
+            match field.schema {
+                Schema::Record { fields: ref inner, .. } if self.flatten => {
+                    self.columns(inner, &format!("{}{}", name, self.separator), columns)
+                },
+                _ => columns.push(name),
+            }
+        }
+    }
+}
+
+fn record_fields(schema: &Schema) -> Result<&[RecordField], AvroError> {
+    match schema {
+        Schema::Record { fields, .. } => Ok(fields),
+        other => Err(AvroError::Resolution(format!(
+            "Record schema expected, got {:?}",
+            SchemaKind::from(other)
+        ))),
+    }
+}
+
+/// Reader of the rows of a CSV file as `Value::Record`s of a record
+/// [Schema](../schema/enum.Schema.html).
+///
+/// Columns are matched to fields by name when the file has headers, and columns not in the
+/// schema are ignored; fields without a column take their default value.
+pub struct CsvReader<'a, R: Read> {
+    schema: &'a Schema,
+    options: CsvOptions,
+    rows: ::csv::StringRecordsIntoIter<R>,
+    // position in a row of the cell of each column, in schema order
+    positions: Vec<Option<usize>>,
+}
+
+impl<'a, R: Read> CsvReader<'a, R> {
+    /// Create a `CsvReader` of the rows of `reader`, reading the header row right away if the
+    /// options tell there is one.
+    pub fn new(schema: &'a Schema, reader: R, options: CsvOptions) -> Result<Self, AvroError> {
+        let mut columns = Vec::new();
+        options.columns(record_fields(schema)?, "", &mut columns);
+        let mut reader = ::csv::ReaderBuilder::new()
+            .delimiter(options.delimiter)
+            .has_headers(options.has_headers)
+            .flexible(true)
+            .from_reader(reader);
+        let positions = if options.has_headers {
+            let headers = reader.headers()?;
+            columns
+                .iter()
+                .map(|column| headers.iter().position(|header| header == column))
+                .collect()
+        } else {
+            (0..columns.len()).map(Some).collect()
+        };
+        Ok(CsvReader {
+            schema,
+            options,
+            rows: reader.into_records(),
+            positions,
+        })
+    }
+
+    fn read_row(&self, row: &::csv::StringRecord) -> Result<Value, AvroError> {
+        let mut cells = self.positions.iter().map(|position| position.and_then(|i| row.get(i)));
+        read_record(record_fields(self.schema)?, &self.options, &mut cells)
+    }
+}
+
+impl<'a, R: Read> Iterator for CsvReader<'a, R> {
+    type Item = Result<Value, AvroError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = match self.rows.next()? {
+            Ok(row) => row,
+            Err(error) => return Some(Err(error.into())),
+        };
+        Some(self.read_row(&row))
+    }
+}
+
+fn read_record<'r, I>(
+    fields: &[RecordField],
+    options: &CsvOptions,
+    cells: &mut I,
+) -> Result<Value, AvroError>
+where
+    I: Iterator<Item = Option<&'r str>>,
+{
+    let mut items = Vec::with_capacity(fields.len());
+    for field in fields {
+        let value = match field.schema {
+            Schema::Record { fields: ref inner, .. } if options.flattens(field) => {
+                read_record(inner, options, cells)
+            },
+            _ => match (cells.next().flatten(), &field.default) {
+                (Some(cell), _) => read_cell(cell, &field.schema, options),
+                (None, Some(default)) => default_to_value(default, &field.schema),
+                (None, None) => Err(AvroError::Resolution("missing column".to_owned())),
+            },
+        };
+        items.push((field.name.clone(), at(value, PathSegment::Field(&field.name))?));
+    }
+    Ok(Value::Record(items, None))
+}
+
+fn read_cell(cell: &str, schema: &Schema, options: &CsvOptions) -> Result<Value, AvroError> {
+    let unexpected = || -> AvroError {
+        AvroError::Resolution(format!(
+            "cannot read {:?} as {:?}",
+            cell,
+            SchemaKind::from(schema)
+        ))
+    };
+    let is_null = cell == options.null;
+    match schema {
+        Schema::Null if is_null => Ok(Value::Null),
+        Schema::Optional(_) if is_null => Ok(Value::Optional(None, None)),
+        Schema::Optional(inner) => {
+            Ok(Value::Optional(Some(Box::new(read_cell(cell, inner, options)?)), None))
+        },
+        Schema::Union(union) if is_null && union.is_nullable() => {
+            Ok(Value::Union(Box::new(Value::Null), None))
+        },
+        Schema::Union(union) => union
+            .variants()
+            .iter()
+            .find_map(|branch| read_cell(cell, branch, options).ok())
+            .map(|value| Value::Union(Box::new(value), None))
+            .ok_or_else(unexpected),
+        Schema::Boolean => match cell.trim().to_lowercase().as_str() {
+            "true" | "1" => Ok(Value::Boolean(true, None)),
+            "false" | "0" => Ok(Value::Boolean(false, None)),
+            _ => Err(unexpected()),
+        },
+        Schema::Int => cell.trim().parse().map(|n| Value::Int(n, None)).map_err(|_| unexpected()),
+        Schema::Long => {
+            cell.trim().parse().map(|n| Value::Long(n, None)).map_err(|_| unexpected())
+        },
+        Schema::Counter => {
+            cell.trim().parse().map(|n| Value::Counter(n, None)).map_err(|_| unexpected())
+        },
+        Schema::Float => {
+            cell.trim().parse().map(|x| Value::Float(x, None)).map_err(|_| unexpected())
+        },
+        Schema::Double => {
+            cell.trim().parse().map(|x| Value::Double(x, None)).map_err(|_| unexpected())
+        },
+        Schema::String => Ok(Value::String(cell.to_owned(), None)),
+        Schema::Enum { symbols, .. } => symbols
+            .iter()
+            .position(|symbol| symbol == cell)
+            .map(|i| Value::Enum(i as i32, cell.to_owned(), None))
+            .ok_or_else(unexpected),
+        Schema::Date(_) => options
+            .dates
+            .parse(cell.trim())
+            .map(|t| Value::Date(t, None))
+            .ok_or_else(unexpected),
+        Schema::Bytes | Schema::Fixed { .. } => {
+            Value::from_avro_json(&JsonValue::String(cell.to_owned()), schema)
+        },
+        Schema::Null => Err(unexpected()),
+        _ => Value::from_avro_json(&serde_json::from_str(cell)?, schema),
+    }
+}
+
+/// Writer of `Value::Record`s of a record [Schema](../schema/enum.Schema.html) as the rows of a
+/// CSV file.
+pub struct CsvWriter<'a, W: Write> {
+    schema: &'a Schema,
+    options: CsvOptions,
+    writer: ::csv::Writer<W>,
+}
+
+impl<'a, W: Write> CsvWriter<'a, W> {
+    /// Create a `CsvWriter` writing rows to `writer`, starting with the header row if the
+    /// options tell there is one.
+    pub fn new(schema: &'a Schema, writer: W, options: CsvOptions) -> Result<Self, AvroError> {
+        let mut writer = ::csv::WriterBuilder::new()
+            .delimiter(options.delimiter)
+            .from_writer(writer);
+        if options.has_headers {
+            let mut columns = Vec::new();
+            options.columns(record_fields(schema)?, "", &mut columns);
+            writer.write_record(&columns)?;
+        }
+        Ok(CsvWriter {
+            schema,
+            options,
+            writer,
+        })
+    }
+
+    /// Write a value as a row, once brought to the canonical form of the schema with
+    /// [`Value::normalize`](../types/enum.Value.html#method.normalize).
+    pub fn write(&mut self, value: &Value) -> Result<(), AvroError> {
+        let value = value.clone().normalize(self.schema)?;
+        let mut cells = Vec::new();
+        write_record(&value, record_fields(self.schema)?, &self.options, &mut cells)?;
+        self.writer.write_record(&cells)?;
+        Ok(())
+    }
+
+    /// Flush the rows written so far to the underlying writer.
+    pub fn flush(&mut self) -> Result<(), AvroError> {
+        Ok(self.writer.flush()?)
+    }
+
+    /// Flush the rows written so far and return the underlying writer.
+    pub fn into_inner(self) -> Result<W, AvroError> {
+        self.writer
+            .into_inner()
+            .map_err(|error| error.into_error().into())
+    }
+}
+
+fn write_record(
+    value: &Value,
+    fields: &[RecordField],
+    options: &CsvOptions,
+    cells: &mut Vec<String>,
+) -> Result<(), AvroError> {
+    let items = match value {
+        Value::Record(items, _) => items,
+        _ => unreachable!("normalized against a record schema"),
+    };
+    for (field, (_, item)) in fields.iter().zip(items) {
+        let written = match field.schema {
+            Schema::Record { fields: ref inner, .. } if options.flattens(field) => {
+                write_record(item, inner, options, cells)
+            },
+            _ => write_cell(item, &field.schema, options).map(|cell| cells.push(cell)),
+        };
+        at(written, PathSegment::Field(&field.name))?;
+    }
+    Ok(())
+}
+
+fn write_cell(value: &Value, schema: &Schema, options: &CsvOptions) -> Result<String, AvroError> {
+    match (value, schema) {
+        (Value::Null, _) | (Value::Optional(None, _), _) => Ok(options.null.clone()),
+        (Value::Optional(Some(inner), _), Schema::Optional(inner_schema)) => {
+            write_cell(inner, inner_schema, options)
+        },
+        (Value::Union(inner, _), Schema::Union(union)) => match union.find_schema(inner) {
+            Some((_, branch)) => write_cell(inner, branch, options),
+            None => Err(AvroError::Resolution(format!("{:?} not in the union", inner))),
+        },
+        (Value::Boolean(b, _), _) => Ok(b.to_string()),
+        (Value::Int(n, _), _) => Ok(n.to_string()),
+        (Value::Long(n, _), _) | (Value::Counter(n, _), _) => Ok(n.to_string()),
+        (Value::Float(x, _), _) => Ok(x.to_string()),
+        (Value::Double(x, _), _) => Ok(x.to_string()),
+        (Value::String(s, _), _) | (Value::Enum(_, s, _), _) => Ok(s.clone()),
+        (Value::Date(t, _), _) => Utc
+            .timestamp_millis_opt(*t)
+            .single()
+            .map(|dt| dt.to_rfc3339_opts(SecondsFormat::Millis, true))
+            .ok_or_else(|| AvroError::Resolution(format!("date {} out of range", t))),
+        (value, schema) => match value.to_avro_json(schema)? {
+            JsonValue::String(s) => Ok(s),
+            json => Ok(json.to_string()),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ToAvro;
+
+    #[test]
+    fn test_csv_round_trip() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "r", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "at", "type": "date"},
+                {"name": "address", "type": {"type": "record", "name": "a", "fields": [
+                    {"name": "city", "type": "string"},
+                    {"name": "zip", "type": ["null", "int", "string"]}
+                ]}},
+                {"name": "tags", "type": {"type": "array", "items": "string"}},
+                {"name": "active", "type": "boolean", "default": true}
+            ]}"#,
+        ).unwrap();
+        let input = "\
+id,at,address.city,address.zip,tags,extra
+1,2020-01-02T03:04:05Z,Paris,75001,\"[\"\"a\"\",\"\"b\"\"]\",x
+2,2020-01-02T03:04:05.250+01:00,\"Lyon, FR\",,[],y
+3,2020-01-02T00:00:00Z,Nice,n/a,[],z
+";
+        let values = CsvReader::new(&schema, input.as_bytes(), CsvOptions::default())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let address = |city: &str, zip: Value| {
+            Value::Record(vec![
+                ("city".into(), city.avro()),
+                ("zip".into(), Value::Union(Box::new(zip), None)),
+            ], None)
+        };
+        assert_eq!(values.len(), 3);
+        assert_eq!(values[0], Value::Record(vec![
+            ("id".into(), Value::Long(1, None)),
+            ("at".into(), Value::Date(1_577_934_245_000, None)),
+            ("address".into(), address("Paris", Value::Int(75001, None))),
+            ("tags".into(), Value::Array(vec!["a".avro(), "b".avro()], None)),
+            ("active".into(), Value::Boolean(true, None)),
+        ], None));
+        if let Value::Record(ref items, _) = values[1] {
+            assert_eq!(items[1].1, Value::Date(1_577_930_645_250, None));
+            assert_eq!(items[2].1, address("Lyon, FR", Value::Null));
+        }
+        if let Value::Record(ref items, _) = values[2] {
+            assert_eq!(items[2].1, address("Nice", "n/a".avro()));
+        }
+
+        let mut writer = CsvWriter::new(&schema, Vec::new(), CsvOptions::default()).unwrap();
+        for value in &values {
+            writer.write(value).unwrap();
+        }
+        let written = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            written.lines().take(2).collect::<Vec<_>>(),
+            vec![
+                "id,at,address.city,address.zip,tags,active",
+                "1,2020-01-02T03:04:05.000Z,Paris,75001,\"[\"\"a\"\",\"\"b\"\"]\",true",
+            ]
+        );
+        let read = CsvReader::new(&schema, written.as_bytes(), CsvOptions::default())
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(read, values);
+
+        // nested records held as JSON, without headers
+        let options = CsvOptions {
+            flatten: false,
+            has_headers: false,
+            ..CsvOptions::default()
+        };
+        let mut writer = CsvWriter::new(&schema, Vec::new(), options.clone()).unwrap();
+        writer.write(&values[1]).unwrap();
+        let written = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert!(written.contains(r#""{""city"":""Lyon, FR"",""zip"":null}""#), "{}", written);
+        let read = CsvReader::new(&schema, written.as_bytes(), options)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(read, vec![values[1].clone()]);
+
+        // errors tell the column
+        let input = "id,at,address.city,tags\n1,yesterday,Paris,[]\n";
+        let error = CsvReader::new(&schema, input.as_bytes(), CsvOptions::default())
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(error.context().unwrap().path(), "at");
+    }
+}
@@ -0,0 +1,228 @@
+//! Logic for the parsing of Avro date values from strings, and their bucketing.
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+use crate::error::AvroError;
+use crate::types::Value;
+
+/// Describes how strings are parsed into `date` values during schema resolution.
+///
+/// RFC 3339 and RFC 2822 timestamps are always accepted; the default options accept nothing
+/// else.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DateParsing {
+    /// Additional [strftime](https://docs.rs/chrono/0.4/chrono/format/strftime/index.html)
+    /// formats, tried in order. Formats may describe a date and time, with or without an offset,
+    /// or only a date, taken at midnight.
+    pub formats: Vec<String>,
+    /// Whether strings made of digits are read as epoch timestamps: in seconds if they are below
+    /// 10^11 (which would be early 1973 in milliseconds), in milliseconds otherwise.
+    pub detect_epoch: bool,
+    /// Offset of the timestamps without one, UTC by default.
+    pub default_offset: FixedOffset,
+}
+
+impl Default for DateParsing {
+    fn default() -> DateParsing {
+        DateParsing {
+            formats: Vec::new(),
+            detect_epoch: false,
+            default_offset: FixedOffset::east_opt(0).unwrap(),
+        }
+    }
+}
+
+const EPOCH_SECONDS_BOUND: i64 = 100_000_000_000;
+
+impl DateParsing {
+    /// Parse a string into a number of milliseconds since epoch, if any of the accepted formats
+    /// matches it.
+    pub fn parse(&self, s: &str) -> Option<i64> {
+        if self.detect_epoch {
+            if let Ok(t) = s.parse::<i64>() {
+                return Some(if t.abs() < EPOCH_SECONDS_BOUND { t * 1000 } else { t })
+            }
+        }
+
+        if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+            return Some(dt.timestamp_millis())
+        }
+        if let Ok(dt) = DateTime::parse_from_rfc2822(s) {
+            return Some(dt.timestamp_millis())
+        }
+
+        self.formats.iter().find_map(|format| self.parse_with(s, format))
+    }
+
+    fn parse_with(&self, s: &str, format: &str) -> Option<i64> {
+        if let Ok(dt) = DateTime::parse_from_str(s, format) {
+            return Some(dt.timestamp_millis())
+        }
+        let naive = NaiveDateTime::parse_from_str(s, format)
+            .ok()
+            .or_else(|| {
+                NaiveDate::parse_from_str(s, format)
+                    .ok()
+                    .and_then(|date| date.and_hms_opt(0, 0, 0))
+            })?;
+        self.default_offset
+            .from_local_datetime(&naive)
+            .single()
+            .map(|dt| dt.timestamp_millis())
+    }
+}
+
+const HOUR_MILLIS: i64 = 60 * 60 * 1000;
+const DAY_MILLIS: i64 = 24 * HOUR_MILLIS;
+const WEEK_MILLIS: i64 = 7 * DAY_MILLIS;
+/// Epoch was on a Thursday: weeks start three days before.
+const WEEK_START_MILLIS: i64 = -3 * DAY_MILLIS;
+
+/// Time bucket in which `date` values are aggregated, in UTC. Weeks start on Monday, as ISO 8601
+/// weeks do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DateBucket {
+    Hour,
+    Day,
+    Week,
+}
+
+impl DateBucket {
+    fn millis(self) -> i64 {
+        match self {
+            DateBucket::Hour => HOUR_MILLIS,
+            DateBucket::Day => DAY_MILLIS,
+            DateBucket::Week => WEEK_MILLIS,
+        }
+    }
+
+    fn origin(self) -> i64 {
+        match self {
+            DateBucket::Hour | DateBucket::Day => 0,
+            DateBucket::Week => WEEK_START_MILLIS,
+        }
+    }
+
+    /// Index of the bucket containing a timestamp in milliseconds, counted from the bucket
+    /// containing epoch.
+    pub fn index(self, t: i64) -> i64 {
+        t.saturating_sub(self.origin()).div_euclid(self.millis())
+    }
+
+    /// Start of the bucket containing a timestamp in milliseconds.
+    pub fn truncate(self, t: i64) -> i64 {
+        self.index(t)
+            .saturating_mul(self.millis())
+            .saturating_add(self.origin())
+    }
+
+    /// Key of the bucket containing a timestamp in milliseconds: `2020-01-31T13` for hours,
+    /// `2020-01-31` for days and `2020-W05` (ISO week) for weeks. `None` if the timestamp is out
+    /// of the range of representable dates.
+    pub fn key(self, t: i64) -> Option<String> {
+        let format = match self {
+            DateBucket::Hour => "%Y-%m-%dT%H",
+            DateBucket::Day => "%Y-%m-%d",
+            DateBucket::Week => "%G-W%V",
+        };
+        Utc.timestamp_millis_opt(t)
+            .single()
+            .map(|date| date.format(format).to_string())
+    }
+}
+
+impl Value {
+    /// Truncate a `Date` value to the start of its bucket. The `ValueSetting` of the value is
+    /// kept.
+    pub fn truncate_date(self, bucket: DateBucket) -> Result<Value, AvroError> {
+        match self {
+            Value::Date(t, setting) => Ok(Value::Date(bucket.truncate(t), setting)),
+            other => {
+                Err(AvroError::Resolution(format!("Date expected, got {:?}", other)))
+            },
+        }
+    }
+
+    /// Index of the bucket of a `Date` value, as per
+    /// [`DateBucket::index`](../enum.DateBucket.html#method.index).
+    pub fn date_bucket_index(&self, bucket: DateBucket) -> Option<i64> {
+        match self {
+            Value::Date(t, _) => Some(bucket.index(*t)),
+            _ => None,
+        }
+    }
+
+    /// Key of the bucket of a `Date` value, as per
+    /// [`DateBucket::key`](../enum.DateBucket.html#method.key).
+    pub fn date_bucket_key(&self, bucket: DateBucket) -> Option<String> {
+        match self {
+            Value::Date(t, _) => bucket.key(*t),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_default() {
+        let parsing = DateParsing::default();
+        assert_eq!(parsing.parse("1970-01-01T00:00:01Z"), Some(1000));
+        assert_eq!(parsing.parse("Thu, 01 Jan 1970 00:00:01 +0000"), Some(1000));
+        assert_eq!(parsing.parse("1970-01-01 00:00:01"), None);
+        assert_eq!(parsing.parse("1000"), None);
+    }
+
+    #[test]
+    fn test_parse_formats() {
+        let parsing = DateParsing {
+            formats: vec!["%Y-%m-%d %H:%M:%S".to_owned(), "%d/%m/%Y".to_owned()],
+            detect_epoch: false,
+            default_offset: FixedOffset::east_opt(3600).unwrap(),
+        };
+        assert_eq!(parsing.parse("1970-01-01 01:00:01"), Some(1000));
+        assert_eq!(parsing.parse("02/01/1970"), Some(23 * 3600 * 1000));
+        assert_eq!(parsing.parse("1970-01-01T00:00:01Z"), Some(1000));
+        assert_eq!(parsing.parse("01-01-1970"), None);
+    }
+
+    #[test]
+    fn test_parse_epoch() {
+        let parsing = DateParsing {
+            detect_epoch: true,
+            ..DateParsing::default()
+        };
+        assert_eq!(parsing.parse("1500000000"), Some(1_500_000_000_000));
+        assert_eq!(parsing.parse("1500000000123"), Some(1_500_000_000_123));
+        assert_eq!(parsing.parse("-60"), Some(-60_000));
+    }
+
+    #[test]
+    fn test_date_buckets() {
+        // Friday 2020-01-31T13:45:00Z
+        let t = 1_580_478_300_000;
+        assert_eq!(DateBucket::Hour.truncate(t), 1_580_475_600_000);
+        assert_eq!(DateBucket::Day.truncate(t), 1_580_428_800_000);
+        assert_eq!(DateBucket::Week.truncate(t), 1_580_083_200_000);
+        assert_eq!(DateBucket::Hour.key(t).unwrap(), "2020-01-31T13");
+        assert_eq!(DateBucket::Day.key(t).unwrap(), "2020-01-31");
+        assert_eq!(DateBucket::Week.key(t).unwrap(), "2020-W05");
+
+        assert_eq!(DateBucket::Day.index(0), 0);
+        assert_eq!(DateBucket::Day.index(-1), -1);
+        assert_eq!(DateBucket::Week.index(-1), 0);
+        assert_eq!(DateBucket::Week.truncate(0), -3 * DAY_MILLIS);
+    }
+
+    #[test]
+    fn test_date_value_buckets() {
+        let date = Value::Date(DAY_MILLIS + 1, None);
+        assert_eq!(date.date_bucket_index(DateBucket::Day), Some(1));
+        assert_eq!(date.date_bucket_key(DateBucket::Day).unwrap(), "1970-01-02");
+        assert_eq!(date.truncate_date(DateBucket::Day).unwrap(), Value::Date(DAY_MILLIS, None));
+
+        assert_eq!(Value::Long(0, None).date_bucket_index(DateBucket::Day), None);
+        assert!(Value::Long(0, None).truncate_date(DateBucket::Day).is_err());
+    }
+}
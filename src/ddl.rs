@@ -0,0 +1,264 @@
+//! Logic for generating the SQL DDL of the tables holding the values of record schemas.
+use crate::error::AvroError;
+use crate::schema::{Schema, SchemaKind};
+
+/// SQL dialect of the DDL generated by
+/// [`Schema::to_sql_ddl`](schema/enum.Schema.html#method.to_sql_ddl).
+///
+/// Avro types map to column types as follows:
+///
+/// | Avro                             | Postgres                   | Hive                     |
+/// |----------------------------------|----------------------------|--------------------------|
+/// | `boolean`                        | `BOOLEAN`                  | `BOOLEAN`                |
+/// | `int`                            | `INTEGER`                  | `INT`                    |
+/// | `long`, `counter`                | `BIGINT`                   | `BIGINT`                 |
+/// | `float`, `double`                | `REAL`, `DOUBLE PRECISION` | `FLOAT`, `DOUBLE`        |
+/// | `string`, `enum`                 | `TEXT`                     | `STRING`                 |
+/// | `bytes`, `fixed`, `hll`, `bloom` | `BYTEA`                    | `BINARY`                 |
+/// | `date`                           | `TIMESTAMPTZ`              | `TIMESTAMP`              |
+/// | `array`, `bounded_list`, `set`   | array of the items         | `ARRAY` of the items     |
+/// | `map`                            | `JSONB`                    | `MAP<STRING, _>`         |
+/// | `lru_set`                        | `JSONB`                    | `MAP` of `STRUCT`s       |
+/// | `topk`                           | `JSONB`                    | `ARRAY` of `STRUCT`s     |
+/// | `record`                         | `JSONB`                    | `STRUCT`                 |
+/// | other unions                     | `JSONB`                    | `UNIONTYPE`              |
+///
+/// The `STRUCT`s of `lru_set` columns hold the `access_time` and `count` of the entries, and
+/// those of `topk` columns the `key`, `count` and `error` of the most frequent keys.
+///
+/// `optional` fields and unions of `null` and another type are nullable columns of the other
+/// type; other Postgres columns are `NOT NULL`. Enum columns of Postgres are checked against the
+/// symbols of the enum.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SqlDialect {
+    Postgres,
+    Hive,
+}
+
+impl SqlDialect {
+    fn quote(self, identifier: &str) -> String {
+        match self {
+            SqlDialect::Postgres => format!("\"{}\"", identifier.replace('"', "\"\"")),
+            SqlDialect::Hive => format!("`{}`", identifier.replace('`', "``")),
+        }
+    }
+
+    /// Column type of `schema`, along with whether the column is nullable.
+    fn column_type(self, schema: &Schema) -> Result<(String, bool), AvroError> {
+        let (schema, nullable) = match schema {
+            Schema::Optional(inner) => (&**inner, true),
+            Schema::Union(union) => match union.variants() {
+                [Schema::Null, other] | [other, Schema::Null] => (other, true),
+                _ => (schema, false),
+            },
+            schema => (schema, false),
+        };
+        let column = match self {
+            SqlDialect::Postgres => postgres_type(schema)?,
+            SqlDialect::Hive => hive_type(schema)?,
+        };
+        Ok((column, nullable))
+    }
+}
+
+fn no_column(schema: &Schema) -> AvroError {
+    AvroError::Resolution(format!("{:?} has no column type", SchemaKind::from(schema)))
+}
+
+fn postgres_type(schema: &Schema) -> Result<String, AvroError> {
+    Ok(match schema {
+        Schema::Boolean => "BOOLEAN".to_owned(),
+        Schema::Int => "INTEGER".to_owned(),
+        Schema::Long | Schema::Counter => "BIGINT".to_owned(),
+        Schema::Float => "REAL".to_owned(),
+        Schema::Double => "DOUBLE PRECISION".to_owned(),
+        Schema::String | Schema::Enum { .. } => "TEXT".to_owned(),
+        Schema::Bytes | Schema::Fixed { .. } | Schema::Hll(_) | Schema::Bloom { .. } => {
+            "BYTEA".to_owned()
+        },
+        Schema::Date(_) => "TIMESTAMPTZ".to_owned(),
+        Schema::Array(items) | Schema::BoundedList { items, .. } | Schema::Set(items) => {
+            format!("{}[]", SqlDialect::Postgres.column_type(items)?.0)
+        },
+        Schema::Map(_)
+        | Schema::LruSet(..)
+        | Schema::TopK(_)
+        | Schema::Record { .. }
+        | Schema::Union(_)
+        | Schema::Optional(_) => "JSONB".to_owned(),
+        Schema::Null => return Err(no_column(schema)),
+    })
+}
+
+fn hive_type(schema: &Schema) -> Result<String, AvroError> {
+    Ok(match schema {
+        Schema::Boolean => "BOOLEAN".to_owned(),
+        Schema::Int => "INT".to_owned(),
+        Schema::Long | Schema::Counter => "BIGINT".to_owned(),
+        Schema::Float => "FLOAT".to_owned(),
+        Schema::Double => "DOUBLE".to_owned(),
+        Schema::String | Schema::Enum { .. } => "STRING".to_owned(),
+        Schema::Bytes | Schema::Fixed { .. } | Schema::Hll(_) | Schema::Bloom { .. } => {
+            "BINARY".to_owned()
+        },
+        Schema::Date(_) => "TIMESTAMP".to_owned(),
+        Schema::Array(items) | Schema::BoundedList { items, .. } | Schema::Set(items) => {
+            format!("ARRAY<{}>", SqlDialect::Hive.column_type(items)?.0)
+        },
+        Schema::Map(values) => {
+            format!("MAP<STRING, {}>", SqlDialect::Hive.column_type(values)?.0)
+        },
+        Schema::LruSet(..) => {
+            "MAP<STRING, STRUCT<access_time: BIGINT, count: BIGINT>>".to_owned()
+        },
+        Schema::TopK(_) => "ARRAY<STRUCT<key: STRING, count: BIGINT, error: BIGINT>>".to_owned(),
+        Schema::Record { fields, .. } => {
+            let fields = fields
+                .iter()
+                .map(|field| {
+                    let (column, _) = SqlDialect::Hive.column_type(&field.schema)?;
+                    Ok(format!("{}: {}", SqlDialect::Hive.quote(&field.name), column))
+                })
+                .collect::<Result<Vec<_>, AvroError>>()?;
+            format!("STRUCT<{}>", fields.join(", "))
+        },
+        Schema::Union(union) => {
+            let variants = union
+                .variants()
+                .iter()
+                .filter(|variant| **variant != Schema::Null)
+                .map(hive_type)
+                .collect::<Result<Vec<_>, _>>()?;
+            format!("UNIONTYPE<{}>", variants.join(", "))
+        },
+        Schema::Optional(inner) => hive_type(inner)?,
+        Schema::Null => return Err(no_column(schema)),
+    })
+}
+
+/// SQL string literal of `s`.
+fn literal(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+impl Schema {
+    /// `CREATE TABLE` statement of a table named `table` (possibly qualified by a schema or
+    /// database, as in `sales.orders`) holding the values of this record schema, one column per
+    /// field, in the given [SqlDialect](enum.SqlDialect.html).
+    ///
+    /// The documentation of the record and of its fields is kept as comments.
+    pub fn to_sql_ddl(&self, table: &str, dialect: SqlDialect) -> Result<String, AvroError> {
+        let (doc, fields) = match self {
+            Schema::Record { doc, fields, .. } => (doc, fields),
+            other => {
+                return Err(AvroError::Resolution(format!(
+                    "Record schema expected, got {:?}",
+                    SchemaKind::from(other)
+                )))
+            },
+        };
+        let table = table
+            .split('.')
+            .map(|part| dialect.quote(part))
+            .collect::<Vec<_>>()
+            .join(".");
+
+        let mut columns = Vec::with_capacity(fields.len());
+        let mut comments = Vec::new();
+        for field in fields {
+            let name = dialect.quote(&field.name);
+            let (column, nullable) = dialect.column_type(&field.schema)?;
+            let mut column = format!("    {} {}", name, column);
+            if let SqlDialect::Postgres = dialect {
+                if !nullable {
+                    column.push_str(" NOT NULL");
+                }
+                if let Schema::Enum { ref symbols, .. } = field.schema {
+                    let symbols = symbols.iter().map(|s| literal(s)).collect::<Vec<_>>();
+                    column.push_str(&format!(" CHECK ({} IN ({}))", name, symbols.join(", ")));
+                }
+            }
+            if let Some(ref doc) = field.doc {
+                match dialect {
+                    SqlDialect::Postgres => comments.push(format!(
+                        "COMMENT ON COLUMN {}.{} IS {};",
+                        table,
+                        name,
+                        literal(doc)
+                    )),
+                    SqlDialect::Hive => column.push_str(&format!(" COMMENT {}", literal(doc))),
+                }
+            }
+            columns.push(column);
+        }
+
+        let mut ddl = format!("CREATE TABLE {} (\n{}\n)", table, columns.join(",\n"));
+        match (dialect, doc) {
+            (SqlDialect::Postgres, Some(doc)) => {
+                comments.insert(0, format!("COMMENT ON TABLE {} IS {};", table, literal(doc)))
+            },
+            (SqlDialect::Hive, Some(doc)) => ddl.push_str(&format!("\nCOMMENT {}", literal(doc))),
+            (_, None) => (),
+        }
+        ddl.push(';');
+        for comment in comments {
+            ddl.push('\n');
+            ddl.push_str(&comment);
+        }
+        Ok(ddl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sql_ddl() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "order", "doc": "Orders", "fields": [
+                {"name": "id", "type": "long", "doc": "Order id"},
+                {"name": "at", "type": "date"},
+                {"name": "status", "type": {
+                    "type": "enum", "name": "status", "symbols": ["new", "paid"]
+                }},
+                {"name": "note", "type": {"type": "optional", "value": "string"}},
+                {"name": "tags", "type": {"type": "set", "items": "string"}},
+                {"name": "seen", "type": {"type": "lru_set", "max_entries": 10}},
+                {"name": "line", "type": ["null", {"type": "record", "name": "line", "fields": [
+                    {"name": "sku", "type": "string"},
+                    {"name": "quantity", "type": "int"}
+                ]}]}
+            ]}"#,
+        ).unwrap();
+
+        assert_eq!(
+            schema.to_sql_ddl("sales.orders", SqlDialect::Postgres).unwrap(),
+            r#"CREATE TABLE "sales"."orders" (
+    "id" BIGINT NOT NULL,
+    "at" TIMESTAMPTZ NOT NULL,
+    "status" TEXT NOT NULL CHECK ("status" IN ('new', 'paid')),
+    "note" TEXT,
+    "tags" TEXT[] NOT NULL,
+    "seen" JSONB NOT NULL,
+    "line" JSONB
+);
+COMMENT ON TABLE "sales"."orders" IS 'Orders';
+COMMENT ON COLUMN "sales"."orders"."id" IS 'Order id';"#
+        );
+        assert_eq!(
+            schema.to_sql_ddl("orders", SqlDialect::Hive).unwrap(),
+            "CREATE TABLE `orders` (
+    `id` BIGINT COMMENT 'Order id',
+    `at` TIMESTAMP,
+    `status` STRING,
+    `note` STRING,
+    `tags` ARRAY<STRING>,
+    `seen` MAP<STRING, STRUCT<access_time: BIGINT, count: BIGINT>>,
+    `line` STRUCT<`sku`: STRING, `quantity`: INT>
+)
+COMMENT 'Orders';"
+        );
+        assert!(Schema::Long.to_sql_ddl("t", SqlDialect::Hive).is_err());
+    }
+}
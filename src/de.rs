@@ -4,6 +4,7 @@ use std::collections::HashMap;
 use std::error::{self, Error as StdError};
 use std::fmt;
 use std::slice::Iter;
+use std::sync::Arc;
 
 use serde::de::{self, Deserialize, DeserializeSeed, Error as SerdeError, Visitor};
 
@@ -48,7 +49,7 @@ struct MapDeserializer<'de> {
 }
 
 struct StructDeserializer<'de> {
-    input: Iter<'de, (String, Value)>,
+    input: Iter<'de, (Arc<str>, Value)>,
     value: Option<&'de Value>,
 }
 
@@ -78,7 +79,7 @@ impl<'de> MapDeserializer<'de> {
 }
 
 impl<'de> StructDeserializer<'de> {
-    pub fn new(input: &'de [(String, Value)]) -> Self {
+    pub fn new(input: &'de [(Arc<str>, Value)]) -> Self {
         StructDeserializer {
             input: input.iter(),
             value: None,
@@ -97,7 +98,7 @@ impl<'a, 'de> de::Deserializer<'de> for &'a mut Deserializer<'de> {
             Value::Null => visitor.visit_unit(),
             Value::Boolean(b, _) => visitor.visit_bool(b),
             Value::Int(i, _) => visitor.visit_i32(i),
-            Value::Long(i, _) => visitor.visit_i64(i),
+            Value::Long(i, _) | Value::Counter(i, _) => visitor.visit_i64(i),
             Value::Float(x, _) => visitor.visit_f32(x),
             Value::Double(x, _) => visitor.visit_f64(x),
             _ => Err(Error::custom("incorrect value")),
@@ -347,7 +348,7 @@ impl<'de> de::MapAccess<'de> for StructDeserializer<'de> {
                 let (ref field, ref value) = *item;
                 self.value = Some(value);
                 seed.deserialize(StringDeserializer {
-                    input: field.clone(),
+                    input: field.to_string(),
                 }).map(Some)
             },
             None => Ok(None),
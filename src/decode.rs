@@ -1,36 +1,466 @@
+use std::cell::{OnceCell, RefCell};
 use std::collections::{HashMap, HashSet};
-use std::io::Read;
+use std::convert::TryFrom;
+use std::io::{self, Read};
 use std::mem::transmute;
+use std::str::Utf8Error;
+use std::sync::Arc;
 
-use failure::Error;
+use crate::bloom::BloomFilter;
+use crate::borrowed::ValueRef;
+use crate::error::{at, AvroError};
+use crate::events::DecodeHandler;
+use crate::hll::HyperLogLog;
+use crate::lazy::LazyValue;
+use crate::lru::LruMap;
+use crate::schema::{DateUnit, RecordField, Schema};
+use crate::topk::{TopK, TopKCount};
+use crate::index::field_value;
+use crate::types::{LruValue, SetItem, Value};
+use crate::visit::PathSegment;
+use crate::util::{
+    bytes_len, decode_options, item_at, read_len, reserved_items, safe_len, usize_len, zag_i32,
+    zag_i64, DepthGuard, EntriesBudget, InvalidUtf8, SetBudget,
+};
 
-use crate::schema::Schema;
-use crate::types::{Value, LruValue};
-use crate::util::{safe_len, zag_i32, zag_i64, DecodeError};
-use crate::schema::SchemaKind::LruSet;
+#[inline]
+fn decode_date<R: Read>(unit: DateUnit, reader: &mut R) -> Result<Value, AvroError> {
+    zag_i64(reader).map(|v| Value::Date(unit.to_millis(v), None))
+}
+
+/// Decode a `long` from avro format, written as a zig-zag variable-length integer.
+#[inline]
+pub fn decode_long<R: Read>(reader: &mut R) -> Result<i64, AvroError> {
+    zag_i64(reader)
+}
 
+/// Decode an `int` from avro format, written as a zig-zag variable-length integer. Fails if the
+/// integer does not fit in 32 bits.
 #[inline]
-fn decode_date<R: Read>(reader: &mut R) -> Result<Value, Error> {
-    zag_i64(reader).map(|v| Value::Date(v, None))
+pub fn decode_int<R: Read>(reader: &mut R) -> Result<i32, AvroError> {
+    zag_i32(reader)
 }
 
 #[inline]
-fn decode_long<R: Read>(reader: &mut R) -> Result<Value, Error> {
-    zag_i64(reader).map(|v| Value::Long(v, None))
+pub(crate) fn decode_len<R: Read>(reader: &mut R) -> Result<usize, AvroError> {
+    zag_i64(reader).and_then(usize_len).and_then(safe_len)
 }
 
+/// Decode the length of a `string` or `bytes` value, checked against the decode limits.
 #[inline]
-fn decode_int<R: Read>(reader: &mut R) -> Result<Value, Error> {
-    zag_i32(reader).map(|v| Value::Int(v, None))
+pub(crate) fn decode_bytes_len<R: Read>(reader: &mut R) -> Result<usize, AvroError> {
+    zag_i64(reader).and_then(usize_len).and_then(bytes_len)
+}
+
+/// Policy applied to decoded strings which aren't valid UTF-8, as given by the options of the
+/// value being decoded.
+#[inline]
+pub(crate) fn utf8_policy() -> InvalidUtf8 {
+    decode_options().invalid_utf8
+}
+
+fn utf8_error(error: Utf8Error) -> AvroError {
+    AvroError::Decode(format!(
+        "not a valid utf-8 string: invalid byte at offset {}",
+        error.valid_up_to()
+    ))
+}
+
+/// Value of a decoded `string` made of `buf`, handled as `policy` tells if it isn't valid UTF-8.
+pub(crate) fn string_value(buf: Vec<u8>, policy: InvalidUtf8) -> Result<Value, AvroError> {
+    match String::from_utf8(buf) {
+        Ok(s) => Ok(Value::String(s, None)),
+        Err(error) => match policy {
+            InvalidUtf8::Error => Err(utf8_error(error.utf8_error())),
+            InvalidUtf8::ReplaceLossy => Ok(Value::String(
+                String::from_utf8_lossy(error.as_bytes()).into_owned(),
+                None,
+            )),
+            InvalidUtf8::FallbackToBytes => Ok(Value::Bytes(error.into_bytes(), None)),
+        },
+    }
+}
+
+/// Decoded map key made of `buf`, handled as `policy` tells if it isn't valid UTF-8.
+pub(crate) fn string_key(buf: Vec<u8>, policy: InvalidUtf8) -> Result<String, AvroError> {
+    match String::from_utf8(buf) {
+        Ok(s) => Ok(s),
+        Err(error) => match policy {
+            InvalidUtf8::Error => Err(utf8_error(error.utf8_error())),
+            InvalidUtf8::ReplaceLossy | InvalidUtf8::FallbackToBytes => {
+                Ok(String::from_utf8_lossy(error.as_bytes()).into_owned())
+            },
+        },
+    }
+}
+
+/// Borrowed value of a decoded `string` made of `bytes`, handled as `policy` tells if it isn't
+/// valid UTF-8.
+fn string_ref(bytes: &[u8], policy: InvalidUtf8) -> Result<ValueRef<'_>, AvroError> {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => Ok(ValueRef::String(s)),
+        Err(error) => match policy {
+            InvalidUtf8::Error => Err(utf8_error(error)),
+            InvalidUtf8::ReplaceLossy => Ok(ValueRef::Owned(Value::String(
+                String::from_utf8_lossy(bytes).into_owned(),
+                None,
+            ))),
+            InvalidUtf8::FallbackToBytes => Ok(ValueRef::Bytes(bytes)),
+        },
+    }
+}
+
+/// Borrowed map key made of `bytes`, which must be valid UTF-8 whatever the policy.
+pub(crate) fn str_key(bytes: &[u8]) -> Result<&str, AvroError> {
+    std::str::from_utf8(bytes).map_err(utf8_error)
+}
+
+/// Decode a map key, as a `string` handled as `policy` tells if it isn't valid UTF-8.
+pub(crate) fn decode_key<R: Read>(
+    reader: &mut R,
+    policy: InvalidUtf8,
+) -> Result<String, AvroError> {
+    string_key(decode_byte_vec(reader, &mut New)?, policy)
 }
 
+/// Enter a level of nesting to decode a value made of other values, which is left once the
+/// returned guard is dropped.
+pub(crate) fn enter_nested(schema: &Schema) -> Result<Option<DepthGuard>, AvroError> {
+    match *schema {
+        Schema::Array(_)
+        | Schema::BoundedList { .. }
+        | Schema::Map(_)
+        | Schema::Record { .. }
+        | Schema::Union(_)
+        | Schema::Optional(_) => DepthGuard::enter().map(Some),
+        _ => Ok(None),
+    }
+}
+
+/// Take the next `len` bytes of `bytes`.
 #[inline]
-fn decode_len<R: Read>(reader: &mut R) -> Result<usize, Error> {
-    zag_i64(reader).and_then(|len| safe_len(len as usize))
+pub(crate) fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], AvroError> {
+    if len > bytes.len() {
+        return Err(AvroError::Decode("unexpected end of input".to_owned()))
+    }
+    let (head, tail) = bytes.split_at(len);
+    *bytes = tail;
+    Ok(head)
+}
+
+/// Decode a `ValueRef` from avro format given its `Schema`, borrowing its bytes and strings from
+/// `bytes` instead of copying them. `bytes` is advanced past the decoded value.
+pub fn decode_ref<'a>(schema: &'a Schema, bytes: &mut &'a [u8]) -> Result<ValueRef<'a>, AvroError> {
+    let _depth = enter_nested(schema)?;
+    match *schema {
+        Schema::Null => Ok(ValueRef::Null),
+        Schema::Boolean => match take(bytes, 1)?[0] {
+            0u8 => Ok(ValueRef::Boolean(false)),
+            1u8 => Ok(ValueRef::Boolean(true)),
+            _ => Err(AvroError::Decode("not a bool".to_owned())),
+        },
+        Schema::Int => zag_i32(bytes).map(ValueRef::Int),
+        Schema::Long => zag_i64(bytes).map(ValueRef::Long),
+        Schema::Counter => zag_i64(bytes).map(ValueRef::Counter),
+        Schema::Date(unit) => zag_i64(bytes).map(|v| ValueRef::Date(unit.to_millis(v))),
+        Schema::Float => {
+            let mut buf = [0u8; 4];
+            buf.copy_from_slice(take(bytes, 4)?);
+            Ok(ValueRef::Float(f32::from_le_bytes(buf)))
+        },
+        Schema::Double => {
+            let mut buf = [0u8; 8];
+            buf.copy_from_slice(take(bytes, 8)?);
+            Ok(ValueRef::Double(f64::from_le_bytes(buf)))
+        },
+        Schema::Bytes => {
+            let len = decode_bytes_len(bytes)?;
+            take(bytes, len).map(ValueRef::Bytes)
+        },
+        Schema::String => {
+            let len = decode_bytes_len(bytes)?;
+            string_ref(take(bytes, len)?, utf8_policy())
+        },
+        Schema::Fixed { size, .. } => take(bytes, size).map(ValueRef::Fixed),
+        Schema::Array(ref inner) => decode_ref_items(inner, bytes).map(ValueRef::Array),
+        Schema::BoundedList {
+            ref items,
+            max_len,
+            truncation,
+        } => {
+            let mut items = decode_ref_items(items, bytes)?;
+            // lists written with a larger maximum length are truncated
+            truncation.apply(&mut items, max_len);
+            Ok(ValueRef::Array(items))
+        },
+        Schema::Map(ref inner) => {
+            let mut items = Vec::new();
+            let mut budget = EntriesBudget::default();
+            loop {
+                let len = decode_len(bytes)?;
+                if len == 0 {
+                    break
+                }
+                budget.reserve(len)?;
+                items.reserve(reserved_items(len));
+                for _ in 0..len {
+                    let len = decode_bytes_len(bytes)?;
+                    let key = str_key(take(bytes, len)?)?;
+                    items.push((key, decode_ref(inner, bytes)?));
+                }
+            }
+            Ok(ValueRef::Map(items))
+        },
+        Schema::Union(ref inner) => {
+            let index = zag_i64(bytes)?;
+            match item_at(inner.variants(), index) {
+                Some(variant) => decode_ref(variant, bytes).map(|x| ValueRef::Union(Box::new(x))),
+                None => Err(AvroError::Decode("Union index out of bounds".to_owned())),
+            }
+        },
+        Schema::Optional(ref inner) => match zag_i64(bytes)? {
+            0 => Ok(ValueRef::Optional(None)),
+            1 => decode_ref(inner, bytes).map(|x| ValueRef::Optional(Some(Box::new(x)))),
+            _ => Err(AvroError::Decode("Optional index out of bounds".to_owned())),
+        },
+        Schema::Record { ref fields, .. } => fields
+            .iter()
+            .map(|field| decode_ref(&field.schema, bytes).map(|value| (&field.name, value)))
+            .collect::<Result<Vec<_>, _>>()
+            .map(ValueRef::Record),
+        Schema::Enum { ref symbols, .. } => {
+            let index = zag_i32(bytes)?;
+            match item_at(symbols, i64::from(index)) {
+                Some(symbol) => Ok(ValueRef::Enum(index, symbol)),
+                _ => Err(AvroError::Decode("enum symbol index out of bounds".to_owned())),
+            }
+        },
+        Schema::Set(_)
+        | Schema::LruSet(..)
+        | Schema::TopK(_)
+        | Schema::Hll(_)
+        | Schema::Bloom { .. } => decode(schema, bytes).map(ValueRef::Owned),
+    }
+}
+
+/// Skip a value in avro format given its `Schema`, keeping it as a `LazyValue` to be decoded when
+/// accessed. `bytes` is advanced past the value.
+pub fn decode_lazy<'a>(
+    schema: &'a Schema,
+    bytes: &mut &'a [u8],
+) -> Result<LazyValue<'a>, AvroError> {
+    let start = *bytes;
+    skip(schema, bytes)?;
+    Ok(LazyValue::new(schema, &start[..start.len() - bytes.len()]))
+}
+
+/// Decode a value from avro format given its `Schema` as a stream of events reported to
+/// `handler`, without building the value. `bytes` is advanced past the decoded value.
+pub fn decode_events<'a, H>(
+    schema: &'a Schema,
+    bytes: &mut &'a [u8],
+    handler: &mut H,
+) -> Result<(), AvroError>
+where
+    H: DecodeHandler + ?Sized,
+{
+    match *schema {
+        Schema::Record { ref fields, .. } => {
+            handler.record_start(schema)?;
+            for field in fields {
+                handler.field(field)?;
+                decode_events(&field.schema, bytes, handler)?;
+            }
+            handler.record_end()
+        },
+        Schema::Array(ref items) | Schema::BoundedList { ref items, .. } => {
+            handler.array_start()?;
+            let mut index = 0;
+            decode_blocks(bytes, |bytes| {
+                handler.array_item(index)?;
+                index += 1;
+                decode_events(items, bytes, handler)
+            })?;
+            handler.array_end()
+        },
+        Schema::Map(ref values) => {
+            handler.map_start()?;
+            decode_blocks(bytes, |bytes| {
+                let len = decode_len(bytes)?;
+                let key = str_key(take(bytes, len)?)?;
+                handler.map_key(key)?;
+                decode_events(values, bytes, handler)
+            })?;
+            handler.map_end()
+        },
+        Schema::Union(ref inner) => {
+            let index = zag_i64(bytes)?;
+            match item_at(inner.variants(), index) {
+                Some(variant) => decode_events(variant, bytes, handler),
+                None => Err(AvroError::Decode("Union index out of bounds".to_owned())),
+            }
+        },
+        Schema::Optional(ref inner) => match zag_i64(bytes)? {
+            0 => handler.value(ValueRef::Null),
+            1 => decode_events(inner, bytes, handler),
+            _ => Err(AvroError::Decode("Optional index out of bounds".to_owned())),
+        },
+        _ => handler.value(decode_ref(schema, bytes)?),
+    }
+}
+
+/// Decode the blocks of items of an array or a map with `item`. Blocks with a negative count are
+/// followed by their size in bytes, which is not needed here.
+fn decode_blocks<'a, F>(bytes: &mut &'a [u8], mut item: F) -> Result<(), AvroError>
+where
+    F: FnMut(&mut &'a [u8]) -> Result<(), AvroError>,
+{
+    let mut budget = EntriesBudget::default();
+    loop {
+        let len = match zag_i64(bytes)? {
+            0 => return Ok(()),
+            len if len < 0 => {
+                zag_i64(bytes)?;
+                usize_len(len.saturating_abs())?
+            },
+            len => usize_len(len)?,
+        };
+        budget.reserve(len)?;
+        for _ in 0..len {
+            item(bytes)?;
+        }
+    }
+}
+
+fn decode_ref_items<'a>(
+    schema: &'a Schema,
+    bytes: &mut &'a [u8],
+) -> Result<Vec<ValueRef<'a>>, AvroError> {
+    let mut items = Vec::new();
+    let mut budget = EntriesBudget::default();
+    loop {
+        let len = decode_len(bytes)?;
+        // arrays are 0-terminated
+        if len == 0 {
+            return Ok(items)
+        }
+        budget.reserve(len)?;
+        items.reserve(reserved_items(len));
+        for _ in 0..len {
+            items.push(decode_ref(schema, bytes)?);
+        }
+    }
+}
+
+/// Source of the allocations of decoded values, new ones unless overridden. A
+/// [ValuePool](../struct.ValuePool.html) hands out the allocations of the values recycled into it
+/// instead, to the same decoder.
+pub(crate) trait Allocations {
+    /// An empty buffer, for the bytes of a `bytes`, `string`, `fixed` or `enum` value, or a key.
+    fn bytes(&mut self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// An empty string, made of an empty buffer.
+    fn string(&mut self) -> String {
+        String::from_utf8(self.bytes()).unwrap_or_default()
+    }
+
+    /// An empty vector, for the items of an `array` or a `bounded_list`.
+    fn array(&mut self) -> Vec<Value> {
+        Vec::new()
+    }
+
+    /// An empty map, for the entries of a `map`.
+    fn map(&mut self) -> HashMap<String, Value> {
+        HashMap::new()
+    }
+
+    /// An empty vector, for the fields of a `record`.
+    fn record(&mut self) -> Vec<(Arc<str>, Value)> {
+        Vec::new()
+    }
+
+    /// An empty set, for the items of a `set`.
+    fn set(&mut self) -> HashSet<SetItem> {
+        HashSet::new()
+    }
+
+    /// An empty map, for the entries of a `lru_set`.
+    fn lru_map(&mut self) -> Box<LruMap> {
+        Box::default()
+    }
+
+    /// A box holding the value of a `union` or an `optional`.
+    fn boxed(&mut self, value: Value) -> Box<Value> {
+        Box::new(value)
+    }
+}
+
+/// New allocations for every decoded value.
+struct New;
+
+impl Allocations for New {}
+
+/// Decode the bytes of a `bytes` or `string` value.
+fn decode_byte_vec<R, A>(reader: &mut R, allocations: &mut A) -> Result<Vec<u8>, AvroError>
+where
+    R: Read,
+    A: Allocations,
+{
+    let len = decode_bytes_len(reader)?;
+    let mut buf = allocations.bytes();
+    read_len(reader, len, &mut buf)?;
+    Ok(buf)
+}
+
+/// Decode the items of an array.
+fn decode_items<R, A>(
+    schema: &Schema,
+    reader: &mut R,
+    allocations: &mut A,
+) -> Result<Vec<Value>, AvroError>
+where
+    R: Read,
+    A: Allocations,
+{
+    let mut items = allocations.array();
+    let mut budget = EntriesBudget::default();
+
+    loop {
+        let len = decode_len(reader)?;
+        // arrays are 0-terminated, 0i64 is also encoded as 0 in Avro
+        // reading a length of 0 means the end of the array
+        if len == 0 {
+            return Ok(items)
+        }
+
+        budget.reserve(len)?;
+        items.reserve(reserved_items(len));
+        for _ in 0..len {
+            let index = items.len();
+            items.push(at(decode_in(schema, reader, allocations), PathSegment::Index(index))?);
+        }
+    }
 }
 
 /// Decode a `Value` from avro format given its `Schema`.
-pub fn decode<R: Read>(schema: &Schema, reader: &mut R) -> Result<Value, Error> {
+pub fn decode<R: Read>(schema: &Schema, reader: &mut R) -> Result<Value, AvroError> {
+    decode_in(schema, reader, &mut New)
+}
+
+/// Decode a `Value` from avro format given its `Schema`, taking its allocations from
+/// `allocations`.
+pub(crate) fn decode_in<R, A>(
+    schema: &Schema,
+    reader: &mut R,
+    allocations: &mut A,
+) -> Result<Value, AvroError>
+where
+    R: Read,
+    A: Allocations,
+{
+    let _depth = enter_nested(schema)?;
     match *schema {
         Schema::Null => Ok(Value::Null),
         Schema::Boolean => {
@@ -40,11 +470,27 @@ pub fn decode<R: Read>(schema: &Schema, reader: &mut R) -> Result<Value, Error>
             match buf[0] {
                 0u8 => Ok(Value::Boolean(false, None)),
                 1u8 => Ok(Value::Boolean(true, None)),
-                _ => Err(DecodeError::new("not a bool").into()),
+                _ => Err(AvroError::Decode("not a bool".to_owned())),
+            }
+        },
+        Schema::Int => decode_int(reader).map(|v| Value::Int(v, None)),
+        Schema::Long => decode_long(reader).map(|v| Value::Long(v, None)),
+        Schema::Counter => zag_i64(reader).map(|n| Value::Counter(n, None)),
+        Schema::Hll(precision) => {
+            let sketch = HyperLogLog::from_bytes(&decode_byte_vec(reader, &mut New)?)?;
+            if sketch.precision() != precision {
+                return Err(AvroError::Decode(format!(
+                    "hll of precision {} expected, got precision {}",
+                    precision,
+                    sketch.precision()
+                )))
             }
+            Ok(Value::Hll(sketch, None))
+        },
+        Schema::Bloom { bits, hashes } => {
+            BloomFilter::from_bytes(&decode_byte_vec(reader, &mut New)?, bits, hashes)
+                .map(|filter| Value::Bloom(filter, None))
         },
-        Schema::Int => decode_int(reader),
-        Schema::Long => decode_long(reader),
         Schema::Float => {
             let mut buf = [0u8; 4];
             reader.read_exact(&mut buf[..])?;
@@ -56,52 +502,31 @@ pub fn decode<R: Read>(schema: &Schema, reader: &mut R) -> Result<Value, Error>
             Ok(Value::Double(unsafe { transmute::<[u8; 8], f64>(buf) }, None))
         },
         Schema::Bytes => {
-            let len = decode_len(reader)?;
-            let mut buf = Vec::with_capacity(len);
-            unsafe {
-                buf.set_len(len);
-            }
-            reader.read_exact(&mut buf)?;
-            Ok(Value::Bytes(buf, None))
-        },
-        Schema::String => {
-            let len = decode_len(reader)?;
-            let mut buf = Vec::with_capacity(len);
-            unsafe {
-                buf.set_len(len);
-            }
-            reader.read_exact(&mut buf)?;
-
-            String::from_utf8(buf)
-                .map(|v| Value::String(v, None))
-                .map_err(|_| DecodeError::new("not a valid utf-8 string").into())
+            decode_byte_vec(reader, allocations).map(|buf| Value::Bytes(buf, None))
         },
+        Schema::String => string_value(decode_byte_vec(reader, allocations)?, utf8_policy()),
         Schema::Fixed { size, .. } => {
-            let mut buf = vec![0u8; size as usize];
+            let mut buf = allocations.bytes();
+            buf.resize(size, 0);
             reader.read_exact(&mut buf)?;
             Ok(Value::Fixed(size, buf, None))
         },
         Schema::Array(ref inner) => {
-            let mut items = Vec::new();
-
-            loop {
-                let len = decode_len(reader)?;
-                // arrays are 0-terminated, 0i64 is also encoded as 0 in Avro
-                // reading a length of 0 means the end of the array
-                if len == 0 {
-                    break
-                }
-
-                items.reserve(len as usize);
-                for _ in 0..len {
-                    items.push(decode(inner, reader)?);
-                }
-            }
-
+            decode_items(inner, reader, allocations).map(|items| Value::Array(items, None))
+        },
+        Schema::BoundedList {
+            ref items,
+            max_len,
+            truncation,
+        } => {
+            let mut items = decode_items(items, reader, allocations)?;
+            // lists written with a larger maximum length are truncated
+            truncation.apply(&mut items, max_len);
             Ok(Value::Array(items, None))
         },
         Schema::Map(ref inner) => {
-            let mut items = HashMap::new();
+            let mut items = allocations.map();
+            let mut budget = EntriesBudget::default();
 
             loop {
                 let len = decode_len(reader)?;
@@ -111,14 +536,12 @@ pub fn decode<R: Read>(schema: &Schema, reader: &mut R) -> Result<Value, Error>
                     break
                 }
 
-                items.reserve(len as usize);
+                budget.reserve(len)?;
+                items.reserve(reserved_items(len));
                 for _ in 0..len {
-                    if let Value::String(key, _) = decode(&Schema::String, reader)? {
-                        let value = decode(inner, reader)?;
-                        items.insert(key, value);
-                    } else {
-                        return Err(DecodeError::new("map key is not a string").into())
-                    }
+                    let key = string_key(decode_byte_vec(reader, allocations)?, utf8_policy())?;
+                    let value = at(decode_in(inner, reader, allocations), PathSegment::Key(&key))?;
+                    items.insert(key, value);
                 }
             }
 
@@ -127,34 +550,38 @@ pub fn decode<R: Read>(schema: &Schema, reader: &mut R) -> Result<Value, Error>
         Schema::Union(ref inner) => {
             let index = zag_i64(reader)?;
             let variants = inner.variants();
-            match variants.get(index as usize) {
-                Some(variant) => decode(variant, reader).map(|x| Value::Union(Box::new(x), None)),
-                None => Err(DecodeError::new("Union index out of bounds").into()),
+            match item_at(variants, index) {
+                Some(variant) => {
+                    let value = decode_in(variant, reader, allocations)?;
+                    Ok(Value::Union(allocations.boxed(value), None))
+                },
+                None => Err(AvroError::Decode("Union index out of bounds".to_owned())),
             }
         },
         Schema::Record { ref fields/*, allow_partial*/, .. } => {
-            // Benchmarks indicate ~10% improvement using this method.
-             fields
-             .iter()
-             .map(|field| decode(&field.schema, reader).map(|value| (field.name.clone(), value)))
-             .collect::<Result<Vec<(String, Value)>, _>>()
-             .map(|items| Value::Record(items, None))
+            let mut items = allocations.record();
+            items.reserve(fields.len());
+            for field in fields {
+                let value = decode_in(&field.schema, reader, allocations);
+                items.push((field.name.clone(), at(value, PathSegment::Field(&field.name))?));
+            }
+            Ok(Value::Record(items, None))
         },
         Schema::Enum { ref symbols, .. } => {
-            if let Value::Int(index, _) = decode_int(reader)? {
-                if index >= 0 && (index as usize) <= symbols.len() {
-                    let symbol = symbols[index as usize].clone();
-                    Ok(Value::Enum(index, symbol, None))
-                } else {
-                    Err(DecodeError::new("enum symbol index out of bounds").into())
-                }
-            } else {
-                Err(DecodeError::new("enum symbol not found").into())
+            let index = decode_int(reader)?;
+            match item_at(symbols, i64::from(index)) {
+                Some(symbol) => {
+                    let mut owned = allocations.string();
+                    owned.push_str(symbol);
+                    Ok(Value::Enum(index, owned, None))
+                },
+                None => Err(AvroError::Decode("enum symbol index out of bounds".to_owned())),
             }
         },
-        Schema::Date => decode_date(reader),
-        Schema::Set => {
-            let mut items: HashSet<String> = HashSet::new();
+        Schema::Date(unit) => decode_date(unit, reader),
+        Schema::Set(ref inner) => {
+            let mut items = allocations.set();
+            let mut budget = SetBudget::default();
 
             loop {
                 let len = decode_len(reader)?;
@@ -164,20 +591,24 @@ pub fn decode<R: Read>(schema: &Schema, reader: &mut R) -> Result<Value, Error>
                     break
                 }
 
-                items.reserve(len as usize);
+                budget.reserve(len)?;
+                items.reserve(reserved_items(len));
                 for _ in 0..len {
-                    if let Value::String(val, _) = decode(&Schema::String, reader)? {
-                        items.insert(val);
-                    } else {
-                        return Err(DecodeError::new("set value is not a string").into())
+                    let item = SetItem::try_from(decode_in(inner, reader, allocations)?)?;
+                    match item {
+                        SetItem::Bytes(ref bytes) => budget.add_key(bytes.len())?,
+                        SetItem::String(ref s) => budget.add_key(s.len())?,
+                        _ => (),
                     }
+                    items.insert(item);
                 }
             }
 
             Ok(Value::Set(items, None))
         },
-        Schema::LruSet(ref lru_limit) => {
-            let mut items: HashMap<String, LruValue> = HashMap::new();
+        Schema::LruSet(ref lru_limit, _) => {
+            let mut items = allocations.lru_map();
+            let mut budget = SetBudget::default();
 
             loop {
                 let len = decode_len(reader)?;
@@ -187,20 +618,41 @@ pub fn decode<R: Read>(schema: &Schema, reader: &mut R) -> Result<Value, Error>
                     break
                 }
 
-                items.reserve(len as usize);
+                budget.reserve(len)?;
+                items.reserve(reserved_items(len));
                 for _ in 0..len {
-                    if let Value::String(key, _) = decode(&Schema::String, reader)? {
-                        let access_time = zag_i64(reader)?;
-                        let count = zag_i64(reader)?;
-                        items.insert(key, LruValue::new(access_time, count));
-                    } else {
-                        return Err(DecodeError::new("map key is not a string").into())
-                    }
+                    let key = string_key(decode_byte_vec(reader, allocations)?, utf8_policy())?;
+                    budget.add_key(key.len())?;
+                    let access_time = zag_i64(reader)?;
+                    let count = zag_i64(reader)?;
+                    items.insert(key, LruValue::new(access_time, count));
                 }
             }
 
             Ok(Value::LruSet(items, lru_limit.clone(), None))
         },
+        Schema::TopK(size) => {
+            let mut topk = TopK::new(size);
+            let mut budget = EntriesBudget::default();
+
+            loop {
+                let len = decode_len(reader)?;
+                if len == 0 {
+                    break
+                }
+
+                budget.reserve(len)?;
+                for _ in 0..len {
+                    let key = string_key(decode_byte_vec(reader, allocations)?, utf8_policy())?;
+                    let count = zag_i64(reader)?;
+                    let error = zag_i64(reader)?;
+                    topk.put(key, TopKCount { count, error });
+                }
+            }
+
+            topk.truncate(size);
+            Ok(Value::TopK(Box::new(topk), None))
+        },
         Schema::Optional(ref inner) => {
             let index = zag_i64(reader)?;
             match index {
@@ -208,12 +660,724 @@ pub fn decode<R: Read>(schema: &Schema, reader: &mut R) -> Result<Value, Error>
                     Ok(Value::Optional(None, None))
                 },
                 1 => {
-                    decode(inner, reader).map(|x| Value::Optional(Some(Box::new(x)), None))
+                    let value = decode_in(inner, reader, allocations)?;
+                    Ok(Value::Optional(Some(allocations.boxed(value)), None))
                 },
                 _ => {
-                    Err(DecodeError::new("Optional index out of bounds").into())
+                    Err(AvroError::Decode("Optional index out of bounds".to_owned()))
                 }
             }
         },
     }
 }
+
+#[inline]
+fn skip_bytes<R: Read>(len: u64, reader: &mut R) -> Result<(), AvroError> {
+    let skipped = io::copy(&mut reader.by_ref().take(len), &mut io::sink())?;
+    if skipped < len {
+        return Err(AvroError::Decode("unexpected end of input while skipping".to_owned()))
+    }
+    Ok(())
+}
+
+/// Skip blocks of `skip_item` items, as found in arrays and maps. Blocks with a negative count
+/// are followed by their size in bytes, and skipped in one go.
+fn skip_blocks<R, F>(reader: &mut R, mut skip_item: F) -> Result<(), AvroError>
+where
+    R: Read,
+    F: FnMut(&mut R) -> Result<(), AvroError>,
+{
+    let mut budget = EntriesBudget::default();
+    loop {
+        let len = zag_i64(reader)?;
+        if len == 0 {
+            return Ok(())
+        }
+        budget.reserve(usize_len(len.saturating_abs())?)?;
+        if len < 0 {
+            let size = zag_i64(reader)?;
+            skip_bytes(safe_len(usize_len(size)?)? as u64, reader)?;
+        } else {
+            for _ in 0..len {
+                skip_item(reader)?;
+            }
+        }
+    }
+}
+
+/// Skip a value in avro format given its `Schema`, without decoding it: only the lengths and
+/// union indexes it holds are read.
+pub fn skip<R: Read>(schema: &Schema, reader: &mut R) -> Result<(), AvroError> {
+    match *schema {
+        Schema::Null => Ok(()),
+        Schema::Boolean => skip_bytes(1, reader),
+        Schema::Int
+        | Schema::Long
+        | Schema::Counter
+        | Schema::Date(_)
+        | Schema::Enum { .. } => zag_i64(reader).map(|_| ()),
+        Schema::Float => skip_bytes(4, reader),
+        Schema::Double => skip_bytes(8, reader),
+        Schema::Bytes | Schema::String | Schema::Hll(_) | Schema::Bloom { .. } => {
+            let len = decode_len(reader)?;
+            skip_bytes(len as u64, reader)
+        },
+        Schema::Fixed { size, .. } => skip_bytes(size as u64, reader),
+        Schema::Array(ref items)
+        | Schema::Set(ref items)
+        | Schema::BoundedList { ref items, .. } => skip_blocks(reader, |r| skip(items, r)),
+        Schema::Map(ref values) => skip_blocks(reader, |r| {
+            skip(&Schema::String, r)?;
+            skip(values, r)
+        }),
+        Schema::LruSet(..) | Schema::TopK(_) => skip_blocks(reader, |r| {
+            skip(&Schema::String, r)?;
+            zag_i64(r)?;
+            zag_i64(r).map(|_| ())
+        }),
+        Schema::Union(ref inner) => {
+            let index = zag_i64(reader)?;
+            match item_at(inner.variants(), index) {
+                Some(variant) => skip(variant, reader),
+                None => Err(AvroError::Decode("Union index out of bounds".to_owned())),
+            }
+        },
+        Schema::Optional(ref inner) => match zag_i64(reader)? {
+            0 => Ok(()),
+            1 => skip(inner, reader),
+            _ => Err(AvroError::Decode("Optional index out of bounds".to_owned())),
+        },
+        Schema::Record { ref fields, .. } => {
+            fields.iter().try_for_each(|field| skip(&field.schema, reader))
+        },
+    }
+}
+
+/// Fields of a record to decode: either a whole field, or some of the fields of a nested record.
+#[derive(Clone, Debug)]
+enum Selection {
+    Whole,
+    Fields(Vec<Option<Selection>>),
+}
+
+impl Selection {
+    fn select(&mut self, fields: &[RecordField], path: &str) -> Result<(), AvroError> {
+        let (name, rest) = match path.find('.') {
+            Some(i) => (&path[..i], Some(&path[i + 1..])),
+            None => (path, None),
+        };
+        let selected = match self {
+            Selection::Whole => return Ok(()),
+            Selection::Fields(selected) => selected,
+        };
+        let position = fields
+            .iter()
+            .position(|field| &*field.name == name)
+            .ok_or_else(|| AvroError::Decode(format!("No field {} in the schema", name)))?;
+        match (rest, &fields[position].schema) {
+            (None, _) => selected[position] = Some(Selection::Whole),
+            (Some(rest), Schema::Record { fields, .. }) => selected[position]
+                .get_or_insert_with(|| Selection::Fields(vec![None; fields.len()]))
+                .select(fields, rest)?,
+            (Some(_), _) => {
+                return Err(AvroError::Decode(format!("Field {} is not a record", name)))
+            },
+        }
+        Ok(())
+    }
+
+    fn decode<R: Read>(&self, schema: &Schema, reader: &mut R) -> Result<Value, AvroError> {
+        let (fields, selected) = match (self, schema) {
+            (Selection::Fields(selected), Schema::Record { fields, .. }) => (fields, selected),
+            _ => return decode(schema, reader),
+        };
+        let mut items = Vec::with_capacity(selected.iter().filter(|s| s.is_some()).count());
+        for (field, selection) in fields.iter().zip(selected) {
+            match selection {
+                Some(selection) => {
+                    items.push((field.name.clone(), selection.decode(&field.schema, reader)?))
+                },
+                None => skip(&field.schema, reader)?,
+            }
+        }
+        Ok(Value::Record(items, None))
+    }
+}
+
+/// Decoder of some of the fields of records in avro format, which skips the others without
+/// decoding them.
+///
+/// Fields are given by dot-separated paths, like `user.id` for the `id` field of the `user`
+/// record field. Decoded records only hold the selected fields, in the order of the schema.
+#[derive(Clone, Debug)]
+pub struct Projection {
+    schema: Schema,
+    selection: Selection,
+}
+
+impl Projection {
+    /// Create a projection of the fields at `paths` of a record schema. Fails if the schema is
+    /// not a record, or if any of the paths does not name a field of it.
+    pub fn new(schema: &Schema, paths: &[&str]) -> Result<Projection, AvroError> {
+        let fields = match schema {
+            Schema::Record { fields, .. } => fields,
+            _ => return Err(AvroError::Decode("Projections require a record schema".to_owned())),
+        };
+        let mut selection = Selection::Fields(vec![None; fields.len()]);
+        for path in paths {
+            selection.select(fields, path)?;
+        }
+        Ok(Projection {
+            schema: schema.clone(),
+            selection,
+        })
+    }
+
+    /// Decode the selected fields of a record from avro format, skipping the others.
+    pub fn decode<R: Read>(&self, reader: &mut R) -> Result<Value, AvroError> {
+        self.selection.decode(&self.schema, reader)
+    }
+}
+
+/// Record in avro format of which the fields are decoded lazily, when first accessed. The fields
+/// preceding an accessed field are skipped without being decoded.
+pub struct PartialRecord<'a> {
+    fields: &'a [RecordField],
+    bytes: &'a [u8],
+    /// Offsets of the fields located so far, from the start of the record.
+    offsets: RefCell<Vec<usize>>,
+    values: Vec<OnceCell<Value>>,
+    error: RefCell<Option<AvroError>>,
+}
+
+impl<'a> PartialRecord<'a> {
+    /// Create a lazily-decoded record of the given fields, starting at the first byte of `bytes`.
+    pub(crate) fn new(fields: &'a [RecordField], bytes: &'a [u8]) -> PartialRecord<'a> {
+        PartialRecord {
+            fields,
+            bytes,
+            offsets: RefCell::new(vec![0]),
+            values: fields.iter().map(|_| OnceCell::new()).collect(),
+            error: RefCell::new(None),
+        }
+    }
+
+    /// Offset of the field at `position`, or of the end of the record if `position` is the
+    /// number of fields, skipping the fields not located yet.
+    fn locate(&self, position: usize) -> Result<usize, AvroError> {
+        let mut offsets = self.offsets.borrow_mut();
+        while offsets.len() <= position {
+            let last = offsets.len() - 1;
+            let mut bytes = &self.bytes[offsets[last]..];
+            skip(&self.fields[last].schema, &mut bytes)?;
+            offsets.push(self.bytes.len() - bytes.len());
+        }
+        Ok(offsets[position])
+    }
+
+    fn field(&self, name: &str) -> Result<Option<&Value>, AvroError> {
+        let position = match self.fields.iter().position(|field| &*field.name == name) {
+            Some(position) => position,
+            None => return Ok(None),
+        };
+        if let Some(value) = self.values[position].get() {
+            return Ok(Some(value))
+        }
+        let mut bytes = &self.bytes[self.locate(position)?..];
+        let value = decode(&self.fields[position].schema, &mut bytes)?;
+        Ok(Some(self.values[position].get_or_init(|| value)))
+    }
+
+    /// Value of the field at the dot-separated `path`, like `user.id` for the `id` field of the
+    /// `user` record field, decoding the top-level field on first access.
+    ///
+    /// Return `None` if there is no such field, or if it cannot be decoded: the error is then
+    /// reported by the reader.
+    pub fn get(&self, path: &str) -> Option<&Value> {
+        let (name, rest) = match path.find('.') {
+            Some(i) => (&path[..i], Some(&path[i + 1..])),
+            None => (path, None),
+        };
+        match self.field(name) {
+            Ok(value) => match rest {
+                Some(rest) => value.and_then(|value| field_value(value, rest)),
+                None => value,
+            },
+            Err(e) => {
+                self.error.borrow_mut().get_or_insert(e);
+                None
+            },
+        }
+    }
+
+    /// Size of the record in bytes, skipping the fields not located yet, or the first error met
+    /// while decoding it.
+    pub(crate) fn finish(self) -> Result<usize, AvroError> {
+        if let Some(e) = self.error.borrow_mut().take() {
+            return Err(e)
+        }
+        self.locate(self.fields.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::{encode_long, encode_to_vec};
+    use crate::types::ToAvro;
+
+    #[test]
+    fn test_decode_set_limit() {
+        use crate::schema::LruLimit;
+
+        // a block claiming more entries than allowed fails before any entry is read
+        let mut encoded = Vec::new();
+        encode_long(10_000_000, &mut encoded);
+        for schema in &[
+            Schema::Set(Box::new(Schema::String)),
+            Schema::LruSet(LruLimit::Count(10), None),
+        ] {
+            let error = decode(schema, &mut &encoded[..]).unwrap_err();
+            assert!(matches!(error, AvroError::Limit(_)));
+        }
+    }
+
+    #[test]
+    fn test_decode_limits() {
+        // a block claiming more items than allowed fails before any item is read
+        let mut encoded = Vec::new();
+        encode_long(20_000_000, &mut encoded);
+        let schemas = [Schema::Array(Box::new(Schema::Long)), Schema::Map(Box::new(Schema::Long))];
+        for schema in &schemas {
+            let error = decode(schema, &mut &encoded[..]).unwrap_err();
+            assert!(matches!(error.root(), AvroError::Limit(_)));
+            let error = decode_ref(schema, &mut &encoded[..]).unwrap_err();
+            assert!(matches!(error.root(), AvroError::Limit(_)));
+        }
+
+        // arrays nested deeper than allowed
+        let mut schema = Schema::Long;
+        for _ in 0..200 {
+            schema = Schema::Array(Box::new(schema));
+        }
+        let mut encoded = Vec::new();
+        for _ in 0..199 {
+            encode_long(1, &mut encoded);
+        }
+        encode_long(0, &mut encoded);
+        encoded.extend(vec![0u8; 199]);
+        let error = decode(&schema, &mut &encoded[..]).unwrap_err();
+        assert!(matches!(error.root(), AvroError::Limit(_)));
+        // the depth is restored after the failure
+        assert!(decode(&Schema::Array(Box::new(Schema::Long)), &mut &[0u8][..]).is_ok());
+    }
+
+    #[test]
+    fn test_decode_claimed_lengths() {
+        struct Ignore;
+
+        impl DecodeHandler for Ignore {
+            fn value(&mut self, _: ValueRef) -> Result<(), AvroError> {
+                Ok(())
+            }
+        }
+
+        // counts and lengths within the limits, of which the input holds nothing, fail on the
+        // end of the input without being allocated first
+        let mut encoded = Vec::new();
+        encode_long(9_000_000, &mut encoded);
+        let schemas = [Schema::Array(Box::new(Schema::String)), Schema::Bytes, Schema::String];
+        for schema in &schemas {
+            match decode(schema, &mut &encoded[..]).unwrap_err().root() {
+                AvroError::Io(error) => assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof),
+                error => panic!("io error expected, got {:?}", error),
+            }
+        }
+
+        // items taking no bytes are counted against the limits as well
+        let mut encoded = Vec::new();
+        encode_long(i64::MAX, &mut encoded);
+        let nulls = Schema::Array(Box::new(Schema::Null));
+        let error = skip(&nulls, &mut &encoded[..]).unwrap_err();
+        assert!(matches!(error.root(), AvroError::Limit(_)));
+        let error = decode_events(&nulls, &mut &encoded[..], &mut Ignore).unwrap_err();
+        assert!(matches!(error.root(), AvroError::Limit(_)));
+        let error = decode_lazy(&nulls, &mut &encoded[..]).unwrap_err();
+        assert!(matches!(error.root(), AvroError::Limit(_)));
+
+        let mut encoded = Vec::new();
+        encode_long(20_000_000, &mut encoded);
+        let error = decode(&Schema::TopK(10), &mut &encoded[..]).unwrap_err();
+        assert!(matches!(error.root(), AvroError::Limit(_)));
+    }
+
+    #[test]
+    fn test_decode_invalid_utf8() {
+        use crate::borrowed::ValueRef;
+        use crate::util::InvalidUtf8;
+
+        // "café" as written in Latin-1
+        let latin1 = b"caf\xe9".to_vec();
+        let error = string_value(latin1.clone(), InvalidUtf8::Error).unwrap_err();
+        assert!(error.to_string().contains("offset 3"));
+        assert_eq!(
+            string_value(latin1.clone(), InvalidUtf8::ReplaceLossy).unwrap(),
+            Value::String("caf\u{fffd}".to_owned(), None)
+        );
+        assert_eq!(
+            string_value(latin1.clone(), InvalidUtf8::FallbackToBytes).unwrap(),
+            Value::Bytes(latin1.clone(), None)
+        );
+        let key = string_key(latin1.clone(), InvalidUtf8::FallbackToBytes).unwrap();
+        assert_eq!(key, "caf\u{fffd}");
+        assert_eq!(
+            string_value(b"cafe".to_vec(), InvalidUtf8::FallbackToBytes).unwrap(),
+            Value::String("cafe".to_owned(), None)
+        );
+
+        // strings fail with the default policy, whether decoded or borrowed
+        let encoded = encode_to_vec(&Value::Bytes(latin1, None), &Schema::Bytes);
+        assert!(decode(&Schema::String, &mut &encoded[..]).is_err());
+        assert!(decode_ref(&Schema::String, &mut &encoded[..]).is_err());
+        let mut encoded = Vec::new();
+        encode_long(1, &mut encoded);
+        encoded.extend(encode_to_vec(&Value::Bytes(b"\xff".to_vec(), None), &Schema::Bytes));
+        encode_long(7, &mut encoded);
+        encode_long(0, &mut encoded);
+        let schema = Schema::Map(Box::new(Schema::Long));
+        assert!(decode(&schema, &mut &encoded[..]).is_err());
+        assert!(decode_ref(&schema, &mut &encoded[..]).is_err());
+        assert!(matches!(
+            decode_ref(&Schema::String, &mut &b"\x04ok"[..]).unwrap(),
+            ValueRef::String("ok")
+        ));
+    }
+
+    #[test]
+    fn test_decode_malformed_input() {
+        use crate::plan::ResolutionPlan;
+        use crate::pool::ValuePool;
+
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": "long"},
+                    {"name": "b", "type": "string"},
+                    {"name": "c", "type": {"type": "array", "items": ["null", "bytes"]}},
+                    {"name": "d", "type": {"type": "map", "values": "double"}},
+                    {"name": "e", "type": {"type": "enum", "name": "e", "symbols": ["x", "y"]}},
+                    {"name": "f", "type": {"type": "fixed", "name": "f", "size": 2}},
+                    {"name": "g", "type": {
+                        "type": "bounded_list", "items": "int", "max_length": 2
+                    }},
+                    {"name": "h", "type": "optional", "value": "date"}
+                ]
+            }
+        "#,
+        ).unwrap();
+        let mut map = HashMap::new();
+        map.insert("k".to_owned(), 1.5f64.avro());
+        let value = Value::record(vec![
+            ("a", 1i64.avro()),
+            ("b", "foo".avro()),
+            ("c", Value::Array(vec![Value::Union(Box::new(vec![1u8].avro()), None)], None)),
+            ("d", Value::Map(map, None)),
+            ("e", Value::Enum(1, "y".to_owned(), None)),
+            ("f", Value::Fixed(2, vec![1, 2], None)),
+            ("g", Value::Array(vec![1i32.avro()], None)),
+            ("h", Value::Optional(Some(Box::new(Value::Date(1000, None))), None)),
+        ]);
+        let encoded = encode_to_vec(&value, &schema);
+        let plan = ResolutionPlan::compile(&schema, &schema);
+        let mut pool = ValuePool::new();
+
+        // every decoder either decodes or fails on truncated and corrupted input, but never panics
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut inputs = (0..encoded.len()).map(|len| encoded[..len].to_vec()).collect::<Vec<_>>();
+        for _ in 0..2000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let mut corrupted = encoded.clone();
+            corrupted[state as usize % encoded.len()] = (state >> 32) as u8;
+            inputs.push(corrupted);
+        }
+        for input in &inputs {
+            let _ = decode(&schema, &mut &input[..]);
+            let _ = decode_ref(&schema, &mut &input[..]);
+            let _ = skip(&schema, &mut &input[..]);
+            let _ = plan.decode(&mut &input[..]);
+            let _ = pool.decode(&schema, &mut &input[..]);
+        }
+        assert!(decode(&schema, &mut &encoded[..]).is_ok());
+    }
+
+    #[test]
+    fn test_decode_projection() {
+
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "tags", "type": {"type": "map", "values": {
+                        "type": "array", "items": "string"
+                    }}},
+                    {"name": "user", "type": {"type": "record", "name": "user", "fields": [
+                        {"name": "id", "type": "long"},
+                        {"name": "name", "type": ["null", "string"]}
+                    ]}},
+                    {"name": "score", "type": "double"},
+                    {"name": "seen", "type": {"type": "optional", "value": "counter"}}
+                ]
+            }
+        "#,
+        ).unwrap();
+        let mut tags = HashMap::new();
+        tags.insert("a".to_owned(), vec!["x", "y"].avro());
+        let user = Value::Record(
+            vec![
+                ("id".into(), 7i64.avro()),
+                ("name".into(), Value::Union(Box::new("bob".avro()), None)),
+            ],
+            None,
+        );
+        let value = Value::Record(
+            vec![
+                ("tags".into(), Value::Map(tags, None)),
+                ("user".into(), user),
+                ("score".into(), 0.5f64.avro()),
+                ("seen".into(), Value::Optional(Some(Box::new(Value::Counter(3, None))), None)),
+            ],
+            None,
+        );
+        let encoded = encode_to_vec(&value, &schema);
+
+        let mut reader = &encoded[..];
+        skip(&schema, &mut reader).unwrap();
+        assert!(reader.is_empty());
+        assert!(skip(&schema, &mut &encoded[..encoded.len() - 1]).is_err());
+
+        let projection = Projection::new(&schema, &["score", "user.id"]).unwrap();
+        assert_eq!(
+            projection.decode(&mut &encoded[..]).unwrap(),
+            Value::Record(
+                vec![
+                    ("user".into(), Value::Record(vec![("id".into(), 7i64.avro())], None)),
+                    ("score".into(), 0.5f64.avro()),
+                ],
+                None,
+            )
+        );
+        let projection = Projection::new(&schema, &["user", "user.id", "tags", "seen"]).unwrap();
+        let mut full = decode(&schema, &mut &encoded[..]).unwrap();
+        if let Value::Record(ref mut fields, _) = full {
+            fields.retain(|(name, _)| &**name != "score");
+        }
+        assert_eq!(projection.decode(&mut &encoded[..]).unwrap(), full);
+
+        assert!(Projection::new(&schema, &["missing"]).is_err());
+        assert!(Projection::new(&schema, &["score.value"]).is_err());
+        assert!(Projection::new(&Schema::Long, &[]).is_err());
+    }
+
+    #[test]
+    fn test_decode_ref() {
+        use crate::ValueRef;
+
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "s", "type": "string"},
+                    {"name": "b", "type": "bytes"},
+                    {"name": "f", "type": {"type": "fixed", "name": "f", "size": 2}},
+                    {"name": "e", "type": {"type": "enum", "name": "e", "symbols": ["x", "y"]}},
+                    {"name": "m", "type": {"type": "map", "values": ["null", "double"]}},
+                    {"name": "l", "type": {"type": "array", "items": "float"}},
+                    {"name": "o", "type": {"type": "optional", "value": "int"}},
+                    {"name": "set", "type": {"type": "set", "items": "string"}}
+                ]
+            }
+        "#,
+        ).unwrap();
+        let mut map = HashMap::new();
+        map.insert("k".to_owned(), Value::Union(Box::new(1.5f64.avro()), None));
+        let value = Value::Record(
+            vec![
+                ("s".into(), "foo".avro()),
+                ("b".into(), Value::Bytes(vec![1, 2], None)),
+                ("f".into(), Value::Fixed(2, vec![3, 4], None)),
+                ("e".into(), Value::Enum(1, "y".to_owned(), None)),
+                ("m".into(), Value::Map(map, None)),
+                ("l".into(), vec![0.5f32].avro()),
+                ("o".into(), Value::Optional(None, None)),
+                ("set".into(), Value::Set(vec!["a".into()].into_iter().collect(), None)),
+            ],
+            None,
+        );
+        let encoded = encode_to_vec(&value, &schema);
+
+        let mut bytes = &encoded[..];
+        let decoded = decode_ref(&schema, &mut bytes).unwrap();
+        assert!(bytes.is_empty());
+        match decoded {
+            ValueRef::Record(ref fields) => match fields[0].1 {
+                // strings point into the decoded buffer
+                ValueRef::String(s) => assert_eq!(s.as_ptr(), encoded[1..].as_ptr()),
+                _ => panic!("string expected"),
+            },
+            _ => panic!("record expected"),
+        }
+        assert_eq!(decoded.to_value(), decode(&schema, &mut &encoded[..]).unwrap());
+        assert_eq!(Value::from(decoded), value);
+
+        assert!(decode_ref(&schema, &mut &encoded[..encoded.len() - 2]).is_err());
+        assert!(decode_ref(&Schema::String, &mut &[2u8, 0xff][..]).is_err());
+    }
+
+    #[test]
+    fn test_decode_lazy() {
+
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": "long"},
+                    {"name": "l", "type": {"type": "array", "items": "string"}},
+                    {"name": "m", "type": {"type": "map", "values": "int"}},
+                    {"name": "inner", "type": ["null", {
+                        "type": "record",
+                        "name": "inner",
+                        "fields": [{"name": "b", "type": "string"}]
+                    }]}
+                ]
+            }
+        "#,
+        ).unwrap();
+        let mut map = HashMap::new();
+        map.insert("k".to_owned(), 3i32.avro());
+        let value = Value::record(vec![
+            ("a", 1i64.avro()),
+            ("l", vec!["x", "y"].avro()),
+            ("m", Value::Map(map, None)),
+            ("inner", Value::Union(Box::new(Value::record(vec![("b", "foo")])), None)),
+        ]);
+        let encoded = encode_to_vec(&value, &schema);
+
+        let mut bytes = &encoded[..];
+        let lazy = decode_lazy(&schema, &mut bytes).unwrap();
+        assert!(bytes.is_empty());
+        assert_eq!(lazy.decode().unwrap(), decode(&schema, &mut &encoded[..]).unwrap());
+
+        assert_eq!(lazy.field("a").unwrap().unwrap().decode().unwrap(), 1i64.avro());
+        assert_eq!(lazy.get("inner.b").unwrap().unwrap().decode().unwrap(), "foo".avro());
+        assert!(lazy.field("other").unwrap().is_none());
+        let items = lazy.field("l").unwrap().unwrap().items().unwrap();
+        assert_eq!(items[1].decode().unwrap(), "y".avro());
+        let entries = lazy.field("m").unwrap().unwrap().entries().unwrap();
+        assert_eq!(entries[0].0, "k");
+        assert_eq!(entries[0].1.decode().unwrap(), 3i32.avro());
+        assert!(lazy.get("a.b").is_err());
+
+        let value = Value::record(vec![
+            ("a", 1i64.avro()),
+            ("l", Vec::<&str>::new().avro()),
+            ("m", Value::Map(HashMap::new(), None)),
+            ("inner", Value::Union(Box::new(Value::Null), None)),
+        ]);
+        let encoded = encode_to_vec(&value, &schema);
+        let lazy = decode_lazy(&schema, &mut &encoded[..]).unwrap();
+        assert!(lazy.get("inner.b").unwrap().is_none());
+        assert!(decode_lazy(&schema, &mut &encoded[..encoded.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_decode_events() {
+        use crate::schema::RecordField;
+        use crate::{DecodeHandler, ValueRef};
+
+        #[derive(Default)]
+        struct Events(Vec<String>);
+
+        impl DecodeHandler for Events {
+            fn record_start(&mut self, _: &Schema) -> Result<(), AvroError> {
+                self.0.push("{".to_owned());
+                Ok(())
+            }
+
+            fn field(&mut self, field: &RecordField) -> Result<(), AvroError> {
+                self.0.push(format!("{}:", field.name));
+                Ok(())
+            }
+
+            fn record_end(&mut self) -> Result<(), AvroError> {
+                self.0.push("}".to_owned());
+                Ok(())
+            }
+
+            fn array_item(&mut self, index: usize) -> Result<(), AvroError> {
+                self.0.push(format!("[{}]", index));
+                Ok(())
+            }
+
+            fn map_key(&mut self, key: &str) -> Result<(), AvroError> {
+                self.0.push(format!("{}=", key));
+                Ok(())
+            }
+
+            fn value(&mut self, value: ValueRef) -> Result<(), AvroError> {
+                self.0.push(format!("{:?}", value));
+                Ok(())
+            }
+        }
+
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": "long"},
+                    {"name": "l", "type": {"type": "array", "items": "string"}},
+                    {"name": "m", "type": {"type": "map", "values": ["null", "int"]}},
+                    {"name": "o", "type": {"type": "optional", "value": "int"}}
+                ]
+            }
+        "#,
+        ).unwrap();
+        let mut map = HashMap::new();
+        map.insert("k".to_owned(), Value::Union(Box::new(Value::Null), None));
+        let value = Value::record(vec![
+            ("a", 1i64.avro()),
+            ("l", vec!["x", "y"].avro()),
+            ("m", Value::Map(map, None)),
+            ("o", Value::Optional(Some(Box::new(2i32.avro())), None)),
+        ]);
+        let encoded = encode_to_vec(&value, &schema);
+
+        let mut events = Events::default();
+        let mut bytes = &encoded[..];
+        decode_events(&schema, &mut bytes, &mut events).unwrap();
+        assert!(bytes.is_empty());
+        assert_eq!(
+            events.0,
+            vec![
+                "{", "a:", "Long(1)", "l:", "[0]", "String(\"x\")", "[1]", "String(\"y\")", "m:",
+                "k=", "Null", "o:", "Int(2)", "}",
+            ]
+        );
+
+        let mut events = Events::default();
+        let truncated = &mut &encoded[..encoded.len() - 1];
+        assert!(decode_events(&schema, truncated, &mut events).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+//! Logic for the human-readable rendering of Avro values.
+use std::fmt;
+
+use chrono::{TimeZone, Utc};
+
+use crate::types::{SetItem, Value};
+
+/// Options driving how a [Value](../types/enum.Value.html) is rendered by
+/// [`Value::display`](../types/enum.Value.html#method.display).
+#[derive(Clone, Debug, PartialEq)]
+pub struct DisplayOptions {
+    /// Number of spaces used for each level of nesting.
+    pub indent: usize,
+    /// Maximum number of items rendered for arrays, maps, sets and lru sets. `None` renders all
+    /// of them.
+    pub max_items: Option<usize>,
+    /// Maximum number of bytes rendered for bytes and fixed values. `None` renders all of them.
+    pub max_bytes: Option<usize>,
+}
+
+impl Default for DisplayOptions {
+    fn default() -> DisplayOptions {
+        DisplayOptions {
+            indent: 2,
+            max_items: Some(100),
+            max_bytes: Some(64),
+        }
+    }
+}
+
+impl DisplayOptions {
+    /// Options rendering every item and byte, however large the value is.
+    pub fn untruncated() -> DisplayOptions {
+        DisplayOptions {
+            max_items: None,
+            max_bytes: None,
+            ..DisplayOptions::default()
+        }
+    }
+}
+
+/// Helper struct implementing `Display` for a `Value` with some given `DisplayOptions`.
+pub struct ValueDisplay<'a> {
+    value: &'a Value,
+    options: DisplayOptions,
+}
+
+impl<'a> fmt::Display for ValueDisplay<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Renderer {
+            f,
+            options: &self.options,
+        }.value(self.value, 0)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.display(DisplayOptions::default()).fmt(f)
+    }
+}
+
+impl Value {
+    /// Render the value in an indented, human-readable way, according to the given options.
+    ///
+    /// Records, maps and sets are rendered one entry per line, and their keys are sorted so that
+    /// the output is stable. The `ValueSetting` attached to values is not rendered.
+    pub fn display(&self, options: DisplayOptions) -> ValueDisplay<'_> {
+        ValueDisplay {
+            value: self,
+            options,
+        }
+    }
+}
+
+struct Renderer<'a, 'b: 'a> {
+    f: &'a mut fmt::Formatter<'b>,
+    options: &'a DisplayOptions,
+}
+
+impl<'a, 'b> Renderer<'a, 'b> {
+    fn newline(&mut self, depth: usize) -> fmt::Result {
+        write!(self.f, "\n{:width$}", "", width = depth * self.options.indent)
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) -> fmt::Result {
+        let shown = self.options.max_bytes.unwrap_or(bytes.len()).min(bytes.len());
+        write!(self.f, "b\"")?;
+        for byte in &bytes[..shown] {
+            write!(self.f, "\\x{:02x}", byte)?;
+        }
+        write!(self.f, "\"")?;
+        if shown < bytes.len() {
+            write!(self.f, " ... ({} more bytes)", bytes.len() - shown)?;
+        }
+        Ok(())
+    }
+
+    /// Render a sequence of already sorted entries between `open` and `close`, one entry per
+    /// line, truncated according to `max_items`.
+    fn entries<T, F>(
+        &mut self,
+        open: &str,
+        close: &str,
+        entries: &[T],
+        depth: usize,
+        mut entry: F,
+    ) -> fmt::Result
+    where
+        F: FnMut(&mut Self, &T, usize) -> fmt::Result,
+    {
+        if entries.is_empty() {
+            return write!(self.f, "{}{}", open, close)
+        }
+
+        let shown = self.options.max_items.unwrap_or(entries.len()).min(entries.len());
+        write!(self.f, "{}", open)?;
+        for item in &entries[..shown] {
+            self.newline(depth + 1)?;
+            entry(self, item, depth + 1)?;
+            write!(self.f, ",")?;
+        }
+        if shown < entries.len() {
+            self.newline(depth + 1)?;
+            write!(self.f, "... ({} more)", entries.len() - shown)?;
+        }
+        self.newline(depth)?;
+        write!(self.f, "{}", close)
+    }
+
+    fn value(&mut self, value: &Value, depth: usize) -> fmt::Result {
+        match value {
+            Value::Null => write!(self.f, "null"),
+            Value::Boolean(b, _) => write!(self.f, "{}", b),
+            Value::Int(n, _) => write!(self.f, "{}", n),
+            Value::Long(n, _) | Value::Counter(n, _) => write!(self.f, "{}", n),
+            Value::Hll(sketch, _) => write!(
+                self.f,
+                "hll(precision: {}, estimate: {})",
+                sketch.precision(),
+                sketch.estimate()
+            ),
+            Value::Bloom(filter, _) => write!(
+                self.f,
+                "bloom(bits: {}, hashes: {})",
+                filter.bits(),
+                filter.hashes()
+            ),
+            Value::Float(x, _) => write!(self.f, "{:?}", x),
+            Value::Double(x, _) => write!(self.f, "{:?}", x),
+            Value::Bytes(bytes, _) | Value::Fixed(_, bytes, _) => self.bytes(bytes),
+            Value::String(s, _) => write!(self.f, "{:?}", s),
+            Value::Enum(_, symbol, _) => write!(self.f, "{}", symbol),
+            Value::Union(inner, _) | Value::Optional(Some(inner), _) => self.value(inner, depth),
+            Value::Optional(None, _) => write!(self.f, "null"),
+            Value::Date(t, _) => match Utc.timestamp_millis_opt(*t).single() {
+                Some(date) => write!(self.f, "{}", date.to_rfc3339()),
+                None => write!(self.f, "{}", t),
+            },
+            Value::Array(items, _) => {
+                let items = items.iter().collect::<Vec<_>>();
+                self.entries("[", "]", &items, depth, |r, item, depth| r.value(item, depth))
+            },
+            Value::Map(items, _) => {
+                let mut items = items.iter().collect::<Vec<_>>();
+                items.sort_by(|a, b| a.0.cmp(b.0));
+                self.entries("{", "}", &items, depth, |r, (key, value), depth| {
+                    write!(r.f, "{:?}: ", key)?;
+                    r.value(value, depth)
+                })
+            },
+            Value::Record(fields, _) => {
+                self.entries("{", "}", fields, depth, |r, (name, value), depth| {
+                    write!(r.f, "{}: ", name)?;
+                    r.value(value, depth)
+                })
+            },
+            Value::Set(items, _) => {
+                let mut items = items.iter().collect::<Vec<_>>();
+                items.sort();
+                self.entries("#{", "}", &items, depth, |r, item, _| match item {
+                    SetItem::Int(n) => write!(r.f, "{}", n),
+                    SetItem::Long(n) => write!(r.f, "{}", n),
+                    SetItem::Bytes(bytes) => r.bytes(bytes),
+                    SetItem::String(s) => write!(r.f, "{:?}", s),
+                    SetItem::Enum(_, symbol) => write!(r.f, "{}", symbol),
+                })
+            },
+            Value::TopK(topk, _) => {
+                let entries = topk.entries();
+                self.entries("topk[", "]", &entries, depth, |r, (key, count), _| {
+                    write!(r.f, "{:?}: (count: {}, error: {})", key, count.count, count.error)
+                })
+            },
+            Value::LruSet(items, _, _) => {
+                let mut items = items.iter().collect::<Vec<_>>();
+                items.sort_by(|a, b| a.0.cmp(b.0));
+                self.entries("#{", "}", &items, depth, |r, (key, value), _| {
+                    write!(
+                        r.f,
+                        "{:?}: (access_time: {}, count: {})",
+                        key, value.access_time, value.count
+                    )
+                })
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ToAvro;
+
+    #[test]
+    fn test_display_scalars() {
+        assert_eq!(format!("{}", Value::Null), "null");
+        assert_eq!(format!("{}", 1.5f64.avro()), "1.5");
+        assert_eq!(format!("{}", "foo".avro()), "\"foo\"");
+        assert_eq!(format!("{}", Value::Bytes(vec![0, 255], None)), "b\"\\x00\\xff\"");
+        assert_eq!(format!("{}", Value::Date(0, None)), "1970-01-01T00:00:00+00:00");
+        assert_eq!(format!("{}", Value::Optional(Some(Box::new(3i32.avro())), None)), "3");
+    }
+
+    #[test]
+    fn test_display_nested() {
+        let value = Value::record(vec![
+            ("a", 1i64.avro()),
+            ("b", vec![1i32, 2i32].avro()),
+            ("c", Value::Array(vec![], None)),
+        ]);
+
+        assert_eq!(
+            format!("{}", value),
+            "{\n  a: 1,\n  b: [\n    1,\n    2,\n  ],\n  c: [],\n}"
+        );
+    }
+
+    #[test]
+    fn test_display_truncated() {
+        let options = DisplayOptions {
+            indent: 1,
+            max_items: Some(1),
+            max_bytes: Some(2),
+        };
+
+        assert_eq!(
+            format!("{}", vec![1i32, 2i32, 3i32].avro().display(options.clone())),
+            "[\n 1,\n ... (2 more)\n]"
+        );
+        assert_eq!(
+            format!("{}", Value::Bytes(vec![1, 2, 3], None).display(options)),
+            "b\"\\x01\\x02\" ... (1 more bytes)"
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                Value::Bytes(vec![1, 2, 3], None).display(DisplayOptions::untruncated())
+            ),
+            "b\"\\x01\\x02\\x03\""
+        );
+    }
+}
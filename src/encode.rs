@@ -1,8 +1,9 @@
 use std::mem::transmute;
 
-use crate::schema::Schema;
-use crate::types::Value;
-use crate::util::{zig_i32, zig_i64};
+use crate::schema::{Schema, Truncation};
+use crate::topk::{TopK, TopKCount};
+use crate::types::{lru_retained, SetItem, Value};
+use crate::util::{count_evictions, zig_i32, zig_i64, zig_i64_len};
 
 /// Encode a `Value` into avro format.
 ///
@@ -19,25 +20,58 @@ fn encode_bytes<B: AsRef<[u8]> + ?Sized>(s: &B, buffer: &mut Vec<u8>) {
     buffer.extend_from_slice(bytes);
 }
 
-fn encode_long(i: i64, buffer: &mut Vec<u8>) {
+/// Encode a `long` in avro format, as a zig-zag variable-length integer.
+#[inline]
+pub fn encode_long(i: i64, buffer: &mut Vec<u8>) {
     zig_i64(i, buffer)
 }
 
-fn encode_int(i: i32, buffer: &mut Vec<u8>) {
+/// Encode an `int` in avro format, as a zig-zag variable-length integer.
+#[inline]
+pub fn encode_int(i: i32, buffer: &mut Vec<u8>) {
     zig_i32(i, buffer)
 }
 
+/// Number of bytes [`encode_long`](fn.encode_long.html) writes for `i`.
+#[inline]
+pub fn encoded_long_len(i: i64) -> usize {
+    zig_i64_len(i)
+}
+
+/// Options driving how values are encoded by [`encode_with_options`](fn.encode_with_options.html).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EncodeOptions {
+    /// Write the items of sets and the keys of maps and lru sets in sorted order, so that equal
+    /// values are always encoded to the same bytes. Lru sets are otherwise written from the most
+    /// to the least recently accessed entry.
+    pub sorted: bool,
+}
+
 /// Encode a `Value` into avro format.
 ///
 /// **NOTE** This will not perform schema validation. The value is assumed to
 /// be valid with regards to the schema. Schema are needed only to guide the
 /// encoding for complex type values.
 pub fn encode_ref(value: &Value, schema: &Schema, buffer: &mut Vec<u8>) {
+    encode_with_options(value, schema, &EncodeOptions::default(), buffer)
+}
+
+/// Encode a `Value` into avro format, according to the given options.
+///
+/// **NOTE** As for [`encode`](fn.encode.html), this will not perform schema validation.
+pub fn encode_with_options(
+    value: &Value,
+    schema: &Schema,
+    options: &EncodeOptions,
+    buffer: &mut Vec<u8>,
+) {
     match value {
         Value::Null => (),
         Value::Boolean(b, _) => buffer.push(if *b { 1u8 } else { 0u8 }),
         Value::Int(i, _) => encode_int(*i, buffer),
-        Value::Long(i, _) => encode_long(*i, buffer),
+        Value::Long(i, _) | Value::Counter(i, _) => encode_long(*i, buffer),
+        Value::Hll(sketch, _) => encode_bytes(&sketch.to_bytes(), buffer),
+        Value::Bloom(filter, _) => encode_bytes(filter.as_bytes(), buffer),
         Value::Float(x, _) => buffer.extend_from_slice(&unsafe { transmute::<f32, [u8; 4]>(*x) }),
         Value::Double(x, _) => buffer.extend_from_slice(&unsafe { transmute::<f64, [u8; 8]>(*x) }),
         Value::Bytes(bytes, _) => encode_bytes(bytes, buffer),
@@ -62,15 +96,16 @@ pub fn encode_ref(value: &Value, schema: &Schema, buffer: &mut Vec<u8>) {
                     .find_schema(item)
                     .expect("Invalid Union validation occurred");
                 encode_long(idx as i64, buffer);
-                encode_ref(&*item, inner_schema, buffer);
+                encode_with_options(&*item, inner_schema, options, buffer);
             }
         },
         Value::Array(items, _) => {
-            if let Schema::Array(ref inner) = *schema {
+            // items beyond the maximum length of a bounded list are dropped rather than written
+            if let Some((inner, items)) = array_items(items, schema) {
                 if items.len() > 0 {
                     encode_long(items.len() as i64, buffer);
                     for item in items.iter() {
-                        encode_ref(item, inner, buffer);
+                        encode_with_options(item, inner, options, buffer);
                     }
                 }
                 buffer.push(0u8);
@@ -80,9 +115,13 @@ pub fn encode_ref(value: &Value, schema: &Schema, buffer: &mut Vec<u8>) {
             if let Schema::Map(ref inner) = *schema {
                 if items.len() > 0 {
                     encode_long(items.len() as i64, buffer);
+                    let mut items = items.iter().collect::<Vec<_>>();
+                    if options.sorted {
+                        items.sort_by(|a, b| a.0.cmp(b.0));
+                    }
                     for (key, value) in items {
                         encode_bytes(key, buffer);
-                        encode_ref(value, inner, buffer);
+                        encode_with_options(value, inner, options, buffer);
                     }
                 }
                 buffer.push(0u8);
@@ -95,22 +134,44 @@ pub fn encode_ref(value: &Value, schema: &Schema, buffer: &mut Vec<u8>) {
             } = *schema
             {
                 for (i, &(_, ref value)) in fields.iter().enumerate() {
-                    encode_ref(value, &schema_fields[i].schema, buffer);
+                    encode_with_options(value, &schema_fields[i].schema, options, buffer);
                 }
             }
         },
 
-        Value::Date(i, _) => encode_long(*i, buffer),
+        Value::Date(i, _) => match *schema {
+            Schema::Date(unit) => encode_long(unit.from_millis(*i), buffer),
+            _ => encode_long(*i, buffer),
+        },
         Value::Set(items, _) => {
             if items.len() > 0 {
                 encode_long(items.len() as i64, buffer);
-                for item in items.iter() {
-                    encode_bytes(item, buffer);
+                let mut items = items.iter().collect::<Vec<_>>();
+                if options.sorted {
+                    items.sort();
+                }
+                for item in items {
+                    encode_set_item(item, buffer);
                 }
             }
             buffer.push(0u8);
         },
-        Value::LruSet(items, _, _) => {
+        Value::LruSet(items, limit, _) => {
+            // entries beyond the limit are evicted rather than written
+            let limit = match *schema {
+                Schema::LruSet(ref limit, _) => limit,
+                _ => limit,
+            };
+            let retained = lru_retained(items, limit, None);
+            if retained.len() < items.len() {
+                let evicted = items.len() - retained.len();
+                trace_event!(trace, evicted, limit = ?limit, "left out evicted lru entries");
+                count_evictions(evicted);
+            }
+            let mut items = retained;
+            if options.sorted {
+                items.sort_by(|a, b| a.0.cmp(b.0));
+            }
             if items.len() > 0 {
                 encode_long(items.len() as i64, buffer);
                 for (key, value) in items {
@@ -121,12 +182,26 @@ pub fn encode_ref(value: &Value, schema: &Schema, buffer: &mut Vec<u8>) {
             }
             buffer.push(0u8);
         },
+        Value::TopK(topk, _) => {
+            // entries are always written from the most to the least frequent key, whatever the
+            // options, and the least frequent ones beyond the size of the schema are dropped
+            let entries = topk_entries(topk, schema);
+            if !entries.is_empty() {
+                encode_long(entries.len() as i64, buffer);
+                for (key, count) in entries {
+                    encode_bytes(key, buffer);
+                    encode_long(count.count, buffer);
+                    encode_long(count.error, buffer);
+                }
+            }
+            buffer.push(0u8);
+        },
         Value::Optional(value, _) => {
             match value {
                 Some(val) => {
                     if let Schema::Optional(ref inner) = *schema {
                         encode_long(1 as i64, buffer);
-                        encode_ref(&*val, inner, buffer);
+                        encode_with_options(&*val, inner, options, buffer);
                     }
                 }
                 None => {
@@ -143,9 +218,182 @@ pub fn encode_to_vec(value: &Value, schema: &Schema) -> Vec<u8> {
     buffer
 }
 
+fn encode_set_item(item: &SetItem, buffer: &mut Vec<u8>) {
+    match item {
+        SetItem::Int(i) | SetItem::Enum(i, _) => encode_int(*i, buffer),
+        SetItem::Long(i) => encode_long(*i, buffer),
+        SetItem::Bytes(bytes) => encode_bytes(bytes, buffer),
+        SetItem::String(s) => encode_bytes(s, buffer),
+    }
+}
+
+fn set_item_size(item: &SetItem) -> usize {
+    match item {
+        SetItem::Int(i) | SetItem::Enum(i, _) => zig_i64_len(i64::from(*i)),
+        SetItem::Long(i) => zig_i64_len(*i),
+        SetItem::Bytes(bytes) => bytes_size(bytes.len()),
+        SetItem::String(s) => bytes_size(s.len()),
+    }
+}
+
+/// Schema of the items of an array or bounded list, along with the items which are encoded.
+fn array_items<'a, 'b>(
+    items: &'a [Value],
+    schema: &'b Schema,
+) -> Option<(&'b Schema, &'a [Value])> {
+    match *schema {
+        Schema::Array(ref inner) => Some((inner, items)),
+        Schema::BoundedList {
+            items: ref inner,
+            max_len,
+            truncation,
+        } => {
+            let kept = items.len().min(max_len);
+            match truncation {
+                Truncation::Head => Some((inner, &items[items.len() - kept..])),
+                Truncation::Tail => Some((inner, &items[..kept])),
+            }
+        },
+        _ => None,
+    }
+}
+
+/// Entries of a top-k summary which are encoded with the given schema.
+fn topk_entries<'a>(topk: &'a TopK, schema: &Schema) -> Vec<(&'a str, TopKCount)> {
+    let mut entries = topk.entries();
+    if let Schema::TopK(size) = *schema {
+        entries.truncate(size);
+    }
+    entries
+}
+
+fn bytes_size(len: usize) -> usize {
+    zig_i64_len(len as i64) + len
+}
+
+/// Size of a block-encoded array, map or set made of `len` items taking `items` bytes overall.
+fn blocks_size(len: usize, items: usize) -> usize {
+    if len > 0 {
+        zig_i64_len(len as i64) + items + 1
+    } else {
+        1
+    }
+}
+
+impl Value {
+    /// Compute the number of bytes [`encode`](fn.encode.html) would write for the value with the
+    /// given [Schema](../schema/enum.Schema.html), without encoding it.
+    ///
+    /// **NOTE** As for `encode`, the value is assumed to be valid with regards to the schema.
+    pub fn encoded_size(&self, schema: &Schema) -> usize {
+        match self {
+            Value::Null => 0,
+            Value::Boolean(_, _) => 1,
+            Value::Int(i, _) => zig_i64_len(i64::from(*i)),
+            Value::Long(i, _) | Value::Counter(i, _) => zig_i64_len(*i),
+            Value::Hll(sketch, _) => bytes_size(sketch.serialized_len()),
+            Value::Bloom(filter, _) => bytes_size(filter.as_bytes().len()),
+            Value::Date(i, _) => match *schema {
+                Schema::Date(unit) => zig_i64_len(unit.from_millis(*i)),
+                _ => zig_i64_len(*i),
+            },
+            Value::Float(_, _) => 4,
+            Value::Double(_, _) => 8,
+            Value::Bytes(bytes, _) => bytes_size(bytes.len()),
+            Value::String(s, _) => match *schema {
+                Schema::String => bytes_size(s.len()),
+                Schema::Enum { ref symbols, .. } => symbols
+                    .iter()
+                    .position(|item| item == s)
+                    .map_or(0, |index| zig_i64_len(index as i64)),
+                _ => 0,
+            },
+            Value::Fixed(_, bytes, _) => bytes.len(),
+            Value::Enum(i, _, _) => zig_i64_len(i64::from(*i)),
+            Value::Union(item, _) => match *schema {
+                Schema::Union(ref inner) => inner.find_schema(item).map_or(0, |(idx, inner_schema)| {
+                    zig_i64_len(idx as i64) + item.encoded_size(inner_schema)
+                }),
+                _ => 0,
+            },
+            Value::Array(items, _) => match array_items(items, schema) {
+                Some((inner, items)) => blocks_size(
+                    items.len(),
+                    items.iter().map(|item| item.encoded_size(inner)).sum(),
+                ),
+                None => 0,
+            },
+            Value::Map(items, _) => match *schema {
+                Schema::Map(ref inner) => blocks_size(
+                    items.len(),
+                    items
+                        .iter()
+                        .map(|(key, value)| bytes_size(key.len()) + value.encoded_size(inner))
+                        .sum(),
+                ),
+                _ => 0,
+            },
+            Value::Record(fields, _) => match *schema {
+                Schema::Record {
+                    fields: ref schema_fields,
+                    ..
+                } => fields
+                    .iter()
+                    .zip(schema_fields.iter())
+                    .map(|((_, value), field)| value.encoded_size(&field.schema))
+                    .sum(),
+                _ => 0,
+            },
+            Value::Set(items, _) => blocks_size(
+                items.len(),
+                items.iter().map(set_item_size).sum(),
+            ),
+            Value::LruSet(items, limit, _) => {
+                let limit = match *schema {
+                    Schema::LruSet(ref limit, _) => limit,
+                    _ => limit,
+                };
+                let items = lru_retained(items, limit, None);
+                blocks_size(
+                    items.len(),
+                    items
+                        .iter()
+                        .map(|(key, value)| {
+                            bytes_size(key.len())
+                                + zig_i64_len(value.access_time)
+                                + zig_i64_len(value.count)
+                        })
+                        .sum(),
+                )
+            },
+            Value::TopK(topk, _) => {
+                let entries = topk_entries(topk, schema);
+                blocks_size(
+                    entries.len(),
+                    entries
+                        .iter()
+                        .map(|(key, count)| {
+                            bytes_size(key.len())
+                                + zig_i64_len(count.count)
+                                + zig_i64_len(count.error)
+                        })
+                        .sum(),
+                )
+            },
+            Value::Optional(value, _) => match (value, schema) {
+                (Some(value), Schema::Optional(inner)) => 1 + value.encoded_size(inner),
+                (Some(_), _) => 0,
+                (None, _) => 1,
+            },
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::schema::DateUnit;
+    use crate::types::ToAvro;
     use std::collections::HashMap;
 
     #[test]
@@ -171,4 +419,238 @@ mod tests {
         );
         assert_eq!(vec![0u8], buf);
     }
+
+    #[test]
+    fn test_encoded_size() {
+        use crate::types::{LruValue, ToAvro};
+        use crate::LruLimit;
+        use std::collections::HashSet;
+
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": "long"},
+                    {"name": "b", "type": ["null", "string"]},
+                    {"name": "c", "type": {"type": "array", "items": "double"}},
+                    {"name": "d", "type": {"type": "map", "values": "int"}},
+                    {"name": "e", "type": {"type": "enum", "name": "e", "symbols": ["x", "y"]}},
+                    {"name": "f", "type": {"type": "fixed", "name": "f", "size": 3}}
+                ]
+            }
+        "#,
+        ).unwrap();
+
+        let mut map = HashMap::new();
+        map.insert("key".to_string(), -300i32);
+        let record = Value::record(vec![
+            ("a", i64::MIN.avro()),
+            ("b", Some("foo").avro()),
+            ("c", vec![1.0f64, 2.0f64].avro()),
+            ("d", map.avro()),
+            ("e", "y".avro()),
+            ("f", Value::Fixed(3, vec![1, 2, 3], None)),
+        ]);
+        assert_eq!(record.encoded_size(&schema), encode_to_vec(&record, &schema).len());
+
+        let mut lru_set = HashMap::new();
+        lru_set.insert("foo".to_string(), LruValue::new(1_600_000_000_000, 3));
+        let lru_set = Value::LruSet(Box::new(lru_set.into()), LruLimit::Count(1), None);
+        let lru_schema = Schema::LruSet(LruLimit::Count(1), None);
+        assert_eq!(lru_set.encoded_size(&lru_schema), encode_to_vec(&lru_set, &lru_schema).len());
+
+        let set = Value::Set(vec!["a".into()].into_iter().collect::<HashSet<_>>(), None);
+        let set_schema = Schema::Set(Box::new(Schema::String));
+        assert_eq!(set.encoded_size(&set_schema), encode_to_vec(&set, &set_schema).len());
+        let set = Value::Set(vec![300i64.into()].into_iter().collect::<HashSet<_>>(), None);
+        let set_schema = Schema::Set(Box::new(Schema::Long));
+        assert_eq!(set.encoded_size(&set_schema), encode_to_vec(&set, &set_schema).len());
+
+        for unit in vec![DateUnit::Seconds, DateUnit::Millis, DateUnit::Micros] {
+            let optional_schema = Schema::Optional(Box::new(Schema::Date(unit)));
+            for value in vec![
+                Value::Optional(None, None),
+                Value::Optional(Some(Box::new(Value::Date(1_600_000_000_000, None))), None),
+            ] {
+                assert_eq!(
+                    value.encoded_size(&optional_schema),
+                    encode_to_vec(&value, &optional_schema).len()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_counter() {
+        use crate::decode::decode;
+
+        let value = Value::Counter(-300, None);
+        let encoded = encode_to_vec(&value, &Schema::Counter);
+        assert_eq!(encoded, encode_to_vec(&(-300i64).avro(), &Schema::Long));
+        assert_eq!(value.encoded_size(&Schema::Counter), encoded.len());
+        assert_eq!(decode(&Schema::Counter, &mut &encoded[..]).unwrap(), value);
+    }
+
+    #[test]
+    fn test_encode_hll() {
+        use crate::decode::decode;
+        use crate::hll::HyperLogLog;
+
+        let mut sketch = HyperLogLog::new(6).unwrap();
+        sketch.add("foo");
+        let value = Value::Hll(sketch, None);
+        let encoded = encode_to_vec(&value, &Schema::Hll(6));
+        assert_eq!(value.encoded_size(&Schema::Hll(6)), encoded.len());
+        assert_eq!(decode(&Schema::Hll(6), &mut &encoded[..]).unwrap(), value);
+        assert!(decode(&Schema::Hll(7), &mut &encoded[..]).is_err());
+
+        // sketches are encoded as bytes, from which they can be resolved
+        let bytes = decode(&Schema::Bytes, &mut &encoded[..]).unwrap();
+        assert_eq!(bytes.resolve(&Schema::Hll(6)).unwrap(), value);
+    }
+
+    #[test]
+    fn test_encode_bounded_list() {
+        use crate::decode::decode;
+
+        let schema = Schema::BoundedList {
+            items: Box::new(Schema::Int),
+            max_len: 2,
+            truncation: Truncation::Head,
+        };
+        let value = vec![1i32, 2i32, 3i32].avro();
+        let encoded = encode_to_vec(&value, &schema);
+        let array = Schema::Array(Box::new(Schema::Int));
+        assert_eq!(encoded, encode_to_vec(&vec![2i32, 3i32].avro(), &array));
+        assert_eq!(value.encoded_size(&schema), encoded.len());
+        assert_eq!(decode(&schema, &mut &encoded[..]).unwrap(), vec![2i32, 3i32].avro());
+
+        // lists written with a larger maximum length are truncated when read
+        let encoded = encode_to_vec(&value, &array);
+        let tail = Schema::BoundedList {
+            items: Box::new(Schema::Int),
+            max_len: 1,
+            truncation: Truncation::Tail,
+        };
+        assert_eq!(decode(&tail, &mut &encoded[..]).unwrap(), vec![1i32].avro());
+    }
+
+    #[test]
+    fn test_encode_bloom() {
+        use crate::bloom::BloomFilter;
+        use crate::decode::decode;
+
+        let schema = Schema::Bloom { bits: 64, hashes: 3 };
+        let mut filter = BloomFilter::new(64, 3).unwrap();
+        filter.insert("foo");
+        let value = Value::Bloom(filter, None);
+        let encoded = encode_to_vec(&value, &schema);
+        assert_eq!(value.encoded_size(&schema), encoded.len());
+        assert_eq!(decode(&schema, &mut &encoded[..]).unwrap(), value);
+        assert!(decode(&Schema::Bloom { bits: 128, hashes: 3 }, &mut &encoded[..]).is_err());
+
+        // filters are encoded as bytes, from which they can be resolved
+        let bytes = decode(&Schema::Bytes, &mut &encoded[..]).unwrap();
+        assert_eq!(bytes.clone().resolve(&schema).unwrap(), value);
+        assert!(bytes.resolve(&Schema::Bloom { bits: 128, hashes: 3 }).is_err());
+    }
+
+    #[test]
+    fn test_encode_topk() {
+        use crate::decode::decode;
+
+        let schema = Schema::TopK(2);
+        let mut a = TopK::new(3);
+        let mut b = TopK::new(3);
+        for (key, count) in &[("x", 1), ("y", 2), ("z", 2)] {
+            a.insert(*key, *count);
+        }
+        for (key, count) in &[("z", 2), ("x", 1), ("y", 2)] {
+            b.insert(*key, *count);
+        }
+
+        // summaries with the same counts are encoded to the same bytes, beyond the schema size
+        let encoded = encode_to_vec(&Value::TopK(Box::new(a.clone()), None), &schema);
+        assert_eq!(encoded, encode_to_vec(&Value::TopK(Box::new(b), None), &schema));
+        assert_eq!(Value::TopK(Box::new(a.clone()), None).encoded_size(&schema), encoded.len());
+
+        a.truncate(2);
+        assert_eq!(decode(&schema, &mut &encoded[..]).unwrap(), Value::TopK(Box::new(a), None));
+    }
+
+    #[test]
+    fn test_encode_date_unit() {
+        use crate::decode::decode;
+
+        let value = Value::Date(1_600_000_000_123, None);
+        let schema = Schema::Date(DateUnit::Seconds);
+        let encoded = encode_to_vec(&value, &schema);
+        assert_eq!(encoded, encode_to_vec(&1_600_000_000i64.avro(), &Schema::Long));
+        assert_eq!(
+            decode(&schema, &mut &encoded[..]).unwrap(),
+            Value::Date(1_600_000_000_000, None)
+        );
+
+        let schema = Schema::Date(DateUnit::Micros);
+        let encoded = encode_to_vec(&value, &schema);
+        assert_eq!(decode(&schema, &mut &encoded[..]).unwrap(), value);
+    }
+
+    #[test]
+    fn test_encode_lru_set_evicts() {
+        use crate::decode::decode;
+        use crate::types::LruValue;
+        use crate::LruLimit;
+
+        let mut items = HashMap::new();
+        items.insert("old".to_string(), LruValue::new(1, 10));
+        items.insert("new".to_string(), LruValue::new(2, 1));
+        let value = Value::LruSet(Box::new(items.into()), LruLimit::Count(2), None);
+        let schema = Schema::LruSet(LruLimit::Count(1), None);
+
+        let encoded = encode_to_vec(&value, &schema);
+        assert_eq!(value.encoded_size(&schema), encoded.len());
+
+        let mut expected = HashMap::new();
+        expected.insert("new".to_string(), LruValue::new(2, 1));
+        assert_eq!(
+            decode(&schema, &mut &encoded[..]).unwrap(),
+            Value::LruSet(Box::new(expected.into()), LruLimit::Count(1), None)
+        );
+    }
+
+    #[test]
+    fn test_encode_sorted() {
+        let options = EncodeOptions { sorted: true };
+        let keys = (0..32).map(|i| format!("key{}", i)).collect::<Vec<_>>();
+
+        let map = keys.iter().map(|key| (key.clone(), 1i64.avro())).collect::<HashMap<_, _>>();
+        let reversed = keys
+            .iter()
+            .rev()
+            .map(|key| (key.clone(), 1i64.avro()))
+            .collect::<HashMap<_, _>>();
+        let schema = Schema::Map(Box::new(Schema::Long));
+        let mut a = Vec::new();
+        encode_with_options(&Value::Map(map, None), &schema, &options, &mut a);
+        let mut b = Vec::new();
+        encode_with_options(&Value::Map(reversed, None), &schema, &options, &mut b);
+        assert_eq!(a, b);
+
+        let set = Value::Set(keys.iter().map(|key| key.as_str().into()).collect(), None);
+        let schema = Schema::Set(Box::new(Schema::String));
+        let mut encoded = Vec::new();
+        encode_with_options(&set, &schema, &options, &mut encoded);
+        let mut expected = Vec::new();
+        let mut sorted = keys.clone();
+        sorted.sort();
+        encode(
+            &Value::Array(sorted.into_iter().map(|key| key.avro()).collect(), None),
+            &Schema::Array(Box::new(Schema::String)),
+            &mut expected,
+        );
+        assert_eq!(encoded, expected);
+    }
 }
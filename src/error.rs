@@ -0,0 +1,353 @@
+//! Logic for the errors returned by the library.
+use std::error;
+use std::fmt;
+use std::io;
+use std::string::FromUtf8Error;
+
+use thiserror::Error;
+
+use crate::de;
+use crate::ser;
+use crate::types::PutError;
+use crate::visit::PathSegment;
+
+/// Error returned by the library, to be matched on instead of inspecting its message.
+///
+/// Errors raised while decoding or resolving the values of records, arrays and maps are given
+/// the [ErrorContext](struct.ErrorContext.html) they were raised in, as an
+/// `AvroError::Context`: match on [`kind`](#method.kind), which is the same whether the error was
+/// given a context or not, rather than on the variants themselves.
+#[derive(Debug, Error)]
+pub enum AvroError {
+    /// A schema, a JSON document or a CSV record failed to parse.
+    #[error("Parse error: {0}")]
+    Parse(String),
+    /// A value or a schema failed to resolve against another schema, or to be converted to the
+    /// types of another format: Arrow, CSV, Polars, protobuf, generated Rust code or SQL DDL.
+    #[error("Resolution error: {0}")]
+    Resolution(String),
+    /// Encoded bytes failed to decode, as they are ill-formed or cut.
+    #[error("Decoding error: {0}")]
+    Decode(String),
+    /// Reading or writing failed, including on input ending in the middle of a value.
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    /// A value didn't match the schema it was written or put with.
+    #[error("Validation error: {0}")]
+    Validation(String),
+    /// A value, a block or an allocation was larger or deeper than allowed.
+    #[error("Limit exceeded: {0}")]
+    Limit(String),
+    /// An error along with where it was raised, see [ErrorContext](struct.ErrorContext.html).
+    #[error(transparent)]
+    Context(Box<ErrorContext>),
+}
+
+/// Kind of an [AvroError](enum.AvroError.html), as given by
+/// [`AvroError::kind`](enum.AvroError.html#method.kind).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// See `AvroError::Parse`.
+    Parse,
+    /// See `AvroError::Resolution`.
+    Resolution,
+    /// See `AvroError::Decode`.
+    Decode,
+    /// See `AvroError::Io`.
+    Io,
+    /// See `AvroError::Validation`.
+    Validation,
+    /// See `AvroError::Limit`.
+    Limit,
+}
+
+// Errors of other crates are kept as the message of the kind they belong to.
+macro_rules! error_from (
+    ($variant:ident, $error:ty) => (
+        impl From<$error> for AvroError {
+            fn from(error: $error) -> AvroError {
+                AvroError::$variant(error.to_string())
+            }
+        }
+    );
+);
+
+error_from!(Validation, PutError);
+error_from!(Resolution, FromUtf8Error);
+error_from!(Validation, ser::Error);
+error_from!(Resolution, de::Error);
+#[cfg(feature = "snappy")]
+error_from!(Decode, snap::Error);
+#[cfg(feature = "arrow")]
+error_from!(Resolution, arrow::error::ArrowError);
+#[cfg(feature = "parquet")]
+error_from!(Decode, parquet::errors::ParquetError);
+#[cfg(feature = "polars")]
+error_from!(Resolution, polars::error::PolarsError);
+#[cfg(feature = "protobuf")]
+error_from!(Decode, prost::DecodeError);
+
+impl From<serde_json::Error> for AvroError {
+    fn from(error: serde_json::Error) -> AvroError {
+        if error.is_io() {
+            AvroError::Io(error.into())
+        } else {
+            AvroError::Parse(error.to_string())
+        }
+    }
+}
+
+#[cfg(feature = "csv")]
+impl From<csv::Error> for AvroError {
+    fn from(error: csv::Error) -> AvroError {
+        if error.is_io_error() {
+            AvroError::Io(error.into())
+        } else {
+            AvroError::Parse(error.to_string())
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl From<rayon::ThreadPoolBuildError> for AvroError {
+    fn from(error: rayon::ThreadPoolBuildError) -> AvroError {
+        AvroError::Io(io::Error::other(error))
+    }
+}
+
+impl AvroError {
+    /// Kind of the error, whether it was given an [ErrorContext](struct.ErrorContext.html) or
+    /// not.
+    pub fn kind(&self) -> ErrorKind {
+        match self.root() {
+            AvroError::Parse(_) => ErrorKind::Parse,
+            AvroError::Resolution(_) => ErrorKind::Resolution,
+            AvroError::Decode(_) => ErrorKind::Decode,
+            AvroError::Io(_) => ErrorKind::Io,
+            AvroError::Validation(_) => ErrorKind::Validation,
+            AvroError::Limit(_) => ErrorKind::Limit,
+            AvroError::Context(context) => context.error().kind(),
+        }
+    }
+
+    /// The error without the [ErrorContext](struct.ErrorContext.html) it was raised in, if any.
+    pub fn root(&self) -> &AvroError {
+        match self {
+            AvroError::Context(context) => context.error(),
+            error => error,
+        }
+    }
+
+    /// Where the error was raised, if known.
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            AvroError::Context(context) => Some(context),
+            _ => None,
+        }
+    }
+}
+
+/// Error raised while decoding or resolving a value, along with where it was raised: the path of
+/// the value from the root one, such as `orders[3].total`, and, when read from a file, the index
+/// of the data block and the offset in the block of the item being decoded.
+///
+/// Decode and resolution errors raised in records, arrays and maps are returned as an
+/// `AvroError::Context`, of which [`root`](enum.AvroError.html#method.root) still gives the
+/// original error.
+#[derive(Debug)]
+pub struct ErrorContext {
+    // segments formatted as `PathSegment`, with the leading `.` of a field
+    path: String,
+    block: Option<usize>,
+    offset: Option<usize>,
+    error: AvroError,
+}
+
+impl ErrorContext {
+    fn new(error: AvroError) -> ErrorContext {
+        ErrorContext {
+            path: String::new(),
+            block: None,
+            offset: None,
+            error,
+        }
+    }
+
+    /// Path of the value from the root one, such as `orders[3].total`, empty for the root value.
+    pub fn path(&self) -> &str {
+        self.path.strip_prefix('.').unwrap_or(&self.path)
+    }
+
+    /// Index of the data block of the file, counted from the start of the file, if known. Blocks
+    /// reached by seeking have no known index.
+    pub fn block(&self) -> Option<usize> {
+        self.block
+    }
+
+    /// Offset of the item being decoded in the decompressed bytes of its data block.
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+
+    /// Error wrapped in this context.
+    pub fn error(&self) -> &AvroError {
+        &self.error
+    }
+}
+
+impl error::Error for ErrorContext {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.error)?;
+        if !self.path.is_empty() {
+            write!(formatter, " at `{}`", self.path())?;
+        }
+        match (self.block, self.offset) {
+            (Some(block), Some(offset)) => write!(formatter, " (block {}, byte {})", block, offset),
+            (None, Some(offset)) => write!(formatter, " (byte {} of the block)", offset),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn with_context<F: FnOnce(&mut ErrorContext)>(error: AvroError, f: F) -> AvroError {
+    let mut context = match error {
+        AvroError::Context(context) => context,
+        error => Box::new(ErrorContext::new(error)),
+    };
+    f(&mut context);
+    AvroError::Context(context)
+}
+
+/// Prepend `segment` to the path of the error of `result`, if any.
+pub(crate) fn at<T>(result: Result<T, AvroError>, segment: PathSegment) -> Result<T, AvroError> {
+    result.map_err(|error| {
+        with_context(error, |context| context.path.insert_str(0, &segment.to_string()))
+    })
+}
+
+/// Set the data block and the offset in the block of the error of `result`, if any.
+pub(crate) fn in_block<T>(
+    result: Result<T, AvroError>,
+    block: Option<usize>,
+    offset: usize,
+) -> Result<T, AvroError> {
+    result.map_err(|error| {
+        with_context(error, |context| {
+            context.block = block;
+            context.offset = Some(offset);
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::decode;
+    use crate::schema::Schema;
+    use crate::types::Value;
+    use crate::util::DecodeOptions;
+    use crate::writer::to_avro_datum;
+
+    #[test]
+    fn test_avro_error_kinds() {
+        let error = Schema::parse_str(r#"{"type": "nope"}"#).unwrap_err();
+        assert!(matches!(error, AvroError::Parse(ref message) if message.contains("nope")));
+
+        let error = Value::Boolean(true, None).resolve(&Schema::Long).unwrap_err();
+        assert!(matches!(error, AvroError::Resolution(_)));
+
+        let error = decode(&Schema::Boolean, &mut &[2u8][..]).unwrap_err();
+        assert!(matches!(error, AvroError::Decode(_)));
+
+        let error = decode(&Schema::Long, &mut &[][..]).unwrap_err();
+        match error {
+            AvroError::Io(error) => assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof),
+            other => panic!("io error expected, got {:?}", other),
+        }
+
+        let error = to_avro_datum(&Schema::Long, Value::Null).unwrap_err();
+        assert!(matches!(error, AvroError::Validation(_)));
+
+        let mut encoded = Vec::new();
+        let max_len = DecodeOptions::default().max_bytes_len;
+        crate::util::zig_i64(max_len as i64 + 1, &mut encoded);
+        let error = decode(&Schema::Bytes, &mut &encoded[..]).unwrap_err();
+        assert!(matches!(error, AvroError::Limit(_)));
+
+        let error = Schema::parse_str("{").unwrap_err();
+        assert!(matches!(error, AvroError::Parse(_)));
+
+        // kinds are kept by errors given a context
+        let schema = Schema::Array(Box::new(Schema::Boolean));
+        let error = decode(&schema, &mut &[2u8, 2][..]).unwrap_err();
+        assert!(error.to_string().ends_with(" at `[0]`"), "{}", error);
+        assert!(!matches!(error, AvroError::Decode(_)));
+        assert_eq!(error.kind(), ErrorKind::Decode);
+        assert!(matches!(error.root(), AvroError::Decode(_)));
+        let error = decode(&schema, &mut &[2u8][..]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::Io);
+        // and are their own root otherwise
+        let error = AvroError::Decode("not a bool".to_owned());
+        assert!(error.context().is_none());
+        assert_eq!(error.kind(), ErrorKind::Decode);
+        assert!(matches!(error.root(), AvroError::Decode(_)));
+    }
+
+    #[test]
+    fn test_avro_error_messages() {
+        let messages = [
+            AvroError::Parse("e".to_owned()).to_string(),
+            AvroError::Resolution("e".to_owned()).to_string(),
+            AvroError::Decode("e".to_owned()).to_string(),
+            AvroError::Validation("e".to_owned()).to_string(),
+            AvroError::Limit("e".to_owned()).to_string(),
+        ];
+        for (i, message) in messages.iter().enumerate() {
+            assert!(!messages[..i].contains(message), "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_error_context_path() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "r", "fields": [
+                {"name": "orders", "type": {"type": "array", "items": {
+                    "type": "record", "name": "order", "fields": [
+                        {"name": "total", "type": "boolean"}
+                    ]
+                }}},
+                {"name": "tags", "type": {"type": "map", "values": "long"}}
+            ]}"#,
+        ).unwrap();
+        let check = |error: AvroError, path: &str| {
+            let context = error.context().unwrap();
+            assert_eq!(context.path(), path);
+            assert_eq!(context.block(), None);
+            assert!(error.to_string().ends_with(&format!(" at `{}`", path)), "{}", error);
+        };
+
+        // four orders, the last one with an invalid boolean
+        let error = decode(&schema, &mut &[8u8, 0, 1, 0, 2][..]).unwrap_err();
+        assert!(matches!(error.root(), AvroError::Decode(_)));
+        check(error, "orders[3].total");
+        // no order, a tag cut short
+        let error = decode(&schema, &mut &[0u8, 2, 2, b'k'][..]).unwrap_err();
+        check(error, r#"tags["k"]"#);
+
+        let value = Value::Record(vec![
+            ("orders".into(), Value::Array(vec![
+                Value::Record(vec![("total".into(), Value::Long(1, None))], None),
+            ], None)),
+            ("tags".into(), Value::Map(Default::default(), None)),
+        ], None);
+        let error = value.resolve(&schema).unwrap_err();
+        assert!(matches!(error.root(), AvroError::Resolution(_)));
+        check(error, "orders[0].total");
+    }
+}
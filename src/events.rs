@@ -0,0 +1,63 @@
+//! Logic for the handling of Avro values as a stream of decoding events.
+use crate::borrowed::ValueRef;
+use crate::error::AvroError;
+use crate::schema::{RecordField, Schema};
+
+/// Handler of the events met while decoding a value with
+/// [`decode_events`](decode/fn.decode_events.html), which builds no value: records, arrays and maps
+/// are reported by their start, their fields, items or keys, and their end, and every other value
+/// as a [ValueRef](enum.ValueRef.html) borrowed from the decoded buffer.
+///
+/// Unions and optionals are transparent: the value they hold is reported, or a `ValueRef::Null` if
+/// they hold none. The items of bounded lists are reported as they were written, before any
+/// truncation. Every method does nothing by default but [`value`](#tymethod.value). Returning an
+/// error stops the decoding.
+pub trait DecodeHandler {
+    /// Start of a record, of which the fields follow.
+    fn record_start(&mut self, _schema: &Schema) -> Result<(), AvroError> {
+        Ok(())
+    }
+
+    /// Field of a record, of which the value follows.
+    fn field(&mut self, _field: &RecordField) -> Result<(), AvroError> {
+        Ok(())
+    }
+
+    /// End of a record.
+    fn record_end(&mut self) -> Result<(), AvroError> {
+        Ok(())
+    }
+
+    /// Start of an array, of which the items follow.
+    fn array_start(&mut self) -> Result<(), AvroError> {
+        Ok(())
+    }
+
+    /// Item of an array at `index`, of which the value follows.
+    fn array_item(&mut self, _index: usize) -> Result<(), AvroError> {
+        Ok(())
+    }
+
+    /// End of an array.
+    fn array_end(&mut self) -> Result<(), AvroError> {
+        Ok(())
+    }
+
+    /// Start of a map, of which the entries follow.
+    fn map_start(&mut self) -> Result<(), AvroError> {
+        Ok(())
+    }
+
+    /// Key of an entry of a map, of which the value follows.
+    fn map_key(&mut self, _key: &str) -> Result<(), AvroError> {
+        Ok(())
+    }
+
+    /// End of a map.
+    fn map_end(&mut self) -> Result<(), AvroError> {
+        Ok(())
+    }
+
+    /// Value other than a record, an array or a map.
+    fn value(&mut self, value: ValueRef) -> Result<(), AvroError>;
+}
@@ -0,0 +1,197 @@
+//! Logic for the HyperLogLog cardinality sketches held by `hll` values.
+use std::hash::Hasher;
+
+use crate::error::AvroError;
+use crate::ordering::FnvHasher;
+
+/// Smallest precision of a [HyperLogLog](struct.HyperLogLog.html) sketch.
+pub const MIN_PRECISION: u8 = 4;
+/// Largest precision of a [HyperLogLog](struct.HyperLogLog.html) sketch.
+pub const MAX_PRECISION: u8 = 16;
+
+/// HyperLogLog sketch estimating the number of distinct items added to it.
+///
+/// A sketch of precision `p` holds `2^p` one-byte registers and estimates cardinalities with a
+/// standard error of about `1.04 / sqrt(2^p)`. Items are hashed in a stable way, so sketches built
+/// by different processes can be merged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HyperLogLog {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+/// Stable 64-bit hash of an item: FNV-1a, followed by the MurmurHash3 finalizer so that every
+/// bit depends on the whole input.
+pub(crate) fn hash(bytes: &[u8]) -> u64 {
+    let mut hasher = FnvHasher::default();
+    hasher.write(bytes);
+    let mut h = hasher.finish();
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^ (h >> 33)
+}
+
+impl HyperLogLog {
+    /// Create an empty sketch, if the precision is between
+    /// [`MIN_PRECISION`](constant.MIN_PRECISION.html) and
+    /// [`MAX_PRECISION`](constant.MAX_PRECISION.html).
+    pub fn new(precision: u8) -> Option<HyperLogLog> {
+        if !(MIN_PRECISION..=MAX_PRECISION).contains(&precision) {
+            return None
+        }
+        Some(HyperLogLog {
+            precision,
+            registers: vec![0; 1 << precision],
+        })
+    }
+
+    /// Precision of the sketch.
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    /// Add an item to the sketch.
+    pub fn add<B: AsRef<[u8]> + ?Sized>(&mut self, item: &B) {
+        let h = hash(item.as_ref());
+        let index = (h >> (64 - u32::from(self.precision))) as usize;
+        let rest = h << self.precision;
+        let rank = if rest == 0 {
+            64 - self.precision + 1
+        } else {
+            rest.leading_zeros() as u8 + 1
+        };
+        if self.registers[index] < rank {
+            self.registers[index] = rank;
+        }
+    }
+
+    /// Merge another sketch into this one, which then estimates the cardinality of the union of
+    /// both. Fails if the precisions of the sketches differ.
+    pub fn merge(&mut self, other: &HyperLogLog) -> Result<(), AvroError> {
+        if self.precision != other.precision {
+            return Err(AvroError::Resolution(format!(
+                "Cannot merge HyperLogLog sketches of precisions {} and {}",
+                self.precision, other.precision
+            )))
+        }
+        for (register, other) in self.registers.iter_mut().zip(other.registers.iter()) {
+            if *register < *other {
+                *register = *other;
+            }
+        }
+        Ok(())
+    }
+
+    /// Estimate the number of distinct items added to the sketch.
+    pub fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let sum = self
+            .registers
+            .iter()
+            .map(|register| 2f64.powi(-i32::from(*register)))
+            .sum::<f64>();
+        let estimate = alpha * m * m / sum;
+
+        let zeros = self.registers.iter().filter(|register| **register == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            // linear counting is more accurate for small cardinalities
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+
+    /// Serialize the sketch as its precision followed by its registers.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.registers.len() + 1);
+        bytes.push(self.precision);
+        bytes.extend_from_slice(&self.registers);
+        bytes
+    }
+
+    /// Deserialize a sketch serialized with [`to_bytes`](#method.to_bytes).
+    pub fn from_bytes(bytes: &[u8]) -> Result<HyperLogLog, AvroError> {
+        let (precision, registers) = match bytes.split_first() {
+            Some((precision, registers)) => (*precision, registers),
+            None => return Err(AvroError::Decode("Empty HyperLogLog sketch".to_owned())),
+        };
+        let mut sketch = HyperLogLog::new(precision).ok_or_else(|| {
+            AvroError::Decode(format!("Invalid HyperLogLog precision: {}", precision))
+        })?;
+        if registers.len() != sketch.registers.len() {
+            return Err(AvroError::Decode(format!(
+                "HyperLogLog sketch of precision {} expects {} registers, got {}",
+                precision,
+                sketch.registers.len(),
+                registers.len()
+            )))
+        }
+        sketch.registers.copy_from_slice(registers);
+        Ok(sketch)
+    }
+
+    /// Number of bytes of the serialized sketch.
+    pub(crate) fn serialized_len(&self) -> usize {
+        self.registers.len() + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hll_estimate() {
+        let mut sketch = HyperLogLog::new(12).unwrap();
+        assert_eq!(sketch.estimate(), 0);
+        for i in 0..10_000 {
+            sketch.add(&format!("item-{}", i));
+            sketch.add(&format!("item-{}", i / 2));
+        }
+
+        let error = (sketch.estimate() as f64 - 10_000.0).abs() / 10_000.0;
+        assert!(error < 0.05, "estimate {} is too far off", sketch.estimate());
+    }
+
+    #[test]
+    fn test_hll_merge() {
+        let mut a = HyperLogLog::new(10).unwrap();
+        let mut b = HyperLogLog::new(10).unwrap();
+        let mut both = HyperLogLog::new(10).unwrap();
+        for i in 0..1000 {
+            let item = i.to_string();
+            if i % 2 == 0 {
+                a.add(&item);
+            } else {
+                b.add(&item);
+            }
+            both.add(&item);
+        }
+
+        a.merge(&b).unwrap();
+        assert_eq!(a, both);
+        assert!(a.merge(&HyperLogLog::new(11).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_hll_bytes() {
+        let mut sketch = HyperLogLog::new(4).unwrap();
+        sketch.add("foo");
+        let bytes = sketch.to_bytes();
+        assert_eq!(bytes.len(), sketch.serialized_len());
+        assert_eq!(HyperLogLog::from_bytes(&bytes).unwrap(), sketch);
+
+        assert!(HyperLogLog::from_bytes(&[]).is_err());
+        assert!(HyperLogLog::from_bytes(&[3, 0, 0]).is_err());
+        assert!(HyperLogLog::from_bytes(&bytes[..5]).is_err());
+        assert!(HyperLogLog::new(17).is_none());
+    }
+}
@@ -0,0 +1,890 @@
+//! Logic for the secondary indexes of the fields flagged with `index` in Avro files.
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::io::{Read, Write};
+use std::mem::replace;
+
+use crate::bloom::BloomFilter;
+use crate::decode::decode;
+use crate::encode::{encode, encode_to_vec};
+use crate::error::AvroError;
+use crate::hll::hash;
+use crate::schema::Schema;
+use crate::types::{ToAvro, Value};
+
+lazy_static! {
+    static ref INDEX_SCHEMA: Schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "file_index",
+                "fields": [
+                    {"name": "fields", "type": {"type": "map", "values": {
+                        "type": "array",
+                        "items": {
+                            "type": "record",
+                            "name": "index_entry",
+                            "fields": [
+                                {"name": "key", "type": "bytes"},
+                                {"name": "postings", "type": {"type": "array", "items": "long"}}
+                            ]
+                        }
+                    }}},
+                    {"name": "blocks", "type": {"type": "array", "items": {
+                        "type": "record",
+                        "name": "block_filters",
+                        "fields": [
+                            {"name": "offset", "type": "long"},
+                            {"name": "filters", "type": {"type": "map", "values": {
+                                "type": "record",
+                                "name": "filter",
+                                "fields": [
+                                    {"name": "bits", "type": "long"},
+                                    {"name": "hashes", "type": "int"},
+                                    {"name": "bytes", "type": "bytes"}
+                                ]
+                            }}},
+                            {"name": "stats", "type": {"type": "map", "values": {
+                                "type": "record",
+                                "name": "stats",
+                                "fields": [
+                                    {"name": "min", "type": ["null", "bytes"]},
+                                    {"name": "max", "type": ["null", "bytes"]},
+                                    {"name": "null_count", "type": "long"}
+                                ]
+                            }}}
+                        ]
+                    }}}
+                ]
+            }
+        "#,
+        ).unwrap();
+}
+
+/// Number of bits per value of the Bloom filters of blocks, giving about 1% of false positives
+/// with `BLOCK_FILTER_HASHES` hash functions.
+const BLOCK_FILTER_BITS_PER_VALUE: usize = 10;
+const BLOCK_FILTER_HASHES: u8 = 7;
+const BLOCK_FILTER_MIN_BITS: usize = 64;
+
+const SIDECAR_MAGIC: &[u8] = b"Idx\x01";
+
+/// Location of a record holding a given value of an indexed field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Posting {
+    /// Offset of the block holding the record, in bytes from the start of the file.
+    pub block_offset: u64,
+    /// Position of the record in the file, counted from 0.
+    pub ordinal: u64,
+}
+
+/// Statistics of the values of an indexed field in a block, kept for the fields of numeric,
+/// string or date types, nullable or not.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockStats {
+    /// Smallest non-null value of the field in the block, `None` if all values are null.
+    pub min: Option<Value>,
+    /// Largest non-null value of the field in the block, `None` if all values are null.
+    pub max: Option<Value>,
+    /// Number of null values of the field in the block.
+    pub null_count: u64,
+}
+
+/// `BlockStats` with their values in binary encoding.
+#[derive(Clone, Debug, PartialEq)]
+struct EncodedStats {
+    min: Option<Vec<u8>>,
+    max: Option<Vec<u8>>,
+    null_count: u64,
+}
+
+impl EncodedStats {
+    fn decode(&self, schema: &Schema) -> Result<BlockStats, AvroError> {
+        let value = |bytes: &Option<Vec<u8>>| {
+            bytes.as_ref().map(|bytes| decode(schema, &mut &bytes[..])).transpose()
+        };
+        Ok(BlockStats {
+            min: value(&self.min)?,
+            max: value(&self.max)?,
+            null_count: self.null_count,
+        })
+    }
+}
+
+/// Secondary index of an Avro file, mapping the values of its indexed fields to the records
+/// holding them.
+///
+/// Indexed fields are the record fields flagged with `"index": true` in the schema of the file,
+/// nested records included, and are named by their dot-separated path from the top-level record.
+/// Values are keyed by their binary encoding, so that lookups do not depend on how the value was
+/// built.
+///
+/// Besides the postings of each value, the index holds a Bloom filter of the values of each
+/// indexed field in each block, which tells the blocks that cannot hold a value at a fraction of
+/// the size of the postings, and the [statistics](struct.BlockStats.html) of the values of each
+/// indexed field of numeric, string or date type in each block, which tell the blocks that cannot
+/// hold a value in a given range.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileIndex {
+    fields: BTreeMap<String, BTreeMap<Vec<u8>, Vec<Posting>>>,
+    blocks: BTreeMap<u64, BTreeMap<String, BloomFilter>>,
+    stats: BTreeMap<u64, BTreeMap<String, EncodedStats>>,
+}
+
+impl FileIndex {
+    /// Paths of the indexed fields.
+    pub fn fields(&self) -> impl Iterator<Item = &str> {
+        self.fields.keys().map(String::as_str)
+    }
+
+    /// Locations of the records whose field at `path` is encoded to `key`, in file order.
+    pub fn postings(&self, path: &str, key: &[u8]) -> &[Posting] {
+        self.fields
+            .get(path)
+            .and_then(|keys| keys.get(key))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Locations of the records whose field at `path` is equal to `value`, in file order. The
+    /// value is resolved against the schema of the field, which is looked up in `schema`, the
+    /// schema of the indexed file.
+    pub fn lookup(
+        &self,
+        schema: &Schema,
+        path: &str,
+        value: Value,
+    ) -> Result<&[Posting], AvroError> {
+        Ok(self.postings(path, &index_key(schema, path, value)?))
+    }
+
+    /// Offsets of the blocks of the indexed file, in file order.
+    pub fn block_offsets(&self) -> impl Iterator<Item = u64> + '_ {
+        self.blocks.keys().cloned()
+    }
+
+    /// Whether the block starting at `block_offset` might hold a record whose field at `path` is
+    /// encoded to `key`. Blocks without a filter for the field might hold any value.
+    pub fn block_might_contain(&self, block_offset: u64, path: &str, key: &[u8]) -> bool {
+        self.blocks
+            .get(&block_offset)
+            .and_then(|filters| filters.get(path))
+            .is_none_or(|filter| filter.might_contain(key))
+    }
+
+    /// Offsets of the blocks which might hold a record whose field at `path` is equal to `value`,
+    /// according to their Bloom filters, in file order. The value is resolved as by
+    /// [`lookup`](#method.lookup).
+    pub fn candidate_blocks(
+        &self,
+        schema: &Schema,
+        path: &str,
+        value: Value,
+    ) -> Result<Vec<u64>, AvroError> {
+        let key = index_key(schema, path, value)?;
+        Ok(self
+            .block_offsets()
+            .filter(|offset| self.block_might_contain(*offset, path, &key))
+            .collect())
+    }
+
+    /// Statistics of the values of the field at `path` in the block starting at `block_offset`,
+    /// if any. `schema` is the schema of the indexed file.
+    pub fn block_stats(
+        &self,
+        schema: &Schema,
+        block_offset: u64,
+        path: &str,
+    ) -> Result<Option<BlockStats>, AvroError> {
+        let schema = stats_field_schema(schema, path)?;
+        self.stats
+            .get(&block_offset)
+            .and_then(|stats| stats.get(path))
+            .map(|stats| stats.decode(schema))
+            .transpose()
+    }
+
+    /// Offsets of the blocks which might hold a record whose field at `path` is between `min`
+    /// and `max`, both inclusive and unbounded if `None`, according to their statistics, in file
+    /// order. Blocks without statistics for the field might hold any value, and blocks where the
+    /// field is always null hold none. The bounds are resolved against the schema of the field,
+    /// looked up in `schema`, the schema of the indexed file.
+    pub fn blocks_in_range(
+        &self,
+        schema: &Schema,
+        path: &str,
+        min: Option<Value>,
+        max: Option<Value>,
+    ) -> Result<Vec<u64>, AvroError> {
+        let schema = stats_field_schema(schema, path)?;
+        let min = min.map(|min| min.resolve(schema)).transpose()?;
+        let max = max.map(|max| max.resolve(schema)).transpose()?;
+
+        let mut offsets = Vec::new();
+        for offset in self.block_offsets() {
+            if self.block_in_range(schema, offset, path, min.as_ref(), max.as_ref())? {
+                offsets.push(offset);
+            }
+        }
+        Ok(offsets)
+    }
+
+    /// Whether the block starting at `block_offset` might hold a value of the field at `path`
+    /// between `min` and `max`, already resolved against `schema`, the schema of the values of
+    /// the statistics of the field.
+    pub(crate) fn block_in_range(
+        &self,
+        schema: &Schema,
+        block_offset: u64,
+        path: &str,
+        min: Option<&Value>,
+        max: Option<&Value>,
+    ) -> Result<bool, AvroError> {
+        let stats = match self.stats.get(&block_offset).and_then(|stats| stats.get(path)) {
+            Some(stats) => stats.decode(schema)?,
+            None => return Ok(true),
+        };
+        Ok(match (stats.min, stats.max) {
+            (Some(block_min), Some(block_max)) => {
+                min.is_none_or(|min| block_max.cmp_with_schema(min, schema) != Ordering::Less)
+                    && max.is_none_or(|max| {
+                        block_min.cmp_with_schema(max, schema) != Ordering::Greater
+                    })
+            },
+            _ => false,
+        })
+    }
+
+    fn insert(&mut self, path: &str, key: Vec<u8>, posting: Posting) {
+        if !self.fields.contains_key(path) {
+            self.fields.insert(path.to_owned(), BTreeMap::new());
+        }
+        if let Some(keys) = self.fields.get_mut(path) {
+            keys.entry(key).or_insert_with(Vec::new).push(posting);
+        }
+    }
+
+    /// Serialize the index as a single Avro datum, keys and postings sorted so that equal indexes
+    /// are written to the same bytes.
+    ///
+    /// Return the number of bytes written.
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<usize, AvroError> {
+        let fields = self
+            .fields
+            .iter()
+            .map(|(path, keys)| {
+                let entries = keys
+                    .iter()
+                    .map(|(key, postings)| {
+                        let postings = postings
+                            .iter()
+                            .flat_map(|p| vec![p.block_offset as i64, p.ordinal as i64])
+                            .collect::<Vec<_>>();
+                        Value::record(vec![
+                            ("key", Value::Bytes(key.clone(), None)),
+                            ("postings", postings.avro()),
+                        ])
+                    })
+                    .collect::<Vec<_>>();
+                (path.clone(), Value::Array(entries, None))
+            })
+            .collect();
+        let blocks = self
+            .blocks
+            .iter()
+            .map(|(offset, filters)| {
+                let filters = filters
+                    .iter()
+                    .map(|(path, filter)| {
+                        let filter = Value::record(vec![
+                            ("bits", i64::from(filter.bits()).avro()),
+                            ("hashes", i32::from(filter.hashes()).avro()),
+                            ("bytes", Value::Bytes(filter.as_bytes().to_vec(), None)),
+                        ]);
+                        (path.clone(), filter)
+                    })
+                    .collect();
+                let nullable = |bytes: &Option<Vec<u8>>| {
+                    let value = match bytes {
+                        Some(bytes) => Value::Bytes(bytes.clone(), None),
+                        None => Value::Null,
+                    };
+                    Value::Union(Box::new(value), None)
+                };
+                let stats = self
+                    .stats
+                    .get(offset)
+                    .into_iter()
+                    .flatten()
+                    .map(|(path, stats)| {
+                        let stats = Value::record(vec![
+                            ("min", nullable(&stats.min)),
+                            ("max", nullable(&stats.max)),
+                            ("null_count", (stats.null_count as i64).avro()),
+                        ]);
+                        (path.clone(), stats)
+                    })
+                    .collect();
+                Value::record(vec![
+                    ("offset", (*offset as i64).avro()),
+                    ("filters", Value::Map(filters, None)),
+                    ("stats", Value::Map(stats, None)),
+                ])
+            })
+            .collect();
+
+        let index = Value::record(vec![
+            ("fields", Value::Map(fields, None)),
+            ("blocks", Value::Array(blocks, None)),
+        ]);
+        let mut buffer = Vec::new();
+        encode(&index, &INDEX_SCHEMA, &mut buffer);
+        writer.write_all(&buffer)?;
+        Ok(buffer.len())
+    }
+
+    /// Deserialize an index serialized with [`write_to`](#method.write_to).
+    pub fn read_from<R: Read>(mut reader: R) -> Result<FileIndex, AvroError> {
+        let (fields, blocks) = match record_fields(decode(&INDEX_SCHEMA, &mut reader)?)? {
+            [Value::Map(fields, _), Value::Array(blocks, _)] => (fields, blocks),
+            _ => return Err(invalid_index()),
+        };
+
+        let mut index = FileIndex::default();
+        for (path, entries) in fields {
+            let entries = match entries {
+                Value::Array(entries, _) => entries,
+                _ => return Err(invalid_index()),
+            };
+            let mut keys = BTreeMap::new();
+            for entry in entries {
+                let (key, postings) = match record_fields(entry)? {
+                    [Value::Bytes(key, _), Value::Array(postings, _)] => (key, postings),
+                    _ => return Err(invalid_index()),
+                };
+                let postings = postings
+                    .chunks(2)
+                    .map(|pair| match pair {
+                        [Value::Long(block_offset, _), Value::Long(ordinal, _)] => Ok(Posting {
+                            block_offset: *block_offset as u64,
+                            ordinal: *ordinal as u64,
+                        }),
+                        _ => Err(invalid_index()),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                keys.insert(key, postings);
+            }
+            index.fields.insert(path, keys);
+        }
+
+        for block in blocks {
+            let (offset, filters, stats) = match record_fields(block)? {
+                [Value::Long(offset, _), Value::Map(filters, _), Value::Map(stats, _)] => {
+                    (offset as u64, filters, stats)
+                },
+                _ => return Err(invalid_index()),
+            };
+            let mut block_filters = BTreeMap::new();
+            for (path, filter) in filters {
+                let filter = match record_fields(filter)? {
+                    [Value::Long(bits, _), Value::Int(hashes, _), Value::Bytes(bytes, _)] => {
+                        BloomFilter::from_bytes(&bytes, bits as u32, hashes as u8)?
+                    },
+                    _ => return Err(invalid_index()),
+                };
+                block_filters.insert(path, filter);
+            }
+            index.blocks.insert(offset, block_filters);
+
+            let nullable = |value: Value| match value {
+                Value::Union(value, _) => match *value {
+                    Value::Bytes(bytes, _) => Ok(Some(bytes)),
+                    Value::Null => Ok(None),
+                    _ => Err(invalid_index()),
+                },
+                _ => Err(invalid_index()),
+            };
+            let mut block_stats = BTreeMap::new();
+            for (path, stats) in stats {
+                let stats = match record_fields(stats)? {
+                    [min, max, Value::Long(null_count, _)] => EncodedStats {
+                        min: nullable(min)?,
+                        max: nullable(max)?,
+                        null_count: null_count as u64,
+                    },
+                    _ => return Err(invalid_index()),
+                };
+                block_stats.insert(path, stats);
+            }
+            if !block_stats.is_empty() {
+                index.stats.insert(offset, block_stats);
+            }
+        }
+        Ok(index)
+    }
+}
+
+/// Stable fingerprint of a schema, hashing its Parsing Canonical Form.
+fn schema_fingerprint(schema: &Schema) -> u64 {
+    hash(schema.canonical_form().as_bytes())
+}
+
+/// Write a sidecar index file holding the index of an Avro data file, given the sync marker and
+/// the writer `Schema` of the data file. See [`SidecarIndex`](struct.SidecarIndex.html).
+///
+/// Return the number of bytes written.
+pub fn write_sidecar_index<W: Write>(
+    mut writer: W,
+    marker: &[u8],
+    schema: &Schema,
+    index: &FileIndex,
+) -> Result<usize, AvroError> {
+    if marker.len() != 16 {
+        let message = format!("Invalid sync marker of {} bytes", marker.len());
+        return Err(AvroError::Decode(message))
+    }
+    writer.write_all(SIDECAR_MAGIC)?;
+    writer.write_all(marker)?;
+    writer.write_all(&schema_fingerprint(schema).to_le_bytes())?;
+    let n = index.write_to(writer)?;
+    Ok(SIDECAR_MAGIC.len() + marker.len() + 8 + n)
+}
+
+/// Sidecar index file of an Avro data file, conventionally named after the data file with `.idx`
+/// appended (`data.avro.idx`).
+///
+/// The file starts with the magic bytes `Idx\x01`, followed by the 16-byte sync marker of the
+/// data file and a 64-bit fingerprint of its writer schema, so that the index can be checked
+/// against the data file before use. The [FileIndex](struct.FileIndex.html) comes next, as
+/// written by [`FileIndex::write_to`](struct.FileIndex.html#method.write_to).
+///
+/// Opening a sidecar only reads its header: the index itself is decoded when loaded.
+pub struct SidecarIndex<R> {
+    reader: R,
+    marker: [u8; 16],
+    fingerprint: u64,
+}
+
+impl<R: Read> SidecarIndex<R> {
+    /// Read the header of a sidecar index file.
+    pub fn open(mut reader: R) -> Result<SidecarIndex<R>, AvroError> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != SIDECAR_MAGIC {
+            return Err(AvroError::Decode("wrong magic in sidecar index header".to_owned()))
+        }
+        let mut marker = [0u8; 16];
+        reader.read_exact(&mut marker)?;
+        let mut fingerprint = [0u8; 8];
+        reader.read_exact(&mut fingerprint)?;
+        Ok(SidecarIndex {
+            reader,
+            marker,
+            fingerprint: u64::from_le_bytes(fingerprint),
+        })
+    }
+
+    /// Sync marker of the indexed data file.
+    pub fn marker(&self) -> &[u8; 16] {
+        &self.marker
+    }
+
+    /// Fingerprint of the writer schema of the indexed data file.
+    pub fn fingerprint(&self) -> u64 {
+        self.fingerprint
+    }
+
+    /// Check that the sidecar indexes the data file of the given sync marker and writer `Schema`.
+    pub fn validate(&self, marker: &[u8], schema: &Schema) -> Result<(), AvroError> {
+        if marker != self.marker {
+            return Err(AvroError::Decode(
+                "sidecar index marker does not match data file".to_owned(),
+            ))
+        }
+        if schema_fingerprint(schema) != self.fingerprint {
+            return Err(AvroError::Decode(
+                "sidecar index schema does not match data file".to_owned(),
+            ))
+        }
+        Ok(())
+    }
+
+    /// Decode the index held by the sidecar.
+    pub fn load(self) -> Result<FileIndex, AvroError> {
+        FileIndex::read_from(self.reader)
+    }
+}
+
+fn invalid_index() -> AvroError {
+    AvroError::Decode("Invalid file index".to_owned())
+}
+
+/// Values of the fields of a decoded record of `N` fields.
+fn record_fields<const N: usize>(value: Value) -> Result<[Value; N], AvroError> {
+    match value {
+        Value::Record(fields, _) => fields
+            .into_iter()
+            .map(|(_, value)| value)
+            .collect::<Vec<_>>()
+            .try_into()
+            .map_err(|_| invalid_index()),
+        _ => Err(invalid_index()),
+    }
+}
+
+/// Paths and schemas of the indexed fields of a record schema, in schema order.
+pub(crate) fn indexed_fields(schema: &Schema) -> Vec<(String, &Schema)> {
+    let mut fields = Vec::new();
+    collect_indexed_fields(schema, "", &mut fields);
+    fields
+}
+
+fn collect_indexed_fields<'a>(
+    schema: &'a Schema,
+    prefix: &str,
+    out: &mut Vec<(String, &'a Schema)>,
+) {
+    if let Schema::Record { fields, .. } = schema {
+        for field in fields {
+            let path = format!("{}{}", prefix, field.name);
+            if field.metadata.index {
+                out.push((path, &field.schema));
+            } else {
+                collect_indexed_fields(&field.schema, &format!("{}.", path), out);
+            }
+        }
+    }
+}
+
+/// Schema of the field at the dot-separated `path` of a record schema.
+pub(crate) fn field_schema<'a>(schema: &'a Schema, path: &str) -> Option<&'a Schema> {
+    path.split('.').try_fold(schema, |schema, name| match schema {
+        Schema::Record { fields, lookup, .. } => lookup.get(name).map(|i| &fields[*i].schema),
+        _ => None,
+    })
+}
+
+/// Value of the field at the dot-separated `path` of a record value.
+pub(crate) fn field_value<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |value, name| match value {
+        Value::Record(fields, _) => {
+            fields.iter().find(|(field, _)| &**field == name).map(|(_, value)| value)
+        },
+        _ => None,
+    })
+}
+
+/// Schema of the values of which blocks keep statistics: numeric, string or date types, possibly
+/// nullable.
+pub(crate) fn stats_schema(schema: &Schema) -> Option<&Schema> {
+    match schema {
+        Schema::Int
+        | Schema::Long
+        | Schema::Float
+        | Schema::Double
+        | Schema::String
+        | Schema::Date(_)
+        | Schema::Counter => Some(schema),
+        Schema::Optional(inner) => stats_schema(inner),
+        Schema::Union(union) => match union.variants() {
+            [Schema::Null, inner] | [inner, Schema::Null] => stats_schema(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn stats_field_schema<'a>(schema: &'a Schema, path: &str) -> Result<&'a Schema, AvroError> {
+    let field = field_schema(schema, path).ok_or_else(|| {
+        AvroError::Resolution(format!("No field {} in the schema", path))
+    })?;
+    stats_schema(field).ok_or_else(|| {
+        AvroError::Resolution(format!("No statistics for field {}", path))
+    })
+}
+
+/// Non-null value held by a possibly nullable value.
+pub(crate) fn non_null(value: &Value) -> Option<&Value> {
+    match value {
+        Value::Null | Value::Optional(None, _) => None,
+        Value::Optional(Some(inner), _) | Value::Union(inner, _) => non_null(inner),
+        value => Some(value),
+    }
+}
+
+/// Key under which a value of the field at `path` is indexed.
+pub(crate) fn index_key(schema: &Schema, path: &str, value: Value) -> Result<Vec<u8>, AvroError> {
+    let schema = field_schema(schema, path).ok_or_else(|| {
+        AvroError::Resolution(format!("No field {} in the schema", path))
+    })?;
+    Ok(encode_to_vec(&value.resolve(schema)?, schema))
+}
+
+/// Collects the postings of the indexed fields of the records appended to a
+/// [Writer](../struct.Writer.html). Postings wait for their block to be flushed, which gives
+/// their offset, along with the Bloom filters and the statistics of the block.
+pub(crate) struct IndexCollector<'a> {
+    fields: Vec<(String, &'a Schema)>,
+    pending: Vec<(usize, Vec<u8>, u64)>,
+    stats: Vec<Option<(&'a Schema, BlockStats)>>,
+    index: FileIndex,
+}
+
+impl<'a> IndexCollector<'a> {
+    pub(crate) fn new(schema: &'a Schema) -> IndexCollector<'a> {
+        let fields = indexed_fields(schema);
+        let stats = fields.iter().map(|(_, schema)| new_stats(schema)).collect();
+        IndexCollector {
+            fields,
+            pending: Vec::new(),
+            stats,
+            index: FileIndex::default(),
+        }
+    }
+
+    /// Collect the indexed fields of a record, already validated against the schema.
+    pub(crate) fn add(&mut self, value: &Value, ordinal: u64) {
+        for (i, (path, schema)) in self.fields.iter().enumerate() {
+            if let Some(value) = field_value(value, path) {
+                self.pending.push((i, encode_to_vec(value, schema), ordinal));
+                if let Some((schema, ref mut stats)) = self.stats[i] {
+                    add_stats(stats, value, schema);
+                }
+            }
+        }
+    }
+
+    /// Assign the postings collected since the last block to the block starting at `offset`,
+    /// and build the Bloom filters of the block.
+    pub(crate) fn flush_block(&mut self, offset: u64) {
+        let mut counts = vec![0; self.fields.len()];
+        for (i, _, _) in &self.pending {
+            counts[*i] += 1;
+        }
+        let mut filters = counts
+            .into_iter()
+            .map(|count| {
+                let bits = (count * BLOCK_FILTER_BITS_PER_VALUE).max(BLOCK_FILTER_MIN_BITS);
+                BloomFilter::new(bits.min(u32::MAX as usize) as u32, BLOCK_FILTER_HASHES)
+            })
+            .collect::<Vec<_>>();
+
+        for (i, key, ordinal) in self.pending.drain(..) {
+            if let Some(ref mut filter) = filters[i] {
+                filter.insert(&key);
+            }
+            let posting = Posting {
+                block_offset: offset,
+                ordinal,
+            };
+            self.index.insert(&self.fields[i].0, key, posting);
+        }
+
+        let filters = self
+            .fields
+            .iter()
+            .zip(filters)
+            .filter_map(|((path, _), filter)| filter.map(|filter| (path.clone(), filter)))
+            .collect();
+        self.index.blocks.insert(offset, filters);
+
+        let mut block_stats = BTreeMap::new();
+        for ((path, schema), stats) in self.fields.iter().zip(self.stats.iter_mut()) {
+            if let Some((value_schema, stats)) = replace(stats, new_stats(schema)) {
+                let encode = |value: Option<Value>| {
+                    value.map(|value| encode_to_vec(&value, value_schema))
+                };
+                let stats = EncodedStats {
+                    min: encode(stats.min),
+                    max: encode(stats.max),
+                    null_count: stats.null_count,
+                };
+                block_stats.insert(path.clone(), stats);
+            }
+        }
+        if !block_stats.is_empty() {
+            self.index.stats.insert(offset, block_stats);
+        }
+    }
+
+    pub(crate) fn index(&self) -> &FileIndex {
+        &self.index
+    }
+
+    pub(crate) fn into_index(self) -> FileIndex {
+        self.index
+    }
+}
+
+fn new_stats(schema: &Schema) -> Option<(&Schema, BlockStats)> {
+    let stats = BlockStats {
+        min: None,
+        max: None,
+        null_count: 0,
+    };
+    stats_schema(schema).map(|schema| (schema, stats))
+}
+
+fn add_stats(stats: &mut BlockStats, value: &Value, schema: &Schema) {
+    let value = match non_null(value) {
+        Some(value) => value,
+        None => {
+            stats.null_count += 1;
+            return
+        },
+    };
+    if stats.min.as_ref().is_none_or(|min| value.cmp_with_schema(min, schema) == Ordering::Less) {
+        stats.min = Some(value.clone());
+    }
+    if stats.max.as_ref().is_none_or(|max| value.cmp_with_schema(max, schema) == Ordering::Greater)
+    {
+        stats.max = Some(value.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_roundtrip() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "id", "type": "string", "index": true},
+                    {"name": "inner", "type": {
+                        "type": "record",
+                        "name": "inner",
+                        "fields": [{"name": "n", "type": "long", "index": true}]
+                    }}
+                ]
+            }
+        "#,
+        ).unwrap();
+
+        let mut collector = IndexCollector::new(&schema);
+        for (ordinal, id) in ["a", "b", "a"].iter().enumerate() {
+            let inner = Value::record(vec![("n", ordinal as i64)]);
+            let record = Value::record(vec![("id", id.avro()), ("inner", inner)]);
+            collector.add(&record, ordinal as u64);
+            if ordinal == 1 {
+                collector.flush_block(10);
+            }
+        }
+        collector.flush_block(20);
+        let index = collector.index().clone();
+
+        assert_eq!(index.fields().collect::<Vec<_>>(), vec!["id", "inner.n"]);
+        let postings = index.lookup(&schema, "id", "a".avro()).unwrap();
+        assert_eq!(
+            postings,
+            &[
+                Posting {
+                    block_offset: 10,
+                    ordinal: 0
+                },
+                Posting {
+                    block_offset: 20,
+                    ordinal: 2
+                },
+            ]
+        );
+        assert_eq!(index.lookup(&schema, "inner.n", 1i64.avro()).unwrap().len(), 1);
+        assert!(index.lookup(&schema, "id", "c".avro()).unwrap().is_empty());
+        assert!(index.lookup(&schema, "other", "c".avro()).is_err());
+
+        assert_eq!(index.block_offsets().collect::<Vec<_>>(), vec![10, 20]);
+        assert_eq!(index.candidate_blocks(&schema, "id", "a".avro()).unwrap(), vec![10, 20]);
+        let key = index_key(&schema, "id", "b".avro()).unwrap();
+        assert!(index.block_might_contain(10, "id", &key));
+        assert!(!index.block_might_contain(20, "id", &key));
+        assert!(index.block_might_contain(30, "id", &key));
+
+        let mut bytes = Vec::new();
+        let n = index.write_to(&mut bytes).unwrap();
+        assert_eq!(n, bytes.len());
+        assert_eq!(FileIndex::read_from(&bytes[..]).unwrap(), index);
+    }
+
+    #[test]
+    fn test_sidecar_index() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "test", "fields": [
+                {"name": "id", "type": "long", "index": true}
+            ]}"#,
+        ).unwrap();
+        let mut collector = IndexCollector::new(&schema);
+        collector.add(&Value::record(vec![("id", 1i64)]), 0);
+        collector.flush_block(10);
+        let marker = [7u8; 16];
+
+        let mut bytes = Vec::new();
+        let n = write_sidecar_index(&mut bytes, &marker, &schema, collector.index()).unwrap();
+        assert_eq!(n, bytes.len());
+
+        let sidecar = SidecarIndex::open(&bytes[..]).unwrap();
+        assert_eq!(sidecar.marker(), &marker);
+        assert!(sidecar.validate(&marker, &schema).is_ok());
+        assert!(sidecar.validate(&[0u8; 16], &schema).is_err());
+        assert!(sidecar.validate(&marker, &Schema::Long).is_err());
+        assert_eq!(&sidecar.load().unwrap(), collector.index());
+
+        assert!(SidecarIndex::open(&bytes[1..]).is_err());
+        let empty = FileIndex::default();
+        assert!(write_sidecar_index(Vec::new(), &[0u8; 4], &schema, &empty).is_err());
+    }
+
+    #[test]
+    fn test_block_stats() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "test", "fields": [
+                {"name": "n", "type": ["null", "long"], "index": true},
+                {"name": "tags", "type": {"type": "array", "items": "string"}, "index": true}
+            ]}"#,
+        ).unwrap();
+        let mut collector = IndexCollector::new(&schema);
+        let blocks: &[&[Option<i64>]] = &[&[Some(5), None, Some(-2)], &[None], &[Some(10)]];
+        let mut ordinal = 0;
+        for (offset, block) in blocks.iter().enumerate() {
+            for n in block.iter() {
+                let n = Value::Union(Box::new(n.map_or(Value::Null, |n| n.avro())), None);
+                let tags = Value::Array(Vec::new(), None);
+                collector.add(&Value::record(vec![("n", n), ("tags", tags)]), ordinal);
+                ordinal += 1;
+            }
+            collector.flush_block(offset as u64);
+        }
+        let index = collector.into_index();
+
+        let stats = index.block_stats(&schema, 0, "n").unwrap().unwrap();
+        assert_eq!(
+            stats,
+            BlockStats {
+                min: Some((-2i64).avro()),
+                max: Some(5i64.avro()),
+                null_count: 1,
+            }
+        );
+        let stats = index.block_stats(&schema, 1, "n").unwrap().unwrap();
+        assert_eq!((stats.min, stats.max, stats.null_count), (None, None, 1));
+        assert!(index.block_stats(&schema, 3, "n").unwrap().is_none());
+        assert!(index.block_stats(&schema, 0, "tags").is_err());
+
+        let range = |min: Option<i64>, max: Option<i64>| {
+            let (min, max) = (min.map(|n| n.avro()), max.map(|n| n.avro()));
+            index.blocks_in_range(&schema, "n", min, max).unwrap()
+        };
+        assert_eq!(range(None, None), vec![0, 2]);
+        assert_eq!(range(Some(5), None), vec![0, 2]);
+        assert_eq!(range(Some(6), Some(9)), Vec::<u64>::new());
+        assert_eq!(range(None, Some(-2)), vec![0]);
+        assert_eq!(range(Some(10), Some(10)), vec![2]);
+
+        let mut bytes = Vec::new();
+        index.write_to(&mut bytes).unwrap();
+        assert_eq!(FileIndex::read_from(&bytes[..]).unwrap(), index);
+    }
+}
@@ -0,0 +1,229 @@
+//! Logic for writing the interop dataset of Avro, checking that the files of this library are read
+//! by the other implementations of Avro.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::bloom::BloomFilter;
+use crate::codec::Codec;
+use crate::error::AvroError;
+use crate::hll::HyperLogLog;
+use crate::lru::LruMap;
+use crate::schema::{LruLimit, Schema};
+use crate::topk::TopK;
+use crate::types::{LruValue, SetItem, ToAvro, Value};
+use crate::writer::Writer;
+
+// as `share/test/schemas/interop.avsc`, but for the recursive `Node` record
+const INTEROP_SCHEMA: &str = r#"{
+    "type": "record",
+    "name": "Interop",
+    "namespace": "org.apache.avro",
+    "fields": [
+        {"name": "intField", "type": "int"},
+        {"name": "longField", "type": "long"},
+        {"name": "stringField", "type": "string"},
+        {"name": "boolField", "type": "boolean"},
+        {"name": "floatField", "type": "float"},
+        {"name": "doubleField", "type": "double"},
+        {"name": "bytesField", "type": "bytes"},
+        {"name": "nullField", "type": "null"},
+        {"name": "arrayField", "type": {"type": "array", "items": "double"}},
+        {"name": "mapField", "type": {"type": "map", "values": {
+            "type": "record", "name": "Foo", "fields": [{"name": "label", "type": "string"}]
+        }}},
+        {"name": "unionField", "type": [
+            "boolean", "double", {"type": "array", "items": "bytes"}
+        ]},
+        {"name": "enumField", "type": {"type": "enum", "name": "Kind", "symbols": ["A", "B", "C"]}},
+        {"name": "fixedField", "type": {"type": "fixed", "name": "MD5", "size": 16}},
+        {"name": "recordField", "type": {"type": "record", "name": "Node", "fields": [
+            {"name": "label", "type": "string"},
+            {"name": "children", "type": {"type": "array", "items": {
+                "type": "record", "name": "Leaf", "fields": [{"name": "label", "type": "string"}]
+            }}}
+        ]}}EXTENSIONS
+    ]
+}"#;
+
+const EXTENSION_FIELDS: &str = r#",
+        {"name": "dateField", "type": "date"},
+        {"name": "setField", "type": {"type": "set", "items": "string"}},
+        {"name": "optionalField", "type": {"type": "optional", "value": "string"}},
+        {"name": "counterField", "type": "counter"},
+        {"name": "boundedListField", "type": {
+            "type": "bounded_list", "items": "int", "max_length": 3
+        }},
+        {"name": "lruSetField", "type": {"type": "lru_set", "max_entries": 10}},
+        {"name": "hllField", "type": {"type": "hll", "precision": 10}},
+        {"name": "bloomField", "type": {"type": "bloom", "bits": 1024, "hashes": 3}},
+        {"name": "topkField", "type": {"type": "topk", "size": 3}}"#;
+
+/// Schema of the Avro interop dataset, the `interop.avsc` shared by the implementations of Avro.
+///
+/// As schemas cannot refer to named types, the `children` of the `Node` record of `recordField`
+/// are `Leaf` records holding a `label` only, instead of nested `Node`s. With `extensions`, the
+/// schema also has a field of each type specific to this library: `dateField`, `setField`,
+/// `optionalField`, `counterField`, `boundedListField`, `lruSetField`, `hllField`, `bloomField`
+/// and `topkField`.
+pub fn interop_schema(extensions: bool) -> Schema {
+    let extensions = if extensions { EXTENSION_FIELDS } else { "" };
+    Schema::parse_str(&INTEROP_SCHEMA.replace("EXTENSIONS", extensions))
+        .expect("Invalid interop schema")
+}
+
+/// Value of the Avro interop dataset, as written by the Java and Python implementations, for the
+/// [`interop_schema`](fn.interop_schema.html) of the same `extensions`.
+pub fn interop_value(extensions: bool) -> Value {
+    let schema = interop_schema(extensions);
+    let mut map = HashMap::new();
+    map.insert("a".to_owned(), label("a"));
+    map.insert("bee".to_owned(), label("cee"));
+
+    let mut fields = vec![
+        ("intField", Value::Int(12, None)),
+        ("longField", Value::Long(15_234_324, None)),
+        ("stringField", "hey".avro()),
+        ("boolField", Value::Boolean(true, None)),
+        ("floatField", Value::Float(1234.0, None)),
+        ("doubleField", Value::Double(-1234.0, None)),
+        ("bytesField", Value::Bytes(b"12312adf".to_vec(), None)),
+        ("nullField", Value::Null),
+        ("arrayField", Value::Array(vec![5.0.avro(), 0.0.avro(), 12.0.avro()], None)),
+        ("mapField", Value::Map(map, None)),
+        ("unionField", Value::Union(Box::new(Value::Double(12.0, None)), None)),
+        ("enumField", Value::Enum(2, "C".to_owned(), None)),
+        ("fixedField", Value::Fixed(16, b"1019181716151413".to_vec(), None)),
+        ("recordField", Value::Record(vec![
+            ("label".into(), "blah".avro()),
+            ("children".into(), Value::Array(vec![label("inner")], None)),
+        ], None)),
+    ];
+
+    if extensions {
+        let mut lru = LruMap::new();
+        lru.insert("seen".to_owned(), LruValue::new(1_500_000_000_000, 3));
+        let mut hll = HyperLogLog::new(10).expect("Invalid HyperLogLog precision");
+        let mut bloom = BloomFilter::new(1024, 3).expect("Invalid bloom filter size");
+        for item in &["a", "bee", "cee"] {
+            hll.add(*item);
+            bloom.insert(*item);
+        }
+        let mut topk = TopK::new(3);
+        topk.insert("a", 5);
+        topk.insert("bee", 2);
+
+        let set = Some(SetItem::String("hey".to_owned())).into_iter().collect();
+        fields.extend(vec![
+            ("dateField", Value::Date(1_500_000_000_000, None)),
+            ("setField", Value::Set(set, None)),
+            ("optionalField", Value::Optional(Some(Box::new("hey".avro())), None)),
+            ("counterField", Value::Counter(42, None)),
+            ("boundedListField", Value::Array(vec![1.avro(), 2.avro(), 3.avro()], None)),
+            ("lruSetField", Value::LruSet(Box::new(lru), lru_limit(&schema), None)),
+            ("hllField", Value::Hll(hll, None)),
+            ("bloomField", Value::Bloom(bloom, None)),
+            ("topkField", Value::TopK(Box::new(topk), None)),
+        ]);
+    }
+
+    let fields = fields.into_iter().map(|(name, value)| (name.into(), value)).collect();
+    Value::Record(fields, None).normalize(&schema).expect("Invalid interop value")
+}
+
+fn lru_limit(schema: &Schema) -> LruLimit {
+    match schema {
+        Schema::Record { fields, .. } => fields
+            .iter()
+            .find_map(|field| match field.schema {
+                Schema::LruSet(ref limit, _) => Some(limit.clone()),
+                _ => None,
+            })
+            .expect("Missing lru_set field"),
+        _ => unreachable!(),
+    }
+}
+
+fn label(label: &str) -> Value {
+    Value::Record(vec![("label".into(), label.avro())], None)
+}
+
+/// Codecs supported by this build, with the suffix of the names of their interop files.
+fn codecs() -> Vec<(Codec, &'static str)> {
+    vec![
+        (Codec::Null, ""),
+        (Codec::Deflate, "_deflate"),
+        #[cfg(feature = "snappy")]
+        (Codec::Snappy, "_snappy"),
+        #[cfg(feature = "zstd")]
+        (Codec::Zstandard, "_zstandard"),
+    ]
+}
+
+/// Write the interop dataset, compressed with `codec`, as a new Avro file to `writer`, returned
+/// once flushed.
+pub fn write_interop_data<W: Write>(
+    writer: W,
+    codec: Codec,
+    extensions: bool,
+) -> Result<W, AvroError> {
+    let schema = interop_schema(extensions);
+    let mut writer = Writer::with_codec(&schema, writer, codec);
+    writer.append(interop_value(extensions))?;
+    writer.flush()?;
+    Ok(writer.into_inner())
+}
+
+/// Write the interop dataset in `dir`, once with each codec supported by this build, returning
+/// the paths of the files written.
+///
+/// Files are named as the interop tests of the other implementations expect them:
+/// `rust.avro` for the `null` codec, and `rust_<codec>.avro`, such as `rust_deflate.avro`, for
+/// the others. Run the interop tests of Java or Python with `dir` as their data directory to check
+/// these files.
+pub fn write_interop_files<P>(dir: P, extensions: bool) -> Result<Vec<PathBuf>, AvroError>
+    where
+        P: AsRef<Path>,
+{
+    codecs()
+        .into_iter()
+        .map(|(codec, suffix)| {
+            let path = dir.as_ref().join(format!("rust{}.avro", suffix));
+            let file = BufWriter::new(File::create(&path)?);
+            write_interop_data(file, codec, extensions)?
+                .into_inner()
+                .map_err(|error| error.into_error())?;
+            Ok(path)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::Reader;
+
+    #[test]
+    fn test_interop_data() {
+        for extensions in &[false, true] {
+            for (codec, _) in codecs() {
+                let data = write_interop_data(Vec::new(), codec, *extensions).unwrap();
+                let reader = Reader::new(&data[..]).unwrap();
+                assert_eq!(reader.writer_schema(), &interop_schema(*extensions));
+                let values = reader.collect::<Result<Vec<_>, _>>().unwrap();
+                assert_eq!(values, vec![interop_value(*extensions)]);
+            }
+        }
+
+        let dir = std::env::temp_dir().join(format!("avro-interop-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths = write_interop_files(&dir, false).unwrap();
+        assert_eq!(paths[0], dir.join("rust.avro"));
+        assert_eq!(paths[1], dir.join("rust_deflate.avro"));
+        let file = File::open(&paths[1]).unwrap();
+        let values = Reader::new(file).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(values, vec![interop_value(false)]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
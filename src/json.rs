@@ -0,0 +1,599 @@
+//! Logic handling the Avro JSON encoding of values.
+//!
+//! Unlike [`Value::json`](../types/enum.Value.html#method.json), which drops every piece of type
+//! information, the encoding implemented here follows the
+//! [Avro specification](https://avro.apache.org/docs/current/spec.html#json_encoding) so that its
+//! output can be consumed by other Avro implementations (e.g. Java's `JsonDecoder`).
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+
+use serde_json::{Map, Value as JsonValue};
+
+use crate::error::AvroError;
+use crate::schema::{Schema, UnionSchema};
+use crate::topk::{TopK, TopKCount};
+use crate::types::{LruValue, SetItem, ToAvro, Value};
+
+const ACCESS_TIME: &str = "access_time";
+const COUNT: &str = "count";
+const KEY: &str = "key";
+const ERROR: &str = "error";
+
+/// Bytes and fixed values are represented as strings where each byte is mapped to the unicode
+/// code point of the same value (ISO-8859-1).
+fn bytes_to_json(bytes: &[u8]) -> JsonValue {
+    JsonValue::String(bytes.iter().map(|&b| b as char).collect::<String>())
+}
+
+fn lru_value_to_json(value: &LruValue) -> JsonValue {
+    let mut map = Map::with_capacity(2);
+    map.insert(ACCESS_TIME.to_owned(), json!(value.access_time));
+    map.insert(COUNT.to_owned(), json!(value.count));
+    JsonValue::Object(map)
+}
+
+/// Top-k summaries are represented as arrays of their entries, most frequent first.
+fn topk_to_json(topk: &TopK) -> JsonValue {
+    JsonValue::Array(
+        topk.entries()
+            .into_iter()
+            .map(|(key, count)| {
+                let mut map = Map::with_capacity(3);
+                map.insert(KEY.to_owned(), json!(key));
+                map.insert(COUNT.to_owned(), json!(count.count));
+                map.insert(ERROR.to_owned(), json!(count.error));
+                JsonValue::Object(map)
+            })
+            .collect(),
+    )
+}
+
+/// Wrap a non-null value in a single-entry object keyed by the name of its union branch.
+fn set_item_to_json(item: &SetItem, schema: &Schema) -> Result<JsonValue, AvroError> {
+    if !item.validate(schema) {
+        return Err(mismatch(&item.clone().avro(), schema))
+    }
+    Ok(match item {
+        SetItem::Int(n) => json!(n),
+        SetItem::Long(n) => json!(n),
+        SetItem::Bytes(bytes) => bytes_to_json(bytes),
+        SetItem::String(s) | SetItem::Enum(_, s) => JsonValue::String(s.clone()),
+    })
+}
+
+fn union_branch_to_json(value: &Value, schema: &Schema) -> Result<JsonValue, AvroError> {
+    match *value {
+        Value::Null => Ok(JsonValue::Null),
+        _ => {
+            let mut map = Map::with_capacity(1);
+            map.insert(schema.type_name(), value.to_avro_json(schema)?);
+            Ok(JsonValue::Object(map))
+        },
+    }
+}
+
+/// Inverse of `bytes_to_json`: every character must be a code point in the `0..=255` range.
+fn json_to_bytes(s: &str) -> Result<Vec<u8>, AvroError> {
+    s.chars()
+        .map(|c| {
+            let code = c as u32;
+            if code <= 0xff {
+                Ok(code as u8)
+            } else {
+                Err(AvroError::Resolution(format!(
+                    "Character {:?} cannot be represented as a byte",
+                    c
+                )))
+            }
+        }).collect::<Result<Vec<_>, _>>()
+}
+
+fn json_to_lru_value(json: &JsonValue) -> Result<LruValue, AvroError> {
+    let field = |name: &str| {
+        json.get(name)
+            .and_then(|v| v.as_i64())
+            .ok_or_else(|| {
+                AvroError::Resolution(format!("Long `{}` expected, got {}", name, json))
+            })
+    };
+
+    Ok(LruValue::new(field(ACCESS_TIME)?, field(COUNT)?))
+}
+
+/// Inverse of `topk_to_json`, keeping the `size` most frequent entries.
+fn json_to_topk(items: &[JsonValue], size: usize) -> Result<TopK, AvroError> {
+    let mut topk = TopK::new(size);
+    for json in items {
+        let field = |name: &str| {
+            json.get(name)
+                .and_then(|v| v.as_i64())
+                .ok_or_else(|| {
+                    AvroError::Resolution(format!("Long `{}` expected, got {}", name, json))
+                })
+        };
+        let key = json.get(KEY).and_then(|v| v.as_str()).ok_or_else(|| {
+            AvroError::Resolution(format!("String `{}` expected, got {}", KEY, json))
+        })?;
+        topk.put(key.to_owned(), TopKCount { count: field(COUNT)?, error: field(ERROR)? });
+    }
+    topk.truncate(size);
+    Ok(topk)
+}
+
+/// Find the union branch a JSON-encoded value belongs to: `null`, or a single-entry object keyed
+/// by the branch type name.
+fn json_to_union_branch<'a>(
+    json: &'a JsonValue,
+    union: &'a UnionSchema,
+) -> Result<(&'a Schema, &'a JsonValue), AvroError> {
+    match json {
+        JsonValue::Null => union
+            .variants()
+            .iter()
+            .find(|variant| **variant == Schema::Null)
+            .map(|variant| (variant, json))
+            .ok_or_else(|| AvroError::Resolution("Union has no `null` branch".to_owned())),
+        JsonValue::Object(map) if map.len() == 1 => {
+            let (name, inner) = map.iter().next().unwrap();
+            union
+                .variants()
+                .iter()
+                .find(|variant| variant.type_name() == *name)
+                .map(|variant| (variant, inner))
+                .ok_or_else(|| {
+                    AvroError::Resolution(format!("Unknown union branch {}", name))
+                })
+        },
+        other => Err(AvroError::Resolution(format!(
+            "Union value expected, got {}",
+            other
+        ))),
+    }
+}
+
+/// Field defaults are not wrapped even when the field is a union: they always refer to the first
+/// branch of the union.
+pub(crate) fn default_to_value(default: &JsonValue, schema: &Schema) -> Result<Value, AvroError> {
+    match schema {
+        Schema::Union(union) => {
+            let first = union
+                .variants()
+                .first()
+                .ok_or_else(|| AvroError::Resolution("Empty union".to_owned()))?;
+            Value::from_avro_json(default, first).map(|v| Value::Union(Box::new(v), None))
+        },
+        // defaults of optional values are not wrapped like union branches: `null` means absent
+        Schema::Optional(inner) => match default {
+            JsonValue::Null => Ok(Value::Optional(None, None)),
+            default => default_to_value(default, inner)
+                .map(|v| Value::Optional(Some(Box::new(v)), None)),
+        },
+        _ => Value::from_avro_json(default, schema),
+    }
+}
+
+fn unexpected(expected: &str, json: &JsonValue) -> AvroError {
+    AvroError::Resolution(format!("{} expected, got {}", expected, json))
+}
+
+fn mismatch(value: &Value, schema: &Schema) -> AvroError {
+    AvroError::Validation(format!(
+        "value {:?} does not match schema {:?}",
+        value, schema
+    ))
+}
+
+impl Value {
+    /// Convert the value into its [Avro JSON encoding](https://avro.apache.org/docs/current/spec.html#json_encoding)
+    /// with regards to the given [Schema](../schema/enum.Schema.html).
+    ///
+    /// Union values are wrapped as `{"<branch type>": value}` (or `null`), and bytes/fixed values
+    /// are written as ISO-8859-1 strings. `Optional` values are encoded like a
+    /// `["null", <type>]` union, `Set` values as arrays of strings and `LruSet` values as maps of
+    /// `{"access_time": long, "count": long}` records.
+    pub fn to_avro_json(&self, schema: &Schema) -> Result<JsonValue, AvroError> {
+        match (self, schema) {
+            (Value::Union(inner, _), Schema::Union(union)) => {
+                let (_, branch) = union
+                    .find_schema(inner)
+                    .ok_or_else(|| mismatch(self, schema))?;
+                union_branch_to_json(inner, branch)
+            },
+            (_, Schema::Union(union)) => {
+                let (_, branch) = union
+                    .find_schema(self)
+                    .ok_or_else(|| mismatch(self, schema))?;
+                union_branch_to_json(self, branch)
+            },
+            (Value::Optional(None, _), Schema::Optional(_)) => Ok(JsonValue::Null),
+            (Value::Optional(Some(inner), _), Schema::Optional(inner_schema)) => {
+                union_branch_to_json(inner, inner_schema)
+            },
+            (Value::Null, Schema::Null) => Ok(JsonValue::Null),
+            (Value::Boolean(b, _), Schema::Boolean) => Ok(JsonValue::Bool(*b)),
+            (Value::Int(n, _), Schema::Int) => Ok(json!(n)),
+            (Value::Long(n, _), Schema::Long) => Ok(json!(n)),
+            (Value::Float(x, _), Schema::Float) => Ok(json!(x)),
+            (Value::Double(x, _), Schema::Double) => Ok(json!(x)),
+            (Value::Bytes(bytes, _), Schema::Bytes) => Ok(bytes_to_json(bytes)),
+            (Value::String(s, _), Schema::String) => Ok(JsonValue::String(s.clone())),
+            (Value::Fixed(n, bytes, _), Schema::Fixed { size, .. }) if n == size => {
+                Ok(bytes_to_json(bytes))
+            },
+            (Value::Enum(_, symbol, _), Schema::Enum { symbols, .. })
+            | (Value::String(symbol, _), Schema::Enum { symbols, .. })
+                if symbols.contains(symbol) =>
+            {
+                Ok(JsonValue::String(symbol.clone()))
+            },
+            (Value::Array(items, _), Schema::Array(inner))
+            | (Value::Array(items, _), Schema::BoundedList { items: inner, .. }) => items
+                .iter()
+                .map(|item| item.to_avro_json(inner))
+                .collect::<Result<Vec<_>, _>>()
+                .map(JsonValue::Array),
+            (Value::Map(items, _), Schema::Map(inner)) => items
+                .iter()
+                .map(|(key, value)| value.to_avro_json(inner).map(|value| (key.clone(), value)))
+                .collect::<Result<Map<_, _>, _>>()
+                .map(JsonValue::Object),
+            (Value::Record(items, _), Schema::Record { fields, .. }) => {
+                if items.len() != fields.len() {
+                    return Err(mismatch(self, schema))
+                }
+                fields
+                    .iter()
+                    .zip(items.iter())
+                    .map(|(field, (name, value))| {
+                        if field.name != *name {
+                            return Err(mismatch(self, schema))
+                        }
+                        value
+                            .to_avro_json(&field.schema)
+                            .map(|value| (name.to_string(), value))
+                    })
+                    .collect::<Result<Map<_, _>, _>>()
+                    .map(JsonValue::Object)
+            },
+            (Value::Date(t, _), Schema::Date(unit)) => Ok(json!(unit.from_millis(*t))),
+            (Value::Counter(n, _), Schema::Counter) => Ok(json!(n)),
+            (Value::Hll(sketch, _), Schema::Hll(precision)) if sketch.precision() == *precision => {
+                Ok(bytes_to_json(&sketch.to_bytes()))
+            },
+            (Value::Bloom(filter, _), Schema::Bloom { bits, hashes })
+                if filter.bits() == *bits && filter.hashes() == *hashes =>
+            {
+                Ok(bytes_to_json(filter.as_bytes()))
+            },
+            (Value::TopK(topk, _), Schema::TopK(_)) => Ok(topk_to_json(topk)),
+            (Value::Set(items, _), Schema::Set(inner)) => items
+                .iter()
+                .map(|item| set_item_to_json(item, inner))
+                .collect::<Result<Vec<_>, _>>()
+                .map(JsonValue::Array),
+            (Value::LruSet(items, _, _), Schema::LruSet(..)) => Ok(JsonValue::Object(
+                items
+                    .iter()
+                    .map(|(key, value)| (key.clone(), lru_value_to_json(value)))
+                    .collect::<_>(),
+            )),
+            _ => Err(mismatch(self, schema)),
+        }
+    }
+
+    /// Parse a value in [Avro JSON encoding](https://avro.apache.org/docs/current/spec.html#json_encoding)
+    /// with regards to the given [Schema](../schema/enum.Schema.html).
+    ///
+    /// This is the inverse of [`to_avro_json`](#method.to_avro_json). Record fields missing from
+    /// the JSON object are filled in with their default value, if any.
+    pub fn from_avro_json(json: &JsonValue, schema: &Schema) -> Result<Value, AvroError> {
+        match schema {
+            Schema::Null => match json {
+                JsonValue::Null => Ok(Value::Null),
+                other => Err(unexpected("Null", other)),
+            },
+            Schema::Boolean => json
+                .as_bool()
+                .map(|b| Value::Boolean(b, None))
+                .ok_or_else(|| unexpected("Boolean", json)),
+            Schema::Int => json
+                .as_i64()
+                .filter(|n| *n >= i64::from(i32::MIN) && *n <= i64::from(i32::MAX))
+                .map(|n| Value::Int(n as i32, None))
+                .ok_or_else(|| unexpected("Int", json)),
+            Schema::Long => json
+                .as_i64()
+                .map(|n| Value::Long(n, None))
+                .ok_or_else(|| unexpected("Long", json)),
+            Schema::Counter => json
+                .as_i64()
+                .map(|n| Value::Counter(n, None))
+                .ok_or_else(|| unexpected("Counter", json)),
+            Schema::Hll(_) => match json {
+                JsonValue::String(s) => Value::Bytes(json_to_bytes(s)?, None).resolve(schema),
+                other => Err(unexpected("Hll", other)),
+            },
+            Schema::Bloom { .. } => match json {
+                JsonValue::String(s) => Value::Bytes(json_to_bytes(s)?, None).resolve(schema),
+                other => Err(unexpected("Bloom", other)),
+            },
+            Schema::TopK(size) => match json {
+                JsonValue::Array(items) => {
+                    json_to_topk(items, *size).map(|topk| Value::TopK(Box::new(topk), None))
+                },
+                other => Err(unexpected("TopK", other)),
+            },
+            Schema::Float => json
+                .as_f64()
+                .map(|x| Value::Float(x as f32, None))
+                .ok_or_else(|| unexpected("Float", json)),
+            Schema::Double => json
+                .as_f64()
+                .map(|x| Value::Double(x, None))
+                .ok_or_else(|| unexpected("Double", json)),
+            Schema::Bytes => match json {
+                JsonValue::String(s) => json_to_bytes(s).map(|bytes| Value::Bytes(bytes, None)),
+                other => Err(unexpected("Bytes", other)),
+            },
+            Schema::String => match json {
+                JsonValue::String(s) => Ok(Value::String(s.clone(), None)),
+                other => Err(unexpected("String", other)),
+            },
+            Schema::Fixed { size, .. } => match json {
+                JsonValue::String(s) => {
+                    let bytes = json_to_bytes(s)?;
+                    if bytes.len() == *size {
+                        Ok(Value::Fixed(*size, bytes, None))
+                    } else {
+                        Err(AvroError::Resolution(format!(
+                            "Fixed size mismatch, {} expected, got {}",
+                            size,
+                            bytes.len()
+                        )))
+                    }
+                },
+                other => Err(unexpected("Fixed", other)),
+            },
+            Schema::Enum { symbols, .. } => match json {
+                JsonValue::String(s) => symbols
+                    .iter()
+                    .position(|symbol| symbol == s)
+                    .map(|i| Value::Enum(i as i32, s.clone(), None))
+                    .ok_or_else(|| {
+                        AvroError::Resolution(format!(
+                            "Enum symbol {} is not among allowed symbols {:?}",
+                            s, symbols
+                        ))
+                    }),
+                other => Err(unexpected("Enum", other)),
+            },
+            Schema::Union(union) => {
+                let (branch, inner) = json_to_union_branch(json, union)?;
+                Value::from_avro_json(inner, branch).map(|v| Value::Union(Box::new(v), None))
+            },
+            Schema::Array(inner) => match json {
+                JsonValue::Array(items) => items
+                    .iter()
+                    .map(|item| Value::from_avro_json(item, inner))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(|items| Value::Array(items, None)),
+                other => Err(unexpected("Array", other)),
+            },
+            Schema::BoundedList {
+                items,
+                max_len,
+                truncation,
+            } => match Value::from_avro_json(json, &Schema::Array(items.clone()))? {
+                Value::Array(mut items, _) => {
+                    truncation.apply(&mut items, *max_len);
+                    Ok(Value::Array(items, None))
+                },
+                _ => unreachable!(),
+            },
+            Schema::Map(inner) => match json {
+                JsonValue::Object(items) => items
+                    .iter()
+                    .map(|(key, value)| {
+                        Value::from_avro_json(value, inner).map(|value| (key.clone(), value))
+                    }).collect::<Result<HashMap<_, _>, _>>()
+                    .map(|items| Value::Map(items, None)),
+                other => Err(unexpected("Map", other)),
+            },
+            Schema::Record { fields, .. } => match json {
+                JsonValue::Object(items) => fields
+                    .iter()
+                    .map(|field| {
+                        let value = match (items.get(&*field.name), &field.default) {
+                            (Some(value), _) => Value::from_avro_json(value, &field.schema)?,
+                            (None, Some(default)) => default_to_value(default, &field.schema)?,
+                            (None, None) => {
+                                return Err(AvroError::Resolution(format!(
+                                    "missing field {} in record",
+                                    field.name
+                                )))
+                            },
+                        };
+                        Ok((field.name.clone(), value))
+                    }).collect::<Result<Vec<_>, AvroError>>()
+                    .map(|fields| Value::Record(fields, None)),
+                other => Err(unexpected("Record", other)),
+            },
+            Schema::Date(unit) => match json {
+                JsonValue::String(s) => Value::String(s.clone(), None).resolve(schema),
+                other => other
+                    .as_i64()
+                    .map(|t| Value::Date(unit.to_millis(t), None))
+                    .ok_or_else(|| unexpected("Date", other)),
+            },
+            Schema::Set(inner) => match json {
+                JsonValue::Array(items) => items
+                    .iter()
+                    .map(|item| SetItem::try_from(Value::from_avro_json(item, inner)?))
+                    .collect::<Result<HashSet<_>, AvroError>>()
+                    .map(|items| Value::Set(items, None)),
+                other => Err(unexpected("Set", other)),
+            },
+            Schema::LruSet(limit, _) => match json {
+                JsonValue::Object(items) => items
+                    .iter()
+                    .map(|(key, value)| json_to_lru_value(value).map(|value| (key.clone(), value)))
+                    .collect::<Result<HashMap<_, _>, _>>()
+                    .map(|items| Value::LruSet(Box::new(items.into()), limit.clone(), None)),
+                other => Err(unexpected("LruSet", other)),
+            },
+            Schema::Optional(inner) => match json {
+                JsonValue::Null => Ok(Value::Optional(None, None)),
+                JsonValue::Object(map) if map.len() == 1 && map.contains_key(&inner.type_name()) => {
+                    Value::from_avro_json(&map[&inner.type_name()], inner)
+                        .map(|v| Value::Optional(Some(Box::new(v)), None))
+                },
+                other => Err(unexpected("Optional", other)),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ToAvro;
+
+    #[test]
+    fn test_union_to_avro_json() {
+        let schema = Schema::parse_str(r#"["null", "long"]"#).unwrap();
+
+        assert_eq!(
+            Value::Union(Box::new(Value::Long(3, None)), None)
+                .to_avro_json(&schema)
+                .unwrap(),
+            json!({"long": 3})
+        );
+        assert_eq!(
+            Value::Union(Box::new(Value::Null), None)
+                .to_avro_json(&schema)
+                .unwrap(),
+            JsonValue::Null
+        );
+        assert!(
+            Value::Union(Box::new(Value::Boolean(true, None)), None)
+                .to_avro_json(&schema)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_bytes_to_avro_json() {
+        let schema = Schema::parse_str(r#"{"type": "fixed", "name": "md5", "size": 2}"#).unwrap();
+
+        assert_eq!(
+            Value::Bytes(vec![0x41, 0xff], None)
+                .to_avro_json(&Schema::Bytes)
+                .unwrap(),
+            json!("A\u{ff}")
+        );
+        assert_eq!(
+            Value::Fixed(2, vec![0x00, 0xe9], None)
+                .to_avro_json(&schema)
+                .unwrap(),
+            json!("\u{0}\u{e9}")
+        );
+    }
+
+    #[test]
+    fn test_record_to_avro_json() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "namespace": "com.example",
+                "fields": [
+                    {"name": "a", "type": "long"},
+                    {"name": "b", "type": ["null", {"type": "enum", "name": "suit", "symbols": ["spades", "hearts"]}]},
+                    {"name": "c", "type": "optional", "value": "string"}
+                ]
+            }
+        "#,
+        ).unwrap();
+
+        let value = Value::record(vec![
+            ("a", 1i64.avro()),
+            ("b", Value::Union(Box::new(Value::Enum(1, "hearts".to_owned(), None)), None)),
+            ("c", Value::Optional(Some(Box::new("foo".avro())), None)),
+        ]);
+
+        assert_eq!(
+            value.to_avro_json(&schema).unwrap(),
+            json!({"a": 1, "b": {"suit": "hearts"}, "c": {"string": "foo"}})
+        );
+    }
+
+    #[test]
+    fn test_record_from_avro_json() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": "long", "default": 42},
+                    {"name": "b", "type": ["null", "bytes"]},
+                    {"name": "c", "type": "optional", "value": "string"},
+                    {"name": "d", "type": "set"},
+                    {"name": "e", "type": "lru_set", "limit": "10"}
+                ]
+            }
+        "#,
+        ).unwrap();
+        let json = json!({
+            "b": {"bytes": "A\u{ff}"},
+            "c": null,
+            "d": ["x"],
+            "e": {"k": {"access_time": 5, "count": 2}}
+        });
+
+        let value = Value::from_avro_json(&json, &schema).unwrap();
+        assert!(value.validate(&schema));
+        assert_eq!(value.field("a"), Some(&Value::Long(42, None)));
+        assert_eq!(
+            value.field("b"),
+            Some(&Value::Union(Box::new(Value::Bytes(vec![0x41, 0xff], None)), None))
+        );
+        assert_eq!(value.field("c"), Some(&Value::Optional(None, None)));
+        match value.field("e") {
+            Some(Value::LruSet(items, _, _)) => assert_eq!(items["k"], LruValue::new(5, 2)),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_avro_json_round_trip() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": {"type": "fixed", "name": "f", "size": 2}},
+                    {"name": "b", "type": ["null", {"type": "enum", "name": "suit", "symbols": ["spades", "hearts"]}]},
+                    {"name": "c", "type": "optional", "value": "long"}
+                ]
+            }
+        "#,
+        ).unwrap();
+        let json = json!({"a": "\u{0}\u{e9}", "b": {"suit": "spades"}, "c": {"long": 3}});
+
+        let value = Value::from_avro_json(&json, &schema).unwrap();
+        assert_eq!(value.to_avro_json(&schema).unwrap(), json);
+    }
+
+    #[test]
+    fn test_invalid_from_avro_json() {
+        let schema = Schema::parse_str(r#"["null", "long"]"#).unwrap();
+
+        assert!(Value::from_avro_json(&json!(3), &schema).is_err());
+        assert!(Value::from_avro_json(&json!({"string": "a"}), &schema).is_err());
+        assert!(Value::from_avro_json(&json!("\u{100}"), &Schema::Bytes).is_err());
+        assert!(Value::from_avro_json(&json!(1i64 << 40), &Schema::Int).is_err());
+    }
+}
@@ -0,0 +1,437 @@
+//! Logic for converting Avro schemas to and from [JSON Schema](https://json-schema.org) (draft-07).
+//!
+//! JSON Schemas describe the plain JSON form of values, as given by
+//! [`Value::json`](../types/enum.Value.html#method.json) and resolved back with `ToAvro` and
+//! [`Value::resolve`](../types/enum.Value.html#method.resolve): unions are untagged and dates are
+//! RFC 3339 strings.
+use std::collections::HashSet;
+use std::convert::TryFrom;
+
+use serde_json::{Map, Value as JsonValue};
+
+use crate::error::AvroError;
+use crate::schema::Schema;
+
+const DRAFT_07: &str = "http://json-schema.org/draft-07/schema#";
+
+impl Schema {
+    /// Draft-07 JSON Schema of the plain JSON form of the values of this schema.
+    ///
+    /// The conversion is lossy for the types JSON Schema has no counterpart for: `float` and
+    /// `double` are both `number`, `bytes`, `fixed`, `hll` and `bloom` are strings, `lru_set` is
+    /// an object of `{"access_time", "count"}` objects without its limit, `topk` an array, and
+    /// `optional` a union with `null`. Names and documentation of named types are kept in the
+    /// `title` and `description` keywords.
+    pub fn to_json_schema(&self) -> JsonValue {
+        let mut json = to_json(self);
+        if let JsonValue::Object(ref mut map) = json {
+            map.insert("$schema".to_owned(), json!(DRAFT_07));
+        }
+        json
+    }
+
+    /// Parse a draft-07 JSON Schema into the `Schema` of the values of the JSON documents it
+    /// describes:
+    ///
+    /// - objects with `properties` are records, named after their `title`, or their property
+    ///   when untitled, with one field per property; properties missing from `required` are
+    ///   unions of `null` and their type, unless they have a default;
+    /// - objects with only `additionalProperties` are maps;
+    /// - arrays are arrays, or sets with `uniqueItems`, or bounded lists with `maxItems`;
+    /// - integers are ints when bounded by the `int` range, longs otherwise; numbers are doubles;
+    /// - strings of the `date-time` or `date` format are dates, and strings with an `enum` of
+    ///   valid names are enums;
+    /// - `anyOf`, `oneOf` and type lists are unions, and local `$ref`s are inlined.
+    ///
+    /// Other constraints (patterns, bounds, formats...) are dropped. Recursive references,
+    /// `allOf` with several schemas and objects allowing any property have no Avro counterpart
+    /// and fail the conversion. Names are made valid by replacing invalid characters with `_`.
+    pub fn from_json_schema(json: &JsonValue) -> Result<Schema, AvroError> {
+        let mut converter = Converter {
+            root: json,
+            refs: Vec::new(),
+        };
+        Schema::parse(&converter.convert(json, "root")?)
+    }
+}
+
+fn to_json(schema: &Schema) -> JsonValue {
+    match schema {
+        Schema::Null => json!({"type": "null"}),
+        Schema::Boolean => json!({"type": "boolean"}),
+        Schema::Int => json!({"type": "integer", "minimum": i32::MIN, "maximum": i32::MAX}),
+        Schema::Long | Schema::Counter => json!({"type": "integer"}),
+        Schema::Float | Schema::Double => json!({"type": "number"}),
+        Schema::String | Schema::Bytes | Schema::Hll(_) | Schema::Bloom { .. } => {
+            json!({"type": "string"})
+        },
+        Schema::Fixed { size, .. } => {
+            json!({"type": "string", "minLength": size, "maxLength": size})
+        },
+        Schema::Enum {
+            name, doc, symbols, ..
+        } => named(json!({"type": "string", "enum": symbols}), &name.name, doc),
+        Schema::Date(_) => json!({"type": "string", "format": "date-time"}),
+        Schema::Array(items) => json!({"type": "array", "items": to_json(items)}),
+        Schema::BoundedList { items, max_len, .. } => {
+            json!({"type": "array", "items": to_json(items), "maxItems": max_len})
+        },
+        Schema::Set(items) => {
+            json!({"type": "array", "items": to_json(items), "uniqueItems": true})
+        },
+        Schema::Map(values) => json!({"type": "object", "additionalProperties": to_json(values)}),
+        Schema::LruSet(..) => json!({"type": "object", "additionalProperties": {
+            "type": "object",
+            "properties": {"access_time": {"type": "integer"}, "count": {"type": "integer"}},
+            "required": ["access_time", "count"]
+        }}),
+        Schema::TopK(_) => json!({"type": "array"}),
+        Schema::Record {
+            name, doc, fields, ..
+        } => {
+            let mut properties = Map::new();
+            let mut required = Vec::new();
+            for field in fields {
+                let mut property = to_json(&field.schema);
+                if let JsonValue::Object(ref mut map) = property {
+                    if let Some(ref doc) = field.doc {
+                        map.insert("description".to_owned(), json!(doc));
+                    }
+                    if let Some(ref default) = field.default {
+                        map.insert("default".to_owned(), default.clone());
+                    }
+                }
+                if field.default.is_none() {
+                    required.push(json!(field.name.as_ref()));
+                }
+                properties.insert(field.name.to_string(), property);
+            }
+            let record = json!({
+                "type": "object",
+                "properties": properties,
+                "required": required,
+                "additionalProperties": false,
+            });
+            named(record, &name.name, doc)
+        },
+        Schema::Union(union) => {
+            json!({"anyOf": union.variants().iter().map(to_json).collect::<Vec<_>>()})
+        },
+        Schema::Optional(inner) => json!({"anyOf": [{"type": "null"}, to_json(inner)]}),
+    }
+}
+
+fn named(mut json: JsonValue, name: &str, doc: &Option<String>) -> JsonValue {
+    if let JsonValue::Object(ref mut map) = json {
+        map.insert("title".to_owned(), json!(name));
+        if let Some(doc) = doc {
+            map.insert("description".to_owned(), json!(doc));
+        }
+    }
+    json
+}
+
+/// Name made of the characters of `s` allowed in Avro names, others replaced with `_`.
+fn avro_name(s: &str) -> String {
+    let mut name = s
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect::<String>();
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        name.insert(0, '_');
+    }
+    name
+}
+
+fn unsupported(what: &str) -> AvroError {
+    AvroError::Parse(format!("JSON Schema {} has no Avro counterpart", what))
+}
+
+/// Converter of JSON Schemas into the JSON form of Avro schemas.
+struct Converter<'a> {
+    root: &'a JsonValue,
+    // references being inlined, to tell recursive ones
+    refs: Vec<&'a str>,
+}
+
+impl<'a> Converter<'a> {
+    /// Avro schema of `json`, named after `hint` if named and untitled.
+    fn convert(&mut self, json: &'a JsonValue, hint: &str) -> Result<JsonValue, AvroError> {
+        let map = match json {
+            JsonValue::Object(map) => map,
+            _ => return Err(unsupported("accepting any value")),
+        };
+        let name = avro_name(map.get("title").and_then(|title| title.as_str()).unwrap_or(hint));
+
+        if let Some(reference) = map.get("$ref").and_then(|reference| reference.as_str()) {
+            let target = reference
+                .strip_prefix('#')
+                .and_then(|pointer| self.root.pointer(pointer))
+                .ok_or_else(|| {
+                    AvroError::Parse(format!("Unresolved JSON Schema $ref {}", reference))
+                })?;
+            if self.refs.contains(&reference) {
+                return Err(unsupported(&format!("recursive $ref {}", reference)))
+            }
+            self.refs.push(reference);
+            let hint = reference.rsplit('/').next().unwrap_or(hint);
+            let schema = self.convert(target, hint);
+            self.refs.pop();
+            return schema
+        }
+        if let Some(branches) = map.get("anyOf").or_else(|| map.get("oneOf")) {
+            let branches = branches
+                .as_array()
+                .ok_or_else(|| AvroError::Parse(
+                    "anyOf and oneOf must be arrays".to_owned(),
+                ))?;
+            let mut schemas = Vec::with_capacity(branches.len());
+            for branch in branches {
+                schemas.push(self.convert(branch, &name)?);
+            }
+            return Ok(union_of(schemas))
+        }
+        if let Some(all) = map.get("allOf").and_then(|all| all.as_array()) {
+            return match all.as_slice() {
+                [single] => self.convert(single, &name),
+                _ => Err(unsupported("allOf of several schemas")),
+            }
+        }
+
+        match map.get("type") {
+            Some(JsonValue::String(kind)) => self.typed(map, kind, &name),
+            Some(JsonValue::Array(kinds)) => {
+                let mut branches = Vec::with_capacity(kinds.len());
+                for kind in kinds {
+                    let kind = kind
+                        .as_str()
+                        .ok_or_else(|| AvroError::Parse(
+                            "JSON Schema types must be strings".to_owned(),
+                        ))?;
+                    branches.push(self.typed(map, kind, &name)?);
+                }
+                Ok(union_of(branches))
+            },
+            Some(other) => Err(AvroError::Parse(
+                format!("Invalid JSON Schema type {}", other),
+            )),
+            None if map.contains_key("properties") => self.typed(map, "object", &name),
+            None if map.contains_key("items") => self.typed(map, "array", &name),
+            None if map.contains_key("enum") => self.typed(map, "string", &name),
+            None => Err(unsupported("without a type")),
+        }
+    }
+
+    /// Avro schema of `map`, of the given JSON Schema type.
+    fn typed(
+        &mut self,
+        map: &'a Map<String, JsonValue>,
+        kind: &str,
+        name: &str,
+    ) -> Result<JsonValue, AvroError> {
+        match kind {
+            "null" => Ok(json!("null")),
+            "boolean" => Ok(json!("boolean")),
+            "number" => Ok(json!("double")),
+            "integer" => {
+                let within = |key: &str| {
+                    map.get(key)
+                        .and_then(|bound| bound.as_i64())
+                        .is_some_and(|bound| i32::try_from(bound).is_ok())
+                };
+                Ok(json!(if within("minimum") && within("maximum") { "int" } else { "long" }))
+            },
+            "string" => {
+                let symbols = map.get("enum").and_then(|symbols| symbols.as_array());
+                if let Some(symbols) = symbols {
+                    let valid = symbols
+                        .iter()
+                        .all(|symbol| symbol.as_str().is_some_and(|s| avro_name(s) == s));
+                    if valid && !symbols.is_empty() {
+                        return Ok(json!({"type": "enum", "name": name, "symbols": symbols}))
+                    }
+                }
+                match map.get("format").and_then(|format| format.as_str()) {
+                    Some("date-time") | Some("date") => Ok(json!("date")),
+                    _ => Ok(json!("string")),
+                }
+            },
+            "array" => {
+                let items = map.get("items").ok_or_else(|| unsupported("array without items"))?;
+                let items = self.convert(items, name)?;
+                let max_len = map.get("maxItems").and_then(|max_len| max_len.as_u64());
+                let unique = map.get("uniqueItems") == Some(&JsonValue::Bool(true));
+                Ok(match max_len {
+                    Some(max_len) => {
+                        json!({"type": "bounded_list", "items": items, "max_length": max_len})
+                    },
+                    None if unique && is_set_item(&items) => json!({"type": "set", "items": items}),
+                    None => json!({"type": "array", "items": items}),
+                })
+            },
+            "object" => match (map.get("properties"), map.get("additionalProperties")) {
+                (Some(JsonValue::Object(properties)), _) => self.record(map, properties, name),
+                (None, Some(values @ JsonValue::Object(_))) => {
+                    Ok(json!({"type": "map", "values": self.convert(values, name)?}))
+                },
+                _ => Err(unsupported("object allowing any property")),
+            },
+            other => Err(AvroError::Parse(format!("Unknown JSON Schema type {}", other))),
+        }
+    }
+
+    fn record(
+        &mut self,
+        map: &'a Map<String, JsonValue>,
+        properties: &'a Map<String, JsonValue>,
+        name: &str,
+    ) -> Result<JsonValue, AvroError> {
+        let required: HashSet<&str> = map
+            .get("required")
+            .and_then(|required| required.as_array())
+            .map(|required| required.iter().filter_map(|name| name.as_str()).collect())
+            .unwrap_or_default();
+        let mut fields = Vec::with_capacity(properties.len());
+        for (property, json) in properties {
+            let mut schema = self.convert(json, property)?;
+            let mut field = Map::new();
+            field.insert("name".to_owned(), json!(avro_name(property)));
+            if let Some(doc) = json.get("description") {
+                field.insert("doc".to_owned(), doc.clone());
+            }
+            match json.get("default") {
+                Some(default) => {
+                    field.insert("default".to_owned(), default.clone());
+                },
+                None if !required.contains(property.as_str()) => {
+                    schema = union_of(vec![json!("null"), schema]);
+                    field.insert("default".to_owned(), JsonValue::Null);
+                },
+                None => (),
+            }
+            field.insert("type".to_owned(), schema);
+            fields.push(JsonValue::Object(field));
+        }
+        let mut record = json!({"type": "record", "name": name, "fields": fields});
+        if let Some(doc) = map.get("description") {
+            record["doc"] = doc.clone();
+        }
+        Ok(record)
+    }
+}
+
+/// Union of `schemas`, flattening nested unions and keeping `null` first, or the only schema.
+fn union_of(schemas: Vec<JsonValue>) -> JsonValue {
+    let mut branches = Vec::with_capacity(schemas.len());
+    for schema in schemas {
+        match schema {
+            JsonValue::Array(inner) => branches.extend(inner),
+            schema => branches.push(schema),
+        }
+    }
+    let mut union = Vec::with_capacity(branches.len());
+    for branch in branches {
+        if !union.contains(&branch) {
+            union.push(branch);
+        }
+    }
+    if let Some(position) = union.iter().position(|branch| *branch == json!("null")) {
+        let null = union.remove(position);
+        union.insert(0, null);
+    }
+    match union.len() {
+        1 => union.remove(0),
+        _ => JsonValue::Array(union),
+    }
+}
+
+fn is_set_item(schema: &JsonValue) -> bool {
+    match schema {
+        JsonValue::String(kind) => ["int", "long", "string", "bytes"].contains(&kind.as_str()),
+        JsonValue::Object(map) => map.get("type") == Some(&json!("enum")),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_schema_round_trip() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "order", "doc": "An order", "fields": [
+                {"name": "at", "type": "date"},
+                {"name": "id", "type": "long"},
+                {"name": "lines", "type": {"type": "array", "items": {
+                    "type": "record", "name": "line", "fields": [
+                        {"name": "quantity", "type": "int"},
+                        {"name": "sku", "type": "string"}
+                    ]
+                }}},
+                {"name": "note", "type": ["null", "string"], "default": null},
+                {"name": "status", "type": {
+                    "type": "enum", "name": "status", "symbols": ["new", "paid"]
+                }},
+                {"name": "tags", "type": {"type": "set", "items": "string"}},
+                {"name": "totals", "type": {"type": "map", "values": "double"}}
+            ]}"#,
+        ).unwrap();
+        let json = schema.to_json_schema();
+        assert_eq!(json["$schema"], json!(DRAFT_07));
+        assert_eq!(json["title"], json!("order"));
+        assert_eq!(json["required"], json!(["at", "id", "lines", "status", "tags", "totals"]));
+        assert_eq!(json["properties"]["at"], json!({"type": "string", "format": "date-time"}));
+        assert_eq!(
+            json["properties"]["note"],
+            json!({"anyOf": [{"type": "null"}, {"type": "string"}], "default": null})
+        );
+        assert_eq!(Schema::from_json_schema(&json).unwrap(), schema);
+    }
+
+    #[test]
+    fn test_from_json_schema() {
+        let json = json!({
+            "$schema": DRAFT_07,
+            "title": "user",
+            "type": "object",
+            "properties": {
+                "address": {"$ref": "#/definitions/address"},
+                "age": {"type": "integer", "minimum": 0},
+                "email": {"type": ["string", "null"]},
+                "name": {"type": "string", "pattern": "^[a-z]+$"},
+                "nick-name": {"type": "string", "default": "anonymous"},
+                "score": {"oneOf": [{"type": "number"}, {"type": "string"}]}
+            },
+            "required": ["age", "name", "score"],
+            "definitions": {
+                "address": {
+                    "type": "object",
+                    "properties": {"city": {"type": "string"}},
+                    "required": ["city"]
+                }
+            }
+        });
+        let expected = Schema::parse_str(
+            r#"{"type": "record", "name": "user", "fields": [
+                {"name": "address", "type": ["null", {
+                    "type": "record", "name": "address", "fields": [
+                        {"name": "city", "type": "string"}
+                    ]
+                }], "default": null},
+                {"name": "age", "type": "long"},
+                {"name": "email", "type": ["null", "string"], "default": null},
+                {"name": "name", "type": "string"},
+                {"name": "nick_name", "type": "string", "default": "anonymous"},
+                {"name": "score", "type": ["double", "string"]}
+            ]}"#,
+        ).unwrap();
+        assert_eq!(Schema::from_json_schema(&json).unwrap(), expected);
+
+        let recursive = json!({"definitions": {"node": {"type": "object", "properties": {
+            "next": {"$ref": "#/definitions/node"}
+        }}}, "$ref": "#/definitions/node"});
+        assert!(Schema::from_json_schema(&recursive).is_err());
+        assert!(Schema::from_json_schema(&json!({"type": "object"})).is_err());
+    }
+}
@@ -0,0 +1,172 @@
+//! Logic for reading and writing JSON Lines files of values in Avro JSON encoding.
+use std::io::{BufRead, Lines, Write};
+
+use crate::error::AvroError;
+use crate::schema::Schema;
+use crate::types::Value;
+
+/// Reader of the lines of a [JSON Lines](https://jsonlines.org) file as values of a
+/// [Schema](schema/enum.Schema.html), each line holding a value in
+/// [Avro JSON encoding](https://avro.apache.org/docs/current/spec.html#json_encoding), as parsed
+/// by [`Value::from_avro_json`](types/enum.Value.html#method.from_avro_json).
+///
+/// Contrary to the conversion of `serde_json` values with `ToAvro`, the schema tells the type of
+/// each value: numbers get the exact type of their schema, strings may be enum symbols, bytes or
+/// dates, and unions are read from their `{"<branch type>": value}` encoding. Blank lines are
+/// skipped.
+pub struct JsonlReader<'a, R: BufRead> {
+    schema: &'a Schema,
+    lines: Lines<R>,
+    line: usize,
+}
+
+impl<'a, R: BufRead> JsonlReader<'a, R> {
+    /// Create a `JsonlReader` of the lines of `reader`.
+    pub fn new(schema: &'a Schema, reader: R) -> JsonlReader<'a, R> {
+        JsonlReader {
+            schema,
+            lines: reader.lines(),
+            line: 0,
+        }
+    }
+
+    /// Number of the last line read, starting from 1, to tell where an error was raised.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl<'a, R: BufRead> Iterator for JsonlReader<'a, R> {
+    type Item = Result<Value, AvroError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(line) => line,
+                Err(error) => return Some(Err(error.into())),
+            };
+            self.line += 1;
+            if line.trim().is_empty() {
+                continue
+            }
+            return Some(
+                serde_json::from_str(&line)
+                    .map_err(AvroError::from)
+                    .and_then(|json| Value::from_avro_json(&json, self.schema)),
+            )
+        }
+    }
+}
+
+/// Writer of values of a [Schema](schema/enum.Schema.html) as the lines of a
+/// [JSON Lines](https://jsonlines.org) file, in
+/// [Avro JSON encoding](https://avro.apache.org/docs/current/spec.html#json_encoding), as given
+/// by [`Value::to_avro_json`](types/enum.Value.html#method.to_avro_json).
+pub struct JsonlWriter<'a, W: Write> {
+    schema: &'a Schema,
+    writer: W,
+}
+
+impl<'a, W: Write> JsonlWriter<'a, W> {
+    /// Create a `JsonlWriter` writing lines to `writer`.
+    pub fn new(schema: &'a Schema, writer: W) -> JsonlWriter<'a, W> {
+        JsonlWriter { schema, writer }
+    }
+
+    /// Write a value as a line, once brought to the canonical form of the schema with
+    /// [`Value::normalize`](types/enum.Value.html#method.normalize).
+    pub fn write(&mut self, value: &Value) -> Result<(), AvroError> {
+        let json = value.clone().normalize(self.schema)?.to_avro_json(self.schema)?;
+        serde_json::to_writer(&mut self.writer, &json)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Write every value of `values` as a line, returning the number of values written.
+    pub fn extend<'b, I>(&mut self, values: I) -> Result<usize, AvroError>
+    where
+        I: IntoIterator<Item = &'b Value>,
+    {
+        let mut count = 0;
+        for value in values {
+            self.write(value)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Flush the underlying writer.
+    pub fn flush(&mut self) -> Result<(), AvroError> {
+        Ok(self.writer.flush()?)
+    }
+
+    /// Flush and return the underlying writer.
+    pub fn into_inner(mut self) -> Result<W, AvroError> {
+        self.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::Reader;
+    use crate::types::ToAvro;
+    use crate::writer::Writer;
+
+    #[test]
+    fn test_jsonl_round_trip() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "r", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "score", "type": ["null", "float", "string"]},
+                {"name": "color", "type": {
+                    "type": "enum", "name": "c", "symbols": ["red", "blue"]
+                }},
+                {"name": "seen", "type": "date", "default": 0}
+            ]}"#,
+        ).unwrap();
+        let input = r#"{"id": 1, "score": {"float": 1.5}, "color": "red", "seen": 1000}
+
+{"id": 2, "score": null, "color": "blue"}
+{"id": 3, "score": {"string": "n/a"}, "color": "blue", "seen": 3000}
+"#;
+        let values = JsonlReader::new(&schema, input.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(values[1], Value::Record(vec![
+            ("id".into(), Value::Long(2, None)),
+            ("score".into(), Value::Union(Box::new(Value::Null), None)),
+            ("color".into(), Value::Enum(1, "blue".to_owned(), None)),
+            ("seen".into(), Value::Date(0, None)),
+        ], None));
+        assert_eq!(values[2], Value::Record(vec![
+            ("id".into(), Value::Long(3, None)),
+            ("score".into(), Value::Union(Box::new("n/a".avro()), None)),
+            ("color".into(), Value::Enum(1, "blue".to_owned(), None)),
+            ("seen".into(), Value::Date(3000, None)),
+        ], None));
+
+        // into an Avro container and back
+        let mut writer = Writer::new(&schema, Vec::new());
+        writer.extend_from_slice(&values).unwrap();
+        let encoded = writer.into_inner();
+        let read = Reader::new(&encoded[..]).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        let mut writer = JsonlWriter::new(&schema, Vec::new());
+        assert_eq!(writer.extend(&read).unwrap(), 3);
+        let written = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(written.lines().next().unwrap()).unwrap(),
+            json!({"id": 1, "score": {"float": 1.5}, "color": "red", "seen": 1000})
+        );
+        let reread = JsonlReader::new(&schema, written.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(reread, values);
+
+        // unions must be tagged
+        let mut reader = JsonlReader::new(&schema, &b"\n{\"id\": 1, \"score\": 1.5}\n"[..]);
+        assert!(reader.next().unwrap().is_err());
+        assert_eq!(reader.line(), 2);
+    }
+}
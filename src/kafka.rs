@@ -0,0 +1,304 @@
+//! Logic for encoding and decoding Kafka message payloads in the wire format of the Confluent
+//! Schema Registry.
+//!
+//! Each payload is framed as a `0` magic byte, followed by the id of the writer schema in the
+//! registry as a 4-byte big-endian integer and by the value encoded in Avro binary format.
+//!
+//! An [`AvroSerializer`](struct.AvroSerializer.html) registers its schema on first use and an
+//! [`AvroDeserializer`](struct.AvroDeserializer.html) fetches the schemas of the ids it reads, both
+//! through a [`SchemaRegistry`](trait.SchemaRegistry.html) and caching the answers. Payloads being
+//! plain bytes, they plug into the producer and consumer of `rdkafka` as they are:
+//!
+//! ```ignore
+//! let payload = serializer.serialize(&value)?;
+//! producer.send(FutureRecord::to(topic).key(&key).payload(&payload), timeout).await;
+//!
+//! let value = message.payload().map(|payload| deserializer.deserialize(payload)).transpose()?;
+//! ```
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::de::from_value;
+use crate::error::AvroError;
+use crate::reader::from_avro_datum;
+use crate::schema::Schema;
+use crate::types::Value;
+use crate::writer::{to_avro_datum_into, to_avro_datum_serde};
+
+const MAGIC: u8 = 0;
+const HEADER_LEN: usize = 5;
+
+/// Registry of the schemas of Kafka payloads, identified by a numeric id.
+///
+/// Implement it for a client of the Confluent Schema Registry to share schemas across services,
+/// or use a [`MemoryRegistry`](struct.MemoryRegistry.html) within a process.
+pub trait SchemaRegistry {
+    /// Register the JSON `document` of a schema under `subject`, returning its id. Registering a
+    /// document already known returns the id it was given before.
+    fn register(&self, subject: &str, document: &str) -> Result<u32, AvroError>;
+
+    /// JSON document of the schema registered with `id`.
+    fn schema(&self, id: u32) -> Result<String, AvroError>;
+}
+
+impl<R: SchemaRegistry + ?Sized> SchemaRegistry for &R {
+    fn register(&self, subject: &str, document: &str) -> Result<u32, AvroError> {
+        (**self).register(subject, document)
+    }
+
+    fn schema(&self, id: u32) -> Result<String, AvroError> {
+        (**self).schema(id)
+    }
+}
+
+impl<R: SchemaRegistry + ?Sized> SchemaRegistry for Arc<R> {
+    fn register(&self, subject: &str, document: &str) -> Result<u32, AvroError> {
+        (**self).register(subject, document)
+    }
+
+    fn schema(&self, id: u32) -> Result<String, AvroError> {
+        (**self).schema(id)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Registered {
+    // the document of id i at i - 1
+    documents: Vec<String>,
+    ids: HashMap<String, u32>,
+    versions: HashMap<String, Vec<u32>>,
+}
+
+/// `SchemaRegistry` held in memory, giving ids from 1 in order of registration.
+#[derive(Debug, Default)]
+pub struct MemoryRegistry {
+    registered: Mutex<Registered>,
+}
+
+impl MemoryRegistry {
+    /// Create an empty `MemoryRegistry`.
+    pub fn new() -> MemoryRegistry {
+        MemoryRegistry::default()
+    }
+
+    /// Ids of the schemas registered under `subject`, oldest first.
+    pub fn versions(&self, subject: &str) -> Vec<u32> {
+        let registered = self.registered.lock().unwrap_or_else(|error| error.into_inner());
+        registered.versions.get(subject).cloned().unwrap_or_default()
+    }
+}
+
+impl SchemaRegistry for MemoryRegistry {
+    fn register(&self, subject: &str, document: &str) -> Result<u32, AvroError> {
+        let mut registered = self.registered.lock().unwrap_or_else(|error| error.into_inner());
+        let id = match registered.ids.get(document) {
+            Some(id) => *id,
+            None => {
+                registered.documents.push(document.to_owned());
+                let id = registered.documents.len() as u32;
+                registered.ids.insert(document.to_owned(), id);
+                id
+            },
+        };
+        let versions = registered.versions.entry(subject.to_owned()).or_default();
+        if !versions.contains(&id) {
+            versions.push(id);
+        }
+        Ok(id)
+    }
+
+    fn schema(&self, id: u32) -> Result<String, AvroError> {
+        let registered = self.registered.lock().unwrap_or_else(|error| error.into_inner());
+        (id as usize)
+            .checked_sub(1)
+            .and_then(|index| registered.documents.get(index))
+            .cloned()
+            .ok_or_else(|| AvroError::Decode(format!("unknown schema id {}", id)))
+    }
+}
+
+/// Subject of the schemas of the keys (when `key` is set) or values of `topic`, following the
+/// default `TopicNameStrategy` of the Confluent Schema Registry: `<topic>-key` or
+/// `<topic>-value`.
+pub fn topic_subject(topic: &str, key: bool) -> String {
+    format!("{}-{}", topic, if key { "key" } else { "value" })
+}
+
+/// Id of the writer schema of a framed `payload`.
+pub fn schema_id(payload: &[u8]) -> Result<u32, AvroError> {
+    match payload {
+        [MAGIC, a, b, c, d, ..] => Ok(u32::from_be_bytes([*a, *b, *c, *d])),
+        [MAGIC, ..] => Err(AvroError::Decode("payload shorter than its header".to_owned())),
+        _ => Err(AvroError::Decode("wrong magic byte in payload".to_owned())),
+    }
+}
+
+/// Encoder of the values of a schema as framed Kafka payloads.
+///
+/// The schema is registered under the subject on the first payload, and its id kept for the
+/// following ones; a failed registration is tried again on the next payload.
+pub struct AvroSerializer<G> {
+    registry: G,
+    subject: String,
+    schema: Schema,
+    id: Mutex<Option<u32>>,
+}
+
+impl<G: SchemaRegistry> AvroSerializer<G> {
+    /// Create an `AvroSerializer` of the values of `schema`, registered in `registry` under
+    /// `subject`, such as the [`topic_subject`](fn.topic_subject.html) of a topic.
+    pub fn new<S: Into<String>>(registry: G, subject: S, schema: Schema) -> AvroSerializer<G> {
+        AvroSerializer {
+            registry,
+            subject: subject.into(),
+            schema,
+            id: Mutex::new(None),
+        }
+    }
+
+    /// Schema of the values.
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    /// Id of the schema in the registry, registering it unless done before.
+    pub fn schema_id(&self) -> Result<u32, AvroError> {
+        let mut id = self.id.lock().unwrap_or_else(|error| error.into_inner());
+        if let Some(id) = *id {
+            return Ok(id)
+        }
+        let document = serde_json::to_string(&self.schema)?;
+        let registered = self.registry.register(&self.subject, &document)?;
+        *id = Some(registered);
+        Ok(registered)
+    }
+
+    /// Framed payload of `value`, which must be valid for the schema.
+    pub fn serialize(&self, value: &Value) -> Result<Vec<u8>, AvroError> {
+        let mut payload = self.header()?;
+        to_avro_datum_into(&self.schema, value, &mut payload)?;
+        Ok(payload)
+    }
+
+    /// Framed payload of anything implementing the `Serialize` trait, encoded as by
+    /// [`to_avro_datum_serde`](../fn.to_avro_datum_serde.html).
+    pub fn serialize_serde<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, AvroError> {
+        let mut payload = self.header()?;
+        to_avro_datum_serde(value, &self.schema, &mut payload)?;
+        Ok(payload)
+    }
+
+    fn header(&self) -> Result<Vec<u8>, AvroError> {
+        let mut payload = Vec::with_capacity(64);
+        payload.push(MAGIC);
+        payload.extend_from_slice(&self.schema_id()?.to_be_bytes());
+        Ok(payload)
+    }
+}
+
+/// Decoder of framed Kafka payloads, whatever the schema they were written with.
+///
+/// The schemas of the ids read are fetched from the registry once, and kept for the following
+/// payloads.
+pub struct AvroDeserializer<G> {
+    registry: G,
+    reader_schema: Option<Schema>,
+    schemas: Mutex<HashMap<u32, Arc<Schema>>>,
+}
+
+impl<G: SchemaRegistry> AvroDeserializer<G> {
+    /// Create an `AvroDeserializer` fetching writer schemas from `registry`.
+    pub fn new(registry: G) -> AvroDeserializer<G> {
+        AvroDeserializer {
+            registry,
+            reader_schema: None,
+            schemas: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Resolve the values read against `schema`, as for a `Reader` given a reader schema.
+    pub fn with_reader_schema(mut self, schema: Schema) -> AvroDeserializer<G> {
+        self.reader_schema = Some(schema);
+        self
+    }
+
+    /// Schema registered with `id`, fetched unless done before.
+    pub fn writer_schema(&self, id: u32) -> Result<Arc<Schema>, AvroError> {
+        if let Some(schema) = self.lock().get(&id) {
+            return Ok(schema.clone())
+        }
+        // fetching is done without the lock held, at the cost of racing fetches of a new id
+        let schema = Arc::new(Schema::parse_str(&self.registry.schema(id)?)?);
+        Ok(self.lock().entry(id).or_insert(schema).clone())
+    }
+
+    /// Value of a framed `payload`.
+    pub fn deserialize(&self, payload: &[u8]) -> Result<Value, AvroError> {
+        let schema = self.writer_schema(schema_id(payload)?)?;
+        let mut datum = Cursor::new(&payload[HEADER_LEN..]);
+        let value = from_avro_datum(&schema, &mut datum, self.reader_schema.as_ref())?;
+        if datum.position() as usize != datum.get_ref().len() {
+            return Err(AvroError::Decode("trailing bytes after the value of payload".to_owned()))
+        }
+        Ok(value)
+    }
+
+    /// Value of a framed `payload`, deserialized with serde.
+    pub fn deserialize_serde<T: DeserializeOwned>(&self, payload: &[u8]) -> Result<T, AvroError> {
+        Ok(from_value(&self.deserialize(payload)?)?)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<u32, Arc<Schema>>> {
+        self.schemas.lock().unwrap_or_else(|error| error.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ToAvro;
+
+    #[test]
+    fn test_kafka_round_trip() {
+        let registry = Arc::new(MemoryRegistry::new());
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "order", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "sku", "type": "string"}
+            ]}"#,
+        ).unwrap();
+        let subject = topic_subject("orders", false);
+        let serializer = AvroSerializer::new(registry.clone(), subject, schema);
+        let value = Value::Record(vec![
+            ("id".into(), Value::Long(7, None)),
+            ("sku".into(), "abc".avro()),
+        ], None);
+
+        let payload = serializer.serialize(&value).unwrap();
+        assert_eq!(&payload[..5], &[0, 0, 0, 0, 1]);
+        assert_eq!(schema_id(&payload).unwrap(), 1);
+        assert_eq!(serializer.serialize(&value).unwrap(), payload);
+        assert_eq!(registry.versions("orders-value"), vec![1]);
+
+        let reader_schema = Schema::parse_str(
+            r#"{"type": "record", "name": "order", "fields": [
+                {"name": "id", "type": "long"}
+            ]}"#,
+        ).unwrap();
+        let deserializer = AvroDeserializer::new(registry.clone());
+        assert_eq!(deserializer.deserialize(&payload).unwrap(), value);
+        let deserializer = deserializer.with_reader_schema(reader_schema);
+        assert_eq!(
+            deserializer.deserialize(&payload).unwrap(),
+            Value::Record(vec![("id".into(), Value::Long(7, None))], None)
+        );
+
+        assert!(deserializer.deserialize(&[1, 0, 0, 0, 1, 14]).is_err());
+        assert!(deserializer.deserialize(&[0, 0, 0, 0, 2, 14]).is_err());
+        assert!(deserializer.deserialize(&[0, 0, 0]).is_err());
+    }
+}
@@ -0,0 +1,167 @@
+//! Logic for Avro values kept in their encoded form until they are accessed.
+use crate::borrowed::ValueRef;
+use crate::decode::{decode, decode_lazy, decode_len, decode_ref, skip, str_key, take};
+use crate::error::AvroError;
+use crate::schema::Schema;
+use crate::types::Value;
+use crate::util::{
+    item_at, reserved_items, safe_len, usize_len, zag_i64, EntriesBudget,
+};
+
+/// Avro value decoded with [`decode_lazy`](decode/fn.decode_lazy.html), which is kept as the
+/// slice of the buffer it is encoded in until it is accessed.
+///
+/// Accessing a field of a record, or the items of an array or a map, only skips over the bytes of
+/// the values before it: nested records, arrays and maps are themselves `LazyValue`s, so that
+/// reading a couple of fields out of a large record does not cost the decoding of the others.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LazyValue<'a> {
+    schema: &'a Schema,
+    bytes: &'a [u8],
+}
+
+impl<'a> LazyValue<'a> {
+    pub(crate) fn new(schema: &'a Schema, bytes: &'a [u8]) -> LazyValue<'a> {
+        LazyValue { schema, bytes }
+    }
+
+    /// `Schema` of the value.
+    pub fn schema(&self) -> &'a Schema {
+        self.schema
+    }
+
+    /// Avro encoding of the value.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Decode the whole value.
+    pub fn decode(&self) -> Result<Value, AvroError> {
+        decode(self.schema, &mut &self.bytes[..])
+    }
+
+    /// Decode the whole value as a [ValueRef](enum.ValueRef.html), borrowing from the buffer it
+    /// is encoded in.
+    pub fn decode_ref(&self) -> Result<ValueRef<'a>, AvroError> {
+        decode_ref(self.schema, &mut &self.bytes[..])
+    }
+
+    /// Value held by a union or an optional, or the value itself for other schemas. `None` if the
+    /// union holds a null, or if the optional holds no value.
+    pub fn branch(&self) -> Result<Option<LazyValue<'a>>, AvroError> {
+        let mut bytes = self.bytes;
+        let schema = match *self.schema {
+            Schema::Union(ref inner) => {
+                let index = zag_i64(&mut bytes)?;
+                match item_at(inner.variants(), index) {
+                    Some(Schema::Null) => return Ok(None),
+                    Some(variant) => variant,
+                    None => return Err(AvroError::Decode("Union index out of bounds".to_owned())),
+                }
+            },
+            Schema::Optional(ref inner) => match zag_i64(&mut bytes)? {
+                0 => return Ok(None),
+                1 => &**inner,
+                _ => return Err(AvroError::Decode("Optional index out of bounds".to_owned())),
+            },
+            _ => return Ok(Some(*self)),
+        };
+        Ok(Some(LazyValue::new(schema, bytes)))
+    }
+
+    /// Field `name` of a record, looked through unions and optionals. `None` if the record has no
+    /// such field, or if a union or an optional holds no record.
+    pub fn field(&self, name: &str) -> Result<Option<LazyValue<'a>>, AvroError> {
+        let value = match self.branch()? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        match *value.schema {
+            Schema::Record {
+                ref fields,
+                ref lookup,
+                ..
+            } => {
+                let position = match lookup.get(name) {
+                    Some(&position) => position,
+                    None => return Ok(None),
+                };
+                let mut bytes = value.bytes;
+                for field in &fields[..position] {
+                    skip(&field.schema, &mut bytes)?;
+                }
+                decode_lazy(&fields[position].schema, &mut bytes).map(Some)
+            },
+            _ => Err(AvroError::Decode(format!("Field {} of a value that is not a record", name))),
+        }
+    }
+
+    /// Field of a record given its dot-separated path, like [`field`](#method.field).
+    pub fn get(&self, path: &str) -> Result<Option<LazyValue<'a>>, AvroError> {
+        let mut value = *self;
+        for name in path.split('.') {
+            value = match value.field(name)? {
+                Some(value) => value,
+                None => return Ok(None),
+            };
+        }
+        Ok(Some(value))
+    }
+
+    /// Items of an array or a bounded list.
+    pub fn items(&self) -> Result<Vec<LazyValue<'a>>, AvroError> {
+        let mut bytes = self.bytes;
+        match *self.schema {
+            Schema::Array(ref items) => lazy_items(&mut bytes, |bytes| decode_lazy(items, bytes)),
+            Schema::BoundedList {
+                ref items,
+                max_len,
+                truncation,
+            } => {
+                let mut items = lazy_items(&mut bytes, |bytes| decode_lazy(items, bytes))?;
+                // lists written with a larger maximum length are truncated
+                truncation.apply(&mut items, max_len);
+                Ok(items)
+            },
+            _ => Err(AvroError::Decode("Items of a value that is not an array".to_owned())),
+        }
+    }
+
+    /// Entries of a map, in the order they are encoded in.
+    pub fn entries(&self) -> Result<Vec<(&'a str, LazyValue<'a>)>, AvroError> {
+        let mut bytes = self.bytes;
+        match *self.schema {
+            Schema::Map(ref values) => lazy_items(&mut bytes, |bytes| {
+                let len = decode_len(bytes)?;
+                let key = str_key(take(bytes, len)?)?;
+                Ok((key, decode_lazy(values, bytes)?))
+            }),
+            _ => Err(AvroError::Decode("Entries of a value that is not a map".to_owned())),
+        }
+    }
+}
+
+/// Read the blocks of items of an array or a map with `item`. Blocks with a negative count are
+/// followed by their size in bytes, which is not needed here.
+fn lazy_items<'a, T, F>(bytes: &mut &'a [u8], mut item: F) -> Result<Vec<T>, AvroError>
+where
+    F: FnMut(&mut &'a [u8]) -> Result<T, AvroError>,
+{
+    let mut items = Vec::new();
+    let mut budget = EntriesBudget::default();
+    loop {
+        let len = match zag_i64(bytes)? {
+            0 => return Ok(items),
+            len if len < 0 => {
+                zag_i64(bytes)?;
+                safe_len(usize_len(len.saturating_abs())?)?
+            },
+            len => safe_len(usize_len(len)?)?,
+        };
+        budget.reserve(len)?;
+        items.reserve(reserved_items(len));
+        for _ in 0..len {
+            items.push(item(bytes)?);
+        }
+    }
+}
@@ -42,6 +42,29 @@
 //! features = ["snappy"]
 //! ```
 //!
+//! The `arrow` feature adds the [arrow](arrow/index.html) module, converting values to and from
+//! Arrow record batches, and the `parquet` feature the [parquet](parquet/index.html) module,
+//! archiving Avro files as Parquet files. The `csv` feature adds the [csv](csv/index.html)
+//! module, reading records from CSV files and writing them as CSV files, and the `protobuf`
+//! feature the [protobuf](protobuf/index.html) module, converting protobuf messages into Avro
+//! schemas. The `polars` feature adds the [polars](polars/index.html) module, reading Avro files
+//! into Polars data frames and writing data frames as Avro files. The `tracing` feature reports
+//! the blocks read and written, their compression, the fields resolved to their default and the
+//! evicted `lru_set` entries as [tracing](https://docs.rs/tracing) spans and events. The `cli`
+//! feature builds the `avro` command line tool, such as `avro cat file.avro --json` printing the
+//! records of a file as JSON Lines.
+//!
+//! The [kafka](kafka/index.html) module encodes and decodes Kafka message payloads in the wire
+//! format of the Confluent Schema Registry.
+//!
+//! Readers and writers count the records, blocks and bytes they read and write into the
+//! [`Metrics`](trait.Metrics.html) set with their `set_metrics` method, such as a
+//! [`PrometheusMetrics`](struct.PrometheusMetrics.html) rendering them for Prometheus.
+//!
+//! [`write_interop_files`](fn.write_interop_files.html) writes the interop dataset shared by the
+//! implementations of Avro with each supported codec, for their interop tests to check that the
+//! files of this library are read by Java or Python.
+//!
 //! To use the library,  just add at the top of the crate:
 //!
 //! ```
@@ -212,7 +235,7 @@
 //!
 //! ## Using codecs to compress data
 //!
-//! Avro supports three different compression codecs when encoding data:
+//! Avro supports four different compression codecs when encoding data:
 //!
 //! * **Null**: leaves data uncompressed;
 //! * **Deflate**: writes the data block using the deflate algorithm as specified in RFC 1951, and
@@ -221,6 +244,11 @@
 //! * **Snappy**: uses Google's [Snappy](http://google.github.io/snappy/) compression library. Each
 //! compressed block is followed by the 4-byte, big-endianCRC32 checksum of the uncompressed data in
 //! the block. You must enable the `snappy` feature to use this codec.
+//! * **Zstandard**: uses the [Zstandard](https://facebook.github.io/zstd/) compression library.
+//!
+//! You must enable the `zstd` feature to use the Zstandard codec. Deflate and Zstandard blocks
+//! can be compressed at another level than the default one of the codec, set with
+//! `Writer::set_compression_level`.
 //!
 //! To specify a codec to use to compress data, just specify it while creating a `Writer`:
 //! ```
@@ -437,10 +465,8 @@
 //!
 //! #[macro_use]
 //! extern crate serde_derive;
-//! extern crate failure;
 //!
-//! use avro_rs::{Codec, Reader, Schema, Writer, from_value, types::Record};
-//! use failure::Error;
+//! use avro_rs::{AvroError, Codec, Reader, Schema, Writer, from_value, types::Record};
 //!
 //! #[derive(Debug, Deserialize, Serialize)]
 //! struct Test {
@@ -448,7 +474,7 @@
 //!     b: String,
 //! }
 //!
-//! fn main() -> Result<(), Error> {
+//! fn main() -> Result<(), AvroError> {
 //!     let raw_schema = r#"
 //!         {
 //!             "type": "record",
@@ -492,9 +518,6 @@
 //! ```
 
 extern crate digest;
-extern crate failure;
-#[macro_use]
-extern crate failure_derive;
 extern crate libflate;
 extern crate rand;
 #[macro_use]
@@ -517,27 +540,108 @@ extern crate serde_derive;
 #[macro_use]
 extern crate lazy_static;
 
+// macros, declared first to be in scope of the other modules
+#[macro_use]
+mod trace;
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+mod bigquery;
+mod bloom;
+mod borrowed;
+mod cache;
 mod codec;
+mod codegen;
+mod compact;
+#[cfg(feature = "csv")]
+pub mod csv;
+mod date;
 mod de;
+mod ddl;
+mod error;
+mod events;
+mod display;
+mod hll;
+mod index;
+mod interop;
+mod json;
+mod json_schema;
+mod jsonl;
+pub mod kafka;
+mod lazy;
+mod lru;
+mod merge;
+mod metrics;
+mod normalize;
+mod optional;
+mod ordering;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+mod plan;
+#[cfg(feature = "polars")]
+pub mod polars;
+mod pool;
+mod predicate;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+mod random;
 pub mod decode;
 pub mod encode;
 mod reader;
 mod ser;
+mod ser_datum;
+mod set;
+mod topk;
 mod util;
+mod visit;
 mod writer;
 
 pub mod schema;
 mod schema_test;
 pub mod types;
 
+pub use crate::bloom::BloomFilter;
+pub use crate::borrowed::ValueRef;
+pub use crate::cache::SchemaCache;
 pub use crate::codec::Codec;
+pub use crate::codegen::{generate, generate_rust, CodegenOptions};
+pub use crate::date::{DateBucket, DateParsing};
+pub use crate::ddl::SqlDialect;
 pub use crate::de::from_value;
-pub use crate::reader::{from_avro_datum, Reader};
-pub use crate::schema::{ParseSchemaError, Schema, LruLimit};
+pub use crate::decode::PartialRecord;
+pub use crate::merge::{ArrayMerge, MergePolicy};
+pub use crate::metrics::{Metrics, NoopMetrics, PrometheusMetrics};
+pub use crate::display::{DisplayOptions, ValueDisplay};
+pub use crate::error::{AvroError, ErrorContext, ErrorKind};
+pub use crate::events::DecodeHandler;
+pub use crate::hll::HyperLogLog;
+pub use crate::lazy::LazyValue;
+pub use crate::lru::LruMap;
+pub use crate::jsonl::{JsonlReader, JsonlWriter};
+pub use crate::interop::{
+    interop_schema, interop_value, write_interop_data, write_interop_files,
+};
+pub use crate::index::{write_sidecar_index, BlockStats, FileIndex, Posting, SidecarIndex};
+pub use crate::plan::ResolutionPlan;
+pub use crate::pool::ValuePool;
+pub use crate::predicate::Predicate;
+pub use crate::random::RandomValues;
+#[cfg(feature = "rayon")]
+pub use crate::reader::{ParIter, ParallelOptions};
+pub use crate::reader::{
+    from_avro_datum, from_avro_datum_with_options, BlockCounts, Filtered, Reader, ScanFiltered,
+};
+pub use crate::schema::{
+    DateUnit, Decay, FieldMetadata, LruLimit, ParseOptions, Schema, Truncation,
+};
 pub use crate::ser::to_value;
-pub use crate::types::SchemaResolutionError;
-pub use crate::util::{max_allocation_bytes, DecodeError};
-pub use crate::writer::{to_avro_datum, ValidationError, Writer};
+pub use crate::topk::{TopK, TopKCount};
+pub use crate::types::{
+    NonFiniteFloats, NumericPromotion, PutError, ResolveOptions, SetItem, UnknownFieldPolicy,
+};
+pub use crate::util::{max_allocation_bytes, DecodeOptions, InvalidUtf8};
+pub use crate::visit::{PathSegment, ValueVisitor, ValueVisitorMut};
+pub use crate::writer::{to_avro_datum, to_avro_datum_into, to_avro_datum_serde, Writer};
 
 #[cfg(test)]
 mod tests {
@@ -642,7 +746,7 @@ mod tests {
                 "value": "string"
               },
               {
-               "name": "followed_np",
+               "name": "blocked_np",
                "type": {
                  "type": "map",
                  "values": {
@@ -710,9 +814,9 @@ mod tests {
         assert_eq!(
             reader.next().unwrap().unwrap(),
             Value::Record(vec![
-                ("a".to_string(), Value::Long(27, None)),
-                ("b".to_string(), Value::String("foo".to_string(), None)),
-                ("c".to_string(), Value::Enum(1, "spades".to_string(), None)),
+                ("a".into(), Value::Long(27, None)),
+                ("b".into(), Value::String("foo".to_string(), None)),
+                ("c".into(), Value::Enum(1, "spades".to_string(), None)),
             ], None)
         );
         assert!(reader.next().is_none());
@@ -753,9 +857,9 @@ mod tests {
         assert_eq!(
             reader.next().unwrap().unwrap(),
             Value::Record(vec![
-                ("a".to_string(), Value::Long(27, None)),
-                ("b".to_string(), Value::String("foo".to_string(), None)),
-                ("c".to_string(), Value::Enum(2, "clubs".to_string(), None)),
+                ("a".into(), Value::Long(27, None)),
+                ("b".into(), Value::String("foo".to_string(), None)),
+                ("c".into(), Value::Enum(2, "clubs".to_string(), None)),
             ], None)
         );
         assert!(reader.next().is_none());
@@ -852,9 +956,9 @@ mod tests {
         assert_eq!(
             reader.next().unwrap().unwrap(),
             Value::Record(vec![
-                ("a".to_string(), Value::Long(27, None)),
-                ("b".to_string(), Value::String("foo".to_string(), None)),
-                ("c".to_string(), Value::Enum(2, "clubs".to_string(), None)),
+                ("a".into(), Value::Long(27, None)),
+                ("b".into(), Value::String("foo".to_string(), None)),
+                ("c".into(), Value::Enum(2, "clubs".to_string(), None)),
             ], None)
         );
     }
@@ -0,0 +1,284 @@
+//! Logic for the entries of `lru_set` values, kept in order of access.
+use std::cmp::Reverse;
+use std::collections::hash_map;
+use std::collections::{BTreeSet, HashMap};
+use std::iter::FromIterator;
+use std::ops::Index;
+
+use crate::schema::LruLimit;
+use crate::types::LruValue;
+use crate::util::count_evictions;
+
+/// Entries of a [`Value::LruSet`](types/enum.Value.html#variant.LruSet): the access time and count
+/// of each key, along with the keys ordered from the most to the least recently accessed.
+///
+/// The order is kept up to date as entries are inserted and removed, so that the oldest entries
+/// are found and evicted without scanning the whole set. Entries are updated by inserting them
+/// again, which is why no mutable access to them is given.
+#[derive(Clone, Debug, Default)]
+pub struct LruMap {
+    entries: HashMap<String, LruValue>,
+    // most recently accessed first, ties broken by key
+    order: BTreeSet<(Reverse<i64>, String)>,
+}
+
+impl LruMap {
+    /// Create an empty `LruMap`.
+    pub fn new() -> LruMap {
+        LruMap::default()
+    }
+
+    /// Number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether there is no entry.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of entries the map holds room for without reallocating.
+    pub(crate) fn capacity(&self) -> usize {
+        self.entries.capacity()
+    }
+
+    /// Reserve room for `additional` more entries.
+    pub fn reserve(&mut self, additional: usize) {
+        self.entries.reserve(additional);
+    }
+
+    /// Entry of `key`, if any.
+    pub fn get(&self, key: &str) -> Option<&LruValue> {
+        self.entries.get(key)
+    }
+
+    /// Whether there is an entry for `key`.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Insert or replace the entry of `key`, returning the replaced one.
+    pub fn insert(&mut self, key: String, value: LruValue) -> Option<LruValue> {
+        let previous = self.entries.insert(key.clone(), value.clone());
+        if let Some(ref previous) = previous {
+            self.order.remove(&(Reverse(previous.access_time), key.clone()));
+        }
+        self.order.insert((Reverse(value.access_time), key));
+        previous
+    }
+
+    /// Remove the entry of `key`, returning it.
+    pub fn remove(&mut self, key: &str) -> Option<LruValue> {
+        let (key, value) = self.entries.remove_entry(key)?;
+        self.order.remove(&(Reverse(value.access_time), key));
+        Some(value)
+    }
+
+    /// Keep the entries for which `f` returns `true`.
+    pub fn retain<F: FnMut(&str, &LruValue) -> bool>(&mut self, mut f: F) {
+        let order = &mut self.order;
+        self.entries.retain(|key, value| {
+            let keep = f(key, value);
+            if !keep {
+                order.remove(&(Reverse(value.access_time), key.clone()));
+            }
+            keep
+        });
+    }
+
+    /// Entries in arbitrary order.
+    pub fn iter(&self) -> hash_map::Iter<'_, String, LruValue> {
+        self.entries.iter()
+    }
+
+    /// Keys in arbitrary order.
+    pub fn keys(&self) -> hash_map::Keys<'_, String, LruValue> {
+        self.entries.keys()
+    }
+
+    /// Entries from the most to the least recently accessed, ties broken by key.
+    pub fn iter_recent(&self) -> impl DoubleEndedIterator<Item = (&String, &LruValue)> {
+        self.order.iter().map(move |(_, key)| (key, &self.entries[key]))
+    }
+
+    /// Most recently accessed entry.
+    pub fn newest(&self) -> Option<(&String, &LruValue)> {
+        self.iter_recent().next()
+    }
+
+    /// Least recently accessed entry.
+    pub fn oldest(&self) -> Option<(&String, &LruValue)> {
+        self.iter_recent().next_back()
+    }
+
+    /// Remove the least recently accessed entry, returning it.
+    pub fn pop_oldest(&mut self) -> Option<(String, LruValue)> {
+        let (_, key) = self.order.iter().next_back()?.clone();
+        self.order.remove(&(Reverse(self.entries[&key].access_time), key.clone()));
+        self.entries.remove_entry(&key)
+    }
+
+    /// Remove every entry, returning their keys.
+    pub(crate) fn drain_keys(&mut self) -> impl Iterator<Item = String> + '_ {
+        self.order.clear();
+        self.entries.drain().map(|(key, _)| key)
+    }
+
+    /// Evict the entries beyond `limit`, starting from the least recently accessed. See
+    /// [`LruLimit`](schema/enum.LruLimit.html) for how each limit applies; time based limits only
+    /// apply given a reference time `now`.
+    pub fn evict(&mut self, limit: &LruLimit, now: Option<i64>) {
+        let len = self.len();
+        if let (Some(ttl), Some(now)) = (limit.ttl_millis(), now) {
+            let expiry = now.saturating_sub(ttl);
+            while self.oldest().is_some_and(|(_, value)| value.access_time < expiry) {
+                self.pop_oldest();
+            }
+        }
+        match *limit {
+            LruLimit::Count(_) | LruLimit::Entries { .. } => {
+                if let Some(max_entries) = limit.max_entries() {
+                    while self.len() > max_entries {
+                        self.pop_oldest();
+                    }
+                }
+            },
+            LruLimit::Bytes(budget) => {
+                let mut used = 0usize;
+                let retained = self
+                    .iter_recent()
+                    .take_while(|(key, _)| {
+                        used += key.len() + LruLimit::ENTRY_OVERHEAD;
+                        used <= budget as usize
+                    })
+                    .count();
+                while self.len() > retained {
+                    self.pop_oldest();
+                }
+            },
+            _ => (),
+        }
+        if self.len() < len {
+            let evicted = len - self.len();
+            trace_event!(trace, evicted, limit = ?limit, "evicted lru entries");
+            count_evictions(evicted);
+        }
+    }
+}
+
+impl PartialEq for LruMap {
+    fn eq(&self, other: &LruMap) -> bool {
+        self.entries == other.entries
+    }
+}
+
+impl<'a> Index<&'a str> for LruMap {
+    type Output = LruValue;
+
+    fn index(&self, key: &'a str) -> &LruValue {
+        &self.entries[key]
+    }
+}
+
+impl Extend<(String, LruValue)> for LruMap {
+    fn extend<I: IntoIterator<Item = (String, LruValue)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl FromIterator<(String, LruValue)> for LruMap {
+    fn from_iter<I: IntoIterator<Item = (String, LruValue)>>(iter: I) -> LruMap {
+        let mut map = LruMap::new();
+        map.extend(iter);
+        map
+    }
+}
+
+impl From<HashMap<String, LruValue>> for LruMap {
+    fn from(entries: HashMap<String, LruValue>) -> LruMap {
+        let order = entries
+            .iter()
+            .map(|(key, value)| (Reverse(value.access_time), key.clone()))
+            .collect();
+        LruMap { entries, order }
+    }
+}
+
+impl From<LruMap> for HashMap<String, LruValue> {
+    fn from(map: LruMap) -> HashMap<String, LruValue> {
+        map.entries
+    }
+}
+
+impl IntoIterator for LruMap {
+    type Item = (String, LruValue);
+    type IntoIter = hash_map::IntoIter<String, LruValue>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a LruMap {
+    type Item = (&'a String, &'a LruValue);
+    type IntoIter = hash_map::Iter<'a, String, LruValue>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_map_order() {
+        let mut map = LruMap::new();
+        map.insert("a".to_owned(), LruValue::new(3, 1));
+        map.insert("b".to_owned(), LruValue::new(1, 1));
+        map.insert("c".to_owned(), LruValue::new(2, 1));
+        map.insert("d".to_owned(), LruValue::new(2, 1));
+        assert_eq!(map.newest().unwrap().0, "a");
+        assert_eq!(map.oldest().unwrap().0, "b");
+        let keys = map.iter_recent().map(|(key, _)| key.as_str()).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["a", "c", "d", "b"]);
+
+        // entries are moved as they are accessed again
+        map.insert("b".to_owned(), LruValue::new(5, 2));
+        assert_eq!(map.newest().unwrap(), (&"b".to_owned(), &LruValue::new(5, 2)));
+        assert_eq!(map.len(), 4);
+        assert_eq!(map.pop_oldest(), Some(("d".to_owned(), LruValue::new(2, 1))));
+        assert_eq!(map.remove("c"), Some(LruValue::new(2, 1)));
+        map.retain(|key, _| key != "a");
+        assert_eq!(map.iter_recent().count(), 1);
+        assert_eq!(map, vec![("b".to_owned(), LruValue::new(5, 2))].into_iter().collect());
+    }
+
+    #[test]
+    fn test_lru_map_evict() {
+        let map = (0..10)
+            .map(|i| (format!("k{}", i), LruValue::new(i * 10_000, 1)))
+            .collect::<LruMap>();
+
+        let mut count = map.clone();
+        count.evict(&LruLimit::Count(3), None);
+        let keys = count.iter_recent().map(|(key, _)| key.as_str()).collect::<Vec<_>>();
+        assert_eq!(keys, vec!["k9", "k8", "k7"]);
+
+        // time based limits only apply given a reference time
+        let mut ttl = map.clone();
+        ttl.evict(&LruLimit::Minute(1), None);
+        assert_eq!(ttl.len(), 10);
+        ttl.evict(&LruLimit::Minute(1), Some(100_000));
+        assert_eq!(ttl.oldest().unwrap().0, "k4");
+
+        let mut bytes = map;
+        bytes.evict(&LruLimit::Bytes(2 * (2 + LruLimit::ENTRY_OVERHEAD as u32)), None);
+        assert_eq!(bytes.len(), 2);
+        assert!(bytes.contains_key("k9") && bytes.contains_key("k8"));
+    }
+}
@@ -0,0 +1,532 @@
+//! Logic for the schema-guided merge of Avro values.
+use std::cmp::Ordering;
+
+use crate::error::AvroError;
+use crate::lru::LruMap;
+use crate::schema::{Decay, LruLimit, Schema};
+use crate::types::{evict_lru, LruValue, Value};
+
+/// How two arrays are merged by [`Value::merge`](../types/enum.Value.html#method.merge).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArrayMerge {
+    /// Append the items of the other array.
+    Concat,
+    /// Append the items of the other array which are not already present, according to
+    /// [`Value::cmp_with_schema`](../types/enum.Value.html#method.cmp_with_schema).
+    Distinct,
+    /// Replace the array with the other one.
+    Replace,
+}
+
+/// Policy driving how [`Value::merge`](../types/enum.Value.html#method.merge) combines values.
+///
+/// The default policy treats the other value as a partial update: its scalars win, its nulls are
+/// ignored and arrays are concatenated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MergePolicy {
+    /// Whether scalar values of the other value replace the existing ones.
+    pub overwrite: bool,
+    /// Whether null values of the other value replace existing non-null ones.
+    pub overwrite_with_null: bool,
+    /// How arrays are merged.
+    pub arrays: ArrayMerge,
+    /// Reference time, in milliseconds since epoch, used to expire the entries of `LruSet`
+    /// values limited by time. The current time is used if `None`.
+    pub now: Option<i64>,
+}
+
+impl Default for MergePolicy {
+    fn default() -> MergePolicy {
+        MergePolicy {
+            overwrite: true,
+            overwrite_with_null: false,
+            arrays: ArrayMerge::Concat,
+            now: None,
+        }
+    }
+}
+
+fn is_null(value: &Value) -> bool {
+    match value {
+        Value::Null | Value::Optional(None, _) => true,
+        Value::Union(inner, _) => is_null(inner),
+        _ => false,
+    }
+}
+
+fn merge_error(left: &Value, right: &Value, schema: &Schema) -> AvroError {
+    AvroError::Resolution(format!(
+        "Cannot merge {:?} with {:?} using schema {:?}",
+        left, right, schema
+    ))
+}
+
+fn merge_lru_items(
+    items: &mut LruMap,
+    updates: LruMap,
+    limit: &LruLimit,
+    decay: Option<Decay>,
+    now: i64,
+) {
+    for (key, update) in updates {
+        let merged = match items.remove(&key) {
+            Some(current) => {
+                let access_time = current.access_time.max(update.access_time);
+                let count = match decay {
+                    Some(decay) => {
+                        current.count_at(decay, access_time)
+                            .saturating_add(update.count_at(decay, access_time))
+                    },
+                    None => current.count.saturating_add(update.count),
+                };
+                LruValue::new(access_time, count)
+            },
+            None => update,
+        };
+        items.insert(key, merged);
+    }
+    evict_lru(items, limit, Some(now));
+}
+
+impl Value {
+    /// Combine two `LruSet` values into one limited by `limit`, typically to aggregate the state
+    /// of several partitions into a global view.
+    ///
+    /// Keys of both sets are united; entries present in both keep the latest access time and the
+    /// sum of their counts. Entries are then evicted according to `limit`, relatively to the
+    /// current time. The `ValueSetting` of `a` is kept.
+    pub fn merge_lru_sets(a: Value, b: Value, limit: &LruLimit) -> Result<Value, AvroError> {
+        Value::merge_lru_sets_at(a, b, limit, chrono::Utc::now().timestamp_millis())
+    }
+
+    /// Combine two `LruSet` values as [`merge_lru_sets`](#method.merge_lru_sets) does, evicting
+    /// entries relatively to `now`, in milliseconds since epoch, instead of the current time.
+    pub fn merge_lru_sets_at(
+        a: Value,
+        b: Value,
+        limit: &LruLimit,
+        now: i64,
+    ) -> Result<Value, AvroError> {
+        match (a, b) {
+            (Value::LruSet(mut items, _, setting), Value::LruSet(updates, _, _)) => {
+                merge_lru_items(&mut items, *updates, limit, None, now);
+                Ok(Value::LruSet(items, limit.clone(), setting))
+            },
+            (a, b) => Err(merge_error(&a, &b, &Schema::LruSet(limit.clone(), None))),
+        }
+    }
+
+    /// Append items to an `Array` value of the given array or `bounded_list` schema. Bounded
+    /// lists are then truncated from the end declared by their schema, so that they hold at most
+    /// their maximum length. The `ValueSetting` of this value is kept.
+    pub fn append<I>(self, items: I, schema: &Schema) -> Result<Value, AvroError>
+    where
+        I: IntoIterator<Item = Value>,
+    {
+        match (self, schema) {
+            (Value::Array(mut current, setting), Schema::Array(_)) => {
+                current.extend(items);
+                Ok(Value::Array(current, setting))
+            },
+            (
+                Value::Array(mut current, setting),
+                Schema::BoundedList {
+                    max_len, truncation, ..
+                },
+            ) => {
+                current.extend(items);
+                truncation.apply(&mut current, *max_len);
+                Ok(Value::Array(current, setting))
+            },
+            (value, schema) => Err(AvroError::Resolution(format!(
+                "Cannot append to {:?} using schema {:?}",
+                value, schema
+            ))),
+        }
+    }
+
+    /// Deep merge `other` into this value, guided by the given [Schema](../schema/enum.Schema.html).
+    ///
+    /// Records are merged field by field and maps key by key. Arrays are combined according to
+    /// `policy.arrays`, counters are summed, sets, sketches and Bloom filters are united and
+    /// `LruSet` entries present on both sides keep the latest access time and the sum of their
+    /// counts (decayed to that time if the schema declares a `half_life`), before being evicted
+    /// according to the limit of the schema. `TopK` counts are
+    /// summed, keeping the most frequent keys up to the size of the schema. Any other value is
+    /// replaced by the one of `other` if `policy.overwrite` is set.
+    ///
+    /// Null values of `other` (including absent `Optional` values) never replace existing values
+    /// unless `policy.overwrite_with_null` is set. The `ValueSetting` of this value is kept.
+    pub fn merge(
+        self,
+        other: Value,
+        schema: &Schema,
+        policy: MergePolicy,
+    ) -> Result<Value, AvroError> {
+        if is_null(&other) && !is_null(&self) {
+            return Ok(if policy.overwrite_with_null { other } else { self })
+        }
+
+        match schema {
+            Schema::Record { fields, .. } => match (self, other) {
+                (Value::Record(mut items, setting), Value::Record(updates, _)) => {
+                    for (name, update) in updates {
+                        let field = match fields.iter().find(|field| field.name == name) {
+                            Some(field) => field,
+                            None => continue,
+                        };
+                        match items.iter().position(|(item, _)| *item == name) {
+                            Some(position) => {
+                                let current = std::mem::replace(&mut items[position].1, Value::Null);
+                                items[position].1 = current.merge(update, &field.schema, policy)?;
+                            },
+                            None => items.push((name, update)),
+                        }
+                    }
+                    Ok(Value::Record(items, setting))
+                },
+                (left, right) => Err(merge_error(&left, &right, schema)),
+            },
+            Schema::Map(inner) => match (self, other) {
+                (Value::Map(mut items, setting), Value::Map(updates, _)) => {
+                    for (key, update) in updates {
+                        let merged = match items.remove(&key) {
+                            Some(current) => current.merge(update, inner, policy)?,
+                            None => update,
+                        };
+                        items.insert(key, merged);
+                    }
+                    Ok(Value::Map(items, setting))
+                },
+                (left, right) => Err(merge_error(&left, &right, schema)),
+            },
+            Schema::Array(inner) | Schema::BoundedList { items: inner, .. } => match (self, other) {
+                (Value::Array(mut items, setting), Value::Array(updates, _)) => {
+                    match policy.arrays {
+                        ArrayMerge::Concat => items.extend(updates),
+                        ArrayMerge::Replace => items = updates,
+                        ArrayMerge::Distinct => {
+                            for update in updates {
+                                if !items.iter().any(|item| {
+                                    item.cmp_with_schema(&update, inner) == Ordering::Equal
+                                }) {
+                                    items.push(update);
+                                }
+                            }
+                        },
+                    }
+                    if let Schema::BoundedList { max_len, truncation, .. } = schema {
+                        truncation.apply(&mut items, *max_len);
+                    }
+                    Ok(Value::Array(items, setting))
+                },
+                (left, right) => Err(merge_error(&left, &right, schema)),
+            },
+            Schema::Set(_) => match (self, other) {
+                (Value::Set(mut items, setting), Value::Set(updates, _)) => {
+                    items.extend(updates);
+                    Ok(Value::Set(items, setting))
+                },
+                (left, right) => Err(merge_error(&left, &right, schema)),
+            },
+            Schema::LruSet(schema_limit, decay) => match (self, other) {
+                (Value::LruSet(mut items, limit, setting), Value::LruSet(updates, _, _)) => {
+                    let now = policy
+                        .now
+                        .unwrap_or_else(|| chrono::Utc::now().timestamp_millis());
+                    merge_lru_items(&mut items, *updates, schema_limit, *decay, now);
+                    Ok(Value::LruSet(items, limit, setting))
+                },
+                (left, right) => Err(merge_error(&left, &right, schema)),
+            },
+            Schema::Counter => match (self, other) {
+                (Value::Counter(current, setting), Value::Counter(update, _)) => {
+                    Ok(Value::Counter(current.saturating_add(update), setting))
+                },
+                (left, right) => Err(merge_error(&left, &right, schema)),
+            },
+            Schema::Hll(_) => match (self, other) {
+                (Value::Hll(mut current, setting), Value::Hll(update, _)) => {
+                    current.merge(&update)?;
+                    Ok(Value::Hll(current, setting))
+                },
+                (left, right) => Err(merge_error(&left, &right, schema)),
+            },
+            Schema::Bloom { .. } => match (self, other) {
+                (Value::Bloom(mut current, setting), Value::Bloom(update, _)) => {
+                    current.merge(&update)?;
+                    Ok(Value::Bloom(current, setting))
+                },
+                (left, right) => Err(merge_error(&left, &right, schema)),
+            },
+            Schema::TopK(size) => match (self, other) {
+                (Value::TopK(mut current, setting), Value::TopK(update, _)) => {
+                    current.truncate(*size);
+                    current.merge(&update);
+                    Ok(Value::TopK(current, setting))
+                },
+                (left, right) => Err(merge_error(&left, &right, schema)),
+            },
+            Schema::Optional(inner) => match (self, other) {
+                (Value::Optional(Some(current), setting), Value::Optional(Some(update), _)) => {
+                    Ok(Value::Optional(
+                        Some(Box::new(current.merge(*update, inner, policy)?)),
+                        setting,
+                    ))
+                },
+                (left, right) => Ok(if policy.overwrite { right } else { left }),
+            },
+            Schema::Union(union) => match (self, other) {
+                (Value::Union(current, setting), Value::Union(update, _)) => {
+                    match (union.find_schema(&current), union.find_schema(&update)) {
+                        (Some((i, branch)), Some((j, _))) if i == j => Ok(Value::Union(
+                            Box::new(current.merge(*update, branch, policy)?),
+                            setting,
+                        )),
+                        _ => Ok(if policy.overwrite {
+                            Value::Union(update, setting)
+                        } else {
+                            Value::Union(current, setting)
+                        }),
+                    }
+                },
+                (left, right) => Ok(if policy.overwrite { right } else { left }),
+            },
+            _ => Ok(if policy.overwrite { other } else { self }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::types::ToAvro;
+    use std::collections::HashSet;
+
+    static SCHEMA: &'static str = r#"
+            {
+                "type": "record",
+                "name": "state",
+                "fields": [
+                    {"name": "count", "type": "long"},
+                    {"name": "name", "type": ["null", "string"]},
+                    {"name": "tags", "type": "set"},
+                    {"name": "events", "type": {"type": "array", "items": "long"}},
+                    {"name": "totals", "type": {"type": "map", "values": "long"}}
+                ]
+            }
+        "#;
+
+    fn state(count: i64, name: Option<&str>, tags: &[&str], events: Vec<i64>, totals: &[(&str, i64)]) -> Value {
+        Value::record(vec![
+            ("count", count.avro()),
+            ("name", name.avro()),
+            ("tags", Value::Set(tags.iter().map(|&t| t.into()).collect(), None)),
+            ("events", events.avro()),
+            (
+                "totals",
+                totals.iter().map(|(k, v)| (k.to_string(), *v)).collect::<HashMap<_, _>>().avro(),
+            ),
+        ])
+    }
+
+    #[test]
+    fn test_merge_record_default_policy() {
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+        let left = state(1, Some("foo"), &["a"], vec![1], &[("x", 1)]);
+        let right = state(2, None, &["b"], vec![1, 2], &[("y", 2)]);
+
+        let merged = left.merge(right, &schema, MergePolicy::default()).unwrap();
+        assert_eq!(
+            merged,
+            state(2, Some("foo"), &["a", "b"], vec![1, 1, 2], &[("x", 1), ("y", 2)])
+        );
+    }
+
+    #[test]
+    fn test_merge_record_custom_policy() {
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+        let left = state(1, Some("foo"), &[], vec![1], &[]);
+        let right = state(2, None, &[], vec![1, 2], &[]);
+        let policy = MergePolicy {
+            overwrite: false,
+            overwrite_with_null: true,
+            arrays: ArrayMerge::Distinct,
+            now: None,
+        };
+
+        let merged = left.merge(right, &schema, policy).unwrap();
+        assert_eq!(merged, state(1, None, &[], vec![1, 2], &[]));
+    }
+
+    #[test]
+    fn test_merge_lru_set() {
+        let mut left = HashMap::new();
+        left.insert("a".to_owned(), LruValue::new(10, 1));
+        let mut right = HashMap::new();
+        right.insert("a".to_owned(), LruValue::new(5, 2));
+        right.insert("b".to_owned(), LruValue::new(7, 1));
+
+        let merged = Value::LruSet(Box::new(left.into()), LruLimit::Count(10), None)
+            .merge(
+                Value::LruSet(Box::new(right.into()), LruLimit::Count(10), None),
+                &Schema::LruSet(LruLimit::Count(10), None),
+                MergePolicy::default(),
+            )
+            .unwrap();
+
+        let mut expected = HashMap::new();
+        expected.insert("a".to_owned(), LruValue::new(10, 3));
+        expected.insert("b".to_owned(), LruValue::new(7, 1));
+        assert_eq!(merged, Value::LruSet(Box::new(expected.into()), LruLimit::Count(10), None));
+    }
+
+    #[test]
+    fn test_merge_lru_set_decay() {
+        let hour = 60 * 60 * 1000;
+        let mut left = HashMap::new();
+        left.insert("a".to_owned(), LruValue::new(0, 100));
+        let mut right = HashMap::new();
+        right.insert("a".to_owned(), LruValue::new(2 * hour, 10));
+
+        let schema = Schema::parse_str(
+            r#"{"type": "lru_set", "limit": "10", "half_life": "1 hour"}"#,
+        ).unwrap();
+        let merged = Value::LruSet(Box::new(left.into()), LruLimit::Count(10), None)
+            .merge(
+                Value::LruSet(Box::new(right.into()), LruLimit::Count(10), None),
+                &schema,
+                MergePolicy::default(),
+            )
+            .unwrap();
+
+        let mut expected = HashMap::new();
+        expected.insert("a".to_owned(), LruValue::new(2 * hour, 35));
+        assert_eq!(merged, Value::LruSet(Box::new(expected.into()), LruLimit::Count(10), None));
+    }
+
+    #[test]
+    fn test_merge_lru_set_expires() {
+        let minute = 60 * 1000;
+        let mut left = HashMap::new();
+        left.insert("a".to_owned(), LruValue::new(0, 1));
+        let mut right = HashMap::new();
+        right.insert("b".to_owned(), LruValue::new(2 * minute, 1));
+
+        let merged = Value::LruSet(Box::new(left.into()), LruLimit::Minute(5), None)
+            .merge(
+                Value::LruSet(Box::new(right.clone().into()), LruLimit::Minute(5), None),
+                &Schema::LruSet(LruLimit::Minute(5), None),
+                MergePolicy {
+                    now: Some(6 * minute),
+                    ..MergePolicy::default()
+                },
+            )
+            .unwrap();
+
+        assert_eq!(merged, Value::LruSet(Box::new(right.into()), LruLimit::Minute(5), None));
+    }
+
+    #[test]
+    fn test_merge_lru_sets() {
+        let mut a = HashMap::new();
+        a.insert("a".to_owned(), LruValue::new(1, 1));
+        a.insert("b".to_owned(), LruValue::new(4, 1));
+        let mut b = HashMap::new();
+        b.insert("a".to_owned(), LruValue::new(3, 2));
+        b.insert("c".to_owned(), LruValue::new(2, 5));
+
+        let merged = Value::merge_lru_sets(
+            Value::LruSet(Box::new(a.into()), LruLimit::Count(10), None),
+            Value::LruSet(Box::new(b.into()), LruLimit::Count(10), None),
+            &LruLimit::Count(2),
+        ).unwrap();
+
+        let mut expected = HashMap::new();
+        expected.insert("a".to_owned(), LruValue::new(3, 3));
+        expected.insert("b".to_owned(), LruValue::new(4, 1));
+        assert_eq!(merged, Value::LruSet(Box::new(expected.into()), LruLimit::Count(2), None));
+
+        // expired entries are evicted relatively to the given time
+        let minute = 60 * 1000;
+        let mut a = HashMap::new();
+        a.insert("a".to_owned(), LruValue::new(0, i64::MAX));
+        a.insert("b".to_owned(), LruValue::new(4 * minute, 1));
+        let mut b = HashMap::new();
+        b.insert("a".to_owned(), LruValue::new(2 * minute, 1));
+        b.insert("c".to_owned(), LruValue::new(minute / 2, 1));
+        let merged = Value::merge_lru_sets_at(
+            Value::LruSet(Box::new(a.into()), LruLimit::Minute(5), None),
+            Value::LruSet(Box::new(b.into()), LruLimit::Minute(5), None),
+            &LruLimit::Minute(5),
+            6 * minute,
+        ).unwrap();
+
+        let mut expected = HashMap::new();
+        expected.insert("a".to_owned(), LruValue::new(2 * minute, i64::MAX));
+        expected.insert("b".to_owned(), LruValue::new(4 * minute, 1));
+        assert_eq!(merged, Value::LruSet(Box::new(expected.into()), LruLimit::Minute(5), None));
+
+        assert!(Value::merge_lru_sets(
+            Value::Set(HashSet::new(), None),
+            Value::LruSet(Box::default(), LruLimit::Count(2), None),
+            &LruLimit::Count(2),
+        ).is_err());
+    }
+
+    #[test]
+    fn test_append_bounded_list() {
+        let schema = Schema::parse_str(
+            r#"{"type": "bounded_list", "items": "int", "max_length": 3}"#,
+        ).unwrap();
+        let value = Value::Array(vec![1i32.avro(), 2i32.avro()], None)
+            .append(vec![3i32.avro(), 4i32.avro()], &schema)
+            .unwrap();
+        assert_eq!(value, vec![2i32, 3i32, 4i32].avro());
+
+        let schema = Schema::parse_str(
+            r#"{"type": "bounded_list", "items": "int", "max_length": 3, "truncate": "tail"}"#,
+        ).unwrap();
+        let value = value.append(vec![5i32.avro()], &schema).unwrap();
+        assert_eq!(value, vec![2i32, 3i32, 4i32].avro());
+
+        let merged = value
+            .merge(vec![6i32].avro(), &schema, MergePolicy::default())
+            .unwrap();
+        assert_eq!(merged, vec![2i32, 3i32, 4i32].avro());
+        assert!(Value::Null.append(vec![], &schema).is_err());
+    }
+
+    #[test]
+    fn test_merge_counter() {
+        let merged = Value::Counter(3, None)
+            .merge(Value::Counter(4, None), &Schema::Counter, MergePolicy::default())
+            .unwrap();
+        assert_eq!(merged, Value::Counter(7, None));
+    }
+
+    #[test]
+    fn test_merge_topk() {
+        use crate::topk::TopK;
+
+        let mut a = TopK::new(2);
+        a.insert("x", 2);
+        a.insert("y", 1);
+        let mut b = TopK::new(2);
+        b.insert("y", 3);
+        b.insert("z", 1);
+
+        let merged = Value::TopK(Box::new(a), None)
+            .merge(Value::TopK(Box::new(b), None), &Schema::TopK(2), MergePolicy::default())
+            .unwrap();
+        assert_eq!(merged.json(), json!({"x": 2, "y": 4}));
+    }
+
+    #[test]
+    fn test_merge_mismatch() {
+        assert!(Value::Array(vec![], None)
+            .merge(Value::Set(HashSet::new(), None), &Schema::Set(Box::new(Schema::String)), MergePolicy::default())
+            .is_err());
+    }
+}
@@ -0,0 +1,267 @@
+//! Logic for counting the records, bytes and blocks read and written by `Reader`s and `Writer`s,
+//! to export them to a monitoring system.
+use std::fmt;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Receiver of the counts of a [`Reader`](struct.Reader.html) or a
+/// [`Writer`](struct.Writer.html), set with their `set_metrics` method.
+///
+/// Every method does nothing by default, so that an implementation only overrides the counts it
+/// exports. Methods are called as the counts grow, on the thread reading or writing, and must
+/// return quickly.
+pub trait Metrics: Send + Sync {
+    /// `count` records were decoded.
+    fn records_read(&self, _count: u64) {}
+
+    /// `count` records were appended.
+    fn records_written(&self, _count: u64) {}
+
+    /// `count` bytes of data blocks were read, as stored in the file.
+    fn bytes_read(&self, _count: u64) {}
+
+    /// `count` bytes of data blocks were written, as stored in the file.
+    fn bytes_written(&self, _count: u64) {}
+
+    /// `count` data blocks were read.
+    fn blocks_read(&self, _count: u64) {}
+
+    /// `count` data blocks were written.
+    fn blocks_written(&self, _count: u64) {}
+
+    /// `count` records or blocks failed to be decoded.
+    fn decode_errors(&self, _count: u64) {}
+
+    /// `count` entries of `lru_set`s were evicted while reading or writing a record.
+    fn evictions(&self, _count: u64) {}
+}
+
+impl<M: Metrics + ?Sized> Metrics for Arc<M> {
+    fn records_read(&self, count: u64) {
+        (**self).records_read(count)
+    }
+
+    fn records_written(&self, count: u64) {
+        (**self).records_written(count)
+    }
+
+    fn bytes_read(&self, count: u64) {
+        (**self).bytes_read(count)
+    }
+
+    fn bytes_written(&self, count: u64) {
+        (**self).bytes_written(count)
+    }
+
+    fn blocks_read(&self, count: u64) {
+        (**self).blocks_read(count)
+    }
+
+    fn blocks_written(&self, count: u64) {
+        (**self).blocks_written(count)
+    }
+
+    fn decode_errors(&self, count: u64) {
+        (**self).decode_errors(count)
+    }
+
+    fn evictions(&self, count: u64) {
+        (**self).evictions(count)
+    }
+}
+
+/// `Metrics` ignoring every count, the default of `Reader`s and `Writer`s.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoopMetrics;
+
+impl Metrics for NoopMetrics {}
+
+/// `Metrics` summing the counts in memory and rendering them as counters in the text exposition
+/// format of Prometheus, to be served on a `/metrics` endpoint.
+///
+/// A single `PrometheusMetrics`, in an `Arc`, can be shared by all the readers and writers of a
+/// process.
+#[derive(Debug, Default)]
+pub struct PrometheusMetrics {
+    records_read: AtomicU64,
+    records_written: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    blocks_read: AtomicU64,
+    blocks_written: AtomicU64,
+    decode_errors: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl PrometheusMetrics {
+    /// Create a `PrometheusMetrics` with every count at zero.
+    pub fn new() -> PrometheusMetrics {
+        PrometheusMetrics::default()
+    }
+
+    /// Counters of the counts, as `(name, help, value)`, named as Prometheus counters.
+    pub fn counters(&self) -> Vec<(&'static str, &'static str, u64)> {
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        vec![
+            ("avro_records_read_total", "Records decoded.", load(&self.records_read)),
+            ("avro_records_written_total", "Records appended.", load(&self.records_written)),
+            ("avro_bytes_read_total", "Bytes of data blocks read.", load(&self.bytes_read)),
+            (
+                "avro_bytes_written_total",
+                "Bytes of data blocks written.",
+                load(&self.bytes_written),
+            ),
+            ("avro_blocks_read_total", "Data blocks read.", load(&self.blocks_read)),
+            ("avro_blocks_written_total", "Data blocks written.", load(&self.blocks_written)),
+            (
+                "avro_decode_errors_total",
+                "Records or blocks which failed to be decoded.",
+                load(&self.decode_errors),
+            ),
+            ("avro_evictions_total", "Evicted entries of lru_sets.", load(&self.evictions)),
+        ]
+    }
+
+    /// The counters in the text exposition format of Prometheus.
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        for (name, help, value) in self.counters() {
+            // writing to a String does not fail
+            let _ = writeln!(text, "# HELP {} {}", name, help);
+            let _ = writeln!(text, "# TYPE {} counter", name);
+            let _ = writeln!(text, "{} {}", name, value);
+        }
+        text
+    }
+}
+
+impl Metrics for PrometheusMetrics {
+    fn records_read(&self, count: u64) {
+        self.records_read.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn records_written(&self, count: u64) {
+        self.records_written.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn bytes_read(&self, count: u64) {
+        self.bytes_read.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn bytes_written(&self, count: u64) {
+        self.bytes_written.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn blocks_read(&self, count: u64) {
+        self.blocks_read.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn blocks_written(&self, count: u64) {
+        self.blocks_written.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn decode_errors(&self, count: u64) {
+        self.decode_errors.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn evictions(&self, count: u64) {
+        self.evictions.fetch_add(count, Ordering::Relaxed);
+    }
+}
+
+/// `Metrics` held by a `Reader` or a `Writer`, `NoopMetrics` unless set.
+#[derive(Clone)]
+pub(crate) struct SharedMetrics(pub(crate) Arc<dyn Metrics>);
+
+impl Default for SharedMetrics {
+    fn default() -> SharedMetrics {
+        SharedMetrics(Arc::new(NoopMetrics))
+    }
+}
+
+impl fmt::Debug for SharedMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SharedMetrics")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::Reader;
+    use crate::schema::Schema;
+    use crate::types::ToAvro;
+    use crate::writer::Writer;
+
+    #[test]
+    fn test_prometheus_metrics() {
+        let schema = Schema::parse_str(r#"{"type": "array", "items": "long"}"#).unwrap();
+        let metrics = Arc::new(PrometheusMetrics::new());
+        let mut writer = Writer::new(&schema, Vec::new());
+        writer.set_metrics(metrics.clone());
+        writer.append(vec![1i64, 2].avro()).unwrap();
+        writer.append(vec![3i64].avro()).unwrap();
+        writer.flush().unwrap();
+        let data = writer.into_inner();
+
+        let mut reader = Reader::new(&data[..]).unwrap();
+        reader.set_metrics(metrics.clone());
+        assert_eq!(reader.count(), 2);
+        let mut reader = Reader::new(&data[..data.len() - 20]).unwrap();
+        reader.set_metrics(metrics.clone());
+        assert!(reader.last().unwrap().is_err());
+
+        let counters = metrics.counters();
+        let count = |name| counters.iter().find(|counter| counter.0 == name).unwrap().2;
+        assert_eq!(count("avro_records_written_total"), 2);
+        assert_eq!(count("avro_blocks_written_total"), 1);
+        assert_eq!(count("avro_records_read_total"), 2);
+        assert_eq!(count("avro_blocks_read_total"), 1);
+        assert_eq!(count("avro_bytes_read_total"), count("avro_bytes_written_total"));
+        assert_eq!(count("avro_decode_errors_total"), 1);
+        assert!(metrics.render().contains(
+            "# TYPE avro_records_read_total counter\navro_records_read_total 2\n"
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_metrics_par_iter() {
+        use crate::lru::LruMap;
+        use crate::schema::LruLimit;
+        use crate::types::{LruValue, Value};
+
+        let schema = Schema::parse_str(r#"{"type": "lru_set", "max_entries": 10}"#).unwrap();
+        let reader_schema = Schema::parse_str(r#"{"type": "lru_set", "max_entries": 1}"#).unwrap();
+        let mut writer = Writer::new(&schema, Vec::new());
+        for i in 0..6 {
+            let mut items = LruMap::new();
+            for key in &["a", "b", "c"] {
+                items.insert((*key).to_owned(), LruValue::new(i, 1));
+            }
+            writer.append(Value::LruSet(Box::new(items), LruLimit::Count(10), None)).unwrap();
+            if i % 2 == 1 {
+                writer.flush().unwrap();
+            }
+        }
+        let data = writer.into_inner();
+
+        let metrics = Arc::new(PrometheusMetrics::new());
+        let mut reader = Reader::with_schema(&reader_schema, &data[..]).unwrap();
+        reader.set_metrics(metrics.clone());
+        // the first record is read before the others are decoded in parallel
+        assert!(reader.next().unwrap().is_ok());
+        assert_eq!(reader.into_par_iter().count(), 5);
+        let mut reader = Reader::new(&data[..data.len() - 20]).unwrap();
+        reader.set_metrics(metrics.clone());
+        assert!(reader.into_par_iter().last().unwrap().is_err());
+
+        let counters = metrics.counters();
+        let count = |name| counters.iter().find(|counter| counter.0 == name).unwrap().2;
+        assert_eq!(count("avro_records_read_total"), 6 + 4);
+        assert_eq!(count("avro_blocks_read_total"), 3 + 2);
+        assert_eq!(count("avro_decode_errors_total"), 1);
+        assert_eq!(count("avro_evictions_total"), 6 * 2);
+    }
+}
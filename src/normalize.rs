@@ -0,0 +1,206 @@
+//! Logic for the canonical form of Avro values.
+use std::collections::HashSet;
+use std::convert::TryFrom;
+
+use crate::error::{at, AvroError};
+use crate::schema::{Schema, SchemaKind};
+use crate::types::{SetItem, ToAvro, Value, ValueSetting};
+use crate::visit::PathSegment;
+
+impl Value {
+    /// Bring the value to the canonical form of the given [Schema](../schema/enum.Schema.html),
+    /// the form values decoded with the schema take, so that values built by different producers
+    /// compare and hash equal when they hold the same data:
+    ///
+    /// - `Union` and `Optional` wrappers are collapsed, then the values of unions are wrapped in
+    ///   exactly one `Union`, and the values of optionals in one `Optional`;
+    /// - numbers are promoted to the exact type of their schema, following the promotions of the
+    ///   Avro specification: ints to longs, floats or doubles, longs to floats or doubles, and
+    ///   floats to doubles;
+    /// - enum symbols given as strings, or with a stale position, get their position in the
+    ///   schema;
+    /// - record fields are put in schema order;
+    /// - arrays given for sets are collected into sets, whose items are unordered.
+    ///
+    /// Contrary to [`resolve`](#method.resolve), no default is filled in, no field is left out
+    /// and stateful values are not evicted: values not matching the schema fail the
+    /// normalization.
+    pub fn normalize(self, schema: &Schema) -> Result<Value, AvroError> {
+        normalize(self, schema)
+    }
+}
+
+/// Collapse the `Union` and `Optional` wrappers around a value, an absent optional being `Null`.
+fn unwrap(mut value: Value) -> Value {
+    loop {
+        value = match value {
+            Value::Union(inner, _) | Value::Optional(Some(inner), _) => *inner,
+            Value::Optional(None, _) => return Value::Null,
+            value => return value,
+        }
+    }
+}
+
+fn mismatch(schema: &Schema, value: &Value) -> AvroError {
+    AvroError::Resolution(format!(
+        "{:?} expected, got {:?}",
+        SchemaKind::from(schema),
+        value
+    ))
+}
+
+fn normalize(value: Value, schema: &Schema) -> Result<Value, AvroError> {
+    match (unwrap(value), schema) {
+        (value, Schema::Union(union)) => {
+            let value = match union.find_schema(&value) {
+                Some((_, branch)) => normalize(value, branch)?,
+                // values of another kind take the first branch they are promoted to
+                None => union
+                    .variants()
+                    .iter()
+                    .find_map(|branch| normalize(value.clone(), branch).ok())
+                    .ok_or_else(|| mismatch(schema, &value))?,
+            };
+            Ok(Value::Union(Box::new(value), None))
+        },
+        (Value::Null, Schema::Optional(_)) => Ok(Value::Optional(None, None)),
+        (value, Schema::Optional(inner)) => {
+            Ok(Value::Optional(Some(Box::new(normalize(value, inner)?)), None))
+        },
+
+        (Value::Int(n, setting), Schema::Long) => Ok(Value::Long(i64::from(n), setting)),
+        (Value::Int(n, setting), Schema::Float) => Ok(Value::Float(n as f32, setting)),
+        (Value::Int(n, setting), Schema::Double) => Ok(Value::Double(f64::from(n), setting)),
+        (Value::Long(n, setting), Schema::Float) => Ok(Value::Float(n as f32, setting)),
+        (Value::Long(n, setting), Schema::Double) => Ok(Value::Double(n as f64, setting)),
+        (Value::Float(x, setting), Schema::Double) => Ok(Value::Double(f64::from(x), setting)),
+
+        (Value::Enum(_, symbol, setting), Schema::Enum { symbols, .. })
+        | (Value::String(symbol, setting), Schema::Enum { symbols, .. }) => {
+            match symbols.iter().position(|s| *s == symbol) {
+                Some(index) => Ok(Value::Enum(index as i32, symbol, setting)),
+                None => Err(AvroError::Resolution(format!(
+                    "Enum symbol {} not found in {:?}",
+                    symbol, symbols
+                ))),
+            }
+        },
+
+        (Value::Array(items, setting), Schema::Array(inner))
+        | (Value::Array(items, setting), Schema::BoundedList { items: inner, .. }) => items
+            .into_iter()
+            .enumerate()
+            .map(|(i, item)| at(normalize(item, inner), PathSegment::Index(i)))
+            .collect::<Result<_, _>>()
+            .map(|items| Value::Array(items, setting)),
+        (Value::Map(items, setting), Schema::Map(inner)) => items
+            .into_iter()
+            .map(|(key, item)| {
+                let item = at(normalize(item, inner), PathSegment::Key(&key))?;
+                Ok((key, item))
+            })
+            .collect::<Result<_, AvroError>>()
+            .map(|items| Value::Map(items, setting)),
+        (Value::Record(mut items, setting), Schema::Record { fields, .. }) => {
+            let mut normalized = Vec::with_capacity(fields.len());
+            for field in fields {
+                let position = items.iter().position(|(name, _)| **name == *field.name);
+                let (name, item) = match position {
+                    Some(position) => items.swap_remove(position),
+                    None => {
+                        return Err(AvroError::Resolution(format!(
+                            "missing field {} in record",
+                            field.name
+                        )))
+                    },
+                };
+                let item = at(normalize(item, &field.schema), PathSegment::Field(&field.name))?;
+                normalized.push((name, item));
+            }
+            match items.first() {
+                Some((name, _)) => Err(AvroError::Resolution(format!(
+                    "unknown field {} in record",
+                    name
+                ))),
+                None => Ok(Value::Record(normalized, setting)),
+            }
+        },
+        (Value::Set(items, setting), Schema::Set(inner)) => {
+            normalize_set(items.into_iter().map(|item| item.avro()), inner, setting)
+        },
+        (Value::Array(items, setting), Schema::Set(inner)) => {
+            normalize_set(items.into_iter(), inner, setting)
+        },
+
+        (value, schema) if value.validate(schema) => Ok(value),
+        (value, schema) => Err(mismatch(schema, &value)),
+    }
+}
+
+fn normalize_set<I: Iterator<Item = Value>>(
+    items: I,
+    schema: &Schema,
+    setting: Option<ValueSetting>,
+) -> Result<Value, AvroError> {
+    items
+        .map(|item| SetItem::try_from(normalize(item, schema)?))
+        .collect::<Result<HashSet<_>, AvroError>>()
+        .map(|items| Value::Set(items, setting))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_normalize() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "r", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "score", "type": ["null", "double"]},
+                {"name": "note", "type": {"type": "optional", "value": "string"}},
+                {"name": "color", "type": {
+                    "type": "enum", "name": "c", "symbols": ["red", "blue"]
+                }},
+                {"name": "tags", "type": {"type": "set", "items": "long"}},
+                {"name": "counts", "type": {"type": "map", "values": "float"}}
+            ]}"#,
+        ).unwrap();
+        let mut counts = HashMap::new();
+        counts.insert("a".to_owned(), Value::Int(2, None));
+
+        // fields out of order, nested wrappers, narrower numbers and an enum given as a string
+        let value = Value::Record(vec![
+            ("counts".into(), Value::Map(counts, None)),
+            ("tags".into(), Value::Array(vec![Value::Int(3, None), Value::Long(3, None)], None)),
+            ("color".into(), Value::String("blue".to_owned(), None)),
+            ("note".into(), Value::Union(Box::new(Value::Optional(None, None)), None)),
+            ("score".into(), Value::Union(Box::new(Value::Int(1, None).into_union()), None)),
+            ("id".into(), Value::Optional(Some(Box::new(Value::Int(7, None))), None)),
+        ], None);
+        let normalized = value.normalize(&schema).unwrap();
+
+        let mut counts = HashMap::new();
+        counts.insert("a".to_owned(), Value::Float(2.0, None));
+        let expected = Value::Record(vec![
+            ("id".into(), Value::Long(7, None)),
+            ("score".into(), Value::Union(Box::new(Value::Double(1.0, None)), None)),
+            ("note".into(), Value::Optional(None, None)),
+            ("color".into(), Value::Enum(1, "blue".to_owned(), None)),
+            ("tags".into(), Value::Set(vec![SetItem::Long(3)].into_iter().collect(), None)),
+            ("counts".into(), Value::Map(counts, None)),
+        ], None);
+        assert_eq!(normalized, expected);
+        // the canonical form is kept
+        assert_eq!(normalized.clone().normalize(&schema).unwrap(), normalized);
+
+        // no default is filled in, and narrowing fails
+        let value = Value::record(vec![("id", 1i64)]);
+        assert!(value.normalize(&schema).is_err());
+        let error = Value::Array(vec![Value::Double(1.5, None)], None)
+            .normalize(&Schema::Array(Box::new(Schema::Float)))
+            .unwrap_err();
+        assert_eq!(error.context().unwrap().path(), "[0]");
+    }
+}
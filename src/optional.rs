@@ -0,0 +1,200 @@
+//! Logic for the conversion between `optional` schemas and values and standard nullable unions.
+use serde_json::Value as JsonValue;
+
+use crate::error::AvroError;
+use crate::schema::{RecordField, Schema, UnionSchema};
+use crate::types::Value;
+
+/// Nullable union holding `inner`, `null` first unless the default of the field is not `null`:
+/// Avro requires union defaults to match the first branch.
+fn nullable_union(inner: Schema, default: Option<&JsonValue>) -> Result<Schema, AvroError> {
+    let variants = match default {
+        Some(default) if !default.is_null() => vec![inner, Schema::Null],
+        _ => vec![Schema::Null, inner],
+    };
+    UnionSchema::new(variants).map(Schema::Union)
+}
+
+fn convert_fields<F>(fields: &[RecordField], mut convert: F) -> Result<Vec<RecordField>, AvroError>
+where
+    F: FnMut(&RecordField) -> Result<Schema, AvroError>,
+{
+    fields
+        .iter()
+        .map(|field| {
+            Ok(RecordField {
+                schema: convert(field)?,
+                ..field.clone()
+            })
+        })
+        .collect()
+}
+
+impl Schema {
+    /// Replace every `optional` schema nested in this one with a standard `["null", T]` union,
+    /// so that the data it describes can be read by any Avro implementation.
+    ///
+    /// Both schemas have the same binary encoding, except for the optional record fields with a
+    /// non-null default, which become `[T, "null"]` unions as Avro requires the default of a union
+    /// to match its first branch. Values are converted by resolving them against the converted
+    /// schema. Fails if an `optional` holds `null` or a union, which unions cannot nest.
+    pub fn optional_to_union(&self) -> Result<Schema, AvroError> {
+        self.convert_optional(None)
+    }
+
+    fn convert_optional(&self, default: Option<&JsonValue>) -> Result<Schema, AvroError> {
+        Ok(match self {
+            Schema::Optional(inner) => nullable_union(inner.optional_to_union()?, default)?,
+            Schema::Array(items) => Schema::Array(Box::new(items.optional_to_union()?)),
+            Schema::Map(values) => Schema::Map(Box::new(values.optional_to_union()?)),
+            Schema::BoundedList {
+                items,
+                max_len,
+                truncation,
+            } => Schema::BoundedList {
+                items: Box::new(items.optional_to_union()?),
+                max_len: *max_len,
+                truncation: *truncation,
+            },
+            Schema::Union(union) => Schema::Union(UnionSchema::new(
+                union
+                    .variants()
+                    .iter()
+                    .map(Schema::optional_to_union)
+                    .collect::<Result<_, _>>()?,
+            )?),
+            Schema::Record {
+                name,
+                doc,
+                fields,
+                lookup,
+            } => Schema::Record {
+                name: name.clone(),
+                doc: doc.clone(),
+                fields: convert_fields(fields, |field| {
+                    field.schema.convert_optional(field.default.as_ref())
+                })?,
+                lookup: lookup.clone(),
+            },
+            other => other.clone(),
+        })
+    }
+
+    /// Replace every union of `null` and a single other type nested in this schema with an
+    /// `optional` schema, in either order of the branches. The reverse of
+    /// [`optional_to_union`](#method.optional_to_union).
+    ///
+    /// Field defaults are kept: `null` means absent, and any other default is the value of the
+    /// `optional`. Values are converted by resolving them against the converted schema.
+    pub fn union_to_optional(&self) -> Result<Schema, AvroError> {
+        Ok(match self {
+            Schema::Union(union) => match union.variants() {
+                [Schema::Null, inner] | [inner, Schema::Null] => {
+                    Schema::Optional(Box::new(inner.union_to_optional()?))
+                },
+                variants => Schema::Union(UnionSchema::new(
+                    variants
+                        .iter()
+                        .map(Schema::union_to_optional)
+                        .collect::<Result<_, _>>()?,
+                )?),
+            },
+            Schema::Optional(inner) => Schema::Optional(Box::new(inner.union_to_optional()?)),
+            Schema::Array(items) => Schema::Array(Box::new(items.union_to_optional()?)),
+            Schema::Map(values) => Schema::Map(Box::new(values.union_to_optional()?)),
+            Schema::BoundedList {
+                items,
+                max_len,
+                truncation,
+            } => Schema::BoundedList {
+                items: Box::new(items.union_to_optional()?),
+                max_len: *max_len,
+                truncation: *truncation,
+            },
+            Schema::Record {
+                name,
+                doc,
+                fields,
+                lookup,
+            } => Schema::Record {
+                name: name.clone(),
+                doc: doc.clone(),
+                fields: convert_fields(fields, |field| field.schema.union_to_optional())?,
+                lookup: lookup.clone(),
+            },
+            other => other.clone(),
+        })
+    }
+}
+
+impl Value {
+    /// Convert an `Optional` value into the equivalent value of a nullable union: `Null` if
+    /// absent. The reverse of [`into_optional`](#method.into_optional); other values are returned
+    /// as is.
+    pub fn into_union(self) -> Value {
+        match self {
+            Value::Optional(Some(inner), setting) => Value::Union(inner, setting),
+            Value::Optional(None, setting) => Value::Union(Box::new(Value::Null), setting),
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ToAvro;
+
+    #[test]
+    fn test_optional_schema_to_union() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": {"type": "optional", "value": "long"}},
+                    {"name": "b", "type": {"type": "optional", "value": "long"}, "default": 5},
+                    {"name": "c", "type": {"type": "array", "items": {
+                        "type": "optional", "value": "string"
+                    }}}
+                ]
+            }
+        "#,
+        ).unwrap();
+        let union = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": ["null", "long"]},
+                    {"name": "b", "type": ["long", "null"], "default": 5},
+                    {"name": "c", "type": {"type": "array", "items": ["null", "string"]}}
+                ]
+            }
+        "#,
+        ).unwrap();
+
+        assert_eq!(schema.optional_to_union().unwrap(), union);
+        assert_eq!(union.union_to_optional().unwrap(), schema);
+
+        let nested = Schema::Optional(Box::new(Schema::Optional(Box::new(Schema::Long))));
+        assert!(nested.optional_to_union().is_err());
+    }
+
+    #[test]
+    fn test_optional_value_to_union() {
+        let schema = Schema::Optional(Box::new(Schema::Long));
+        let union = schema.optional_to_union().unwrap();
+
+        let some = Value::Optional(Some(Box::new(1i64.avro())), None);
+        let none = Value::Optional(None, None);
+        assert_eq!(some.clone().into_union(), Value::Union(Box::new(1i64.avro()), None));
+        // resolution against a union yields the value of the matching branch
+        assert_eq!(none.clone().resolve(&union).unwrap(), Value::Null);
+        assert_eq!(some.clone().resolve(&union).unwrap(), 1i64.avro());
+        assert_eq!(some.clone().resolve(&union).unwrap().resolve(&schema).unwrap(), some);
+        assert_eq!(none.clone().into_union().into_optional(), none);
+    }
+}
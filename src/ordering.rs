@@ -0,0 +1,409 @@
+//! Logic for the schema-aware ordering and hashing of Avro values.
+//!
+//! More information about the ordering of Avro values can be found in the
+//! [Avro specification](https://avro.apache.org/docs/current/spec.html#order)
+use std::cmp::Ordering;
+use std::hash::Hasher;
+use std::sync::Arc;
+
+use crate::schema::{RecordFieldOrder, Schema, SchemaKind};
+use crate::types::{SetItem, Value};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// 64-bit FNV-1a hasher.
+///
+/// Contrary to `std::collections::hash_map::DefaultHasher`, its output is guaranteed to be the
+/// same across processes, platforms and Rust versions, which makes it suitable for persisted
+/// hashes.
+pub(crate) struct FnvHasher(u64);
+
+impl Default for FnvHasher {
+    fn default() -> FnvHasher {
+        FnvHasher(FNV_OFFSET_BASIS)
+    }
+}
+
+impl Hasher for FnvHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    // the default implementations use the native endianness
+    fn write_u64(&mut self, n: u64) {
+        self.write(&n.to_le_bytes());
+    }
+
+    fn write_i64(&mut self, n: i64) {
+        self.write(&n.to_le_bytes());
+    }
+}
+
+/// Unwrap a `Union` value and find the position of the branch it belongs to.
+fn union_branch<'a>(value: &'a Value, schema: &'a Schema) -> Option<(usize, &'a Value, &'a Schema)> {
+    let inner = match value {
+        Value::Union(inner, _) => inner,
+        other => other,
+    };
+    match schema {
+        Schema::Union(union) => union
+            .find_schema(inner)
+            .map(|(index, branch)| (index, inner, branch)),
+        _ => None,
+    }
+}
+
+fn as_i64(value: &Value) -> Option<i64> {
+    match *value {
+        Value::Int(n, _) => Some(i64::from(n)),
+        Value::Long(n, _) | Value::Date(n, _) | Value::Counter(n, _) => Some(n),
+        _ => None,
+    }
+}
+
+fn as_f64(value: &Value) -> Option<f64> {
+    match *value {
+        Value::Int(n, _) => Some(f64::from(n)),
+        Value::Long(n, _) => Some(n as f64),
+        Value::Float(x, _) => Some(f64::from(x)),
+        Value::Double(x, _) => Some(x),
+        _ => None,
+    }
+}
+
+fn as_bytes(value: &Value) -> Option<&[u8]> {
+    match value {
+        Value::Bytes(bytes, _) | Value::Fixed(_, bytes, _) => Some(bytes),
+        Value::String(s, _) => Some(s.as_bytes()),
+        _ => None,
+    }
+}
+
+fn enum_position(value: &Value, symbols: &[String]) -> Option<usize> {
+    match value {
+        Value::Enum(_, symbol, _) | Value::String(symbol, _) => {
+            symbols.iter().position(|s| s == symbol)
+        },
+        _ => None,
+    }
+}
+
+/// Record field values are stored positionally; fall back to a lookup by name if the value does
+/// not follow the schema order.
+fn record_field<'a>(fields: &'a [(Arc<str>, Value)], name: &str, position: usize) -> Option<&'a Value> {
+    match fields.get(position) {
+        Some((field, value)) if **field == *name => Some(value),
+        _ => fields.iter().find(|(field, _)| **field == *name).map(|(_, value)| value),
+    }
+}
+
+fn sorted_items<'a, T: Ord + 'a, I: Iterator<Item = &'a T>>(items: I) -> Vec<&'a T> {
+    let mut items = items.collect::<Vec<_>>();
+    items.sort();
+    items
+}
+
+impl Value {
+    /// Compare two values according to the Avro sort order of the given
+    /// [Schema](../schema/enum.Schema.html).
+    ///
+    /// Record fields are compared in schema order, honouring their `order` attribute
+    /// (`descending` fields are reversed, `ignore` fields are skipped). Union values are ordered
+    /// by branch position first, then by value; `Optional` values put absent values first.
+    /// `Set` values are compared as sorted arrays of strings.
+    ///
+    /// **NOTE** Maps (and `LruSet`, `TopK`, `Hll` and `Bloom` values) cannot be compared according
+    /// to the specification and are always considered equal. Values which do not match the
+    /// schema are ordered by kind.
+    pub fn cmp_with_schema(&self, other: &Value, schema: &Schema) -> Ordering {
+        match schema {
+            Schema::Null
+            | Schema::Map(_)
+            | Schema::LruSet(..)
+            | Schema::Hll(_)
+            | Schema::Bloom { .. }
+            | Schema::TopK(_) => Ordering::Equal,
+            Schema::Boolean => match (self, other) {
+                (Value::Boolean(a, _), Value::Boolean(b, _)) => a.cmp(b),
+                _ => self.cmp_kind(other),
+            },
+            Schema::Int | Schema::Long | Schema::Date(_) | Schema::Counter => {
+                match (as_i64(self), as_i64(other)) {
+                    (Some(a), Some(b)) => a.cmp(&b),
+                    _ => self.cmp_kind(other),
+                }
+            },
+            Schema::Float | Schema::Double => match (as_f64(self), as_f64(other)) {
+                (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or_else(|| a.is_nan().cmp(&b.is_nan())),
+                _ => self.cmp_kind(other),
+            },
+            Schema::Bytes | Schema::String | Schema::Fixed { .. } => {
+                match (as_bytes(self), as_bytes(other)) {
+                    (Some(a), Some(b)) => a.cmp(b),
+                    _ => self.cmp_kind(other),
+                }
+            },
+            Schema::Enum { symbols, .. } => {
+                match (enum_position(self, symbols), enum_position(other, symbols)) {
+                    (Some(a), Some(b)) => a.cmp(&b),
+                    _ => self.cmp_kind(other),
+                }
+            },
+            Schema::Union(_) => match (union_branch(self, schema), union_branch(other, schema)) {
+                (Some((i, a, branch)), Some((j, b, _))) => {
+                    i.cmp(&j).then_with(|| a.cmp_with_schema(b, branch))
+                },
+                _ => self.cmp_kind(other),
+            },
+            Schema::Array(inner) | Schema::BoundedList { items: inner, .. } => match (self, other) {
+                (Value::Array(a, _), Value::Array(b, _)) => a
+                    .iter()
+                    .zip(b.iter())
+                    .map(|(x, y)| x.cmp_with_schema(y, inner))
+                    .find(|ordering| *ordering != Ordering::Equal)
+                    .unwrap_or_else(|| a.len().cmp(&b.len())),
+                _ => self.cmp_kind(other),
+            },
+            Schema::Set(_) => match (self, other) {
+                (Value::Set(a, _), Value::Set(b, _)) => {
+                    sorted_items(a.iter()).cmp(&sorted_items(b.iter()))
+                },
+                _ => self.cmp_kind(other),
+            },
+            Schema::Optional(inner) => match (self, other) {
+                (Value::Optional(a, _), Value::Optional(b, _)) => match (a, b) {
+                    (Some(a), Some(b)) => a.cmp_with_schema(b, inner),
+                    (a, b) => a.is_some().cmp(&b.is_some()),
+                },
+                _ => self.cmp_kind(other),
+            },
+            Schema::Record { fields, .. } => match (self, other) {
+                (Value::Record(a, _), Value::Record(b, _)) => fields
+                    .iter()
+                    .filter(|field| field.order != RecordFieldOrder::Ignore)
+                    .map(|field| {
+                        let ordering = match (
+                            record_field(a, &field.name, field.position),
+                            record_field(b, &field.name, field.position),
+                        ) {
+                            (Some(x), Some(y)) => x.cmp_with_schema(y, &field.schema),
+                            (x, y) => x.is_some().cmp(&y.is_some()),
+                        };
+                        match field.order {
+                            RecordFieldOrder::Descending => ordering.reverse(),
+                            _ => ordering,
+                        }
+                    })
+                    .find(|ordering| *ordering != Ordering::Equal)
+                    .unwrap_or(Ordering::Equal),
+                _ => self.cmp_kind(other),
+            },
+        }
+    }
+
+    /// Hash the value according to the given [Schema](../schema/enum.Schema.html).
+    ///
+    /// The hash is consistent with [`cmp_with_schema`](#method.cmp_with_schema): values comparing
+    /// as equal have the same hash. It is also stable, meaning the same value always produces the
+    /// same hash, regardless of the process, platform or version of Rust it is computed with.
+    pub fn hash_with_schema(&self, schema: &Schema) -> u64 {
+        let mut hasher = FnvHasher::default();
+        self.hash_into(schema, &mut hasher);
+        hasher.finish()
+    }
+
+    fn hash_into<H: Hasher>(&self, schema: &Schema, state: &mut H) {
+        match schema {
+            Schema::Null
+            | Schema::Map(_)
+            | Schema::LruSet(..)
+            | Schema::Hll(_)
+            | Schema::Bloom { .. }
+            | Schema::TopK(_) => (),
+            Schema::Boolean => {
+                if let Value::Boolean(b, _) = self {
+                    state.write_u8(*b as u8);
+                }
+            },
+            Schema::Int | Schema::Long | Schema::Date(_) | Schema::Counter => {
+                if let Some(n) = as_i64(self) {
+                    state.write_i64(n);
+                }
+            },
+            Schema::Float | Schema::Double => {
+                if let Some(x) = as_f64(self) {
+                    // `0.0` and `-0.0` compare as equal and must hash the same.
+                    let x = if x == 0.0 { 0.0 } else { x };
+                    state.write_u64(x.to_bits());
+                }
+            },
+            Schema::Bytes | Schema::String | Schema::Fixed { .. } => {
+                if let Some(bytes) = as_bytes(self) {
+                    state.write_u64(bytes.len() as u64);
+                    state.write(bytes);
+                }
+            },
+            Schema::Enum { symbols, .. } => {
+                if let Some(position) = enum_position(self, symbols) {
+                    state.write_u64(position as u64);
+                }
+            },
+            Schema::Union(_) => {
+                if let Some((index, inner, branch)) = union_branch(self, schema) {
+                    state.write_u64(index as u64);
+                    inner.hash_into(branch, state);
+                }
+            },
+            Schema::Array(inner) | Schema::BoundedList { items: inner, .. } => {
+                if let Value::Array(items, _) = self {
+                    state.write_u64(items.len() as u64);
+                    for item in items {
+                        item.hash_into(inner, state);
+                    }
+                }
+            },
+            Schema::Set(_) => {
+                if let Value::Set(items, _) = self {
+                    state.write_u64(items.len() as u64);
+                    for item in sorted_items(items.iter()) {
+                        match item {
+                            SetItem::Int(n) => state.write_i64(i64::from(*n)),
+                            SetItem::Long(n) => state.write_i64(*n),
+                            SetItem::Bytes(bytes) => state.write(bytes),
+                            SetItem::String(s) => state.write(s.as_bytes()),
+                            SetItem::Enum(i, _) => state.write_i64(i64::from(*i)),
+                        }
+                        state.write_u8(0xff);
+                    }
+                }
+            },
+            Schema::Optional(inner) => {
+                if let Value::Optional(value, _) = self {
+                    state.write_u8(value.is_some() as u8);
+                    if let Some(value) = value {
+                        value.hash_into(inner, state);
+                    }
+                }
+            },
+            Schema::Record { fields, .. } => {
+                if let Value::Record(values, _) = self {
+                    for field in fields {
+                        if field.order == RecordFieldOrder::Ignore {
+                            continue
+                        }
+                        if let Some(value) = record_field(values, &field.name, field.position) {
+                            value.hash_into(&field.schema, state);
+                        }
+                    }
+                }
+            },
+        }
+    }
+
+    fn cmp_kind(&self, other: &Value) -> Ordering {
+        (SchemaKind::from(self) as u8).cmp(&(SchemaKind::from(other) as u8))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ToAvro;
+
+    static SCHEMA: &'static str = r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": "long", "order": "descending"},
+                    {"name": "b", "type": "string"},
+                    {"name": "c", "type": "double", "order": "ignore"}
+                ]
+            }
+        "#;
+
+    fn record(a: i64, b: &str, c: f64) -> Value {
+        Value::record(vec![("a", a.avro()), ("b", b.avro()), ("c", c.avro())])
+    }
+
+    #[test]
+    fn test_cmp_primitives() {
+        assert_eq!(
+            1i32.avro().cmp_with_schema(&2i64.avro(), &Schema::Long),
+            Ordering::Less
+        );
+        assert_eq!(
+            "b".avro().cmp_with_schema(&"a".avro(), &Schema::String),
+            Ordering::Greater
+        );
+        assert_eq!(Value::Null.cmp_with_schema(&Value::Null, &Schema::Null), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_cmp_union_and_enum() {
+        let union = Schema::parse_str(r#"["null", "long"]"#).unwrap();
+        assert_eq!(
+            None::<i64>.avro().cmp_with_schema(&Some(0i64).avro(), &union),
+            Ordering::Less
+        );
+
+        let suit = Schema::parse_str(
+            r#"{"type": "enum", "name": "suit", "symbols": ["spades", "hearts"]}"#,
+        ).unwrap();
+        assert_eq!(
+            Value::Enum(1, "hearts".to_owned(), None)
+                .cmp_with_schema(&"spades".avro(), &suit),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn test_cmp_record_field_order() {
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+
+        assert_eq!(
+            record(1, "x", 0.0).cmp_with_schema(&record(2, "a", 0.0), &schema),
+            Ordering::Greater
+        );
+        assert_eq!(
+            record(1, "a", 0.0).cmp_with_schema(&record(1, "b", 0.0), &schema),
+            Ordering::Less
+        );
+        assert_eq!(
+            record(1, "a", 0.0).cmp_with_schema(&record(1, "a", 5.0), &schema),
+            Ordering::Equal
+        );
+
+        let mut records = vec![record(1, "a", 0.0), record(3, "a", 0.0), record(2, "a", 0.0)];
+        records.sort_by(|x, y| x.cmp_with_schema(y, &schema));
+        assert_eq!(records[0].field("a"), Some(&Value::Long(3, None)));
+    }
+
+    #[test]
+    fn test_hash_with_schema() {
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+
+        assert_eq!(
+            record(1, "a", 0.0).hash_with_schema(&schema),
+            record(1, "a", 5.0).hash_with_schema(&schema)
+        );
+        assert_ne!(
+            record(1, "a", 0.0).hash_with_schema(&schema),
+            record(1, "b", 0.0).hash_with_schema(&schema)
+        );
+        assert_eq!(
+            1i32.avro().hash_with_schema(&Schema::Long),
+            1i64.avro().hash_with_schema(&Schema::Long)
+        );
+        // the hash must not change between runs or releases
+        assert_eq!("foo".avro().hash_with_schema(&Schema::String), 0x8731_bbd2_3194_e5b6);
+    }
+}
@@ -0,0 +1,163 @@
+//! Logic for archiving Avro files as Parquet files and back, enabled by the `parquet` feature.
+//!
+//! Values go through Arrow record batches, so Avro types map to Parquet types through their
+//! [Arrow counterparts](../arrow/index.html): `date` values are stored as `TIMESTAMP(MILLIS)`,
+//! `set` and `array` values as `LIST`, `map` and `lru_set` values as `MAP` (the access time and
+//! count of lru entries in a group), enum symbols as `STRING`, and `optional` values and unions
+//! of `null` and another type as optional columns.
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use ::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use ::parquet::arrow::ArrowWriter;
+use ::parquet::file::metadata::KeyValue;
+use ::parquet::file::properties::WriterProperties;
+
+use crate::arrow::{from_record_batch, to_record_batch};
+use crate::error::AvroError;
+use crate::reader::Reader;
+use crate::writer::Writer;
+
+/// Key of the Parquet metadata holding the Avro schema of the values.
+pub const AVRO_SCHEMA_KEY: &str = "avro.schema";
+
+/// Options driving how Parquet files are written by [`to_parquet`](fn.to_parquet.html) and read
+/// by [`from_parquet`](fn.from_parquet.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParquetOptions {
+    /// Number of values converted at once, into a single record batch.
+    pub batch_size: usize,
+    /// Maximum number of rows of a row group.
+    pub row_group_size: usize,
+}
+
+impl Default for ParquetOptions {
+    fn default() -> ParquetOptions {
+        ParquetOptions {
+            batch_size: 1024,
+            row_group_size: 1024 * 1024,
+        }
+    }
+}
+
+/// Write the values following the current position of `reader` (the whole file for a new
+/// `Reader`) to a Parquet file at `path`, one column per field of the reader `Schema` (the writer
+/// one if not set), which must be a record. The Avro schema is kept in the metadata of the file,
+/// under [`AVRO_SCHEMA_KEY`](constant.AVRO_SCHEMA_KEY.html).
+///
+/// Return the number of values written.
+pub fn to_parquet<R, P>(
+    mut reader: Reader<R>,
+    path: P,
+    options: &ParquetOptions,
+) -> Result<usize, AvroError>
+where
+    R: Read,
+    P: AsRef<Path>,
+{
+    let schema = reader.reader_schema().unwrap_or_else(|| reader.writer_schema()).clone();
+    let properties = WriterProperties::builder()
+        .set_max_row_group_size(options.row_group_size.max(1))
+        .set_key_value_metadata(Some(vec![KeyValue::new(
+            AVRO_SCHEMA_KEY.to_owned(),
+            serde_json::to_string(&schema)?,
+        )]))
+        .build();
+    let arrow_schema = crate::arrow::to_arrow_schema(&schema)?;
+    let file = File::create(path)?;
+    let mut writer = ArrowWriter::try_new(file, arrow_schema.into(), Some(properties))?;
+
+    let mut count = 0;
+    let mut values = Vec::with_capacity(options.batch_size);
+    loop {
+        let next = reader.next().transpose()?;
+        let done = next.is_none();
+        values.extend(next);
+        if values.len() >= options.batch_size.max(1) || (done && !values.is_empty()) {
+            count += values.len();
+            writer.write(&to_record_batch(&schema, values.drain(..))?)?;
+        }
+        if done {
+            break
+        }
+    }
+    writer.close()?;
+    Ok(count)
+}
+
+/// Append the rows of the Parquet file at `path` to `writer`, reading each field of the
+/// `Schema` of the writer, which must be a record, from the column of the same name. The writer
+/// is flushed once every row is appended.
+///
+/// Return the number of values appended.
+pub fn from_parquet<P, W>(
+    path: P,
+    writer: &mut Writer<W>,
+    options: &ParquetOptions,
+) -> Result<usize, AvroError>
+where
+    P: AsRef<Path>,
+    W: Write,
+{
+    let batches = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?
+        .with_batch_size(options.batch_size.max(1))
+        .build()?;
+
+    let mut count = 0;
+    for batch in batches {
+        for value in from_record_batch(writer.schema(), &batch?)? {
+            writer.append_ref(&value)?;
+            count += 1;
+        }
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::Schema;
+    use crate::types::{ToAvro, Value};
+
+    #[test]
+    fn test_parquet_round_trip() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "r", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "at", "type": "date"},
+                {"name": "tags", "type": {"type": "set", "items": "string"}},
+                {"name": "note", "type": ["null", "string"]}
+            ]}"#,
+        ).unwrap();
+        let values = (0..10i64)
+            .map(|i| {
+                let note = if i % 2 == 0 { Value::Null } else { format!("n{}", i).avro() };
+                Value::Record(vec![
+                    ("id".into(), Value::Long(i, None)),
+                    ("at".into(), Value::Date(i * 1_000, None)),
+                    ("tags".into(), Value::Set(Default::default(), None)),
+                    ("note".into(), Value::Union(Box::new(note), None)),
+                ], None)
+            })
+            .collect::<Vec<_>>();
+        let mut writer = Writer::new(&schema, Vec::new());
+        writer.extend_from_slice(&values).unwrap();
+        let encoded = writer.into_inner();
+
+        let path = std::env::temp_dir().join(format!("avro-rs-{}.parquet", std::process::id()));
+        let options = ParquetOptions {
+            batch_size: 3,
+            row_group_size: 4,
+        };
+        assert_eq!(to_parquet(Reader::new(&encoded[..]).unwrap(), &path, &options).unwrap(), 10);
+
+        let mut writer = Writer::new(&schema, Vec::new());
+        assert_eq!(from_parquet(&path, &mut writer, &options).unwrap(), 10);
+        std::fs::remove_file(&path).unwrap();
+        let written = writer.into_inner();
+        let read = Reader::new(&written[..]).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(read, values);
+    }
+}
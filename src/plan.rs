@@ -0,0 +1,568 @@
+//! Logic for the resolution of values decoded with a writer schema against a reader schema,
+//! planned once per pair of schemas.
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Arc;
+
+use crate::decode::{decode, decode_key, decode_len, skip, utf8_policy};
+use crate::error::{at, AvroError};
+use crate::schema::{RecordField, Schema, Truncation};
+use crate::types::{
+    field_default, record_setting, zero_value, ResolveContext, ResolveOptions,
+    UnknownFieldPolicy, Value, ValueSetting,
+};
+use crate::util::{item_at, reserved_items, zag_i64, EntriesBudget};
+use crate::visit::PathSegment;
+
+/// Step of a `ResolutionPlan`, decoding a value with a writer schema into a value resolved against
+/// a reader schema.
+#[derive(Clone, Debug)]
+enum Step {
+    /// Decode with the writer schema, then resolve against the reader schema.
+    Resolve {
+        writer: Box<Schema>,
+        reader: Box<Schema>,
+        setting: Option<ValueSetting>,
+    },
+    Record {
+        writer_fields: Vec<WriterField>,
+        reader_fields: Vec<ReaderField>,
+        setting: Option<ValueSetting>,
+    },
+    Array {
+        items: Box<Step>,
+        writer_limit: Option<(usize, Truncation)>,
+        reader_limit: Option<(usize, Truncation)>,
+        setting: Option<ValueSetting>,
+    },
+    Map {
+        values: Box<Step>,
+        setting: Option<ValueSetting>,
+    },
+    /// Step of each branch of a writer union.
+    Union(Vec<Step>),
+    Optional {
+        inner: Box<Step>,
+        setting: Option<ValueSetting>,
+    },
+}
+
+#[derive(Clone, Debug)]
+enum WriterField {
+    /// Field read into the reader field at `position`.
+    Read { position: usize, step: Step },
+    /// Field unknown to the reader schema.
+    Unknown { name: Arc<str>, schema: Schema },
+}
+
+#[derive(Clone, Debug)]
+struct ReaderField {
+    name: Arc<str>,
+    /// Default value, already resolved.
+    default: Option<Value>,
+    /// Schema of the values of the field if it is a map, which may collect unknown fields.
+    map_values: Option<Schema>,
+    setting: Option<ValueSetting>,
+}
+
+/// Plan of the schema resolution of the values written with a writer `Schema` against a reader
+/// `Schema`, compiled once for the pair of schemas and applied to each datum while it is decoded.
+///
+/// Resolving a decoded value with [`Value::resolve`](enum.Value.html#method.resolve) walks both
+/// schemas and looks every record field up by name, for each value. A plan matches record fields
+/// (by name or alias), resolves their defaults and finds their annotations once, then decodes
+/// each datum directly into its resolved value: writer fields unknown to the reader are skipped
+/// without being decoded. Values of other types are resolved as by `Value::resolve`, with the
+/// same results.
+#[derive(Clone, Debug)]
+pub struct ResolutionPlan {
+    step: Step,
+}
+
+impl ResolutionPlan {
+    /// Compile the plan of the resolution of values written with `writer` against `reader`.
+    pub fn compile(writer: &Schema, reader: &Schema) -> ResolutionPlan {
+        ResolutionPlan {
+            step: compile(writer, reader, None),
+        }
+    }
+
+    /// Decode a datum written with the writer `Schema` into a value resolved against the reader
+    /// `Schema`, with the default [ResolveOptions](struct.ResolveOptions.html).
+    pub fn decode<R: Read>(&self, reader: &mut R) -> Result<Value, AvroError> {
+        self.decode_with_options(reader, &ResolveOptions::default())
+    }
+
+    /// Decode a datum written with the writer `Schema` into a value resolved against the reader
+    /// `Schema`, with the given [ResolveOptions](struct.ResolveOptions.html).
+    pub fn decode_with_options<R: Read>(
+        &self,
+        reader: &mut R,
+        options: &ResolveOptions,
+    ) -> Result<Value, AvroError> {
+        self.step.decode(reader, &ResolveContext::new(options))
+    }
+}
+
+fn fallback(writer: &Schema, reader: &Schema, setting: Option<ValueSetting>) -> Step {
+    Step::Resolve {
+        writer: Box::new(writer.clone()),
+        reader: Box::new(reader.clone()),
+        setting,
+    }
+}
+
+fn compile(writer: &Schema, reader: &Schema, setting: Option<ValueSetting>) -> Step {
+    match (writer, reader) {
+        // union values are unwrapped before being resolved, against a union or not
+        (Schema::Union(union), _) => Step::Union(
+            union
+                .variants()
+                .iter()
+                .map(|variant| compile(variant, reader, setting.clone()))
+                .collect(),
+        ),
+        (Schema::Optional(_), Schema::Union(_)) => fallback(writer, reader, setting),
+        // other values resolve against the union branch of their kind
+        (_, Schema::Union(union)) => match union.find_schema(&zero_value(writer)) {
+            Some((_, branch)) => compile(writer, branch, setting),
+            None => fallback(writer, reader, setting),
+        },
+        (
+            Schema::Record {
+                fields: writer_fields,
+                ..
+            },
+            Schema::Record {
+                name,
+                fields: reader_fields,
+                ..
+            },
+        ) => compile_record(writer_fields, reader_fields, record_setting(name).cloned())
+            .unwrap_or_else(|| fallback(writer, reader, setting)),
+        (Schema::Array(writer_items), Schema::Array(reader_items)) => Step::Array {
+            items: Box::new(compile(writer_items, reader_items, setting.clone())),
+            writer_limit: None,
+            reader_limit: None,
+            setting,
+        },
+        (
+            Schema::BoundedList {
+                items: writer_items,
+                max_len,
+                truncation,
+            },
+            Schema::Array(reader_items),
+        ) => Step::Array {
+            items: Box::new(compile(writer_items, reader_items, setting.clone())),
+            writer_limit: Some((*max_len, *truncation)),
+            reader_limit: None,
+            setting,
+        },
+        (
+            Schema::Array(writer_items),
+            Schema::BoundedList {
+                items: reader_items,
+                max_len,
+                truncation,
+            },
+        ) => Step::Array {
+            items: Box::new(compile(writer_items, reader_items, setting.clone())),
+            writer_limit: None,
+            reader_limit: Some((*max_len, *truncation)),
+            setting,
+        },
+        (
+            Schema::BoundedList {
+                items: writer_items,
+                max_len: writer_max_len,
+                truncation: writer_truncation,
+            },
+            Schema::BoundedList {
+                items: reader_items,
+                max_len: reader_max_len,
+                truncation: reader_truncation,
+            },
+        ) => Step::Array {
+            items: Box::new(compile(writer_items, reader_items, setting.clone())),
+            writer_limit: Some((*writer_max_len, *writer_truncation)),
+            reader_limit: Some((*reader_max_len, *reader_truncation)),
+            setting,
+        },
+        (Schema::Map(writer_values), Schema::Map(reader_values)) => Step::Map {
+            values: Box::new(compile(writer_values, reader_values, setting.clone())),
+            setting,
+        },
+        // values of optionals are resolved without the setting of their field
+        (Schema::Optional(writer_inner), Schema::Optional(reader_inner)) => Step::Optional {
+            inner: Box::new(compile(writer_inner, reader_inner, None)),
+            setting,
+        },
+        _ => fallback(writer, reader, setting),
+    }
+}
+
+/// Plan the resolution of records, `None` if a default value of the reader does not resolve.
+fn compile_record(
+    writer_fields: &[RecordField],
+    reader_fields: &[RecordField],
+    setting: Option<ValueSetting>,
+) -> Option<Step> {
+    let options = ResolveOptions::default();
+    let context = ResolveContext::new(&options);
+
+    let mut writer_positions = writer_fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| (&*field.name, i))
+        .collect::<HashMap<_, _>>();
+    let mut matches = vec![None; writer_fields.len()];
+    let mut fields = Vec::with_capacity(reader_fields.len());
+    for (position, field) in reader_fields.iter().enumerate() {
+        let field_setting = ValueSetting::of_field(field);
+        let writer_position = writer_positions.remove(&*field.name).or_else(|| {
+            // writers may still use a former name of the field
+            field
+                .aliases
+                .iter()
+                .flatten()
+                .find_map(|alias| writer_positions.remove(alias.as_str()))
+        });
+        if let Some(i) = writer_position {
+            let step = compile(&writer_fields[i].schema, &field.schema, field_setting.clone());
+            matches[i] = Some(WriterField::Read { position, step });
+        }
+        let default = match field_default(field, field_setting.as_ref()) {
+            Ok(default) => default,
+            Err(_) => return None,
+        };
+        let default = match default {
+            Some(default) => Some(
+                default
+                    .resolve_internal(&field.schema, field_setting.as_ref(), &context)
+                    .ok()?,
+            ),
+            None => None,
+        };
+        fields.push(ReaderField {
+            name: field.name.clone(),
+            default,
+            map_values: match field.schema {
+                Schema::Map(ref values) => Some((**values).clone()),
+                _ => None,
+            },
+            setting: field_setting,
+        });
+    }
+
+    let writer_fields = writer_fields
+        .iter()
+        .zip(matches)
+        .map(|(field, step)| {
+            step.unwrap_or_else(|| WriterField::Unknown {
+                name: field.name.clone(),
+                schema: field.schema.clone(),
+            })
+        })
+        .collect();
+    Some(Step::Record {
+        writer_fields,
+        reader_fields: fields,
+        setting,
+    })
+}
+
+impl Step {
+    fn decode<R: Read>(
+        &self,
+        reader: &mut R,
+        context: &ResolveContext,
+    ) -> Result<Value, AvroError> {
+        match self {
+            Step::Resolve {
+                writer,
+                reader: schema,
+                setting,
+            } => decode(writer, reader)?.resolve_internal(schema, setting.as_ref(), context),
+            Step::Record {
+                writer_fields,
+                reader_fields,
+                setting,
+            } => decode_record(writer_fields, reader_fields, setting, reader, context),
+            Step::Array {
+                items,
+                writer_limit,
+                reader_limit,
+                setting,
+            } => {
+                let mut values = Vec::new();
+                let mut budget = EntriesBudget::default();
+                loop {
+                    let len = decode_len(reader)?;
+                    // arrays are 0-terminated
+                    if len == 0 {
+                        break
+                    }
+                    budget.reserve(len)?;
+                    values.reserve(reserved_items(len));
+                    for _ in 0..len {
+                        let index = values.len();
+                        values.push(at(items.decode(reader, context), PathSegment::Index(index))?);
+                    }
+                }
+                for (max_len, truncation) in writer_limit.iter().chain(reader_limit) {
+                    truncation.apply(&mut values, *max_len);
+                }
+                Ok(Value::Array(values, setting.clone()))
+            },
+            Step::Map { values, setting } => {
+                let mut items = HashMap::new();
+                let mut budget = EntriesBudget::default();
+                loop {
+                    let len = decode_len(reader)?;
+                    // maps are 0-terminated
+                    if len == 0 {
+                        break
+                    }
+                    budget.reserve(len)?;
+                    items.reserve(reserved_items(len));
+                    for _ in 0..len {
+                        let key = decode_key(reader, utf8_policy())?;
+                        let value = at(values.decode(reader, context), PathSegment::Key(&key))?;
+                        items.insert(key, value);
+                    }
+                }
+                Ok(Value::Map(items, setting.clone()))
+            },
+            Step::Union(branches) => match item_at(branches, zag_i64(reader)?) {
+                Some(branch) => branch.decode(reader, context),
+                None => Err(AvroError::Decode("Union index out of bounds".to_owned())),
+            },
+            Step::Optional { inner, setting } => match zag_i64(reader)? {
+                0 => Ok(Value::Optional(None, setting.clone())),
+                1 => {
+                    let value = inner.decode(reader, context)?;
+                    Ok(Value::Optional(Some(Box::new(value)), setting.clone()))
+                },
+                _ => Err(AvroError::Decode("Optional index out of bounds".to_owned())),
+            },
+        }
+    }
+}
+
+fn decode_record<R: Read>(
+    writer_fields: &[WriterField],
+    reader_fields: &[ReaderField],
+    setting: &Option<ValueSetting>,
+    reader: &mut R,
+    context: &ResolveContext,
+) -> Result<Value, AvroError> {
+    let mut values = vec![None; reader_fields.len()];
+    let mut unknown = Vec::new();
+    for field in writer_fields {
+        match field {
+            WriterField::Read { position, step } => {
+                let name = &reader_fields[*position].name;
+                let value = at(step.decode(reader, context), PathSegment::Field(name))?;
+                values[*position] = Some(value);
+            },
+            WriterField::Unknown { name, schema } => match context.unknown_fields {
+                UnknownFieldPolicy::Ignore => skip(schema, reader)?,
+                _ => unknown.push((name, at(decode(schema, reader), PathSegment::Field(name))?)),
+            },
+        }
+    }
+
+    let collect_into = match context.unknown_fields {
+        UnknownFieldPolicy::CollectInto(extras) => Some(extras.as_str()),
+        _ => None,
+    };
+    let mut fields = reader_fields
+        .iter()
+        .zip(values)
+        .map(|(field, value)| {
+            let value = match (value, &field.default) {
+                (Some(value), _) => value,
+                // the field collecting unknown fields may be left out by writers
+                (None, None) if collect_into == Some(&*field.name) => {
+                    Value::Map(HashMap::new(), field.setting.clone())
+                },
+                (None, Some(default)) => default.clone(),
+                (None, None) => {
+                    return Err(AvroError::Resolution(format!(
+                        "missing field {} in record",
+                        field.name
+                    )))
+                },
+            };
+            Ok((field.name.clone(), value))
+        })
+        .collect::<Result<Vec<_>, AvroError>>()?;
+
+    match context.unknown_fields {
+        UnknownFieldPolicy::Ignore => (),
+        UnknownFieldPolicy::Error => {
+            if let Some((name, _)) = unknown.iter().min_by_key(|(name, _)| *name) {
+                return Err(AvroError::Resolution(format!(
+                    "unknown field {} in record",
+                    name
+                )))
+            }
+        },
+        UnknownFieldPolicy::CollectInto(extras) => {
+            let position = reader_fields.iter().position(|field| *field.name == **extras);
+            if let (Some(position), false) = (position, unknown.is_empty()) {
+                let values = reader_fields[position].map_values.as_ref().ok_or_else(|| {
+                    AvroError::Resolution(format!(
+                        "field {} collecting unknown fields must be a map",
+                        extras
+                    ))
+                })?;
+                let collected = match fields[position].1 {
+                    Value::Map(ref mut collected, _) => collected,
+                    ref other => {
+                        return Err(AvroError::Resolution(format!(
+                            "Map expected to collect unknown fields, got {:?}",
+                            other
+                        )))
+                    },
+                };
+                for (name, value) in unknown {
+                    collected.insert(
+                        name.to_string(),
+                        value.resolve_internal(values, setting.as_ref(), context)?,
+                    );
+                }
+            }
+        },
+    }
+
+    Ok(Value::Record(fields, setting.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::encode_to_vec;
+    use crate::types::ToAvro;
+
+    fn writer_schema() -> Schema {
+        Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": "int"},
+                    {"name": "old_b", "type": "string"},
+                    {"name": "gone", "type": {"type": "array", "items": "long"}},
+                    {"name": "inner", "type": ["null", {
+                        "type": "record",
+                        "name": "inner",
+                        "fields": [{"name": "x", "type": "float"}]
+                    }]},
+                    {"name": "list", "type": {"type": "array", "items": "int"}}
+                ]
+            }
+        "#,
+        ).unwrap()
+    }
+
+    fn reader_schema() -> Schema {
+        Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "inner", "type": ["null", {
+                        "type": "record",
+                        "name": "inner",
+                        "fields": [
+                            {"name": "x", "type": "double"},
+                            {"name": "y", "type": "string", "default": "none"}
+                        ]
+                    }]},
+                    {"name": "a", "type": "long", "index": true},
+                    {"name": "b", "type": "string", "aliases": ["old_b"]},
+                    {"name": "list", "type": {
+                        "type": "bounded_list", "items": "long", "max_length": 2
+                    }},
+                    {"name": "e", "type": {
+                        "type": "enum", "name": "e", "symbols": ["x", "y"]
+                    }, "default": "y"},
+                    {"name": "extras", "type": {"type": "map", "values": {
+                        "type": "array", "items": "long"
+                    }}}
+                ]
+            }
+        "#,
+        ).unwrap()
+    }
+
+    #[test]
+    fn test_plan_matches_resolve() {
+        let writer = writer_schema();
+        let reader = reader_schema();
+        let inner = Value::record(vec![("x", 0.5f32.avro())]);
+        let values = vec![
+            Value::record(vec![
+                ("a", 1i32.avro()),
+                ("old_b", "foo".avro()),
+                ("gone", vec![1i64, 2].avro()),
+                ("inner", Value::Union(Box::new(inner), None)),
+                ("list", vec![1i32, 2, 3].avro()),
+            ]),
+            Value::record(vec![
+                ("a", 2i32.avro()),
+                ("old_b", "bar".avro()),
+                ("gone", Vec::<i64>::new().avro()),
+                ("inner", Value::Union(Box::new(Value::Null), None)),
+                ("list", Vec::<i32>::new().avro()),
+            ]),
+        ];
+        let plan = ResolutionPlan::compile(&writer, &reader);
+        for policy in &[
+            UnknownFieldPolicy::Ignore,
+            UnknownFieldPolicy::Error,
+            UnknownFieldPolicy::CollectInto("extras".to_owned()),
+        ] {
+            let options = ResolveOptions {
+                unknown_fields: policy.clone(),
+                now: Some(0),
+                ..ResolveOptions::default()
+            };
+            for value in &values {
+                let encoded = encode_to_vec(value, &writer);
+                let decoded = decode(&writer, &mut &encoded[..]).unwrap();
+                let expected = decoded.resolve_with_options(&reader, &options);
+                let planned = plan.decode_with_options(&mut &encoded[..], &options);
+                match (expected, planned) {
+                    (Ok(expected), Ok(planned)) => assert_eq!(planned, expected),
+                    (Err(_), Err(_)) => (),
+                    (expected, planned) => panic!("{:?} != {:?}", planned, expected),
+                }
+            }
+        }
+
+        let plan = ResolutionPlan::compile(&writer, &writer);
+        let encoded = encode_to_vec(&values[0], &writer);
+        assert_eq!(
+            plan.decode(&mut &encoded[..]).unwrap(),
+            values[0].clone().resolve(&writer).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_plan_missing_field() {
+        let writer = Schema::parse_str(
+            r#"{"type": "record", "name": "test", "fields": [{"name": "a", "type": "long"}]}"#,
+        ).unwrap();
+        let reader = Schema::parse_str(
+            r#"{"type": "record", "name": "test", "fields": [{"name": "b", "type": "long"}]}"#,
+        ).unwrap();
+        let encoded = encode_to_vec(&Value::record(vec![("a", 1i64)]), &writer);
+        let plan = ResolutionPlan::compile(&writer, &reader);
+        assert!(plan.decode(&mut &encoded[..]).is_err());
+    }
+}
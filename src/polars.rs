@@ -0,0 +1,570 @@
+//! Logic for reading Avro files into Polars data frames and writing data frames as Avro files,
+//! enabled by the `polars` feature.
+//!
+//! Avro types map to Polars types as follows:
+//!
+//! | Avro                           | Polars                                  |
+//! |--------------------------------|-----------------------------------------|
+//! | `boolean`                      | `Boolean`                               |
+//! | `int`, `long`, `counter`       | `Int32`, `Int64`, `Int64`               |
+//! | `float`, `double`              | `Float32`, `Float64`                    |
+//! | `bytes`, `fixed`               | `Binary`                                |
+//! | `string`, `enum`               | `String`, holding the symbol of enums   |
+//! | `date`                         | `Datetime(Milliseconds)`                |
+//! | `array`, `bounded_list`, `set` | `List`                                  |
+//! | `record`                       | `Struct`                                |
+//! | `optional`, `["null", _]`      | the type of the value, with nulls       |
+//!
+//! Other unions and the `map`, `lru_set`, `hll`, `bloom` and `topk` types have no Polars
+//! counterpart and fail the conversion.
+use std::io::{Read, Write};
+
+use ::polars::chunked_array::builder::get_list_builder;
+use ::polars::prelude::{
+    DataFrame, DataType, IntoSeries, NamedFrom, Series, StructChunked, TimeUnit,
+};
+
+use crate::borrowed::ValueRef;
+use crate::error::{at, AvroError};
+use crate::events::DecodeHandler;
+use crate::reader::Reader;
+use crate::schema::{RecordField, Schema, SchemaKind};
+use crate::types::{SetItem, Value};
+use crate::visit::PathSegment;
+use crate::writer::Writer;
+
+/// Read the values following the current position of `reader` (the whole file for a new
+/// `Reader`) into a data frame, one column per field of the writer `Schema`, which must be a
+/// record.
+///
+/// Values are decoded straight into the buffers of their columns, with
+/// [`Reader::for_each_event`](../struct.Reader.html#method.for_each_event), without building
+/// any `Value`. As for events, the items of bounded lists are read as they were written, before
+/// any truncation.
+pub fn read_dataframe<R: Read>(mut reader: Reader<R>) -> Result<DataFrame, AvroError> {
+    let schema = reader.writer_schema().clone();
+    let mut columns = Columns::new(&schema)?;
+    reader.for_each_event(&mut columns)?;
+
+    let fields = match columns.nodes[columns.root].column {
+        Column::Struct { ref fields, .. } => fields.clone(),
+        _ => unreachable!(),
+    };
+    let series = fields
+        .into_iter()
+        .map(|id| columns.series(id).map(Into::into))
+        .collect::<Result<Vec<_>, AvroError>>()?;
+    Ok(DataFrame::new(series)?)
+}
+
+/// Write the rows of `df` as values of the record `schema`, reading each field from the column
+/// of the same name, to a new Avro file written to `writer`, returned once flushed.
+///
+/// Rows are encoded one at a time, as `Value`s brought to the canonical form of the schema.
+pub fn write_dataframe<W: Write>(
+    df: &DataFrame,
+    schema: &Schema,
+    writer: W,
+) -> Result<W, AvroError> {
+    let fields = record_fields(schema)?;
+    let columns = fields
+        .iter()
+        .map(|field| {
+            let column = df.column(&field.name)?.as_materialized_series();
+            // dates are read as their physical milliseconds
+            Ok(column.to_physical_repr().into_owned())
+        })
+        .collect::<Result<Vec<_>, AvroError>>()?;
+
+    let mut writer = Writer::new(schema, writer);
+    for row in 0..df.height() {
+        let items = fields
+            .iter()
+            .zip(columns.iter())
+            .map(|(field, column)| {
+                let value = value_at(column, row, &field.schema);
+                Ok((field.name.clone(), at(value, PathSegment::Field(&field.name))?))
+            })
+            .collect::<Result<Vec<_>, AvroError>>()?;
+        writer.append_ref(&Value::Record(items, None))?;
+    }
+    writer.flush()?;
+    Ok(writer.into_inner())
+}
+
+fn record_fields(schema: &Schema) -> Result<&[RecordField], AvroError> {
+    match schema {
+        Schema::Record { fields, .. } => Ok(fields),
+        other => Err(AvroError::Resolution(format!(
+            "Record schema expected, got {:?}",
+            SchemaKind::from(other)
+        ))),
+    }
+}
+
+fn unsupported(schema: &Schema) -> AvroError {
+    AvroError::Resolution(format!(
+        "{:?} has no Polars counterpart",
+        SchemaKind::from(schema)
+    ))
+}
+
+/// Schema of the values of `schema` other than `null`, along with whether it is nullable.
+fn non_null(schema: &Schema) -> Result<(&Schema, bool), AvroError> {
+    match schema {
+        Schema::Optional(inner) => Ok((inner, true)),
+        Schema::Union(union) => match union.variants() {
+            [Schema::Null, other] | [other, Schema::Null] => Ok((other, true)),
+            _ => Err(unsupported(schema)),
+        },
+        schema => Ok((schema, false)),
+    }
+}
+
+/// Buffer of the values of a column, a node of the tree of columns.
+enum Column {
+    Boolean(Vec<Option<bool>>),
+    Int(Vec<Option<i32>>),
+    Long(Vec<Option<i64>>),
+    Float(Vec<Option<f32>>),
+    Double(Vec<Option<f64>>),
+    String(Vec<Option<String>>),
+    Binary(Vec<Option<Vec<u8>>>),
+    Date(Vec<Option<i64>>),
+    // items of the lists in the `items` column, the list at i spanning offsets i to i + 1
+    List {
+        items: usize,
+        offsets: Vec<usize>,
+        valid: Vec<bool>,
+    },
+    Struct {
+        fields: Vec<usize>,
+        valid: Vec<bool>,
+    },
+}
+
+struct Node {
+    name: String,
+    column: Column,
+}
+
+/// Columns of a data frame, filled by decoding events.
+struct Columns {
+    nodes: Vec<Node>,
+    // struct and list columns being filled
+    stack: Vec<usize>,
+    // column of the values read, added after those of its fields
+    root: usize,
+    // column of the next value
+    target: usize,
+}
+
+impl Columns {
+    fn new(schema: &Schema) -> Result<Columns, AvroError> {
+        record_fields(schema)?;
+        let mut columns = Columns {
+            nodes: Vec::new(),
+            stack: Vec::new(),
+            root: 0,
+            target: 0,
+        };
+        columns.root = columns.add("", schema)?;
+        Ok(columns)
+    }
+
+    /// Add the columns of the values of `schema`, returning the id of the top one.
+    fn add(&mut self, name: &str, schema: &Schema) -> Result<usize, AvroError> {
+        let column = match non_null(schema)?.0 {
+            Schema::Boolean => Column::Boolean(Vec::new()),
+            Schema::Int => Column::Int(Vec::new()),
+            Schema::Long | Schema::Counter => Column::Long(Vec::new()),
+            Schema::Float => Column::Float(Vec::new()),
+            Schema::Double => Column::Double(Vec::new()),
+            Schema::String | Schema::Enum { .. } => Column::String(Vec::new()),
+            Schema::Bytes | Schema::Fixed { .. } => Column::Binary(Vec::new()),
+            Schema::Date(_) => Column::Date(Vec::new()),
+            Schema::Array(items) | Schema::BoundedList { items, .. } | Schema::Set(items) => {
+                Column::List {
+                    items: self.add(name, items)?,
+                    offsets: vec![0],
+                    valid: Vec::new(),
+                }
+            },
+            Schema::Record { fields, .. } => Column::Struct {
+                fields: fields
+                    .iter()
+                    .map(|field| {
+                        let id = self.add(&field.name, &field.schema);
+                        at(id, PathSegment::Field(&field.name))
+                    })
+                    .collect::<Result<_, _>>()?,
+                valid: Vec::new(),
+            },
+            other => return Err(unsupported(other)),
+        };
+        self.nodes.push(Node {
+            name: name.to_owned(),
+            column,
+        });
+        Ok(self.nodes.len() - 1)
+    }
+
+    fn push(&mut self, id: usize, value: ValueRef) -> Result<(), AvroError> {
+        match (&mut self.nodes[id].column, value) {
+            (_, ValueRef::Null) => self.push_null(id),
+            (Column::Boolean(values), ValueRef::Boolean(b)) => values.push(Some(b)),
+            (Column::Int(values), ValueRef::Int(n)) => values.push(Some(n)),
+            (Column::Long(values), ValueRef::Long(n))
+            | (Column::Long(values), ValueRef::Counter(n))
+            | (Column::Date(values), ValueRef::Date(n)) => values.push(Some(n)),
+            (Column::Float(values), ValueRef::Float(x)) => values.push(Some(x)),
+            (Column::Double(values), ValueRef::Double(x)) => values.push(Some(x)),
+            (Column::String(values), ValueRef::String(s))
+            | (Column::String(values), ValueRef::Enum(_, s)) => values.push(Some(s.to_owned())),
+            (Column::Binary(values), ValueRef::Bytes(bytes))
+            | (Column::Binary(values), ValueRef::Fixed(bytes)) => values.push(Some(bytes.to_vec())),
+            (Column::List { .. }, ValueRef::Owned(Value::Set(items, _))) => {
+                let (items_id, end) = match self.nodes[id].column {
+                    Column::List {
+                        items: items_id,
+                        ref offsets,
+                        ..
+                    } => (items_id, offsets[offsets.len() - 1] + items.len()),
+                    _ => unreachable!(),
+                };
+                for item in &items {
+                    let item = match item {
+                        SetItem::Int(n) => ValueRef::Int(*n),
+                        SetItem::Long(n) => ValueRef::Long(*n),
+                        SetItem::Bytes(bytes) => ValueRef::Bytes(bytes),
+                        SetItem::String(s) => ValueRef::String(s),
+                        SetItem::Enum(index, symbol) => ValueRef::Enum(*index, symbol),
+                    };
+                    self.push(items_id, item)?;
+                }
+                if let Column::List {
+                    ref mut offsets,
+                    ref mut valid,
+                    ..
+                } = self.nodes[id].column
+                {
+                    offsets.push(end);
+                    valid.push(true);
+                }
+            },
+            (_, value) => {
+                return Err(AvroError::Resolution(format!(
+                    "unexpected {:?} in column {}",
+                    value.to_value(),
+                    self.nodes[id].name
+                )))
+            },
+        }
+        Ok(())
+    }
+
+    fn push_null(&mut self, id: usize) {
+        let fields = match self.nodes[id].column {
+            Column::Boolean(ref mut values) => return values.push(None),
+            Column::Int(ref mut values) => return values.push(None),
+            Column::Long(ref mut values) | Column::Date(ref mut values) => {
+                return values.push(None)
+            },
+            Column::Float(ref mut values) => return values.push(None),
+            Column::Double(ref mut values) => return values.push(None),
+            Column::String(ref mut values) => return values.push(None),
+            Column::Binary(ref mut values) => return values.push(None),
+            Column::List {
+                ref mut offsets,
+                ref mut valid,
+                ..
+            } => {
+                offsets.push(offsets[offsets.len() - 1]);
+                return valid.push(false)
+            },
+            Column::Struct {
+                ref fields,
+                ref mut valid,
+            } => {
+                valid.push(false);
+                fields.clone()
+            },
+        };
+        // the fields of a null record are null too, for them to keep the length of the record
+        for field in fields {
+            self.push_null(field);
+        }
+    }
+
+    fn series(&self, id: usize) -> Result<Series, AvroError> {
+        let name = self.nodes[id].name.as_str().into();
+        Ok(match self.nodes[id].column {
+            Column::Boolean(ref values) => Series::new(name, values),
+            Column::Int(ref values) => Series::new(name, values),
+            Column::Long(ref values) => Series::new(name, values),
+            Column::Float(ref values) => Series::new(name, values),
+            Column::Double(ref values) => Series::new(name, values),
+            Column::String(ref values) => Series::new(name, values),
+            Column::Binary(ref values) => Series::new(name, values),
+            Column::Date(ref values) => Series::new(name, values)
+                .cast(&DataType::Datetime(TimeUnit::Milliseconds, None))?,
+            Column::List {
+                items,
+                ref offsets,
+                ref valid,
+            } => {
+                let items = self.series(items)?;
+                let mut builder = get_list_builder(items.dtype(), items.len(), valid.len(), name);
+                for (i, valid) in valid.iter().enumerate() {
+                    if *valid {
+                        let (start, end) = (offsets[i], offsets[i + 1]);
+                        builder.append_series(&items.slice(start as i64, end - start))?;
+                    } else {
+                        builder.append_null();
+                    }
+                }
+                builder.finish().into_series()
+            },
+            Column::Struct {
+                ref fields,
+                ref valid,
+            } => {
+                let fields = fields
+                    .iter()
+                    .map(|field| self.series(*field))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut records = StructChunked::from_series(name, valid.len(), fields.iter())?;
+                if valid.contains(&false) {
+                    records = records.with_outer_validity(Some(valid.iter().copied().collect()));
+                }
+                records.into_series()
+            },
+        })
+    }
+}
+
+impl DecodeHandler for Columns {
+    fn record_start(&mut self, _schema: &Schema) -> Result<(), AvroError> {
+        let id = if self.stack.is_empty() { self.root } else { self.target };
+        match self.nodes[id].column {
+            Column::Struct { ref mut valid, .. } => valid.push(true),
+            _ => return Err(AvroError::Resolution("unexpected record".to_owned())),
+        }
+        self.stack.push(id);
+        Ok(())
+    }
+
+    fn field(&mut self, field: &RecordField) -> Result<(), AvroError> {
+        let id = self.stack[self.stack.len() - 1];
+        if let Column::Struct { ref fields, .. } = self.nodes[id].column {
+            self.target = fields[field.position];
+        }
+        Ok(())
+    }
+
+    fn record_end(&mut self) -> Result<(), AvroError> {
+        self.stack.pop();
+        Ok(())
+    }
+
+    fn array_start(&mut self) -> Result<(), AvroError> {
+        match self.nodes[self.target].column {
+            Column::List { .. } => self.stack.push(self.target),
+            _ => return Err(AvroError::Resolution("unexpected array".to_owned())),
+        }
+        Ok(())
+    }
+
+    fn array_item(&mut self, _index: usize) -> Result<(), AvroError> {
+        if let Column::List { items, .. } = self.nodes[self.stack[self.stack.len() - 1]].column {
+            self.target = items;
+        }
+        Ok(())
+    }
+
+    fn array_end(&mut self) -> Result<(), AvroError> {
+        let id = self.stack.pop().unwrap_or_default();
+        let len = match self.nodes[id].column {
+            Column::List { items, .. } => self.len(items),
+            _ => unreachable!(),
+        };
+        if let Column::List {
+            ref mut offsets,
+            ref mut valid,
+            ..
+        } = self.nodes[id].column
+        {
+            offsets.push(len);
+            valid.push(true);
+        }
+        Ok(())
+    }
+
+    fn map_start(&mut self) -> Result<(), AvroError> {
+        Err(AvroError::Resolution("unexpected map".to_owned()))
+    }
+
+    fn value(&mut self, value: ValueRef) -> Result<(), AvroError> {
+        self.push(self.target, value)
+    }
+}
+
+impl Columns {
+    /// Number of values of a column.
+    fn len(&self, id: usize) -> usize {
+        match self.nodes[id].column {
+            Column::Boolean(ref values) => values.len(),
+            Column::Int(ref values) => values.len(),
+            Column::Long(ref values) | Column::Date(ref values) => values.len(),
+            Column::Float(ref values) => values.len(),
+            Column::Double(ref values) => values.len(),
+            Column::String(ref values) => values.len(),
+            Column::Binary(ref values) => values.len(),
+            Column::List { ref valid, .. } | Column::Struct { ref valid, .. } => valid.len(),
+        }
+    }
+}
+
+/// Value of `schema` at `row` of `column`, whose dates are physical.
+fn value_at(column: &Series, row: usize, schema: &Schema) -> Result<Value, AvroError> {
+    let is_null = column.get(row)?.is_null();
+    match schema {
+        Schema::Optional(inner) if is_null => return Ok(Value::Optional(None, None)),
+        Schema::Optional(inner) => {
+            let value = value_at(column, row, inner)?;
+            return Ok(Value::Optional(Some(Box::new(value)), None))
+        },
+        Schema::Union(_) => {
+            let value = match non_null(schema)? {
+                (_, true) if is_null => Value::Null,
+                (inner, _) => value_at(column, row, inner)?,
+            };
+            return Ok(Value::Union(Box::new(value), None))
+        },
+        _ if is_null => {
+            return Err(AvroError::Resolution(format!(
+                "null in a column of non-null {:?}",
+                SchemaKind::from(schema)
+            )))
+        },
+        _ => (),
+    }
+
+    // not null, as checked above
+    Ok(match schema {
+        Schema::Boolean => Value::Boolean(column.bool()?.get(row).unwrap_or_default(), None),
+        Schema::Int => Value::Int(column.i32()?.get(row).unwrap_or_default(), None),
+        Schema::Long => Value::Long(column.i64()?.get(row).unwrap_or_default(), None),
+        Schema::Counter => Value::Counter(column.i64()?.get(row).unwrap_or_default(), None),
+        Schema::Date(_) => Value::Date(column.i64()?.get(row).unwrap_or_default(), None),
+        Schema::Float => Value::Float(column.f32()?.get(row).unwrap_or_default(), None),
+        Schema::Double => Value::Double(column.f64()?.get(row).unwrap_or_default(), None),
+        Schema::String => Value::String(string_at(column, row)?, None),
+        Schema::Enum { .. } => Value::String(string_at(column, row)?, None).normalize(schema)?,
+        Schema::Bytes => Value::Bytes(column.binary()?.get(row).unwrap_or_default().to_vec(), None),
+        Schema::Fixed { size, .. } => {
+            Value::Fixed(*size, column.binary()?.get(row).unwrap_or_default().to_vec(), None)
+                .normalize(schema)?
+        },
+        Schema::Array(inner) | Schema::BoundedList { items: inner, .. } | Schema::Set(inner) => {
+            let items = column.list()?.get_as_series(row).unwrap_or_default();
+            let items = (0..items.len())
+                .map(|i| at(value_at(&items, i, inner), PathSegment::Index(i)))
+                .collect::<Result<Vec<_>, _>>()?;
+            // sets and bounded lists are collected from arrays
+            Value::Array(items, None).normalize(schema)?
+        },
+        Schema::Record { fields, .. } => {
+            let columns = column.struct_()?.fields_as_series();
+            let items = fields
+                .iter()
+                .map(|field| {
+                    let column = columns
+                        .iter()
+                        .find(|column| column.name().as_str() == &*field.name)
+                        .ok_or_else(|| {
+                            AvroError::Resolution(format!("missing field {}", field.name))
+                        })?;
+                    let value = value_at(column, row, &field.schema);
+                    Ok((field.name.clone(), at(value, PathSegment::Field(&field.name))?))
+                })
+                .collect::<Result<Vec<_>, AvroError>>()?;
+            Value::Record(items, None)
+        },
+        other => return Err(unsupported(other)),
+    })
+}
+
+fn string_at(column: &Series, row: usize) -> Result<String, AvroError> {
+    Ok(column.str()?.get(row).unwrap_or_default().to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ToAvro;
+
+    #[test]
+    fn test_dataframe_round_trip() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "r", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "at", "type": "date"},
+                {"name": "color", "type": {
+                    "type": "enum", "name": "c", "symbols": ["red", "blue"]
+                }},
+                {"name": "tags", "type": {"type": "set", "items": "string"}},
+                {"name": "scores", "type": {"type": "array", "items": ["null", "double"]}},
+                {"name": "line", "type": ["null", {"type": "record", "name": "l", "fields": [
+                    {"name": "sku", "type": "string"},
+                    {"name": "quantity", "type": "int"}
+                ]}]}
+            ]}"#,
+        ).unwrap();
+        let values = (0..5i64)
+            .map(|i| {
+                let line = if i % 2 == 0 {
+                    Value::Null
+                } else {
+                    Value::Record(vec![
+                        ("sku".into(), format!("s{}", i).avro()),
+                        ("quantity".into(), Value::Int(i as i32, None)),
+                    ], None)
+                };
+                let color = ["red", "blue"][i as usize % 2];
+                let tags = vec![SetItem::String(format!("t{}", i))].into_iter().collect();
+                let scores = (0..i)
+                    .map(|j| {
+                        let score = match j {
+                            1 => Value::Null,
+                            j => Value::Double(j as f64, None),
+                        };
+                        Value::Union(Box::new(score), None)
+                    })
+                    .collect();
+                Value::Record(vec![
+                    ("id".into(), Value::Long(i, None)),
+                    ("at".into(), Value::Date(i * 1_000, None)),
+                    ("color".into(), Value::Enum((i % 2) as i32, color.to_owned(), None)),
+                    ("tags".into(), Value::Set(tags, None)),
+                    ("scores".into(), Value::Array(scores, None)),
+                    ("line".into(), Value::Union(Box::new(line), None)),
+                ], None)
+            })
+            .collect::<Vec<_>>();
+        let mut writer = Writer::new(&schema, Vec::new());
+        writer.extend_from_slice(&values).unwrap();
+        let encoded = writer.into_inner();
+
+        let df = read_dataframe(Reader::new(&encoded[..]).unwrap()).unwrap();
+        assert_eq!(df.shape(), (5, 6));
+        let date = DataType::Datetime(TimeUnit::Milliseconds, None);
+        assert_eq!(df.column("at").unwrap().dtype(), &date);
+        assert_eq!(df.column("color").unwrap().str().unwrap().get(1), Some("blue"));
+        assert_eq!(df.column("line").unwrap().null_count(), 3);
+
+        let written = write_dataframe(&df, &schema, Vec::new()).unwrap();
+        let read = Reader::new(&written[..]).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(read, values);
+    }
+}
@@ -0,0 +1,332 @@
+//! Logic for the decoding of Avro values into allocations recycled from previous values.
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::mem;
+use std::sync::Arc;
+
+use crate::decode::{decode_in, Allocations};
+use crate::error::AvroError;
+use crate::lru::LruMap;
+use crate::schema::Schema;
+use crate::types::{LruValue, SetItem, Value};
+
+// allocations kept of each kind, beyond which recycled ones are freed
+const MAX_POOLED: usize = 4096;
+// bytes an allocation may hold on to and still be kept, larger ones are freed when recycled
+const MAX_POOLED_SIZE: usize = 1 << 20;
+
+/// Pool of the allocations of decoded values, so that the bytes, strings, arrays, maps, records,
+/// sets, `lru_set`s and unions of a value that is done with are reused to decode the next one
+/// instead of being freed and allocated again.
+///
+/// Values are decoded with [`decode`](#method.decode) and handed back with
+/// [`recycle`](#method.recycle). As a value is most often recycled before the next one is decoded,
+/// the pool holds no more allocations than the largest value decoded from it, and at most 4096
+/// of each kind: the ones recycled beyond are freed. Allocations grown over 1 MiB (such as the
+/// buffer of a huge `bytes` value) are freed as well, so that an outlier does not stay pinned in
+/// the pool. Every allocation left in the pool is freed when it is dropped.
+#[derive(Debug, Default)]
+pub struct ValuePool {
+    bytes: Vec<Vec<u8>>,
+    arrays: Vec<Vec<Value>>,
+    maps: Vec<HashMap<String, Value>>,
+    records: Vec<Vec<(Arc<str>, Value)>>,
+    sets: Vec<HashSet<SetItem>>,
+    #[allow(clippy::vec_box)]
+    lru_maps: Vec<Box<LruMap>>,
+    // the boxes of unions and optionals are the allocations being pooled
+    #[allow(clippy::vec_box)]
+    boxes: Vec<Box<Value>>,
+}
+
+impl ValuePool {
+    /// Create an empty `ValuePool`.
+    pub fn new() -> ValuePool {
+        ValuePool::default()
+    }
+
+    /// Decode a value from avro format given its `Schema`, like
+    /// [`decode`](decode/fn.decode.html), taking its allocations from the pool.
+    pub fn decode<R: Read>(&mut self, schema: &Schema, reader: &mut R) -> Result<Value, AvroError> {
+        decode_in(schema, reader, self)
+    }
+
+    /// Hand the allocations of a value back to the pool, to be reused by the next values decoded
+    /// from it. The allocations of `hll` and `bloom` values are freed.
+    pub fn recycle(&mut self, value: Value) {
+        match value {
+            Value::Bytes(bytes, _) | Value::Fixed(_, bytes, _) => self.recycle_bytes(bytes),
+            Value::String(s, _) | Value::Enum(_, s, _) => self.recycle_bytes(s.into_bytes()),
+            Value::Array(mut items, _) => {
+                for item in items.drain(..) {
+                    self.recycle(item);
+                }
+                keep(&mut self.arrays, items);
+            },
+            Value::Map(mut items, _) => {
+                for (key, value) in items.drain() {
+                    self.recycle_bytes(key.into_bytes());
+                    self.recycle(value);
+                }
+                keep(&mut self.maps, items);
+            },
+            Value::Record(mut items, _) => {
+                for (_, value) in items.drain(..) {
+                    self.recycle(value);
+                }
+                keep(&mut self.records, items);
+            },
+            Value::Set(mut items, _) => {
+                for item in items.drain() {
+                    match item {
+                        SetItem::Bytes(bytes) => self.recycle_bytes(bytes),
+                        SetItem::String(s) | SetItem::Enum(_, s) => {
+                            self.recycle_bytes(s.into_bytes())
+                        },
+                        SetItem::Int(_) | SetItem::Long(_) => (),
+                    }
+                }
+                keep(&mut self.sets, items);
+            },
+            Value::LruSet(mut items, _, _) => {
+                for key in items.drain_keys() {
+                    self.recycle_bytes(key.into_bytes());
+                }
+                keep(&mut self.lru_maps, items);
+            },
+            Value::TopK(mut topk, _) => {
+                for key in topk.drain_keys() {
+                    self.recycle_bytes(key.into_bytes());
+                }
+            },
+            Value::Union(mut inner, _) | Value::Optional(Some(mut inner), _) => {
+                let value = mem::replace(&mut *inner, Value::Null);
+                self.recycle(value);
+                keep(&mut self.boxes, inner);
+            },
+            _ => (),
+        }
+    }
+
+    fn recycle_bytes(&mut self, mut bytes: Vec<u8>) {
+        bytes.clear();
+        keep(&mut self.bytes, bytes);
+    }
+}
+
+/// Keep an emptied allocation in a pool, unless it is full or the allocation is too large.
+fn keep<T: Allocation>(pool: &mut Vec<T>, allocation: T) {
+    if pool.len() < MAX_POOLED && allocation.size() <= MAX_POOLED_SIZE {
+        pool.push(allocation);
+    }
+}
+
+/// Allocation kept by a `ValuePool`.
+trait Allocation {
+    /// Number of bytes held on to by the allocation.
+    fn size(&self) -> usize;
+}
+
+impl<T> Allocation for Vec<T> {
+    fn size(&self) -> usize {
+        self.capacity() * mem::size_of::<T>()
+    }
+}
+
+impl<K, V> Allocation for HashMap<K, V> {
+    fn size(&self) -> usize {
+        self.capacity() * mem::size_of::<(K, V)>()
+    }
+}
+
+impl<T> Allocation for HashSet<T> {
+    fn size(&self) -> usize {
+        self.capacity() * mem::size_of::<T>()
+    }
+}
+
+impl Allocation for Box<LruMap> {
+    fn size(&self) -> usize {
+        self.capacity() * mem::size_of::<(String, LruValue)>()
+    }
+}
+
+impl Allocation for Box<Value> {
+    fn size(&self) -> usize {
+        mem::size_of::<Value>()
+    }
+}
+
+impl Allocations for ValuePool {
+    fn bytes(&mut self) -> Vec<u8> {
+        self.bytes.pop().unwrap_or_default()
+    }
+
+    fn array(&mut self) -> Vec<Value> {
+        self.arrays.pop().unwrap_or_default()
+    }
+
+    fn map(&mut self) -> HashMap<String, Value> {
+        self.maps.pop().unwrap_or_default()
+    }
+
+    fn record(&mut self) -> Vec<(Arc<str>, Value)> {
+        self.records.pop().unwrap_or_default()
+    }
+
+    fn set(&mut self) -> HashSet<SetItem> {
+        self.sets.pop().unwrap_or_default()
+    }
+
+    fn lru_map(&mut self) -> Box<LruMap> {
+        self.lru_maps.pop().unwrap_or_default()
+    }
+
+    fn boxed(&mut self, value: Value) -> Box<Value> {
+        match self.boxes.pop() {
+            Some(mut boxed) => {
+                *boxed = value;
+                boxed
+            },
+            None => Box::new(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::decode;
+    use crate::encode::encode_to_vec;
+    use crate::types::ToAvro;
+
+    #[test]
+    fn test_pool_decode() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": "long"},
+                    {"name": "b", "type": "string"},
+                    {"name": "c", "type": {"type": "array", "items": ["null", "bytes"]}},
+                    {"name": "d", "type": {"type": "map", "values": "int"}},
+                    {"name": "e", "type": {"type": "enum", "name": "e", "symbols": ["x", "y"]}}
+                ]
+            }
+        "#,
+        ).unwrap();
+        let mut map = HashMap::new();
+        map.insert("k".to_owned(), 1i32.avro());
+        let value = |b: &str| {
+            Value::record(vec![
+                ("a", 1i64.avro()),
+                ("b", b.avro()),
+                (
+                    "c",
+                    Value::Array(
+                        vec![
+                            Value::Union(Box::new(Value::Null), None),
+                            Value::Union(Box::new(Value::Bytes(vec![1, 2], None)), None),
+                        ],
+                        None,
+                    ),
+                ),
+                ("d", Value::Map(map.clone(), None)),
+                ("e", Value::Enum(1, "y".to_owned(), None)),
+            ])
+        };
+
+        let mut pool = ValuePool::new();
+        let encoded = encode_to_vec(&value("foo"), &schema);
+        let decoded = pool.decode(&schema, &mut &encoded[..]).unwrap();
+        assert_eq!(decoded, decode(&schema, &mut &encoded[..]).unwrap());
+
+        let string = match decoded.field("b") {
+            Some(Value::String(s, _)) => s.as_ptr(),
+            _ => unreachable!(),
+        };
+        pool.recycle(decoded);
+        assert!(!pool.bytes.is_empty());
+        assert_eq!(pool.records.len(), 1);
+
+        // the allocations of the recycled value are reused for the next one
+        let encoded = encode_to_vec(&value("bar"), &schema);
+        let decoded = pool.decode(&schema, &mut &encoded[..]).unwrap();
+        assert_eq!(decoded, value("bar"));
+        assert!(pool.records.is_empty());
+        assert!(pool.boxes.is_empty());
+        let reused = [&decoded.field("b"), &decoded.field("e")].iter().any(|field| match field {
+            Some(Value::String(s, _)) | Some(Value::Enum(_, s, _)) => s.as_ptr() == string,
+            _ => false,
+        });
+        assert!(reused);
+    }
+
+    #[test]
+    fn test_pool_recycle_sets() {
+        use crate::schema::LruLimit;
+        use crate::types::LruValue;
+
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "tags", "type": {"type": "set", "items": "string"}},
+                    {"name": "seen", "type": {"type": "lru_set", "max_entries": 10}}
+                ]
+            }
+        "#,
+        ).unwrap();
+        let mut seen = LruMap::new();
+        seen.insert("a".to_owned(), LruValue::new(1, 1));
+        seen.insert("b".to_owned(), LruValue::new(2, 1));
+        let tags: HashSet<_> = vec![SetItem::String("x".to_owned())].into_iter().collect();
+        let value = Value::record(vec![
+            ("tags", tags.avro()),
+            ("seen", Value::LruSet(Box::new(seen), LruLimit::Count(10), None)),
+        ]);
+
+        let mut pool = ValuePool::new();
+        let encoded = encode_to_vec(&value, &schema);
+        let decoded = pool.decode(&schema, &mut &encoded[..]).unwrap();
+        pool.recycle(decoded);
+        assert_eq!(pool.sets.len(), 1);
+        assert_eq!(pool.lru_maps.len(), 1);
+        assert!(pool.lru_maps[0].is_empty());
+        assert_eq!(pool.bytes.len(), 3);
+
+        let decoded = pool.decode(&schema, &mut &encoded[..]).unwrap();
+        assert_eq!(decoded, decode(&schema, &mut &encoded[..]).unwrap());
+        assert!(pool.sets.is_empty());
+        assert!(pool.lru_maps.is_empty());
+        assert!(pool.bytes.is_empty());
+    }
+
+    #[test]
+    fn test_pool_bounded() {
+        let mut pool = ValuePool::new();
+        pool.recycle(Value::Array(vec![Value::Bytes(vec![0], None); MAX_POOLED + 1], None));
+        assert_eq!(pool.bytes.len(), MAX_POOLED);
+        assert_eq!(pool.arrays.len(), 1);
+    }
+
+    #[test]
+    fn test_pool_bounded_size() {
+        let mut pool = ValuePool::new();
+
+        // a huge buffer is freed instead of staying pinned in the pool
+        pool.recycle(Value::Bytes(Vec::with_capacity(MAX_POOLED_SIZE + 1), None));
+        pool.recycle(Value::Array(Vec::with_capacity(MAX_POOLED_SIZE), None));
+        assert!(pool.bytes.is_empty());
+        assert!(pool.arrays.is_empty());
+
+        pool.recycle(Value::Bytes(Vec::with_capacity(MAX_POOLED_SIZE), None));
+        pool.recycle(Value::Array(Vec::with_capacity(16), None));
+        assert_eq!(pool.bytes.len(), 1);
+        assert_eq!(pool.arrays.len(), 1);
+    }
+}
@@ -0,0 +1,181 @@
+//! Logic for the filtering of records by conditions on their fields.
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+
+use crate::encode::encode_to_vec;
+use crate::error::AvroError;
+use crate::index::{field_schema, field_value, non_null, stats_schema, FileIndex};
+use crate::schema::Schema;
+use crate::types::{SetItem, Value};
+
+/// Condition on the fields of records, by which the records of a [Reader](struct.Reader.html) are
+/// filtered with [`Reader::filter`](struct.Reader.html#method.filter).
+///
+/// Fields are named by their dot-separated path from the top-level record, and values are
+/// resolved against the schema of the field in the writer `Schema`. Null values only match `Eq`
+/// and `In` predicates with a null value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Predicate {
+    /// The field is equal to `value`.
+    Eq { path: String, value: Value },
+    /// The field is between `min` and `max`, both inclusive and unbounded if `None`.
+    Range {
+        path: String,
+        min: Option<Value>,
+        max: Option<Value>,
+    },
+    /// The field is equal to any of `values`.
+    In { path: String, values: Vec<Value> },
+    /// The `set` field holds `item`.
+    Contains { path: String, item: Value },
+    /// All the predicates hold.
+    And(Vec<Predicate>),
+}
+
+fn field_schema_of<'a>(schema: &'a Schema, path: &str) -> Result<&'a Schema, AvroError> {
+    field_schema(schema, path).ok_or_else(|| {
+        AvroError::Resolution(format!("No field {} in the schema", path))
+    })
+}
+
+/// `Predicate` with its values resolved against the writer `Schema`.
+pub(crate) enum CompiledPredicate {
+    Keys {
+        path: String,
+        schema: Schema,
+        keys: Vec<Vec<u8>>,
+    },
+    Range {
+        path: String,
+        schema: Schema,
+        min: Option<Value>,
+        max: Option<Value>,
+    },
+    Contains {
+        path: String,
+        item: SetItem,
+    },
+    And(Vec<CompiledPredicate>),
+}
+
+impl CompiledPredicate {
+    pub(crate) fn new(
+        predicate: Predicate,
+        schema: &Schema,
+    ) -> Result<CompiledPredicate, AvroError> {
+        Ok(match predicate {
+            Predicate::Eq { path, value } => {
+                CompiledPredicate::new(Predicate::In { path, values: vec![value] }, schema)?
+            },
+            Predicate::In { path, values } => {
+                let field = field_schema_of(schema, &path)?;
+                let keys = values
+                    .into_iter()
+                    .map(|value| Ok(encode_to_vec(&value.resolve(field)?, field)))
+                    .collect::<Result<_, AvroError>>()?;
+                CompiledPredicate::Keys {
+                    schema: field.clone(),
+                    path,
+                    keys,
+                }
+            },
+            Predicate::Range { path, min, max } => {
+                let field = field_schema_of(schema, &path)?;
+                let field = stats_schema(field).unwrap_or(field);
+                CompiledPredicate::Range {
+                    min: min.map(|min| min.resolve(field)).transpose()?,
+                    max: max.map(|max| max.resolve(field)).transpose()?,
+                    schema: field.clone(),
+                    path,
+                }
+            },
+            Predicate::Contains { path, item } => match field_schema_of(schema, &path)? {
+                Schema::Set(inner) => CompiledPredicate::Contains {
+                    item: SetItem::try_from(item.resolve(inner)?)?,
+                    path,
+                },
+                _ => {
+                    let message = format!("Field {} is not a set", path);
+                    return Err(AvroError::Resolution(message))
+                },
+            },
+            Predicate::And(predicates) => CompiledPredicate::And(
+                predicates
+                    .into_iter()
+                    .map(|predicate| CompiledPredicate::new(predicate, schema))
+                    .collect::<Result<_, _>>()?,
+            ),
+        })
+    }
+
+    /// Paths of the fields the predicate depends on.
+    pub(crate) fn paths(&self) -> Vec<&str> {
+        match self {
+            CompiledPredicate::Keys { path, .. }
+            | CompiledPredicate::Range { path, .. }
+            | CompiledPredicate::Contains { path, .. } => vec![path.as_str()],
+            CompiledPredicate::And(predicates) => {
+                predicates.iter().flat_map(CompiledPredicate::paths).collect()
+            },
+        }
+    }
+
+    /// Whether the block starting at `block_offset` might hold matching records, according to
+    /// the Bloom filters and the statistics of the block in `index`.
+    pub(crate) fn might_match_block(
+        &self,
+        index: &FileIndex,
+        block_offset: u64,
+    ) -> Result<bool, AvroError> {
+        Ok(match self {
+            CompiledPredicate::Keys { path, keys, .. } => keys
+                .iter()
+                .any(|key| index.block_might_contain(block_offset, path, key)),
+            CompiledPredicate::Range {
+                path,
+                schema,
+                min,
+                max,
+            } => index.block_in_range(schema, block_offset, path, min.as_ref(), max.as_ref())?,
+            CompiledPredicate::Contains { .. } => true,
+            CompiledPredicate::And(predicates) => {
+                for predicate in predicates {
+                    if !predicate.might_match_block(index, block_offset)? {
+                        return Ok(false)
+                    }
+                }
+                true
+            },
+        })
+    }
+
+    /// Whether a record, of which at least the fields the predicate depends on are decoded,
+    /// matches the predicate.
+    pub(crate) fn matches(&self, record: &Value) -> bool {
+        match self {
+            CompiledPredicate::Keys { path, schema, keys } => field_value(record, path)
+                .is_some_and(|field| keys.contains(&encode_to_vec(field, schema))),
+            CompiledPredicate::Range {
+                path,
+                schema,
+                min,
+                max,
+            } => field_value(record, path).and_then(non_null).is_some_and(|field| {
+                min.as_ref()
+                    .is_none_or(|min| field.cmp_with_schema(min, schema) != Ordering::Less)
+                    && max
+                        .as_ref()
+                        .is_none_or(|max| field.cmp_with_schema(max, schema) != Ordering::Greater)
+            }),
+            CompiledPredicate::Contains { path, item } => {
+                match field_value(record, path).and_then(non_null) {
+                    Some(Value::Set(items, _)) => items.contains(item),
+                    _ => false,
+                }
+            },
+            CompiledPredicate::And(predicates) => {
+                predicates.iter().all(|predicate| predicate.matches(record))
+            },
+        }
+    }
+}
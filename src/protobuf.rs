@@ -0,0 +1,431 @@
+//! Logic for converting the messages of compiled protobuf descriptors into Avro schemas, enabled
+//! by the `protobuf` feature.
+//!
+//! Descriptors are read from a `FileDescriptorSet`, as written by
+//! `protoc --include_imports --descriptor_set_out`. Protobuf types map to Avro types as follows:
+//!
+//! | Protobuf                                               | Avro                            |
+//! |--------------------------------------------------------|---------------------------------|
+//! | `double`, `float`, `bool`                              | `double`, `float`, `boolean`    |
+//! | `string`, `bytes`                                      | `string`, `bytes`               |
+//! | `int32`, `sint32`, `sfixed32`                          | `int`                           |
+//! | `int64`, `sint64`, `sfixed64`, `uint32`, `fixed32`     | `long`                          |
+//! | `uint64`, `fixed64`                                    | `long`, wrapping past i64 max  |
+//! | enum                                                   | `enum` of the value names       |
+//! | message                                                | `record`                        |
+//! | `google.protobuf.Timestamp`                            | `date`                          |
+//! | `repeated` field                                       | `array`                         |
+//! | `map<_, V>` field                                      | `map` of `V`                    |
+//! | `oneof`                                                | union of `null` and its types   |
+//!
+//! A `oneof` is a single field, named after the `oneof`, in place of its first field.
+//!
+//! Fields with presence (message fields and `optional` fields) are unions of `null` and their
+//! type, defaulting to `null`; other fields default to the protobuf default of their type. Named
+//! types take the package of their file, and their enclosing messages, as namespace.
+//!
+//! As Avro unions may not hold two types of the same kind, `oneof`s holding two fields of the same
+//! kind (such as two messages) fail the conversion, as do recursive messages and groups.
+use std::collections::HashMap;
+
+use ::prost::Message;
+use ::prost_types::field_descriptor_proto::{Label, Type};
+use ::prost_types::{DescriptorProto, EnumDescriptorProto, FieldDescriptorProto};
+use serde_json::{Map, Value as JsonValue};
+
+use crate::error::AvroError;
+use crate::schema::Schema;
+
+pub use ::prost_types::FileDescriptorSet;
+
+const TIMESTAMP: &str = ".google.protobuf.Timestamp";
+
+/// Decode a `FileDescriptorSet` from its protobuf encoding.
+pub fn parse_file_descriptor_set(bytes: &[u8]) -> Result<FileDescriptorSet, AvroError> {
+    Ok(FileDescriptorSet::decode(bytes)?)
+}
+
+/// Avro [Schema](../schema/enum.Schema.html) of the message of the given full name, such as
+/// `shop.Order` or `.shop.Order.Line`, among the messages of `set`.
+pub fn to_avro_schema(set: &FileDescriptorSet, message: &str) -> Result<Schema, AvroError> {
+    let full_name = format!(".{}", message.trim_start_matches('.'));
+    Schema::parse(&Descriptors::new(set).message(&full_name, &mut Vec::new())?)
+}
+
+/// Avro [Schemas](../schema/enum.Schema.html) of the top level messages of every file of `set`,
+/// along with the full name of the message, in declaration order.
+pub fn to_avro_schemas(set: &FileDescriptorSet) -> Result<Vec<(String, Schema)>, AvroError> {
+    let descriptors = Descriptors::new(set);
+    let mut schemas = Vec::new();
+    for file in &set.file {
+        for message in &file.message_type {
+            let full_name = qualify(file.package(), message.name());
+            let schema = Schema::parse(&descriptors.message(&full_name, &mut Vec::new())?)?;
+            schemas.push((full_name[1..].to_owned(), schema));
+        }
+    }
+    Ok(schemas)
+}
+
+/// `.`-prefixed full name of a type declared in `scope`, as used by the `type_name` of fields.
+fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        format!(".{}", name)
+    } else {
+        format!(".{}.{}", scope, name)
+    }
+}
+
+/// Named type along with its Avro namespace.
+struct Declared<'a, T> {
+    descriptor: &'a T,
+    namespace: String,
+    proto3: bool,
+}
+
+/// Messages and enums of a `FileDescriptorSet`, by full name.
+struct Descriptors<'a> {
+    messages: HashMap<String, Declared<'a, DescriptorProto>>,
+    enums: HashMap<String, Declared<'a, EnumDescriptorProto>>,
+}
+
+impl<'a> Descriptors<'a> {
+    fn new(set: &'a FileDescriptorSet) -> Descriptors<'a> {
+        let mut descriptors = Descriptors {
+            messages: HashMap::new(),
+            enums: HashMap::new(),
+        };
+        for file in &set.file {
+            let proto3 = file.syntax() == "proto3";
+            for message in &file.message_type {
+                descriptors.add_message(message, file.package(), proto3);
+            }
+            for descriptor in &file.enum_type {
+                descriptors.enums.insert(qualify(file.package(), descriptor.name()), Declared {
+                    descriptor,
+                    namespace: file.package().to_owned(),
+                    proto3,
+                });
+            }
+        }
+        descriptors
+    }
+
+    fn add_message(&mut self, message: &'a DescriptorProto, scope: &str, proto3: bool) {
+        let full_name = qualify(scope, message.name());
+        let inner_scope = full_name[1..].to_owned();
+        for nested in &message.nested_type {
+            self.add_message(nested, &inner_scope, proto3);
+        }
+        for descriptor in &message.enum_type {
+            self.enums.insert(qualify(&inner_scope, descriptor.name()), Declared {
+                descriptor,
+                namespace: inner_scope.clone(),
+                proto3,
+            });
+        }
+        self.messages.insert(full_name, Declared {
+            descriptor: message,
+            namespace: scope.to_owned(),
+            proto3,
+        });
+    }
+
+    /// JSON Avro schema of the message of the given full name. `stack` holds the messages being
+    /// converted, to tell recursive messages.
+    fn message(&self, full_name: &str, stack: &mut Vec<String>) -> Result<JsonValue, AvroError> {
+        let message = self.messages.get(full_name).ok_or_else(|| {
+            AvroError::Resolution(format!("unknown message {}", &full_name[1..]))
+        })?;
+        if stack.iter().any(|name| name == full_name) {
+            return Err(AvroError::Resolution(format!(
+                "recursive message {} has no Avro counterpart",
+                &full_name[1..]
+            )))
+        }
+        stack.push(full_name.to_owned());
+
+        let descriptor = message.descriptor;
+        let mut fields = Vec::with_capacity(descriptor.field.len());
+        for (position, field) in descriptor.field.iter().enumerate() {
+            let oneof = field.oneof_index.filter(|_| !field.proto3_optional());
+            match oneof {
+                // a oneof is converted at its first field
+                Some(index) => {
+                    if descriptor.field[..position]
+                        .iter()
+                        .any(|other| other.oneof_index == Some(index) && !other.proto3_optional())
+                    {
+                        continue
+                    }
+                    let mut types = vec![json!("null")];
+                    for member in &descriptor.field {
+                        if member.oneof_index == Some(index) && !member.proto3_optional() {
+                            types.push(self.value_type(member, stack)?);
+                        }
+                    }
+                    let name = descriptor
+                        .oneof_decl
+                        .get(index as usize)
+                        .map(|oneof| oneof.name())
+                        .unwrap_or_default();
+                    fields.push(json!({"name": name, "type": types, "default": null}));
+                },
+                None => fields.push(self.field(field, message.proto3, stack)?),
+            }
+        }
+        stack.pop();
+
+        let mut schema = Map::new();
+        schema.insert("type".to_owned(), json!("record"));
+        schema.insert("name".to_owned(), json!(descriptor.name()));
+        if !message.namespace.is_empty() {
+            schema.insert("namespace".to_owned(), json!(message.namespace));
+        }
+        schema.insert("fields".to_owned(), JsonValue::Array(fields));
+        Ok(JsonValue::Object(schema))
+    }
+
+    /// JSON Avro record field of a message field outside of a `oneof`.
+    fn field(
+        &self,
+        field: &FieldDescriptorProto,
+        proto3: bool,
+        stack: &mut Vec<String>,
+    ) -> Result<JsonValue, AvroError> {
+        let (schema, default) = if let Some(entry) = self.map_entry(field) {
+            let value = entry
+                .field
+                .iter()
+                .find(|field| field.number() == 2)
+                .ok_or_else(|| AvroError::Resolution(
+                    "map entry without value".to_owned(),
+                ))?;
+            (json!({"type": "map", "values": self.value_type(value, stack)?}), json!({}))
+        } else if field.label() == Label::Repeated {
+            (json!({"type": "array", "items": self.value_type(field, stack)?}), json!([]))
+        } else if field.r#type() == Type::Message
+            || field.proto3_optional()
+            || (!proto3 && field.label() == Label::Optional)
+        {
+            (json!(["null", self.value_type(field, stack)?]), JsonValue::Null)
+        } else {
+            let default = self.default(field)?;
+            (self.value_type(field, stack)?, default)
+        };
+        Ok(json!({"name": field.name(), "type": schema, "default": default}))
+    }
+
+    /// Entry message of a `map` field, if the field is one.
+    fn map_entry(&self, field: &FieldDescriptorProto) -> Option<&'a DescriptorProto> {
+        if field.label() != Label::Repeated || field.r#type() != Type::Message {
+            return None
+        }
+        self.messages
+            .get(field.type_name())
+            .map(|message| message.descriptor)
+            .filter(|entry| entry.options.as_ref().is_some_and(|options| options.map_entry()))
+    }
+
+    /// JSON Avro schema of a single value of a field.
+    fn value_type(
+        &self,
+        field: &FieldDescriptorProto,
+        stack: &mut Vec<String>,
+    ) -> Result<JsonValue, AvroError> {
+        Ok(match field.r#type() {
+            Type::Double => json!("double"),
+            Type::Float => json!("float"),
+            Type::Int32 | Type::Sint32 | Type::Sfixed32 => json!("int"),
+            // unsigned 64 bits integers wrap past i64::MAX
+            Type::Int64
+            | Type::Sint64
+            | Type::Sfixed64
+            | Type::Uint32
+            | Type::Fixed32
+            | Type::Uint64
+            | Type::Fixed64 => json!("long"),
+            Type::Bool => json!("boolean"),
+            Type::String => json!("string"),
+            Type::Bytes => json!("bytes"),
+            Type::Message if field.type_name() == TIMESTAMP => json!("date"),
+            Type::Message => self.message(field.type_name(), stack)?,
+            Type::Enum => {
+                let declared = self.enum_type(field)?;
+                let mut schema = Map::new();
+                schema.insert("type".to_owned(), json!("enum"));
+                schema.insert("name".to_owned(), json!(declared.descriptor.name()));
+                if !declared.namespace.is_empty() {
+                    schema.insert("namespace".to_owned(), json!(declared.namespace));
+                }
+                let symbols = declared.descriptor.value.iter().map(|value| value.name());
+                schema.insert("symbols".to_owned(), json!(symbols.collect::<Vec<_>>()));
+                JsonValue::Object(schema)
+            },
+            Type::Group => {
+                return Err(AvroError::Resolution(format!(
+                    "group field {} has no Avro counterpart",
+                    field.name()
+                )))
+            },
+        })
+    }
+
+    fn enum_type(
+        &self,
+        field: &FieldDescriptorProto,
+    ) -> Result<&Declared<'a, EnumDescriptorProto>, AvroError> {
+        self.enums.get(field.type_name()).ok_or_else(|| {
+            AvroError::Resolution(format!("unknown enum {}", field.type_name()))
+        })
+    }
+
+    /// Protobuf default of a field without presence, as a JSON Avro default.
+    fn default(&self, field: &FieldDescriptorProto) -> Result<JsonValue, AvroError> {
+        Ok(match field.r#type() {
+            Type::Double | Type::Float => json!(0.0),
+            Type::Bool => json!(false),
+            Type::String | Type::Bytes => json!(""),
+            Type::Enum => {
+                let declared = self.enum_type(field)?;
+                // the default is the value numbered 0 in proto3, the first value in proto2
+                let value = declared
+                    .descriptor
+                    .value
+                    .iter()
+                    .find(|value| !declared.proto3 || value.number() == 0)
+                    .or_else(|| declared.descriptor.value.first());
+                json!(value.map(|value| value.name()))
+            },
+            _ => json!(0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ::prost_types::{
+        EnumValueDescriptorProto, FileDescriptorProto, MessageOptions, OneofDescriptorProto,
+    };
+
+    fn field(name: &str, number: i32, label: Label, r#type: Type) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_owned()),
+            number: Some(number),
+            label: Some(label as i32),
+            r#type: Some(r#type as i32),
+            ..Default::default()
+        }
+    }
+
+    fn typed(mut field: FieldDescriptorProto, type_name: &str) -> FieldDescriptorProto {
+        field.type_name = Some(type_name.to_owned());
+        field
+    }
+
+    fn message(name: &str, field: Vec<FieldDescriptorProto>) -> DescriptorProto {
+        DescriptorProto {
+            name: Some(name.to_owned()),
+            field,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_protobuf_to_avro_schema() {
+        let mut tags = message("TagsEntry", vec![
+            field("key", 1, Label::Optional, Type::String),
+            field("value", 2, Label::Optional, Type::Int64),
+        ]);
+        tags.options = Some(MessageOptions {
+            map_entry: Some(true),
+            ..Default::default()
+        });
+        let line = message("Line", vec![
+            field("sku", 1, Label::Optional, Type::String),
+            field("quantity", 2, Label::Optional, Type::Uint32),
+        ]);
+        let mut order = message("Order", vec![
+            field("id", 1, Label::Optional, Type::Int64),
+            typed(field("status", 2, Label::Optional, Type::Enum), ".shop.Status"),
+            typed(field("lines", 3, Label::Repeated, Type::Message), ".shop.Order.Line"),
+            typed(field("tags", 4, Label::Repeated, Type::Message), ".shop.Order.TagsEntry"),
+            typed(field("at", 5, Label::Optional, Type::Message), TIMESTAMP),
+            field("card", 6, Label::Optional, Type::String),
+            field("points", 7, Label::Optional, Type::Int32),
+            field("note", 8, Label::Optional, Type::String),
+        ]);
+        order.field[5].oneof_index = Some(0);
+        order.field[6].oneof_index = Some(0);
+        order.field[7].oneof_index = Some(1);
+        order.field[7].proto3_optional = Some(true);
+        order.oneof_decl = vec![
+            OneofDescriptorProto {
+                name: Some("payment".to_owned()),
+                ..Default::default()
+            },
+            OneofDescriptorProto {
+                name: Some("_note".to_owned()),
+                ..Default::default()
+            },
+        ];
+        order.nested_type = vec![line, tags];
+        let status = EnumDescriptorProto {
+            name: Some("Status".to_owned()),
+            value: ["NEW", "PAID"]
+                .iter()
+                .enumerate()
+                .map(|(number, name)| EnumValueDescriptorProto {
+                    name: Some(name.to_string()),
+                    number: Some(number as i32),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("shop.proto".to_owned()),
+                package: Some("shop".to_owned()),
+                syntax: Some("proto3".to_owned()),
+                message_type: vec![order],
+                enum_type: vec![status],
+                ..Default::default()
+            }],
+        };
+        let set = parse_file_descriptor_set(&set.encode_to_vec()).unwrap();
+
+        let expected = Schema::parse_str(
+            r#"{"type": "record", "name": "Order", "namespace": "shop", "fields": [
+                {"name": "id", "type": "long", "default": 0},
+                {"name": "status", "type": {
+                    "type": "enum", "name": "Status", "namespace": "shop",
+                    "symbols": ["NEW", "PAID"]
+                }, "default": "NEW"},
+                {"name": "lines", "type": {"type": "array", "items": {
+                    "type": "record", "name": "Line", "namespace": "shop.Order", "fields": [
+                        {"name": "sku", "type": "string", "default": ""},
+                        {"name": "quantity", "type": "long", "default": 0}
+                    ]
+                }}, "default": []},
+                {"name": "tags", "type": {"type": "map", "values": "long"}, "default": {}},
+                {"name": "at", "type": ["null", "date"], "default": null},
+                {"name": "payment", "type": ["null", "string", "int"], "default": null},
+                {"name": "note", "type": ["null", "string"], "default": null}
+            ]}"#,
+        ).unwrap();
+        assert_eq!(to_avro_schema(&set, "shop.Order").unwrap(), expected);
+        let schemas = to_avro_schemas(&set).unwrap();
+        assert_eq!(schemas, vec![("shop.Order".to_owned(), expected)]);
+
+        assert!(to_avro_schema(&set, "shop.Missing").is_err());
+        // a message holding itself
+        let mut set = set;
+        set.file[0].message_type[0]
+            .field
+            .push(typed(field("parent", 9, Label::Optional, Type::Message), ".shop.Order"));
+        let error = to_avro_schema(&set, ".shop.Order").unwrap_err();
+        assert!(error.to_string().contains("recursive message shop.Order"), "{}", error);
+    }
+}
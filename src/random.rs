@@ -0,0 +1,233 @@
+//! Logic for generating random values of a schema, for load tests and fixtures.
+use std::collections::{HashMap, HashSet};
+
+use chrono::Utc;
+use rand::{Rng, SeedableRng, StdRng};
+
+use crate::bloom::BloomFilter;
+use crate::hll::HyperLogLog;
+use crate::json::default_to_value;
+use crate::lru::LruMap;
+use crate::schema::Schema;
+use crate::topk::TopK;
+use crate::types::{LruValue, SetItem, Value};
+
+// largest number of items of a collection, and of characters or bytes of a string or bytes
+const MAX_ITEMS: usize = 4;
+const MAX_LEN: usize = 12;
+const DAY_MILLIS: i64 = 24 * 60 * 60 * 1000;
+
+/// Iterator over random values valid for a [Schema](schema/enum.Schema.html), never ending.
+///
+/// Record fields with a default take it once in four values, unions take any of their branches,
+/// and `bounded_list`s and `lru_set`s stay within their limits. Dates and the access times of
+/// `lru_set` entries fall within the year, or the time-to-live of the set, before the reference
+/// time, which is the current time unless set with [`set_now`](#method.set_now).
+pub struct RandomValues<'a> {
+    schema: &'a Schema,
+    rng: StdRng,
+    now: i64,
+}
+
+impl<'a> RandomValues<'a> {
+    /// Create a `RandomValues` of `schema`, generating other values on every run.
+    pub fn new(schema: &'a Schema) -> RandomValues<'a> {
+        Self::with_seed(schema, rand::random())
+    }
+
+    /// Create a `RandomValues` of `schema`, generating the same values for the same `seed` and
+    /// reference time.
+    pub fn with_seed(schema: &'a Schema, seed: u64) -> RandomValues<'a> {
+        RandomValues {
+            schema,
+            rng: StdRng::from_seed(&[seed as usize, (seed >> 32) as usize][..]),
+            now: Utc::now().timestamp_millis(),
+        }
+    }
+
+    /// Set the reference time of dates and access times, in milliseconds since epoch.
+    pub fn set_now(&mut self, now: i64) {
+        self.now = now;
+    }
+
+    fn value(&mut self, schema: &Schema) -> Value {
+        match *schema {
+            Schema::Null => Value::Null,
+            Schema::Boolean => Value::Boolean(self.rng.gen(), None),
+            Schema::Int => Value::Int(self.rng.gen(), None),
+            Schema::Long => Value::Long(self.rng.gen(), None),
+            Schema::Float => Value::Float(self.rng.gen_range(-1000.0, 1000.0), None),
+            Schema::Double => Value::Double(self.rng.gen_range(-1000.0, 1000.0), None),
+            Schema::Bytes => Value::Bytes(self.bytes(), None),
+            Schema::String => Value::String(self.string(), None),
+            Schema::Fixed { size, .. } => {
+                Value::Fixed(size, (0..size).map(|_| self.rng.gen()).collect(), None)
+            },
+            Schema::Enum { ref symbols, .. } => {
+                let index = self.rng.gen_range(0, symbols.len());
+                Value::Enum(index as i32, symbols[index].clone(), None)
+            },
+            Schema::Union(ref union) => {
+                let variants = union.variants();
+                let index = self.rng.gen_range(0, variants.len());
+                Value::Union(Box::new(self.value(&variants[index])), None)
+            },
+            Schema::Optional(ref inner) => {
+                let value = if self.rng.gen_weighted_bool(3) {
+                    None
+                } else {
+                    Some(Box::new(self.value(inner)))
+                };
+                Value::Optional(value, None)
+            },
+            Schema::Array(ref items) => Value::Array(self.items(items, MAX_ITEMS), None),
+            Schema::BoundedList {
+                ref items, max_len, ..
+            } => Value::Array(self.items(items, max_len.min(MAX_ITEMS)), None),
+            Schema::Map(ref values) => {
+                let len = self.rng.gen_range(0, MAX_ITEMS + 1);
+                let map = (0..len)
+                    .map(|_| (self.string(), self.value(values)))
+                    .collect::<HashMap<_, _>>();
+                Value::Map(map, None)
+            },
+            Schema::Record { ref fields, .. } => {
+                let fields = fields
+                    .iter()
+                    .map(|field| {
+                        let default = field
+                            .default
+                            .as_ref()
+                            .filter(|_| self.rng.gen_weighted_bool(4))
+                            .and_then(|default| default_to_value(default, &field.schema).ok());
+                        let value = default.unwrap_or_else(|| self.value(&field.schema));
+                        (field.name.clone(), value)
+                    })
+                    .collect();
+                Value::Record(fields, None)
+            },
+            Schema::Date(_) => {
+                Value::Date(self.now - self.rng.gen_range(0, 365 * DAY_MILLIS), None)
+            },
+            Schema::Set(ref items) => {
+                let len = self.rng.gen_range(0, MAX_ITEMS + 1);
+                let set = (0..len)
+                    .filter_map(|_| match self.value(items) {
+                        Value::Int(n, _) => Some(SetItem::Int(n)),
+                        Value::Long(n, _) => Some(SetItem::Long(n)),
+                        Value::Bytes(bytes, _) => Some(SetItem::Bytes(bytes)),
+                        Value::String(string, _) => Some(SetItem::String(string)),
+                        Value::Enum(index, symbol, _) => Some(SetItem::Enum(index, symbol)),
+                        _ => None,
+                    })
+                    .collect::<HashSet<_>>();
+                Value::Set(set, None)
+            },
+            Schema::LruSet(ref limit, _) => {
+                let ttl = limit.ttl_millis().unwrap_or(DAY_MILLIS).max(1);
+                let len = self.rng.gen_range(0, MAX_ITEMS + 1);
+                let len = limit.max_entries().map_or(len, |max_entries| len.min(max_entries));
+                let mut entries = (0..len)
+                    .map(|_| {
+                        let access_time = self.now - self.rng.gen_range(0, ttl);
+                        (self.string(), LruValue::new(access_time, self.rng.gen_range(1, 100)))
+                    })
+                    .collect::<Vec<_>>();
+                // inserted from the least to the most recently accessed entry
+                entries.sort_by_key(|(_, value)| value.access_time);
+                let mut items = LruMap::new();
+                for (key, value) in entries {
+                    items.insert(key, value);
+                }
+                // for the limits on the size of the entries
+                items.evict(limit, Some(self.now));
+                Value::LruSet(Box::new(items), limit.clone(), None)
+            },
+            Schema::Counter => Value::Counter(self.rng.gen_range(0, 1000), None),
+            Schema::Hll(precision) => {
+                let mut sketch =
+                    HyperLogLog::new(precision).expect("Invalid HyperLogLog precision");
+                for _ in 0..self.rng.gen_range(0, MAX_ITEMS + 1) {
+                    sketch.add(&self.string());
+                }
+                Value::Hll(sketch, None)
+            },
+            Schema::Bloom { bits, hashes } => {
+                let mut filter = BloomFilter::new(bits, hashes).expect("Invalid bloom filter size");
+                for _ in 0..self.rng.gen_range(0, MAX_ITEMS + 1) {
+                    filter.insert(&self.string());
+                }
+                Value::Bloom(filter, None)
+            },
+            Schema::TopK(size) => {
+                let mut topk = TopK::new(size);
+                for _ in 0..self.rng.gen_range(0, MAX_ITEMS + 1) {
+                    let count = self.rng.gen_range(1, 100);
+                    topk.insert(self.string(), count);
+                }
+                Value::TopK(Box::new(topk), None)
+            },
+        }
+    }
+
+    fn items(&mut self, items: &Schema, max_len: usize) -> Vec<Value> {
+        let len = self.rng.gen_range(0, max_len + 1);
+        (0..len).map(|_| self.value(items)).collect()
+    }
+
+    fn bytes(&mut self) -> Vec<u8> {
+        let len = self.rng.gen_range(0, MAX_LEN + 1);
+        (0..len).map(|_| self.rng.gen()).collect()
+    }
+
+    fn string(&mut self) -> String {
+        let len = self.rng.gen_range(0, MAX_LEN + 1);
+        self.rng.gen_ascii_chars().take(len).collect()
+    }
+}
+
+impl<'a> Iterator for RandomValues<'a> {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Value> {
+        let schema = self.schema;
+        Some(self.value(schema))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_values() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "event", "fields": [
+                {"name": "id", "type": "long"},
+                {"name": "kind", "type": {"type": "enum", "name": "kind", "symbols": ["A", "B"]}},
+                {"name": "tags", "type": {"type": "map", "values": ["null", "string", "double"]}},
+                {"name": "score", "type": "int", "default": 7},
+                {"name": "at", "type": "date"},
+                {"name": "seen", "type": {"type": "set", "items": "string"}},
+                {"name": "recent", "type": {"type": "lru_set", "max_entries": 2}},
+                {"name": "last", "type": {"type": "bounded_list", "items": "int", "max_length": 1}},
+                {"name": "note", "type": {"type": "optional", "value": "string"}},
+                {"name": "uniques", "type": {"type": "hll", "precision": 8}},
+                {"name": "top", "type": {"type": "topk", "size": 2}}
+            ]}"#,
+        ).unwrap();
+        let values = RandomValues::with_seed(&schema, 42).take(200).collect::<Vec<_>>();
+        assert!(values.iter().all(|value| value.validate(&schema)));
+        assert!(values.iter().any(|value| value.field("score") == Some(&Value::Int(7, None))));
+        assert!(values.iter().all(|value| match value.field("recent") {
+            Some(Value::LruSet(items, _, _)) => items.len() <= 2,
+            _ => false,
+        }));
+
+        let mut again = RandomValues::with_seed(&schema, 42);
+        again.set_now(0);
+        let mut other = RandomValues::with_seed(&schema, 42);
+        other.set_now(0);
+        assert_eq!(again.take(10).collect::<Vec<_>>(), other.take(10).collect::<Vec<_>>());
+    }
+}
@@ -1,15 +1,34 @@
 //! Logic handling reading from Avro format at user level.
-use std::io::{ErrorKind, Read};
+use std::collections::{BTreeSet, HashMap};
+#[cfg(feature = "rayon")]
+use std::collections::BTreeMap;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
 use std::str::{from_utf8, FromStr};
+#[cfg(feature = "rayon")]
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+#[cfg(feature = "rayon")]
+use std::vec;
 
-use failure::Error;
 use serde_json::from_slice;
 
-use crate::decode::decode;
-use crate::schema::ParseSchemaError;
-use crate::schema::Schema;
-use crate::types::Value;
-use crate::util::{self, DecodeError};
+use crate::borrowed::ValueRef;
+use crate::decode::{decode, decode_events, decode_lazy, decode_ref, PartialRecord, Projection};
+use crate::encode::encode_to_vec;
+use crate::error::{in_block, AvroError};
+use crate::lazy::LazyValue;
+use crate::metrics::{Metrics, SharedMetrics};
+use crate::index::{
+    field_schema, field_value, index_key, FileIndex, IndexCollector, SidecarIndex,
+};
+use crate::plan::ResolutionPlan;
+use crate::pool::ValuePool;
+use crate::predicate::{CompiledPredicate, Predicate};
+use crate::schema::{ParseOptions, Schema};
+use crate::date::DateParsing;
+use crate::events::DecodeHandler;
+use crate::types::{ResolveOptions, UnknownFieldPolicy, Value};
+use crate::util::{self, DecodeOptions};
 use crate::Codec;
 
 // Internal Block reader.
@@ -21,13 +40,20 @@ struct Block<R> {
     buf_idx: usize,
     // Number of elements expected to exist within this block.
     message_count: usize,
+    // Number of data blocks read or skipped so far, unknown after seeking.
+    blocks: Option<usize>,
     marker: [u8; 16],
     codec: Codec,
     writer_schema: Schema,
+    // entries of the header outside of the `avro.` namespace
+    user_metadata: HashMap<String, Vec<u8>>,
+    metrics: SharedMetrics,
+    // limits the blocks and their items are decoded with
+    options: DecodeOptions,
 }
 
 impl<R: Read> Block<R> {
-    fn new(reader: R) -> Result<Block<R>, Error> {
+    fn new(reader: R, parse_options: &ParseOptions) -> Result<Block<R>, AvroError> {
         let mut block = Block {
             reader,
             codec: Codec::Null,
@@ -35,23 +61,27 @@ impl<R: Read> Block<R> {
             buf: vec![],
             buf_idx: 0,
             message_count: 0,
+            blocks: Some(0),
             marker: [0; 16],
+            user_metadata: HashMap::new(),
+            metrics: SharedMetrics::default(),
+            options: DecodeOptions::default(),
         };
 
-        block.read_header()?;
+        block.read_header(parse_options)?;
         Ok(block)
     }
 
     /// Try to read the header and to set the writer `Schema`, the `Codec` and the marker based on
     /// its content.
-    fn read_header(&mut self) -> Result<(), Error> {
+    fn read_header(&mut self, parse_options: &ParseOptions) -> Result<(), AvroError> {
         let meta_schema = Schema::Map(Box::new(Schema::Bytes));
 
         let mut buf = [0u8; 4];
         self.reader.read_exact(&mut buf)?;
 
         if buf != [b'O', b'b', b'j', 1u8] {
-            return Err(DecodeError::new("wrong magic in header").into())
+            return Err(AvroError::Decode("wrong magic in header".to_owned()))
         }
 
         if let Value::Map(meta, _) = decode(&meta_schema, &mut self.reader)? {
@@ -65,11 +95,11 @@ impl<R: Read> Block<R> {
                         None
                     }
                 })
-                .and_then(|json| Schema::parse(&json).ok());
+                .and_then(|json| Schema::parse_with_options(&json, parse_options).ok());
             if let Some(schema) = schema {
                 self.writer_schema = schema;
             } else {
-                return Err(ParseSchemaError::new("unable to parse schema").into())
+                return Err(AvroError::Parse("unable to parse schema".to_owned()))
             }
 
             if let Some(codec) = meta
@@ -85,45 +115,56 @@ impl<R: Read> Block<R> {
             {
                 self.codec = codec;
             }
+
+            self.user_metadata = meta
+                .into_iter()
+                .filter(|(key, _)| !key.starts_with("avro."))
+                .filter_map(|(key, value)| match value {
+                    Value::Bytes(bytes, _) => Some((key, bytes)),
+                    _ => None,
+                })
+                .collect();
         } else {
-            return Err(DecodeError::new("no metadata in header").into())
+            return Err(AvroError::Decode("no metadata in header".to_owned()))
         }
 
         let mut buf = [0u8; 16];
         self.reader.read_exact(&mut buf)?;
         self.marker = buf;
 
+        trace_event!(debug, codec = ?self.codec, "read Avro header");
         Ok(())
     }
 
-    fn fill_buf(&mut self, n: usize) -> Result<(), Error> {
-        // We don't have enough space in the buffer, need to grow it.
-        if n >= self.buf.capacity() {
-            self.buf.reserve(n);
-        }
-
-        unsafe {
-            self.buf.set_len(n);
-        }
-        self.reader.read_exact(&mut self.buf[..n])?;
+    fn fill_buf(&mut self, n: usize) -> Result<(), AvroError> {
+        // the buffer grows as the block is read, not to the size it claims
+        self.buf.clear();
+        util::read_len(&mut self.reader, n, &mut self.buf)?;
         self.buf_idx = 0;
         Ok(())
     }
 
     /// Try to read a data block, also performing schema resolution for the objects contained in
     /// the block. The objects are stored in an internal buffer to the `Reader`.
-    fn read_block_next(&mut self) -> Result<(), Error> {
+    fn read_block_next(&mut self) -> Result<(), AvroError> {
+        let _options = self.options.enter();
         assert!(self.is_empty(), "Expected self to be empty!");
         match util::read_long(&mut self.reader) {
             Ok(block_len) => {
-                self.message_count = block_len as usize;
+                self.blocks = self.blocks.map(|blocks| blocks + 1);
+                let _span = trace_span!(debug_span, "read_block", block = ?self.blocks);
+                self.message_count = util::usize_len(block_len)?;
                 let block_bytes = util::read_long(&mut self.reader)?;
-                self.fill_buf(block_bytes as usize)?;
+                self.fill_buf(util::block_len(util::usize_len(block_bytes)?)?)?;
                 let mut marker = [0u8; 16];
                 self.reader.read_exact(&mut marker)?;
+                self.metrics.0.blocks_read(1);
+                self.metrics.0.bytes_read(self.buf.len() as u64);
 
                 if marker != self.marker {
-                    return Err(DecodeError::new("block marker does not match header marker").into())
+                    return Err(AvroError::Decode(
+                        "block marker does not match header marker".to_owned(),
+                    ))
                 }
 
                 // NOTE (JAB): This doesn't fit this Reader pattern very well.
@@ -133,26 +174,77 @@ impl<R: Read> Block<R> {
                 // We can address this by using some "limited read" type to decode directly
                 // into the buffer. But this is fine, for now.
                 self.codec.decompress(&mut self.buf)?;
+                trace_event!(
+                    debug,
+                    records = self.message_count,
+                    bytes = self.buf.len(),
+                    "read block"
+                );
 
                 return Ok(())
             },
-            Err(e) => if let ErrorKind::UnexpectedEof = e.downcast::<::std::io::Error>()?.kind() {
+            Err(AvroError::Io(e)) => if let ErrorKind::UnexpectedEof = e.kind() {
                 // to not return any error in case we only finished to read cleanly from the stream
                 return Ok(())
             },
+            Err(e) => return Err(e),
+        };
+        Err(AvroError::Decode("unable to read block".to_owned()))
+    }
+
+    /// Read the next data block of the stream without decompressing it, returning its object
+    /// count and its bytes, or `None` at the end of the stream.
+    fn read_raw_block(&mut self) -> Result<Option<(usize, Vec<u8>)>, AvroError> {
+        let _options = self.options.enter();
+        assert!(self.is_empty(), "Expected self to be empty!");
+        let block_len = match util::read_long(&mut self.reader) {
+            Ok(block_len) => block_len,
+            Err(AvroError::Io(e)) => {
+                return match e.kind() {
+                    ErrorKind::UnexpectedEof => Ok(None),
+                    _ => Err(AvroError::Decode("unable to read block".to_owned())),
+                }
+            },
+            Err(e) => return Err(e),
         };
-        Err(DecodeError::new("unable to read block").into())
+        self.blocks = self.blocks.map(|blocks| blocks + 1);
+        let block_len = util::usize_len(block_len)?;
+        let block_bytes = util::read_long(&mut self.reader)?;
+        let len = util::block_len(util::usize_len(block_bytes)?)?;
+        let mut bytes = Vec::new();
+        util::read_len(&mut self.reader, len, &mut bytes)?;
+        let mut marker = [0u8; 16];
+        self.reader.read_exact(&mut marker)?;
+        if marker != self.marker {
+            return Err(AvroError::Decode("block marker does not match header marker".to_owned()))
+        }
+        Ok(Some((block_len, bytes)))
     }
 
     fn len(&self) -> usize {
         self.message_count
     }
 
+    /// Index of the current data block, if known.
+    fn block_index(&self) -> Option<usize> {
+        self.blocks.and_then(|blocks| blocks.checked_sub(1))
+    }
+
+    /// Give the error of decoding the next item the current data block and the offset of the item.
+    fn locate<T>(&self, result: Result<T, AvroError>) -> Result<T, AvroError> {
+        in_block(result, self.block_index(), self.buf_idx)
+    }
+
     fn is_empty(&self) -> bool {
         self.len() == 0
     }
 
-    fn read_next(&mut self, read_schema: Option<&Schema>) -> Result<Option<Value>, Error> {
+    fn read_next(
+        &mut self,
+        plan: Option<&ResolutionPlan>,
+        options: &ResolveOptions,
+    ) -> Result<Option<Value>, AvroError> {
+        let _options = self.options.enter();
         if self.is_empty() {
             self.read_block_next()?;
             if self.is_empty() {
@@ -160,15 +252,178 @@ impl<R: Read> Block<R> {
             }
         }
 
+        match plan {
+            Some(plan) => {
+                let mut block_bytes = &self.buf[self.buf_idx..];
+                let b_original = block_bytes.len();
+                let item = self.locate(plan.decode_with_options(&mut block_bytes, options))?;
+                self.buf_idx += b_original - block_bytes.len();
+                self.message_count -= 1;
+                Ok(Some(item))
+            },
+            None => self.decode_next().map(Some),
+        }
+    }
+
+    /// Decode the next item of the current block, which must not be empty, with the writer
+    /// `Schema`.
+    fn decode_next(&mut self) -> Result<Value, AvroError> {
+        let _options = self.options.enter();
         let mut block_bytes = &self.buf[self.buf_idx..];
         let b_original = block_bytes.len();
-        let item = from_avro_datum(&self.writer_schema, &mut block_bytes, read_schema)?;
+        let item = self.locate(decode(&self.writer_schema, &mut block_bytes))?;
         self.buf_idx += b_original - block_bytes.len();
         self.message_count -= 1;
-        Ok(Some(item))
+        Ok(item)
+    }
+
+    /// Decode the next item of the current block, which must not be empty, with the writer
+    /// `Schema`, borrowing from the buffer of the block.
+    fn decode_next_ref(&mut self) -> Result<ValueRef<'_>, AvroError> {
+        let _options = self.options.enter();
+        let mut block_bytes = &self.buf[self.buf_idx..];
+        let b_original = block_bytes.len();
+        let item = self.locate(decode_ref(&self.writer_schema, &mut block_bytes))?;
+        self.buf_idx += b_original - block_bytes.len();
+        self.message_count -= 1;
+        Ok(item)
+    }
+
+    /// Decode the next item of the current block, which must not be empty, with the writer
+    /// `Schema`, as events reported to `handler`.
+    fn decode_next_events<H>(&mut self, handler: &mut H) -> Result<(), AvroError>
+    where
+        H: DecodeHandler + ?Sized,
+    {
+        let _options = self.options.enter();
+        let mut block_bytes = &self.buf[self.buf_idx..];
+        let b_original = block_bytes.len();
+        self.locate(decode_events(&self.writer_schema, &mut block_bytes, handler))?;
+        self.buf_idx += b_original - block_bytes.len();
+        self.message_count -= 1;
+        Ok(())
+    }
+
+    /// Skip the next item of the current block, which must not be empty, keeping it as a
+    /// `LazyValue` of the writer `Schema`.
+    fn decode_next_lazy(&mut self) -> Result<LazyValue<'_>, AvroError> {
+        let _options = self.options.enter();
+        let mut block_bytes = &self.buf[self.buf_idx..];
+        let b_original = block_bytes.len();
+        let item = self.locate(decode_lazy(&self.writer_schema, &mut block_bytes))?;
+        self.buf_idx += b_original - block_bytes.len();
+        self.message_count -= 1;
+        Ok(item)
+    }
+
+    /// Decode the next item of the current block, which must not be empty, with the writer
+    /// `Schema`, taking its allocations from `pool`.
+    fn decode_next_in(&mut self, pool: &mut ValuePool) -> Result<Value, AvroError> {
+        let _options = self.options.enter();
+        let mut block_bytes = &self.buf[self.buf_idx..];
+        let b_original = block_bytes.len();
+        let item = self.locate(pool.decode(&self.writer_schema, &mut block_bytes))?;
+        self.buf_idx += b_original - block_bytes.len();
+        self.message_count -= 1;
+        Ok(item)
+    }
+
+    /// Decode the fields selected by `projection` of the next item of the current block, which
+    /// must not be empty, and decode the whole item only if `accept` accepts them.
+    fn decode_next_if<F>(
+        &mut self,
+        projection: &Projection,
+        accept: F,
+    ) -> Result<Option<Value>, AvroError>
+    where
+        F: FnOnce(&Value) -> bool,
+    {
+        let _options = self.options.enter();
+        let start = self.buf_idx;
+        let mut block_bytes = &self.buf[start..];
+        let b_original = block_bytes.len();
+        let partial = self.locate(projection.decode(&mut block_bytes))?;
+        self.buf_idx += b_original - block_bytes.len();
+        self.message_count -= 1;
+        if !accept(&partial) {
+            return Ok(None)
+        }
+        let item = decode(&self.writer_schema, &mut &self.buf[start..self.buf_idx]);
+        in_block(item, self.block_index(), start).map(Some)
+    }
+
+    /// Decode the next item of the current block, which must not be empty, only if `accept`
+    /// accepts it as a lazily-decoded record. The writer `Schema` must be a record.
+    fn decode_next_with<F>(&mut self, accept: F) -> Result<Option<Value>, AvroError>
+    where
+        F: FnOnce(&PartialRecord) -> bool,
+    {
+        let _options = self.options.enter();
+        let fields = match self.writer_schema {
+            Schema::Record { ref fields, .. } => fields,
+            _ => return Err(AvroError::Decode("Writer schema is not a record".to_owned())),
+        };
+        let start = self.buf_idx;
+        let partial = PartialRecord::new(fields, &self.buf[start..]);
+        let accepted = accept(&partial);
+        self.buf_idx += self.locate(partial.finish())?;
+        self.message_count -= 1;
+        if !accepted {
+            return Ok(None)
+        }
+        let item = decode(&self.writer_schema, &mut &self.buf[start..self.buf_idx]);
+        in_block(item, self.block_index(), start).map(Some)
     }
 }
 
+impl<R: Read + Seek> Block<R> {
+    /// Read the data block starting at `offset`, from the start of the stream, dropping the
+    /// items left in the current block.
+    fn seek_block(&mut self, offset: u64) -> Result<(), AvroError> {
+        self.reader.seek(SeekFrom::Start(offset))?;
+        self.message_count = 0;
+        self.blocks = None;
+        self.read_block_next()
+    }
+
+    /// Skip the next data block without reading its items, which must be done with the current
+    /// block. Return the number of items of the block, or `None` at the end of the stream.
+    fn skip_block_next(&mut self) -> Result<Option<usize>, AvroError> {
+        let _options = self.options.enter();
+        assert!(self.is_empty(), "Expected self to be empty!");
+        let block_len = match util::read_long(&mut self.reader) {
+            Ok(block_len) => block_len,
+            Err(AvroError::Io(e)) => {
+                return match e.kind() {
+                    ErrorKind::UnexpectedEof => Ok(None),
+                    _ => Err(AvroError::Decode("unable to read block".to_owned())),
+                }
+            },
+            Err(e) => return Err(e),
+        };
+        self.blocks = self.blocks.map(|blocks| blocks + 1);
+        let block_len = util::usize_len(block_len)?;
+        // a negative size would seek back to the same block over and over
+        let block_bytes = util::read_long(&mut self.reader)?;
+        util::block_len(util::usize_len(block_bytes)?)?;
+        // the sync marker follows the block
+        let skipped = block_bytes
+            .checked_add(16)
+            .ok_or_else(|| AvroError::Decode(format!("Invalid block size: {}", block_bytes)))?;
+        self.reader.seek(SeekFrom::Current(skipped))?;
+        Ok(Some(block_len))
+    }
+}
+
+/// Number of the data blocks of a file, of their records and of their compressed bytes, as
+/// given by [`Reader::count_blocks`](struct.Reader.html#method.count_blocks).
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct BlockCounts {
+    pub blocks: usize,
+    pub records: u64,
+    pub bytes: u64,
+}
+
 /// Main interface for reading Avro formatted values.
 ///
 /// To be used as an iterator:
@@ -189,6 +444,10 @@ pub struct Reader<'a, R> {
     reader_schema: Option<&'a Schema>,
     errored: bool,
     should_resolve_schema: bool,
+    resolve_options: ResolveOptions,
+    /// Plan of the resolution against the reader `Schema`, if it differs from the writer one.
+    plan: Option<ResolutionPlan>,
+    index: Option<FileIndex>,
 }
 
 impl<'a, R: Read> Reader<'a, R> {
@@ -196,13 +455,29 @@ impl<'a, R: Read> Reader<'a, R> {
     /// No reader `Schema` will be set.
     ///
     /// **NOTE** The avro header is going to be read automatically upon creation of the `Reader`.
-    pub fn new(reader: R) -> Result<Reader<'a, R>, Error> {
-        let block = Block::new(reader)?;
+    pub fn new(reader: R) -> Result<Reader<'a, R>, AvroError> {
+        Reader::with_parse_options(reader, &ParseOptions::default())
+    }
+
+    /// Creates a `Reader` given something implementing the `io::Read` trait to read from, of
+    /// which the writer `Schema` is parsed according to the given
+    /// [ParseOptions](struct.ParseOptions.html), to read legacy data for instance.
+    /// No reader `Schema` will be set.
+    ///
+    /// **NOTE** The avro header is going to be read automatically upon creation of the `Reader`.
+    pub fn with_parse_options(
+        reader: R,
+        options: &ParseOptions,
+    ) -> Result<Reader<'a, R>, AvroError> {
+        let block = Block::new(reader, options)?;
         let reader = Reader {
             block,
             reader_schema: None,
             errored: false,
             should_resolve_schema: false,
+            resolve_options: ResolveOptions::default(),
+            plan: None,
+            index: None,
         };
         Ok(reader)
     }
@@ -211,16 +486,22 @@ impl<'a, R: Read> Reader<'a, R> {
     /// to read from.
     ///
     /// **NOTE** The avro header is going to be read automatically upon creation of the `Reader`.
-    pub fn with_schema(schema: &'a Schema, reader: R) -> Result<Reader<'a, R>, Error> {
-        let block = Block::new(reader)?;
+    pub fn with_schema(schema: &'a Schema, reader: R) -> Result<Reader<'a, R>, AvroError> {
+        let block = Block::new(reader, &ParseOptions::default())?;
         let mut reader = Reader {
             block,
             reader_schema: Some(schema),
             errored: false,
             should_resolve_schema: false,
+            resolve_options: ResolveOptions::default(),
+            plan: None,
+            index: None,
         };
         // Check if the reader and writer schemas disagree.
         reader.should_resolve_schema = reader.writer_schema() != schema;
+        if reader.should_resolve_schema {
+            reader.plan = Some(ResolutionPlan::compile(reader.writer_schema(), schema));
+        }
         Ok(reader)
     }
 
@@ -234,20 +515,790 @@ impl<'a, R: Read> Reader<'a, R> {
         self.reader_schema
     }
 
+    /// Set how the fields of writer records unknown to the reader `Schema` are handled during
+    /// schema resolution. They are ignored by default.
+    pub fn set_unknown_field_policy(&mut self, policy: UnknownFieldPolicy) {
+        self.resolve_options.unknown_fields = policy;
+    }
+
+    /// Set how strings are parsed into `date` values during schema resolution. Only RFC 3339 and
+    /// RFC 2822 timestamps are accepted by default.
+    pub fn set_date_parsing(&mut self, dates: DateParsing) {
+        self.resolve_options.dates = dates;
+    }
+
+    /// Set the limits the blocks read from now on and their records are decoded with, the
+    /// default [DecodeOptions](struct.DecodeOptions.html) otherwise.
+    pub fn set_decode_options(&mut self, options: DecodeOptions) {
+        self.block.options = options;
+    }
+
+    /// Set the [`Metrics`](trait.Metrics.html) the records, blocks and bytes read from now on are
+    /// reported to, as well as decode errors and `lru_set` evictions. Records are reported as they
+    /// are yielded by iterating over the `Reader`, or per block by a
+    /// [ParIter](struct.ParIter.html) as its blocks are decoded.
+    pub fn set_metrics<M: Metrics + 'static>(&mut self, metrics: M) {
+        self.block.metrics = SharedMetrics(Arc::new(metrics));
+    }
+
+    /// Set the secondary index of the file, as collected by
+    /// [`Writer::collect_index`](struct.Writer.html#method.collect_index), used by
+    /// [`find`](struct.Reader.html#method.find).
+    pub fn set_index(&mut self, index: FileIndex) {
+        self.index = Some(index);
+    }
+
+    /// Get a reference to the secondary index of the file, if set.
+    pub fn index(&self) -> Option<&FileIndex> {
+        self.index.as_ref()
+    }
+
+    /// Load the index held by a sidecar index file and set it, after checking that the sidecar
+    /// indexes this file.
+    pub fn set_sidecar_index<I: Read>(
+        &mut self,
+        sidecar: SidecarIndex<I>,
+    ) -> Result<(), AvroError> {
+        sidecar.validate(self.marker(), self.writer_schema())?;
+        self.index = Some(sidecar.load()?);
+        Ok(())
+    }
+
+    /// Get the sync marker of the file.
+    pub fn marker(&self) -> &[u8; 16] {
+        &self.block.marker
+    }
+
+    /// Get the `Codec` the data blocks of the file are compressed with.
+    pub fn codec(&self) -> Codec {
+        self.block.codec
+    }
+
+    /// Get the metadata of the file header set by its writer, that is every entry but the
+    /// reserved `avro.schema` and `avro.codec` ones.
+    pub fn user_metadata(&self) -> &HashMap<String, Vec<u8>> {
+        &self.block.user_metadata
+    }
+
+    /// Read the next data block, decompressed, without decoding its records: return the number of
+    /// records it holds and their bytes in Avro binary format, or `None` at the end of the file.
+    /// The records of the block can be split with [`decode::skip`](decode/fn.skip.html), and
+    /// appended as they are with [`Writer::append_datum`](struct.Writer.html#method.append_datum).
+    ///
+    /// **NOTE** The records of the current block not iterated over yet are skipped.
+    pub fn next_block(&mut self) -> Result<Option<(usize, Vec<u8>)>, AvroError> {
+        let block = &mut self.block;
+        block.buf.clear();
+        block.buf_idx = 0;
+        block.message_count = 0;
+        match block.read_raw_block()? {
+            Some((records, mut bytes)) => {
+                block.codec.decompress(&mut bytes)?;
+                Ok(Some((records, bytes)))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Count the data blocks following the current position (the whole file for a new `Reader`),
+    /// with their records and bytes, reading the blocks without decompressing nor decoding them.
+    ///
+    /// **NOTE** Iterating over the `Reader` afterwards yields no more records.
+    pub fn count_blocks(&mut self) -> Result<BlockCounts, AvroError> {
+        let block = &mut self.block;
+        // the records left in the current block, if any, belong to a block already counted
+        block.buf.clear();
+        block.buf_idx = 0;
+        block.message_count = 0;
+        let mut counts = BlockCounts::default();
+        while let Some((records, bytes)) = block.read_raw_block()? {
+            counts.blocks += 1;
+            counts.records += records as u64;
+            counts.bytes += bytes.len() as u64;
+        }
+        Ok(counts)
+    }
+
+    /// Iterate over the records for which `accept` returns `true`, resolved against the reader
+    /// `Schema` if any. The writer `Schema` must be a record.
+    ///
+    /// `accept` is given each record as a [PartialRecord](decode/struct.PartialRecord.html), of
+    /// which the fields are only decoded when accessed: the bytes of the rejected records are
+    /// skipped, and only the accepted ones are decoded in full.
+    pub fn scan_filtered<F>(self, accept: F) -> ScanFiltered<'a, R, F>
+    where
+        F: FnMut(&PartialRecord) -> bool,
+    {
+        ScanFiltered {
+            reader: self,
+            accept,
+            errored: false,
+        }
+    }
+
+    /// Call `f` on each of the records following the current position (the whole file for a new
+    /// `Reader`), decoded as [ValueRef](enum.ValueRef.html)s which borrow their bytes and strings
+    /// from the buffer of their block instead of copying them. Records are not resolved against
+    /// the reader `Schema`.
+    ///
+    /// Stop at the first error, met while decoding or returned by `f`.
+    pub fn for_each_ref<F>(&mut self, mut f: F) -> Result<(), AvroError>
+    where
+        F: FnMut(ValueRef) -> Result<(), AvroError>,
+    {
+        loop {
+            if self.block.is_empty() {
+                self.block.read_block_next()?;
+                if self.block.is_empty() {
+                    return Ok(())
+                }
+            }
+            f(self.block.decode_next_ref()?)?;
+        }
+    }
+
+    /// Report the records following the current position (the whole file for a new `Reader`) to
+    /// `handler` as the events met while decoding them with the writer `Schema`, without building
+    /// them: only the current block is held in memory, whatever the size of the arrays and maps
+    /// of the records.
+    ///
+    /// Stop at the first error, met while decoding or returned by `handler`.
+    pub fn for_each_event<H>(&mut self, handler: &mut H) -> Result<(), AvroError>
+    where
+        H: DecodeHandler + ?Sized,
+    {
+        loop {
+            if self.block.is_empty() {
+                self.block.read_block_next()?;
+                if self.block.is_empty() {
+                    return Ok(())
+                }
+            }
+            self.block.decode_next_events(handler)?;
+        }
+    }
+
+    /// Call `f` on each of the records following the current position (the whole file for a new
+    /// `Reader`), kept as [LazyValue](struct.LazyValue.html)s of which only the accessed fields
+    /// are decoded. Records are not resolved against the reader `Schema`.
+    ///
+    /// Stop at the first error, met while decoding or returned by `f`.
+    pub fn for_each_lazy<F>(&mut self, mut f: F) -> Result<(), AvroError>
+    where
+        F: FnMut(LazyValue) -> Result<(), AvroError>,
+    {
+        // the records are decoded by `f`
+        let _options = self.block.options.enter();
+        loop {
+            if self.block.is_empty() {
+                self.block.read_block_next()?;
+                if self.block.is_empty() {
+                    return Ok(())
+                }
+            }
+            f(self.block.decode_next_lazy()?)?;
+        }
+    }
+
+    /// Call `f` on each of the records following the current position (the whole file for a new
+    /// `Reader`), decoded into the allocations of the records before them: each record is handed
+    /// back to a [ValuePool](struct.ValuePool.html) once `f` returns, to be reused by the next
+    /// one. Records are not resolved against the reader `Schema`.
+    ///
+    /// Stop at the first error, met while decoding or returned by `f`.
+    pub fn for_each_pooled<F>(&mut self, mut f: F) -> Result<(), AvroError>
+    where
+        F: FnMut(&Value) -> Result<(), AvroError>,
+    {
+        let mut pool = ValuePool::new();
+        loop {
+            if self.block.is_empty() {
+                self.block.read_block_next()?;
+                if self.block.is_empty() {
+                    return Ok(())
+                }
+            }
+            let record = self.block.decode_next_in(&mut pool)?;
+            f(&record)?;
+            pool.recycle(record);
+        }
+    }
+
+    /// Iterate over the records following the current position (the whole file for a new
+    /// `Reader`) in file order, decompressing and decoding their blocks on the global rayon
+    /// thread pool. Records are resolved against the reader `Schema`, if any.
+    ///
+    /// Blocks are still read from the stream by the calling thread.
+    #[cfg(feature = "rayon")]
+    pub fn into_par_iter(self) -> ParIter<'a, R> {
+        self.into_par_iter_on(None, true)
+    }
+
+    /// Iterate over the records following the current position, decoding their blocks in
+    /// parallel as for [`into_par_iter`](#method.into_par_iter), according to the given options.
+    ///
+    /// Fails if the thread pool for `options.threads` threads cannot be built.
+    #[cfg(feature = "rayon")]
+    pub fn into_par_iter_with(self, options: ParallelOptions) -> Result<ParIter<'a, R>, AvroError> {
+        let pool = match options.threads {
+            0 => None,
+            threads => Some(rayon::ThreadPoolBuilder::new().num_threads(threads).build()?),
+        };
+        Ok(self.into_par_iter_on(pool, options.ordered))
+    }
+
+    #[cfg(feature = "rayon")]
+    fn into_par_iter_on(
+        mut self,
+        pool: Option<rayon::ThreadPool>,
+        ordered: bool,
+    ) -> ParIter<'a, R> {
+        let threads = match pool {
+            Some(ref pool) => pool.current_num_threads(),
+            None => rayon::current_num_threads(),
+        };
+        let decoder = Arc::new(BlockDecoder {
+            codec: self.block.codec,
+            writer_schema: self.block.writer_schema.clone(),
+            plan: self.plan.clone(),
+            options: self.resolve_options.clone(),
+            decode_options: self.block.options,
+        });
+        let (results_sender, results) = channel();
+
+        // the records left in the current block, already decompressed, come first
+        let mut first = Vec::new();
+        let mut ready = BTreeMap::new();
+        while !self.block.is_empty() {
+            match self.read_next() {
+                Ok(Some(item)) => first.push(item),
+                Ok(None) => break,
+                Err(e) => {
+                    ready.insert(0, Err(e));
+                    break
+                },
+            }
+        }
+        ready.entry(0).or_insert(Ok(first));
+
+        ParIter {
+            reader: self,
+            pool,
+            decoder,
+            results_sender,
+            results,
+            ready,
+            items: Vec::new().into_iter(),
+            ordered,
+            max_in_flight: threads * 2,
+            in_flight: 0,
+            sent: 1,
+            next: 0,
+            exhausted: false,
+            errored: false,
+        }
+    }
+
+    /// Resolve a record decoded with the writer `Schema` against the reader `Schema`, if any.
+    fn resolve(&self, item: Value) -> Result<Value, AvroError> {
+        match self.reader_schema {
+            Some(reader_schema) if self.should_resolve_schema => {
+                item.resolve_with_options(reader_schema, &self.resolve_options)
+            },
+            _ => Ok(item),
+        }
+    }
+
     #[inline]
-    fn read_next(&mut self) -> Result<Option<Value>, Error> {
-        let read_schema = if self.should_resolve_schema {
-            self.reader_schema
-        } else {
-            None
+    fn read_next(&mut self) -> Result<Option<Value>, AvroError> {
+        // evictions counted before this record are not of this reader
+        util::take_evictions();
+        let next = self.block.read_next(self.plan.as_ref(), &self.resolve_options);
+        let metrics = &self.block.metrics.0;
+        match next {
+            Ok(Some(_)) => metrics.records_read(1),
+            Ok(None) => (),
+            Err(_) => metrics.decode_errors(1),
+        }
+        let evicted = util::take_evictions();
+        if evicted > 0 {
+            metrics.evictions(evicted as u64);
+        }
+        next
+    }
+}
+
+impl<'a, R: Read + Seek> Reader<'a, R> {
+    /// Build the secondary index of the fields flagged with `"index": true` in the writer
+    /// `Schema`, by reading the blocks following the current position (the whole file for a new
+    /// `Reader`), and set it. The index can then be saved with
+    /// [`write_sidecar_index`](fn.write_sidecar_index.html).
+    ///
+    /// **NOTE** Iterating over the `Reader` afterwards yields no more records.
+    pub fn build_index(&mut self) -> Result<&FileIndex, AvroError> {
+        // the items left in the current block cannot be located anymore
+        while !self.block.is_empty() {
+            self.block.decode_next()?;
+        }
+        let schema = self.writer_schema().clone();
+        let mut collector = IndexCollector::new(&schema);
+        let mut ordinal = 0;
+        loop {
+            let offset = self.block.reader.stream_position()?;
+            self.block.read_block_next()?;
+            if self.block.is_empty() {
+                break
+            }
+            while !self.block.is_empty() {
+                collector.add(&self.block.decode_next()?, ordinal);
+                ordinal += 1;
+            }
+            collector.flush_block(offset);
+        }
+        Ok(self.index.insert(collector.into_index()))
+    }
+
+    /// Position the reader on the last `n` records of the stream, so that iterating over it
+    /// yields them, or all the records of the following blocks if there are fewer.
+    ///
+    /// The following blocks are skipped over without being read, but for their record counts,
+    /// before seeking back to the block holding the first of these records.
+    ///
+    /// **NOTE** The records of the current block not iterated over yet are skipped.
+    pub fn seek_to_last(&mut self, n: usize) -> Result<(), AvroError> {
+        // the items left in the current block cannot be seeked back to
+        while !self.block.is_empty() {
+            self.block.decode_next()?;
+        }
+        let mut blocks = Vec::new();
+        loop {
+            let offset = self.block.reader.stream_position()?;
+            match self.block.skip_block_next()? {
+                Some(records) => blocks.push((offset, records)),
+                // the end of the stream, where no block is read
+                None => {
+                    blocks.push((offset, 0));
+                    break
+                },
+            }
+        }
+
+        let (mut start, mut skipped, mut remaining) = (blocks[blocks.len() - 1].0, 0, n);
+        for &(offset, records) in blocks.iter().rev().skip(1) {
+            if remaining == 0 {
+                break
+            }
+            start = offset;
+            skipped = records.saturating_sub(remaining);
+            remaining -= records.min(remaining);
+        }
+        self.block.seek_block(start)?;
+        for _ in 0..skipped {
+            self.block.decode_next()?;
+        }
+        Ok(())
+    }
+
+    /// Find the records whose indexed field at the dot-separated `path` is equal to `value`, in
+    /// file order, resolved against the reader `Schema` if any.
+    ///
+    /// Only the blocks holding such records according to the index set with
+    /// [`set_index`](struct.Reader.html#method.set_index) are read: the reader seeks to each of
+    /// them, with offsets counted from the start of the stream. The value is resolved against
+    /// the schema of the field in the writer `Schema`. Fails if no index is set or if the field is
+    /// not indexed.
+    ///
+    /// **NOTE** Iterating over the `Reader` afterwards yields the records following the last
+    /// block read.
+    pub fn find(&mut self, path: &str, value: Value) -> Result<Vec<Value>, AvroError> {
+        let index = self
+            .index
+            .as_ref()
+            .ok_or_else(|| AvroError::Decode("No index set on the reader".to_owned()))?;
+        if !index.fields().any(|field| field == path) {
+            return Err(AvroError::Decode(format!("Field {} is not indexed", path)))
+        }
+        let key = index_key(self.writer_schema(), path, value)?;
+        let blocks = index
+            .postings(path, &key)
+            .iter()
+            .map(|posting| posting.block_offset)
+            .filter(|offset| index.block_might_contain(*offset, path, &key))
+            .collect::<BTreeSet<_>>();
+        let schema = field_schema(self.writer_schema(), path)
+            .cloned()
+            .ok_or_else(|| AvroError::Decode(format!("No field {} in the writer schema", path)))?;
+
+        let mut found = Vec::new();
+        for offset in blocks {
+            self.block.seek_block(offset)?;
+            while !self.block.is_empty() {
+                let item = self.block.decode_next()?;
+                let matches = field_value(&item, path)
+                    .is_some_and(|field| encode_to_vec(field, &schema) == key);
+                if !matches {
+                    continue
+                }
+                found.push(self.resolve(item)?);
+            }
+        }
+        Ok(found)
+    }
+
+    /// Filter the records following the current position (the whole file for a new `Reader`)
+    /// by a [Predicate](enum.Predicate.html), in file order and resolved against the reader
+    /// `Schema` if any.
+    ///
+    /// If an index is set with [`set_index`](struct.Reader.html#method.set_index), the blocks
+    /// which cannot hold matching records according to their Bloom filters and statistics are
+    /// skipped without being read. The predicate is then evaluated against the fields it depends
+    /// on, decoded alone, and only the matching records are decoded in full.
+    pub fn filter(self, predicate: Predicate) -> Result<Filtered<'a, R>, AvroError> {
+        let predicate = CompiledPredicate::new(predicate, self.writer_schema())?;
+        let projection = Projection::new(self.writer_schema(), &predicate.paths())?;
+        Ok(Filtered {
+            reader: self,
+            predicate,
+            projection,
+            errored: false,
+        })
+    }
+}
+
+/// Iterator over the records of a [Reader](struct.Reader.html) matching a
+/// [Predicate](enum.Predicate.html), created by
+/// [`Reader::filter`](struct.Reader.html#method.filter).
+pub struct Filtered<'a, R> {
+    reader: Reader<'a, R>,
+    predicate: CompiledPredicate,
+    projection: Projection,
+    errored: bool,
+}
+
+impl<'a, R: Read + Seek> Filtered<'a, R> {
+    fn read_next(&mut self) -> Result<Option<Value>, AvroError> {
+        let block = &mut self.reader.block;
+        loop {
+            while !block.is_empty() {
+                let predicate = &self.predicate;
+                let item = block.decode_next_if(&self.projection, |p| predicate.matches(p))?;
+                if let Some(item) = item {
+                    return self.reader.resolve(item).map(Some)
+                }
+            }
+
+            let offset = block.reader.stream_position()?;
+            let skip = match self.reader.index {
+                Some(ref index) => !self.predicate.might_match_block(index, offset)?,
+                None => false,
+            };
+            if skip {
+                if block.skip_block_next()?.is_none() {
+                    return Ok(None)
+                }
+            } else {
+                block.read_block_next()?;
+                if block.is_empty() {
+                    return Ok(None)
+                }
+            }
+        }
+    }
+}
+
+impl<'a, R: Read + Seek> Iterator for Filtered<'a, R> {
+    type Item = Result<Value, AvroError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // to prevent keep on reading after the first error occurs
+        if self.errored {
+            return None
+        };
+        match self.read_next() {
+            Ok(opt) => opt.map(Ok),
+            Err(e) => {
+                self.errored = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+/// Iterator over the records of a [Reader](struct.Reader.html) accepted by a closure, created by
+/// [`Reader::scan_filtered`](struct.Reader.html#method.scan_filtered).
+pub struct ScanFiltered<'a, R, F> {
+    reader: Reader<'a, R>,
+    accept: F,
+    errored: bool,
+}
+
+impl<'a, R, F> ScanFiltered<'a, R, F>
+where
+    R: Read,
+    F: FnMut(&PartialRecord) -> bool,
+{
+    fn read_next(&mut self) -> Result<Option<Value>, AvroError> {
+        let block = &mut self.reader.block;
+        loop {
+            if block.is_empty() {
+                block.read_block_next()?;
+                if block.is_empty() {
+                    return Ok(None)
+                }
+            }
+            if let Some(item) = block.decode_next_with(&mut self.accept)? {
+                return self.reader.resolve(item).map(Some)
+            }
+        }
+    }
+}
+
+impl<'a, R, F> Iterator for ScanFiltered<'a, R, F>
+where
+    R: Read,
+    F: FnMut(&PartialRecord) -> bool,
+{
+    type Item = Result<Value, AvroError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // to prevent keep on reading after the first error occurs
+        if self.errored {
+            return None
+        };
+        match self.read_next() {
+            Ok(opt) => opt.map(Ok),
+            Err(e) => {
+                self.errored = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+/// Options driving how the records of a [Reader](struct.Reader.html) are decoded in parallel by
+/// [`Reader::into_par_iter_with`](struct.Reader.html#method.into_par_iter_with).
+#[cfg(feature = "rayon")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParallelOptions {
+    /// Number of threads of a dedicated rayon thread pool decoding blocks, or `0` to decode them
+    /// on the global rayon thread pool.
+    pub threads: usize,
+    /// Yield the records in file order. Otherwise, the records of a block are yielded as soon as
+    /// it is decoded, which keeps the threads busy when blocks take unequal times to decode.
+    pub ordered: bool,
+}
+
+#[cfg(feature = "rayon")]
+impl Default for ParallelOptions {
+    fn default() -> ParallelOptions {
+        ParallelOptions {
+            threads: 0,
+            ordered: true,
+        }
+    }
+}
+
+/// Data block of a [ParIter](struct.ParIter.html) to decode, as read from the stream.
+#[cfg(feature = "rayon")]
+struct BlockJob {
+    /// Sequence number of the block in the iteration.
+    sequence: usize,
+    /// Index of the block in the file, when known.
+    block: Option<usize>,
+    count: usize,
+    bytes: Vec<u8>,
+}
+
+/// Data block of a [ParIter](struct.ParIter.html) decoded by the thread pool, with the counts
+/// to report to the [`Metrics`](trait.Metrics.html) of its `Reader`.
+#[cfg(feature = "rayon")]
+struct DecodedBlock {
+    sequence: usize,
+    records: Result<Vec<Value>, AvroError>,
+    /// Entries of `lru_set`s evicted while resolving the records.
+    evictions: usize,
+}
+
+/// Decoder of the data blocks of a [ParIter](struct.ParIter.html), shared by its threads.
+#[cfg(feature = "rayon")]
+struct BlockDecoder {
+    codec: Codec,
+    writer_schema: Schema,
+    plan: Option<ResolutionPlan>,
+    options: ResolveOptions,
+    decode_options: DecodeOptions,
+}
+
+#[cfg(feature = "rayon")]
+impl BlockDecoder {
+    fn decode(&self, job: BlockJob) -> Result<Vec<Value>, AvroError> {
+        let BlockJob {
+            block,
+            count,
+            mut bytes,
+            ..
+        } = job;
+        let _options = self.decode_options.enter();
+        self.codec.decompress(&mut bytes)?;
+        let mut block_bytes = &bytes[..];
+        let mut items = Vec::with_capacity(count.min(block_bytes.len()));
+        for _ in 0..count {
+            let offset = bytes.len() - block_bytes.len();
+            let item = match self.plan {
+                Some(ref plan) => plan.decode_with_options(&mut block_bytes, &self.options),
+                None => decode(&self.writer_schema, &mut block_bytes),
+            };
+            items.push(in_block(item, block, offset)?);
+        }
+        Ok(items)
+    }
+}
+
+/// Iterator over the records of a [Reader](struct.Reader.html) of which the blocks are decoded in
+/// parallel, created by [`Reader::into_par_iter`](struct.Reader.html#method.into_par_iter).
+///
+/// Dropping the iterator does not wait for the blocks being decoded, their records are discarded.
+#[cfg(feature = "rayon")]
+pub struct ParIter<'a, R> {
+    reader: Reader<'a, R>,
+    /// Dedicated thread pool decoding the blocks, the global one if `None`.
+    pool: Option<rayon::ThreadPool>,
+    decoder: Arc<BlockDecoder>,
+    results_sender: Sender<DecodedBlock>,
+    results: Receiver<DecodedBlock>,
+    /// Decoded blocks not yielded yet, by sequence number.
+    ready: BTreeMap<usize, Result<Vec<Value>, AvroError>>,
+    items: vec::IntoIter<Value>,
+    ordered: bool,
+    max_in_flight: usize,
+    in_flight: usize,
+    /// Sequence number of the next block to send to the thread pool.
+    sent: usize,
+    /// Sequence number of the next block to yield, when blocks are yielded in order.
+    next: usize,
+    exhausted: bool,
+    errored: bool,
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, R: Read> ParIter<'a, R> {
+    fn read_next(&mut self) -> Result<Option<Value>, AvroError> {
+        loop {
+            if let Some(item) = self.items.next() {
+                return Ok(Some(item))
+            }
+
+            // keep the thread pool busy with the next blocks of the stream
+            while !self.exhausted && self.in_flight < self.max_in_flight {
+                match self.reader.block.read_raw_block() {
+                    Ok(Some((count, bytes))) => {
+                        let metrics = &self.reader.block.metrics.0;
+                        metrics.blocks_read(1);
+                        metrics.bytes_read(bytes.len() as u64);
+                        self.spawn(BlockJob {
+                            sequence: self.sent,
+                            block: self.reader.block.block_index(),
+                            count,
+                            bytes,
+                        });
+                        self.sent += 1;
+                        self.in_flight += 1;
+                    },
+                    Ok(None) => self.exhausted = true,
+                    // yielded after the records of the blocks read before
+                    Err(e) => {
+                        self.reader.block.metrics.0.decode_errors(1);
+                        self.ready.insert(self.sent, Err(e));
+                        self.sent += 1;
+                        self.exhausted = true;
+                    },
+                }
+            }
+
+            let block = if self.ordered {
+                self.ready.remove(&self.next)
+            } else {
+                // errors wait for the blocks in flight, which may come before them in the file
+                let decoded = self.ready.iter().find(|(_, block)| block.is_ok());
+                let sequence = match decoded {
+                    Some((&sequence, _)) => Some(sequence),
+                    None if self.in_flight == 0 => self.ready.keys().next().copied(),
+                    None => None,
+                };
+                sequence.and_then(|sequence| self.ready.remove(&sequence))
+            };
+            match block {
+                Some(block) => {
+                    self.next += 1;
+                    self.items = block?.into_iter();
+                },
+                None if self.in_flight == 0 => return Ok(None),
+                None => {
+                    let block = self
+                        .results
+                        .recv()
+                        .map_err(|_| AvroError::Decode("decoding threads stopped".to_owned()))?;
+                    self.in_flight -= 1;
+                    // reported here as the threads of the pool do not know the metrics
+                    let metrics = &self.reader.block.metrics.0;
+                    match block.records {
+                        Ok(ref records) => metrics.records_read(records.len() as u64),
+                        Err(_) => metrics.decode_errors(1),
+                    }
+                    if block.evictions > 0 {
+                        metrics.evictions(block.evictions as u64);
+                    }
+                    self.ready.insert(block.sequence, block.records);
+                },
+            }
+        }
+    }
+
+    /// Decode a block on the thread pool, sending its records back with its sequence number.
+    fn spawn(&self, job: BlockJob) {
+        let decoder = Arc::clone(&self.decoder);
+        let results = self.results_sender.clone();
+        let task = move || {
+            let sequence = job.sequence;
+            // evictions counted before this block on this thread are not of this iterator
+            util::take_evictions();
+            let records = decoder.decode(job);
+            let evictions = util::take_evictions();
+            // the iterator is gone if the records cannot be sent back
+            let _ = results.send(DecodedBlock {
+                sequence,
+                records,
+                evictions,
+            });
         };
+        match self.pool {
+            Some(ref pool) => pool.spawn(task),
+            None => rayon::spawn(task),
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, R: Read> Iterator for ParIter<'a, R> {
+    type Item = Result<Value, AvroError>;
 
-        self.block.read_next(read_schema)
+    fn next(&mut self) -> Option<Self::Item> {
+        // to prevent keep on reading after the first error occurs
+        if self.errored {
+            return None
+        };
+        match self.read_next() {
+            Ok(opt) => opt.map(Ok),
+            Err(e) => {
+                self.errored = true;
+                Some(Err(e))
+            },
+        }
     }
 }
 
 impl<'a, R: Read> Iterator for Reader<'a, R> {
-    type Item = Result<Value, Error>;
+    type Item = Result<Value, AvroError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         // to prevent keep on reading after the first error occurs
@@ -276,8 +1327,22 @@ pub fn from_avro_datum<R: Read>(
     writer_schema: &Schema,
     reader: &mut R,
     reader_schema: Option<&Schema>,
-) -> Result<Value, Error> {
-    let value = decode(writer_schema, reader)?;
+) -> Result<Value, AvroError> {
+    from_avro_datum_with_options(writer_schema, reader, reader_schema, &DecodeOptions::default())
+}
+
+/// Decode a `Value` encoded in Avro format as for [`from_avro_datum`](fn.from_avro_datum.html),
+/// checked against the limits of the given [DecodeOptions](struct.DecodeOptions.html).
+pub fn from_avro_datum_with_options<R: Read>(
+    writer_schema: &Schema,
+    reader: &mut R,
+    reader_schema: Option<&Schema>,
+    options: &DecodeOptions,
+) -> Result<Value, AvroError> {
+    let value = {
+        let _options = options.enter();
+        decode(writer_schema, reader)?
+    };
     match reader_schema {
         Some(ref schema) => value.resolve(schema),
         None => Ok(value),
@@ -288,6 +1353,7 @@ pub fn from_avro_datum<R: Read>(
 mod tests {
     use super::*;
     use std::io::Cursor;
+    use crate::index::write_sidecar_index;
     use crate::types::{Record, ToAvro};
     use crate::Reader;
 
@@ -337,6 +1403,41 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_from_avro_datum_with_options() {
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+        let encoded: &'static [u8] = &[54, 6, 102, 111, 111];
+        let options = DecodeOptions {
+            max_bytes_len: 2,
+            ..DecodeOptions::default()
+        };
+
+        let error = from_avro_datum_with_options(&schema, &mut &encoded[..], None, &options);
+        assert!(matches!(error.unwrap_err().root(), AvroError::Limit(_)));
+        // the options only apply to the call they are given to
+        assert!(from_avro_datum(&schema, &mut &encoded[..], None).is_ok());
+    }
+
+    #[test]
+    fn test_record_field_names_shared() {
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+        let fields = match schema {
+            Schema::Record { ref fields, .. } => fields,
+            _ => unreachable!(),
+        };
+        let mut encoded: &'static [u8] = &[54, 6, 102, 111, 111];
+
+        let value = from_avro_datum(&schema, &mut encoded, Some(&schema)).unwrap();
+        match value {
+            Value::Record(items, _) => {
+                for ((name, _), field) in items.iter().zip(fields.iter()) {
+                    assert!(std::sync::Arc::ptr_eq(name, &field.name));
+                }
+            },
+            other => panic!("expected record, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_null_union() {
         let schema = Schema::parse_str(UNION_SCHEMA).unwrap();
@@ -348,6 +1449,146 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_reader_unknown_field_policy() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "b", "type": "string"}
+                ]
+            }
+        "#,
+        ).unwrap();
+
+        let reader = Reader::with_schema(&schema, ENCODED).unwrap();
+        assert_eq!(reader.filter(Result::is_ok).count(), 2);
+
+        let mut reader = Reader::with_schema(&schema, ENCODED).unwrap();
+        reader.set_unknown_field_policy(UnknownFieldPolicy::Error);
+        assert!(reader.next().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_reader_decode_options() {
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+        let strings = DecodeOptions {
+            max_bytes_len: 2,
+            ..DecodeOptions::default()
+        };
+        let blocks = DecodeOptions {
+            max_block_bytes: 4,
+            ..DecodeOptions::default()
+        };
+
+        // readers of trusted and untrusted sources decode side by side with their own limits
+        let mut strict = Reader::with_schema(&schema, ENCODED).unwrap();
+        strict.set_decode_options(strings);
+        let mut lenient = Reader::with_schema(&schema, ENCODED).unwrap();
+        assert!(lenient.next().unwrap().is_ok());
+        let error = strict.next().unwrap().unwrap_err();
+        assert!(matches!(error.root(), AvroError::Limit(_)));
+        assert!(lenient.next().unwrap().is_ok());
+
+        let mut reader = Reader::new(ENCODED).unwrap();
+        reader.set_decode_options(blocks);
+        let error = reader.next().unwrap().unwrap_err();
+        assert!(matches!(error.root(), AvroError::Limit(_)));
+
+        let mut reader = Reader::new(ENCODED).unwrap();
+        reader.set_decode_options(strings);
+        assert!(reader.for_each_lazy(|record| record.decode().map(drop)).is_err());
+        let mut reader = Reader::new(ENCODED).unwrap();
+        assert!(reader.for_each_lazy(|record| record.decode().map(drop)).is_ok());
+    }
+
+    #[test]
+    fn test_reader_invalid_utf8() {
+        use std::collections::HashMap;
+        use crate::util::InvalidUtf8;
+
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "test", "fields": [
+                {"name": "s", "type": "string"},
+                {"name": "m", "type": {"type": "map", "values": "long"}}
+            ]}"#,
+        ).unwrap();
+        let mut writer = crate::Writer::new(&schema, Vec::new());
+        let mut map = HashMap::new();
+        map.insert("cafe".to_owned(), 1i64);
+        writer.append(Value::record(vec![("s", "cafe".avro()), ("m", map.avro())])).unwrap();
+        writer.flush().unwrap();
+        let mut encoded = writer.into_inner();
+        // "café" as written in Latin-1 by a legacy producer, for the string and the map key
+        for i in 3..encoded.len() {
+            if &encoded[i - 3..=i] == b"cafe" {
+                encoded[i] = 0xe9;
+            }
+        }
+        let latin1 = b"caf\xe9".to_vec();
+        let read = |policy| {
+            let mut reader = Reader::new(&encoded[..]).unwrap();
+            reader.set_decode_options(DecodeOptions {
+                invalid_utf8: policy,
+                ..DecodeOptions::default()
+            });
+            reader.next().unwrap()
+        };
+
+        let error = read(InvalidUtf8::Error).unwrap_err();
+        assert!(error.to_string().contains("not a valid utf-8 string"));
+        let replaced = "caf\u{fffd}";
+        let mut map = HashMap::new();
+        map.insert(replaced.to_owned(), 1i64);
+        assert_eq!(
+            read(InvalidUtf8::ReplaceLossy).unwrap(),
+            Value::record(vec![("s", replaced.avro()), ("m", map.clone().avro())])
+        );
+        // map keys are always strings, replaced as with `ReplaceLossy`
+        assert_eq!(
+            read(InvalidUtf8::FallbackToBytes).unwrap(),
+            Value::record(vec![("s", Value::Bytes(latin1.clone(), None)), ("m", map.avro())])
+        );
+
+        let datum = [8, b'c', b'a', b'f', 0xe9];
+        let decode = |policy| {
+            let options = DecodeOptions {
+                invalid_utf8: policy,
+                ..DecodeOptions::default()
+            };
+            from_avro_datum_with_options(&Schema::String, &mut &datum[..], None, &options)
+        };
+        assert!(decode(InvalidUtf8::Error).is_err());
+        assert_eq!(decode(InvalidUtf8::ReplaceLossy).unwrap(), replaced.avro());
+        assert_eq!(decode(InvalidUtf8::FallbackToBytes).unwrap(), Value::Bytes(latin1, None));
+        assert!(from_avro_datum(&Schema::String, &mut &datum[..], None).is_err());
+    }
+
+    #[test]
+    fn test_reader_parse_options() {
+        let options = ParseOptions {
+            validate_names: false,
+        };
+        let schema = Schema::parse_str_with_options(
+            r#"{"type": "record", "name": "legacy-record", "fields": [
+                {"name": "a.b", "type": "long"}
+            ]}"#,
+            &options,
+        ).unwrap();
+        let mut writer = crate::Writer::new(&schema, Vec::new());
+        writer.append(Value::record(vec![("a.b", 1i64.avro())])).unwrap();
+        writer.flush().unwrap();
+        let encoded = writer.into_inner();
+
+        let error = Reader::new(&encoded[..]).map(drop).unwrap_err();
+        assert!(matches!(error, AvroError::Parse(_)));
+        let reader = Reader::with_parse_options(&encoded[..], &options).unwrap();
+        assert_eq!(reader.writer_schema(), &schema);
+        assert_eq!(reader.count(), 1);
+    }
+
     #[test]
     fn test_reader_iterator() {
         let schema = Schema::parse_str(SCHEMA).unwrap();
@@ -411,4 +1652,544 @@ mod tests {
             assert!(value.is_err());
         }
     }
+
+    #[test]
+    fn test_reader_find() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": "long", "index": true},
+                    {"name": "b", "type": "string"}
+                ]
+            }
+        "#,
+        ).unwrap();
+        let record = |a: i64, b: &str| Value::record(vec![("a", a.avro()), ("b", b.avro())]);
+
+        let mut writer = crate::Writer::new(&schema, Vec::new());
+        writer.collect_index();
+        writer.append(record(1, "foo")).unwrap();
+        writer.append(record(2, "bar")).unwrap();
+        writer.flush().unwrap();
+        writer.append(record(3, "baz")).unwrap();
+        writer.append(record(1, "qux")).unwrap();
+        let mut index = Vec::new();
+        let encoded = writer.close_with_index(&mut index).unwrap();
+
+        let mut reader = Reader::new(Cursor::new(encoded)).unwrap();
+        assert!(reader.find("a", 1i64.avro()).is_err());
+        reader.set_sidecar_index(SidecarIndex::open(&index[..]).unwrap()).unwrap();
+
+        assert_eq!(
+            reader.find("a", 1i64.avro()).unwrap(),
+            vec![record(1, "foo"), record(1, "qux")]
+        );
+        // values are resolved against the schema of the field
+        assert_eq!(reader.find("a", 3i32.avro()).unwrap(), vec![record(3, "baz")]);
+        assert!(reader.find("a", 4i64.avro()).unwrap().is_empty());
+        assert!(reader.find("b", "foo".avro()).is_err());
+    }
+
+    #[test]
+    fn test_reader_filter() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": "long", "index": true},
+                    {"name": "b", "type": "string"},
+                    {"name": "tags", "type": {"type": "set", "items": "string"}}
+                ]
+            }
+        "#,
+        ).unwrap();
+        let record = |a: i64, b: &str| {
+            let tags = Value::Set(vec![b.into()].into_iter().collect(), None);
+            Value::record(vec![("a", a.avro()), ("b", b.avro()), ("tags", tags)])
+        };
+
+        let mut writer = crate::Writer::new(&schema, Vec::new());
+        writer.collect_index();
+        writer.append(record(1, "foo")).unwrap();
+        writer.append(record(2, "bar")).unwrap();
+        writer.flush().unwrap();
+        writer.append(record(5, "baz")).unwrap();
+        writer.flush().unwrap();
+        writer.append(record(1, "qux")).unwrap();
+        let mut index = Vec::new();
+        let mut encoded = writer.close_with_index(&mut index).unwrap();
+        let index = SidecarIndex::open(&index[..]).unwrap().load().unwrap();
+
+        let filter = |encoded: &[u8], index: Option<&FileIndex>, predicate: Predicate| {
+            let mut reader = Reader::new(Cursor::new(encoded.to_vec())).unwrap();
+            if let Some(index) = index {
+                reader.set_index(index.clone());
+            }
+            reader.filter(predicate)?.collect::<Result<Vec<_>, _>>()
+        };
+        let eq = Predicate::Eq {
+            path: "a".to_owned(),
+            value: 1i64.avro(),
+        };
+        let range = Predicate::Range {
+            path: "a".to_owned(),
+            min: Some(2i32.avro()),
+            max: None,
+        };
+        let contains = Predicate::Contains {
+            path: "tags".to_owned(),
+            item: "bar".avro(),
+        };
+        let values = Predicate::In {
+            path: "b".to_owned(),
+            values: vec!["qux".avro(), "baz".avro()],
+        };
+        for index in &[None, Some(&index)] {
+            assert_eq!(
+                filter(&encoded, *index, eq.clone()).unwrap(),
+                vec![record(1, "foo"), record(1, "qux")]
+            );
+            assert_eq!(
+                filter(&encoded, *index, range.clone()).unwrap(),
+                vec![record(2, "bar"), record(5, "baz")]
+            );
+            assert_eq!(filter(&encoded, *index, contains.clone()).unwrap(), vec![record(2, "bar")]);
+            let both = Predicate::And(vec![range.clone(), values.clone()]);
+            assert_eq!(filter(&encoded, *index, both).unwrap(), vec![record(5, "baz")]);
+        }
+        assert!(filter(&encoded, None, Predicate::Contains {
+            path: "b".to_owned(),
+            item: "foo".avro(),
+        }).is_err());
+
+        // blocks pruned by the index are not read: a corrupted sync marker goes unnoticed
+        let offsets = index.block_offsets().collect::<Vec<_>>();
+        encoded[offsets[2] as usize - 1] ^= 1;
+        assert!(filter(&encoded, None, eq.clone()).is_err());
+        assert_eq!(
+            filter(&encoded, Some(&index), eq).unwrap(),
+            vec![record(1, "foo"), record(1, "qux")]
+        );
+    }
+
+    #[test]
+    fn test_reader_scan_filtered() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": "long"},
+                    {"name": "b", "type": {"type": "array", "items": "string"}},
+                    {"name": "c", "type": {"type": "record", "name": "c", "fields": [
+                        {"name": "d", "type": "string"}
+                    ]}}
+                ]
+            }
+        "#,
+        ).unwrap();
+        let record = |a: i64, d: &str| {
+            let c = Value::record(vec![("d", d.avro())]);
+            Value::record(vec![("a", a.avro()), ("b", vec![d].avro()), ("c", c)])
+        };
+        let mut writer = crate::Writer::new(&schema, Vec::new());
+        for (a, d) in &[(1, "foo"), (2, "bar"), (3, "baz")] {
+            writer.append(record(*a, d)).unwrap();
+        }
+        writer.flush().unwrap();
+        let encoded = writer.into_inner();
+
+        let reader = Reader::new(&encoded[..]).unwrap();
+        let found = reader
+            .scan_filtered(|partial| {
+                assert!(partial.get("missing").is_none());
+                partial.get("c.d") != Some(&"bar".avro())
+                    && partial.get("a").is_some_and(|a| *a != 1i64.avro())
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(found, vec![record(3, "baz")]);
+
+        let reader = Reader::new(&encoded[..]).unwrap();
+        assert_eq!(reader.scan_filtered(|_| true).count(), 3);
+
+        let mut reader = Reader::new(&encoded[..]).unwrap().scan_filtered(|partial| {
+            partial.get("c.d").is_some()
+        });
+        assert!(reader.next().unwrap().is_ok());
+
+        // errors met while decoding the accessed fields are reported
+        let mut writer = crate::Writer::new(&schema, Vec::new());
+        writer.append(record(1, "zzzz")).unwrap();
+        writer.flush().unwrap();
+        let mut encoded = writer.into_inner();
+        let position = encoded.windows(5).position(|w| w == b"\x08zzzz").unwrap();
+        // the length of the string now exceeds the block
+        encoded[position] = 0x7e;
+        let mut reader = Reader::new(&encoded[..])
+            .unwrap()
+            .scan_filtered(|partial| partial.get("c.d").is_none());
+        assert!(reader.next().unwrap().is_err());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_reader_for_each_ref() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "test", "fields": [
+                {"name": "a", "type": "long"},
+                {"name": "b", "type": "string"}
+            ]}"#,
+        ).unwrap();
+        let mut writer = crate::Writer::new(&schema, Vec::new());
+        writer.append(Value::record(vec![("a", 1i64.avro()), ("b", "foo".avro())])).unwrap();
+        writer.flush().unwrap();
+        writer.append(Value::record(vec![("a", 2i64.avro()), ("b", "bar".avro())])).unwrap();
+        writer.flush().unwrap();
+        let encoded = writer.into_inner();
+
+        let mut strings = Vec::new();
+        let mut reader = Reader::new(&encoded[..]).unwrap();
+        reader
+            .for_each_ref(|record| {
+                if let ValueRef::Record(fields) = record {
+                    if let ValueRef::String(s) = fields[1].1 {
+                        strings.push(s.to_owned());
+                    }
+                }
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(strings, vec!["foo", "bar"]);
+        assert!(reader.next().is_none());
+
+        let mut reader = Reader::new(&encoded[..]).unwrap();
+        let error = reader.for_each_ref(|_| Err(AvroError::Decode("stop".to_owned())));
+        assert!(error.is_err());
+        assert_eq!(reader.count(), 1);
+    }
+
+    #[test]
+    fn test_reader_for_each_lazy() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "test", "fields": [
+                {"name": "a", "type": "long"},
+                {"name": "b", "type": {"type": "record", "name": "b", "fields": [
+                    {"name": "c", "type": "string"}
+                ]}}
+            ]}"#,
+        ).unwrap();
+        let record = |a: i64, c: &str| {
+            Value::record(vec![("a", a.avro()), ("b", Value::record(vec![("c", c.avro())]))])
+        };
+        let mut writer = crate::Writer::new(&schema, Vec::new());
+        writer.append(record(1, "foo")).unwrap();
+        writer.append(record(2, "bar")).unwrap();
+        writer.flush().unwrap();
+        let encoded = writer.into_inner();
+
+        let mut strings = Vec::new();
+        let mut reader = Reader::new(&encoded[..]).unwrap();
+        reader
+            .for_each_lazy(|record| {
+                strings.push(record.get("b.c")?.unwrap().decode()?);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(strings, vec!["foo".avro(), "bar".avro()]);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_reader_for_each_event() {
+        struct Count(usize);
+
+        impl DecodeHandler for Count {
+            fn value(&mut self, _: ValueRef) -> Result<(), AvroError> {
+                self.0 += 1;
+                Ok(())
+            }
+        }
+
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "test", "fields": [
+                {"name": "l", "type": {"type": "array", "items": "long"}}
+            ]}"#,
+        ).unwrap();
+        let mut writer = crate::Writer::new(&schema, Vec::new());
+        writer.append(Value::record(vec![("l", vec![1i64, 2i64].avro())])).unwrap();
+        writer.flush().unwrap();
+        writer.append(Value::record(vec![("l", vec![3i64].avro())])).unwrap();
+        writer.flush().unwrap();
+        let encoded = writer.into_inner();
+
+        let mut count = Count(0);
+        let mut reader = Reader::new(&encoded[..]).unwrap();
+        reader.for_each_event(&mut count).unwrap();
+        assert_eq!(count.0, 3);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "rayon")]
+    fn test_reader_into_par_iter() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "test", "fields": [
+                {"name": "a", "type": "long"},
+                {"name": "b", "type": "string"}
+            ]}"#,
+        ).unwrap();
+        let mut writer = crate::Writer::with_codec(&schema, Vec::new(), Codec::Deflate);
+        for i in 0..20i64 {
+            writer.append(Value::record(vec![("a", i.avro()), ("b", "foo".avro())])).unwrap();
+            if i % 3 == 0 {
+                writer.flush().unwrap();
+            }
+        }
+        writer.flush().unwrap();
+        let encoded = writer.into_inner();
+        let expected = Reader::new(&encoded[..]).unwrap().collect::<Result<Vec<_>, _>>();
+        let expected = expected.unwrap();
+
+        let records = Reader::new(&encoded[..]).unwrap().into_par_iter();
+        assert_eq!(records.collect::<Result<Vec<_>, _>>().unwrap(), expected);
+
+        // the records left in the current block come first
+        let mut reader = Reader::new(&encoded[..]).unwrap();
+        reader.next().unwrap().unwrap();
+        let options = ParallelOptions {
+            threads: 3,
+            ordered: false,
+        };
+        let mut records = reader
+            .into_par_iter_with(options)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        records.sort_by_key(|record| match record.field("a") {
+            Some(Value::Long(a, _)) => *a,
+            _ => unreachable!(),
+        });
+        assert_eq!(records, expected[1..]);
+
+        let reader_schema = Schema::parse_str(
+            r#"{"type": "record", "name": "test", "fields": [
+                {"name": "b", "type": "string"},
+                {"name": "c", "type": "int", "default": 1}
+            ]}"#,
+        ).unwrap();
+        let records = Reader::with_schema(&reader_schema, &encoded[..]).unwrap().into_par_iter();
+        let records = records.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(records.len(), 20);
+        assert_eq!(records[0], Value::record(vec![("b", "foo".avro()), ("c", 1i32.avro())]));
+
+        let mut corrupted = encoded.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 1;
+        let records = Reader::new(&corrupted[..]).unwrap().into_par_iter();
+        assert!(records.collect::<Result<Vec<_>, _>>().is_err());
+
+        // the records of the blocks before a cut one are all yielded before its error
+        let mut writer = crate::Writer::new(&schema, Vec::new());
+        for i in 0..30i64 {
+            writer.append(Value::record(vec![("a", i.avro()), ("b", "foo".avro())])).unwrap();
+            if i % 10 == 9 {
+                writer.flush().unwrap();
+            }
+        }
+        let mut cut = writer.into_inner();
+        let marker = cut[cut.len() - 16..].to_vec();
+        let markers = (0..cut.len() - 15).filter(|&i| cut[i..i + 16] == marker[..]);
+        // after the header marker and the marker of the first block
+        let second_end = markers.collect::<Vec<_>>()[2];
+        cut.truncate(second_end - 20);
+        for ordered in &[true, false] {
+            let options = ParallelOptions {
+                threads: 2,
+                ordered: *ordered,
+            };
+            let records = Reader::new(&cut[..]).unwrap().into_par_iter_with(options).unwrap();
+            let records = records.collect::<Vec<_>>();
+            assert_eq!(records.len(), 11);
+            assert!(records[..10].iter().all(Result::is_ok));
+            assert!(records[10].is_err());
+        }
+
+        // the blocks are decoded with the options of the reader
+        let mut reader = Reader::new(&encoded[..]).unwrap();
+        reader.set_decode_options(DecodeOptions {
+            max_bytes_len: 2,
+            ..DecodeOptions::default()
+        });
+        assert!(reader.into_par_iter().collect::<Result<Vec<_>, _>>().is_err());
+    }
+
+    #[test]
+    fn test_reader_error_context() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "test", "fields": [
+                {"name": "a", "type": "long"},
+                {"name": "b", "type": "string"}
+            ]}"#,
+        ).unwrap();
+        let mut writer = crate::Writer::new(&schema, Vec::new());
+        for i in 0..4i64 {
+            writer.append(Value::record(vec![("a", i.avro()), ("b", "foo".avro())])).unwrap();
+            if i % 2 == 1 {
+                writer.flush().unwrap();
+            }
+        }
+        let mut encoded = writer.into_inner();
+        // the string of the last record, second of the second block
+        let position = encoded.windows(3).rposition(|window| window == b"foo").unwrap();
+        encoded[position] = 0xff;
+
+        let check = |error: AvroError| {
+            let context = error.context().unwrap();
+            assert_eq!(context.path(), "b");
+            assert_eq!((context.block(), context.offset()), (Some(1), Some(5)));
+            assert!(error.to_string().ends_with(" at `b` (block 1, byte 5)"), "{}", error);
+        };
+        let records = Reader::new(&encoded[..]).unwrap().collect::<Vec<_>>();
+        assert_eq!(records.iter().filter(|record| record.is_ok()).count(), 3);
+        check(records.into_iter().last().unwrap().unwrap_err());
+        #[cfg(feature = "rayon")]
+        {
+            let records = Reader::new(&encoded[..]).unwrap().into_par_iter();
+            check(records.collect::<Result<Vec<_>, _>>().unwrap_err());
+        }
+    }
+
+    #[test]
+    fn test_reader_for_each_pooled() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "test", "fields": [
+                {"name": "a", "type": "long"},
+                {"name": "b", "type": "string"}
+            ]}"#,
+        ).unwrap();
+        let mut writer = crate::Writer::new(&schema, Vec::new());
+        writer.append(Value::record(vec![("a", 1i64.avro()), ("b", "foo".avro())])).unwrap();
+        writer.flush().unwrap();
+        writer.append(Value::record(vec![("a", 2i64.avro()), ("b", "bar".avro())])).unwrap();
+        writer.flush().unwrap();
+        let encoded = writer.into_inner();
+
+        let mut records = Vec::new();
+        let mut reader = Reader::new(&encoded[..]).unwrap();
+        reader
+            .for_each_pooled(|record| {
+                records.push(record.clone());
+                Ok(())
+            })
+            .unwrap();
+        let expected = Reader::new(&encoded[..]).unwrap().collect::<Result<Vec<_>, _>>();
+        assert_eq!(records, expected.unwrap());
+        assert!(reader.next().is_none());
+
+        let mut reader = Reader::new(&encoded[..]).unwrap();
+        let error = reader.for_each_pooled(|_| Err(AvroError::Decode("stop".to_owned())));
+        assert!(error.is_err());
+        assert_eq!(reader.count(), 1);
+    }
+
+    #[test]
+    fn test_reader_build_index() {
+        let schema = Schema::parse_str(
+            r#"{"type": "record", "name": "test", "fields": [
+                {"name": "a", "type": "long", "index": true}
+            ]}"#,
+        ).unwrap();
+        let mut writer = crate::Writer::new(&schema, Vec::new());
+        writer.append(Value::record(vec![("a", 1i64)])).unwrap();
+        writer.flush().unwrap();
+        writer.append(Value::record(vec![("a", 2i64)])).unwrap();
+        writer.flush().unwrap();
+        let encoded = writer.into_inner();
+
+        let mut reader = Reader::new(Cursor::new(encoded.clone())).unwrap();
+        let index = reader.build_index().unwrap().clone();
+        assert_eq!(index.block_offsets().count(), 2);
+        let mut sidecar = Vec::new();
+        write_sidecar_index(&mut sidecar, reader.marker(), reader.writer_schema(), &index).unwrap();
+
+        let mut reader = Reader::new(Cursor::new(encoded)).unwrap();
+        reader.set_sidecar_index(SidecarIndex::open(&sidecar[..]).unwrap()).unwrap();
+        assert_eq!(reader.find("a", 2i64.avro()).unwrap(), vec![Value::record(vec![("a", 2i64)])]);
+
+        // sidecars of other files are rejected
+        let mut other = Reader::new(Cursor::new(ENCODED)).unwrap();
+        assert!(other.set_sidecar_index(SidecarIndex::open(&sidecar[..]).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_reader_user_metadata_and_count_blocks() {
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+        let mut writer = crate::Writer::with_codec(&schema, Vec::new(), Codec::Deflate);
+        writer.add_user_metadata("owner", "team").unwrap();
+        assert!(writer.add_user_metadata("avro.codec", "null").is_err());
+        for a in 0..3i64 {
+            writer.append(Value::record(vec![("a", a.avro()), ("b", "foo".avro())])).unwrap();
+            writer.flush().unwrap();
+        }
+        assert!(writer.add_user_metadata("late", "key").is_err());
+        let encoded = writer.into_inner();
+
+        let mut reader = Reader::new(&encoded[..]).unwrap();
+        assert_eq!(reader.codec(), Codec::Deflate);
+        assert_eq!(reader.user_metadata().len(), 1);
+        assert_eq!(reader.user_metadata()["owner"], b"team".to_vec());
+        let counts = reader.count_blocks().unwrap();
+        assert_eq!((counts.blocks, counts.records), (3, 3));
+        assert!(counts.bytes > 0);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_reader_seek_to_last() {
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+        let mut writer = crate::Writer::new(&schema, Vec::new());
+        let values = (0..10i64)
+            .map(|a| Value::record(vec![("a", a.avro()), ("b", "foo".avro())]))
+            .collect::<Vec<_>>();
+        // blocks of 3, 3, 3 and 1 records
+        for chunk in values.chunks(3) {
+            writer.extend_from_slice(chunk).unwrap();
+            writer.flush().unwrap();
+        }
+        let encoded = writer.into_inner();
+
+        for &n in &[0, 1, 4, 6, 10, 20] {
+            let mut reader = Reader::new(Cursor::new(&encoded)).unwrap();
+            reader.seek_to_last(n).unwrap();
+            let last = reader.collect::<Result<Vec<_>, _>>().unwrap();
+            assert_eq!(last, values[10 - n.min(10)..].to_vec());
+        }
+
+        let mut reader = Reader::new(Cursor::new(&encoded)).unwrap();
+        assert_eq!(reader.by_ref().take(2).count(), 2);
+        reader.seek_to_last(20).unwrap();
+        assert_eq!(reader.count(), 7);
+
+        // the first block header, following the sync marker ending the file header
+        let marker = &encoded[encoded.len() - 16..];
+        let header = (0..encoded.len()).find(|&i| &encoded[i..i + 16] == marker).unwrap() + 16;
+        let mut block = &encoded[header..];
+        let count = util::read_long(&mut block).unwrap();
+        util::read_long(&mut block).unwrap();
+        for &size in &[-1, -40, i64::MAX] {
+            let mut corrupted = encoded[..header].to_vec();
+            util::zig_i64(count, &mut corrupted);
+            util::zig_i64(size, &mut corrupted);
+            corrupted.extend_from_slice(block);
+            let mut reader = Reader::new(Cursor::new(&corrupted)).unwrap();
+            assert!(reader.seek_to_last(1).is_err(), "block of {} bytes skipped", size);
+        }
+    }
 }
@@ -1,34 +1,29 @@
 //! Logic for parsing and interacting with schemas in Avro format.
 use std::borrow::Cow;
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::fmt;
+use std::sync::Arc;
 
 use digest::Digest;
-use failure::Error;
 use regex::Regex;
 use serde::Deserialize;
 use serde::ser::{Serialize, SerializeMap, Serializer, SerializeSeq};
 use serde_json::{self, Map, Value as JsonValue};
 
-use crate::types::Value as AvroValue;
-use crate::util::MapHelper;
+use crate::cache::SchemaCache;
+use crate::error::AvroError;
+use crate::hll::{MAX_PRECISION, MIN_PRECISION};
+use crate::types::{SetItem, Value as AvroValue};
+use crate::util::{rabin_fingerprint, MapHelper};
 
-lazy_static! {
-    static ref LRU_LIMIT_REGEX:Regex = Regex::new("(?P<value>[[:digit:]]+)[[:space:]]*(?P<type>days|hour|minute|second)?$").unwrap();
+thread_local! {
+    // options of the schema being parsed on this thread
+    static PARSE_OPTIONS: Cell<ParseOptions> = const { Cell::new(ParseOptions::DEFAULT) };
 }
 
-/// Describes errors happened while parsing Avro schemas.
-#[derive(Fail, Debug)]
-#[fail(display = "Failed to parse schema: {}", _0)]
-pub struct ParseSchemaError(String);
-
-impl ParseSchemaError {
-    pub fn new<S>(msg: S) -> ParseSchemaError
-        where
-            S: Into<String>,
-    {
-        ParseSchemaError(msg.into())
-    }
+lazy_static! {
+    static ref LRU_LIMIT_REGEX:Regex = Regex::new("(?P<value>[[:digit:]]+)[[:space:]]*(?P<type>days|hour|minute|second|bytes)?$").unwrap();
 }
 
 /// Represents an Avro schema fingerprint
@@ -95,31 +90,171 @@ pub enum Schema {
         lookup: HashMap<String, usize>,
     },
     /// An `enum` Avro schema.
+    ///
+    /// The `default` symbol, if any, replaces the symbols unknown to this schema during schema
+    /// resolution.
     Enum {
         name: Name,
         doc: Documentation,
         symbols: Vec<String>,
+        default: Option<String>,
     },
     /// A `fixed` Avro schema.
     Fixed { name: Name, size: usize },
 
-    Date,
+    // instant in time, stored as a long in the given unit since epoch
+    Date(DateUnit),
 
-    Set,
+    // array of at most `max_len` items, truncated from the given end when longer
+    BoundedList {
+        items: Box<Schema>,
+        max_len: usize,
+        truncation: Truncation,
+    },
+
+    // set of distinct items, of type int, long, bytes, string (the default) or enum
+    Set(Box<Schema>),
 
     // capture limit and limit by = supported values: days, hour, minute, count
-    LruSet(LruLimit),
+    // along with the optional decay of the counts
+    LruSet(LruLimit, Option<Decay>),
+
+    // running count, encoded as a long and merged by sum
+    Counter,
+
+    // HyperLogLog sketch of the given precision, encoded as bytes
+    Hll(u8),
+
+    // Bloom filter of the given number of bits and hash functions, encoded as bytes
+    Bloom { bits: u32, hashes: u8 },
+
+    // space-saving summary of the given number of most frequent keys
+    TopK(usize),
 
     // optional type
     Optional(Box<Schema>),
 }
 
+/// Unit in which a `date` is stored, declared by its `unit` attribute.
+///
+/// [`Value::Date`](../types/enum.Value.html#variant.Date) values are always in milliseconds:
+/// they are converted from and to the unit of their schema when decoded and encoded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DateUnit {
+    Seconds,
+    #[default]
+    Millis,
+    Micros,
+}
+
+impl DateUnit {
+    /// Convert a timestamp in this unit into milliseconds, rounding down.
+    pub fn to_millis(self, t: i64) -> i64 {
+        match self {
+            DateUnit::Seconds => t.saturating_mul(1000),
+            DateUnit::Millis => t,
+            DateUnit::Micros => t.div_euclid(1000),
+        }
+    }
+
+    /// Convert a timestamp in milliseconds into this unit.
+    pub fn from_millis(self, millis: i64) -> i64 {
+        match self {
+            DateUnit::Seconds => millis.div_euclid(1000),
+            DateUnit::Millis => millis,
+            DateUnit::Micros => millis.saturating_mul(1000),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            DateUnit::Seconds => "seconds",
+            DateUnit::Millis => "millis",
+            DateUnit::Micros => "micros",
+        }
+    }
+}
+
+/// End from which the items of a `bounded_list` are dropped once it grows beyond its maximum
+/// length, declared by its `truncate` attribute.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Truncation {
+    /// Drop the first items, keeping the most recently appended ones.
+    #[default]
+    Head,
+    /// Drop the last items, keeping the first appended ones.
+    Tail,
+}
+
+impl Truncation {
+    /// Drop items from this end of `items`, so that at most `max_len` of them remain.
+    pub fn apply<T>(self, items: &mut Vec<T>, max_len: usize) {
+        if items.len() <= max_len {
+            return
+        }
+        match self {
+            Truncation::Head => {
+                items.drain(..items.len() - max_len);
+            },
+            Truncation::Tail => items.truncate(max_len),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Truncation::Head => "head",
+            Truncation::Tail => "tail",
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum LruLimit {
     Days(u16),
     Hour(u16),
     Minute(u16),
     Count(u16),
+    /// Approximate memory budget, in bytes: each entry accounts for the length of its key plus
+    /// [`LruLimit::ENTRY_OVERHEAD`](#associatedconstant.ENTRY_OVERHEAD).
+    Bytes(u32),
+    /// Limit declared by the `max_entries` and `ttl_ms` attributes of a `lru_set` schema rather
+    /// than by its `limit`: at most `max_entries` entries are kept, and entries expire after
+    /// `ttl_millis` milliseconds. Either may be absent, and both for an unbounded set, declared
+    /// with `"max_entries": null`.
+    Entries {
+        max_entries: Option<u32>,
+        ttl_millis: Option<i64>,
+    },
+}
+
+impl LruLimit {
+    /// Approximate number of bytes taken by an entry of a `lru_set` besides its key, accounted
+    /// by `LruLimit::Bytes` limits.
+    pub const ENTRY_OVERHEAD: usize = 48;
+
+    /// Time-to-live of the entries of a `lru_set` limited by time, in milliseconds.
+    ///
+    /// Entries whose `access_time` (in milliseconds since epoch) is older than the reference time
+    /// minus this duration are expired. Returns `None` for `LruLimit::Count` and `LruLimit::Bytes`
+    /// limits, and for `LruLimit::Entries` limits without a `ttl_ms`.
+    pub fn ttl_millis(&self) -> Option<i64> {
+        match *self {
+            LruLimit::Days(days) => Some(i64::from(days) * 24 * 60 * 60 * 1000),
+            LruLimit::Hour(hours) => Some(i64::from(hours) * 60 * 60 * 1000),
+            LruLimit::Minute(minutes) => Some(i64::from(minutes) * 60 * 1000),
+            LruLimit::Entries { ttl_millis, .. } => ttl_millis,
+            LruLimit::Count(_) | LruLimit::Bytes(_) => None,
+        }
+    }
+
+    /// Maximum number of entries of a `lru_set` limited by count.
+    pub fn max_entries(&self) -> Option<usize> {
+        match *self {
+            LruLimit::Count(count) => Some(usize::from(count)),
+            LruLimit::Entries { max_entries, .. } => max_entries.map(|count| count as usize),
+            _ => None,
+        }
+    }
 }
 
 impl Serialize for LruLimit {
@@ -132,6 +267,71 @@ impl Serialize for LruLimit {
             LruLimit::Hour(limit) => serializer.serialize_str(&format!("{} hour", limit)),
             LruLimit::Minute(limit) => serializer.serialize_str(&format!("{} minute", limit)),
             LruLimit::Count(limit) => serializer.serialize_str(&format!("{}", limit)),
+            LruLimit::Bytes(limit) => serializer.serialize_str(&format!("{} bytes", limit)),
+            LruLimit::Entries {
+                max_entries,
+                ttl_millis,
+            } => {
+                let mut map = serializer.serialize_map(None)?;
+                // an absent limit is kept as null, as a lru_set without attributes is refused
+                if max_entries.is_some() || ttl_millis.is_none() {
+                    map.serialize_entry("max_entries", &max_entries)?;
+                }
+                if let Some(ttl_millis) = ttl_millis {
+                    map.serialize_entry("ttl_ms", &ttl_millis)?;
+                }
+                map.end()
+            },
+        }
+    }
+}
+
+/// Exponential decay of the counts of a `lru_set`, declared by its `half_life` attribute, either
+/// as a duration such as `"7 days"` (in `days`, `hour` or `minute`) or as a number of
+/// milliseconds.
+///
+/// Counts are stored as of the `access_time` of their entry. When two entries of the same key are
+/// merged, both counts are decayed to the later access time before being summed, so that stale
+/// activity weighs less than recent one. During schema resolution, entries whose count has
+/// decayed below one by the reference time are dropped.
+///
+/// The other counts are not rewritten as of the reference time: their access time is kept for
+/// eviction, so that they would decay over the same period again on the next merge or
+/// resolution. Entries are ranked by their recent activity with
+/// [`LruValue::count_at`](../types/struct.LruValue.html#method.count_at) instead.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Decay {
+    /// Duration after which a count is halved, in milliseconds.
+    pub half_life_millis: i64,
+}
+
+impl Decay {
+    /// Decay a count over the given elapsed duration, in milliseconds, rounding to the nearest
+    /// integer.
+    pub fn apply(&self, count: i64, elapsed: i64) -> i64 {
+        if elapsed <= 0 || self.half_life_millis <= 0 {
+            return count
+        }
+        let factor = 0.5f64.powf(elapsed as f64 / self.half_life_millis as f64);
+        (count as f64 * factor).round() as i64
+    }
+}
+
+impl Serialize for Decay {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let minute = 60 * 1000;
+        let t = self.half_life_millis;
+        if t > 0 && t % (24 * 60 * minute) == 0 {
+            serializer.serialize_str(&format!("{} days", t / (24 * 60 * minute)))
+        } else if t > 0 && t % (60 * minute) == 0 {
+            serializer.serialize_str(&format!("{} hour", t / (60 * minute)))
+        } else if t > 0 && t % minute == 0 {
+            serializer.serialize_str(&format!("{} minute", t / minute))
+        } else {
+            serializer.serialize_i64(t)
         }
     }
 }
@@ -163,6 +363,10 @@ pub(crate) enum SchemaKind {
     Date,
     Set,
     LruSet,
+    Counter,
+    Hll,
+    Bloom,
+    TopK,
     Optional,
 }
 
@@ -179,15 +383,20 @@ impl<'a> From<&'a Schema> for SchemaKind {
             Schema::Double => SchemaKind::Double,
             Schema::Bytes => SchemaKind::Bytes,
             Schema::String => SchemaKind::String,
-            Schema::Array(_) => SchemaKind::Array,
+            // bounded lists hold arrays, and cannot be told apart from them in unions
+            Schema::Array(_) | Schema::BoundedList { .. } => SchemaKind::Array,
             Schema::Map(_) => SchemaKind::Map,
             Schema::Union(_) => SchemaKind::Union,
             Schema::Record { .. } => SchemaKind::Record,
             Schema::Enum { .. } => SchemaKind::Enum,
             Schema::Fixed { .. } => SchemaKind::Fixed,
-            Schema::Date => SchemaKind::Date,
-            Schema::Set => SchemaKind::Set,
-            Schema::LruSet(_) => SchemaKind::LruSet,
+            Schema::Date(_) => SchemaKind::Date,
+            Schema::Set(_) => SchemaKind::Set,
+            Schema::LruSet(..) => SchemaKind::LruSet,
+            Schema::Counter => SchemaKind::Counter,
+            Schema::Hll(_) => SchemaKind::Hll,
+            Schema::Bloom { .. } => SchemaKind::Bloom,
+            Schema::TopK(_) => SchemaKind::TopK,
             Schema::Optional(_) => SchemaKind::Optional,
         }
     }
@@ -214,6 +423,10 @@ impl<'a> From<&'a AvroValue> for SchemaKind {
             AvroValue::Date(_, _) => SchemaKind::Date,
             AvroValue::Set(_, _) => SchemaKind::Set,
             AvroValue::LruSet(_, _, _) => SchemaKind::LruSet,
+            AvroValue::Counter(_, _) => SchemaKind::Counter,
+            AvroValue::Hll(_, _) => SchemaKind::Hll,
+            AvroValue::Bloom(_, _) => SchemaKind::Bloom,
+            AvroValue::TopK(_, _) => SchemaKind::TopK,
             AvroValue::Optional(_, _) => SchemaKind::Optional,
         }
     }
@@ -238,6 +451,71 @@ pub struct Name {
     pub index: bool,
 }
 
+/// Options driving the parsing of a schema with
+/// [`Schema::parse_with_options`](enum.Schema.html#method.parse_with_options).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Check the names, namespaces and aliases of named types, the names and aliases of record
+    /// fields and the symbols of enums against the `[A-Za-z_][A-Za-z0-9_]*` rule of the Avro
+    /// specification. Turning the checks off allows reading legacy data written with names other
+    /// implementations refuse.
+    pub validate_names: bool,
+}
+
+impl ParseOptions {
+    const DEFAULT: ParseOptions = ParseOptions {
+        validate_names: true,
+    };
+}
+
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions::DEFAULT
+    }
+}
+
+/// Options of the schema parsed on this thread, which are restored to the ones used before once
+/// the guard is dropped.
+struct ParseOptionsGuard(ParseOptions);
+
+impl Drop for ParseOptionsGuard {
+    fn drop(&mut self) {
+        PARSE_OPTIONS.with(|options| options.set(self.0));
+    }
+}
+
+/// Whether the names of the schema being parsed are checked.
+fn validate_names() -> bool {
+    PARSE_OPTIONS.with(|options| options.get().validate_names)
+}
+
+/// Whether `name` follows the `[A-Za-z_][A-Za-z0-9_]*` rule of the Avro specification.
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Check a name which can't be qualified, such as a field name or an enum symbol, unless
+/// `ParseOptions::validate_names` turned the checks off. `kind` describes the name in errors.
+fn check_name(name: &str, kind: &str) -> Result<(), AvroError> {
+    if !validate_names() || is_valid_name(name) {
+        Ok(())
+    } else {
+        Err(AvroError::Parse(format!("Invalid {} `{}`", kind, name)))
+    }
+}
+
+/// Check a name which can be qualified with dot separated namespaces, such as the name of a named
+/// type, or a namespace.
+fn check_fullname(name: &str, kind: &str) -> Result<(), AvroError> {
+    if !validate_names() || name.split('.').all(is_valid_name) {
+        Ok(())
+    } else {
+        Err(AvroError::Parse(format!("Invalid {} `{}`", kind, name)))
+    }
+}
+
 /// Represents documentation for complex Avro schemas.
 pub type Documentation = Option<String>;
 
@@ -254,23 +532,22 @@ impl Name {
     }
 
     /// Parse a `serde_json::Value` into a `Name`.
-    fn parse(complex: &Map<String, JsonValue>) -> Result<Self, Error> {
+    fn parse(complex: &Map<String, JsonValue>) -> Result<Self, AvroError> {
         let name = complex
             .name()
-            .ok_or_else(|| ParseSchemaError::new("No `name` field"))?;
+            .ok_or_else(|| AvroError::Parse("No `name` field".to_owned()))?;
 
         let namespace = complex.string("namespace");
+        let aliases = complex.aliases();
 
-        let aliases: Option<Vec<String>> = complex
-            .get("aliases")
-            .and_then(|aliases| aliases.as_array())
-            .and_then(|aliases| {
-                aliases
-                    .iter()
-                    .map(|alias| alias.as_str())
-                    .map(|alias| alias.map(|a| a.to_string()))
-                    .collect::<Option<_>>()
-            });
+        check_fullname(&name, "name")?;
+        // the empty namespace stands for no namespace
+        if let Some(namespace) = namespace.as_ref().filter(|namespace| !namespace.is_empty()) {
+            check_fullname(namespace, "namespace")?;
+        }
+        for alias in aliases.iter().flatten() {
+            check_fullname(alias, "alias")?;
+        }
 
         Ok(Name {
             name,
@@ -306,9 +583,15 @@ impl Name {
 #[derive(Clone, Debug, PartialEq)]
 pub struct RecordField {
     /// Name of the field.
-    pub name: String,
+    ///
+    /// Shared with the `Value::Record` values built from this schema, so that decoding or
+    /// resolving a record does not allocate its field names.
+    pub name: Arc<str>,
     /// Documentation of the field.
     pub doc: Documentation,
+    /// Former names of the field, under which the values of writers are looked up during schema
+    /// resolution when they lack the field.
+    pub aliases: Option<Vec<String>>,
     /// Default value of the field.
     /// This value will be used when reading Avro datum if schema resolution
     /// is enabled.
@@ -317,12 +600,12 @@ pub struct RecordField {
     pub schema: Schema,
     /// Order of the field.
     ///
-    /// **NOTE** This currently has no effect.
+    /// Used by [`Value::cmp_with_schema`](../types/enum.Value.html#method.cmp_with_schema).
     pub order: RecordFieldOrder,
     /// Position of the field in the list of `field` of its parent `Schema`
     pub position: usize,
-
-    pub index: bool,
+    /// Annotations of the field, attached to the values resolved against it.
+    pub metadata: Arc<FieldMetadata>,
 }
 
 /// Represents any valid order for a `field` in a `record` Avro schema.
@@ -333,17 +616,81 @@ pub enum RecordFieldOrder {
     Ignore,
 }
 
+/// Annotations of a `field` in a `record` Avro schema, declared by its `index`, `pii`, `ttl_ms`
+/// and `tags` attributes.
+///
+/// Schema resolution attaches them to the values of annotated fields, as their
+/// [ValueSetting](../types/struct.ValueSetting.html), so that downstream processors can act upon
+/// them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FieldMetadata {
+    /// Whether the field is indexed, see [`FileIndex`](../struct.FileIndex.html).
+    pub index: bool,
+    /// Whether the field holds personally identifiable information.
+    pub pii: bool,
+    /// Time to live of the values of the field, in milliseconds.
+    pub ttl_millis: Option<i64>,
+    /// Custom tags of the field.
+    pub tags: Vec<String>,
+}
+
+impl FieldMetadata {
+    /// Whether the field has no annotation at all.
+    pub fn is_empty(&self) -> bool {
+        *self == FieldMetadata::default()
+    }
+
+    fn parse(field: &Map<String, JsonValue>) -> Result<Self, AvroError> {
+        let ttl_millis = match field.get("ttl_ms") {
+            Some(ttl) => match ttl.as_i64() {
+                Some(ttl) if ttl > 0 => Some(ttl),
+                _ => {
+                    return Err(AvroError::Parse(
+                        "`ttl_ms` must be a positive integer".to_owned(),
+                    ))
+                },
+            },
+            None => None,
+        };
+        let tags = match field.get("tags") {
+            Some(tags) => tags
+                .as_array()
+                .and_then(|tags| {
+                    tags.iter()
+                        .map(|tag| tag.as_str().map(|tag| tag.to_owned()))
+                        .collect::<Option<_>>()
+                })
+                .ok_or_else(|| AvroError::Parse(
+                    "`tags` must be an array of strings".to_owned(),
+                ))?,
+            None => Vec::new(),
+        };
+        Ok(FieldMetadata {
+            index: field.index(),
+            pii: field.bool("pii"),
+            ttl_millis,
+            tags,
+        })
+    }
+}
+
 impl RecordField {
     /// Parse a `serde_json::Value` into a `RecordField`.
-    fn parse(field: &Map<String, JsonValue>, position: usize) -> Result<Self, Error> {
+    fn parse(field: &Map<String, JsonValue>, position: usize) -> Result<Self, AvroError> {
         let name = field
             .name()
-            .ok_or_else(|| ParseSchemaError::new("No `name` in record field"))?;
+            .ok_or_else(|| AvroError::Parse("No `name` in record field".to_owned()))?;
+        let aliases = field.aliases();
+
+        check_name(&name, "field name")?;
+        for alias in aliases.iter().flatten() {
+            check_name(alias, "field alias")?;
+        }
 
         // TODO: "type" = "<record name>"
         let schema = field
             .get("type")
-            .ok_or_else(|| ParseSchemaError::new("No `type` in record field").into())
+            .ok_or_else(|| AvroError::Parse("No `type` in record field".to_owned()))
             .and_then(|type_| Schema::parse_complex(field))?;
 
         let default = field.get("default").cloned();
@@ -359,13 +706,14 @@ impl RecordField {
             }).unwrap_or_else(|| RecordFieldOrder::Ascending);
 
         Ok(RecordField {
-            name,
+            name: name.into(),
             doc: field.doc(),
+            aliases,
             default,
             schema,
             order,
             position,
-            index: field.index(),
+            metadata: Arc::new(FieldMetadata::parse(field)?),
         })
     }
 }
@@ -381,18 +729,18 @@ pub struct UnionSchema {
 }
 
 impl UnionSchema {
-    pub(crate) fn new(schemas: Vec<Schema>) -> Result<Self, Error> {
+    pub(crate) fn new(schemas: Vec<Schema>) -> Result<Self, AvroError> {
         let mut vindex = HashMap::new();
         for (i, schema) in schemas.iter().enumerate() {
             if let Schema::Union(_) = schema {
-                Err(ParseSchemaError::new(
-                    "Unions may not directly contain a union",
+                Err(AvroError::Parse(
+                    "Unions may not directly contain a union".to_owned(),
                 ))?;
             }
             let kind = SchemaKind::from(schema);
             if vindex.insert(kind, i).is_some() {
-                Err(ParseSchemaError::new(
-                    "Unions cannot contain duplicate types",
+                Err(AvroError::Parse(
+                    "Unions cannot contain duplicate types".to_owned(),
                 ))?;
             }
         }
@@ -432,22 +780,45 @@ impl PartialEq for UnionSchema {
 
 impl Schema {
     /// Create a `Schema` from a string representing a JSON Avro schema.
-    pub fn parse_str(input: &str) -> Result<Self, Error> {
+    pub fn parse_str(input: &str) -> Result<Self, AvroError> {
         let value = serde_json::from_str(input)?;
         Self::parse(&value)
     }
 
+    /// Create a `Schema` from a string representing a JSON Avro schema, according to the given
+    /// options.
+    pub fn parse_str_with_options(input: &str, options: &ParseOptions) -> Result<Self, AvroError> {
+        let value = serde_json::from_str(input)?;
+        Self::parse_with_options(&value, options)
+    }
+
+    /// Create a `Schema` from a string representing a JSON Avro schema, shared with every
+    /// identical string parsed through the [global cache](struct.SchemaCache.html#method.global).
+    pub fn parse_str_cached(input: &str) -> Result<Arc<Self>, AvroError> {
+        SchemaCache::global().parse_str(input)
+    }
+
     /// Create a `Schema` from a `serde_json::Value` representing a JSON Avro
     /// schema.
-    pub fn parse(value: &JsonValue) -> Result<Self, Error> {
+    pub fn parse(value: &JsonValue) -> Result<Self, AvroError> {
         match *value {
             JsonValue::String(ref t) => Schema::parse_primitive(t.as_str()),
             JsonValue::Object(ref data) => Schema::parse_complex(data),
             JsonValue::Array(ref data) => Schema::parse_union(data),
-            _ => Err(ParseSchemaError::new("Must be a JSON string, object or array").into()),
+            _ => Err(AvroError::Parse("Must be a JSON string, object or array".to_owned())),
         }
     }
 
+    /// Create a `Schema` from a `serde_json::Value` representing a JSON Avro schema, according to
+    /// the given options.
+    pub fn parse_with_options(
+        value: &JsonValue,
+        options: &ParseOptions,
+    ) -> Result<Self, AvroError> {
+        let _options = ParseOptionsGuard(PARSE_OPTIONS.with(|current| current.replace(*options)));
+        Self::parse(value)
+    }
+
     /// Converts `self` into its [Parsing Canonical Form].
     ///
     /// [Parsing Canonical Form]:
@@ -471,9 +842,57 @@ impl Schema {
         }
     }
 
+    /// Generate the 64-bit Rabin [fingerprint] of Schema's [Parsing Canonical Form], as its 8 bytes
+    /// in little-endian order, which is how they follow the marker of single object encoded
+    /// payloads.
+    ///
+    /// [Parsing Canonical Form]:
+    /// https://avro.apache.org/docs/1.8.2/spec.html#Parsing+Canonical+Form+for+Schemas
+    /// [fingerprint]:
+    /// https://avro.apache.org/docs/current/spec.html#schema_fingerprints
+    pub fn rabin_fingerprint(&self) -> SchemaFingerprint {
+        let fingerprint = rabin_fingerprint(self.canonical_form().as_bytes());
+        SchemaFingerprint {
+            bytes: fingerprint.to_le_bytes().to_vec(),
+        }
+    }
+
+    /// Return the name identifying this schema as a branch of a union: the `fullname` for named
+    /// types and the type name otherwise.
+    ///
+    /// More information about union branch names can be found in the
+    /// [Avro specification](https://avro.apache.org/docs/current/spec.html#json_encoding)
+    pub fn type_name(&self) -> String {
+        match *self {
+            Schema::Null => "null".to_owned(),
+            Schema::Boolean => "boolean".to_owned(),
+            Schema::Int => "int".to_owned(),
+            Schema::Long => "long".to_owned(),
+            Schema::Float => "float".to_owned(),
+            Schema::Double => "double".to_owned(),
+            Schema::Bytes => "bytes".to_owned(),
+            Schema::String => "string".to_owned(),
+            Schema::Array(_) => "array".to_owned(),
+            Schema::BoundedList { .. } => "bounded_list".to_owned(),
+            Schema::Map(_) => "map".to_owned(),
+            Schema::Union(_) => "union".to_owned(),
+            Schema::Record { ref name, .. }
+            | Schema::Enum { ref name, .. }
+            | Schema::Fixed { ref name, .. } => name.fullname(None),
+            Schema::Date(_) => "date".to_owned(),
+            Schema::Set(_) => "set".to_owned(),
+            Schema::LruSet(..) => "lru_set".to_owned(),
+            Schema::Counter => "counter".to_owned(),
+            Schema::Hll(_) => "hll".to_owned(),
+            Schema::Bloom { .. } => "bloom".to_owned(),
+            Schema::TopK(_) => "topk".to_owned(),
+            Schema::Optional(_) => "optional".to_owned(),
+        }
+    }
+
     /// Parse a `serde_json::Value` representing a primitive Avro type into a
     /// `Schema`.
-    fn parse_primitive(primitive: &str) -> Result<Self, Error> {
+    fn parse_primitive(primitive: &str) -> Result<Self, AvroError> {
         match primitive {
             "null" => Ok(Schema::Null),
             "boolean" => Ok(Schema::Boolean),
@@ -483,9 +902,15 @@ impl Schema {
             "float" => Ok(Schema::Float),
             "bytes" => Ok(Schema::Bytes),
             "string" => Ok(Schema::String),
-            "date" => Ok(Schema::Date),
-            "set" => Ok(Schema::Set),
-            other => Err(ParseSchemaError::new(format!("Unknown type: {}", other)).into()),
+            "date" => Ok(Schema::Date(DateUnit::Millis)),
+            "set" => Ok(Schema::Set(Box::new(Schema::String))),
+            "counter" => Ok(Schema::Counter),
+            // named types can't be referenced by name, which also keeps schemas from being cyclic
+            other if other.split('.').all(is_valid_name) => Err(AvroError::Parse(format!(
+                "Unknown type: {} (references to named types are not supported)",
+                other
+            ))),
+            other => Err(AvroError::Parse(format!("Unknown type: {}", other))),
         }
     }
 
@@ -494,14 +919,20 @@ impl Schema {
     ///
     /// Avro supports "recursive" definition of types.
     /// e.g: {"type": {"type": "string"}}
-    fn parse_complex(complex: &Map<String, JsonValue>) -> Result<Self, Error> {
+    fn parse_complex(complex: &Map<String, JsonValue>) -> Result<Self, AvroError> {
         match complex.get("type") {
             Some(&JsonValue::String(ref t)) => match t.as_str() {
                 "record" => Schema::parse_record(complex),
                 "enum" => Schema::parse_enum(complex),
                 "array" => Schema::parse_array(complex),
+                "bounded_list" => Schema::parse_bounded_list(complex),
                 "map" => Schema::parse_map(complex),
                 "fixed" => Schema::parse_fixed(complex),
+                "date" => Schema::parse_date(complex),
+                "hll" => Schema::parse_hll(complex),
+                "bloom" => Schema::parse_bloom(complex),
+                "topk" => Schema::parse_topk(complex),
+                "set" => Schema::parse_set(complex),
                 "lru_set" => Schema::parse_lru_set(complex),
                 "optional" => Schema::parse_optional(complex),
                 other => Schema::parse_primitive(other),
@@ -509,16 +940,17 @@ impl Schema {
             Some(&JsonValue::Object(ref data)) => Schema::parse_complex(data) /*match data.get("type") {
                 Some(ref value) => Schema::parse(value),
                 None => Err(
-                    ParseSchemaError::new(format!("Unknown complex type: {:?}", complex)).into(),
+                    AvroError::Parse(format!("Unknown complex type: {:?}", complex)),
                 ),
             }*/,
-            _ => Err(ParseSchemaError::new("No `type` in complex type").into()),
+            Some(JsonValue::Array(items)) => Schema::parse_union(items),
+            _ => Err(AvroError::Parse("No `type` in complex type".to_owned())),
         }
     }
 
     /// Parse a `serde_json::Value` representing a Avro record type into a
     /// `Schema`.
-    fn parse_record(complex: &Map<String, JsonValue>) -> Result<Self, Error> {
+    fn parse_record(complex: &Map<String, JsonValue>) -> Result<Self, AvroError> {
         let name = Name::parse(complex)?;
 
         let mut lookup = HashMap::new();
@@ -526,7 +958,7 @@ impl Schema {
         let fields: Vec<RecordField> = complex
             .get("fields")
             .and_then(|fields| fields.as_array())
-            .ok_or_else(|| ParseSchemaError::new("No `fields` in record").into())
+            .ok_or_else(|| AvroError::Parse("No `fields` in record".to_owned()))
             .and_then(|fields| {
                 fields
                     .iter()
@@ -537,7 +969,12 @@ impl Schema {
             })?;
 
         for field in &fields {
-            lookup.insert(field.name.clone(), field.position);
+            if let Some(first) = lookup.insert(field.name.to_string(), field.position) {
+                return Err(AvroError::Parse(format!(
+                    "Duplicate field `{}` in record `{}` at position {}, first at position {}",
+                    field.name, name.name, field.position, first
+                )))
+            }
         }
 
         Ok(Schema::Record {
@@ -550,51 +987,102 @@ impl Schema {
 
     /// Parse a `serde_json::Value` representing a Avro enum type into a
     /// `Schema`.
-    fn parse_enum(complex: &Map<String, JsonValue>) -> Result<Self, Error> {
+    fn parse_enum(complex: &Map<String, JsonValue>) -> Result<Self, AvroError> {
         let name = Name::parse(complex)?;
 
-        let symbols = complex
+        let symbols: Vec<String> = complex
             .get("symbols")
             .and_then(|v| v.as_array())
-            .ok_or_else(|| ParseSchemaError::new("No `symbols` field in enum"))
+            .ok_or_else(|| AvroError::Parse("No `symbols` field in enum".to_owned()))
             .and_then(|symbols| {
                 symbols
                     .iter()
                     .map(|symbol| symbol.as_str().map(|s| s.to_string()))
                     .collect::<Option<_>>()
-                    .ok_or_else(|| ParseSchemaError::new("Unable to parse `symbols` in enum"))
+                    .ok_or_else(|| AvroError::Parse(
+                        "Unable to parse `symbols` in enum".to_owned(),
+                    ))
             })?;
 
+        let mut positions = HashMap::with_capacity(symbols.len());
+        for (position, symbol) in symbols.iter().enumerate() {
+            check_name(symbol, "enum symbol")?;
+            if let Some(first) = positions.insert(symbol.as_str(), position) {
+                return Err(AvroError::Parse(format!(
+                    "Duplicate symbol `{}` in enum `{}` at position {}, first at position {}",
+                    symbol, name.name, position, first
+                )))
+            }
+        }
+
+        let default = match complex.get("default") {
+            Some(JsonValue::String(default)) if symbols.contains(default) => Some(default.clone()),
+            Some(default) => {
+                return Err(AvroError::Parse(format!(
+                    "Enum default {} is not among allowed symbols {:?}",
+                    default, symbols
+                )))
+            },
+            None => None,
+        };
+
         Ok(Schema::Enum {
             name,
             doc: complex.doc(),
             symbols,
+            default,
         })
     }
 
     /// Parse a `serde_json::Value` representing a Avro array type into a
     /// `Schema`.
-    fn parse_array(complex: &Map<String, JsonValue>) -> Result<Self, Error> {
+    fn parse_array(complex: &Map<String, JsonValue>) -> Result<Self, AvroError> {
         complex
             .get("items")
-            .ok_or_else(|| ParseSchemaError::new("No `items` in array").into())
+            .ok_or_else(|| AvroError::Parse("No `items` in array".to_owned()))
             .and_then(|items| Schema::parse(items))
             .map(|schema| Schema::Array(Box::new(schema)))
     }
 
+    /// Parse a `serde_json::Value` representing a bounded list type into a `Schema`. Items are
+    /// dropped from the head unless specified otherwise.
+    fn parse_bounded_list(complex: &Map<String, JsonValue>) -> Result<Self, AvroError> {
+        let items = match Schema::parse_array(complex)? {
+            Schema::Array(items) => items,
+            _ => unreachable!(),
+        };
+        let max_len = complex
+            .get("max_length")
+            .and_then(|max_len| max_len.as_u64())
+            .ok_or_else(|| AvroError::Parse("No `max_length` in bounded_list".to_owned()))?;
+        let truncation = match complex.get("truncate") {
+            None => Truncation::Head,
+            Some(JsonValue::String(end)) if end == "head" => Truncation::Head,
+            Some(JsonValue::String(end)) if end == "tail" => Truncation::Tail,
+            Some(other) => {
+                return Err(AvroError::Parse(format!("Unknown truncation: {}", other)))
+            },
+        };
+        Ok(Schema::BoundedList {
+            items,
+            max_len: max_len as usize,
+            truncation,
+        })
+    }
+
     /// Parse a `serde_json::Value` representing a Avro map type into a
     /// `Schema`.
-    fn parse_map(complex: &Map<String, JsonValue>) -> Result<Self, Error> {
+    fn parse_map(complex: &Map<String, JsonValue>) -> Result<Self, AvroError> {
         complex
             .get("values")
-            .ok_or_else(|| ParseSchemaError::new("No `values` in map").into())
+            .ok_or_else(|| AvroError::Parse("No `values` in map".to_owned()))
             .and_then(|items| Schema::parse(items))
             .map(|schema| Schema::Map(Box::new(schema)))
     }
 
     /// Parse a `serde_json::Value` representing a Avro union type into a
     /// `Schema`.
-    fn parse_union(items: &[JsonValue]) -> Result<Self, Error> {
+    fn parse_union(items: &[JsonValue]) -> Result<Self, AvroError> {
         items
             .iter()
             .map(Schema::parse)
@@ -604,13 +1092,13 @@ impl Schema {
 
     /// Parse a `serde_json::Value` representing a Avro fixed type into a
     /// `Schema`.
-    fn parse_fixed(complex: &Map<String, JsonValue>) -> Result<Self, Error> {
+    fn parse_fixed(complex: &Map<String, JsonValue>) -> Result<Self, AvroError> {
         let name = Name::parse(complex)?;
 
         let size = complex
             .get("size")
             .and_then(|v| v.as_i64())
-            .ok_or_else(|| ParseSchemaError::new("No `size` in fixed"))?;
+            .ok_or_else(|| AvroError::Parse("No `size` in fixed".to_owned()))?;
 
         Ok(Schema::Fixed {
             name,
@@ -618,46 +1106,176 @@ impl Schema {
         })
     }
 
+    /// Parse a `serde_json::Value` representing a date type into a `Schema`. Dates are in
+    /// milliseconds unless specified otherwise.
+    fn parse_date(complex: &Map<String, JsonValue>) -> Result<Self, AvroError> {
+        let unit = match complex.get("unit") {
+            None => DateUnit::Millis,
+            Some(JsonValue::String(unit)) => match unit.as_str() {
+                "seconds" => DateUnit::Seconds,
+                "millis" => DateUnit::Millis,
+                "micros" => DateUnit::Micros,
+                other => {
+                    return Err(AvroError::Parse(format!("Unknown date unit: {}", other)))
+                },
+            },
+            Some(other) => {
+                return Err(AvroError::Parse(format!("Unknown date unit: {}", other)))
+            },
+        };
+        Ok(Schema::Date(unit))
+    }
+
+    /// Parse a `serde_json::Value` representing a HyperLogLog type into a `Schema`.
+    fn parse_hll(complex: &Map<String, JsonValue>) -> Result<Self, AvroError> {
+        let precision = complex
+            .get("precision")
+            .and_then(|precision| precision.as_u64())
+            .ok_or_else(|| AvroError::Parse("No `precision` in hll".to_owned()))?;
+        if precision < u64::from(MIN_PRECISION) || precision > u64::from(MAX_PRECISION) {
+            return Err(AvroError::Parse(format!(
+                "hll precision must be between {} and {}, got {}",
+                MIN_PRECISION, MAX_PRECISION, precision
+            )))
+        }
+        Ok(Schema::Hll(precision as u8))
+    }
+
+    /// Parse a `serde_json::Value` representing a Bloom filter type into a `Schema`.
+    fn parse_bloom(complex: &Map<String, JsonValue>) -> Result<Self, AvroError> {
+        let bits = complex
+            .get("bits")
+            .and_then(|bits| bits.as_u64())
+            .ok_or_else(|| AvroError::Parse("No `bits` in bloom".to_owned()))?;
+        let hashes = complex
+            .get("hashes")
+            .and_then(|hashes| hashes.as_u64())
+            .ok_or_else(|| AvroError::Parse("No `hashes` in bloom".to_owned()))?;
+        if bits == 0 || bits > u64::from(u32::MAX) {
+            return Err(AvroError::Parse(format!("Invalid bloom bits: {}", bits)))
+        }
+        if hashes == 0 || hashes > u64::from(u8::MAX) {
+            return Err(AvroError::Parse(format!("Invalid bloom hashes: {}", hashes)))
+        }
+        Ok(Schema::Bloom {
+            bits: bits as u32,
+            hashes: hashes as u8,
+        })
+    }
+
+    /// Parse a `serde_json::Value` representing a top-k type into a `Schema`.
+    fn parse_topk(complex: &Map<String, JsonValue>) -> Result<Self, AvroError> {
+        let size = complex
+            .get("size")
+            .and_then(|size| size.as_u64())
+            .ok_or_else(|| AvroError::Parse("No `size` in topk".to_owned()))?;
+        if size == 0 {
+            return Err(AvroError::Parse("topk size must be positive".to_owned()))
+        }
+        Ok(Schema::TopK(size as usize))
+    }
+
+    /// Parse a `serde_json::Value` representing a Avro set type into a
+    /// `Schema`. Items are strings unless specified otherwise.
+    fn parse_set(complex: &Map<String, JsonValue>) -> Result<Self, AvroError> {
+        let items = match complex.get("items") {
+            Some(items) => Schema::parse(items)?,
+            None => Schema::String,
+        };
+        if !SetItem::supports(&items) {
+            return Err(AvroError::Parse(format!(
+                "Unsupported set items: {}", items.type_name()
+            )))
+        }
+        Ok(Schema::Set(Box::new(items)))
+    }
+
     /// Parse a `serde_json::Value` representing a Avro array type into a
     /// `Schema`.
-    fn parse_lru_set(complex: &Map<String, JsonValue>) -> Result<Self, Error> {
-        complex
-            .get("limit")
-            .ok_or_else(|| ParseSchemaError::new("No `limit` specified for lru_set").into())
-            .and_then(|limit| Schema::parse_lru_limit(limit))
-            .map(|lru_limit| Schema::LruSet(lru_limit))
-    }
-
-    fn parse_lru_limit(v: &JsonValue) -> Result<LruLimit, Error> {
-        v.as_str()
-            .ok_or_else(|| failure::err_msg(format!("Not a valid limit value for lru_set type: {}", v)))
-            .map(|s| LRU_LIMIT_REGEX.captures(s))
-            .and_then(|r| {
-                r
-                    .ok_or_else(|| failure::err_msg(format!("Not a valid limit value for lru_set type: {}", v)))
-                    .and_then(|caps| {
-                        let value = caps.name("value").unwrap().as_str();
-                        let value = value.parse::<u16>()?;
-
-                        caps.name("type")
-                            .map_or(Ok(LruLimit::Count(value)), |r| {
-                                match r.as_str() {
-                                    "days" => Ok(LruLimit::Days(value)),
-                                    "hour" => Ok(LruLimit::Hour(value)),
-                                    "minute" => Ok(LruLimit::Minute(value)),
-                                    other => Err(failure::err_msg(format!("Not a valid limit value for lru_set type: {}", other))),
-                                }
-                            })
-                    })
-            })
+    fn parse_lru_set(complex: &Map<String, JsonValue>) -> Result<Self, AvroError> {
+        let invalid = |name: &str, v: &JsonValue| {
+            AvroError::Parse(format!("Not a valid {} for lru_set: {}", name, v))
+        };
+        let max_entries = match complex.get("max_entries") {
+            None | Some(JsonValue::Null) => None,
+            Some(v) => Some(
+                v.as_u64()
+                    .filter(|max_entries| *max_entries <= u64::from(u32::MAX))
+                    .ok_or_else(|| invalid("max_entries", v))? as u32,
+            ),
+        };
+        let ttl_millis = match complex.get("ttl_ms") {
+            None | Some(JsonValue::Null) => None,
+            Some(v) => Some(
+                v.as_i64()
+                    .filter(|ttl_millis| *ttl_millis > 0)
+                    .ok_or_else(|| invalid("ttl_ms", v))?,
+            ),
+        };
+        // null attributes declare an unbounded set
+        let entries = complex.contains_key("max_entries") || complex.contains_key("ttl_ms");
+        let limit = match (complex.get("limit"), entries) {
+            (Some(limit), false) => Schema::parse_lru_limit(limit)?,
+            (None, false) => {
+                return Err(AvroError::Parse("No `limit` specified for lru_set".to_owned()))
+            },
+            (None, true) => LruLimit::Entries {
+                max_entries,
+                ttl_millis,
+            },
+            (Some(_), true) => {
+                return Err(AvroError::Parse(
+                    "lru_set takes either a `limit` or `max_entries` and `ttl_ms` attributes"
+                        .to_owned(),
+                ))
+            },
+        };
+        let decay = match complex.get("half_life") {
+            None => None,
+            Some(half_life) => Some(Schema::parse_half_life(half_life)?),
+        };
+        Ok(Schema::LruSet(limit, decay))
+    }
+
+    /// Parse the `half_life` of a `lru_set`, either a duration or a number of milliseconds.
+    fn parse_half_life(v: &JsonValue) -> Result<Decay, AvroError> {
+        let half_life_millis = match v.as_i64() {
+            Some(millis) => Some(millis),
+            None => Schema::parse_lru_limit(v).ok().and_then(|limit| limit.ttl_millis()),
+        };
+        match half_life_millis {
+            Some(half_life_millis) if half_life_millis > 0 => Ok(Decay { half_life_millis }),
+            _ => {
+                Err(AvroError::Parse(format!("Not a valid half_life for lru_set: {}", v)))
+            },
+        }
+    }
+
+    fn parse_lru_limit(v: &JsonValue) -> Result<LruLimit, AvroError> {
+        let invalid = || {
+            AvroError::Parse(format!("Not a valid limit value for lru_set type: {}", v))
+        };
+        let caps = v.as_str().and_then(|s| LRU_LIMIT_REGEX.captures(s)).ok_or_else(invalid)?;
+        let value = caps.name("value").unwrap().as_str();
+        let count = || value.parse::<u16>().map_err(|_| invalid());
+        match caps.name("type").map(|r| r.as_str()) {
+            None => Ok(LruLimit::Count(count()?)),
+            Some("days") => Ok(LruLimit::Days(count()?)),
+            Some("hour") => Ok(LruLimit::Hour(count()?)),
+            Some("minute") => Ok(LruLimit::Minute(count()?)),
+            Some("bytes") => Ok(LruLimit::Bytes(value.parse::<u32>().map_err(|_| invalid())?)),
+            Some(other) => Err(AvroError::Parse(format!(
+                "Not a valid limit value for lru_set type: {}", other
+            ))),
+        }
     }
 
     /// Parse a `serde_json::Value` representing a Avro array type into a
     /// `Schema`.
-    fn parse_optional(complex: &Map<String, JsonValue>) -> Result<Self, Error> {
+    fn parse_optional(complex: &Map<String, JsonValue>) -> Result<Self, AvroError> {
         complex
             .get("value")
-            .ok_or_else(|| ParseSchemaError::new("No `value` defined for optional").into())
+            .ok_or_else(|| AvroError::Parse("No `value` defined for optional".to_owned()))
             .and_then(|value| Schema::parse(value))
             .map(|schema| Schema::Optional(Box::new(schema)))
     }
@@ -699,6 +1317,20 @@ impl Serialize for Schema {
                 map.serialize_entry("items", &*inner.clone())?;
                 map.end()
             }
+            Schema::BoundedList {
+                ref items,
+                max_len,
+                truncation,
+            } => {
+                let mut map = serializer.serialize_map(None)?;
+                map.serialize_entry("type", "bounded_list")?;
+                map.serialize_entry("items", &**items)?;
+                map.serialize_entry("max_length", &max_len)?;
+                if truncation != Truncation::Head {
+                    map.serialize_entry("truncate", truncation.name())?;
+                }
+                map.end()
+            }
             Schema::Map(ref inner) => {
                 let mut map = serializer.serialize_map(Some(2))?;
                 map.serialize_entry("type", "map")?;
@@ -738,12 +1370,16 @@ impl Serialize for Schema {
             Schema::Enum {
                 ref name,
                 ref symbols,
+                ref default,
                 ..
             } => {
                 let mut map = serializer.serialize_map(None)?;
                 map.serialize_entry("type", "enum")?;
                 map.serialize_entry("name", &name.name)?;
                 map.serialize_entry("symbols", symbols)?;
+                if let Some(ref default) = *default {
+                    map.serialize_entry("default", default)?;
+                }
                 map.end()
             }
             Schema::Fixed { ref name, ref size } => {
@@ -753,16 +1389,63 @@ impl Serialize for Schema {
                 map.serialize_entry("size", size)?;
                 map.end()
             }
-            Schema::Set => serializer.serialize_str("set"),
-            Schema::Date => {
+            Schema::Set(ref items) => match **items {
+                Schema::String => serializer.serialize_str("set"),
+                ref items => {
+                    let mut map = serializer.serialize_map(Some(2))?;
+                    map.serialize_entry("type", "set")?;
+                    map.serialize_entry("items", items)?;
+                    map.end()
+                }
+            },
+            Schema::Date(unit) => {
                 let mut map = serializer.serialize_map(None)?;
                 map.serialize_entry("type", "date")?;
+                if unit != DateUnit::Millis {
+                    map.serialize_entry("unit", unit.name())?;
+                }
                 map.end()
             }
-            Schema::LruSet(ref limit) => {
+            Schema::LruSet(ref limit, ref decay) => {
                 let mut map = serializer.serialize_map(None)?;
                 map.serialize_entry("type", "lru_set")?;
-                map.serialize_entry("limit", limit)?;
+                match *limit {
+                    LruLimit::Entries {
+                        max_entries,
+                        ttl_millis,
+                    } => {
+                        if max_entries.is_some() || ttl_millis.is_none() {
+                            map.serialize_entry("max_entries", &max_entries)?;
+                        }
+                        if let Some(ttl_millis) = ttl_millis {
+                            map.serialize_entry("ttl_ms", &ttl_millis)?;
+                        }
+                    },
+                    _ => map.serialize_entry("limit", limit)?,
+                }
+                if let Some(decay) = decay {
+                    map.serialize_entry("half_life", decay)?;
+                }
+                map.end()
+            }
+            Schema::Counter => serializer.serialize_str("counter"),
+            Schema::Hll(precision) => {
+                let mut map = serializer.serialize_map(Some(2))?;
+                map.serialize_entry("type", "hll")?;
+                map.serialize_entry("precision", &precision)?;
+                map.end()
+            }
+            Schema::Bloom { bits, hashes } => {
+                let mut map = serializer.serialize_map(Some(3))?;
+                map.serialize_entry("type", "bloom")?;
+                map.serialize_entry("bits", &bits)?;
+                map.serialize_entry("hashes", &hashes)?;
+                map.end()
+            }
+            Schema::TopK(size) => {
+                let mut map = serializer.serialize_map(Some(2))?;
+                map.serialize_entry("type", "topk")?;
+                map.serialize_entry("size", &size)?;
                 map.end()
             }
             Schema::Optional(ref inner) => {
@@ -781,13 +1464,30 @@ impl Serialize for RecordField {
             S: Serializer,
     {
         let mut map = serializer.serialize_map(None)?;
-        map.serialize_entry("name", &self.name)?;
+        map.serialize_entry("name", &*self.name)?;
         map.serialize_entry("type", &self.schema)?;
 
+        if let Some(ref aliases) = self.aliases {
+            map.serialize_entry("aliases", aliases)?;
+        }
+
         if let Some(ref default) = self.default {
             map.serialize_entry("default", default)?;
         }
 
+        if self.metadata.index {
+            map.serialize_entry("index", &true)?;
+        }
+        if self.metadata.pii {
+            map.serialize_entry("pii", &true)?;
+        }
+        if let Some(ttl_millis) = self.metadata.ttl_millis {
+            map.serialize_entry("ttl_ms", &ttl_millis)?;
+        }
+        if !self.metadata.tags.is_empty() {
+            map.serialize_entry("tags", &self.metadata.tags)?;
+        }
+
         map.end()
     }
 }
@@ -991,22 +1691,24 @@ mod tests {
             doc: None,
             fields: vec![
                 RecordField {
-                    name: "a".to_string(),
+                    name: "a".into(),
                     doc: None,
+                    aliases: None,
                     default: Some(JsonValue::Number(42i64.into())),
                     schema: Schema::Long,
                     order: RecordFieldOrder::Ascending,
                     position: 0,
-                    index: false,
+                    metadata: Default::default(),
                 },
                 RecordField {
-                    name: "b".to_string(),
+                    name: "b".into(),
                     doc: None,
+                    aliases: None,
                     default: None,
                     schema: Schema::String,
                     order: RecordFieldOrder::Ascending,
                     position: 1,
-                    index: false,
+                    metadata: Default::default(),
                 },
             ],
             lookup,
@@ -1015,6 +1717,30 @@ mod tests {
         assert_eq!(expected, schema);
     }
 
+    #[test]
+    fn test_record_union_field_schema() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": ["null", "long"]}
+                ]
+            }
+        "#,
+        ).unwrap();
+
+        match schema {
+            Schema::Record { ref fields, .. } => assert_eq!(
+                fields[0].schema,
+                Schema::Union(UnionSchema::new(vec![Schema::Null, Schema::Long]).unwrap())
+            ),
+            _ => unreachable!(),
+        }
+        assert_eq!(schema.type_name(), "test");
+    }
+
     #[test]
     fn test_enum_schema() {
         let schema = Schema::parse_str(
@@ -1030,11 +1756,101 @@ mod tests {
                 "clubs".to_owned(),
                 "hearts".to_owned(),
             ],
+            default: None,
         };
 
         assert_eq!(expected, schema);
     }
 
+    #[test]
+    fn test_enum_default() {
+        let raw = r#"{"type":"enum","name":"Suit","symbols":["spades","other"],"default":"other"}"#;
+        let schema = Schema::parse_str(raw).unwrap();
+        match schema {
+            Schema::Enum { ref default, .. } => assert_eq!(default.as_deref(), Some("other")),
+            _ => panic!("enum expected"),
+        }
+        assert_eq!(serde_json::to_string(&schema).unwrap(), raw);
+
+        assert!(Schema::parse_str(
+            r#"{"type": "enum", "name": "Suit", "symbols": ["spades"], "default": "hearts"}"#
+        ).is_err());
+    }
+
+    #[test]
+    fn test_duplicate_names() {
+        let error = Schema::parse_str(
+            r#"{"type": "record", "name": "r", "fields": [
+                {"name": "a", "type": "long"},
+                {"name": "b", "type": "long"},
+                {"name": "a", "type": "string"}
+            ]}"#,
+        ).unwrap_err();
+        let message = "field `a` in record `r` at position 2, first at position 0";
+        assert!(error.to_string().contains(message));
+
+        let error = Schema::parse_str(
+            r#"{"type": "enum", "name": "Suit", "symbols": ["spades", "hearts", "spades"]}"#,
+        ).unwrap_err();
+        assert!(error.to_string().contains("symbol `spades` in enum `Suit` at position 2"));
+    }
+
+    #[test]
+    fn test_name_validation() {
+        assert!(is_valid_name("_Name_01"));
+        assert!(!is_valid_name("1st"));
+        assert!(!is_valid_name("with-dash"));
+        assert!(!is_valid_name(""));
+
+        let parse = |name: &str, namespace: &str, field: &str, symbol: &str| {
+            Schema::parse_str(&format!(
+                r#"{{"type": "record", "name": "{}", "namespace": "{}", "fields": [
+                    {{"name": "{}", "type": {{"type": "enum", "name": "e", "symbols": ["{}"]}}}}
+                ]}}"#,
+                name, namespace, field, symbol
+            ))
+        };
+        assert!(parse("r", "", "f", "S").is_ok());
+        assert!(parse("com.example.r", "org.example", "f", "S").is_ok());
+        let errors = [
+            (parse("r-1", "", "f", "S"), "Invalid name `r-1`"),
+            (parse("com..r", "", "f", "S"), "Invalid name `com..r`"),
+            (parse("r", "org.1example", "f", "S"), "Invalid namespace `org.1example`"),
+            (parse("r", "", "f.g", "S"), "Invalid field name `f.g`"),
+            (parse("r", "", "f", "S S"), "Invalid enum symbol `S S`"),
+        ];
+        for (result, message) in &errors {
+            let error = result.as_ref().map(|_| ()).unwrap_err();
+            assert!(error.to_string().contains(message), "{}", error);
+        }
+
+        // legacy names are accepted when asked to, for this schema only
+        let legacy = r#"{"type": "record", "name": "r-1", "fields": [
+            {"name": "f.g", "type": {"type": "enum", "name": "e", "symbols": ["S S"]}}
+        ]}"#;
+        let options = ParseOptions {
+            validate_names: false,
+        };
+        let schema = Schema::parse_str_with_options(legacy, &options).unwrap();
+        assert!(matches!(schema, Schema::Record { ref name, .. } if name.name == "r-1"));
+        assert!(Schema::parse_str(legacy).is_err());
+        let error = Schema::parse_str_with_options(legacy, &ParseOptions::default()).unwrap_err();
+        assert!(error.to_string().contains("Invalid name `r-1`"));
+    }
+
+    #[test]
+    fn test_named_type_reference() {
+        let error = Schema::parse_str(
+            r#"{"type": "record", "name": "node", "fields": [
+                {"name": "next", "type": ["null", "node"]}
+            ]}"#,
+        ).unwrap_err();
+        assert!(error.to_string().contains("references to named types are not supported"));
+
+        let error = Schema::parse_str(r#""not a type""#).unwrap_err();
+        assert!(!error.to_string().contains("references"));
+    }
+
     #[test]
     fn test_fixed_schema() {
         let schema = Schema::parse_str(r#"{"type": "fixed", "name": "test", "size": 16}"#).unwrap();
@@ -1047,6 +1863,215 @@ mod tests {
         assert_eq!(expected, schema);
     }
 
+    #[test]
+    fn test_set_schema() {
+        let schema = Schema::parse_str(r#""set""#).unwrap();
+        assert_eq!(schema, Schema::Set(Box::new(Schema::String)));
+        assert_eq!(serde_json::to_string(&schema).unwrap(), r#""set""#);
+
+        let schema = Schema::parse_str(r#"{"type": "set", "items": "long"}"#).unwrap();
+        assert_eq!(schema, Schema::Set(Box::new(Schema::Long)));
+        assert_eq!(serde_json::to_string(&schema).unwrap(), r#"{"type":"set","items":"long"}"#);
+
+        assert!(Schema::parse_str(r#"{"type": "set", "items": "double"}"#).is_err());
+    }
+
+    #[test]
+    fn test_hll_schema() {
+        let schema = Schema::parse_str(r#"{"type": "hll", "precision": 12}"#).unwrap();
+        assert_eq!(schema, Schema::Hll(12));
+        assert_eq!(serde_json::to_string(&schema).unwrap(), r#"{"type":"hll","precision":12}"#);
+
+        assert!(Schema::parse_str(r#"{"type": "hll"}"#).is_err());
+        assert!(Schema::parse_str(r#"{"type": "hll", "precision": 20}"#).is_err());
+    }
+
+    #[test]
+    fn test_bounded_list_schema() {
+        let schema = Schema::parse_str(
+            r#"{"type": "bounded_list", "items": "long", "max_length": 3, "truncate": "tail"}"#,
+        ).unwrap();
+        assert_eq!(
+            schema,
+            Schema::BoundedList {
+                items: Box::new(Schema::Long),
+                max_len: 3,
+                truncation: Truncation::Tail,
+            }
+        );
+        assert_eq!(
+            serde_json::to_string(&schema).unwrap(),
+            r#"{"type":"bounded_list","items":"long","max_length":3,"truncate":"tail"}"#
+        );
+
+        let schema = Schema::parse_str(
+            r#"{"type": "bounded_list", "items": "long", "max_length": 3}"#,
+        ).unwrap();
+        assert_eq!(
+            serde_json::to_string(&schema).unwrap(),
+            r#"{"type":"bounded_list","items":"long","max_length":3}"#
+        );
+
+        assert!(Schema::parse_str(r#"{"type": "bounded_list", "items": "long"}"#).is_err());
+        assert!(Schema::parse_str(
+            r#"{"type": "bounded_list", "items": "long", "max_length": 3, "truncate": "middle"}"#
+        ).is_err());
+    }
+
+    #[test]
+    fn test_truncation() {
+        let mut items = vec![1, 2, 3, 4];
+        Truncation::Head.apply(&mut items, 2);
+        assert_eq!(items, vec![3, 4]);
+        Truncation::Tail.apply(&mut items, 1);
+        assert_eq!(items, vec![3]);
+        Truncation::Tail.apply(&mut items, 5);
+        assert_eq!(items, vec![3]);
+    }
+
+    #[test]
+    fn test_bloom_schema() {
+        let schema = Schema::parse_str(r#"{"type": "bloom", "bits": 1024, "hashes": 3}"#).unwrap();
+        assert_eq!(schema, Schema::Bloom { bits: 1024, hashes: 3 });
+        assert_eq!(
+            serde_json::to_string(&schema).unwrap(),
+            r#"{"type":"bloom","bits":1024,"hashes":3}"#
+        );
+
+        assert!(Schema::parse_str(r#"{"type": "bloom", "bits": 1024}"#).is_err());
+        assert!(Schema::parse_str(r#"{"type": "bloom", "bits": 0, "hashes": 3}"#).is_err());
+        assert!(Schema::parse_str(r#"{"type": "bloom", "bits": 8, "hashes": 300}"#).is_err());
+    }
+
+    #[test]
+    fn test_topk_schema() {
+        let schema = Schema::parse_str(r#"{"type": "topk", "size": 10}"#).unwrap();
+        assert_eq!(schema, Schema::TopK(10));
+        assert_eq!(serde_json::to_string(&schema).unwrap(), r#"{"type":"topk","size":10}"#);
+
+        assert!(Schema::parse_str(r#"{"type": "topk"}"#).is_err());
+        assert!(Schema::parse_str(r#"{"type": "topk", "size": 0}"#).is_err());
+    }
+
+    #[test]
+    fn test_date_schema() {
+        let schema = Schema::parse_str(r#""date""#).unwrap();
+        assert_eq!(schema, Schema::Date(DateUnit::Millis));
+        assert_eq!(serde_json::to_string(&schema).unwrap(), r#"{"type":"date"}"#);
+
+        let schema = Schema::parse_str(r#"{"type": "date", "unit": "seconds"}"#).unwrap();
+        assert_eq!(schema, Schema::Date(DateUnit::Seconds));
+        assert_eq!(serde_json::to_string(&schema).unwrap(), r#"{"type":"date","unit":"seconds"}"#);
+
+        assert!(Schema::parse_str(r#"{"type": "date", "unit": "days"}"#).is_err());
+        assert_eq!(DateUnit::Micros.to_millis(-1), -1);
+        assert_eq!(DateUnit::Seconds.from_millis(1999), 1);
+    }
+
+    #[test]
+    fn test_lru_set_bytes_limit() {
+        let schema = Schema::parse_str(r#"{"type": "lru_set", "limit": "100000 bytes"}"#).unwrap();
+        assert_eq!(schema, Schema::LruSet(LruLimit::Bytes(100000), None));
+        assert_eq!(
+            serde_json::to_string(&schema).unwrap(),
+            r#"{"type":"lru_set","limit":"100000 bytes"}"#
+        );
+        assert!(Schema::parse_str(r#"{"type": "lru_set", "limit": "100000 days"}"#).is_err());
+    }
+
+    #[test]
+    fn test_lru_set_attributes() {
+        let schema =
+            Schema::parse_str(r#"{"type": "lru_set", "max_entries": 1000, "ttl_ms": 86400000}"#)
+                .unwrap();
+        let limit = LruLimit::Entries {
+            max_entries: Some(1000),
+            ttl_millis: Some(86_400_000),
+        };
+        assert_eq!(limit.max_entries(), Some(1000));
+        assert_eq!(limit.ttl_millis(), Some(86_400_000));
+        assert_eq!(schema, Schema::LruSet(limit, None));
+        assert_eq!(
+            serde_json::to_string(&schema).unwrap(),
+            r#"{"type":"lru_set","max_entries":1000,"ttl_ms":86400000}"#
+        );
+
+        let schema = Schema::parse_str(r#"{"type": "lru_set", "ttl_ms": 1000}"#).unwrap();
+        assert_eq!(
+            serde_json::to_string(&schema).unwrap(),
+            r#"{"type":"lru_set","ttl_ms":1000}"#
+        );
+
+        // every limit is serialized in a form parsed back to the same limit
+        for &(max_entries, ttl_millis) in &[
+            (None, None),
+            (Some(10), None),
+            (None, Some(1000)),
+            (Some(10), Some(1000)),
+        ] {
+            let limit = LruLimit::Entries {
+                max_entries,
+                ttl_millis,
+            };
+            let schema = Schema::LruSet(limit, None);
+            let parsed = Schema::parse_str(&serde_json::to_string(&schema).unwrap()).unwrap();
+            assert_eq!(parsed, schema);
+        }
+        let unbounded = Schema::parse_str(r#"{"type": "lru_set", "max_entries": null}"#).unwrap();
+        assert_eq!(
+            serde_json::to_string(&unbounded).unwrap(),
+            r#"{"type":"lru_set","max_entries":null}"#
+        );
+
+        for schema in &[
+            r#"{"type": "lru_set"}"#,
+            r#"{"type": "lru_set", "limit": "10", "max_entries": 10}"#,
+            r#"{"type": "lru_set", "limit": "10", "max_entries": null}"#,
+            r#"{"type": "lru_set", "max_entries": -1}"#,
+            r#"{"type": "lru_set", "ttl_ms": 0}"#,
+        ] {
+            assert!(Schema::parse_str(schema).is_err());
+        }
+    }
+
+    #[test]
+    fn test_lru_set_half_life() {
+        let schema = Schema::parse_str(
+            r#"{"type": "lru_set", "limit": "10", "half_life": "7 days"}"#,
+        ).unwrap();
+        let decay = Decay {
+            half_life_millis: 7 * 24 * 60 * 60 * 1000,
+        };
+        assert_eq!(schema, Schema::LruSet(LruLimit::Count(10), Some(decay)));
+        assert_eq!(
+            serde_json::to_string(&schema).unwrap(),
+            r#"{"type":"lru_set","limit":"10","half_life":"7 days"}"#
+        );
+
+        let schema =
+            Schema::parse_str(r#"{"type": "lru_set", "limit": "10", "half_life": 1500}"#).unwrap();
+        assert_eq!(
+            serde_json::to_string(&schema).unwrap(),
+            r#"{"type":"lru_set","limit":"10","half_life":1500}"#
+        );
+
+        for half_life in &[r#""10""#, "0", r#""1 bytes""#] {
+            let schema = format!(r#"{{"type": "lru_set", "limit": "10", "half_life": {}}}"#, half_life);
+            assert!(Schema::parse_str(&schema).is_err());
+        }
+    }
+
+    #[test]
+    fn test_decay() {
+        let decay = Decay {
+            half_life_millis: 1000,
+        };
+        assert_eq!(decay.apply(100, 0), 100);
+        assert_eq!(decay.apply(100, 1000), 50);
+        assert_eq!(decay.apply(100, 2000), 25);
+        assert_eq!(decay.apply(100, -1000), 100);
+    }
+
     #[test]
     fn test_no_documentation() {
         let schema =
@@ -1120,5 +2145,7 @@ mod tests {
             "7bce8188f28e66480a45ffbdc3615b7d",
             format!("{}", schema.fingerprint::<Md5>())
         );
+
+        assert_eq!("8a8f25cce724dd63", format!("{}", Schema::Null.rabin_fingerprint()));
     }
 }
@@ -7,7 +7,7 @@ use crate::{Codec, Writer};
 use crate::reader::Reader;
 use crate::schema::Schema;
 use crate::types;
-use crate::types::{LruValue, Record, Value};
+use crate::types::{LruValue, Record, SetItem, Value};
 
 use super::*;
 
@@ -104,7 +104,7 @@ fn test_write_file_for_all_types() {
     map1.insert("key1", "FOLLOW");
 
     let mut set = HashSet::new();
-    set.insert("hello".to_owned());
+    set.insert(SetItem::from("hello"));
 
     let mut lru_set: HashMap<String, LruValue> = HashMap::new();
     let lru_value = LruValue { access_time: 123455, count: 22 };
@@ -117,7 +117,7 @@ fn test_write_file_for_all_types() {
     record.put("map", map);
     record.put("map1", map1);
     record.put("set", Value::Set(set, None));
-    record.put("lruSet", Value::LruSet(lru_set, LruLimit::Days(30), None));
+    record.put("lruSet", Value::LruSet(Box::new(lru_set.into()), LruLimit::Days(30), None));
     record.put("optionalStr", Value::Optional(None, None));
 
 
@@ -189,8 +189,8 @@ fn test_write_file_enum_string_value() {
     assert_eq!(
         reader.next().unwrap().unwrap(),
         Value::Record(vec![
-            ("a".to_string(), Value::Long(27, None)),
-            ("b".to_string(), Value::String("foo".to_string(), None)),
+            ("a".into(), Value::Long(27, None)),
+            ("b".into(), Value::String("foo".to_string(), None)),
         ], None)
     );
     assert!(reader.next().is_none());
@@ -2,6 +2,7 @@
 use std::collections::HashMap;
 use std::error;
 use std::fmt;
+use std::sync::Arc;
 use std::iter::once;
 
 use serde::ser::{self, Error as SerdeError, Serialize};
@@ -21,7 +22,7 @@ pub struct MapSerializer {
 }
 
 pub struct StructSerializer {
-    fields: Vec<(String, Value)>,
+    fields: Vec<(Arc<str>, Value)>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -374,7 +375,7 @@ impl ser::SerializeStruct for StructSerializer {
         T: Serialize,
     {
         self.fields.push((
-            name.to_owned(),
+            name.into(),
             value.serialize(&mut Serializer::default())?,
         ));
         Ok(())
@@ -427,8 +428,8 @@ mod tests {
             b: "foo".to_owned(),
         };
         let expected = Value::Record(vec![
-            ("a".to_owned(), Value::Long(27, None)),
-            ("b".to_owned(), Value::String("foo".to_owned(), None)),
+            ("a".into(), Value::Long(27, None)),
+            ("b".into(), Value::String("foo".to_owned(), None)),
         ], None);
 
         assert_eq!(to_value(test).unwrap(), expected);
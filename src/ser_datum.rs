@@ -0,0 +1,1005 @@
+//! Logic for serde-compatible serialization straight into avro format.
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use serde::ser::{self, Error as SerdeError, Impossible, Serialize};
+
+use crate::encode::{encode, encode_int, encode_long};
+use crate::schema::{RecordField, Schema, SchemaKind, Truncation};
+use crate::ser::{to_value, Error};
+use crate::types::{field_default, Value, ValueSetting};
+
+/// Serialize `value` in avro format given its `Schema` into `buffer`, without building a `Value`
+/// first.
+///
+/// Struct fields are matched to the record fields by name, in any order: fields unknown to the
+/// schema are left out, and missing ones take their default value. Numbers are converted to the
+/// numeric type of the schema, as for schema resolution, and union variants are picked by the
+/// kind of the serialized value. Dates, sets, lru sets, topks, hlls and blooms, which must be
+/// resolved as a whole, are still serialized through a `Value`.
+pub(crate) fn serialize_datum<T>(
+    value: &T,
+    schema: &Schema,
+    buffer: &mut Vec<u8>,
+) -> Result<(), Error>
+where
+    T: Serialize + ?Sized,
+{
+    if via_value(schema) {
+        let value = to_value(value)?.resolve(schema).map_err(Error::custom)?;
+        encode(&value, schema, buffer);
+        return Ok(())
+    }
+    value.serialize(DatumSerializer { schema, buffer })
+}
+
+/// Whether values of `schema` must be built as a `Value` to be resolved against it.
+fn via_value(schema: &Schema) -> bool {
+    match *schema {
+        Schema::Date(_)
+        | Schema::Set(_)
+        | Schema::LruSet(..)
+        | Schema::TopK(_)
+        | Schema::Hll(_)
+        | Schema::Bloom { .. } => true,
+        Schema::Union(ref inner) => inner.variants().iter().any(via_value),
+        Schema::Optional(ref inner) => via_value(inner),
+        _ => false,
+    }
+}
+
+fn mismatch(schema: &Schema, kind: SchemaKind) -> Error {
+    Error::custom(format!("{} expected, got {:?}", schema.type_name(), kind))
+}
+
+fn write_bytes(bytes: &[u8], buffer: &mut Vec<u8>) {
+    encode_long(bytes.len() as i64, buffer);
+    buffer.extend_from_slice(bytes);
+}
+
+/// Write the count of the `count` items of an array or a map written from `start`, unless it was
+/// written before them, and the end of its blocks.
+fn end_block(buffer: &mut Vec<u8>, start: usize, count: usize, counted: bool) {
+    if count > 0 && !counted {
+        let mut header = Vec::new();
+        encode_long(count as i64, &mut header);
+        buffer.splice(start..start, header);
+    }
+    buffer.push(0u8);
+}
+
+/// Serializer of a value given its `Schema`.
+struct DatumSerializer<'b, 's> {
+    schema: &'s Schema,
+    buffer: &'b mut Vec<u8>,
+}
+
+impl<'b, 's> DatumSerializer<'b, 's> {
+    /// Schema to write a value of `kind` with: the first variant of this kind of a union, of
+    /// which the index is written first, the value of an optional, written as present, or the
+    /// schema itself.
+    fn target(&mut self, kind: SchemaKind) -> Result<&'s Schema, Error> {
+        match *self.schema {
+            Schema::Union(ref inner) => {
+                let position = inner
+                    .variants()
+                    .iter()
+                    .position(|variant| SchemaKind::from(variant) == kind)
+                    .ok_or_else(|| mismatch(self.schema, kind))?;
+                encode_long(position as i64, self.buffer);
+                Ok(&inner.variants()[position])
+            },
+            Schema::Optional(ref inner) => {
+                encode_long(1, self.buffer);
+                Ok(inner)
+            },
+            ref schema => Ok(schema),
+        }
+    }
+
+    fn write_number(mut self, n: i64, kind: SchemaKind) -> Result<(), Error> {
+        match *self.target(kind)? {
+            Schema::Int => match i32::try_from(n) {
+                Ok(n) => encode_int(n, self.buffer),
+                Err(_) => return Err(Error::custom(format!("{} does not fit in an int", n))),
+            },
+            Schema::Long | Schema::Counter => encode_long(n, self.buffer),
+            Schema::Float => self.buffer.extend_from_slice(&(n as f32).to_le_bytes()),
+            Schema::Double => self.buffer.extend_from_slice(&(n as f64).to_le_bytes()),
+            ref schema => return Err(mismatch(schema, kind)),
+        }
+        Ok(())
+    }
+
+    fn write_float(mut self, x: f64, kind: SchemaKind) -> Result<(), Error> {
+        match *self.target(kind)? {
+            Schema::Float => self.buffer.extend_from_slice(&(x as f32).to_le_bytes()),
+            Schema::Double => self.buffer.extend_from_slice(&x.to_le_bytes()),
+            ref schema => return Err(mismatch(schema, kind)),
+        }
+        Ok(())
+    }
+
+    fn write_symbol(self, schema: &Schema, symbol: &str) -> Result<(), Error> {
+        if let Schema::Enum {
+            ref symbols,
+            ref default,
+            ..
+        } = *schema
+        {
+            let position = |symbol: &str| symbols.iter().position(|item| item == symbol);
+            // symbols unknown to the schema are written as its default symbol, if any
+            if let Some(index) = position(symbol).or_else(|| default.as_deref().and_then(position))
+            {
+                encode_int(index as i32, self.buffer);
+                return Ok(())
+            }
+        }
+        Err(Error::custom(format!("symbol {} is not in {:?}", symbol, schema.type_name())))
+    }
+}
+
+impl<'b, 's> ser::Serializer for DatumSerializer<'b, 's> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = SeqSerializer<'b, 's>;
+    type SerializeTuple = SeqSerializer<'b, 's>;
+    type SerializeTupleStruct = SeqSerializer<'b, 's>;
+    type SerializeTupleVariant = Impossible<(), Error>;
+    type SerializeMap = CompoundSerializer<'b, 's>;
+    type SerializeStruct = CompoundSerializer<'b, 's>;
+    type SerializeStructVariant = Impossible<(), Error>;
+
+    fn serialize_bool(mut self, v: bool) -> Result<Self::Ok, Self::Error> {
+        match *self.target(SchemaKind::Boolean)? {
+            Schema::Boolean => {
+                self.buffer.push(v as u8);
+                Ok(())
+            },
+            ref schema => Err(mismatch(schema, SchemaKind::Boolean)),
+        }
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok, Self::Error> {
+        self.write_number(i64::from(v), SchemaKind::Int)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok, Self::Error> {
+        self.write_number(i64::from(v), SchemaKind::Int)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok, Self::Error> {
+        self.write_number(i64::from(v), SchemaKind::Int)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok, Self::Error> {
+        self.write_number(v, SchemaKind::Long)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok, Self::Error> {
+        self.write_number(i64::from(v), SchemaKind::Int)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok, Self::Error> {
+        self.write_number(i64::from(v), SchemaKind::Int)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok, Self::Error> {
+        if v <= i32::MAX as u32 {
+            self.write_number(i64::from(v), SchemaKind::Int)
+        } else {
+            self.write_number(i64::from(v), SchemaKind::Long)
+        }
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        match i64::try_from(v) {
+            Ok(v) => self.write_number(v, SchemaKind::Long),
+            Err(_) => Err(Error::custom("u64 is too large")),
+        }
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Self::Ok, Self::Error> {
+        self.write_float(f64::from(v), SchemaKind::Float)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Self::Ok, Self::Error> {
+        self.write_float(v, SchemaKind::Double)
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        self.serialize_str(v.encode_utf8(&mut [0u8; 4]))
+    }
+
+    fn serialize_str(mut self, v: &str) -> Result<Self::Ok, Self::Error> {
+        match *self.target(SchemaKind::String)? {
+            Schema::String | Schema::Bytes => write_bytes(v.as_bytes(), self.buffer),
+            ref schema @ Schema::Enum { .. } => return self.write_symbol(schema, v),
+            ref schema => return Err(mismatch(schema, SchemaKind::String)),
+        }
+        Ok(())
+    }
+
+    fn serialize_bytes(mut self, v: &[u8]) -> Result<Self::Ok, Self::Error> {
+        match *self.target(SchemaKind::Bytes)? {
+            Schema::Bytes => write_bytes(v, self.buffer),
+            Schema::String => match std::str::from_utf8(v) {
+                Ok(_) => write_bytes(v, self.buffer),
+                Err(_) => return Err(Error::custom("not a valid utf-8 string")),
+            },
+            Schema::Fixed { size, .. } if size == v.len() => self.buffer.extend_from_slice(v),
+            ref schema => return Err(mismatch(schema, SchemaKind::Bytes)),
+        }
+        Ok(())
+    }
+
+    fn serialize_none(mut self) -> Result<Self::Ok, Self::Error> {
+        match *self.schema {
+            Schema::Null => Ok(()),
+            Schema::Optional(_) => {
+                encode_long(0, self.buffer);
+                Ok(())
+            },
+            Schema::Union(_) => self.target(SchemaKind::Null).map(|_| ()),
+            ref schema => Err(mismatch(schema, SchemaKind::Null)),
+        }
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        self.serialize_none()
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<Self::Ok, Self::Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        mut self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        match *self.target(SchemaKind::Enum)? {
+            Schema::String => {
+                write_bytes(variant.as_bytes(), self.buffer);
+                Ok(())
+            },
+            ref schema => self.write_symbol(schema, variant),
+        }
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_seq(mut self, len: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        let (items, bounds) = match *self.target(SchemaKind::Array)? {
+            Schema::Array(ref items) => (&**items, None),
+            Schema::BoundedList {
+                ref items,
+                max_len,
+                truncation,
+            } => (&**items, Some((max_len, truncation, Vec::new()))),
+            Schema::Bytes => {
+                return Ok(SeqSerializer::Bytes {
+                    buffer: self.buffer,
+                    bytes: Vec::with_capacity(len.unwrap_or(0)),
+                })
+            },
+            ref schema => return Err(mismatch(schema, SchemaKind::Array)),
+        };
+        // the count of the items is written ahead of them when it is known and final
+        let counted = match len {
+            Some(len) if len > 0 && bounds.is_none() => {
+                encode_long(len as i64, self.buffer);
+                true
+            },
+            _ => false,
+        };
+        Ok(SeqSerializer::Items(SeqWriter {
+            start: self.buffer.len(),
+            buffer: self.buffer,
+            items,
+            count: 0,
+            counted,
+            bounds,
+        }))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        _: u32,
+        variant: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(Error::custom(format!("variant {}::{} with fields is not supported", name, variant)))
+    }
+
+    fn serialize_map(mut self, len: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        let schema = self.target(SchemaKind::Map)?;
+        CompoundSerializer::new(schema, self.buffer, len, SchemaKind::Map)
+    }
+
+    fn serialize_struct(
+        mut self,
+        _: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        let schema = self.target(SchemaKind::Record)?;
+        CompoundSerializer::new(schema, self.buffer, Some(len), SchemaKind::Record)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        _: u32,
+        variant: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(Error::custom(format!("variant {}::{} with fields is not supported", name, variant)))
+    }
+}
+
+/// Writer of the items of an array or a bounded list.
+pub(crate) struct SeqWriter<'b, 's> {
+    buffer: &'b mut Vec<u8>,
+    items: &'s Schema,
+    /// Position of the first item in the buffer.
+    start: usize,
+    count: usize,
+    /// Whether the count of the items was written before them.
+    counted: bool,
+    /// Maximum length and truncation of a bounded list, with the positions of its items.
+    bounds: Option<(usize, Truncation, Vec<usize>)>,
+}
+
+pub(crate) enum SeqSerializer<'b, 's> {
+    Items(SeqWriter<'b, 's>),
+    /// Sequence of bytes written with a `bytes` schema.
+    Bytes {
+        buffer: &'b mut Vec<u8>,
+        bytes: Vec<u8>,
+    },
+}
+
+impl<'b, 's> ser::SerializeSeq for SeqSerializer<'b, 's> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        match self {
+            SeqSerializer::Items(writer) => {
+                if let Some((_, _, ref mut positions)) = writer.bounds {
+                    positions.push(writer.buffer.len());
+                }
+                serialize_datum(value, writer.items, writer.buffer)?;
+                writer.count += 1;
+            },
+            SeqSerializer::Bytes { bytes, .. } => match to_value(value)? {
+                Value::Int(n, _) if (0..=255).contains(&n) => bytes.push(n as u8),
+                other => return Err(Error::custom(format!("byte expected, got {:?}", other))),
+            },
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        let mut writer = match self {
+            SeqSerializer::Items(writer) => writer,
+            SeqSerializer::Bytes { buffer, bytes } => {
+                write_bytes(&bytes, buffer);
+                return Ok(())
+            },
+        };
+        if let Some((max_len, truncation, positions)) = writer.bounds {
+            // items beyond the maximum length of a bounded list are dropped rather than written
+            if writer.count > max_len {
+                match truncation {
+                    Truncation::Head => {
+                        let end = positions[writer.count - max_len];
+                        writer.buffer.drain(writer.start..end);
+                    },
+                    Truncation::Tail => writer.buffer.truncate(positions[max_len]),
+                }
+                writer.count = max_len;
+            }
+        }
+        end_block(writer.buffer, writer.start, writer.count, writer.counted);
+        Ok(())
+    }
+}
+
+impl<'b, 's> ser::SerializeTuple for SeqSerializer<'b, 's> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl<'b, 's> ser::SerializeTupleStruct for SeqSerializer<'b, 's> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+/// Writer of the entries of a map.
+pub(crate) struct MapWriter<'b, 's> {
+    buffer: &'b mut Vec<u8>,
+    values: &'s Schema,
+    /// Position of the first entry in the buffer.
+    start: usize,
+    count: usize,
+    /// Whether the count of the entries was written before them.
+    counted: bool,
+}
+
+/// Writer of the fields of a record, in the order of the schema.
+pub(crate) struct RecordWriter<'b, 's> {
+    buffer: &'b mut Vec<u8>,
+    fields: &'s [RecordField],
+    lookup: &'s HashMap<String, usize>,
+    /// Position of the next field to write.
+    next: usize,
+    /// Fields serialized before the ones preceding them in the schema, by position.
+    pending: Vec<Option<Vec<u8>>>,
+    /// Key of the entry being serialized, when the record is serialized from a map.
+    key: Option<String>,
+}
+
+impl<'b, 's> RecordWriter<'b, 's> {
+    fn field<T>(&mut self, name: &str, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let position = match self.fields.get(self.next) {
+            Some(field) if &*field.name == name => self.next,
+            _ => match self.lookup.get(name) {
+                Some(&position) => position,
+                // fields unknown to the schema are left out
+                None => return Ok(()),
+            },
+        };
+        if position < self.next || self.pending.get(position).is_some_and(Option::is_some) {
+            return Err(Error::custom(format!("duplicate field {} in record", name)))
+        }
+
+        let schema = &self.fields[position].schema;
+        if position > self.next {
+            // fields ahead of their position in the schema wait for the ones before them
+            if self.pending.is_empty() {
+                self.pending.resize_with(self.fields.len(), || None);
+            }
+            let mut bytes = Vec::new();
+            serialize_datum(value, schema, &mut bytes)?;
+            self.pending[position] = Some(bytes);
+            return Ok(())
+        }
+
+        serialize_datum(value, schema, self.buffer)?;
+        self.next += 1;
+        while let Some(bytes) = self.pending.get_mut(self.next).and_then(Option::take) {
+            self.buffer.extend_from_slice(&bytes);
+            self.next += 1;
+        }
+        Ok(())
+    }
+
+    fn end(mut self) -> Result<(), Error> {
+        while let Some(field) = self.fields.get(self.next) {
+            match self.pending.get_mut(self.next).and_then(Option::take) {
+                Some(bytes) => self.buffer.extend_from_slice(&bytes),
+                None => {
+                    let setting = ValueSetting::of_field(field);
+                    let value = field_default(field, setting.as_ref())
+                        .map_err(Error::custom)?
+                        .ok_or_else(|| {
+                            Error::custom(format!("missing field {} in record", field.name))
+                        })?
+                        .resolve(&field.schema)
+                        .map_err(Error::custom)?;
+                    encode(&value, &field.schema, self.buffer);
+                },
+            }
+            self.next += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Serializer of a map or a struct, either into a map or into a record.
+pub(crate) enum CompoundSerializer<'b, 's> {
+    Map(MapWriter<'b, 's>),
+    Record(RecordWriter<'b, 's>),
+}
+
+impl<'b, 's> CompoundSerializer<'b, 's> {
+    fn new(
+        schema: &'s Schema,
+        buffer: &'b mut Vec<u8>,
+        len: Option<usize>,
+        kind: SchemaKind,
+    ) -> Result<CompoundSerializer<'b, 's>, Error> {
+        match *schema {
+            Schema::Map(ref values) => {
+                // the count of the entries is written ahead of them when it is known
+                let counted = match len {
+                    Some(len) if len > 0 => {
+                        encode_long(len as i64, buffer);
+                        true
+                    },
+                    _ => false,
+                };
+                Ok(CompoundSerializer::Map(MapWriter {
+                    start: buffer.len(),
+                    buffer,
+                    values,
+                    count: 0,
+                    counted,
+                }))
+            },
+            Schema::Record {
+                ref fields,
+                ref lookup,
+                ..
+            } => Ok(CompoundSerializer::Record(RecordWriter {
+                buffer,
+                fields,
+                lookup,
+                next: 0,
+                pending: Vec::new(),
+                key: None,
+            })),
+            ref schema => Err(mismatch(schema, kind)),
+        }
+    }
+}
+
+impl<'b, 's> ser::SerializeMap for CompoundSerializer<'b, 's> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        let key = key.serialize(KeySerializer)?;
+        match self {
+            CompoundSerializer::Map(writer) => {
+                write_bytes(key.as_bytes(), writer.buffer);
+                writer.count += 1;
+            },
+            CompoundSerializer::Record(writer) => writer.key = Some(key),
+        }
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        match self {
+            CompoundSerializer::Map(writer) => serialize_datum(value, writer.values, writer.buffer),
+            CompoundSerializer::Record(writer) => match writer.key.take() {
+                Some(key) => writer.field(&key, value),
+                None => Err(Error::custom("map value serialized before its key")),
+            },
+        }
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        match self {
+            CompoundSerializer::Map(writer) => {
+                end_block(writer.buffer, writer.start, writer.count, writer.counted);
+                Ok(())
+            },
+            CompoundSerializer::Record(writer) => writer.end(),
+        }
+    }
+}
+
+impl<'b, 's> ser::SerializeStruct for CompoundSerializer<'b, 's> {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T>(
+        &mut self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        match self {
+            CompoundSerializer::Map(writer) => {
+                write_bytes(name.as_bytes(), writer.buffer);
+                writer.count += 1;
+                serialize_datum(value, writer.values, writer.buffer)
+            },
+            CompoundSerializer::Record(writer) => writer.field(name, value),
+        }
+    }
+
+    fn end(self) -> Result<Self::Ok, Self::Error> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+/// Serializer of the keys of maps, which must be strings.
+struct KeySerializer;
+
+fn key_error() -> Error {
+    Error::custom("map key is not a string")
+}
+
+impl ser::Serializer for KeySerializer {
+    type Ok = String;
+    type Error = Error;
+    type SerializeSeq = Impossible<String, Error>;
+    type SerializeTuple = Impossible<String, Error>;
+    type SerializeTupleStruct = Impossible<String, Error>;
+    type SerializeTupleVariant = Impossible<String, Error>;
+    type SerializeMap = Impossible<String, Error>;
+    type SerializeStruct = Impossible<String, Error>;
+    type SerializeStructVariant = Impossible<String, Error>;
+
+    fn serialize_bool(self, _: bool) -> Result<Self::Ok, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_i8(self, _: i8) -> Result<Self::Ok, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_i16(self, _: i16) -> Result<Self::Ok, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_i32(self, _: i32) -> Result<Self::Ok, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_i64(self, _: i64) -> Result<Self::Ok, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_u8(self, _: u8) -> Result<Self::Ok, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_u16(self, _: u16) -> Result<Self::Ok, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_u32(self, _: u32) -> Result<Self::Ok, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_u64(self, _: u64) -> Result<Self::Ok, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_f32(self, _: f32) -> Result<Self::Ok, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_f64(self, _: f64) -> Result<Self::Ok, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_char(self, v: char) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Self::Ok, Self::Error> {
+        Ok(v.to_owned())
+    }
+
+    fn serialize_bytes(self, _: &[u8]) -> Result<Self::Ok, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_some<T>(self, _: &T) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        Err(key_error())
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<Self::Ok, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        variant: &'static str,
+    ) -> Result<Self::Ok, Self::Error> {
+        Ok(variant.to_owned())
+    }
+
+    fn serialize_newtype_struct<T>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<Self::Ok, Self::Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        Err(key_error())
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStruct, Self::Error> {
+        Err(key_error())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, Self::Error> {
+        Err(key_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::encode_to_vec;
+    use crate::types::ToAvro;
+
+    #[derive(Serialize)]
+    enum Color {
+        Red,
+        Blue,
+    }
+
+    #[derive(Serialize)]
+    struct Inner {
+        c: Option<String>,
+        b: Vec<u8>,
+    }
+
+    #[derive(Serialize)]
+    struct Test {
+        extra: bool,
+        inner: Inner,
+        a: i32,
+        color: Color,
+        l: Vec<i64>,
+        m: HashMap<String, f32>,
+        tags: Vec<String>,
+    }
+
+    fn schema() -> Schema {
+        Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": "long"},
+                    {"name": "d", "type": "string", "default": "foo"},
+                    {"name": "color", "type": {
+                        "type": "enum", "name": "color", "symbols": ["Blue", "Red"]
+                    }},
+                    {"name": "inner", "type": {
+                        "type": "record",
+                        "name": "inner",
+                        "fields": [
+                            {"name": "b", "type": "bytes"},
+                            {"name": "c", "type": ["null", "string"]}
+                        ]
+                    }},
+                    {"name": "l", "type": {
+                        "type": "bounded_list", "items": "long", "max_length": 2
+                    }},
+                    {"name": "m", "type": {"type": "map", "values": "double"}},
+                    {"name": "tags", "type": {"type": "set", "items": "string"}}
+                ]
+            }
+        "#,
+        ).unwrap()
+    }
+
+    #[test]
+    fn test_serialize_datum() {
+        let schema = schema();
+        let mut m = HashMap::new();
+        m.insert("x".to_owned(), 0.5f32);
+        let test = Test {
+            extra: true,
+            inner: Inner {
+                c: Some("bar".to_owned()),
+                b: vec![1, 2],
+            },
+            a: 3,
+            color: Color::Red,
+            l: vec![1, 2, 3],
+            m: m.clone(),
+            tags: vec!["t".to_owned()],
+        };
+        let mut expected = HashMap::new();
+        expected.insert("x".to_owned(), 0.5f64.avro());
+        let value = Value::record(vec![
+            ("a", 3i64.avro()),
+            ("d", "foo".avro()),
+            ("color", Value::Enum(1, "Red".to_owned(), None)),
+            (
+                "inner",
+                Value::record(vec![
+                    ("b", Value::Bytes(vec![1, 2], None)),
+                    ("c", Value::Union(Box::new("bar".avro()), None)),
+                ]),
+            ),
+            ("l", vec![2i64, 3i64].avro()),
+            ("m", Value::Map(expected, None)),
+            ("tags", Value::Set(vec!["t".into()].into_iter().collect(), None)),
+        ]);
+        assert!(value.validate(&schema));
+
+        let mut buffer = Vec::new();
+        serialize_datum(&test, &schema, &mut buffer).unwrap();
+        assert_eq!(buffer, encode_to_vec(&value, &schema));
+
+        let test = Test {
+            inner: Inner { c: None, b: vec![] },
+            color: Color::Blue,
+            l: vec![],
+            m: HashMap::new(),
+            ..test
+        };
+        let value = Value::record(vec![
+            ("a", 3i64.avro()),
+            ("d", "foo".avro()),
+            ("color", Value::Enum(0, "Blue".to_owned(), None)),
+            (
+                "inner",
+                Value::record(vec![
+                    ("b", Value::Bytes(vec![], None)),
+                    ("c", Value::Union(Box::new(Value::Null), None)),
+                ]),
+            ),
+            ("l", Vec::<i64>::new().avro()),
+            ("m", Value::Map(HashMap::new(), None)),
+            ("tags", Value::Set(vec!["t".into()].into_iter().collect(), None)),
+        ]);
+        let mut buffer = Vec::new();
+        serialize_datum(&test, &schema, &mut buffer).unwrap();
+        assert_eq!(buffer, encode_to_vec(&value, &schema));
+    }
+
+    #[test]
+    fn test_serialize_datum_errors() {
+        #[derive(Serialize)]
+        struct Partial {
+            d: String,
+        }
+
+        let schema = schema();
+        let partial = Partial { d: "foo".to_owned() };
+        assert!(serialize_datum(&partial, &schema, &mut Vec::new()).is_err());
+        assert!(serialize_datum("foo", &Schema::Long, &mut Vec::new()).is_err());
+        assert!(serialize_datum(&i64::MAX, &Schema::Int, &mut Vec::new()).is_err());
+        assert!(serialize_datum(&None::<i64>, &Schema::Long, &mut Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,134 @@
+//! Logic for the algebra of Avro set values.
+use std::collections::HashSet;
+
+use crate::error::AvroError;
+use crate::types::{SetItem, Value};
+
+fn set_expected(value: &Value) -> AvroError {
+    AvroError::Resolution(format!("Set expected, got {:?}", value))
+}
+
+impl Value {
+    /// Whether this value is a `Set` containing the given item.
+    pub fn contains<T: Into<SetItem>>(&self, item: T) -> bool {
+        match self {
+            Value::Set(items, _) => items.contains(&item.into()),
+            _ => false,
+        }
+    }
+
+    /// Whether this value is a `Set` containing every one of the given items.
+    pub fn contains_all<T, I>(&self, items: I) -> bool
+    where
+        T: Into<SetItem>,
+        I: IntoIterator<Item = T>,
+    {
+        items.into_iter().all(|item| self.contains(item))
+    }
+
+    /// Union of two `Set` values. The `ValueSetting` of this value is kept.
+    pub fn union(self, other: &Value) -> Result<Value, AvroError> {
+        match other {
+            Value::Set(items, _) => self.union_with(items.iter().cloned()),
+            other => Err(set_expected(other)),
+        }
+    }
+
+    /// Add the given items to this `Set` value.
+    pub fn union_with<T, I>(self, items: I) -> Result<Value, AvroError>
+    where
+        T: Into<SetItem>,
+        I: IntoIterator<Item = T>,
+    {
+        match self {
+            Value::Set(mut current, setting) => {
+                current.extend(items.into_iter().map(Into::into));
+                Ok(Value::Set(current, setting))
+            },
+            other => Err(set_expected(&other)),
+        }
+    }
+
+    /// Intersection of two `Set` values. The `ValueSetting` of this value is kept.
+    pub fn intersection(self, other: &Value) -> Result<Value, AvroError> {
+        match (self, other) {
+            (Value::Set(mut current, setting), Value::Set(items, _)) => {
+                current.retain(|item| items.contains(item));
+                Ok(Value::Set(current, setting))
+            },
+            (Value::Set(_, _), other) => Err(set_expected(other)),
+            (other, _) => Err(set_expected(&other)),
+        }
+    }
+
+    /// Keep only the items of this `Set` value which are among the given ones.
+    pub fn intersection_with<T, I>(self, items: I) -> Result<Value, AvroError>
+    where
+        T: Into<SetItem>,
+        I: IntoIterator<Item = T>,
+    {
+        let items = items.into_iter().map(Into::into).collect::<HashSet<SetItem>>();
+        self.intersection(&Value::Set(items, None))
+    }
+
+    /// Items of this `Set` value which are not in the other one. The `ValueSetting` of this value
+    /// is kept.
+    pub fn difference(self, other: &Value) -> Result<Value, AvroError> {
+        match other {
+            Value::Set(items, _) => self.difference_with(items.iter().cloned()),
+            other => Err(set_expected(other)),
+        }
+    }
+
+    /// Remove the given items from this `Set` value.
+    pub fn difference_with<T, I>(self, items: I) -> Result<Value, AvroError>
+    where
+        T: Into<SetItem>,
+        I: IntoIterator<Item = T>,
+    {
+        match self {
+            Value::Set(mut current, setting) => {
+                for item in items {
+                    current.remove(&item.into());
+                }
+                Ok(Value::Set(current, setting))
+            },
+            other => Err(set_expected(&other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(items: &[i64]) -> Value {
+        Value::Set(items.iter().map(|&i| SetItem::Long(i)).collect(), None)
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        assert_eq!(set(&[1, 2]).union(&set(&[2, 3])).unwrap(), set(&[1, 2, 3]));
+        assert_eq!(set(&[1, 2]).intersection(&set(&[2, 3])).unwrap(), set(&[2]));
+        assert_eq!(set(&[1, 2]).difference(&set(&[2, 3])).unwrap(), set(&[1]));
+
+        assert!(set(&[1, 2]).contains(2i64));
+        assert!(!set(&[1, 2]).contains(2i32));
+        assert!(set(&[1, 2, 3]).contains_all(vec![1i64, 3i64]));
+        assert!(!Value::Null.contains("a"));
+    }
+
+    #[test]
+    fn test_set_algebra_bulk() {
+        assert_eq!(set(&[1]).union_with(vec![2i64, 3i64]).unwrap(), set(&[1, 2, 3]));
+        assert_eq!(set(&[1, 2, 3]).intersection_with(vec![3i64, 4i64]).unwrap(), set(&[3]));
+        assert_eq!(set(&[1, 2, 3]).difference_with(vec![1i64, 3i64]).unwrap(), set(&[2]));
+    }
+
+    #[test]
+    fn test_set_algebra_mismatch() {
+        assert!(set(&[1]).union(&Value::Null).is_err());
+        assert!(Value::Null.intersection(&set(&[1])).is_err());
+        assert!(Value::Array(vec![], None).difference_with(vec![1i64]).is_err());
+    }
+}
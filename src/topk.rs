@@ -0,0 +1,189 @@
+//! Logic for the space-saving summaries held by `topk` values.
+use std::collections::HashMap;
+
+/// Count of a key tracked by a [TopK](struct.TopK.html) summary.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TopKCount {
+    /// Estimated count of the key, which never underestimates the actual count.
+    pub count: i64,
+    /// Maximum overestimation of `count`, inherited from the key it evicted.
+    pub error: i64,
+}
+
+/// Summary keeping the (approximately) most frequent keys, up to a given capacity, following the
+/// space-saving algorithm.
+///
+/// Once the summary is full, a new key replaces the least frequent one and inherits its count,
+/// which is recorded as the error of the new key. Keys are ordered by decreasing count, ties being
+/// broken by key, so that summaries holding the same counts are encoded to the same bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TopK {
+    capacity: usize,
+    counts: HashMap<String, TopKCount>,
+}
+
+impl TopK {
+    /// Create an empty summary keeping up to `capacity` keys.
+    pub fn new(capacity: usize) -> TopK {
+        TopK {
+            capacity,
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Maximum number of keys kept by the summary.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of keys kept by the summary.
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Whether the summary keeps no key.
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Count of a key, if it is kept by the summary.
+    pub fn get(&self, key: &str) -> Option<TopKCount> {
+        self.counts.get(key).cloned()
+    }
+
+    /// Add `count` occurrences of a key.
+    pub fn insert<K: Into<String>>(&mut self, key: K, count: i64) {
+        let key = key.into();
+        if let Some(current) = self.counts.get_mut(&key) {
+            current.count = current.count.saturating_add(count);
+            return
+        }
+        if self.capacity == 0 {
+            return
+        }
+
+        let error = if self.counts.len() < self.capacity {
+            0
+        } else {
+            // evict the least frequent key, the greatest one among ties
+            let evicted = self
+                .entries()
+                .last()
+                .map(|(key, count)| (key.to_string(), count.count));
+            match evicted {
+                Some((evicted, min)) => {
+                    self.counts.remove(&evicted);
+                    min
+                },
+                None => 0,
+            }
+        };
+        self.counts.insert(
+            key,
+            TopKCount {
+                count: error.saturating_add(count),
+                error,
+            },
+        );
+    }
+
+    /// Merge another summary into this one: the counts and errors of the keys are summed, then
+    /// only the most frequent keys are kept, up to the capacity of this summary.
+    pub fn merge(&mut self, other: &TopK) {
+        for (key, count) in &other.counts {
+            let current = self.counts.entry(key.clone()).or_insert(TopKCount { count: 0, error: 0 });
+            current.count = current.count.saturating_add(count.count);
+            current.error = current.error.saturating_add(count.error);
+        }
+        let capacity = self.capacity;
+        self.truncate(capacity);
+    }
+
+    /// Keep only the `capacity` most frequent keys, and limit the summary to that capacity.
+    pub fn truncate(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        if self.counts.len() > capacity {
+            let kept = self
+                .entries()
+                .into_iter()
+                .take(capacity)
+                .map(|(key, count)| (key.to_owned(), count))
+                .collect();
+            self.counts = kept;
+        }
+    }
+
+    /// Keys of the summary along with their counts, most frequent first.
+    pub fn entries(&self) -> Vec<(&str, TopKCount)> {
+        let mut entries = self
+            .counts
+            .iter()
+            .map(|(key, count)| (key.as_str(), *count))
+            .collect::<Vec<_>>();
+        entries.sort_by(|(a_key, a), (b_key, b)| b.count.cmp(&a.count).then_with(|| a_key.cmp(b_key)));
+        entries
+    }
+
+    /// Remove every key, returning them.
+    pub(crate) fn drain_keys(&mut self) -> impl Iterator<Item = String> + '_ {
+        self.counts.drain().map(|(key, _)| key)
+    }
+
+    /// Put a key with its count and error, as read from a serialized summary.
+    pub(crate) fn put(&mut self, key: String, count: TopKCount) {
+        self.counts.insert(key, count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(topk: &TopK) -> Vec<(&str, i64)> {
+        topk.entries()
+            .into_iter()
+            .map(|(key, count)| (key, count.count))
+            .collect()
+    }
+
+    #[test]
+    fn test_topk_insert() {
+        let mut topk = TopK::new(2);
+        topk.insert("a", 3);
+        topk.insert("b", 1);
+        topk.insert("a", 1);
+        assert_eq!(keys(&topk), vec![("a", 4), ("b", 1)]);
+
+        // "c" evicts "b" and inherits its count
+        topk.insert("c", 1);
+        assert_eq!(keys(&topk), vec![("a", 4), ("c", 2)]);
+        assert_eq!(topk.get("c"), Some(TopKCount { count: 2, error: 1 }));
+        assert_eq!(topk.get("b"), None);
+    }
+
+    #[test]
+    fn test_topk_merge() {
+        let mut a = TopK::new(2);
+        a.insert("x", 5);
+        a.insert("y", 1);
+        let mut b = TopK::new(3);
+        b.insert("y", 5);
+        b.insert("z", 2);
+        b.insert("w", 1);
+
+        a.merge(&b);
+        assert_eq!(keys(&a), vec![("y", 6), ("x", 5)]);
+        assert_eq!(a.capacity(), 2);
+    }
+
+    #[test]
+    fn test_topk_ties() {
+        let mut topk = TopK::new(3);
+        for key in &["c", "a", "b"] {
+            topk.insert(*key, 1);
+        }
+        assert_eq!(keys(&topk), vec![("a", 1), ("b", 1), ("c", 1)]);
+        topk.truncate(1);
+        assert_eq!(keys(&topk), vec![("a", 1)]);
+    }
+}
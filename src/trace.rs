@@ -0,0 +1,30 @@
+//! Macros instrumenting the library with `tracing` spans and events when the `tracing` feature is
+//! enabled, and compiling to nothing otherwise.
+//!
+//! Block reads and writes, compression, resolution falling back to defaults and evictions are
+//! reported, all at the `debug` or `trace` level, under the `avro_rs` target.
+
+/// Emit a `tracing` event, as `trace_event!(debug, field = value, "message")` for
+/// `tracing::debug!(field = value, "message")`.
+macro_rules! trace_event {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        ::tracing::$level!($($arg)+);
+    };
+}
+
+/// Enter a `tracing` span until the end of the scope of the returned guard, as
+/// `let _span = trace_span!(debug_span, "name", field = value);`.
+macro_rules! trace_span {
+    ($span:ident, $($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        let span = ::tracing::$span!($($arg)+).entered();
+        #[cfg(not(feature = "tracing"))]
+        let span = $crate::trace::NoSpan;
+        span
+    }};
+}
+
+/// Guard of the spans entered while the `tracing` feature is disabled.
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct NoSpan;
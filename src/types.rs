@@ -1,14 +1,29 @@
 //! Logic handling the intermediate representation of Avro values.
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt;
 use std::hash::BuildHasher;
+use std::io;
+use std::sync::Arc;
 use std::u8;
 
-use failure::Error;
+use serde::ser::{Error as _, Serialize, SerializeMap, SerializeSeq, Serializer};
 use serde_json::Value as JsonValue;
 
 use crate::LruLimit;
-use crate::schema::{RecordField, Schema, SchemaKind, UnionSchema};
+use crate::date::DateParsing;
+use crate::error::{at, AvroError};
+use crate::bloom::BloomFilter;
+use crate::hll::HyperLogLog;
+use crate::lru::LruMap;
+use crate::topk::TopK;
+use crate::schema::{
+    DateUnit, Decay, FieldMetadata, Name, RecordField, Schema, SchemaKind, Truncation, UnionSchema,
+};
+use crate::json::default_to_value;
+use crate::visit::PathSegment;
 
 const ACCESS_TIME: &str = "access_time";
 const COUNT: &str = "count";
@@ -28,23 +43,205 @@ lazy_static! {
         ).unwrap();
 }
 
-/// Describes errors happened while performing schema resolution on Avro data.
-#[derive(Fail, Debug)]
-#[fail(display = "Decoding error: {}", _0)]
-pub struct SchemaResolutionError(String);
+lazy_static! {
+    static ref INDEXED_RECORD: ValueSetting = ValueSetting {
+        metadata: Arc::new(FieldMetadata {
+            index: true,
+            ..FieldMetadata::default()
+        }),
+    };
+}
 
-impl SchemaResolutionError {
-    pub fn new<S>(msg: S) -> SchemaResolutionError
-        where
-            S: Into<String>,
-    {
-        SchemaResolutionError(msg.into())
+/// Setting of the values resolved against a record schema flagged with `index`.
+pub(crate) fn record_setting(name: &Name) -> Option<&'static ValueSetting> {
+    if name.index {
+        Some(&INDEXED_RECORD)
+    } else {
+        None
+    }
+}
+
+/// Describes how schema resolution handles the fields of a writer record which are unknown to the
+/// reader schema.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum UnknownFieldPolicy {
+    /// Drop unknown fields.
+    #[default]
+    Ignore,
+    /// Fail the resolution.
+    Error,
+    /// Put unknown fields in the reader field with the given name, which must be a map whose
+    /// values unknown fields resolve to. Records without such a field drop unknown fields.
+    CollectInto(String),
+}
+
+/// Describes how `NaN` and infinite `float` and `double` values, which JSON can't represent, are
+/// converted to JSON.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NonFiniteFloats {
+    /// Convert them to `null`, losing them, as [`Value::json`](enum.Value.html#method.json) does.
+    #[default]
+    Null,
+    /// Convert them to the strings `"NaN"`, `"Infinity"` and `"-Infinity"`.
+    String,
+    /// Fail the conversion.
+    Error,
+}
+
+/// Options driving schema resolution with
+/// [`Value::resolve_with_options`](enum.Value.html#method.resolve_with_options).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResolveOptions {
+    /// How the fields of writer records unknown to the reader schema are handled.
+    pub unknown_fields: UnknownFieldPolicy,
+    /// Reference time used to expire `LruSet` entries, in milliseconds since epoch. The current
+    /// time is used if `None`.
+    pub now: Option<i64>,
+    /// How strings are parsed into `date` values.
+    pub dates: DateParsing,
+    /// Which conversions between numeric types are allowed.
+    pub numbers: NumericPromotion,
+}
+
+/// Describes which conversions between `int`, `long`, `float` and `double` values schema
+/// resolution allows. Values of the numeric type expected by the reader schema are always kept as
+/// they are.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NumericPromotion {
+    /// Convert between any numeric types, truncating longs to ints and rounding doubles to floats.
+    #[default]
+    Lossy,
+    /// Only allow the promotions of the Avro specification: ints to longs, floats or doubles, longs
+    /// to floats or doubles, and floats to doubles. Longs and doubles resolved to ints and floats
+    /// fail the resolution.
+    Widening,
+    /// Allow any conversion which represents the value exactly, and fail the resolution otherwise:
+    /// longs resolve to ints when in range, and doubles to floats when no precision is lost.
+    Exact,
+}
+
+impl NumericPromotion {
+    /// Check the conversion of `value` to a number of kind `kind`. Values which aren't numbers are
+    /// let through, to be rejected by the resolution itself.
+    fn check(self, value: &Value, kind: SchemaKind) -> Result<(), AvroError> {
+        let allowed = match (self, value, kind) {
+            (_, Value::Int(..), SchemaKind::Int)
+            | (_, Value::Long(..), SchemaKind::Long)
+            | (_, Value::Float(..), SchemaKind::Float)
+            | (_, Value::Double(..), SchemaKind::Double)
+            | (NumericPromotion::Lossy, _, _) => true,
+            (NumericPromotion::Widening, Value::Long(..), SchemaKind::Int)
+            | (NumericPromotion::Widening, Value::Double(..), SchemaKind::Float) => false,
+            (NumericPromotion::Exact, &Value::Long(n, _), SchemaKind::Int) => {
+                i32::try_from(n).is_ok()
+            },
+            (NumericPromotion::Exact, &Value::Int(n, _), SchemaKind::Float) => {
+                f64::from(n as f32) == f64::from(n)
+            },
+            (NumericPromotion::Exact, &Value::Long(n, _), SchemaKind::Float) => {
+                n as f32 as i128 == i128::from(n)
+            },
+            (NumericPromotion::Exact, &Value::Long(n, _), SchemaKind::Double) => {
+                n as f64 as i128 == i128::from(n)
+            },
+            (NumericPromotion::Exact, &Value::Double(x, _), SchemaKind::Float) => {
+                x.is_nan() || f64::from(x as f32) == x
+            },
+            _ => true,
+        };
+        if allowed {
+            Ok(())
+        } else {
+            Err(AvroError::Resolution(format!(
+                "{:?} can't be converted to {:?} without losing precision",
+                value, kind
+            )))
+        }
+    }
+}
+
+/// State shared by the whole resolution of a value.
+pub(crate) struct ResolveContext<'a> {
+    pub(crate) unknown_fields: &'a UnknownFieldPolicy,
+    /// Reference time used to expire `LruSet` entries, in milliseconds since epoch.
+    now: i64,
+    dates: &'a DateParsing,
+    numbers: NumericPromotion,
+}
+
+impl<'a> ResolveContext<'a> {
+    pub(crate) fn new(options: &'a ResolveOptions) -> ResolveContext<'a> {
+        ResolveContext {
+            unknown_fields: &options.unknown_fields,
+            now: options
+                .now
+                .unwrap_or_else(|| chrono::Utc::now().timestamp_millis()),
+            dates: &options.dates,
+            numbers: options.numbers,
+        }
+    }
+}
+
+/// Default value of a record field, before its resolution against the schema of the field.
+pub(crate) fn field_default(
+    field: &RecordField,
+    setting: Option<&ValueSetting>,
+) -> Result<Option<Value>, AvroError> {
+    let value = match field.default {
+        Some(ref value) => value,
+        None => return Ok(None),
+    };
+    Ok(Some(match field.schema {
+        Schema::Enum { ref symbols, .. } => {
+            value.clone().avro().resolve_enum(symbols, None, setting)?
+        },
+        Schema::Optional(_) => default_to_value(value, &field.schema)?,
+        _ => value.clone().avro(),
+    }))
+}
+
+/// Describes errors happened while putting a value in a [Record](struct.Record.html) with
+/// [`try_put`](struct.Record.html#method.try_put).
+#[derive(Debug, PartialEq)]
+pub enum PutError {
+    /// The field is not part of the record schema.
+    UnknownField(String),
+    /// The value does not match the schema of the field.
+    InvalidValue { field: String, value: Value },
+}
+
+impl fmt::Display for PutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PutError::UnknownField(field) => write!(f, "Unknown field: {}", field),
+            PutError::InvalidValue { field, value } => {
+                write!(f, "Invalid value for field {}: {:?}", field, value)
+            },
+        }
     }
 }
 
+impl std::error::Error for PutError {}
+
+/// Annotations of the record field a value was resolved against, declared in the schema.
 #[derive(Clone, Debug, PartialEq)]
 pub struct ValueSetting {
-    pub index: bool
+    /// Shared with the [RecordField](../schema/struct.RecordField.html) declaring it.
+    pub metadata: Arc<FieldMetadata>,
+}
+
+impl ValueSetting {
+    /// Setting of the values resolved against a record field, `None` if the field has no
+    /// annotation.
+    pub(crate) fn of_field(field: &RecordField) -> Option<ValueSetting> {
+        if field.metadata.is_empty() {
+            None
+        } else {
+            Some(ValueSetting {
+                metadata: field.metadata.clone(),
+            })
+        }
+    }
 }
 
 /// Represents any valid Avro value
@@ -99,25 +296,38 @@ pub enum Value {
 
     /// A `record` Avro value.
     ///
-    /// A Record is represented by a vector of (`<record name>`, `value`).
+    /// A Record is represented by a vector of (`<field name>`, `value`).
     /// This allows schema-less encoding.
     ///
     /// See [Record](types.Record) for a more user-friendly support.
-    Record(Vec<(String, Value)>, Option<ValueSetting>),
+    Record(Vec<(Arc<str>, Value)>, Option<ValueSetting>),
 
     // date is serialized as millis since epoch
     Date(i64, Option<ValueSetting>),
 
     // hashset of values
-    Set(HashSet<String>, Option<ValueSetting>),
+    Set(HashSet<SetItem>, Option<ValueSetting>),
+
+    // map of value to access time and count, kept in order of access and boxed to keep values
+    // small
+    LruSet(Box<LruMap>, LruLimit, Option<ValueSetting>),
 
-    // vector of value, access time, counts
-    LruSet(HashMap<String, LruValue>, LruLimit, Option<ValueSetting>),
+    // running count, merged by sum
+    Counter(i64, Option<ValueSetting>),
+
+    // cardinality sketch
+    Hll(HyperLogLog, Option<ValueSetting>),
+
+    // membership filter
+    Bloom(BloomFilter, Option<ValueSetting>),
+
+    // most frequent keys with their counts, boxed to keep values small
+    TopK(Box<TopK>, Option<ValueSetting>),
 
     Optional(Option<Box<Value>>, Option<ValueSetting>),
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, serde_derive::Serialize, serde_derive::Deserialize)]
 pub struct LruValue {
     pub access_time: i64,
     pub count: i64,
@@ -131,6 +341,12 @@ impl LruValue {
         }
     }
 
+    /// The count decayed from the access time of the entry to `now`, in milliseconds since
+    /// epoch, to rank entries by their recent activity.
+    pub fn count_at(&self, decay: Decay, now: i64) -> i64 {
+        decay.apply(self.count, now.saturating_sub(self.access_time))
+    }
+
     fn json(&self) -> JsonValue {
         let mut map: serde_json::Map<String, JsonValue> = serde_json::Map::new();
         map.insert(ACCESS_TIME.to_owned(), json!(self.access_time));
@@ -140,11 +356,148 @@ impl LruValue {
     }
 }
 
+/// Entries of a `LruSet` retained by the given limit, most recently accessed first (ties are
+/// broken by key so that the result is deterministic).
+///
+/// A `LruLimit::Count` limit (or the `max_entries` of a `LruLimit::Entries` one) keeps that many
+/// entries, and a `LruLimit::Bytes` limit keeps the most recent entries whose approximate sizes
+/// add up within the budget. Time based limits are time-to-live: if a reference time `now` is
+/// given, entries last accessed before `now - ttl` are dropped.
+pub(crate) fn lru_retained<'a>(
+    items: &'a LruMap,
+    limit: &LruLimit,
+    now: Option<i64>,
+) -> Vec<(&'a String, &'a LruValue)> {
+    let expiry = match (limit.ttl_millis(), now) {
+        (Some(ttl), Some(now)) => Some(now.saturating_sub(ttl)),
+        _ => None,
+    };
+    // entries are already ordered, expired ones come last
+    let entries = items.iter_recent().take_while(|(_, value)| match expiry {
+        Some(expiry) => value.access_time >= expiry,
+        None => true,
+    });
+    match *limit {
+        LruLimit::Count(_) | LruLimit::Entries { .. } => match limit.max_entries() {
+            Some(max_entries) => entries.take(max_entries).collect(),
+            None => entries.collect(),
+        },
+        LruLimit::Bytes(budget) => {
+            let mut used = 0usize;
+            entries
+                .take_while(|(key, _)| {
+                    used += key.len() + LruLimit::ENTRY_OVERHEAD;
+                    used <= budget as usize
+                })
+                .collect()
+        },
+        _ => entries.collect(),
+    }
+}
+
+/// Evict the entries of a `LruSet` beyond the given limit, as `lru_retained` does.
+pub(crate) fn evict_lru(items: &mut LruMap, limit: &LruLimit, now: Option<i64>) {
+    items.evict(limit, now);
+}
+
+/// Evict the entries of a `LruSet` relatively to `now`, as `evict_lru` does, then drop the entries
+/// whose count has decayed below one by then.
+pub(crate) fn compact_lru(items: &mut LruMap, limit: &LruLimit, decay: Option<Decay>, now: i64) {
+    evict_lru(items, limit, Some(now));
+    if let Some(decay) = decay {
+        items.retain(|_, value| value.count_at(decay, now) > 0);
+    }
+}
+
+/// Element of a [`Value::Set`](enum.Value.html#variant.Set), typed after the items schema of
+/// the `set`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SetItem {
+    Int(i32),
+    Long(i64),
+    Bytes(Vec<u8>),
+    String(String),
+    /// An enum symbol, along with its position in the symbols list of the items schema.
+    Enum(i32, String),
+}
+
+impl SetItem {
+    /// Whether the items of a `set` may be of the given schema.
+    pub(crate) fn supports(schema: &Schema) -> bool {
+        matches!(
+            schema,
+            Schema::Int | Schema::Long | Schema::Bytes | Schema::String | Schema::Enum { .. }
+        )
+    }
+
+    /// Whether the item is valid against the given items schema.
+    pub(crate) fn validate(&self, schema: &Schema) -> bool {
+        match (self, schema) {
+            (SetItem::Int(_), Schema::Int)
+            | (SetItem::Long(_), Schema::Long)
+            | (SetItem::Bytes(_), Schema::Bytes)
+            | (SetItem::String(_), Schema::String) => true,
+            (SetItem::Enum(i, symbol), Schema::Enum { symbols, .. }) => {
+                symbols.get(*i as usize) == Some(symbol)
+            },
+            _ => false,
+        }
+    }
+}
+
+impl From<i32> for SetItem {
+    fn from(n: i32) -> SetItem {
+        SetItem::Int(n)
+    }
+}
+
+impl From<i64> for SetItem {
+    fn from(n: i64) -> SetItem {
+        SetItem::Long(n)
+    }
+}
+
+impl From<Vec<u8>> for SetItem {
+    fn from(bytes: Vec<u8>) -> SetItem {
+        SetItem::Bytes(bytes)
+    }
+}
+
+impl From<String> for SetItem {
+    fn from(s: String) -> SetItem {
+        SetItem::String(s)
+    }
+}
+
+impl<'a> From<&'a str> for SetItem {
+    fn from(s: &'a str) -> SetItem {
+        SetItem::String(s.to_owned())
+    }
+}
+
+impl ToAvro for SetItem {
+    fn avro(self) -> Value {
+        match self {
+            SetItem::Int(n) => Value::Int(n, None),
+            SetItem::Long(n) => Value::Long(n, None),
+            SetItem::Bytes(bytes) => Value::Bytes(bytes, None),
+            SetItem::String(s) => Value::String(s, None),
+            SetItem::Enum(i, symbol) => Value::Enum(i, symbol, None),
+        }
+    }
+}
+
+impl<S: BuildHasher> ToAvro for HashSet<SetItem, S> {
+    fn avro(self) -> Value {
+        Value::Set(self.into_iter().collect(), None)
+    }
+}
+
 impl ToAvro for LruValue {
     fn avro(self) -> Value {
-        let mut fields: Vec<(String, Value)> = Vec::new();
-        fields.push((ACCESS_TIME.to_owned(), Value::Long(self.access_time as i64, None)));
-        fields.push((COUNT.to_owned(), Value::Long(self.count as i64, None)));
+        let mut fields: Vec<(Arc<str>, Value)> = Vec::new();
+        fields.push((ACCESS_TIME.into(), Value::Long(self.access_time as i64, None)));
+        fields.push((COUNT.into(), Value::Long(self.count as i64, None)));
 
         Value::Record(fields, None)
     }
@@ -186,18 +539,86 @@ impl ToAvro for usize {
     }
 }
 
+impl ToAvro for i8 {
+    fn avro(self) -> Value {
+        i32::from(self).avro()
+    }
+}
+
+impl ToAvro for i16 {
+    fn avro(self) -> Value {
+        i32::from(self).avro()
+    }
+}
+
+impl ToAvro for u32 {
+    fn avro(self) -> Value {
+        i64::from(self).avro()
+    }
+}
+
+/// `u64` values are stored as `long`, failing for values above `i64::MAX` which cannot be
+/// represented instead of wrapping around.
+impl TryFrom<u64> for Value {
+    type Error = AvroError;
+
+    fn try_from(n: u64) -> Result<Self, Self::Error> {
+        i64::try_from(n).map(ToAvro::avro).map_err(|_| {
+            AvroError::Resolution(format!("{} does not fit in a long", n))
+        })
+    }
+}
+
 impl<'a> ToAvro for &'a str {
     fn avro(self) -> Value {
         Value::String(self.to_owned(), None)
     }
 }
 
+impl ToAvro for &String {
+    fn avro(self) -> Value {
+        Value::String(self.clone(), None)
+    }
+}
+
 impl<'a> ToAvro for &'a [u8] {
     fn avro(self) -> Value {
         Value::Bytes(self.to_owned(), None)
     }
 }
 
+impl<const N: usize> ToAvro for [u8; N] {
+    fn avro(self) -> Value {
+        Value::Fixed(N, self.to_vec(), None)
+    }
+}
+
+impl<T> ToAvro for Vec<T>
+    where
+        T: ToAvro,
+{
+    fn avro(self) -> Value {
+        Value::Array(self.into_iter().map(T::avro).collect::<_>(), None)
+    }
+}
+
+impl<T> ToAvro for BTreeMap<String, T>
+    where
+        T: ToAvro,
+{
+    fn avro(self) -> Value {
+        Value::Map(
+            self.into_iter()
+                .map(|(key, value)| (key, value.avro()))
+                .collect::<_>(),
+            None,
+        )
+    }
+}
+
+/// `Option`s are converted into nullable `Union` values. When put in a
+/// [Record](struct.Record.html) field with an `optional` schema, or resolved against one, they
+/// are turned into `Value::Optional` instead.
 impl<T> ToAvro for Option<T>
     where
         T: ToAvro,
@@ -245,6 +666,221 @@ impl ToAvro for Value {
     }
 }
 
+macro_rules! try_from_value (
+    ($t:ty, $expected:expr, $($p:pat => $v:expr),+) => (
+        impl TryFrom<Value> for $t {
+            type Error = AvroError;
+
+            fn try_from(value: Value) -> Result<Self, Self::Error> {
+                match value {
+                    $($p => Ok($v),)+
+                    other => Err(AvroError::Resolution(format!(
+                        "{} expected, got {:?}",
+                        $expected, other
+                    ))),
+                }
+            }
+        }
+
+        impl TryFrom<Value> for Option<$t> {
+            type Error = AvroError;
+
+            fn try_from(value: Value) -> Result<Self, Self::Error> {
+                try_from_optional(value)
+            }
+        }
+    );
+);
+
+try_from_value!(bool, "Boolean", Value::Boolean(b, _) => b);
+try_from_value!(i32, "Int", Value::Int(n, _) => n);
+try_from_value!(i64, "Long",
+    Value::Int(n, _) => i64::from(n),
+    Value::Long(n, _) => n,
+    Value::Date(n, _) => n,
+    Value::Counter(n, _) => n
+);
+try_from_value!(f64, "Double",
+    Value::Int(n, _) => f64::from(n),
+    Value::Long(n, _) => n as f64,
+    Value::Float(x, _) => f64::from(x),
+    Value::Double(x, _) => x
+);
+try_from_value!(String, "String",
+    Value::String(s, _) => s,
+    Value::Enum(_, s, _) => s
+);
+try_from_value!(Vec<u8>, "Bytes",
+    Value::Bytes(bytes, _) => bytes,
+    Value::Fixed(_, bytes, _) => bytes,
+    Value::String(s, _) => s.into_bytes()
+);
+try_from_value!(SetItem, "Set item",
+    Value::Int(n, _) => SetItem::Int(n),
+    Value::Long(n, _) => SetItem::Long(n),
+    Value::Bytes(bytes, _) => SetItem::Bytes(bytes),
+    Value::String(s, _) => SetItem::String(s),
+    Value::Enum(i, symbol, _) => SetItem::Enum(i, symbol)
+);
+try_from_value!(HashSet<SetItem>, "Set",
+    Value::Set(items, _) => items
+);
+
+impl TryFrom<Value> for HashSet<String> {
+    type Error = AvroError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        HashSet::<SetItem>::try_from(value)?
+            .into_iter()
+            .map(|item| match item {
+                SetItem::String(s) => Ok(s),
+                other => Err(AvroError::Resolution(format!(
+                    "String expected, got {:?}", other
+                ))),
+            })
+            .collect()
+    }
+}
+
+impl TryFrom<Value> for Option<HashSet<String>> {
+    type Error = AvroError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        try_from_optional(value)
+    }
+}
+
+impl<T> TryFrom<Value> for Vec<T>
+    where
+        T: TryFrom<Value, Error = AvroError>,
+{
+    type Error = AvroError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Array(items, _) => items.into_iter().map(T::try_from).collect(),
+            other => Err(AvroError::Resolution(format!(
+                "Array expected, got {:?}",
+                other
+            ))),
+        }
+    }
+}
+
+impl<T> TryFrom<Value> for Option<Vec<T>>
+    where
+        T: TryFrom<Value, Error = AvroError>,
+{
+    type Error = AvroError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        try_from_optional(value)
+    }
+}
+
+impl<T, S> TryFrom<Value> for HashMap<String, T, S>
+    where
+        T: TryFrom<Value, Error = AvroError>,
+        S: BuildHasher + Default,
+{
+    type Error = AvroError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Map(items, _) => items
+                .into_iter()
+                .map(|(key, value)| T::try_from(value).map(|value| (key, value)))
+                .collect(),
+            other => Err(AvroError::Resolution(format!(
+                "Map expected, got {:?}",
+                other
+            ))),
+        }
+    }
+}
+
+impl<T, S> TryFrom<Value> for Option<HashMap<String, T, S>>
+    where
+        T: TryFrom<Value, Error = AvroError>,
+        S: BuildHasher + Default,
+{
+    type Error = AvroError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        try_from_optional(value)
+    }
+}
+
+/// Unwrap the `null`-able wrappers (`Null`, `Union` and `Optional`) before converting the inner
+/// value, so that the same conversion applies regardless of how the writer modelled absence.
+fn try_from_optional<T>(value: Value) -> Result<Option<T>, AvroError>
+    where
+        T: TryFrom<Value, Error = AvroError>,
+{
+    match value {
+        Value::Null | Value::Optional(None, _) => Ok(None),
+        Value::Union(inner, _) | Value::Optional(Some(inner), _) => match *inner {
+            Value::Null => Ok(None),
+            inner => T::try_from(inner).map(Some),
+        },
+        other => T::try_from(other).map(Some),
+    }
+}
+
+/// Build the zero value of a schema, as used by
+/// [`Value::unwrap_or_default`](enum.Value.html#method.unwrap_or_default).
+pub(crate) fn zero_value(schema: &Schema) -> Value {
+    match schema {
+        Schema::Null => Value::Null,
+        Schema::Boolean => Value::Boolean(false, None),
+        Schema::Int => Value::Int(0, None),
+        Schema::Long => Value::Long(0, None),
+        Schema::Float => Value::Float(0.0, None),
+        Schema::Double => Value::Double(0.0, None),
+        Schema::Bytes => Value::Bytes(Vec::new(), None),
+        Schema::String => Value::String(String::new(), None),
+        Schema::Fixed { size, .. } => Value::Fixed(*size, vec![0; *size], None),
+        Schema::Enum { symbols, .. } => match symbols.first() {
+            Some(symbol) => Value::Enum(0, symbol.clone(), None),
+            None => Value::Null,
+        },
+        Schema::Array(_) | Schema::BoundedList { .. } => Value::Array(Vec::new(), None),
+        Schema::Map(_) => Value::Map(HashMap::new(), None),
+        Schema::Union(union) => match union.variants().first() {
+            Some(first) => Value::Union(Box::new(zero_value(first)), None),
+            None => Value::Null,
+        },
+        Schema::Record { fields, .. } => Value::Record(
+            fields
+                .iter()
+                .map(|field| {
+                    let value = field
+                        .default
+                        .as_ref()
+                        .and_then(|default| default_to_value(default, &field.schema).ok())
+                        .unwrap_or_else(|| zero_value(&field.schema));
+                    (field.name.clone(), value)
+                })
+                .collect(),
+            None,
+        ),
+        Schema::Date(_) => Value::Date(0, None),
+        Schema::Set(_) => Value::Set(HashSet::new(), None),
+        Schema::LruSet(limit, _) => Value::LruSet(Box::default(), limit.clone(), None),
+        Schema::Counter => Value::Counter(0, None),
+        Schema::Hll(precision) => match HyperLogLog::new(*precision) {
+            Some(sketch) => Value::Hll(sketch, None),
+            None => Value::Null,
+        },
+        Schema::Bloom { bits, hashes } => match BloomFilter::new(*bits, *hashes) {
+            Some(filter) => Value::Bloom(filter, None),
+            None => Value::Null,
+        },
+        Schema::TopK(size) => Value::TopK(Box::new(TopK::new(*size)), None),
+        Schema::Optional(_) => Value::Optional(None, None),
+    }
+}
+
 /*
 impl<S: Serialize> ToAvro for S {
     fn avro(self) -> Value {
@@ -261,8 +897,10 @@ pub struct Record<'a> {
     /// List of fields contained in the record.
     /// Ordered according to the fields in the schema given to create this
     /// `Record` object. Any unset field defaults to `Value::Null`.
-    pub fields: Vec<(String, Value)>,
+    pub fields: Vec<(Arc<str>, Value)>,
     schema_lookup: &'a HashMap<String, usize>,
+    schema_fields: &'a [RecordField],
+    validate: bool,
 }
 
 impl<'a> Record<'a> {
@@ -284,25 +922,85 @@ impl<'a> Record<'a> {
                 Some(Record {
                     fields,
                     schema_lookup,
+                    schema_fields,
+                    validate: false,
                 })
             }
             _ => None,
         }
     }
 
+    /// Create a `Record` given a `Schema`, validating every value given to
+    /// [`try_put`](#method.try_put) against the schema of its field.
+    ///
+    /// If the `Schema` is not a `Schema::Record` variant, `None` will be returned.
+    pub fn with_validation(schema: &Schema) -> Option<Record<'_>> {
+        Record::new(schema).map(|record| Record {
+            validate: true,
+            ..record
+        })
+    }
+
     /// Put a compatible value (implementing the `ToAvro` trait) in the
     /// `Record` for a given `field` name.
     ///
     /// **NOTE** Only ensure that the field name is present in the `Schema` given when creating
-    /// this `Record`. Does not perform any schema validation.
+    /// this `Record`. Does not perform any schema validation, and silently ignores unknown fields:
+    /// see [`try_put`](#method.try_put) for a checked alternative.
     pub fn put<V>(&mut self, field: &str, value: V)
         where
             V: ToAvro,
     {
         if let Some(&position) = self.schema_lookup.get(field) {
-            self.fields[position].1 = value.avro()
+            self.fields[position].1 = self.adapt(position, value.avro())
+        }
+    }
+
+    /// Put every (`field name`, `value`) tuple in the `Record`, as [`put`](#method.put) would.
+    pub fn put_all<'b, I, V>(&mut self, fields: I)
+        where
+            I: IntoIterator<Item = (&'b str, V)>,
+            V: ToAvro,
+    {
+        for (field, value) in fields {
+            self.put(field, value);
+        }
+    }
+
+    /// Turn the `Union` built by `ToAvro for Option<T>` into a `Value::Optional` if the field
+    /// expects one.
+    fn adapt(&self, position: usize, value: Value) -> Value {
+        match (&self.schema_fields[position].schema, value) {
+            (Schema::Optional(_), value @ Value::Union(_, _)) | (Schema::Optional(_), value @ Value::Null) => {
+                value.into_optional()
+            },
+            (_, value) => value,
         }
     }
+
+    /// Put a compatible value (implementing the `ToAvro` trait) in the
+    /// `Record` for a given `field` name, failing if the field is not part of the `Schema`.
+    ///
+    /// If the `Record` was created with [`with_validation`](#method.with_validation), the value
+    /// is also validated against the schema of the field, and left out of the record if invalid.
+    pub fn try_put<V>(&mut self, field: &str, value: V) -> Result<(), PutError>
+        where
+            V: ToAvro,
+    {
+        let position = match self.schema_lookup.get(field) {
+            Some(&position) => position,
+            None => return Err(PutError::UnknownField(field.to_owned())),
+        };
+        let value = self.adapt(position, value.avro());
+        if self.validate && !value.validate(&self.schema_fields[position].schema) {
+            return Err(PutError::InvalidValue {
+                field: field.to_owned(),
+                value,
+            })
+        }
+        self.fields[position].1 = value;
+        Ok(())
+    }
 }
 
 impl<'a> ToAvro for Record<'a> {
@@ -339,33 +1037,139 @@ impl ToAvro for JsonValue {
 }
 
 impl Value {
-    /// Validate the value against the given [Schema](../schema/enum.Schema.html).
+    /// Build a `Value::Record` out of (`field name`, `value`) tuples, keeping their order.
     ///
-    /// See the [Avro specification](https://avro.apache.org/docs/current/spec.html)
-    /// for the full set of rules of schema validation.
-    pub fn validate(&self, schema: &Schema) -> bool {
-        match (self, schema) {
-            (&Value::Null, &Schema::Null) => true,
-            (&Value::Boolean(_, _), &Schema::Boolean) => true,
-            (&Value::Int(_, _), &Schema::Int) => true,
-            (&Value::Long(_, _), &Schema::Long) => true,
-            (&Value::Float(_, _), &Schema::Float) => true,
-            (&Value::Double(_, _), &Schema::Double) => true,
-            (&Value::Bytes(_, _), &Schema::Bytes) => true,
-            (&Value::String(_, _), &Schema::String) => true,
-            (&Value::Fixed(n, _, _), &Schema::Fixed { size, .. }) => n == size,
-            (&Value::String(ref s, _), &Schema::Enum { ref symbols, .. }) => symbols.contains(s),
-            (&Value::Enum(i, ref s, _), &Schema::Enum { ref symbols, .. }) => symbols
-                .get(i as usize)
-                .map(|ref symbol| symbol == &s)
-                .unwrap_or(false),
-            // (&Value::Union(None), &Schema::Union(_)) => true,
-            (&Value::Union(ref value, _), &Schema::Union(ref inner)) => {
-                inner.find_schema(value).is_some()
-            }
+    /// **NOTE** Unlike [Record](struct.Record.html), no schema is involved: fields must be given
+    /// in the order of the schema the value will be written with.
+    pub fn record<I, K, V>(fields: I) -> Value
+        where
+            I: IntoIterator<Item = (K, V)>,
+            K: Into<Arc<str>>,
+            V: ToAvro,
+    {
+        Value::Record(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name.into(), value.avro()))
+                .collect::<_>(),
+            None,
+        )
+    }
+
+    /// View the value as an `Option`, looking through `Optional` and `Union` wrappers.
+    ///
+    /// Returns `None` for `Value::Null` and absent (or null) wrapped values, and the inner value
+    /// otherwise. Any other value is returned as is.
+    pub fn as_option(&self) -> Option<&Value> {
+        match self {
+            Value::Null | Value::Optional(None, _) => None,
+            Value::Optional(Some(inner), _) | Value::Union(inner, _) => inner.as_option(),
+            other => Some(other),
+        }
+    }
+
+    /// Convert the value into a `Value::Optional`.
+    ///
+    /// `Value::Null` and null `Union` values become absent, other `Union` values are unwrapped
+    /// and any other value becomes present. This is how the `Union` produced by
+    /// `ToAvro for Option<T>` is adapted to an `optional` schema.
+    pub fn into_optional(self) -> Value {
+        match self {
+            Value::Optional(_, _) => self,
+            Value::Null => Value::Optional(None, None),
+            Value::Union(inner, setting) => match *inner {
+                Value::Null => Value::Optional(None, setting),
+                inner => Value::Optional(Some(Box::new(inner)), setting),
+            },
+            other => Value::Optional(Some(Box::new(other)), None),
+        }
+    }
+
+    /// Return the inner value of an `Optional` (or nullable `Union`) value, or the zero value of
+    /// the given [Schema](../schema/enum.Schema.html) if absent.
+    ///
+    /// The schema can be either the `optional` schema itself or the schema of its value. Zero
+    /// values are `false`, `0`, empty strings, bytes and collections, the first enum symbol and
+    /// records made of the default (or zero) value of each field.
+    pub fn unwrap_or_default(self, schema: &Schema) -> Value {
+        let schema = match schema {
+            Schema::Optional(inner) => inner,
+            other => other,
+        };
+        match self.into_optional() {
+            Value::Optional(Some(inner), _) => *inner,
+            _ => zero_value(schema),
+        }
+    }
+
+    /// Look up the value of a record field by name.
+    ///
+    /// `Union` and `Optional` wrappers are looked through, so that a nullable record can be
+    /// accessed the same way as a plain one. Returns `None` if the value is not a record or if
+    /// the field does not exist.
+    pub fn field(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Record(fields, _) => fields
+                .iter()
+                .find(|(field, _)| **field == *name)
+                .map(|(_, value)| value),
+            Value::Union(inner, _) | Value::Optional(Some(inner), _) => inner.field(name),
+            _ => None,
+        }
+    }
+
+    /// Return the fields of a record keyed by their name, looking through `Union` and `Optional`
+    /// wrappers like [`field`](#method.field) does.
+    pub fn fields_map(&self) -> Option<HashMap<&str, &Value>> {
+        match self {
+            Value::Record(fields, _) => Some(
+                fields
+                    .iter()
+                    .map(|(field, value)| (&**field, value))
+                    .collect::<_>(),
+            ),
+            Value::Union(inner, _) | Value::Optional(Some(inner), _) => inner.fields_map(),
+            _ => None,
+        }
+    }
+
+    /// Validate the value against the given [Schema](../schema/enum.Schema.html).
+    ///
+    /// See the [Avro specification](https://avro.apache.org/docs/current/spec.html)
+    /// for the full set of rules of schema validation.
+    pub fn validate(&self, schema: &Schema) -> bool {
+        match (self, schema) {
+            (&Value::Null, &Schema::Null) => true,
+            (&Value::Boolean(_, _), &Schema::Boolean) => true,
+            (&Value::Int(_, _), &Schema::Int) => true,
+            (&Value::Long(_, _), &Schema::Long) => true,
+            (&Value::Float(_, _), &Schema::Float) => true,
+            (&Value::Double(_, _), &Schema::Double) => true,
+            (&Value::Bytes(_, _), &Schema::Bytes) => true,
+            (&Value::String(_, _), &Schema::String) => true,
+            (&Value::Fixed(n, _, _), &Schema::Fixed { size, .. }) => n == size,
+            (&Value::String(ref s, _), &Schema::Enum { ref symbols, .. }) => symbols.contains(s),
+            (&Value::Enum(i, ref s, _), &Schema::Enum { ref symbols, .. }) => symbols
+                .get(i as usize)
+                .map(|ref symbol| symbol == &s)
+                .unwrap_or(false),
+            // (&Value::Union(None), &Schema::Union(_)) => true,
+            (&Value::Union(ref value, _), &Schema::Union(ref inner)) => {
+                inner.find_schema(value).is_some()
+            }
             (&Value::Array(ref items, _), &Schema::Array(ref inner)) => {
                 items.iter().all(|item| item.validate(inner))
             }
+            (
+                &Value::Array(ref items, _),
+                &Schema::BoundedList {
+                    items: ref inner,
+                    max_len,
+                    ..
+                },
+            ) => {
+                items.len() <= max_len && items.iter().all(|item| item.validate(inner))
+            }
             (&Value::Map(ref items, _), &Schema::Map(ref inner)) => {
                 items.iter().all(|(_, value)| value.validate(inner))
             }
@@ -378,15 +1182,17 @@ impl Value {
                 )
             }
 
-            (&Value::Date(ref value, _), &Schema::Date) => {
-                // if value can be represented as u4, then it's a valid Date
-                true
+            (&Value::Date(_, _), &Schema::Date(_)) => true,
+            (&Value::Counter(_, _), &Schema::Counter) => true,
+            (&Value::Hll(ref sketch, _), &Schema::Hll(precision)) => sketch.precision() == precision,
+            (&Value::Bloom(ref filter, _), &Schema::Bloom { bits, hashes }) => {
+                filter.bits() == bits && filter.hashes() == hashes
             }
-            (&Value::Set(ref items, _), &Schema::Set) => {
-                // if value could be represented as typed HashSet of String, then no further validations are required.
-                true
+            (&Value::TopK(ref topk, _), &Schema::TopK(size)) => topk.len() <= size,
+            (&Value::Set(ref items, _), &Schema::Set(ref inner)) => {
+                items.iter().all(|item| item.validate(inner))
             }
-            (&Value::LruSet(ref items, _, _), &Schema::LruSet(ref lru_limit)) => {
+            (&Value::LruSet(ref items, _, _), &Schema::LruSet(ref lru_limit, _)) => {
                 // if value could be represented as typed HashMap of String and LruValue, then no further validations are required.
                 true
             }
@@ -401,44 +1207,226 @@ impl Value {
         }
     }
 
+    /// Validate the value against the given [Schema](../schema/enum.Schema.html), reporting every
+    /// mismatch instead of a single `bool`.
+    ///
+    /// Each error describes the path of the mismatching value (e.g. `$.items[2].name`), the
+    /// expected schema and the actual variant. Unlike [`validate`](#method.validate), the branch
+    /// selected for a `Union` value is validated as well.
+    pub fn validate_verbose(&self, schema: &Schema) -> Result<(), Vec<AvroError>> {
+        let mut errors = Vec::new();
+        self.collect_validation_errors(schema, "$", &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn collect_validation_errors(&self, schema: &Schema, path: &str, errors: &mut Vec<AvroError>) {
+        let mismatch = |expected: String| {
+            AvroError::Validation(format!(
+                "{}: expected {}, got {:?}",
+                path,
+                expected,
+                SchemaKind::from(self)
+            ))
+        };
+
+        match (self, schema) {
+            (Value::Union(value, _), Schema::Union(inner)) => match inner.find_schema(value) {
+                Some((_, branch)) => value.collect_validation_errors(branch, path, errors),
+                None => {
+                    let variants = inner
+                        .variants()
+                        .iter()
+                        .map(|variant| variant.type_name())
+                        .collect::<Vec<_>>();
+                    errors.push(AvroError::Validation(format!(
+                        "{}: expected one of [{}], got {:?}",
+                        path,
+                        variants.join(", "),
+                        SchemaKind::from(&**value)
+                    )))
+                },
+            },
+            (Value::Array(items, _), Schema::BoundedList { max_len, .. })
+                if items.len() > *max_len =>
+            {
+                errors.push(AvroError::Validation(format!(
+                    "{}: expected at most {} items, got {}",
+                    path,
+                    max_len,
+                    items.len()
+                )))
+            },
+            (Value::Array(items, _), Schema::Array(inner))
+            | (Value::Array(items, _), Schema::BoundedList { items: inner, .. }) => {
+                for (i, item) in items.iter().enumerate() {
+                    item.collect_validation_errors(inner, &format!("{}[{}]", path, i), errors);
+                }
+            },
+            (Value::Map(items, _), Schema::Map(inner)) => {
+                let mut keys = items.keys().collect::<Vec<_>>();
+                keys.sort();
+                for key in keys {
+                    items[key].collect_validation_errors(inner, &format!("{}[{:?}]", path, key), errors);
+                }
+            },
+            (Value::Record(record_fields, _), Schema::Record { fields, .. }) => {
+                for (i, field) in fields.iter().enumerate() {
+                    let field_path = format!("{}.{}", path, field.name);
+                    match record_fields.get(i) {
+                        Some((name, value)) if *name == field.name => {
+                            value.collect_validation_errors(&field.schema, &field_path, errors)
+                        },
+                        Some((name, _)) => errors.push(AvroError::Validation(format!(
+                            "{}: expected field, got field {:?}",
+                            field_path, name
+                        ))),
+                        None => errors.push(AvroError::Validation(format!(
+                            "{}: expected field, got nothing",
+                            field_path
+                        ))),
+                    }
+                }
+                for (name, _) in record_fields.iter().skip(fields.len()) {
+                    errors.push(AvroError::Validation(format!(
+                        "{}.{}: unexpected field",
+                        path, name
+                    )));
+                }
+            },
+            (Value::Optional(Some(value), _), Schema::Optional(inner)) => {
+                value.collect_validation_errors(inner, path, errors)
+            },
+            (Value::Fixed(n, _, _), Schema::Fixed { size, .. }) if n != size => {
+                errors.push(AvroError::Validation(format!(
+                    "{}: expected fixed of size {}, got size {}",
+                    path, size, n
+                )))
+            },
+            (Value::String(s, _), Schema::Enum { symbols, .. })
+            | (Value::Enum(_, s, _), Schema::Enum { symbols, .. })
+                if !self.validate(schema) =>
+            {
+                errors.push(AvroError::Validation(format!(
+                    "{}: expected one of the symbols {:?}, got {:?}",
+                    path, symbols, s
+                )))
+            },
+            _ => {
+                if !self.validate(schema) {
+                    errors.push(mismatch(schema.type_name()))
+                }
+            },
+        }
+    }
+
     /// Attempt to perform schema resolution on the value, with the given
     /// [Schema](../schema/enum.Schema.html).
     ///
     /// See [Schema Resolution](https://avro.apache.org/docs/current/spec.html#Schema+Resolution)
     /// in the Avro specification for the full set of rules of schema
     /// resolution.
-    pub fn resolve(mut self, schema: &Schema) -> Result<Self, Error> {
+    ///
+    /// Writer fields unknown to the reader schema are ignored; see
+    /// [`resolve_with_policy`](#method.resolve_with_policy) to detect or keep them instead.
+    pub fn resolve(self, schema: &Schema) -> Result<Self, AvroError> {
+        self.resolve_with_policy(schema, &UnknownFieldPolicy::Ignore)
+    }
+
+    /// Attempt to perform schema resolution on the value, with the given
+    /// [Schema](../schema/enum.Schema.html), handling the record fields unknown to the reader
+    /// schema according to the given [UnknownFieldPolicy](enum.UnknownFieldPolicy.html).
+    ///
+    /// Entries of `LruSet` values limited by time expire relatively to the current time.
+    pub fn resolve_with_policy(
+        self,
+        schema: &Schema,
+        policy: &UnknownFieldPolicy,
+    ) -> Result<Self, AvroError> {
+        self.resolve_at(schema, policy, chrono::Utc::now().timestamp_millis())
+    }
+
+    /// Same as [`resolve_with_policy`](#method.resolve_with_policy), using `now` (in milliseconds
+    /// since epoch) instead of the current time as the reference time for expiring `LruSet`
+    /// entries.
+    pub fn resolve_at(
+        self,
+        schema: &Schema,
+        policy: &UnknownFieldPolicy,
+        now: i64,
+    ) -> Result<Self, AvroError> {
+        self.resolve_in(
+            schema,
+            &ResolveContext {
+                unknown_fields: policy,
+                now,
+                dates: &DateParsing::default(),
+                numbers: NumericPromotion::default(),
+            },
+        )
+    }
+
+    /// Attempt to perform schema resolution on the value, with the given
+    /// [Schema](../schema/enum.Schema.html) and [ResolveOptions](struct.ResolveOptions.html).
+    pub fn resolve_with_options(
+        self,
+        schema: &Schema,
+        options: &ResolveOptions,
+    ) -> Result<Self, AvroError> {
+        self.resolve_in(schema, &ResolveContext::new(options))
+    }
+
+    fn resolve_in(mut self, schema: &Schema, context: &ResolveContext) -> Result<Self, AvroError> {
         // Check if this schema is a union, and if the reader schema is not.
-        if SchemaKind::from(&self) == SchemaKind::Union
-            && SchemaKind::from(schema) != SchemaKind::Union
-        {
+        if SchemaKind::from(schema) != SchemaKind::Union {
             // Pull out the Union, and attempt to resolve against it.
-            let v = match self {
-                Value::Union(b, _) => *b,
-                _ => unreachable!(),
-            };
-            self = v;
+            if let Value::Union(v, _) = self {
+                self = *v;
+            }
         }
         match *schema {
             Schema::Null => self.resolve_null(),
-            Schema::Boolean => self.resolve_boolean(false),
-            Schema::Int => self.resolve_int(false),
-            Schema::Long => self.resolve_long(false),
-            Schema::Float => self.resolve_float(false),
-            Schema::Double => self.resolve_double(false),
-            Schema::Bytes => self.resolve_bytes(false),
-            Schema::String => self.resolve_string(false),
-            Schema::Fixed { size, .. } => self.resolve_fixed(size, false),
-            Schema::Union(ref inner) => self.resolve_union(inner, false),
-            Schema::Enum { ref symbols, .. } => self.resolve_enum(symbols, false),
-            Schema::Array(ref inner) => self.resolve_array(inner, false),
-            Schema::Map(ref inner) => self.resolve_map(inner, false),
-            Schema::Record { ref name, ref fields, .. } => self.resolve_record(fields, name.index),
-
-            Schema::Date => self.resolve_datetime(false),
-            Schema::Set => self.resolve_set(false),
-            Schema::LruSet(ref lru_limit) => self.resolve_lru_set(lru_limit.clone(), false),
-            Schema::Optional(ref inner) => self.resolve_optional(inner, false),
+            Schema::Boolean => self.resolve_boolean(None),
+            Schema::Int => self.resolve_int(None, context),
+            Schema::Long => self.resolve_long(None, context),
+            Schema::Float => self.resolve_float(None, context),
+            Schema::Double => self.resolve_double(None, context),
+            Schema::Bytes => self.resolve_bytes(None),
+            Schema::String => self.resolve_string(None),
+            Schema::Fixed { size, .. } => self.resolve_fixed(size, None),
+            Schema::Union(ref inner) => self.resolve_union(inner, None, context),
+            Schema::Enum {
+                ref symbols,
+                ref default,
+                ..
+            } => self.resolve_enum(symbols, default.as_deref(), None),
+            Schema::Array(ref inner) => self.resolve_array(inner, None, context),
+            Schema::BoundedList {
+                ref items,
+                max_len,
+                truncation,
+            } => self.resolve_bounded_list(items, max_len, truncation, None, context),
+            Schema::Map(ref inner) => self.resolve_map(inner, None, context),
+            Schema::Record {
+                ref name,
+                ref fields,
+                ref lookup,
+                ..
+            } => self.resolve_record(fields, lookup, record_setting(name), context),
+
+            Schema::Date(unit) => self.resolve_datetime(unit, None, context),
+            Schema::Set(ref inner) => self.resolve_set(inner, None, context),
+            Schema::LruSet(ref lru_limit, decay) => {
+                self.resolve_lru_set(lru_limit.clone(), decay, None, context)
+            },
+            Schema::Counter => self.resolve_counter(None),
+            Schema::Hll(precision) => self.resolve_hll(precision, None),
+            Schema::Bloom { bits, hashes } => self.resolve_bloom(bits, hashes, None),
+            Schema::TopK(size) => self.resolve_topk(size, None),
+            Schema::Optional(ref inner) => self.resolve_optional(inner, None, context),
         }
     }
 
@@ -448,179 +1436,391 @@ impl Value {
     /// See [Schema Resolution](https://avro.apache.org/docs/current/spec.html#Schema+Resolution)
     /// in the Avro specification for the full set of rules of schema
     /// resolution.
-    fn resolve_internal(mut self, schema: &Schema, index: bool) -> Result<Self, Error> {
+    /// Project the value onto the given reader [Schema](../schema/enum.Schema.html), keeping
+    /// only the record fields it declares.
+    ///
+    /// Fields unknown to the reader schema are dropped and missing fields are filled with their
+    /// default value, at any depth (through arrays, maps, unions and optionals). Unlike
+    /// [`resolve`](#method.resolve), the values which are kept are neither converted nor checked,
+    /// which makes it a cheap way of selecting a subset of the columns of decoded data.
+    pub fn project(self, reader_schema: &Schema) -> Result<Self, AvroError> {
+        match (self, reader_schema) {
+            (Value::Record(items, setting), Schema::Record { fields, .. }) => {
+                let mut items = items.into_iter().collect::<HashMap<_, _>>();
+                let projected = fields
+                    .iter()
+                    .map(|field| {
+                        let value = match items.remove(&field.name) {
+                            Some(value) => value.project(&field.schema)?,
+                            None => match field.default {
+                                Some(ref default) => {
+                                    trace_event!(
+                                        debug,
+                                        field = %field.name,
+                                        "projected missing field to its default"
+                                    );
+                                    default_to_value(default, &field.schema)?
+                                },
+                                None => {
+                                    return Err(AvroError::Resolution(format!(
+                                        "missing field {} in record",
+                                        field.name
+                                    )))
+                                },
+                            },
+                        };
+                        Ok((field.name.clone(), value))
+                    }).collect::<Result<Vec<_>, AvroError>>()?;
+                Ok(Value::Record(projected, setting))
+            },
+            (Value::Union(inner, setting), Schema::Union(union)) => {
+                let inner = match union.find_schema(&inner) {
+                    Some((_, branch)) => inner.project(branch)?,
+                    None => *inner,
+                };
+                Ok(Value::Union(Box::new(inner), setting))
+            },
+            (Value::Optional(Some(inner), setting), Schema::Optional(schema)) => {
+                Ok(Value::Optional(Some(Box::new(inner.project(schema)?)), setting))
+            },
+            (Value::Array(items, setting), Schema::Array(schema))
+            | (Value::Array(items, setting), Schema::BoundedList { items: schema, .. }) => {
+                Ok(Value::Array(
+                    items
+                        .into_iter()
+                        .map(|item| item.project(schema))
+                        .collect::<Result<_, _>>()?,
+                    setting,
+                ))
+            },
+            (Value::Map(items, setting), Schema::Map(schema)) => Ok(Value::Map(
+                items
+                    .into_iter()
+                    .map(|(key, item)| item.project(schema).map(|item| (key, item)))
+                    .collect::<Result<_, _>>()?,
+                setting,
+            )),
+            (value, _) => Ok(value),
+        }
+    }
+
+    pub(crate) fn resolve_internal(
+        mut self,
+        schema: &Schema,
+        setting: Option<&ValueSetting>,
+        context: &ResolveContext,
+    ) -> Result<Self, AvroError> {
         // Check if this schema is a union, and if the reader schema is not.
-        if SchemaKind::from(&self) == SchemaKind::Union
-            && SchemaKind::from(schema) != SchemaKind::Union
-        {
+        if SchemaKind::from(schema) != SchemaKind::Union {
             // Pull out the Union, and attempt to resolve against it.
-            let v = match self {
-                Value::Union(b, _) => *b,
-                _ => unreachable!(),
-            };
-            self = v;
+            if let Value::Union(v, _) = self {
+                self = *v;
+            }
         }
         match *schema {
             Schema::Null => self.resolve_null(),
-            Schema::Boolean => self.resolve_boolean(index),
-            Schema::Int => self.resolve_int(false),
-            Schema::Long => self.resolve_long(false),
-            Schema::Float => self.resolve_float(false),
-            Schema::Double => self.resolve_double(false),
-            Schema::Bytes => self.resolve_bytes(false),
-            Schema::String => self.resolve_string(index),
-            Schema::Fixed { size, .. } => self.resolve_fixed(size, false),
-            Schema::Union(ref inner) => self.resolve_union(inner, false),
-            Schema::Enum { ref symbols, .. } => self.resolve_enum(symbols, index),
-            Schema::Array(ref inner) => self.resolve_array(inner, index),
-            Schema::Map(ref inner) => self.resolve_map(inner, index),
-            Schema::Record { ref name, ref fields, .. } => self.resolve_record(fields, name.index),
-
-            Schema::Date => self.resolve_datetime(index),
-            Schema::Set => self.resolve_set(index),
-            Schema::LruSet(ref lru_limit) => self.resolve_lru_set(lru_limit.clone(), index),
-            Schema::Optional(ref inner) => self.resolve_optional(inner, index),
+            Schema::Boolean => self.resolve_boolean(setting),
+            Schema::Int => self.resolve_int(setting, context),
+            Schema::Long => self.resolve_long(setting, context),
+            Schema::Float => self.resolve_float(setting, context),
+            Schema::Double => self.resolve_double(setting, context),
+            Schema::Bytes => self.resolve_bytes(setting),
+            Schema::String => self.resolve_string(setting),
+            Schema::Fixed { size, .. } => self.resolve_fixed(size, setting),
+            Schema::Union(ref inner) => self.resolve_union(inner, setting, context),
+            Schema::Enum {
+                ref symbols,
+                ref default,
+                ..
+            } => self.resolve_enum(symbols, default.as_deref(), setting),
+            Schema::Array(ref inner) => self.resolve_array(inner, setting, context),
+            Schema::BoundedList {
+                ref items,
+                max_len,
+                truncation,
+            } => self.resolve_bounded_list(items, max_len, truncation, setting, context),
+            Schema::Map(ref inner) => self.resolve_map(inner, setting, context),
+            Schema::Record {
+                ref name,
+                ref fields,
+                ref lookup,
+                ..
+            } => self.resolve_record(fields, lookup, record_setting(name), context),
+
+            Schema::Date(unit) => self.resolve_datetime(unit, setting, context),
+            Schema::Set(ref inner) => self.resolve_set(inner, setting, context),
+            Schema::LruSet(ref lru_limit, decay) => {
+                self.resolve_lru_set(lru_limit.clone(), decay, setting, context)
+            },
+            Schema::Counter => self.resolve_counter(setting),
+            Schema::Hll(precision) => self.resolve_hll(precision, setting),
+            Schema::Bloom { bits, hashes } => self.resolve_bloom(bits, hashes, setting),
+            Schema::TopK(size) => self.resolve_topk(size, setting),
+            Schema::Optional(ref inner) => self.resolve_optional(inner, setting, context),
         }
     }
 
-    fn resolve_null(self) -> Result<Self, Error> {
+    fn resolve_null(self) -> Result<Self, AvroError> {
         match self {
             Value::Null => Ok(Value::Null),
             other => {
-                Err(SchemaResolutionError::new(format!("Null expected, got {:?}", other)).into())
+                Err(AvroError::Resolution(format!("Null expected, got {:?}", other)))
+            }
+        }
+    }
+
+    fn resolve_boolean(self, setting: Option<&ValueSetting>) -> Result<Self, AvroError> {
+        match self {
+            Value::Boolean(b, _) => Ok(Value::Boolean(b, setting.cloned())),
+            other => {
+                Err(AvroError::Resolution(format!("Boolean expected, got {:?}", other)))
             }
         }
     }
 
-    fn resolve_boolean(self, index: bool) -> Result<Self, Error> {
+    fn resolve_int(
+        self,
+        setting: Option<&ValueSetting>,
+        context: &ResolveContext,
+    ) -> Result<Self, AvroError> {
+        context.numbers.check(&self, SchemaKind::Int)?;
         match self {
-            Value::Boolean(b, _) => Ok(Value::Boolean(b, Self::get_value_setting(index))),
+            Value::Int(n, _) => Ok(Value::Int(n, setting.cloned())),
+            Value::Long(n, _) => Ok(Value::Int(n as i32, setting.cloned())),
             other => {
-                Err(SchemaResolutionError::new(format!("Boolean expected, got {:?}", other)).into())
+                Err(AvroError::Resolution(format!("Int expected, got {:?}", other)))
             }
         }
     }
 
-    fn resolve_int(self, index: bool) -> Result<Self, Error> {
+    fn resolve_long(
+        self,
+        setting: Option<&ValueSetting>,
+        context: &ResolveContext,
+    ) -> Result<Self, AvroError> {
+        context.numbers.check(&self, SchemaKind::Long)?;
         match self {
-            Value::Int(n, _) => Ok(Value::Int(n, Self::get_value_setting(index))),
-            Value::Long(n, _) => Ok(Value::Int(n as i32, Self::get_value_setting(index))),
+            Value::Int(n, _) => Ok(Value::Long(i64::from(n), setting.cloned())),
+            Value::Long(n, _) => Ok(Value::Long(n, setting.cloned())),
             other => {
-                Err(SchemaResolutionError::new(format!("Int expected, got {:?}", other)).into())
+                Err(AvroError::Resolution(format!("Long expected, got {:?}", other)))
             }
         }
     }
 
-    fn resolve_long(self, index: bool) -> Result<Self, Error> {
+    fn resolve_counter(self, setting: Option<&ValueSetting>) -> Result<Self, AvroError> {
         match self {
-            Value::Int(n, _) => Ok(Value::Long(i64::from(n), Self::get_value_setting(index))),
-            Value::Long(n, _) => Ok(Value::Long(n, Self::get_value_setting(index))),
+            Value::Int(n, _) => Ok(Value::Counter(i64::from(n), setting.cloned())),
+            Value::Long(n, _) | Value::Counter(n, _) => {
+                Ok(Value::Counter(n, setting.cloned()))
+            },
+            other => {
+                Err(AvroError::Resolution(format!("Counter expected, got {:?}", other)))
+            }
+        }
+    }
+
+    fn resolve_hll(self, precision: u8, setting: Option<&ValueSetting>) -> Result<Self, AvroError> {
+        let sketch = match self {
+            Value::Hll(sketch, _) => sketch,
+            Value::Bytes(bytes, _) => HyperLogLog::from_bytes(&bytes)?,
+            other => {
+                return Err(AvroError::Resolution(format!("Hll expected, got {:?}", other)))
+            }
+        };
+        if sketch.precision() != precision {
+            return Err(AvroError::Resolution(format!(
+                "Hll of precision {} expected, got precision {}",
+                precision,
+                sketch.precision()
+            )))
+        }
+        Ok(Value::Hll(sketch, setting.cloned()))
+    }
+
+    fn resolve_bloom(
+        self,
+        bits: u32,
+        hashes: u8,
+        setting: Option<&ValueSetting>,
+    ) -> Result<Self, AvroError> {
+        let filter = match self {
+            Value::Bloom(filter, _) => filter,
+            Value::Bytes(bytes, _) => BloomFilter::from_bytes(&bytes, bits, hashes)?,
             other => {
-                Err(SchemaResolutionError::new(format!("Long expected, got {:?}", other)).into())
+                return Err(AvroError::Resolution(format!("Bloom expected, got {:?}", other)))
             }
+        };
+        if filter.bits() != bits || filter.hashes() != hashes {
+            return Err(AvroError::Resolution(format!(
+                "Bloom of {} bits and {} hashes expected, got {} bits and {} hashes",
+                bits,
+                hashes,
+                filter.bits(),
+                filter.hashes()
+            )))
         }
+        Ok(Value::Bloom(filter, setting.cloned()))
+    }
+
+    fn resolve_topk(self, size: usize, setting: Option<&ValueSetting>) -> Result<Self, AvroError> {
+        let mut topk = match self {
+            Value::TopK(topk, _) => topk,
+            Value::Map(items, _) => {
+                // keep every count until truncation, so that the result does not depend on the
+                // iteration order of the map
+                let mut topk = TopK::new(items.len());
+                for (key, value) in items {
+                    match value {
+                        Value::Int(n, _) => topk.insert(key, i64::from(n)),
+                        Value::Long(n, _) | Value::Counter(n, _) => topk.insert(key, n),
+                        other => {
+                            return Err(AvroError::Resolution(format!(
+                                "TopK count expected, got {:?}",
+                                other
+                            )))
+                        },
+                    }
+                }
+                Box::new(topk)
+            },
+            other => {
+                return Err(AvroError::Resolution(format!("TopK expected, got {:?}", other)))
+            }
+        };
+        topk.truncate(size);
+        Ok(Value::TopK(topk, setting.cloned()))
     }
 
-    fn resolve_float(self, index: bool) -> Result<Self, Error> {
+    fn resolve_float(
+        self,
+        setting: Option<&ValueSetting>,
+        context: &ResolveContext,
+    ) -> Result<Self, AvroError> {
+        context.numbers.check(&self, SchemaKind::Float)?;
         match self {
-            Value::Int(n, _) => Ok(Value::Float(n as f32, Self::get_value_setting(index))),
-            Value::Long(n, _) => Ok(Value::Float(n as f32, Self::get_value_setting(index))),
-            Value::Float(x, _) => Ok(Value::Float(x, Self::get_value_setting(index))),
-            Value::Double(x, _) => Ok(Value::Float(x as f32, Self::get_value_setting(index))),
+            Value::Int(n, _) => Ok(Value::Float(n as f32, setting.cloned())),
+            Value::Long(n, _) => Ok(Value::Float(n as f32, setting.cloned())),
+            Value::Float(x, _) => Ok(Value::Float(x, setting.cloned())),
+            Value::Double(x, _) => Ok(Value::Float(x as f32, setting.cloned())),
             other => {
-                Err(SchemaResolutionError::new(format!("Float expected, got {:?}", other)).into())
+                Err(AvroError::Resolution(format!("Float expected, got {:?}", other)))
             }
         }
     }
 
-    fn resolve_double(self, index: bool) -> Result<Self, Error> {
+    fn resolve_double(
+        self,
+        setting: Option<&ValueSetting>,
+        context: &ResolveContext,
+    ) -> Result<Self, AvroError> {
+        context.numbers.check(&self, SchemaKind::Double)?;
         match self {
-            Value::Int(n, _) => Ok(Value::Double(f64::from(n), Self::get_value_setting(index))),
-            Value::Long(n, _) => Ok(Value::Double(n as f64, Self::get_value_setting(index))),
-            Value::Float(x, _) => Ok(Value::Double(f64::from(x), Self::get_value_setting(index))),
-            Value::Double(x, _) => Ok(Value::Double(x, Self::get_value_setting(index))),
+            Value::Int(n, _) => Ok(Value::Double(f64::from(n), setting.cloned())),
+            Value::Long(n, _) => Ok(Value::Double(n as f64, setting.cloned())),
+            Value::Float(x, _) => Ok(Value::Double(f64::from(x), setting.cloned())),
+            Value::Double(x, _) => Ok(Value::Double(x, setting.cloned())),
             other => {
-                Err(SchemaResolutionError::new(format!("Double expected, got {:?}", other)).into())
+                Err(AvroError::Resolution(format!("Double expected, got {:?}", other)))
             }
         }
     }
 
-    fn resolve_bytes(self, index: bool) -> Result<Self, Error> {
+    fn resolve_bytes(self, setting: Option<&ValueSetting>) -> Result<Self, AvroError> {
         match self {
-            Value::Bytes(bytes, _) => Ok(Value::Bytes(bytes, Self::get_value_setting(index))),
-            Value::String(s, _) => Ok(Value::Bytes(s.into_bytes(), Self::get_value_setting(index))),
+            Value::Bytes(bytes, _) => Ok(Value::Bytes(bytes, setting.cloned())),
+            Value::String(s, _) => Ok(Value::Bytes(s.into_bytes(), setting.cloned())),
             Value::Array(items, _) => Ok(Value::Bytes(
                 items
                     .into_iter()
                     .map(Value::try_u8)
                     .collect::<Result<Vec<_>, _>>()?,
-                Self::get_value_setting(index),
+                setting.cloned(),
             )),
             other => {
-                Err(SchemaResolutionError::new(format!("Bytes expected, got {:?}", other)).into())
+                Err(AvroError::Resolution(format!("Bytes expected, got {:?}", other)))
             }
         }
     }
 
-    fn resolve_string(self, index: bool) -> Result<Self, Error> {
+    fn resolve_string(self, setting: Option<&ValueSetting>) -> Result<Self, AvroError> {
         match self {
-            Value::String(s, _) => Ok(Value::String(s, Self::get_value_setting(index))),
-            Value::Bytes(bytes, _) => Ok(Value::String(String::from_utf8(bytes)?, Self::get_value_setting(index))),
+            Value::String(s, _) => Ok(Value::String(s, setting.cloned())),
+            Value::Bytes(bytes, _) => {
+                Ok(Value::String(String::from_utf8(bytes)?, setting.cloned()))
+            },
             other => {
-                Err(SchemaResolutionError::new(format!("String expected, got {:?}", other)).into())
+                Err(AvroError::Resolution(format!("String expected, got {:?}", other)))
             }
         }
     }
 
-    fn resolve_fixed(self, size: usize, index: bool) -> Result<Self, Error> {
+    fn resolve_fixed(self, size: usize, setting: Option<&ValueSetting>) -> Result<Self, AvroError> {
         match self {
             Value::Fixed(n, bytes, _) => if n == size {
-                Ok(Value::Fixed(n, bytes, Self::get_value_setting(index)))
+                Ok(Value::Fixed(n, bytes, setting.cloned()))
             } else {
-                Err(SchemaResolutionError::new(format!(
+                Err(AvroError::Resolution(format!(
                     "Fixed size mismatch, {} expected, got {}",
                     size, n
-                )).into())
+                )))
             },
             other => {
-                Err(SchemaResolutionError::new(format!("String expected, got {:?}", other)).into())
+                Err(AvroError::Resolution(format!("String expected, got {:?}", other)))
             }
         }
     }
 
-    fn resolve_enum(self, symbols: &[String], index: bool) -> Result<Self, Error> {
-        let validate_symbol = |symbol: String, symbols: &[String]| {
-            if let Some(i) = symbols.iter().position(|ref item| item == &&symbol) {
-                Ok(Value::Enum(i as i32, symbol, Self::get_value_setting(index)))
-            } else {
-                Err(SchemaResolutionError::new(format!(
+    fn resolve_enum(
+        self,
+        symbols: &[String],
+        default: Option<&str>,
+        setting: Option<&ValueSetting>,
+    ) -> Result<Self, AvroError> {
+        let position = |symbol: &str| symbols.iter().position(|item| item == symbol);
+        let validate_symbol = |symbol: String| match position(&symbol) {
+            Some(i) => Ok(Value::Enum(i as i32, symbol, setting.cloned())),
+            // symbols unknown to the reader resolve to its default symbol, if any
+            None => match default.and_then(|default| position(default).map(|i| (i, default))) {
+                Some((i, default)) => Ok(Value::Enum(
+                    i as i32,
+                    default.to_owned(),
+                    setting.cloned(),
+                )),
+                None => Err(AvroError::Resolution(format!(
                     "Enum default {} is not among allowed symbols {:?}",
                     symbol, symbols,
-                )).into())
-            }
+                ))),
+            },
         };
 
         match self {
-            Value::Enum(i, s, _) => if i >= 0 && i < symbols.len() as i32 {
-                validate_symbol(s, symbols)
+            // the index of the writer does not matter when the reader has a default
+            Value::Enum(i, s, _) => if (i >= 0 && i < symbols.len() as i32) || default.is_some() {
+                validate_symbol(s)
             } else {
-                Err(SchemaResolutionError::new(format!(
+                Err(AvroError::Resolution(format!(
                     "Enum value {} is out of bound {}",
                     i,
                     symbols.len() as i32
-                )).into())
+                )))
             },
-            Value::String(s, _) => validate_symbol(s, symbols),
-            other => Err(SchemaResolutionError::new(format!(
+            Value::String(s, _) => validate_symbol(s),
+            other => Err(AvroError::Resolution(format!(
                 "Enum({:?}) expected, got {:?}",
                 symbols, other
-            )).into()),
+            ))),
         }
     }
 
-    fn resolve_union(self, schema: &UnionSchema, index: bool) -> Result<Self, Error> {
-        let v = match self {
-            // Both are unions case.
+    fn resolve_union(
+        self,
+        schema: &UnionSchema,
+        setting: Option<&ValueSetting>,
+        context: &ResolveContext,
+    ) -> Result<Self, AvroError> {
+        let v = match self.into_union() {
+            // Both are unions case, or the writer is an optional.
             Value::Union(v, _) => *v,
             // Reader is a union, but writer is not.
             v => v,
@@ -628,132 +1828,269 @@ impl Value {
         // Find the first match in the reader schema.
         let (_, inner) = schema
             .find_schema(&v)
-            .ok_or_else(|| SchemaResolutionError::new("Could not find matching type in union"))?;
-        v.resolve_internal(inner, index)
+            .ok_or_else(|| AvroError::Resolution(
+                "Could not find matching type in union".to_owned(),
+            ))?;
+        v.resolve_internal(inner, setting, context)
     }
 
-    fn resolve_array(self, schema: &Schema, index: bool) -> Result<Self, Error> {
+    fn resolve_array(
+        self,
+        schema: &Schema,
+        setting: Option<&ValueSetting>,
+        context: &ResolveContext,
+    ) -> Result<Self, AvroError> {
         match self {
             Value::Array(items, _) => Ok(Value::Array(
                 items
                     .into_iter()
-                    .map(|item| item.resolve_internal(schema, index))
+                    .enumerate()
+                    .map(|(i, item)| {
+                        at(item.resolve_internal(schema, setting, context), PathSegment::Index(i))
+                    })
                     .collect::<Result<Vec<_>, _>>()?,
-                Self::get_value_setting(index),
+                setting.cloned(),
             )),
-            other => Err(SchemaResolutionError::new(format!(
+            other => Err(AvroError::Resolution(format!(
                 "Array({:?}) expected, got {:?}",
                 schema, other
-            )).into()),
+            ))),
+        }
+    }
+
+    fn resolve_bounded_list(
+        self,
+        schema: &Schema,
+        max_len: usize,
+        truncation: Truncation,
+        setting: Option<&ValueSetting>,
+        context: &ResolveContext,
+    ) -> Result<Self, AvroError> {
+        match self.resolve_array(schema, setting, context)? {
+            Value::Array(mut items, setting) => {
+                truncation.apply(&mut items, max_len);
+                Ok(Value::Array(items, setting))
+            },
+            other => {
+                Err(AvroError::Resolution(format!("Array expected, got {:?}", other)))
+            },
         }
     }
 
-    fn resolve_map(self, schema: &Schema, index: bool) -> Result<Self, Error> {
+    fn resolve_map(
+        self,
+        schema: &Schema,
+        setting: Option<&ValueSetting>,
+        context: &ResolveContext,
+    ) -> Result<Self, AvroError> {
         match self {
             Value::Map(items, _) => Ok(Value::Map(
                 items
                     .into_iter()
-                    .map(|(key, value)| value.resolve_internal(schema, index).map(|value| (key, value)))
+                    .map(|(key, value)| {
+                        let value = value.resolve_internal(schema, setting, context);
+                        at(value, PathSegment::Key(&key)).map(|value| (key, value))
+                    })
                     .collect::<Result<HashMap<_, _>, _>>()?,
-                Self::get_value_setting(index),
+                setting.cloned(),
             )),
-            other => Err(SchemaResolutionError::new(format!(
+            other => Err(AvroError::Resolution(format!(
                 "Map({:?}) expected, got {:?}",
                 schema, other
-            )).into()),
+            ))),
         }
     }
 
-    fn resolve_record(self, fields: &[RecordField], index: bool) -> Result<Self, Error> {
-        let mut items = match self {
-            Value::Map(items, _) => Ok(items),
-            Value::Record(fields, _) => Ok(fields.into_iter().collect::<HashMap<_, _>>()),
-            other => Err(SchemaResolutionError::new(format!(
-                "Record({:?}) expected, got {:?}",
-                fields, other
-            ))),
-        }?;
+    fn resolve_record(
+        self,
+        fields: &[RecordField],
+        lookup: &HashMap<String, usize>,
+        setting: Option<&ValueSetting>,
+        context: &ResolveContext,
+    ) -> Result<Self, AvroError> {
+        // writer values are placed at the position of the reader field of the same name, and
+        // every value without one is kept as unknown, to be matched against aliases
+        let mut values: Vec<Option<Value>> = vec![None; fields.len()];
+        let mut unknown = Vec::new();
+        match self {
+            Value::Map(items, _) => {
+                for (key, value) in items {
+                    match lookup.get(&key) {
+                        Some(&position) => values[position] = Some(value),
+                        None => unknown.push((Arc::from(key), value)),
+                    }
+                }
+            },
+            Value::Record(items, _) => {
+                for (position, (name, value)) in items.into_iter().enumerate() {
+                    // writer fields are most often in the order of the reader fields
+                    let position = match fields.get(position) {
+                        Some(field) if field.name == name => Some(position),
+                        _ => lookup.get(&*name).copied(),
+                    };
+                    match position {
+                        Some(position) => values[position] = Some(value),
+                        None => unknown.push((name, value)),
+                    }
+                }
+            },
+            other => {
+                return Err(AvroError::Resolution(format!(
+                    "Record({:?}) expected, got {:?}",
+                    fields, other
+                )))
+            },
+        }
+
+        let collect_into = match context.unknown_fields {
+            UnknownFieldPolicy::CollectInto(extras) => Some(extras.as_str()),
+            _ => None,
+        };
 
-        let new_fields = fields
+        let mut new_fields = fields
             .iter()
-            .map(|field| {
-                let value = match items.remove(&field.name) {
+            .zip(values)
+            .map(|(field, value)| {
+                let value = value.or_else(|| {
+                    // writers may still use a former name of the field
+                    let position = field.aliases.iter().flatten().find_map(|alias| {
+                        unknown.iter().position(|(name, _)| **name == **alias)
+                    })?;
+                    Some(unknown.swap_remove(position).1)
+                });
+                let setting = ValueSetting::of_field(field);
+                let value = match value {
                     Some(value) => value,
-                    None => match field.default {
-                        Some(ref value) => match field.schema {
-                            Schema::Enum { ref symbols, .. } => {
-                                value.clone().avro().resolve_enum(symbols, field.index)?
-                            }
-                            _ => value.clone().avro(),
+                    // the field collecting unknown fields may be left out by writers
+                    None if collect_into == Some(&*field.name) && field.default.is_none() => {
+                        Value::Map(HashMap::new(), None)
+                    },
+                    None => match field_default(field, setting.as_ref())? {
+                        Some(value) => {
+                            trace_event!(
+                                debug,
+                                field = %field.name,
+                                "resolved missing field to its default"
+                            );
+                            value
                         },
-                        _ => {
-                            return Err(SchemaResolutionError::new(format!(
+                        None => {
+                            return Err(AvroError::Resolution(format!(
                                 "missing field {} in record",
                                 field.name
-                            )).into());
+                            )));
                         }
                     },
                 };
 
                 // for partial fields put value as Value::Null, which is handled properly at encode and decode time
-                value
-                    .resolve_internal(&field.schema, field.index)
-                    .map(|value| (field.name.clone(), value))
+                let value = value.resolve_internal(&field.schema, setting.as_ref(), context);
+                at(value, PathSegment::Field(&field.name)).map(|value| (field.name.clone(), value))
             }).collect::<Result<Vec<_>, _>>()?;
 
-        Ok(Value::Record(new_fields, Self::get_value_setting(index)))
+        // every remaining item is a writer field unknown to the reader schema
+        match context.unknown_fields {
+            UnknownFieldPolicy::Ignore => (),
+            UnknownFieldPolicy::Error => {
+                if let Some(name) = unknown.iter().map(|(name, _)| name).min() {
+                    return Err(AvroError::Resolution(format!(
+                        "unknown field {} in record",
+                        name
+                    )))
+                }
+            },
+            UnknownFieldPolicy::CollectInto(extras) => {
+                let position = fields.iter().position(|field| *field.name == **extras);
+                if let (Some(position), false) = (position, unknown.is_empty()) {
+                    let values = match fields[position].schema {
+                        Schema::Map(ref values) => values,
+                        _ => {
+                            return Err(AvroError::Resolution(format!(
+                                "field {} collecting unknown fields must be a map",
+                                extras
+                            )))
+                        },
+                    };
+                    let collected = match new_fields[position].1 {
+                        Value::Map(ref mut collected, _) => collected,
+                        ref other => {
+                            return Err(AvroError::Resolution(format!(
+                                "Map expected to collect unknown fields, got {:?}",
+                                other
+                            )))
+                        },
+                    };
+                    for (name, value) in unknown {
+                        collected.insert(
+                            name.to_string(),
+                            value.resolve_internal(values, setting, context)?,
+                        );
+                    }
+                }
+            },
+        }
+
+        Ok(Value::Record(new_fields, setting.cloned()))
     }
 
     // u64 to u64 is default
     // string to u64 is through well defined patterns
-    fn resolve_datetime(self, index: bool) -> Result<Self, Error> {
+    fn resolve_datetime(
+        self,
+        unit: DateUnit,
+        setting: Option<&ValueSetting>,
+        context: &ResolveContext,
+    ) -> Result<Self, AvroError> {
         match self {
-            Value::Long(val, _) => Ok(Value::Date(val, Self::get_value_setting(index))),
-            Value::Date(val, _) => Ok(Value::Date(val, Self::get_value_setting(index))),
-            Value::String(val, _) => {
-                let dt = chrono::DateTime::parse_from_rfc3339(&val);
-                if !dt.is_err() {
-                    let epoch = dt.unwrap().timestamp_millis();
-                    return Ok(Value::Date(epoch, Self::get_value_setting(index)));
-                }
-
-                let dt = chrono::DateTime::parse_from_rfc2822(&val);
-                if !dt.is_err() {
-                    let epoch = dt.unwrap().timestamp_millis();
-                    return Ok(Value::Date(epoch, Self::get_value_setting(index)));
-                }
-
-                return Err(failure::err_msg(format!("Couldn't resolve string value {} to date", val)));
-            }
-            other => Err(SchemaResolutionError::new(format!("Date expected, got {:?}", other)).into()),
+            // longs are taken in the unit of the reader schema
+            Value::Long(val, _) => Ok(Value::Date(unit.to_millis(val), setting.cloned())),
+            Value::Date(val, _) => Ok(Value::Date(val, setting.cloned())),
+            Value::String(val, _) => match context.dates.parse(&val) {
+                Some(epoch) => Ok(Value::Date(epoch, setting.cloned())),
+                None => Err(AvroError::Resolution(format!(
+                    "Couldn't resolve string value {} to date", val
+                ))),
+            },
+            other => Err(AvroError::Resolution(format!("Date expected, got {:?}", other))),
         }
     }
 
-    fn resolve_set(self, index: bool) -> Result<Self, Error> {
-        match self {
-            Value::Array(items, _) => Ok(Value::Set(
-                items
-                    .into_iter()
-                    .map(|item| {
-                        match item {
-                            Value::String(s, _) => Ok(s),
-                            other => Err(SchemaResolutionError::new(format!(
-                                "String expected, got {:?}", other
-                            )).into())
-                        }
-                    })
-                    .collect::<Result<HashSet<_>, SchemaResolutionError>>()?,
-                Self::get_value_setting(index),
-            )),
-            Value::Set(items, _) => Ok(Value::Set(items, Self::get_value_setting(index))),
-            other => Err(SchemaResolutionError::new(format!(
+    fn resolve_set(
+        self,
+        schema: &Schema,
+        setting: Option<&ValueSetting>,
+        context: &ResolveContext,
+    ) -> Result<Self, AvroError> {
+        let items = match self {
+            Value::Array(items, _) => items,
+            Value::Set(items, _) => items.into_iter().map(|item| item.avro()).collect(),
+            other => return Err(AvroError::Resolution(format!(
                 "Set expected, got {:?}", other
-            )).into()),
-        }
+            ))),
+        };
+        Ok(Value::Set(
+            items
+                .into_iter()
+                .map(|item| {
+                    let item = item.resolve_internal(schema, None, context)?;
+                    SetItem::try_from(item)
+                })
+                .collect::<Result<HashSet<_>, AvroError>>()?,
+            setting.cloned(),
+        ))
     }
 
-    fn resolve_lru_value(self) -> Result<LruValue, Error> {
-        let resolved = self.resolve_internal(&LRU_VALUE_SCHEMA, false)?;
+    fn resolve_lru_value(self) -> Result<LruValue, AvroError> {
+        let resolved = self.resolve_internal(
+            &LRU_VALUE_SCHEMA,
+            None,
+            &ResolveContext {
+                unknown_fields: &UnknownFieldPolicy::Ignore,
+                now: 0,
+                dates: &DateParsing::default(),
+                numbers: NumericPromotion::default(),
+            },
+        )?;
 
         match resolved {
             Value::Record(fields, _) => {
@@ -761,7 +2098,7 @@ impl Value {
                 let mut count = 0;
 
                 for (field, value) in fields {
-                    match (field.as_str(), value) {
+                    match (&*field, value) {
                         (ACCESS_TIME, Value::Long(v, _)) => {
                             access_time = v;
                         }
@@ -778,42 +2115,50 @@ impl Value {
                 })
             }
             other => {
-                Err(SchemaResolutionError::new(format!("Expected LruValue record, got {:?}", other)).into())
+                Err(AvroError::Resolution(
+                    format!("Expected LruValue record, got {:?}", other),
+                ))
             }
         }
     }
 
-    fn resolve_lru_set(self, lru_limit: LruLimit, index: bool) -> Result<Self, Error> {
-        match self {
-            Value::Map(items, _) => Ok(Value::LruSet(
+    fn resolve_lru_set(
+        self,
+        lru_limit: LruLimit,
+        decay: Option<Decay>,
+        setting: Option<&ValueSetting>,
+        context: &ResolveContext,
+    ) -> Result<Self, AvroError> {
+        let mut items = match self {
+            Value::Map(items, _) => Box::new(
                 items
                     .into_iter()
                     .map(|(key, value)| value.resolve_lru_value().map(|value| (key, value)))
-                    .collect::<Result<HashMap<_, _>, _>>()?,
-                lru_limit,
-                Self::get_value_setting(index),
-            )),
-            Value::LruSet(items, _, _) => Ok(Value::LruSet(items, lru_limit, Self::get_value_setting(index))),
-            other => Err(SchemaResolutionError::new(format!(
+                    .collect::<Result<LruMap, _>>()?,
+            ),
+            Value::LruSet(items, _, _) => items,
+            other => return Err(AvroError::Resolution(format!(
                 "LruSet expected, got {:?}", other
-            )).into()),
-        }
+            ))),
+        };
+        // the reader limit applies, which may be stricter than the writer one
+        compact_lru(&mut items, &lru_limit, decay, context.now);
+        Ok(Value::LruSet(items, lru_limit, setting.cloned()))
     }
 
-    fn resolve_optional(self, schema: &Schema, index: bool) -> Result<Self, Error> {
-        let v = match self {
-            // Both are Optional case.
-            Value::Optional(v, _) => v,
-            // Reader is an Optional, but writer is not.
-            v => Some(Box::new(v)),
-        };
+    fn resolve_optional(
+        self,
+        schema: &Schema,
+        setting: Option<&ValueSetting>,
+        context: &ResolveContext,
+    ) -> Result<Self, AvroError> {
         // Find the first match in the reader schema.
-        match v {
-            Some(value) => {
-                let value = value.resolve(schema)?;
-                Ok(Value::Optional(Some(Box::new(value)), Self::get_value_setting(index)))
+        match self.into_optional() {
+            Value::Optional(Some(value), _) => {
+                let value = value.resolve_in(schema, context)?;
+                Ok(Value::Optional(Some(Box::new(value)), setting.cloned()))
             }
-            None => Ok(Value::Optional(None, Self::get_value_setting(index)))
+            _ => Ok(Value::Optional(None, setting.cloned()))
         }
     }
 
@@ -822,7 +2167,15 @@ impl Value {
             Value::Null => JsonValue::Null,
             Value::Boolean(b, _) => JsonValue::Bool(b.to_owned()),
             Value::Int(n, _) => json!(n),
-            Value::Long(n, _) => json!(n),
+            Value::Long(n, _) | Value::Counter(n, _) => json!(n),
+            Value::Hll(sketch, _) => json!(sketch.estimate()),
+            Value::Bloom(filter, _) => json!(filter.as_bytes()),
+            Value::TopK(topk, _) => JsonValue::Object(
+                topk.entries()
+                    .into_iter()
+                    .map(|(key, count)| (key.to_owned(), json!(count.count)))
+                    .collect(),
+            ),
             Value::Float(n, _) => json!(n),
             Value::Double(n, _) => json!(n),
             Value::Bytes(b, _) => json!(b),
@@ -837,14 +2190,14 @@ impl Value {
                 JsonValue::Object(items.into_iter().map(|(key, value)| (key.clone(), value.json())).collect::<_>())
             }
             Value::Record(items, _) => {
-                JsonValue::Object(items.iter().map(|(key, value)| (key.clone(), value.json())).collect::<_>())
+                JsonValue::Object(items.iter().map(|(key, value)| (key.to_string(), value.json())).collect::<_>())
             }
             Value::Date(t, _) => json!(t),
             Value::Set(items, _) => {
-                JsonValue::Array(items.into_iter().map(|item| JsonValue::String(item.to_owned())).collect::<_>())
+                JsonValue::Array(items.iter().map(|item| item.clone().avro().json()).collect::<_>())
             }
             Value::LruSet(items, _, _) => {
-                JsonValue::Object(items.into_iter().map(|(key, value)| (key.clone(), value.json())).collect::<_>())
+                JsonValue::Object(items.iter().map(|(key, value)| (key.clone(), value.json())).collect::<_>())
             }
             Value::Optional(value, _) => {
                 match value {
@@ -855,26 +2208,163 @@ impl Value {
         }
     }
 
-    fn try_u8(self) -> Result<u8, Error> {
-        let int = self.resolve(&Schema::Int)?;
-        if let Value::Int(n, None) = int {
-            if n >= 0 && n <= i32::from(u8::MAX) {
-                return Ok(n as u8);
-            }
-        }
-
-        Err(
-            SchemaResolutionError::new(
-                format!("Unable to convert to u8, got {:?}", int)
-            ).into()
-        )
-    }
-
-    fn get_value_setting(index: bool) -> Option<ValueSetting> {
-        if !index {
-            None
-        } else {
-            Some(ValueSetting { index })
+    /// Convert the value to JSON like [`json`](#method.json), moving its strings and keys instead
+    /// of cloning them.
+    pub fn into_json(self) -> JsonValue {
+        match self {
+            Value::Bytes(b, _) | Value::Fixed(_, b, _) => json!(b),
+            Value::String(s, _) | Value::Enum(_, s, _) => JsonValue::String(s),
+            Value::Union(value, _) | Value::Optional(Some(value), _) => value.into_json(),
+            Value::Optional(None, _) => JsonValue::Null,
+            Value::Array(items, _) => {
+                JsonValue::Array(items.into_iter().map(Value::into_json).collect())
+            },
+            Value::Map(items, _) => JsonValue::Object(
+                items.into_iter().map(|(key, value)| (key, value.into_json())).collect(),
+            ),
+            Value::Record(items, _) => JsonValue::Object(
+                items
+                    .into_iter()
+                    .map(|(key, value)| (key.to_string(), value.into_json()))
+                    .collect(),
+            ),
+            Value::Set(items, _) => {
+                JsonValue::Array(items.into_iter().map(|item| item.avro().into_json()).collect())
+            },
+            Value::LruSet(items, _, _) => JsonValue::Object(
+                (*items).into_iter().map(|(key, value)| (key, value.json())).collect(),
+            ),
+            other => other.json(),
+        }
+    }
+
+    /// Convert the value to JSON like [`json`](#method.json), with `NaN` and infinite floats
+    /// converted as `floats` tells.
+    pub fn json_with(&self, floats: NonFiniteFloats) -> Result<JsonValue, AvroError> {
+        Ok(serde_json::to_value(JsonView(self, floats))?)
+    }
+
+    /// Write the value to `writer` as the JSON given by [`json`](#method.json), without building
+    /// it in memory first.
+    pub fn write_json<W: io::Write>(&self, writer: W) -> Result<(), AvroError> {
+        self.write_json_with(writer, NonFiniteFloats::Null)
+    }
+
+    /// Write the value to `writer` as the JSON given by [`json_with`](#method.json_with), without
+    /// building it in memory first.
+    pub fn write_json_with<W: io::Write>(
+        &self,
+        writer: W,
+        floats: NonFiniteFloats,
+    ) -> Result<(), AvroError> {
+        serde_json::to_writer(writer, &JsonView(self, floats))?;
+        Ok(())
+    }
+
+    fn try_u8(self) -> Result<u8, AvroError> {
+        let int = self.resolve(&Schema::Int)?;
+        if let Value::Int(n, None) = int {
+            if n >= 0 && n <= i32::from(u8::MAX) {
+                return Ok(n as u8);
+            }
+        }
+
+        Err(
+            AvroError::Resolution(
+                format!("Unable to convert to u8, got {:?}", int)
+            )
+        )
+    }
+
+}
+
+/// Serialization of a value to the JSON given by [`Value::json`](enum.Value.html#method.json),
+/// with non-finite floats converted as told.
+struct JsonView<'a>(&'a Value, NonFiniteFloats);
+
+impl<'a> JsonView<'a> {
+    fn of(&self, value: &'a Value) -> JsonView<'a> {
+        JsonView(value, self.1)
+    }
+
+    fn serialize_f64<S: Serializer>(&self, n: f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if n.is_finite() {
+            return serializer.serialize_f64(n)
+        }
+        let name = if n.is_nan() {
+            "NaN"
+        } else if n > 0.0 {
+            "Infinity"
+        } else {
+            "-Infinity"
+        };
+        match self.1 {
+            NonFiniteFloats::Null => serializer.serialize_unit(),
+            NonFiniteFloats::String => serializer.serialize_str(name),
+            NonFiniteFloats::Error => {
+                Err(S::Error::custom(format!("{} can't be converted to JSON", name)))
+            },
+        }
+    }
+}
+
+impl<'a> Serialize for JsonView<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Null | Value::Optional(None, _) => serializer.serialize_unit(),
+            Value::Boolean(b, _) => serializer.serialize_bool(*b),
+            Value::Int(n, _) => serializer.serialize_i32(*n),
+            Value::Long(n, _) | Value::Counter(n, _) | Value::Date(n, _) => {
+                serializer.serialize_i64(*n)
+            },
+            Value::Hll(sketch, _) => serializer.serialize_u64(sketch.estimate()),
+            Value::Bloom(filter, _) => filter.as_bytes().serialize(serializer),
+            Value::TopK(topk, _) => {
+                let entries = topk.entries();
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, count) in entries {
+                    map.serialize_entry(key, &count.count)?;
+                }
+                map.end()
+            },
+            // as json!, which keeps floats as doubles
+            Value::Float(n, _) => self.serialize_f64(f64::from(*n), serializer),
+            Value::Double(n, _) => self.serialize_f64(*n, serializer),
+            Value::Bytes(b, _) | Value::Fixed(_, b, _) => b.serialize(serializer),
+            Value::String(s, _) | Value::Enum(_, s, _) => serializer.serialize_str(s),
+            Value::Union(value, _) | Value::Optional(Some(value), _) => {
+                self.of(value).serialize(serializer)
+            },
+            Value::Array(items, _) => {
+                serializer.collect_seq(items.iter().map(|item| self.of(item)))
+            },
+            Value::Map(items, _) => {
+                serializer.collect_map(items.iter().map(|(key, value)| (key, self.of(value))))
+            },
+            Value::Record(items, _) => serializer
+                .collect_map(items.iter().map(|(key, value)| (&**key, self.of(value)))),
+            Value::Set(items, _) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    match item {
+                        SetItem::Int(n) => seq.serialize_element(n)?,
+                        SetItem::Long(n) => seq.serialize_element(n)?,
+                        SetItem::Bytes(b) => seq.serialize_element(b)?,
+                        SetItem::String(s) | SetItem::Enum(_, s) => seq.serialize_element(s)?,
+                    }
+                }
+                seq.end()
+            },
+            Value::LruSet(items, _, _) => {
+                let mut map = serializer.serialize_map(Some(items.len()))?;
+                for (key, value) in items.iter() {
+                    let mut entry = BTreeMap::new();
+                    entry.insert(ACCESS_TIME, value.access_time);
+                    entry.insert(COUNT, value.count);
+                    map.serialize_entry(key, &entry)?;
+                }
+                map.end()
+            },
         }
     }
 }
@@ -935,6 +2425,56 @@ mod tests {
         }
     }
 
+    #[test]
+    fn validate_verbose() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": "long"},
+                    {"name": "b", "type": {"type": "array", "items": ["null", "string"]}},
+                    {"name": "c", "type": {"type": "enum", "name": "suit", "symbols": ["spades"]}}
+                ]
+            }
+        "#,
+        ).unwrap();
+
+        let valid = Value::record(vec![
+            ("a", 1i64.avro()),
+            ("b", vec![Some("foo"), None].avro()),
+            ("c", "spades".avro()),
+        ]);
+        assert!(valid.validate_verbose(&schema).is_ok());
+
+        let invalid = Value::record(vec![
+            ("a", "foo".avro()),
+            ("b", Value::Array(vec![Value::Union(Box::new(1i32.avro()), None)], None)),
+            ("c", "hearts".avro()),
+        ]);
+        let errors = invalid
+            .validate_verbose(&schema)
+            .unwrap_err()
+            .iter()
+            .map(|error| error.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            vec![
+                "Validation error: $.a: expected long, got String",
+                "Validation error: $.b[0]: expected one of [null, string], got Int",
+                "Validation error: $.c: expected one of the symbols [\"spades\"], got \"hearts\"",
+            ]
+        );
+
+        let errors = Value::record(vec![("a", 1i64)]).validate_verbose(&schema).unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "Validation error: $.b: expected field, got nothing"
+        );
+    }
+
     #[test]
     fn validate_fixed() {
         let schema = Schema::Fixed {
@@ -957,6 +2497,7 @@ mod tests {
                 "diamonds".to_string(),
                 "clubs".to_string(),
             ],
+            default: None,
         };
 
         assert!(Value::Enum(0, "spades".to_string(), None).validate(&schema));
@@ -974,6 +2515,7 @@ mod tests {
                 "clubs".to_string(),
                 "spades".to_string(),
             ],
+            default: None,
         };
 
         assert!(!Value::Enum(0, "spades".to_string(), None).validate(&other_schema));
@@ -993,22 +2535,24 @@ mod tests {
             doc: None,
             fields: vec![
                 RecordField {
-                    name: "a".to_string(),
+                    name: "a".into(),
                     doc: None,
+                    aliases: None,
                     default: None,
                     schema: Schema::Long,
                     order: RecordFieldOrder::Ascending,
                     position: 0,
-                    index: false,
+                    metadata: Default::default(),
                 },
                 RecordField {
-                    name: "b".to_string(),
+                    name: "b".into(),
                     doc: None,
+                    aliases: None,
                     default: None,
                     schema: Schema::String,
                     order: RecordFieldOrder::Ascending,
                     position: 1,
-                    index: false,
+                    metadata: Default::default(),
                 },
             ],
             lookup: HashMap::new(),
@@ -1016,37 +2560,37 @@ mod tests {
 
         assert!(
             Value::Record(vec![
-                ("a".to_string(), Value::Long(42i64, None)),
-                ("b".to_string(), Value::String("foo".to_string(), None)),
+                ("a".into(), Value::Long(42i64, None)),
+                ("b".into(), Value::String("foo".to_string(), None)),
             ], None).validate(&schema)
         );
 
         assert!(
             !Value::Record(vec![
-                ("b".to_string(), Value::String("foo".to_string(), None)),
-                ("a".to_string(), Value::Long(42i64, None)),
+                ("b".into(), Value::String("foo".to_string(), None)),
+                ("a".into(), Value::Long(42i64, None)),
             ], None).validate(&schema)
         );
 
         assert!(
             !Value::Record(vec![
-                ("a".to_string(), Value::Boolean(false, None)),
-                ("b".to_string(), Value::String("foo".to_string(), None)),
+                ("a".into(), Value::Boolean(false, None)),
+                ("b".into(), Value::String("foo".to_string(), None)),
             ], None).validate(&schema)
         );
 
         assert!(
             !Value::Record(vec![
-                ("a".to_string(), Value::Long(42i64, None)),
-                ("c".to_string(), Value::String("foo".to_string(), None)),
+                ("a".into(), Value::Long(42i64, None)),
+                ("c".into(), Value::String("foo".to_string(), None)),
             ], None).validate(&schema)
         );
 
         assert!(
             !Value::Record(vec![
-                ("a".to_string(), Value::Long(42i64, None)),
-                ("b".to_string(), Value::String("foo".to_string(), None)),
-                ("c".to_string(), Value::Null),
+                ("a".into(), Value::Long(42i64, None)),
+                ("b".into(), Value::String("foo".to_string(), None)),
+                ("c".into(), Value::Null),
             ], None).validate(&schema)
         );
     }
@@ -1065,4 +2609,868 @@ mod tests {
         let value = Value::Array(vec![Value::Int(2000, None), Value::Int(-42, None)], None);
         assert!(value.resolve(&Schema::Bytes).is_err());
     }
+
+    #[test]
+    fn project_record() {
+        let reader = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "outer",
+                "fields": [
+                    {"name": "b", "type": {"type": "array", "items": {
+                        "type": "record",
+                        "name": "inner",
+                        "fields": [
+                            {"name": "y", "type": "string"},
+                            {"name": "z", "type": ["null", "long"], "default": null}
+                        ]
+                    }}},
+                    {"name": "c", "type": "int", "default": 42}
+                ]
+            }
+        "#,
+        ).unwrap();
+
+        let value = Value::record(vec![
+            ("a", 1i64.avro()),
+            (
+                "b",
+                Value::Array(
+                    vec![Value::record(vec![("x", 1i64.avro()), ("y", "foo".avro())])],
+                    None,
+                ),
+            ),
+        ]);
+
+        let projected = value.project(&reader).unwrap();
+        assert_eq!(
+            projected,
+            Value::record(vec![
+                (
+                    "b",
+                    Value::Array(
+                        vec![Value::record(vec![
+                            ("y", "foo".avro()),
+                            ("z", Value::Union(Box::new(Value::Null), None)),
+                        ])],
+                        None,
+                    ),
+                ),
+                ("c", 42i32.avro()),
+            ])
+        );
+        assert!(projected.validate(&reader));
+
+        assert!(Value::record(vec![("c", 1i32)]).project(&reader).is_err());
+    }
+
+    #[test]
+    fn resolve_unknown_fields() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": "long"},
+                    {"name": "extras", "type": {"type": "map", "values": "string"}, "default": {}}
+                ]
+            }
+        "#,
+        ).unwrap();
+        let value = || Value::record(vec![("a", 1i64.avro()), ("b", "foo".avro())]);
+
+        let resolved = value().resolve(&schema).unwrap();
+        assert_eq!(resolved.field("extras"), Some(&Value::Map(HashMap::new(), None)));
+        assert!(value()
+            .resolve_with_policy(&schema, &UnknownFieldPolicy::Error)
+            .is_err());
+
+        let collected = value()
+            .resolve_with_policy(&schema, &UnknownFieldPolicy::CollectInto("extras".to_string()))
+            .unwrap();
+        let mut extras = HashMap::new();
+        extras.insert("b".to_string(), "foo".avro());
+        assert_eq!(
+            collected,
+            Value::record(vec![("a", 1i64.avro()), ("extras", Value::Map(extras, None))])
+        );
+    }
+
+    #[test]
+    fn resolve_record_field_order() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": "long"},
+                    {"name": "b", "type": "string"},
+                    {"name": "c", "type": "long", "aliases": ["d"]}
+                ]
+            }
+        "#,
+        ).unwrap();
+        let expected = Value::record(vec![
+            ("a", 1i64.avro()),
+            ("b", "x".avro()),
+            ("c", 2i64.avro()),
+        ]);
+        assert_eq!(expected.clone().resolve(&schema).unwrap(), expected);
+
+        let value = Value::record(vec![("d", 2i32.avro()), ("b", "x".avro()), ("a", 1i32.avro())]);
+        assert_eq!(value.resolve(&schema).unwrap(), expected);
+
+        let mut items = HashMap::new();
+        items.insert("a".to_owned(), 1i64.avro());
+        items.insert("b".to_owned(), "x".avro());
+        items.insert("c".to_owned(), 2i64.avro());
+        assert_eq!(Value::Map(items, None).resolve(&schema).unwrap(), expected);
+    }
+
+    #[test]
+    fn resolve_counter() {
+        let schema = Schema::parse_str(r#""counter""#).unwrap();
+        assert_eq!(schema, Schema::Counter);
+        assert_eq!(Value::Int(3, None).resolve(&schema).unwrap(), Value::Counter(3, None));
+        assert_eq!(Value::Long(3, None).resolve(&schema).unwrap(), Value::Counter(3, None));
+        assert!(Value::String("3".to_owned(), None).resolve(&schema).is_err());
+
+        let counter = Value::Counter(3, None);
+        assert_eq!(counter.json(), json!(3));
+        let json = counter.to_avro_json(&schema).unwrap();
+        assert_eq!(Value::from_avro_json(&json, &schema).unwrap(), counter);
+    }
+
+    #[test]
+    fn resolve_bounded_list() {
+        let schema = Schema::parse_str(
+            r#"{"type": "bounded_list", "items": "long", "max_length": 2}"#,
+        ).unwrap();
+        let value = vec![1i32, 2i32, 3i32].avro().resolve(&schema).unwrap();
+        assert_eq!(value, vec![2i64, 3i64].avro());
+        assert!(value.validate(&schema));
+        assert!(!vec![1i64, 2i64, 3i64].avro().validate(&schema));
+
+        let schema = Schema::parse_str(
+            r#"{"type": "bounded_list", "items": "long", "max_length": 2, "truncate": "tail"}"#,
+        ).unwrap();
+        let value = vec![1i64, 2i64, 3i64].avro().resolve(&schema).unwrap();
+        assert_eq!(value, vec![1i64, 2i64].avro());
+    }
+
+    #[test]
+    fn resolve_topk() {
+        let schema = Schema::TopK(2);
+        let mut counts = HashMap::new();
+        counts.insert("a".to_owned(), Value::Long(3, None));
+        counts.insert("b".to_owned(), Value::Int(1, None));
+        counts.insert("c".to_owned(), Value::Counter(2, None));
+        let value = Value::Map(counts, None).resolve(&schema).unwrap();
+        assert_eq!(value.json(), json!({"a": 3, "c": 2}));
+
+        let mut topk = TopK::new(3);
+        topk.insert("x", 1);
+        topk.insert("y", 2);
+        topk.insert("z", 3);
+        let value = Value::TopK(Box::new(topk), None).resolve(&schema).unwrap();
+        assert_eq!(value.json(), json!({"y": 2, "z": 3}));
+        assert!(value.validate(&schema));
+
+        let json = value.to_avro_json(&schema).unwrap();
+        assert_eq!(Value::from_avro_json(&json, &schema).unwrap(), value);
+        assert!(Value::Long(1, None).resolve(&schema).is_err());
+    }
+
+    #[test]
+    fn resolve_date_unit() {
+        assert_eq!(
+            Value::Long(1_600_000_000, None).resolve(&Schema::Date(DateUnit::Seconds)).unwrap(),
+            Value::Date(1_600_000_000_000, None)
+        );
+        assert_eq!(
+            Value::Long(1_600_000_000_000_000, None).resolve(&Schema::Date(DateUnit::Micros)).unwrap(),
+            Value::Date(1_600_000_000_000, None)
+        );
+        // dates are already in milliseconds, whatever the unit of their writer schema
+        assert_eq!(
+            Value::Date(1000, None).resolve(&Schema::Date(DateUnit::Seconds)).unwrap(),
+            Value::Date(1000, None)
+        );
+    }
+
+    #[test]
+    fn resolve_optional_default() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": {"type": "optional", "value": "long"}, "default": 5},
+                    {"name": "b", "type": {"type": "optional", "value": "string"}, "default": null},
+                    {"name": "c", "type": {"type": "optional", "value": "long"}}
+                ]
+            }
+        "#,
+        ).unwrap();
+
+        let value = Value::record(vec![("c", Value::Optional(None, None))]);
+        assert_eq!(
+            value.resolve(&schema).unwrap(),
+            Value::record(vec![
+                ("a", Value::Optional(Some(Box::new(5i64.avro())), None)),
+                ("b", Value::Optional(None, None)),
+                ("c", Value::Optional(None, None)),
+            ])
+        );
+
+        // fields without a default are still required
+        assert!(Value::record(vec![("a", 1i64)]).resolve(&schema).is_err());
+    }
+
+    #[test]
+    fn resolve_field_aliases() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "count", "type": "long", "aliases": ["total", "n"], "default": 0}
+                ]
+            }
+        "#,
+        ).unwrap();
+
+        let value = Value::record(vec![("n", 3i64)]);
+        assert_eq!(value.resolve(&schema).unwrap(), Value::record(vec![("count", 3i64)]));
+
+        // the current name takes precedence over the aliases
+        let value = Value::record(vec![("total", 2i64), ("count", 1i64)]);
+        assert_eq!(value.resolve(&schema).unwrap(), Value::record(vec![("count", 1i64)]));
+
+        let value = Value::record(vec![("other", 3i64)]);
+        assert_eq!(value.resolve(&schema).unwrap(), Value::record(vec![("count", 0i64)]));
+    }
+
+    #[test]
+    fn resolve_field_metadata() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "email", "type": "string", "pii": true, "ttl_ms": 1000,
+                     "tags": ["contact"]},
+                    {"name": "id", "type": "long", "index": true},
+                    {"name": "n", "type": "long"}
+                ]
+            }
+        "#,
+        ).unwrap();
+        let metadata = match schema {
+            Schema::Record { ref fields, .. } => fields[0].metadata.clone(),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            *metadata,
+            FieldMetadata {
+                index: false,
+                pii: true,
+                ttl_millis: Some(1000),
+                tags: vec!["contact".to_owned()],
+            }
+        );
+
+        let value = Value::record(vec![
+            ("email", "a@b.c".avro()),
+            ("id", 1i64.avro()),
+            ("n", 2i64.avro()),
+        ]);
+        let resolved = match value.resolve(&schema).unwrap() {
+            Value::Record(fields, _) => fields,
+            other => panic!("expected record, got {:?}", other),
+        };
+        match resolved[0].1 {
+            Value::String(_, Some(ref setting)) => {
+                assert!(Arc::ptr_eq(&setting.metadata, &metadata))
+            },
+            ref other => panic!("expected annotated string, got {:?}", other),
+        }
+        match resolved[1].1 {
+            Value::Long(1, Some(ref setting)) => assert!(setting.metadata.index),
+            ref other => panic!("expected annotated long, got {:?}", other),
+        }
+        assert_eq!(resolved[2].1, Value::Long(2, None));
+
+        let raw = serde_json::to_string(&schema).unwrap();
+        assert_eq!(Schema::parse_str(&raw).unwrap(), schema);
+        assert!(Schema::parse_str(
+            r#"{"type": "record", "name": "test", "fields": [
+                {"name": "a", "type": "long", "ttl_ms": -1}
+            ]}"#
+        ).is_err());
+    }
+
+    #[test]
+    fn resolve_date_with_options() {
+        let value = Value::String("01/01/1970 00:00:01".to_owned(), None);
+        assert!(value.clone().resolve(&Schema::Date(DateUnit::Millis)).is_err());
+
+        let options = ResolveOptions {
+            dates: DateParsing {
+                formats: vec!["%d/%m/%Y %H:%M:%S".to_owned()],
+                ..DateParsing::default()
+            },
+            ..ResolveOptions::default()
+        };
+        assert_eq!(
+            value.resolve_with_options(&Schema::Date(DateUnit::Millis), &options).unwrap(),
+            Value::Date(1000, None)
+        );
+        assert_eq!(
+            Value::String("1970-01-01T00:00:01Z".to_owned(), None).resolve(&Schema::Date(DateUnit::Millis)).unwrap(),
+            Value::Date(1000, None)
+        );
+    }
+
+    #[test]
+    fn resolve_numeric_promotion() {
+        let options = |numbers| ResolveOptions {
+            numbers,
+            ..ResolveOptions::default()
+        };
+        let resolve = |value: Value, schema: &Schema, numbers| {
+            value.resolve_with_options(schema, &options(numbers)).ok()
+        };
+        let big = i64::from(i32::MAX) + 1;
+
+        // narrowing is silent by default
+        assert_eq!(
+            resolve(Value::Long(big, None), &Schema::Int, NumericPromotion::Lossy),
+            Some(Value::Int(i32::MIN, None))
+        );
+        assert_eq!(resolve(Value::Long(1, None), &Schema::Int, NumericPromotion::Widening), None);
+        assert_eq!(
+            resolve(Value::Long(1, None), &Schema::Int, NumericPromotion::Exact),
+            Some(Value::Int(1, None))
+        );
+        assert_eq!(resolve(Value::Long(big, None), &Schema::Int, NumericPromotion::Exact), None);
+
+        assert_eq!(
+            resolve(Value::Double(0.1, None), &Schema::Float, NumericPromotion::Widening),
+            None
+        );
+        assert_eq!(
+            resolve(Value::Double(0.1, None), &Schema::Float, NumericPromotion::Exact),
+            None
+        );
+        assert_eq!(
+            resolve(Value::Double(0.5, None), &Schema::Float, NumericPromotion::Exact),
+            Some(Value::Float(0.5, None))
+        );
+        assert_eq!(
+            resolve(Value::Long(1 << 53 | 1, None), &Schema::Double, NumericPromotion::Widening),
+            Some(Value::Double((1u64 << 53) as f64, None))
+        );
+        assert_eq!(
+            resolve(Value::Long(1 << 53 | 1, None), &Schema::Double, NumericPromotion::Exact),
+            None
+        );
+        assert_eq!(
+            resolve(Value::Int(1 << 24 | 1, None), &Schema::Float, NumericPromotion::Exact),
+            None
+        );
+        assert_eq!(
+            resolve(Value::Float(0.1, None), &Schema::Double, NumericPromotion::Exact),
+            Some(Value::Double(f64::from(0.1f32), None))
+        );
+
+        // values of the expected type and other types are left to the resolution
+        assert_eq!(
+            resolve(Value::Int(7, None), &Schema::Int, NumericPromotion::Widening),
+            Some(Value::Int(7, None))
+        );
+        let error = Value::String("7".to_owned(), None)
+            .resolve_with_options(&Schema::Int, &options(NumericPromotion::Exact))
+            .unwrap_err();
+        assert!(error.to_string().contains("Int expected"));
+    }
+
+    #[test]
+    fn resolve_typed_set() {
+        let ids = Value::Array(vec![Value::Int(1, None), Value::Long(2, None)], None)
+            .resolve(&Schema::Set(Box::new(Schema::Long)))
+            .unwrap();
+        let expected = vec![SetItem::Long(1), SetItem::Long(2)].into_iter().collect();
+        assert_eq!(ids, Value::Set(expected, None));
+
+        let suits = Schema::parse_str(
+            r#"{"type": "set", "items": {"type": "enum", "name": "suit", "symbols": ["hearts", "spades"]}}"#,
+        ).unwrap();
+        let items = vec![SetItem::from("spades")].into_iter().collect();
+        let resolved = Value::Set(items, None).resolve(&suits).unwrap();
+        let expected = vec![SetItem::Enum(1, "spades".to_owned())].into_iter().collect();
+        assert_eq!(resolved, Value::Set(expected, None));
+        assert!(resolved.validate(&suits));
+
+        let items = vec![SetItem::from("clubs")].into_iter().collect();
+        assert!(Value::Set(items, None).resolve(&suits).is_err());
+    }
+
+    #[test]
+    fn resolve_enum_default() {
+        let schema = Schema::parse_str(
+            r#"{"type": "enum", "name": "suit", "symbols": ["hearts", "spades", "unknown"],
+                "default": "unknown"}"#,
+        ).unwrap();
+
+        assert_eq!(
+            Value::Enum(1, "spades".to_owned(), None).resolve(&schema).unwrap(),
+            Value::Enum(1, "spades".to_owned(), None)
+        );
+        assert_eq!(
+            Value::Enum(3, "clubs".to_owned(), None).resolve(&schema).unwrap(),
+            Value::Enum(2, "unknown".to_owned(), None)
+        );
+        assert_eq!(
+            "clubs".avro().resolve(&schema).unwrap(),
+            Value::Enum(2, "unknown".to_owned(), None)
+        );
+    }
+
+    #[test]
+    fn resolve_lru_set_evicts() {
+        let mut items = HashMap::new();
+        items.insert("old".to_string(), LruValue::new(1, 10));
+        items.insert("new".to_string(), LruValue::new(3, 1));
+        items.insert("mid".to_string(), LruValue::new(2, 5));
+
+        let resolved = Value::LruSet(Box::new(items.clone().into()), LruLimit::Count(10), None)
+            .resolve(&Schema::LruSet(LruLimit::Count(2), None))
+            .unwrap();
+        items.remove("old");
+        let expected = Value::LruSet(Box::new(items.clone().into()), LruLimit::Count(2), None);
+        assert_eq!(resolved, expected);
+
+        let resolved = Value::LruSet(Box::new(items.clone().into()), LruLimit::Count(2), None)
+            .resolve_at(&Schema::LruSet(LruLimit::Days(1), None), &UnknownFieldPolicy::Ignore, 3)
+            .unwrap();
+        assert_eq!(resolved, Value::LruSet(Box::new(items.into()), LruLimit::Days(1), None));
+    }
+
+    #[test]
+    fn resolve_lru_set_expires() {
+        let hour = 60 * 60 * 1000;
+        let mut items = HashMap::new();
+        items.insert("old".to_string(), LruValue::new(0, 1));
+        items.insert("new".to_string(), LruValue::new(2 * hour, 1));
+        let value = Value::LruSet(Box::new(items.clone().into()), LruLimit::Hour(1), None);
+
+        let resolved = value
+            .clone()
+            .resolve_at(
+                &Schema::LruSet(LruLimit::Hour(1), None),
+                &UnknownFieldPolicy::Ignore,
+                2 * hour,
+            )
+            .unwrap();
+        items.remove("old");
+        assert_eq!(resolved, Value::LruSet(Box::new(items.into()), LruLimit::Hour(1), None));
+
+        let resolved = value
+            .clone()
+            .resolve_at(
+                &Schema::LruSet(LruLimit::Days(1), None),
+                &UnknownFieldPolicy::Ignore,
+                2 * hour,
+            )
+            .unwrap();
+        let mut kept = HashMap::new();
+        kept.insert("old".to_string(), LruValue::new(0, 1));
+        kept.insert("new".to_string(), LruValue::new(2 * hour, 1));
+        assert_eq!(resolved, Value::LruSet(Box::new(kept.into()), LruLimit::Days(1), None));
+
+        // relatively to the current time, every entry is expired
+        let resolved = value.resolve(&Schema::LruSet(LruLimit::Days(1), None)).unwrap();
+        assert_eq!(resolved, Value::LruSet(Box::default(), LruLimit::Days(1), None));
+        assert_eq!(LruLimit::Days(1).ttl_millis(), Some(24 * hour));
+        assert_eq!(LruLimit::Count(1).ttl_millis(), None);
+    }
+
+    #[test]
+    fn resolve_lru_set_entries() {
+        let mut items = HashMap::new();
+        items.insert("expired".to_string(), LruValue::new(0, 1));
+        items.insert("old".to_string(), LruValue::new(1000, 1));
+        items.insert("new".to_string(), LruValue::new(2000, 1));
+        let schema =
+            Schema::parse_str(r#"{"type": "lru_set", "max_entries": 1, "ttl_ms": 1500}"#).unwrap();
+
+        let resolved = Value::LruSet(Box::new(items.into()), LruLimit::Count(10), None)
+            .resolve_at(&schema, &UnknownFieldPolicy::Ignore, 2000)
+            .unwrap();
+        match resolved {
+            Value::LruSet(items, _, _) => {
+                assert_eq!(items.keys().collect::<Vec<_>>(), vec!["new"]);
+            },
+            other => panic!("LruSet expected, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn resolve_lru_set_decay() {
+        let hour = 60 * 60 * 1000;
+        let mut items = HashMap::new();
+        items.insert("stale".to_string(), LruValue::new(0, 4));
+        items.insert("active".to_string(), LruValue::new(0, 1000));
+        items.insert("recent".to_string(), LruValue::new(4 * hour, 100));
+        let schema = Schema::parse_str(
+            r#"{"type": "lru_set", "limit": "10", "half_life": "1 hour"}"#,
+        ).unwrap();
+        let decay = match schema {
+            Schema::LruSet(_, Some(decay)) => decay,
+            ref other => panic!("LruSet with decay expected, got {:?}", other),
+        };
+
+        // "stale" decayed to 4 / 2^4, which rounds to zero; counts are kept as of their access
+        // time, not to decay them twice on the next resolution
+        let resolved = Value::LruSet(Box::new(items.clone().into()), LruLimit::Count(10), None)
+            .resolve_at(&schema, &UnknownFieldPolicy::Ignore, 4 * hour)
+            .unwrap();
+        items.remove("stale");
+        let expected = Value::LruSet(Box::new(items.clone().into()), LruLimit::Count(10), None);
+        assert_eq!(resolved, expected);
+        let resolved = resolved.resolve_at(&schema, &UnknownFieldPolicy::Ignore, 4 * hour);
+        assert_eq!(resolved.unwrap(), expected);
+
+        // decayed to the reference time, the larger but older count ranks below the recent one
+        assert_eq!(items.get("active").unwrap().count_at(decay, 4 * hour), 63);
+        assert_eq!(items.get("recent").unwrap().count_at(decay, 4 * hour), 100);
+    }
+
+    #[test]
+    fn resolve_lru_set_malformed_access_time() {
+        use crate::decode::decode;
+        use crate::encode::encode_to_vec;
+        use crate::merge::MergePolicy;
+
+        // access times decoded from malformed input, as far as possible from the reference time
+        let writer = Schema::LruSet(LruLimit::Count(10), None);
+        let mut items = HashMap::new();
+        items.insert("min".to_string(), LruValue::new(i64::MIN, i64::MAX));
+        items.insert("max".to_string(), LruValue::new(i64::MAX, i64::MIN));
+        let encoded = encode_to_vec(
+            &Value::LruSet(Box::new(items.into()), LruLimit::Count(10), None),
+            &writer,
+        );
+        let reader = Schema::parse_str(
+            r#"{"type": "lru_set", "limit": "10", "half_life": "1 hour"}"#,
+        ).unwrap();
+        for &now in &[i64::MIN, 0, i64::MAX] {
+            let value = decode(&writer, &mut &encoded[..]).unwrap();
+            let resolved = value.clone().resolve_at(&reader, &UnknownFieldPolicy::Ignore, now);
+            assert!(resolved.is_ok());
+            let policy = MergePolicy { now: Some(now), ..MergePolicy::default() };
+            assert!(value.clone().merge(value, &reader, policy).is_ok());
+        }
+    }
+
+    #[test]
+    fn resolve_lru_set_byte_budget() {
+        let mut items = HashMap::new();
+        items.insert("a".repeat(100), LruValue::new(1, 1));
+        items.insert("b".to_string(), LruValue::new(2, 1));
+        items.insert("c".to_string(), LruValue::new(3, 1));
+        let budget = 2 * (1 + LruLimit::ENTRY_OVERHEAD) as u32 + 10;
+
+        let resolved = Value::LruSet(Box::new(items.clone().into()), LruLimit::Count(10), None)
+            .resolve(&Schema::LruSet(LruLimit::Bytes(budget), None))
+            .unwrap();
+        items.remove(&"a".repeat(100));
+        assert_eq!(resolved, Value::LruSet(Box::new(items.into()), LruLimit::Bytes(budget), None));
+    }
+
+    #[test]
+    fn to_avro_numbers() {
+        assert_eq!(7i8.avro(), Value::Int(7, None));
+        assert_eq!(7i16.avro(), Value::Int(7, None));
+        assert_eq!(7u32.avro(), Value::Long(7, None));
+        assert_eq!(Value::try_from(7u64).unwrap(), Value::Long(7, None));
+        assert!(Value::try_from(u64::MAX).is_err());
+    }
+
+    #[test]
+    fn to_avro_collections() {
+        assert_eq!(
+            vec![1i32, 2i32].avro(),
+            Value::Array(vec![Value::Int(1, None), Value::Int(2, None)], None)
+        );
+        assert_eq!([1u8, 2u8].avro(), Value::Fixed(2, vec![1, 2], None));
+        assert_eq!((&"foo".to_string()).avro(), Value::String("foo".to_string(), None));
+
+        let mut map = BTreeMap::new();
+        map.insert("a".to_string(), 1i64);
+        let mut expected = HashMap::new();
+        expected.insert("a".to_string(), Value::Long(1, None));
+        assert_eq!(map.avro(), Value::Map(expected, None));
+    }
+
+    #[test]
+    fn to_avro_record_from_tuples() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": "long"},
+                    {"name": "b", "type": "long"}
+                ]
+            }
+        "#,
+        ).unwrap();
+
+        let value = Value::record(vec![("a", 1i64), ("b", 2i64)]);
+        assert!(value.validate(&schema));
+
+        let mut record = Record::new(&schema).unwrap();
+        record.put_all(vec![("b", 2i64), ("a", 1i64)]);
+        assert_eq!(record.avro(), value);
+    }
+
+    #[test]
+    fn record_try_put() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": "long"}
+                ]
+            }
+        "#,
+        ).unwrap();
+
+        let mut record = Record::new(&schema).unwrap();
+        assert_eq!(record.try_put("a", "foo"), Ok(()));
+        assert_eq!(
+            record.try_put("b", 1i64),
+            Err(PutError::UnknownField("b".to_string()))
+        );
+
+        let mut record = Record::with_validation(&schema).unwrap();
+        assert_eq!(
+            record.try_put("a", "foo"),
+            Err(PutError::InvalidValue {
+                field: "a".to_string(),
+                value: Value::String("foo".to_string(), None),
+            })
+        );
+        assert_eq!(record.fields[0].1, Value::Null);
+        assert_eq!(record.try_put("a", 1i64), Ok(()));
+        assert_eq!(record.avro(), Value::record(vec![("a", 1i64)]));
+    }
+
+    #[test]
+    fn optional_helpers() {
+        let present = Value::Optional(Some(Box::new(1i64.avro())), None);
+        assert_eq!(present.as_option(), Some(&Value::Long(1, None)));
+        assert_eq!(Value::Optional(None, None).as_option(), None);
+        assert_eq!(None::<i64>.avro().as_option(), None);
+        assert_eq!(Some(1i64).avro().as_option(), Some(&Value::Long(1, None)));
+
+        let schema = Schema::Optional(Box::new(Schema::Long));
+        assert_eq!(present.unwrap_or_default(&schema), Value::Long(1, None));
+        assert_eq!(
+            Value::Optional(None, None).unwrap_or_default(&schema),
+            Value::Long(0, None)
+        );
+        assert_eq!(
+            None::<String>.avro().unwrap_or_default(&Schema::String),
+            Value::String(String::new(), None)
+        );
+    }
+
+    #[test]
+    fn optional_from_option() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": {"type": "optional", "value": "long"}},
+                    {"name": "b", "type": {"type": "optional", "value": "long"}}
+                ]
+            }
+        "#,
+        ).unwrap();
+
+        let mut record = Record::with_validation(&schema).unwrap();
+        assert_eq!(record.try_put("a", Some(1i64)), Ok(()));
+        assert_eq!(record.try_put("b", None::<i64>), Ok(()));
+        assert_eq!(
+            record.fields,
+            vec![
+                ("a".into(), Value::Optional(Some(Box::new(Value::Long(1, None))), None)),
+                ("b".into(), Value::Optional(None, None)),
+            ]
+        );
+
+        let inner = Schema::Optional(Box::new(Schema::Long));
+        assert_eq!(
+            None::<i64>.avro().resolve(&inner).unwrap(),
+            Value::Optional(None, None)
+        );
+    }
+
+    #[test]
+    fn record_field_access() {
+        let record = Value::record(vec![("a", 1i64.avro()), ("b", "foo".avro())]);
+
+        assert_eq!(record.field("a"), Some(&Value::Long(1, None)));
+        assert_eq!(record.field("c"), None);
+        assert_eq!(Value::Long(1, None).field("a"), None);
+
+        let nested = Value::Optional(
+            Some(Box::new(Value::Union(Box::new(record.clone()), None))),
+            None,
+        );
+        assert_eq!(nested.field("b"), Some(&Value::String("foo".to_string(), None)));
+
+        let fields = nested.fields_map().unwrap();
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields["a"], &Value::Long(1, None));
+        assert!(Value::Optional(None, None).fields_map().is_none());
+    }
+
+    #[test]
+    fn try_from_primitives() {
+        assert_eq!(i32::try_from(Value::Int(42, None)).unwrap(), 42);
+        assert_eq!(i64::try_from(Value::Int(42, None)).unwrap(), 42i64);
+        assert_eq!(f64::try_from(Value::Float(1.5, None)).unwrap(), 1.5f64);
+        assert_eq!(bool::try_from(Value::Boolean(true, None)).unwrap(), true);
+        assert_eq!(
+            String::try_from(Value::Enum(1, "spades".to_string(), None)).unwrap(),
+            "spades".to_string()
+        );
+        assert_eq!(
+            Vec::<u8>::try_from(Value::Fixed(2, vec![1, 2], None)).unwrap(),
+            vec![1u8, 2u8]
+        );
+
+        assert!(i32::try_from(Value::Long(42, None)).is_err());
+        assert!(String::try_from(Value::Null).is_err());
+    }
+
+    #[test]
+    fn try_from_collections() {
+        let array = Value::Array(vec![Value::Long(1, None), Value::Long(2, None)], None);
+        assert_eq!(Vec::<i64>::try_from(array).unwrap(), vec![1i64, 2i64]);
+
+        let mut items = HashMap::new();
+        items.insert("a".to_string(), Value::String("foo".to_string(), None));
+        let map = HashMap::<String, String>::try_from(Value::Map(items, None)).unwrap();
+        assert_eq!(map.get("a"), Some(&"foo".to_string()));
+
+        let mut set = HashSet::new();
+        set.insert("foo".to_string());
+        let items = set.iter().cloned().map(SetItem::from).collect::<HashSet<_>>();
+        assert_eq!(HashSet::<String>::try_from(Value::Set(items, None)).unwrap(), set);
+        let mut ids = HashSet::new();
+        ids.insert(SetItem::Long(1));
+        assert!(HashSet::<String>::try_from(Value::Set(ids.clone(), None)).is_err());
+        assert_eq!(HashSet::<SetItem>::try_from(Value::Set(ids.clone(), None)).unwrap(), ids);
+
+        let mixed = Value::Array(vec![Value::Long(1, None), Value::Null], None);
+        assert!(Vec::<i64>::try_from(mixed).is_err());
+    }
+
+    #[test]
+    fn try_from_nullable() {
+        assert_eq!(Option::<i32>::try_from(Value::Null).unwrap(), None);
+        assert_eq!(
+            Option::<i32>::try_from(Value::Union(Box::new(Value::Null), None)).unwrap(),
+            None
+        );
+        assert_eq!(
+            Option::<i32>::try_from(Value::Union(Box::new(Value::Int(3, None)), None)).unwrap(),
+            Some(3)
+        );
+        assert_eq!(
+            Option::<String>::try_from(Value::Optional(
+                Some(Box::new(Value::String("foo".to_string(), None))),
+                None
+            )).unwrap(),
+            Some("foo".to_string())
+        );
+        assert_eq!(Option::<String>::try_from(Value::Optional(None, None)).unwrap(), None);
+        assert!(Option::<i32>::try_from(Value::Boolean(true, None)).is_err());
+    }
+
+    #[test]
+    fn value_size() {
+        // lru sets and topk summaries are boxed, so that no variant is larger than a map
+        let map = std::mem::size_of::<HashMap<String, Value>>();
+        assert!(std::mem::size_of::<Value>() <= map + 2 * std::mem::size_of::<usize>());
+    }
+
+    #[test]
+    fn into_json_and_write_json() {
+        let mut map = HashMap::new();
+        map.insert("k".to_owned(), Value::Float(0.5, None));
+        let mut set = HashSet::new();
+        set.insert(SetItem::String("s".to_owned()));
+        let mut lru = HashMap::new();
+        lru.insert("l".to_owned(), LruValue::new(10, 2));
+        let value = Value::record(vec![
+            ("a", Value::Long(1, None)),
+            ("b", Value::String("foo".to_owned(), None)),
+            ("c", Value::Array(vec![Value::Bytes(vec![1, 2], None), Value::Null], None)),
+            ("d", Value::Map(map, None)),
+            ("e", Value::Enum(1, "y".to_owned(), None)),
+            ("f", Value::Union(Box::new(Value::Boolean(true, None)), None)),
+            ("g", Value::Optional(None, None)),
+            ("h", Value::Set(set, None)),
+            ("i", Value::LruSet(Box::new(lru.into()), LruLimit::Count(1), None)),
+        ]);
+        let expected = value.json();
+
+        let mut buffer = Vec::new();
+        value.write_json(&mut buffer).unwrap();
+        assert_eq!(serde_json::from_slice::<JsonValue>(&buffer).unwrap(), expected);
+        assert_eq!(value.into_json(), expected);
+    }
+
+    #[test]
+    fn json_with_non_finite_floats() {
+        let value = Value::Array(
+            vec![
+                Value::Double(f64::NAN, None),
+                Value::Float(f32::INFINITY, None),
+                Value::Double(f64::NEG_INFINITY, None),
+                Value::Double(1.5, None),
+            ],
+            None,
+        );
+        assert_eq!(value.json(), json!([null, null, null, 1.5]));
+        assert_eq!(value.json_with(NonFiniteFloats::Null).unwrap(), value.json());
+        assert_eq!(
+            value.json_with(NonFiniteFloats::String).unwrap(),
+            json!(["NaN", "Infinity", "-Infinity", 1.5])
+        );
+        let error = value.json_with(NonFiniteFloats::Error).unwrap_err();
+        assert!(error.to_string().contains("NaN can't be converted to JSON"));
+
+        let mut buffer = Vec::new();
+        value.write_json_with(&mut buffer, NonFiniteFloats::String).unwrap();
+        assert_eq!(buffer, br#"["NaN","Infinity","-Infinity",1.5]"#.to_vec());
+        assert!(value.write_json_with(&mut Vec::new(), NonFiniteFloats::Error).is_err());
+    }
 }
@@ -1,10 +1,13 @@
-use std::io::Read;
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::io::{self, Read};
 use std::sync::{Once, ONCE_INIT};
 use std::i64;
 
-use failure::Error;
 use serde_json::{Map, Value};
 
+use crate::error::AvroError;
+
 /// Maximum number of bytes that can be allocated when decoding
 /// Avro-encoded values. This is a protection against ill-formed
 /// data, whose length field might be interpreted as enourmous.
@@ -12,39 +15,101 @@ use serde_json::{Map, Value};
 pub static mut MAX_ALLOCATION_BYTES: usize = 512 * 1024 * 1024;
 static MAX_ALLOCATION_BYTES_ONCE: Once = ONCE_INIT;
 
-/// Describes errors happened trying to allocate too many bytes
-#[derive(Fail, Debug)]
-#[fail(display = "Allocation error: {}", _0)]
-pub struct AllocationError(String);
+thread_local! {
+    // options of the reader or datum being decoded on this thread
+    static DECODE_OPTIONS: Cell<DecodeOptions> = const { Cell::new(DecodeOptions::DEFAULT) };
+    static NESTING_DEPTH: Cell<usize> = const { Cell::new(0) };
+    // entries of lru sets evicted on this thread and not yet reported to `Metrics`
+    static EVICTIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Describes how decoding handles the `string` values which aren't valid UTF-8, as written by
+/// legacy producers of Latin-1 strings for instance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InvalidUtf8 {
+    /// Fail the decoding with an `AvroError::Decode` giving the offset of the first invalid byte.
+    Error,
+    /// Replace the invalid sequences with `U+FFFD REPLACEMENT CHARACTER`.
+    ReplaceLossy,
+    /// Decode the string as a `bytes` value. Map keys, which are always strings, are replaced as
+    /// with `ReplaceLossy`.
+    FallbackToBytes,
+}
+
+/// Limits checked when decoding Avro-encoded values, as a protection against ill-formed data or
+/// data from untrusted producers, and handling of the strings written by legacy producers.
+/// Given to a [Reader](struct.Reader.html) with
+/// [`set_decode_options`](struct.Reader.html#method.set_decode_options), or to
+/// [`from_avro_datum_with_options`](fn.from_avro_datum_with_options.html). Values decoded with
+/// the functions of the `decode` module are decoded with the default options.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeOptions {
+    /// Maximum number of entries of a decoded `set` or `lru_set` value.
+    pub max_set_entries: usize,
+    /// Maximum number of bytes taken by the string and bytes items of a decoded `set` value, or
+    /// by the keys of a decoded `lru_set` value.
+    pub max_set_key_bytes: usize,
+    /// Maximum length of a decoded `string` or `bytes` value, map keys included.
+    pub max_bytes_len: usize,
+    /// Maximum number of items of a decoded `array` value, or of entries of a decoded `map` value.
+    pub max_collection_entries: usize,
+    /// Maximum number of arrays, maps, records, unions and optionals nested in one another in a
+    /// decoded value.
+    pub max_nesting_depth: usize,
+    /// Maximum number of bytes of a block read from an Avro file, before it is decompressed.
+    pub max_block_bytes: usize,
+    /// How decoded `string` values which aren't valid UTF-8 are handled. Map keys decoded as
+    /// borrowed or lazy values, which can't own a replacement, always fail.
+    pub invalid_utf8: InvalidUtf8,
+}
+
+impl DecodeOptions {
+    const DEFAULT: DecodeOptions = DecodeOptions {
+        max_set_entries: 1_000_000,
+        max_set_key_bytes: 128 * 1024 * 1024,
+        max_bytes_len: 512 * 1024 * 1024,
+        max_collection_entries: 10_000_000,
+        max_nesting_depth: 128,
+        max_block_bytes: 512 * 1024 * 1024,
+        invalid_utf8: InvalidUtf8::Error,
+    };
+
+    /// Check the values decoded on this thread against these options, until the returned guard
+    /// is dropped.
+    pub(crate) fn enter(self) -> OptionsGuard {
+        OptionsGuard(DECODE_OPTIONS.with(|options| options.replace(self)))
+    }
+}
 
-impl AllocationError {
-    pub fn new<S>(msg: S) -> AllocationError
-    where
-        S: Into<String>,
-    {
-        AllocationError(msg.into())
+impl Default for DecodeOptions {
+    fn default() -> DecodeOptions {
+        DecodeOptions::DEFAULT
     }
 }
 
-/// Describes errors happened while decoding Avro data.
-#[derive(Fail, Debug)]
-#[fail(display = "Decoding error: {}", _0)]
-pub struct DecodeError(String);
+/// Options of the values decoded on this thread, which are restored to the ones entered before
+/// once the guard is dropped.
+pub(crate) struct OptionsGuard(DecodeOptions);
 
-impl DecodeError {
-    pub fn new<S>(msg: S) -> DecodeError
-    where
-        S: Into<String>,
-    {
-        DecodeError(msg.into())
+impl Drop for OptionsGuard {
+    fn drop(&mut self) {
+        DECODE_OPTIONS.with(|options| options.set(self.0));
     }
 }
 
+/// Options of the values being decoded on this thread.
+#[inline]
+pub(crate) fn decode_options() -> DecodeOptions {
+    DECODE_OPTIONS.with(Cell::get)
+}
+
 pub trait MapHelper {
     fn string(&self, key: &str) -> Option<String>;
 
     fn bool(&self, key: &str) -> bool;
 
+    fn aliases(&self) -> Option<Vec<String>>;
+
     fn name(&self) -> Option<String> {
         self.string("name")
     }
@@ -75,70 +140,93 @@ impl MapHelper for Map<String, Value> {
             None => false
         }
     }
+
+    fn aliases(&self) -> Option<Vec<String>> {
+        self.get("aliases")
+            .and_then(|aliases| aliases.as_array())
+            .and_then(|aliases| {
+                aliases
+                    .iter()
+                    .map(|alias| alias.as_str().map(|a| a.to_string()))
+                    .collect::<Option<_>>()
+            })
+    }
 }
 
-pub fn read_long<R: Read>(reader: &mut R) -> Result<i64, Error> {
+pub fn read_long<R: Read>(reader: &mut R) -> Result<i64, AvroError> {
     zag_i64(reader)
 }
 
+#[inline]
 pub fn zig_i32(n: i32, buffer: &mut Vec<u8>) {
     zig_i64(n as i64, buffer)
 }
 
+#[inline]
 pub fn zig_i64(n: i64, buffer: &mut Vec<u8>) {
     encode_variable(((n << 1) ^ (n >> 63)) as u64, buffer)
 }
 
-pub fn zag_i32<R: Read>(reader: &mut R) -> Result<i32, Error> {
+/// Number of bytes `zig_i64` writes for `n`.
+#[inline]
+pub fn zig_i64_len(n: i64) -> usize {
+    let z = ((n << 1) ^ (n >> 63)) as u64;
+    // 7 bits per byte, and at least one byte for 0
+    (64 - (z | 1).leading_zeros() as usize).div_ceil(7)
+}
+
+#[inline]
+pub fn zag_i32<R: Read>(reader: &mut R) -> Result<i32, AvroError> {
     let i = zag_i64(reader)?;
     if i < i64::from(i32::min_value()) || i > i64::from(i32::max_value()) {
-        Err(DecodeError::new("int out of range").into())
+        Err(AvroError::Decode("int out of range".to_owned()))
     } else {
         Ok(i as i32)
     }
 }
 
-pub fn zag_i64<R: Read>(reader: &mut R) -> Result<i64, Error> {
+#[inline]
+pub fn zag_i64<R: Read>(reader: &mut R) -> Result<i64, AvroError> {
     let z = decode_variable(reader)?;
-    Ok(if z & 0x1 == 0 {
-        (z >> 1) as i64
-    } else {
-        !(z >> 1) as i64
-    })
+    Ok(((z >> 1) as i64) ^ -((z & 1) as i64))
 }
 
+#[inline]
 fn encode_variable(mut z: u64, buffer: &mut Vec<u8>) {
-    loop {
-        if z <= 0x7F {
-            buffer.push((z & 0x7F) as u8);
-            break
-        } else {
-            buffer.push((0x80 | (z & 0x7F)) as u8);
-            z >>= 7;
-        }
+    // most integers written are small: one byte, without going through the scratch buffer
+    if z <= 0x7F {
+        buffer.push(z as u8);
+        return
+    }
+    let mut bytes = [0u8; 10];
+    let mut len = 0;
+    while z > 0x7F {
+        bytes[len] = 0x80 | (z as u8 & 0x7F);
+        z >>= 7;
+        len += 1;
     }
+    bytes[len] = z as u8;
+    buffer.extend_from_slice(&bytes[..=len]);
 }
 
-fn decode_variable<R: Read>(reader: &mut R) -> Result<u64, Error> {
-    let mut i = 0u64;
+#[inline]
+fn decode_variable<R: Read>(reader: &mut R) -> Result<u64, AvroError> {
     let mut buf = [0u8; 1];
+    reader.read_exact(&mut buf[..])?;
+    if buf[0] & 0x80 == 0 {
+        return Ok(u64::from(buf[0]))
+    }
 
-    let mut j = 0;
-    loop {
-        if j > 9 {
-            // if j * 7 > 64
-            return Err(DecodeError::new("Overflow when decoding integer value").into())
-        }
+    let mut i = u64::from(buf[0] & 0x7F);
+    // at most 10 bytes of 7 bits hold a 64-bit integer
+    for shift in (7..64).step_by(7) {
         reader.read_exact(&mut buf[..])?;
-        i |= (u64::from(buf[0] & 0x7F)) << (j * 7);
-        if (buf[0] >> 7) == 0 {
-            break
-        } else {
-            j += 1;
+        i |= u64::from(buf[0] & 0x7F) << shift;
+        if buf[0] & 0x80 == 0 {
+            return Ok(i)
         }
     }
-
-    Ok(i)
+    Err(AvroError::Decode("Overflow when decoding integer value".to_owned()))
 }
 
 /// Set a new maximum number of bytes that can be allocated when decoding data.
@@ -157,23 +245,223 @@ pub fn max_allocation_bytes(num_bytes: usize) -> usize {
     }
 }
 
-pub fn safe_len(len: usize) -> Result<usize, Error> {
+/// Check the length of a `string` or `bytes` value against `DecodeOptions::max_bytes_len`,
+/// before it is allocated.
+pub(crate) fn bytes_len(len: usize) -> Result<usize, AvroError> {
+    let max_len = decode_options().max_bytes_len;
+    if len > max_len {
+        return Err(AvroError::Limit(format!(
+            "Unable to decode a string or bytes of {} bytes (Maximum allowed: {})",
+            len, max_len
+        )))
+    }
+    safe_len(len)
+}
+
+/// Check the size of a block against `DecodeOptions::max_block_bytes`, before it is read.
+pub(crate) fn block_len(len: usize) -> Result<usize, AvroError> {
+    let max_bytes = decode_options().max_block_bytes;
+    if len > max_bytes {
+        return Err(AvroError::Limit(format!(
+            "Unable to read a block of {} bytes (Maximum allowed: {})",
+            len, max_bytes
+        )))
+    }
+    safe_len(len)
+}
+
+/// Number of items reserved ahead of decoding a block of an `array`, `map` or `set`, whatever the
+/// count of the block: the count comes from the input, which may not hold as many items.
+const MAX_RESERVED_ITEMS: usize = 1024;
+
+/// Number of bytes reserved ahead of reading a `string` or `bytes` value or a block, whatever
+/// their length: the buffer grows as the bytes are read past it.
+const MAX_RESERVED_BYTES: usize = 64 * 1024;
+
+/// Number of items to reserve ahead of decoding a block of `len` items.
+#[inline]
+pub(crate) fn reserved_items(len: usize) -> usize {
+    len.min(MAX_RESERVED_ITEMS)
+}
+
+/// Read exactly `len` bytes of `reader` at the end of `buf`, growing it as the bytes are read
+/// instead of allocating all of them first.
+pub(crate) fn read_len<R: Read>(
+    reader: &mut R,
+    len: usize,
+    buf: &mut Vec<u8>,
+) -> Result<(), AvroError> {
+    buf.reserve(len.min(MAX_RESERVED_BYTES));
+    let read = reader.take(len as u64).read_to_end(buf)?;
+    if read < len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into())
+    }
+    Ok(())
+}
+
+/// Running number of items of an `array` or `map` being decoded, failing with a
+/// `AvroError::Limit` as soon as it exceeds
+/// `DecodeOptions::max_collection_entries`.
+#[derive(Default)]
+pub(crate) struct EntriesBudget {
+    entries: usize,
+}
+
+impl EntriesBudget {
+    /// Account for a block of `len` items, before they are decoded.
+    pub(crate) fn reserve(&mut self, len: usize) -> Result<(), AvroError> {
+        let max_entries = decode_options().max_collection_entries;
+        self.entries = self.entries.saturating_add(len);
+        if self.entries > max_entries {
+            return Err(AvroError::Limit(format!(
+                "Unable to decode more than {} array or map entries",
+                max_entries
+            )))
+        }
+        Ok(())
+    }
+}
+
+/// Level of nesting of the value being decoded on this thread, entered for as long as the guard
+/// is alive. Fails with an `AvroError::Limit` beyond
+/// `DecodeOptions::max_nesting_depth`.
+pub(crate) struct DepthGuard(());
+
+impl DepthGuard {
+    pub(crate) fn enter() -> Result<DepthGuard, AvroError> {
+        let max_depth = decode_options().max_nesting_depth;
+        let depth = NESTING_DEPTH.with(|depth| {
+            depth.set(depth.get() + 1);
+            depth.get()
+        });
+        // the guard is created first, so that the depth is restored on error as well
+        let guard = DepthGuard(());
+        if depth > max_depth {
+            return Err(AvroError::Limit(format!(
+                "Unable to decode values nested more than {} levels deep",
+                max_depth
+            )))
+        }
+        Ok(guard)
+    }
+}
+
+impl Drop for DepthGuard {
+    fn drop(&mut self) {
+        NESTING_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// Count `evicted` entries of a lru set, until reported with `take_evictions`.
+pub(crate) fn count_evictions(evicted: usize) {
+    if evicted > 0 {
+        EVICTIONS.with(|evictions| evictions.set(evictions.get() + evicted));
+    }
+}
+
+/// Number of the lru set entries evicted on this thread since the previous call.
+pub(crate) fn take_evictions() -> usize {
+    EVICTIONS.with(|evictions| evictions.replace(0))
+}
+
+/// Running size of a `set` or `lru_set` being decoded, failing with a
+/// `AvroError::Limit` as soon as it exceeds `DecodeOptions::max_set_entries` or
+/// `DecodeOptions::max_set_key_bytes`.
+#[derive(Default)]
+pub(crate) struct SetBudget {
+    entries: usize,
+    key_bytes: usize,
+}
+
+impl SetBudget {
+    /// Account for a block of `len` entries, before they are decoded.
+    pub(crate) fn reserve(&mut self, len: usize) -> Result<(), AvroError> {
+        let max_entries = decode_options().max_set_entries;
+        self.entries = self.entries.saturating_add(len);
+        if self.entries > max_entries {
+            return Err(AvroError::Limit(format!(
+                "Unable to decode more than {} set entries",
+                max_entries
+            )))
+        }
+        Ok(())
+    }
+
+    /// Account for a decoded key of `len` bytes.
+    pub(crate) fn add_key(&mut self, len: usize) -> Result<(), AvroError> {
+        let max_bytes = decode_options().max_set_key_bytes;
+        self.key_bytes = self.key_bytes.saturating_add(len);
+        if self.key_bytes > max_bytes {
+            return Err(AvroError::Limit(format!(
+                "Unable to decode more than {} bytes of set keys",
+                max_bytes
+            )))
+        }
+        Ok(())
+    }
+}
+
+/// Convert a decoded length or count to `usize`, failing if it is negative or too large for the
+/// platform instead of wrapping around.
+pub(crate) fn usize_len(len: i64) -> Result<usize, AvroError> {
+    usize::try_from(len).map_err(|_| AvroError::Decode(format!("Invalid length: {}", len)))
+}
+
+/// Item of `items` at a decoded `index`, if any. Negative indexes are out of bounds.
+pub(crate) fn item_at<T>(items: &[T], index: i64) -> Option<&T> {
+    usize::try_from(index).ok().and_then(|index| items.get(index))
+}
+
+pub fn safe_len(len: usize) -> Result<usize, AvroError> {
     let max_bytes = max_allocation_bytes(512 * 1024 * 1024);
 
     if len <= max_bytes {
         Ok(len)
     } else {
-        Err(AllocationError::new(format!(
+        Err(AvroError::Limit(format!(
             "Unable to allocate {} bytes (Maximum allowed: {})",
             len, max_bytes
-        )).into())
+        )))
     }
 }
 
+/// Fingerprint of the empty sequence of bytes, which is also the polynomial of the 64-bit Rabin
+/// fingerprint used by Avro.
+const RABIN_EMPTY: u64 = 0xc15d_213a_a4d7_a795;
+
+lazy_static! {
+    static ref RABIN_TABLE: [u64; 256] = {
+        let mut table = [0u64; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut fp = i as u64;
+            for _ in 0..8 {
+                fp = (fp >> 1) ^ (RABIN_EMPTY & (fp & 1).wrapping_neg());
+            }
+            *entry = fp;
+        }
+        table
+    };
+}
+
+/// 64-bit Rabin fingerprint of `bytes`, as described in the
+/// [Avro specification](https://avro.apache.org/docs/current/spec.html#schema_fingerprints).
+pub(crate) fn rabin_fingerprint(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(RABIN_EMPTY, |fp, &byte| {
+        (fp >> 8) ^ RABIN_TABLE[((fp ^ u64::from(byte)) & 0xff) as usize]
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_rabin_fingerprint() {
+        assert_eq!(rabin_fingerprint(b""), RABIN_EMPTY);
+        assert_eq!(rabin_fingerprint(br#""null""#) as i64, 7_195_948_357_588_979_594);
+        assert_ne!(rabin_fingerprint(b"int"), rabin_fingerprint(b"long"));
+    }
+
     #[test]
     fn test_zigzag() {
         let mut a = Vec::new();
@@ -237,6 +525,25 @@ mod tests {
         assert_eq!(s, [255, 255, 255, 255, 15]);
     }
 
+    #[test]
+    fn test_zigzag_roundtrip() {
+        let values = [
+            0, 1, -1, 63, -64, 64, -65, 8191, 8192, i64::from(i32::MAX), i64::MAX, i64::MIN,
+        ];
+        for &n in values.iter() {
+            let mut s = Vec::new();
+            zig_i64(n, &mut s);
+            assert_eq!(s.len(), zig_i64_len(n));
+            let mut bytes = &s[..];
+            assert_eq!(zag_i64(&mut bytes).unwrap(), n);
+            assert!(bytes.is_empty());
+        }
+        let mut s = Vec::new();
+        zig_i64(i64::MAX, &mut s);
+        assert!(zag_i32(&mut &s[..]).is_err());
+        assert!(zag_i64(&mut &[0xff; 11][..]).is_err());
+    }
+
     #[test]
     fn test_overflow() {
         let causes_left_shift_overflow: &[u8] = &[0xe1, 0xe1, 0xe1, 0xe1, 0xe1];
@@ -248,4 +555,53 @@ mod tests {
         assert_eq!(42usize, safe_len(42usize).unwrap());
         assert!(safe_len(1024 * 1024 * 1024).is_err());
     }
+
+    #[test]
+    fn test_decode_limits() {
+        let options = DecodeOptions::default();
+        assert_eq!(bytes_len(42).unwrap(), 42);
+        let error = bytes_len(options.max_bytes_len + 1).unwrap_err();
+        assert!(matches!(error, AvroError::Limit(_)));
+        let error = block_len(options.max_block_bytes + 1).unwrap_err();
+        assert!(matches!(error, AvroError::Limit(_)));
+
+        let mut budget = EntriesBudget::default();
+        budget.reserve(10).unwrap();
+        assert!(budget.reserve(options.max_collection_entries).is_err());
+
+        let guards = (0..options.max_nesting_depth)
+            .map(|_| DepthGuard::enter())
+            .collect::<Result<Vec<_>, _>>();
+        assert!(guards.is_ok());
+        assert!(DepthGuard::enter().is_err());
+        drop(guards);
+        assert!(DepthGuard::enter().is_ok());
+    }
+
+    #[test]
+    fn test_set_budget() {
+        let options = DecodeOptions::default();
+        let mut budget = SetBudget::default();
+        budget.reserve(10).unwrap();
+        budget.add_key(10).unwrap();
+        assert!(budget.reserve(options.max_set_entries).is_err());
+        assert!(budget.add_key(options.max_set_key_bytes).is_err());
+    }
+
+    #[test]
+    fn test_decode_options_entered() {
+        let options = DecodeOptions {
+            max_bytes_len: 10,
+            ..DecodeOptions::default()
+        };
+        let outer = options.enter();
+        assert!(bytes_len(11).is_err());
+        {
+            let _inner = DecodeOptions::default().enter();
+            assert!(bytes_len(11).is_ok());
+        }
+        assert!(bytes_len(11).is_err());
+        drop(outer);
+        assert!(bytes_len(11).is_ok());
+    }
 }
@@ -0,0 +1,252 @@
+//! Logic for traversing Avro values alongside their schemas.
+use std::fmt;
+
+use crate::schema::Schema;
+use crate::types::Value;
+
+/// One step of the path leading from the root of a walked value to a nested value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathSegment<'a> {
+    /// Field of a record.
+    Field(&'a str),
+    /// Item of an array.
+    Index(usize),
+    /// Entry of a map.
+    Key(&'a str),
+}
+
+impl<'a> fmt::Display for PathSegment<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PathSegment::Field(name) => write!(f, ".{}", name),
+            PathSegment::Index(i) => write!(f, "[{}]", i),
+            PathSegment::Key(key) => write!(f, "[{:?}]", key),
+        }
+    }
+}
+
+/// Visitor called for every value reached by [`Value::walk`](../types/enum.Value.html#method.walk).
+///
+/// Implemented for closures taking the same arguments as [`visit`](#tymethod.visit).
+pub trait ValueVisitor {
+    /// Visit a value along with its schema and its path from the root value. Returning `false`
+    /// skips the values nested in this one.
+    fn visit(&mut self, path: &[PathSegment], value: &Value, schema: &Schema) -> bool;
+}
+
+impl<F> ValueVisitor for F
+    where
+        F: FnMut(&[PathSegment], &Value, &Schema) -> bool,
+{
+    fn visit(&mut self, path: &[PathSegment], value: &Value, schema: &Schema) -> bool {
+        self(path, value, schema)
+    }
+}
+
+/// Visitor called for every value reached by
+/// [`Value::walk_mut`](../types/enum.Value.html#method.walk_mut), allowed to modify them.
+///
+/// Implemented for closures taking the same arguments as [`visit`](#tymethod.visit).
+pub trait ValueVisitorMut {
+    /// Visit a value along with its schema and its path from the root value. Nested values are
+    /// walked after the visit, so they reflect its modifications. Returning `false` skips them.
+    fn visit(&mut self, path: &[PathSegment], value: &mut Value, schema: &Schema) -> bool;
+}
+
+impl<F> ValueVisitorMut for F
+    where
+        F: FnMut(&[PathSegment], &mut Value, &Schema) -> bool,
+{
+    fn visit(&mut self, path: &[PathSegment], value: &mut Value, schema: &Schema) -> bool {
+        self(path, value, schema)
+    }
+}
+
+fn walk<'a, V: ValueVisitor + ?Sized>(
+    value: &'a Value,
+    schema: &Schema,
+    path: &mut Vec<PathSegment<'a>>,
+    visitor: &mut V,
+) {
+    if !visitor.visit(path, value, schema) {
+        return
+    }
+
+    match (value, schema) {
+        (Value::Union(inner, _), Schema::Union(union)) => {
+            if let Some((_, branch)) = union.find_schema(inner) {
+                walk(inner, branch, path, visitor);
+            }
+        },
+        (Value::Optional(Some(inner), _), Schema::Optional(branch)) => {
+            walk(inner, branch, path, visitor);
+        },
+        (Value::Array(items, _), Schema::Array(inner))
+        | (Value::Array(items, _), Schema::BoundedList { items: inner, .. }) => {
+            for (i, item) in items.iter().enumerate() {
+                path.push(PathSegment::Index(i));
+                walk(item, inner, path, visitor);
+                path.pop();
+            }
+        },
+        (Value::Map(items, _), Schema::Map(inner)) => {
+            for (key, item) in items.iter() {
+                path.push(PathSegment::Key(key));
+                walk(item, inner, path, visitor);
+                path.pop();
+            }
+        },
+        (Value::Record(items, _), Schema::Record { fields, lookup, .. }) => {
+            for (name, item) in items.iter() {
+                if let Some(&position) = lookup.get(&**name) {
+                    path.push(PathSegment::Field(name));
+                    walk(item, &fields[position].schema, path, visitor);
+                    path.pop();
+                }
+            }
+        },
+        _ => (),
+    }
+}
+
+fn walk_mut<'a, V: ValueVisitorMut + ?Sized>(
+    value: &'a mut Value,
+    schema: &Schema,
+    path: &mut Vec<PathSegment<'a>>,
+    visitor: &mut V,
+) {
+    if !visitor.visit(path, value, schema) {
+        return
+    }
+
+    match (value, schema) {
+        (Value::Union(inner, _), Schema::Union(union)) => {
+            if let Some((_, branch)) = union.find_schema(inner) {
+                walk_mut(inner, branch, path, visitor);
+            }
+        },
+        (Value::Optional(Some(inner), _), Schema::Optional(branch)) => {
+            walk_mut(inner, branch, path, visitor);
+        },
+        (Value::Array(items, _), Schema::Array(inner))
+        | (Value::Array(items, _), Schema::BoundedList { items: inner, .. }) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                path.push(PathSegment::Index(i));
+                walk_mut(item, inner, path, visitor);
+                path.pop();
+            }
+        },
+        (Value::Map(items, _), Schema::Map(inner)) => {
+            for (key, item) in items.iter_mut() {
+                path.push(PathSegment::Key(key));
+                walk_mut(item, inner, path, visitor);
+                path.pop();
+            }
+        },
+        (Value::Record(items, _), Schema::Record { fields, lookup, .. }) => {
+            for (name, item) in items.iter_mut() {
+                if let Some(&position) = lookup.get(&**name) {
+                    path.push(PathSegment::Field(name));
+                    walk_mut(item, &fields[position].schema, path, visitor);
+                    path.pop();
+                }
+            }
+        },
+        _ => (),
+    }
+}
+
+impl Value {
+    /// Traverse the value depth-first alongside the given [Schema](../schema/enum.Schema.html),
+    /// calling the visitor for the value itself and for every value nested in it.
+    ///
+    /// `Union` and `Optional` values are visited first, then their inner value with the schema of
+    /// the matching branch, under the same path. Values not matching their schema are visited but
+    /// not descended into, and the items of `Set` and `LruSet` values are not visited.
+    pub fn walk<V: ValueVisitor + ?Sized>(&self, schema: &Schema, visitor: &mut V) {
+        walk(self, schema, &mut Vec::new(), visitor)
+    }
+
+    /// Same as [`walk`](#method.walk), giving the visitor mutable access to the values.
+    pub fn walk_mut<V: ValueVisitorMut + ?Sized>(&mut self, schema: &Schema, visitor: &mut V) {
+        walk_mut(self, schema, &mut Vec::new(), visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ToAvro;
+
+    static SCHEMA: &'static str = r#"
+            {
+                "type": "record",
+                "name": "user",
+                "fields": [
+                    {"name": "id", "type": "long"},
+                    {"name": "emails", "type": {"type": "array", "items": "string"}},
+                    {"name": "manager", "type": ["null", "string"]}
+                ]
+            }
+        "#;
+
+    fn user() -> Value {
+        Value::record(vec![
+            ("id", 1i64.avro()),
+            ("emails", vec!["a@b.c", "d@e.f"].avro()),
+            ("manager", Some("g@h.i").avro()),
+        ])
+    }
+
+    #[test]
+    fn test_walk_paths() {
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+        let mut strings = Vec::new();
+        user().walk(&schema, &mut |path: &[PathSegment], value: &Value, _: &Schema| {
+            if let Value::String(s, _) = value {
+                let path = path.iter().map(|s| s.to_string()).collect::<String>();
+                strings.push(format!("${} = {}", path, s));
+            }
+            true
+        });
+
+        assert_eq!(
+            strings,
+            vec!["$.emails[0] = a@b.c", "$.emails[1] = d@e.f", "$.manager = g@h.i"]
+        );
+    }
+
+    #[test]
+    fn test_walk_skip() {
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+        let mut count = 0;
+        user().walk(&schema, &mut |path: &[PathSegment], _: &Value, _: &Schema| {
+            count += 1;
+            path.first() != Some(&PathSegment::Field("emails"))
+        });
+
+        // record, id, emails, manager union and its string
+        assert_eq!(count, 5);
+    }
+
+    #[test]
+    fn test_walk_mut_scrub() {
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+        let mut value = user();
+        value.walk_mut(&schema, &mut |_: &[PathSegment], value: &mut Value, schema: &Schema| {
+            if let (Value::String(s, _), Schema::String) = (&mut *value, schema) {
+                *s = "***".to_string();
+            }
+            true
+        });
+
+        assert_eq!(
+            value,
+            Value::record(vec![
+                ("id", 1i64.avro()),
+                ("emails", vec!["***", "***"].avro()),
+                ("manager", Some("***").avro()),
+            ])
+        );
+    }
+}
@@ -1,37 +1,28 @@
 //! Logic handling writing in Avro format at user level.
 use std::collections::HashMap;
 use std::io::Write;
+use std::sync::Arc;
 
-use failure::Error;
 use rand::random;
 use serde::Serialize;
 use serde_json;
 
-use crate::encode::{encode, encode_ref, encode_to_vec};
+use crate::encode::{encode, encode_to_vec, encode_with_options, EncodeOptions};
+use crate::error::AvroError;
+use crate::index::{write_sidecar_index, FileIndex, IndexCollector};
+use crate::metrics::{Metrics, SharedMetrics};
 use crate::schema::Schema;
 use crate::ser::Serializer;
+use crate::ser_datum::serialize_datum;
 use crate::types::{ToAvro, Value};
+use crate::util;
 use crate::Codec;
 
 const SYNC_SIZE: usize = 16;
-const SYNC_INTERVAL: usize = 1000 * SYNC_SIZE; // TODO: parametrize in Writer
+const SYNC_INTERVAL: usize = 1000 * SYNC_SIZE;
 
 const AVRO_OBJECT_HEADER: &[u8] = &[b'O', b'b', b'j', 1u8];
 
-/// Describes errors happened while validating Avro data.
-#[derive(Fail, Debug)]
-#[fail(display = "Decoding error: {}", _0)]
-pub struct ValidationError(String);
-
-impl ValidationError {
-    pub fn new<S>(msg: S) -> ValidationError
-    where
-        S: Into<String>,
-    {
-        ValidationError(msg.into())
-    }
-}
-
 /// Main interface for writing Avro formatted values.
 pub struct Writer<'a, W> {
     schema: &'a Schema,
@@ -39,9 +30,20 @@ pub struct Writer<'a, W> {
     writer: W,
     buffer: Vec<u8>,
     num_values: usize,
+    // number of encoded bytes a block is flushed at
+    block_size: usize,
     codec: Codec,
+    compression_level: Option<i32>,
     marker: Vec<u8>,
     has_header: bool,
+    // number of bytes written so far, and of records appended so far
+    offset: u64,
+    ordinal: u64,
+    index: Option<IndexCollector<'a>>,
+    validate: bool,
+    encode_options: EncodeOptions,
+    user_metadata: HashMap<String, Vec<u8>>,
+    metrics: SharedMetrics,
 }
 
 impl<'a, W: Write> Writer<'a, W> {
@@ -66,9 +68,18 @@ impl<'a, W: Write> Writer<'a, W> {
             writer,
             buffer: Vec::with_capacity(SYNC_INTERVAL),
             num_values: 0,
+            block_size: SYNC_INTERVAL,
             codec,
+            compression_level: None,
             marker,
             has_header: false,
+            offset: 0,
+            ordinal: 0,
+            index: None,
+            validate: true,
+            encode_options: EncodeOptions::default(),
+            user_metadata: HashMap::new(),
+            metrics: SharedMetrics::default(),
         }
     }
 
@@ -85,56 +96,62 @@ impl<'a, W: Write> Writer<'a, W> {
     /// **NOTE** This function is not guaranteed to perform any actual write, since it relies on
     /// internal buffering for performance reasons. If you want to be sure the value has been
     /// written, then call [`flush`](struct.Writer.html#method.flush).
-    pub fn append<T: ToAvro>(&mut self, value: T) -> Result<usize, Error> {
-        let n = if !self.has_header {
-            let header = self.header()?;
-            let n = self.append_bytes(header.as_ref())?;
-            self.has_header = true;
-            n
-        } else {
-            0
-        };
-
-        let avro = value.avro();
-        write_value_ref(self.schema, &avro, &mut self.buffer)?;
-
-        self.num_values += 1;
-
-        if self.buffer.len() >= SYNC_INTERVAL {
-            return self.flush().map(|b| b + n)
-        }
-
-        Ok(n)
+    pub fn append<T: ToAvro>(&mut self, value: T) -> Result<usize, AvroError> {
+        self.append_ref(&value.avro())
     }
 
-    /// Append a compatible value to a `Writer`, also performing schema validation.
+    /// Append a borrowed `Value` to a `Writer`, also performing schema validation. The value is
+    /// encoded straight into the buffer of the current block, without being cloned.
     ///
     /// Return the number of bytes written (it might be 0, see below).
     ///
     /// **NOTE** This function is not guaranteed to perform any actual write, since it relies on
     /// internal buffering for performance reasons. If you want to be sure the value has been
     /// written, then call [`flush`](struct.Writer.html#method.flush).
-    pub fn append_value_ref(&mut self, value: &Value) -> Result<usize, Error> {
-        let n = if !self.has_header {
-            let header = self.header()?;
-            let n = self.append_bytes(header.as_ref())?;
-            self.has_header = true;
-            n
-        } else {
-            0
-        };
+    pub fn append_ref(&mut self, value: &Value) -> Result<usize, AvroError> {
+        self.append_encoded(value, self.validate)
+    }
 
-        write_value_ref(self.schema, value, &mut self.buffer)?;
+    /// Append a borrowed `Value` to a `Writer` without performing schema validation, for callers
+    /// who already guarantee that the value matches the schema.
+    ///
+    /// Return the number of bytes written (it might be 0, see below).
+    ///
+    /// **NOTE** A value not matching the schema is encoded anyway, and makes the file unreadable.
+    /// As for [`append`](struct.Writer.html#method.append), this function is not guaranteed to
+    /// perform any actual write.
+    pub fn append_unchecked(&mut self, value: &Value) -> Result<usize, AvroError> {
+        self.append_encoded(value, false)
+    }
 
+    /// Append a value already encoded in Avro binary format with the schema of the `Writer`, such
+    /// as the bytes of a record of a data block, without decoding it again.
+    ///
+    /// Return the number of bytes written (it might be 0, see below).
+    ///
+    /// **NOTE** The bytes are neither validated nor collected by the secondary index. As for
+    /// [`append`](struct.Writer.html#method.append), this function is not guaranteed to perform
+    /// any actual write.
+    pub fn append_datum(&mut self, datum: &[u8]) -> Result<usize, AvroError> {
+        let n = self.write_header()?;
+        self.buffer.extend_from_slice(datum);
         self.num_values += 1;
+        self.ordinal += 1;
+        self.metrics.0.records_written(1);
 
-        if self.buffer.len() >= SYNC_INTERVAL {
+        if self.buffer.len() >= self.block_size {
             return self.flush().map(|b| b + n)
         }
 
         Ok(n)
     }
 
+    /// Append a compatible value to a `Writer`, also performing schema validation, as
+    /// [`append_ref`](struct.Writer.html#method.append_ref) does.
+    pub fn append_value_ref(&mut self, value: &Value) -> Result<usize, AvroError> {
+        self.append_ref(value)
+    }
+
     /// Append anything implementing the `Serialize` trait to a `Writer` for
     /// [`serde`](https://docs.serde.rs/serde/index.html) compatibility, also performing schema
     /// validation.
@@ -144,7 +161,7 @@ impl<'a, W: Write> Writer<'a, W> {
     /// **NOTE** This function is not guaranteed to perform any actual write, since it relies on
     /// internal buffering for performance reasons. If you want to be sure the value has been
     /// written, then call [`flush`](struct.Writer.html#method.flush).
-    pub fn append_ser<S: Serialize>(&mut self, value: S) -> Result<usize, Error> {
+    pub fn append_ser<S: Serialize>(&mut self, value: S) -> Result<usize, AvroError> {
         let avro_value = value.serialize(&mut self.serializer)?;
         self.append(avro_value)
     }
@@ -154,9 +171,13 @@ impl<'a, W: Write> Writer<'a, W> {
     ///
     /// Return the number of bytes written.
     ///
+    /// The header is written once and the schema is prepared for validation once, then the values
+    /// are encoded one after the other into the current block, which is flushed every time it
+    /// reaches the sync interval.
+    ///
     /// **NOTE** This function forces the written data to be flushed (an implicit
     /// call to [`flush`](struct.Writer.html#method.flush) is performed).
-    pub fn extend<I, T: ToAvro>(&mut self, values: I) -> Result<usize, Error>
+    pub fn extend<I, T: ToAvro>(&mut self, values: I) -> Result<usize, AvroError>
     where
         I: IntoIterator<Item = T>,
     {
@@ -174,9 +195,10 @@ impl<'a, W: Write> Writer<'a, W> {
             });
         */
 
-        let mut num_bytes = 0;
+        let validator = self.validator();
+        let mut num_bytes = self.write_header()?;
         for value in values {
-            num_bytes += self.append(value)?;
+            num_bytes += self.push(&value.avro(), validator.as_ref())?;
         }
         num_bytes += self.flush()?;
 
@@ -191,7 +213,7 @@ impl<'a, W: Write> Writer<'a, W> {
     ///
     /// **NOTE** This function forces the written data to be flushed (an implicit
     /// call to [`flush`](struct.Writer.html#method.flush) is performed).
-    pub fn extend_ser<I, T: Serialize>(&mut self, values: I) -> Result<usize, Error>
+    pub fn extend_ser<I, T: Serialize>(&mut self, values: I) -> Result<usize, AvroError>
     where
         I: IntoIterator<Item = T>,
     {
@@ -209,9 +231,11 @@ impl<'a, W: Write> Writer<'a, W> {
             });
         */
 
-        let mut num_bytes = 0;
+        let validator = self.validator();
+        let mut num_bytes = self.write_header()?;
         for value in values {
-            num_bytes += self.append_ser(value)?;
+            let value = value.serialize(&mut self.serializer)?;
+            num_bytes += self.push(&value, validator.as_ref())?;
         }
         num_bytes += self.flush()?;
 
@@ -225,10 +249,11 @@ impl<'a, W: Write> Writer<'a, W> {
     ///
     /// **NOTE** This function forces the written data to be flushed (an implicit
     /// call to [`flush`](struct.Writer.html#method.flush) is performed).
-    pub fn extend_from_slice(&mut self, values: &[Value]) -> Result<usize, Error> {
-        let mut num_bytes = 0;
+    pub fn extend_from_slice(&mut self, values: &[Value]) -> Result<usize, AvroError> {
+        let validator = self.validator();
+        let mut num_bytes = self.write_header()?;
         for value in values {
-            num_bytes += self.append_value_ref(value)?;
+            num_bytes += self.push(value, validator.as_ref())?;
         }
         num_bytes += self.flush()?;
 
@@ -239,27 +264,128 @@ impl<'a, W: Write> Writer<'a, W> {
     /// has been written before releasing the `Writer`.
     ///
     /// Return the number of bytes written.
-    pub fn flush(&mut self) -> Result<usize, Error> {
+    pub fn flush(&mut self) -> Result<usize, AvroError> {
         if self.num_values == 0 {
             return Ok(0)
         }
 
-        self.codec.compress(&mut self.buffer)?;
+        let _span = trace_span!(debug_span, "write_block", records = self.num_values);
+        self.codec.compress_at(&mut self.buffer, self.compression_level)?;
 
         let num_values = self.num_values;
         let stream_len = self.buffer.len();
 
+        if let Some(ref mut index) = self.index {
+            index.flush_block(self.offset);
+        }
+
         let num_bytes = self.append_raw(&num_values.avro(), &Schema::Long)?
             + self.append_raw(&stream_len.avro(), &Schema::Long)?
             + self.writer.write(self.buffer.as_ref())?
             + self.append_marker()?;
 
+        trace_event!(debug, records = num_values, bytes = num_bytes, "wrote block");
+        self.metrics.0.blocks_written(1);
+        self.metrics.0.bytes_written(stream_len as u64);
         self.buffer.clear();
         self.num_values = 0;
+        self.offset += num_bytes as u64;
 
         Ok(num_bytes)
     }
 
+    /// Add an entry to the metadata of the file header, to be read back with
+    /// [`Reader::user_metadata`](struct.Reader.html#method.user_metadata). Entries must be added
+    /// before the header is written, that is before the first value is appended, and keys of the
+    /// `avro.` namespace are reserved.
+    pub fn add_user_metadata<K, V>(&mut self, key: K, value: V) -> Result<(), AvroError>
+    where
+        K: Into<String>,
+        V: Into<Vec<u8>>,
+    {
+        let key = key.into();
+        if self.has_header {
+            return Err(AvroError::Validation(
+                "metadata added after the header was written".to_owned(),
+            ))
+        }
+        if key.starts_with("avro.") {
+            return Err(AvroError::Validation(format!("reserved metadata key {}", key)))
+        }
+        self.user_metadata.insert(key, value.into());
+        Ok(())
+    }
+
+    /// Set the [`Metrics`](trait.Metrics.html) the records, blocks and bytes written from now on
+    /// are reported to, as well as the `lru_set` entries evicted while encoding them.
+    pub fn set_metrics<M: Metrics + 'static>(&mut self, metrics: M) {
+        self.metrics = SharedMetrics(Arc::new(metrics));
+    }
+
+    /// Set the number of bytes of encoded values, before compression, a data block is written at.
+    /// Blocks are written every 16000 bytes by default.
+    pub fn set_block_size(&mut self, bytes: usize) {
+        self.block_size = bytes.max(1);
+    }
+
+    /// Set the level data blocks are compressed at, one of the
+    /// [`levels`](enum.Codec.html#method.levels) of the `Codec`. Blocks are compressed at the
+    /// default level of the `Codec` otherwise.
+    pub fn set_compression_level(&mut self, level: i32) -> Result<(), AvroError> {
+        if !self.codec.levels().is_some_and(|levels| levels.contains(&level)) {
+            return Err(AvroError::Validation(format!(
+                "unsupported compression level {} for codec {:?}", level, self.codec
+            )))
+        }
+        self.compression_level = Some(level);
+        Ok(())
+    }
+
+    /// Whether the values appended from now on are validated against the schema, which they are
+    /// by default. Turning validation off makes every append behave as
+    /// [`append_unchecked`](struct.Writer.html#method.append_unchecked).
+    pub fn set_validation(&mut self, validate: bool) {
+        self.validate = validate;
+    }
+
+    /// Whether the keys of maps and lru sets, and the items of sets, of the values appended from now
+    /// on are written in lexicographic order, so that appending the same value twice writes the
+    /// same bytes whatever the order of its entries in memory. Maps and sets are otherwise written
+    /// in arbitrary order, and lru sets from the most to the least recently accessed entry. See
+    /// [`EncodeOptions`](encode/struct.EncodeOptions.html).
+    pub fn set_sorted(&mut self, sorted: bool) {
+        self.encode_options.sorted = sorted;
+    }
+
+    /// Collect a secondary index of the fields flagged with `"index": true` in the schema, nested
+    /// records included, for the records appended from now on. See
+    /// [`FileIndex`](struct.FileIndex.html).
+    pub fn collect_index(&mut self) {
+        if self.index.is_none() {
+            self.index = Some(IndexCollector::new(self.schema));
+        }
+    }
+
+    /// The secondary index collected so far, if enabled by
+    /// [`collect_index`](struct.Writer.html#method.collect_index). Records appended since the last
+    /// flush are not part of it yet.
+    pub fn index(&self) -> Option<&FileIndex> {
+        self.index.as_ref().map(IndexCollector::index)
+    }
+
+    /// Flush the content appended to a `Writer`, then write the secondary index collected since
+    /// [`collect_index`](struct.Writer.html#method.collect_index) was called (empty otherwise) to
+    /// `index`, as a sidecar index file (see [`SidecarIndex`](struct.SidecarIndex.html)).
+    ///
+    /// Return what the `Writer` is writing to, consuming the `Writer` itself.
+    pub fn close_with_index<I: Write>(mut self, index: I) -> Result<W, AvroError> {
+        self.flush()?;
+        let empty = FileIndex::default();
+        let collected = self.index().unwrap_or(&empty);
+        write_sidecar_index(index, &self.marker, self.schema, collected)?;
+        Ok(self.writer)
+    }
+
     /// Return what the `Writer` is writing to, consuming the `Writer` itself.
     ///
     /// **NOTE** This function doesn't guarantee that everything gets written before consuming the
@@ -269,29 +395,95 @@ impl<'a, W: Write> Writer<'a, W> {
     }
 
     /// Generate and append synchronization marker to the payload.
-    fn append_marker(&mut self) -> Result<usize, Error> {
+    fn append_marker(&mut self) -> Result<usize, AvroError> {
         // using .writer.write directly to avoid mutable borrow of self
         // with ref borrowing of self.marker
         Ok(self.writer.write(&self.marker)?)
     }
 
+    /// Write the header, unless already written.
+    fn write_header(&mut self) -> Result<usize, AvroError> {
+        if self.has_header {
+            return Ok(0)
+        }
+        let header = self.header()?;
+        let n = self.append_bytes(header.as_ref())?;
+        self.has_header = true;
+        self.offset += n as u64;
+        Ok(n)
+    }
+
+    /// Count a value appended to the buffer, and collect its indexed fields if needed.
+    fn append_encoded(&mut self, value: &Value, validate: bool) -> Result<usize, AvroError> {
+        if validate && !value.validate(self.schema) {
+            return Err(AvroError::Validation("value does not match schema".to_owned()))
+        }
+        let n = self.write_header()?;
+        self.push(value, None).map(|b| b + n)
+    }
+
+    /// The validator of the values extending the `Writer`, if they are validated at all.
+    fn validator(&self) -> Option<Validator<'a>> {
+        if self.validate {
+            Some(Validator::new(self.schema))
+        } else {
+            None
+        }
+    }
+
+    /// Encode a value into the current block, which is flushed once it reaches the sync interval.
+    /// The value is validated first if a validator is given.
+    fn push(&mut self, value: &Value, validator: Option<&Validator>) -> Result<usize, AvroError> {
+        if let Some(validator) = validator {
+            if !validator.validate(value) {
+                return Err(AvroError::Validation("value does not match schema".to_owned()))
+            }
+        }
+        // evictions counted before this value are not of this writer
+        util::take_evictions();
+        encode_with_options(value, self.schema, &self.encode_options, &mut self.buffer);
+        self.collect(value);
+        self.metrics.0.records_written(1);
+        let evicted = util::take_evictions();
+        if evicted > 0 {
+            self.metrics.0.evictions(evicted as u64);
+        }
+
+        if self.buffer.len() >= self.block_size {
+            return self.flush()
+        }
+
+        Ok(0)
+    }
+
+    fn collect(&mut self, value: &Value) {
+        if let Some(ref mut index) = self.index {
+            index.add(value, self.ordinal);
+        }
+        self.num_values += 1;
+        self.ordinal += 1;
+    }
+
     /// Append a raw Avro Value to the payload avoiding to encode it again.
-    fn append_raw(&mut self, value: &Value, schema: &Schema) -> Result<usize, Error> {
+    fn append_raw(&mut self, value: &Value, schema: &Schema) -> Result<usize, AvroError> {
         self.append_bytes(encode_to_vec(&value, schema).as_ref())
     }
 
     /// Append pure bytes to the payload.
-    fn append_bytes(&mut self, bytes: &[u8]) -> Result<usize, Error> {
+    fn append_bytes(&mut self, bytes: &[u8]) -> Result<usize, AvroError> {
         Ok(self.writer.write(bytes)?)
     }
 
     /// Create an Avro header based on schema, codec and sync marker.
-    fn header(&self) -> Result<Vec<u8>, Error> {
+    fn header(&self) -> Result<Vec<u8>, AvroError> {
         let schema_bytes = serde_json::to_string(self.schema)?.into_bytes();
 
-        let mut metadata = HashMap::with_capacity(2);
+        let mut metadata = HashMap::with_capacity(2 + self.user_metadata.len());
         metadata.insert("avro.schema", Value::Bytes(schema_bytes, None));
         metadata.insert("avro.codec", self.codec.avro());
+        for (key, value) in &self.user_metadata {
+            metadata.insert(key.as_str(), Value::Bytes(value.clone(), None));
+        }
 
         let mut header = Vec::new();
         header.extend_from_slice(AVRO_OBJECT_HEADER);
@@ -315,38 +507,161 @@ fn write_avro_datum<T: ToAvro>(
     schema: &Schema,
     value: T,
     buffer: &mut Vec<u8>,
-) -> Result<(), Error> {
+) -> Result<(), AvroError> {
     let avro = value.avro();
     if !avro.validate(schema) {
-        return Err(ValidationError::new("value does not match schema").into())
+        return Err(AvroError::Validation("value does not match schema".to_owned()))
     }
     encode(&avro, schema, buffer);
     Ok(())
 }
 
-fn write_value_ref(schema: &Schema, value: &Value, buffer: &mut Vec<u8>) -> Result<(), Error> {
+fn write_value_ref(
+    schema: &Schema,
+    value: &Value,
+    options: &EncodeOptions,
+    buffer: &mut Vec<u8>,
+) -> Result<(), AvroError> {
     if !value.validate(schema) {
-        return Err(ValidationError::new("value does not match schema").into())
+        return Err(AvroError::Validation("value does not match schema".to_owned()))
     }
-    encode_ref(value, schema, buffer);
+    encode_with_options(value, schema, options, buffer);
     Ok(())
 }
 
+/// Validation of values against a schema, as done by
+/// [`Value::validate`](../types/enum.Value.html#method.validate), with the lookups into the schema
+/// (enum symbols, record fields) prepared once for all the values validated.
+enum Validator<'a> {
+    /// Schemas without lookups to prepare, checked by `Value::validate`.
+    Schema(&'a Schema),
+    Enum {
+        symbols: &'a [String],
+        positions: HashMap<&'a str, usize>,
+    },
+    Array(Box<Validator<'a>>, Option<usize>),
+    Map(Box<Validator<'a>>),
+    Record(Vec<(&'a str, Validator<'a>)>),
+    Optional(Box<Validator<'a>>),
+}
+
+impl<'a> Validator<'a> {
+    fn new(schema: &'a Schema) -> Validator<'a> {
+        match *schema {
+            Schema::Enum { ref symbols, .. } => Validator::Enum {
+                symbols,
+                positions: symbols
+                    .iter()
+                    .enumerate()
+                    .map(|(i, symbol)| (symbol.as_str(), i))
+                    .collect(),
+            },
+            Schema::Array(ref items) => Validator::Array(Box::new(Validator::new(items)), None),
+            Schema::BoundedList {
+                ref items, max_len, ..
+            } => Validator::Array(Box::new(Validator::new(items)), Some(max_len)),
+            Schema::Map(ref values) => Validator::Map(Box::new(Validator::new(values))),
+            Schema::Record { ref fields, .. } => Validator::Record(
+                fields
+                    .iter()
+                    .map(|field| (&*field.name, Validator::new(&field.schema)))
+                    .collect(),
+            ),
+            Schema::Optional(ref inner) => Validator::Optional(Box::new(Validator::new(inner))),
+            _ => Validator::Schema(schema),
+        }
+    }
+
+    fn validate(&self, value: &Value) -> bool {
+        match (value, self) {
+            (_, Validator::Schema(schema)) => value.validate(schema),
+            (Value::String(s, _), Validator::Enum { positions, .. }) => {
+                positions.contains_key(s.as_str())
+            },
+            (Value::Enum(i, s, _), Validator::Enum { symbols, .. }) => {
+                symbols.get(*i as usize) == Some(s)
+            },
+            (Value::Array(items, _), Validator::Array(inner, max_len)) => {
+                max_len.is_none_or(|max_len| items.len() <= max_len)
+                    && items.iter().all(|item| inner.validate(item))
+            },
+            (Value::Map(items, _), Validator::Map(inner)) => {
+                items.values().all(|value| inner.validate(value))
+            },
+            (Value::Record(record_fields, _), Validator::Record(fields)) => {
+                fields.len() == record_fields.len()
+                    && fields.iter().zip(record_fields.iter()).all(
+                        |((field_name, field), (name, value))| {
+                            *field_name == &**name && field.validate(value)
+                        },
+                    )
+            },
+            (Value::Optional(value, _), Validator::Optional(inner)) => match value {
+                Some(value) => inner.validate(value),
+                None => true,
+            },
+            _ => false,
+        }
+    }
+}
+
 /// Encode a compatible value (implementing the `ToAvro` trait) into Avro format, also
 /// performing schema validation.
 ///
 /// **NOTE** This function has a quite small niche of usage and does NOT generate headers and sync
 /// markers; use [`Writer`](struct.Writer.html) to be fully Avro-compatible if you don't know what
 /// you are doing, instead.
-pub fn to_avro_datum<T: ToAvro>(schema: &Schema, value: T) -> Result<Vec<u8>, Error> {
+pub fn to_avro_datum<T: ToAvro>(schema: &Schema, value: T) -> Result<Vec<u8>, AvroError> {
     let mut buffer = Vec::new();
     write_avro_datum(schema, value, &mut buffer)?;
     Ok(buffer)
 }
 
+/// Encode a `Value` into Avro format at the end of `buffer`, also performing schema validation.
+///
+/// Unlike [`to_avro_datum`](fn.to_avro_datum.html), the value is borrowed and no buffer is
+/// allocated: clearing and reusing the same buffer encodes many values without allocating for
+/// each of them.
+///
+/// **NOTE** As for [`to_avro_datum`](fn.to_avro_datum.html), no headers and sync markers are
+/// written.
+pub fn to_avro_datum_into(
+    schema: &Schema,
+    value: &Value,
+    buffer: &mut Vec<u8>,
+) -> Result<(), AvroError> {
+    write_value_ref(schema, value, &EncodeOptions::default(), buffer)
+}
+
+/// Encode anything implementing the `Serialize` trait into Avro format, straight into `writer`
+/// without building a `Value` first.
+///
+/// Struct fields are matched to record fields by name, in any order: fields unknown to the schema
+/// are left out and missing ones take their default value. Numbers are converted to the numeric
+/// type of the schema, as for schema resolution.
+///
+/// **NOTE** As for [`to_avro_datum`](fn.to_avro_datum.html), no headers and sync markers are
+/// written.
+pub fn to_avro_datum_serde<T, W>(
+    value: &T,
+    schema: &Schema,
+    writer: &mut W,
+) -> Result<(), AvroError>
+where
+    T: Serialize + ?Sized,
+    W: Write,
+{
+    let mut buffer = Vec::new();
+    serialize_datum(value, schema, &mut buffer)?;
+    writer.write_all(&buffer)?;
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::index::SidecarIndex;
+    use crate::reader::Reader;
     use crate::types::Record;
     use crate::util::zig_i64;
 
@@ -379,6 +694,112 @@ mod tests {
         assert_eq!(to_avro_datum(&schema, record).unwrap(), expected);
     }
 
+    #[test]
+    fn test_to_avro_datum_into() {
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+        let mut record = Record::new(&schema).unwrap();
+        record.put("a", 27i64);
+        record.put("b", "foo");
+        let value = record.avro();
+
+        let mut buffer = Vec::new();
+        to_avro_datum_into(&schema, &value, &mut buffer).unwrap();
+        assert_eq!(buffer, to_avro_datum(&schema, value.clone()).unwrap());
+
+        // the buffer is appended to, and can be cleared to be reused
+        let len = buffer.len();
+        to_avro_datum_into(&schema, &value, &mut buffer).unwrap();
+        assert_eq!(buffer.len(), 2 * len);
+        buffer.clear();
+        assert!(to_avro_datum_into(&schema, &Value::Null, &mut buffer).is_err());
+    }
+
+    #[test]
+    fn test_append_ref() {
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+        let mut record = Record::new(&schema).unwrap();
+        record.put("a", 27i64);
+        record.put("b", "foo");
+        let value = record.avro();
+
+        let mut writer = Writer::new(&schema, Vec::new());
+        writer.append_ref(&value).unwrap();
+        writer.append_ref(&value).unwrap();
+        assert!(writer.append_ref(&Value::Null).is_err());
+        writer.flush().unwrap();
+
+        let written = writer.into_inner();
+        let reader = Reader::new(&written[..]).unwrap();
+        let values = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(values, vec![value.clone(), value]);
+    }
+
+    #[test]
+    fn test_append_unchecked() {
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+        let mut record = Record::new(&schema).unwrap();
+        record.put("a", 27i64);
+        record.put("b", "foo");
+        let value = record.avro();
+
+        let mut writer = Writer::new(&schema, Vec::new());
+        writer.append_unchecked(&value).unwrap();
+        writer.set_validation(false);
+        writer.append(value.clone()).unwrap();
+        // invalid values are encoded anyway
+        assert!(writer.append_unchecked(&Value::Null).is_ok());
+        writer.set_validation(true);
+        assert!(writer.append_ref(&Value::Null).is_err());
+        writer.flush().unwrap();
+
+        let written = writer.into_inner();
+        let mut reader = Reader::new(&written[..]).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap(), value);
+        assert_eq!(reader.next().unwrap().unwrap(), value);
+    }
+
+    #[test]
+    fn test_writer_sorted() {
+        let schema = Schema::parse_str(r#"{"type": "map", "values": "long"}"#).unwrap();
+        let map = |keys: &mut dyn Iterator<Item = usize>| {
+            Value::Map(keys.map(|i| (format!("k{}", i), Value::Long(1, None))).collect(), None)
+        };
+        let ascending = map(&mut (0..50));
+        let descending = map(&mut (0..50).rev());
+        let mut expected = Vec::new();
+        encode_with_options(&ascending, &schema, &EncodeOptions { sorted: true }, &mut expected);
+
+        for value in &[ascending, descending] {
+            let mut writer = Writer::new(&schema, Vec::new());
+            writer.set_sorted(true);
+            writer.append_ref(value).unwrap();
+            writer.flush().unwrap();
+            let written = writer.into_inner();
+            assert!(written.windows(expected.len()).any(|window| window == &expected[..]));
+            let mut reader = Reader::new(&written[..]).unwrap();
+            assert_eq!(&reader.next().unwrap().unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_to_avro_datum_serde() {
+        #[derive(Serialize)]
+        struct Test {
+            b: &'static str,
+        }
+
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+        let mut expected = Vec::new();
+        zig_i64(42, &mut expected);
+        zig_i64(3, &mut expected);
+        expected.extend(b"foo");
+
+        let mut buffer = Vec::new();
+        to_avro_datum_serde(&Test { b: "foo" }, &schema, &mut buffer).unwrap();
+        assert_eq!(buffer, expected);
+        assert!(to_avro_datum_serde(&27i64, &schema, &mut buffer).is_err());
+    }
+
     #[test]
     fn test_union() {
         let schema = Schema::parse_str(UNION_SCHEMA).unwrap();
@@ -442,6 +863,78 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_writer_extend_blocks() {
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+        let mut writer = Writer::new(&schema, Vec::new());
+        let values = (0..SYNC_INTERVAL as i64)
+            .map(|a| Value::record(vec![("a", Value::Long(a, None)), ("b", "foo".avro())]))
+            .collect::<Vec<_>>();
+
+        let n = writer.extend(values.clone()).unwrap();
+        let result = writer.into_inner();
+        assert_eq!(n, result.len());
+
+        // the values span several blocks, flushed as they fill up: the sync marker follows the
+        // header and every block
+        let reader = Reader::new(&result[..]).unwrap();
+        let markers = result.windows(SYNC_SIZE).filter(|w| w == reader.marker()).count();
+        assert!(markers > 2);
+        assert_eq!(reader.collect::<Result<Vec<_>, _>>().unwrap(), values);
+    }
+
+    #[test]
+    fn test_writer_extend_validator() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "e", "type": {"type": "enum", "name": "e", "symbols": ["x", "y"]}},
+                    {"name": "l", "type": {"type": "array", "items": "long"}},
+                    {"name": "m", "type": {"type": "map", "values": "string"}}
+                ]
+            }
+        "#,
+        )
+        .unwrap();
+        let record =
+            |e: Value, l: Value, m: Value| Value::record(vec![("e", e), ("l", l), ("m", m)]);
+        let map =
+            |value: Value| Value::Map(vec![("k".to_owned(), value)].into_iter().collect(), None);
+        let longs = Value::Array(vec![Value::Long(1, None)], None);
+        let strings = map("v".avro());
+        let values = vec![
+            record(Value::Enum(1, "y".to_owned(), None), longs.clone(), strings.clone()),
+            record("x".avro(), longs.clone(), strings.clone()),
+            record("z".avro(), longs.clone(), strings.clone()),
+            record(Value::Enum(0, "y".to_owned(), None), longs.clone(), strings.clone()),
+            record("x".avro(), Value::Array(vec!["a".avro()], None), strings.clone()),
+            record("x".avro(), longs.clone(), Value::Map(HashMap::new(), None)),
+            record("x".avro(), longs, map(1.avro())),
+            Value::record(vec![("e", "x".avro())]),
+        ];
+
+        // the validator prepared once for the schema agrees with `Value::validate`
+        let validator = Validator::new(&schema);
+        for value in &values {
+            assert_eq!(validator.validate(value), value.validate(&schema), "{:?}", value);
+        }
+        assert_eq!(values.iter().filter(|value| validator.validate(value)).count(), 3);
+
+        // `extend` stops at the first invalid value, unless validation is turned off
+        let mut writer = Writer::new(&schema, Vec::new());
+        assert!(writer.extend_from_slice(&values[..3]).is_err());
+        assert_eq!(writer.num_values, 2);
+
+        let mut writer = Writer::new(&schema, Vec::new());
+        writer.set_validation(false);
+        writer.extend_from_slice(&values[..3]).unwrap();
+        assert_eq!(writer.num_values, 0);
+        assert_eq!(writer.ordinal, 3);
+    }
+
     #[test]
     fn test_writer_extend() {
         let schema = Schema::parse_str(SCHEMA).unwrap();
@@ -653,4 +1146,92 @@ mod tests {
             data
         );
     }
+
+    #[test]
+    fn test_writer_compression_level() {
+        use crate::Reader;
+
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+        let mut writer = Writer::new(&schema, Vec::new());
+        assert!(writer.set_compression_level(1).is_err());
+
+        let mut record = Record::new(&schema).unwrap();
+        record.put("a", 27i64);
+        record.put("b", "foo");
+        let mut writer = Writer::with_codec(&schema, Vec::new(), Codec::Deflate);
+        assert!(writer.set_compression_level(10).is_err());
+        writer.set_compression_level(9).unwrap();
+        writer.append(record.clone()).unwrap();
+        writer.append(record).unwrap();
+        writer.flush().unwrap();
+        let result = writer.into_inner();
+
+        let records = Reader::new(&result[..]).unwrap().collect::<Result<Vec<_>, _>>();
+        assert_eq!(records.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_writer_index() {
+        let schema = Schema::parse_str(
+            r#"
+            {
+                "type": "record",
+                "name": "test",
+                "fields": [
+                    {"name": "a", "type": "long", "index": true},
+                    {"name": "b", "type": "string"}
+                ]
+            }
+        "#,
+        ).unwrap();
+        let mut writer = Writer::new(&schema, Vec::new());
+        writer.collect_index();
+
+        let record = |a: i64| Value::record(vec![("a", a.avro()), ("b", "foo".avro())]);
+        let n1 = writer.append(record(1)).unwrap();
+        let n2 = writer.append(record(2)).unwrap();
+        let n3 = writer.flush().unwrap();
+        writer.append(record(1)).unwrap();
+
+        let mut index = Vec::new();
+        let result = writer.close_with_index(&mut index).unwrap();
+        let index = SidecarIndex::open(&index[..]).unwrap().load().unwrap();
+
+        let postings = index.lookup(&schema, "a", 1i64.avro()).unwrap();
+        assert_eq!(postings.iter().map(|p| p.ordinal).collect::<Vec<_>>(), vec![0, 2]);
+        // blocks start right after the header, then right after the previous block
+        let offsets = postings.iter().map(|p| p.block_offset as usize).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![n1 + n2, n1 + n2 + n3]);
+        assert_eq!(&result[offsets[1] - 16..offsets[1]], &result[n1 - 16..n1]);
+    }
+
+    #[test]
+    fn test_writer_append_datum() {
+        let schema = Schema::parse_str(SCHEMA).unwrap();
+        let mut writer = Writer::with_codec(&schema, Vec::new(), Codec::Deflate);
+        for a in 0..4i64 {
+            writer.append(Value::record(vec![("a", a.avro()), ("b", "foo".avro())])).unwrap();
+            writer.flush().unwrap();
+        }
+        let source = writer.into_inner();
+
+        // the 4 blocks of a single record are merged into blocks of 2 records
+        let mut reader = Reader::new(&source[..]).unwrap();
+        let mut writer = Writer::new(&schema, Vec::new());
+        writer.set_block_size(10);
+        while let Some((records, bytes)) = reader.next_block().unwrap() {
+            assert_eq!((records, bytes.len()), (1, 5));
+            writer.append_datum(&bytes).unwrap();
+        }
+        writer.flush().unwrap();
+        let copy = writer.into_inner();
+
+        let mut reader = Reader::new(&copy[..]).unwrap();
+        assert_eq!(reader.codec(), Codec::Null);
+        assert_eq!(reader.count_blocks().unwrap().blocks, 2);
+        let values = |data: &[u8]| {
+            Reader::new(data).unwrap().collect::<Result<Vec<_>, _>>().unwrap()
+        };
+        assert_eq!(values(&copy), values(&source));
+    }
 }